@@ -2,7 +2,7 @@
 //!
 //! Cross-platform desktop automation and workflow recording for AI agents.
 //!
-//! Supported: macOS, Windows
+//! Supported: macOS, Windows, Linux (record & replay, X11 and Wayland)
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
@@ -13,6 +13,14 @@ use std::sync::Arc;
 
 use bigbrother::prelude::*;
 use bigbrother::error::{Error, ErrorCode};
+use bigbrother::recorder::redact::default_redactors;
+use snapshot::Snapshot;
+#[cfg(feature = "otlp")]
+use bigbrother::telemetry::Telemetry;
+
+mod repl;
+mod serve;
+mod snapshot;
 
 // macOS-only imports
 #[cfg(target_os = "macos")]
@@ -23,41 +31,373 @@ use bigbrother::input;
 #[command(about = "BigBrother - cross-platform desktop automation and workflow recording")]
 #[command(version)]
 struct Cli {
+    /// Print every result and error as {success, data, error} JSON
+    #[arg(long, global = true)]
+    json: bool,
+    /// Print nothing but errors (and results with --json)
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    /// Log everything bb does to stderr, not just warnings
+    #[arg(short, long, global = true)]
+    verbose: bool,
+    /// Send spans for every action to an OpenTelemetry collector, at URL or
+    /// OTEL_EXPORTER_OTLP_ENDPOINT
+    #[arg(long, global = true, value_name = "URL", num_args = 0..=1, require_equals = true)]
+    otlp: Option<Option<String>>,
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(clap::Args)]
+struct RecordArgs {
+    #[arg(short, long, default_value = "workflow")]
+    name: String,
+    /// What the recording does, saved with it
+    #[arg(long)]
+    description: Option<String>,
+    /// Label to find the recording by with bb list --tag (repeatable)
+    #[arg(long = "tag")]
+    tags: Vec<String>,
+    #[arg(long)]
+    no_context: bool,
+    #[arg(long, default_value = "5")]
+    threshold: f64,
+    /// Only record while this app is frontmost (repeatable)
+    #[arg(long = "allow-app")]
+    allow_apps: Vec<String>,
+    /// Never record while this app is frontmost (repeatable)
+    #[arg(long = "block-app")]
+    block_apps: Vec<String>,
+    /// Scrub emails, card numbers and API keys from recorded text
+    #[arg(long)]
+    scrub: bool,
+    /// Save a screenshot on every click and app switch
+    #[arg(long)]
+    screenshots: bool,
+    /// Characters of clipboard content kept in each Paste event
+    #[arg(long, value_name = "CHARS", default_value = "100")]
+    clipboard_preview: usize,
+    /// Save the full content of every clipboard change next to the recording
+    #[arg(long)]
+    save_clipboard: bool,
+    /// Record file changes in this directory, e.g. ~/Downloads (repeatable)
+    #[arg(long = "watch-dir", value_name = "DIR")]
+    watch_dirs: Vec<std::path::PathBuf>,
+    /// Record the screen alongside events (lined up to ~100ms with ffmpeg,
+    /// about a second with screencapture or wf-recorder)
+    #[cfg(feature = "video")]
+    #[arg(long)]
+    video: bool,
+    /// Hotkey that prompts for a note to attach to the recording, e.g. ctrl+shift+n
+    #[arg(long)]
+    annotate_key: Option<Hotkey>,
+    /// Hotkey that pauses and resumes recording, e.g. ctrl+shift+p
+    #[arg(long)]
+    pause_key: Option<Hotkey>,
+    /// Hotkey that stops recording, so the switch back to the terminal isn't recorded
+    #[arg(long)]
+    stop_key: Option<Hotkey>,
+    /// Stamp every event with its wall-clock time
+    #[arg(long)]
+    absolute_timestamps: bool,
+    /// Collapse pauses longer than this many ms, so replay doesn't sit through them
+    #[arg(long, value_name = "MS")]
+    collapse_idle: Option<u64>,
+    /// Write events to disk as they arrive instead of at the end
+    #[arg(long)]
+    stream: bool,
+    /// Stop after this many seconds
+    #[arg(long, value_name = "SECS")]
+    max_duration: Option<u64>,
+    /// Stop after this many events
+    #[arg(long, value_name = "N")]
+    max_events: Option<u64>,
+    /// Start a new file instead of stopping at --max-duration/--max-events (implies --stream)
+    #[arg(long)]
+    rotate: bool,
+    /// Spill events to a temporary file instead of dropping them when the buffer is full
+    #[arg(long)]
+    spill: bool,
+    /// Save as jsonl, or bbr (MessagePack, about half the size)
+    #[arg(long, default_value = "jsonl")]
+    format: Format,
+    /// Compress the recording: none, gzip or zstd
+    #[arg(long, default_value = "none")]
+    compress: Compression,
+}
+
+#[derive(clap::Args)]
+struct ReplayArgs {
+    file: String,
+    #[arg(short, long, default_value = "1.0")]
+    speed: f64,
+    /// Never wait more than this many ms between events
+    #[arg(long, value_name = "MS")]
+    max_wait: Option<u64>,
+    /// Leave at least this many ms between clicks, whatever the speed
+    #[arg(long, value_name = "MS")]
+    min_click_delay: Option<u64>,
+    /// Type text at this many characters per second
+    #[arg(long, value_name = "CPS")]
+    typing_cps: Option<f64>,
+    /// Type text a character at a time, this many ms apart (0: all at once)
+    #[arg(long, value_name = "MS")]
+    key_delay: Option<u64>,
+    /// Paste typed text longer than this many characters through the clipboard
+    #[arg(long, value_name = "CHARS")]
+    paste_over: Option<usize>,
+    /// Don't wait before mouse moves
+    #[arg(long)]
+    fast_moves: bool,
+    /// Wait up to this many ms for the recorded app and element before input (0: don't)
+    #[arg(long, value_name = "MS", default_value = "5000")]
+    sync_timeout: u64,
+    /// Leave focus alone instead of switching apps where the recording did
+    #[arg(long)]
+    no_activate: bool,
+    /// Keep going when the mouse is moved or Escape is pressed
+    #[arg(long)]
+    no_guard: bool,
+    /// Abort when input's app isn't frontmost after the sync timeout
+    #[arg(long)]
+    strict: bool,
+    /// Abort at the first failed assertion
+    #[arg(long)]
+    fail_fast: bool,
+    /// Pause before every event and ask whether to run, skip or abort
+    #[arg(long)]
+    step: bool,
+    /// Pause before the event at this index (repeatable)
+    #[arg(long = "break", value_name = "INDEX")]
+    breakpoints: Vec<usize>,
+    /// Start at this event index, or the first annotation containing this text
+    #[arg(long, value_name = "INDEX|LABEL")]
+    from: Option<String>,
+    /// Stop before this event index, or the next annotation containing this text
+    #[arg(long, value_name = "INDEX|LABEL")]
+    to: Option<String>,
+    /// Play the workflow this many times in a row
+    #[arg(long, value_name = "N")]
+    repeat: Option<usize>,
+    /// Keep replaying on a cron schedule, e.g. "0 9 * * MON-FRI"
+    #[arg(long, value_name = "CRON")]
+    every: Option<Schedule>,
+    /// Try a failed event this many more times before giving up on it
+    #[arg(long, value_name = "N", default_value = "0")]
+    retry: u32,
+    /// After retrying, click wherever a failed click's element is now
+    #[arg(long)]
+    relocate: bool,
+    /// Go on with the next event instead of stopping at a failed one
+    #[arg(long)]
+    skip_failed: bool,
+    /// Save a screenshot here when the replay stops at a failed event
+    #[arg(long, value_name = "PATH")]
+    failure_screenshot: Option<std::path::PathBuf>,
+    /// Fill in {{NAME}} placeholders in typed and pasted text (repeatable)
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_var)]
+    vars: Vec<(String, String)>,
+    /// Replay on another machine through its `bb serve`, e.g. ws://10.0.0.5:8123
+    #[arg(long, value_name = "URL", requires = "token_file")]
+    remote: Option<String>,
+    /// File holding the token of the `bb serve` at --remote
+    #[arg(long, value_name = "PATH")]
+    token_file: Option<std::path::PathBuf>,
+}
+
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    let (name, value) = s.split_once('=').ok_or_else(|| format!("expected NAME=VALUE, got {}", s))?;
+    Ok((name.trim().to_string(), value.to_string()))
+}
+
+/// `START..END`, either end left out for the first or last event
+fn parse_range(s: &str) -> Result<std::ops::Range<usize>, String> {
+    let (start, end) = s.split_once("..").ok_or_else(|| format!("expected START..END, got {}", s))?;
+    let index = |n: &str, empty: usize| match n.trim() {
+        "" => Ok(empty),
+        n => n.parse().map_err(|_| format!("{:?} isn't an event index", n)),
+    };
+    Ok(index(start, 0)?..index(end, usize::MAX)?)
+}
+
+fn parse_retime(s: &str) -> Result<(std::ops::Range<usize>, f64), String> {
+    let (range, factor) =
+        s.rsplit_once(':').ok_or_else(|| format!("expected START..END:FACTOR, got {}", s))?;
+    let factor: f64 = factor.parse().map_err(|_| format!("{:?} isn't a number", factor))?;
+    if factor < 0.0 {
+        return Err("the factor can't be negative".to_string());
+    }
+    Ok((parse_range(range)?, factor))
+}
+
+/// What `bb export` writes: a script, or the events as a table
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    Script(bigbrother::export::ScriptFormat),
+    Table(TableFormat),
+}
+
+fn parse_export_format(s: &str) -> Result<ExportFormat, String> {
+    s.parse()
+        .map(ExportFormat::Script)
+        .or_else(|_| s.parse().map(ExportFormat::Table))
+        .map_err(|_| format!("unknown export format: {} (rust, python, csv or parquet)", s))
+}
+
 #[derive(Subcommand)]
 enum Commands {
     // === Recording Commands ===
     /// Start recording user interactions
-    Record {
-        #[arg(short, long, default_value = "workflow")]
-        name: String,
-        #[arg(long)]
-        no_context: bool,
-        #[arg(long, default_value = "5")]
-        threshold: f64,
-    },
+    Record(RecordArgs),
     /// Replay a recorded workflow
-    Replay {
-        file: String,
-        #[arg(short, long, default_value = "1.0")]
-        speed: f64,
-    },
+    Replay(ReplayArgs),
     /// List saved workflows
-    List,
+    List {
+        /// Only workflows with this tag (repeatable)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+        /// Only workflows that switched to this app
+        #[arg(long)]
+        app: Option<String>,
+        /// Only workflows with this in their name or description
+        #[arg(long)]
+        search: Option<String>,
+    },
     /// Show workflow info
     Show {
         file: String,
         #[arg(long)]
         all: bool,
+        /// List what was done ("typed ... into ...") instead of raw events
+        #[arg(long)]
+        actions: bool,
+    },
+    /// Tell what was done in a few lines, for a prompt
+    Summarize {
+        file: String,
+        /// Keep the summary within about this many tokens
+        #[arg(long, value_name = "N")]
+        max_tokens: Option<usize>,
+        /// Cut typed text and clipboard previews to this many characters
+        #[arg(long, value_name = "N", default_value = "80")]
+        max_text: usize,
+    },
+    /// Compile a workflow to automation steps (locator clicks, typing,
+    /// shortcuts) and print them as JSON
+    Plan {
+        file: String,
+        /// Run the steps instead of printing them
+        #[arg(long)]
+        run: bool,
+    },
+    /// Write a workflow out as a Rust or Python script to edit and run, or
+    /// its events as a CSV or Parquet table
+    Export {
+        file: String,
+        /// rust, python, csv or parquet
+        #[arg(long, default_value = "rust", value_parser = parse_export_format)]
+        format: ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Turn workflows into (screen context, next action) examples for
+    /// fine-tuning, as JSON lines
+    Dataset {
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// prompt, chat or structured
+        #[arg(long, default_value = "prompt")]
+        schema: bigbrother::recorder::training::Schema,
+        /// Actions before the next one kept in the context
+        #[arg(long, value_name = "N", default_value = "5")]
+        history: usize,
+        /// Mouse moves per action sampled as clicks that didn't happen
+        #[arg(long, value_name = "N", default_value = "1")]
+        negatives: usize,
+        /// Write to this file instead of stdout
+        #[arg(short, long)]
+        output: Option<std::path::PathBuf>,
+    },
+    /// Clean up a workflow and save the result as a new recording
+    Edit {
+        file: String,
+        /// Remove events START..END, closing the time they took (repeatable)
+        #[arg(long, value_name = "START..END", value_parser = parse_range)]
+        delete: Vec<std::ops::Range<usize>>,
+        /// Cut the wait before the first input and after the last
+        #[arg(long)]
+        trim_idle: bool,
+        /// Drop mouse moves
+        #[arg(long)]
+        strip_moves: bool,
+        /// Join consecutive text events
+        #[arg(long)]
+        merge_text: bool,
+        /// Stretch the time between events START..END by FACTOR, e.g.
+        /// 10..20:0.5 to play them twice as fast (repeatable)
+        #[arg(long, value_name = "START..END:FACTOR", value_parser = parse_retime)]
+        retime: Vec<(std::ops::Range<usize>, f64)>,
+        /// Add what was on screen at each click and window switch, from
+        /// screenpipe's database (default: ~/.screenpipe/db.sqlite)
+        #[arg(long, value_name = "DB", num_args = 0..=1)]
+        screenpipe: Option<Option<std::path::PathBuf>>,
+    },
+    /// Convert an rrweb session, Selenium IDE project or Playwright trace
+    /// into a workflow
+    Import {
+        file: std::path::PathBuf,
+        /// rrweb, selenium or playwright (default: from the extension)
+        #[arg(long)]
+        from: Option<ImportFormat>,
+    },
+    /// Join workflows recorded in several sittings into one
+    Merge {
+        #[arg(required = true, num_args = 2..)]
+        files: Vec<String>,
+        /// Output file name, e.g. combined.jsonl (default: a new recording
+        /// named after the first)
+        #[arg(short, long)]
+        output: Option<String>,
     },
     /// Delete a workflow
     Delete {
         file: String,
     },
-    /// Check/request permissions
+    /// Save recordings left unfinished by a crash
+    Recover,
+    /// Delete old recordings
+    Prune {
+        /// Delete recordings older than this many days
+        #[arg(long, value_name = "DAYS")]
+        keep_days: Option<u64>,
+        /// Then delete the oldest until the rest take at most this many MB
+        #[arg(long, value_name = "MB")]
+        max_mb: Option<u64>,
+        /// Recordings to prune (default ~/.workflow-recorder; the daemon's
+        /// are in ~/.workflow-recorder/daemon)
+        #[arg(long)]
+        dir: Option<std::path::PathBuf>,
+    },
+    /// Convert a workflow between jsonl and bbr (MessagePack), or (de)compress it
+    Convert {
+        file: String,
+        /// jsonl or bbr
+        #[arg(long)]
+        to: Format,
+        /// Compress the copy: none, gzip or zstd
+        #[arg(long, default_value = "none")]
+        compress: Compression,
+    },
+    /// Record continuously into rotating files, controlled through a socket
+    Daemon {
+        /// Control socket (default ~/.workflow-recorder/daemon/control.sock)
+        #[arg(long, global = true)]
+        socket: Option<std::path::PathBuf>,
+        #[command(subcommand)]
+        action: DaemonAction,
+    },
+    /// Check/request permissions, with how to grant the missing ones
     Permissions {
         #[arg(long)]
         request: bool,
@@ -73,12 +413,29 @@ enum Commands {
         #[command(subcommand)]
         action: WebAction,
     },
-    /// Get accessibility tree for an app
+    /// Get accessibility tree for an app, saved for `--cached` lookups and
+    /// reused for 30s
     Tree {
         #[arg(long)]
         app: String,
         #[arg(long, default_value = "15")]
         depth: usize,
+        /// Build it again even if a saved one is fresh
+        #[arg(long)]
+        refresh: bool,
+        /// Print at most this many nodes, with `next` set to where the rest start
+        #[arg(long)]
+        max_nodes: Option<usize>,
+        /// Index of the first node to print, a previous page's `next`
+        #[arg(long, default_value = "0")]
+        start: usize,
+        /// Only count the nodes
+        #[arg(long)]
+        count: bool,
+        /// json, indented (an outline, `role "name" [index]` per line), jsonl
+        /// (a node per line) or flat (the outline without indentation)
+        #[arg(long, default_value = "json")]
+        format: TreeFormat,
     },
     /// Find elements matching selector
     Find {
@@ -87,12 +444,29 @@ enum Commands {
         app: Option<String>,
         #[arg(long, default_value = "5000")]
         timeout: u64,
+        /// Match against the app's saved tree instead of walking it
+        #[arg(long, requires = "app")]
+        cached: bool,
+    },
+    /// Show the element that has keyboard focus
+    Focused,
+    /// Inspect the element at a screen point
+    Inspect {
+        /// Screen coordinates as x,y
+        #[arg(long)]
+        at: String,
     },
     /// Click an element
     Click {
         selector: String,
         #[arg(long)]
         app: Option<String>,
+        /// Click the first match, the shallowest, when several are as shallow
+        #[arg(long)]
+        first: bool,
+        /// Look the element up in the app's saved tree instead of walking it
+        #[arg(long, requires = "app")]
+        cached: bool,
     },
     /// Type text
     Type {
@@ -159,6 +533,21 @@ enum Commands {
     Activate {
         app: String,
     },
+    /// Run AppleScript and print its result as JSON (macOS)
+    Applescript {
+        /// The script, or - to read it from stdin
+        script: String,
+        /// JavaScript for Automation instead
+        #[arg(long)]
+        jxa: bool,
+    },
+    /// Run a shortcut from the Shortcuts app and print its output (macOS)
+    RunShortcut {
+        name: String,
+        /// Input: JSON, or text as is; - to read it from stdin
+        #[arg(long)]
+        input: Option<String>,
+    },
     /// Click at screen coordinates
     ClickAt {
         x: i32,
@@ -174,6 +563,30 @@ enum Commands {
         #[arg(long)]
         no_enter: bool,
     },
+    /// Run a YAML or JSON batch of steps (activate, click, type, wait,
+    /// assert, exec...) with one desktop connection
+    Run {
+        file: std::path::PathBuf,
+    },
+    /// Interactive session keeping one desktop connection and the last tree
+    Repl,
+    /// Serve automation and recording over HTTP, as REST or JSON-RPC
+    Serve {
+        #[arg(long, default_value = "8123")]
+        port: u16,
+        /// Address to listen on; anything but localhost lets other machines
+        /// drive this one, and needs --token-file
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+        /// Read the token clients must send from this file, creating it
+        /// readable by you only if missing; a new one is printed otherwise
+        #[arg(long)]
+        token_file: Option<std::path::PathBuf>,
+        /// Let `run` batches have `exec` and `applescript` steps: every
+        /// client can then run shell commands as you
+        #[arg(long)]
+        allow_exec: bool,
+    },
     /// WezTerm pane control
     Wezterm {
         #[command(subcommand)]
@@ -238,6 +651,62 @@ enum WebAction {
     },
 }
 
+#[derive(Subcommand)]
+enum DaemonAction {
+    /// Run the daemon in the foreground
+    Start(DaemonArgs),
+    /// Show what a running daemon is doing
+    Status,
+    /// Stop recording input until resumed
+    Pause,
+    Resume,
+    /// Finish the current file and exit
+    Stop,
+}
+
+#[derive(clap::Args)]
+struct DaemonArgs {
+    /// Recording name, files are <name>_<timestamp>.<format>
+    #[arg(long, default_value = "activity")]
+    name: String,
+    /// Save as jsonl, or bbr (MessagePack, about half the size)
+    #[arg(long, default_value = "jsonl")]
+    format: Format,
+    /// Compress recordings: none, gzip or zstd
+    #[arg(long, default_value = "none")]
+    compress: Compression,
+    /// Where recordings go (default ~/.workflow-recorder/daemon)
+    #[arg(long)]
+    dir: Option<std::path::PathBuf>,
+    /// Start a new file this often: hourly, daily or a number of minutes
+    #[arg(long, default_value = "hourly", value_parser = parse_rotation)]
+    rotate: std::time::Duration,
+    /// Delete the oldest recordings once they take more than this many MB
+    #[arg(long, value_name = "MB")]
+    quota_mb: Option<u64>,
+    /// Delete recordings older than this many days
+    #[arg(long, value_name = "DAYS")]
+    keep_days: Option<u64>,
+    /// Never record while this app is frontmost (repeatable)
+    #[arg(long = "block-app")]
+    block_apps: Vec<String>,
+    /// Scrub emails, card numbers and API keys from recorded text
+    #[arg(long)]
+    scrub: bool,
+    /// Hotkey that pauses and resumes recording, e.g. ctrl+shift+p
+    #[arg(long)]
+    pause_key: Option<Hotkey>,
+}
+
+fn parse_rotation(s: &str) -> Result<std::time::Duration, String> {
+    let minutes = match s {
+        "hourly" => 60,
+        "daily" => 24 * 60,
+        _ => s.parse().map_err(|_| format!("expected hourly, daily or minutes, got {}", s))?,
+    };
+    Ok(std::time::Duration::from_secs(minutes * 60))
+}
+
 #[derive(Subcommand)]
 enum WeztermAction {
     List,
@@ -274,64 +743,55 @@ fn print_json<T: Serialize>(output: &T) {
     println!("{}", serde_json::to_string_pretty(output).unwrap());
 }
 
-// ── macOS key code mapping ──────────────────────────────────────────────────
+/// Set from the global flags before the command runs
+static JSON: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
 
-#[cfg(target_os = "macos")]
-fn key_name_to_code(name: &str) -> Option<u8> {
-    match name.to_lowercase().as_str() {
-        "pageup" | "page_up" => Some(input::key_codes::PAGE_UP),
-        "pagedown" | "page_down" => Some(input::key_codes::PAGE_DOWN),
-        "return" | "enter" => Some(input::key_codes::RETURN),
-        "tab" => Some(input::key_codes::TAB),
-        "escape" | "esc" => Some(input::key_codes::ESCAPE),
-        "space" => Some(input::key_codes::SPACE),
-        "delete" | "backspace" => Some(input::key_codes::DELETE),
-        "up" | "arrow_up" => Some(input::key_codes::ARROW_UP),
-        "down" | "arrow_down" => Some(input::key_codes::ARROW_DOWN),
-        "left" | "arrow_left" => Some(input::key_codes::ARROW_LEFT),
-        "right" | "arrow_right" => Some(input::key_codes::ARROW_RIGHT),
-        "home" => Some(input::key_codes::HOME),
-        "end" => Some(input::key_codes::END),
-        _ => None,
-    }
+fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
 }
 
-// ── Windows key code mapping ────────────────────────────────────────────────
+/// Whether to print progress and results for people, not --json or --quiet
+fn human() -> bool {
+    !json() && !QUIET.load(Ordering::Relaxed)
+}
 
-#[cfg(target_os = "windows")]
-fn key_name_to_vk(name: &str) -> Option<u16> {
-    match name.to_lowercase().as_str() {
-        "pageup" | "page_up" => Some(vk::PAGE_UP),
-        "pagedown" | "page_down" => Some(vk::PAGE_DOWN),
-        "return" | "enter" => Some(vk::RETURN),
-        "tab" => Some(vk::TAB),
-        "escape" | "esc" => Some(vk::ESCAPE),
-        "space" => Some(vk::SPACE),
-        "delete" | "backspace" => Some(vk::BACKSPACE),
-        "up" | "arrow_up" => Some(vk::UP),
-        "down" | "arrow_down" => Some(vk::DOWN),
-        "left" | "arrow_left" => Some(vk::LEFT),
-        "right" | "arrow_right" => Some(vk::RIGHT),
-        "home" => Some(vk::HOME),
-        "end" => Some(vk::END),
-        "f1" => Some(vk::F1),
-        "f4" => Some(0x73), // VK_F4
-        "f12" => Some(vk::F12),
-        // Single letter keys
-        k if k.len() == 1 => {
-            let c = k.chars().next().unwrap().to_ascii_uppercase();
-            if c.is_ascii_alphabetic() {
-                Some(c as u16)
-            } else if c.is_ascii_digit() {
-                Some(c as u16)
-            } else {
-                None
-            }
+/// `println!` for people, so stdout holds nothing but the envelope with --json
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::human() {
+            println!($($arg)*);
         }
-        _ => None,
+    };
+}
+pub(crate) use say;
+
+/// Result of an automation command: JSON unless --quiet
+fn print_ok<T: Serialize>(data: T) {
+    if json() || !QUIET.load(Ordering::Relaxed) {
+        print_json(&Output::ok(data));
+    }
+}
+
+/// Result of a recording command, which prints for people otherwise
+fn report<T: Serialize>(data: T) {
+    if json() {
+        print_json(&Output::ok(data));
     }
 }
 
+/// Structured errors keep their code, recording ones too; anything else is
+/// `UNKNOWN`
+fn error_output(e: &anyhow::Error) -> Output<()> {
+    let err = e.downcast_ref::<Error>().cloned().or_else(|| {
+        let err = Error::from(e.downcast_ref::<RecorderError>()?);
+        Some(Error { message: format!("{:#}", e), ..err })
+    });
+    Output::<()>::err(err.unwrap_or_else(|| Error::new(ErrorCode::Unknown, format!("{:#}", e))))
+}
+
+// ── Windows modifier mapping ────────────────────────────────────────────────
+
 #[cfg(target_os = "windows")]
 fn modifier_name_to_vk(name: &str) -> u16 {
     match name.trim().to_lowercase().as_str() {
@@ -343,161 +803,64 @@ fn modifier_name_to_vk(name: &str) -> u16 {
     }
 }
 
-// ── Windows element helpers ─────────────────────────────────────────────────
-
-#[cfg(target_os = "windows")]
-#[derive(Serialize)]
-struct ElementInfo {
-    name: Option<String>,
-    control_type: &'static str,
-    control_type_id: i32,
-    bounds: Option<(i32, i32, i32, i32)>,
-    is_enabled: bool,
-    class_name: Option<String>,
-    automation_id: Option<String>,
-}
-
-#[cfg(target_os = "windows")]
-impl ElementInfo {
-    fn from_element(el: &Element) -> Self {
-        Self {
-            name: el.name(),
-            control_type: el.control_type_name(),
-            control_type_id: el.control_type(),
-            bounds: el.bounds(),
-            is_enabled: el.is_enabled(),
-            class_name: el.class_name(),
-            automation_id: el.automation_id(),
-        }
-    }
-}
-
-#[cfg(target_os = "windows")]
-#[derive(Serialize)]
-struct TreeNode {
-    name: Option<String>,
-    role: &'static str,
-    children: Vec<TreeNode>,
-}
+// ── Main ────────────────────────────────────────────────────────────────────
 
-#[cfg(target_os = "windows")]
-fn build_tree(walker: &TreeWalker, element: &Element, depth: usize, max_depth: usize) -> TreeNode {
-    let mut children = Vec::new();
-    if depth < max_depth {
-        let mut child = walker.first_child(element);
-        while let Some(c) = child {
-            children.push(build_tree(walker, &c, depth + 1, max_depth));
-            child = walker.next_sibling(&c);
+fn main() {
+    let cli = Cli::parse();
+    JSON.store(cli.json, Ordering::Relaxed);
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    // The exporter flushes its spans when dropped, after the command
+    let telemetry = logging(cli.verbose, cli.otlp);
+    if let Err(e) = telemetry.and_then(|_telemetry| run(cli.command)) {
+        if json() {
+            print_json(&error_output(&e));
+        } else {
+            eprintln!("Error: {}", e);
         }
-    }
-    TreeNode {
-        name: element.name(),
-        role: element.control_type_name(),
-        children,
+        std::process::exit(1);
     }
 }
 
-#[cfg(target_os = "windows")]
-fn collect_text(walker: &TreeWalker, element: &Element, depth: usize, max_depth: usize, items: &mut Vec<serde_json::Value>) {
-    if let Some(name) = element.name() {
-        if !name.is_empty() {
-            items.push(serde_json::json!({
-                "text": name,
-                "role": element.control_type_name(),
-            }));
+fn run(command: Commands) -> Result<()> {
+    match command {
+        Commands::Record(args) => record(args),
+        Commands::Replay(args) => replay(args),
+        Commands::List { tags, app, search } => list(tags, app, search),
+        Commands::Show { file, all, actions } => show(&file, all, actions),
+        Commands::Summarize { file, max_tokens, max_text } => {
+            summarize(&file, max_tokens, max_text)
         }
-    }
-    if depth < max_depth {
-        let mut child = walker.first_child(element);
-        while let Some(c) = child {
-            collect_text(walker, &c, depth + 1, max_depth, items);
-            child = walker.next_sibling(&c);
+        Commands::Plan { file, run } => plan(&file, run),
+        Commands::Export { file, format, output } => export(&file, format, output),
+        Commands::Dataset { files, schema, history, negatives, output } => {
+            dataset(&files, schema, history, negatives, output)
         }
-    }
-}
-
-/// Parse a selector string like "role:Button AND name~:Submit"
-/// Returns matching elements from the tree
-#[cfg(target_os = "windows")]
-fn find_elements_matching(
-    walker: &TreeWalker,
-    element: &Element,
-    selector: &str,
-    max_depth: usize,
-    results: &mut Vec<ElementInfo>,
-    depth: usize,
-) {
-    if depth > max_depth { return; }
-
-    if matches_selector(element, selector) {
-        results.push(ElementInfo::from_element(element));
-    }
-
-    let mut child = walker.first_child(element);
-    while let Some(c) = child {
-        find_elements_matching(walker, &c, selector, max_depth, results, depth + 1);
-        child = walker.next_sibling(&c);
-    }
-}
-
-#[cfg(target_os = "windows")]
-fn matches_selector(element: &Element, selector: &str) -> bool {
-    // Parse AND-separated conditions
-    let conditions: Vec<&str> = selector.split(" AND ").collect();
-
-    for cond in conditions {
-        let cond = cond.trim();
-
-        if cond.starts_with("role:") {
-            let expected_role = &cond[5..];
-            if !element.control_type_name().eq_ignore_ascii_case(expected_role) {
-                return false;
-            }
-        } else if cond.starts_with("name~:") || cond.starts_with("title~:") {
-            // Partial match
-            let needle = if cond.starts_with("name~:") { &cond[6..] } else { &cond[7..] };
-            let name = element.name().unwrap_or_default();
-            if !name.to_lowercase().contains(&needle.to_lowercase()) {
-                return false;
-            }
-        } else if cond.starts_with("name:") || cond.starts_with("title:") {
-            // Exact/substring match
-            let needle = if cond.starts_with("name:") { &cond[5..] } else { &cond[6..] };
-            let name = element.name().unwrap_or_default();
-            if !name.to_lowercase().contains(&needle.to_lowercase()) {
-                return false;
-            }
+        Commands::Edit { file, delete, trim_idle, strip_moves, merge_text, retime, screenpipe } => {
+            edit(&file, delete, retime, trim_idle, strip_moves, merge_text, screenpipe)
         }
-    }
-
-    true
-}
-
-#[cfg(target_os = "windows")]
-fn find_app_window(app_name: &str) -> Result<Element> {
-    find_window(app_name)?
-        .ok_or_else(|| anyhow::anyhow!("Window not found: {}", app_name))
-}
-
-// ── Main ────────────────────────────────────────────────────────────────────
-
-fn main() {
-    let cli = Cli::parse();
-
-    let result: Result<(), anyhow::Error> = match cli.command {
-        Commands::Record { name, no_context, threshold } => record(&name, !no_context, threshold),
-        Commands::Replay { file, speed } => replay(&file, speed),
-        Commands::List => list(),
-        Commands::Show { file, all } => show(&file, all),
+        Commands::Import { file, from } => import(&file, from),
+        Commands::Merge { files, output } => merge(&files, output.as_deref()),
         Commands::Delete { file } => delete(&file),
+        Commands::Recover => recover(),
+        Commands::Prune { keep_days, max_mb, dir } => prune(keep_days, max_mb, dir),
+        Commands::Convert { file, to, compress } => convert(&file, to, compress),
+        Commands::Daemon { socket, action } => daemon(socket, action),
         Commands::Permissions { request } => permissions(request),
 
         // ── Automation (platform-dispatched) ──────────────────────────────
         Commands::Apps => run_automation(cmd_apps),
         Commands::Browser => run_automation(cmd_browser),
-        Commands::Tree { app, depth } => run_automation(move || cmd_tree(&app, depth)),
-        Commands::Find { selector, app, timeout } => run_automation(move || cmd_find(&selector, app.as_deref(), timeout)),
-        Commands::Click { selector, app } => run_automation(move || cmd_click(&selector, app.as_deref())),
+        Commands::Tree { app, depth, refresh, max_nodes, start, count, format } => {
+            run_automation(move || cmd_tree(&app, depth, refresh, max_nodes, start, count, format))
+        }
+        Commands::Find { selector, app, timeout, cached } => {
+            run_automation(move || cmd_find(&selector, app.as_deref(), timeout, cached))
+        }
+        Commands::Focused => run_automation(cmd_focused),
+        Commands::Inspect { at } => run_automation(move || cmd_inspect(&at)),
+        Commands::Click { selector, app, first, cached } => {
+            run_automation(move || cmd_click(&selector, app.as_deref(), first, cached))
+        }
         Commands::Type { text, selector, app } => run_automation(move || cmd_type(&text, selector.as_deref(), app.as_deref())),
         Commands::Scroll { direction, pages, app } => run_automation(move || cmd_scroll(&direction, pages, app.as_deref())),
         Commands::Press { key, repeat, delay } => run_automation(move || cmd_press(&key, repeat, delay)),
@@ -507,16 +870,66 @@ fn main() {
         Commands::Scrape { app, depth } => run_automation(move || cmd_scrape(&app, depth)),
         Commands::Shortcut { key, modifiers } => run_automation(move || cmd_shortcut(&key, &modifiers)),
         Commands::Activate { app } => run_automation(move || cmd_activate(&app)),
+        Commands::Applescript { script, jxa } => {
+            run_automation(move || cmd_applescript(&script, jxa))
+        }
+        Commands::RunShortcut { name, input } => {
+            run_automation(move || cmd_run_shortcut(&name, input))
+        }
         Commands::ClickAt { x, y, button } => run_automation(move || cmd_click_at(x, y, &button)),
         Commands::Send { text, app, no_enter } => run_automation(move || cmd_send(&text, &app, no_enter)),
+        Commands::Run { file } => run_automation(move || cmd_run(&file)),
+        Commands::Repl => repl::run(),
+        Commands::Serve { port, host, token_file, allow_exec } => {
+            serve::run(&host, port, token_file.as_deref(), allow_exec)
+        }
         Commands::Web { action } => cmd_web(action),
         Commands::Wezterm { action } => cmd_wezterm(action),
+    }
+}
+
+/// Log warnings to stderr, everything when verbose, and send spans to a
+/// collector with `--otlp`
+fn logging(verbose: bool, otlp: Option<Option<String>>) -> Result<Option<Telemetry>> {
+    use tracing::level_filters::LevelFilter;
+    use tracing_subscriber::filter::Targets;
+    use tracing_subscriber::prelude::*;
+
+    let filter = if verbose {
+        Targets::new().with_default(LevelFilter::DEBUG)
+    } else {
+        // Failed actions come back as errors that bb prints itself
+        Targets::new()
+            .with_default(LevelFilter::WARN)
+            .with_target("bigbrother_core", LevelFilter::OFF)
+            .with_target("bigbrother_recorder::replay", LevelFilter::OFF)
     };
+    let stderr = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .without_time()
+        .with_target(verbose)
+        .with_filter(filter);
+    let telemetry = otlp.map(|url| telemetry(url.as_deref())).transpose()?;
+    let subscriber = tracing_subscriber::registry().with(stderr);
+    #[cfg(feature = "otlp")]
+    let subscriber = subscriber
+        .with(telemetry.as_ref().map(|t| t.layer().with_filter(LevelFilter::INFO)));
+    subscriber.init();
+    Ok(telemetry)
+}
 
-    if let Err(e) = result {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
-    }
+/// Nothing to flush without the exporter
+#[cfg(not(feature = "otlp"))]
+type Telemetry = std::convert::Infallible;
+
+#[cfg(feature = "otlp")]
+fn telemetry(url: Option<&str>) -> Result<Telemetry> {
+    Telemetry::exporter(url)
+}
+
+#[cfg(not(feature = "otlp"))]
+fn telemetry(_: Option<&str>) -> Result<Telemetry> {
+    anyhow::bail!("--otlp needs the `otlp` feature")
 }
 
 fn run_automation<F>(f: F) -> Result<(), anyhow::Error>
@@ -524,7 +937,8 @@ where F: FnOnce() -> Result<(), anyhow::Error> {
     match f() {
         Ok(()) => Ok(()),
         Err(e) => {
-            if let Some(err) = e.downcast_ref::<Error>() {
+            // With --json, main prints every error
+            if let Some(err) = e.downcast_ref::<Error>().filter(|_| !json()) {
                 print_json(&Output::<()>::err(err.clone()));
             }
             Err(e)
@@ -533,35 +947,98 @@ where F: FnOnce() -> Result<(), anyhow::Error> {
 }
 
 // ══════════════════════════════════════════════════════════════════════════════
-//  macOS automation commands
+//  Automation commands (Desktop facade, all platforms)
 // ══════════════════════════════════════════════════════════════════════════════
 
-#[cfg(target_os = "macos")]
 fn cmd_apps() -> Result<()> {
     let desktop = Desktop::new()?;
     let apps = desktop.apps()?;
-    print_json(&Output::ok(apps));
+    print_ok(apps);
     Ok(())
 }
 
-#[cfg(target_os = "macos")]
 fn cmd_browser() -> Result<()> {
     let desktop = Desktop::new()?;
     let browser = desktop.browser()?;
-    print_json(&Output::ok(browser));
+    print_ok(browser);
     Ok(())
 }
 
-#[cfg(target_os = "macos")]
-fn cmd_tree(app: &str, depth: usize) -> Result<()> {
-    let mut desktop = Desktop::new()?;
-    let tree = desktop.tree(app, depth)?;
-    print_json(&Output::ok(tree));
+/// How `bb tree` prints
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TreeFormat {
+    Json,
+    Indented,
+    Jsonl,
+    Flat,
+}
+
+impl std::str::FromStr for TreeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(TreeFormat::Json),
+            "indented" | "outline" => Ok(TreeFormat::Indented),
+            "jsonl" | "ndjson" => Ok(TreeFormat::Jsonl),
+            "flat" => Ok(TreeFormat::Flat),
+            other => Err(format!("unknown format: {} (json, indented, jsonl or flat)", other)),
+        }
+    }
+}
+
+fn cmd_tree(
+    app: &str,
+    depth: usize,
+    refresh: bool,
+    max_nodes: Option<usize>,
+    start: usize,
+    count: bool,
+    format: TreeFormat,
+) -> Result<()> {
+    if count {
+        let element_count = match Snapshot::load(app, Some(depth)).filter(|_| !refresh) {
+            Some(snapshot) => snapshot.tree.element_count,
+            None => Desktop::new()?.tree_count(app, depth)?,
+        };
+        print_ok(serde_json::json!({"app": app, "element_count": element_count}));
+        return Ok(());
+    }
+    // Later pages come from the tree the first one did, however old
+    let saved = match start {
+        0 => Snapshot::load(app, Some(depth)),
+        _ => Snapshot::saved(app, Some(depth)),
+    };
+    let mut snapshot = match saved.filter(|_| !refresh) {
+        Some(snapshot) => snapshot,
+        None => Snapshot::take(&mut Desktop::new()?, app, depth)?,
+    };
+    snapshot.tree = snapshot.tree.page(start, max_nodes.unwrap_or(usize::MAX));
+    if format == TreeFormat::Json || json() {
+        print_ok(snapshot);
+        return Ok(());
+    }
+    for node in &snapshot.tree.nodes {
+        let text = repl::describe(&node.role, &node.name, &node.title, &node.value);
+        match format {
+            TreeFormat::Indented => say!("{}{} [{}]", "  ".repeat(node.depth), text, node.index),
+            TreeFormat::Flat => say!("{} [{}]", text, node.index),
+            _ => say!("{}", serde_json::to_string(node)?),
+        }
+    }
+    if let Some(next) = snapshot.tree.next.filter(|_| human()) {
+        eprintln!("hint: more from --start {}", next);
+    }
     Ok(())
 }
 
-#[cfg(target_os = "macos")]
-fn cmd_find(selector: &str, app: Option<&str>, timeout: u64) -> Result<()> {
+fn cmd_find(selector: &str, app: Option<&str>, timeout: u64, cached: bool) -> Result<()> {
+    if let (true, Some(app)) = (cached, app) {
+        let selector = Selector::parse(selector)?;
+        let snapshot = Snapshot::get(&mut Desktop::new()?, app)?;
+        print_ok(snapshot.find(&selector));
+        return Ok(());
+    }
     let desktop = Desktop::new()?;
     let desktop = match app {
         Some(a) => desktop.in_app(a),
@@ -570,23 +1047,66 @@ fn cmd_find(selector: &str, app: Option<&str>, timeout: u64) -> Result<()> {
     let loc = desktop.locator(selector)?.timeout(timeout);
     let elements = loc.find_all()?;
     let infos: Vec<_> = elements.iter().map(|e| e.info()).collect();
-    print_json(&Output::ok(infos));
+    print_ok(infos);
     Ok(())
 }
 
-#[cfg(target_os = "macos")]
-fn cmd_click(selector: &str, app: Option<&str>) -> Result<()> {
+fn cmd_focused() -> Result<()> {
+    let desktop = Desktop::new()?;
+    print_ok(desktop.focused()?.info());
+    Ok(())
+}
+
+fn cmd_inspect(at: &str) -> Result<()> {
+    let (x, y) = parse_point(at)?;
+    let desktop = Desktop::new()?;
+    print_ok(desktop.element_at(x, y)?.info());
+    Ok(())
+}
+
+fn parse_point(at: &str) -> Result<(f64, f64)> {
+    at.split_once(',')
+        .and_then(|(x, y)| Some((x.trim().parse::<f64>().ok()?, y.trim().parse::<f64>().ok()?)))
+        .ok_or_else(|| anyhow::anyhow!("Invalid point '{}', expected x,y", at))
+}
+
+fn cmd_click(selector: &str, app: Option<&str>, first: bool, cached: bool) -> Result<()> {
+    if let (true, Some(app)) = (cached, app) {
+        let selector = Selector::parse(selector)?;
+        let mut desktop = Desktop::new()?;
+        let snapshot = Snapshot::get(&mut desktop, app)?;
+        let element = match snapshot.find_one(&selector, first) {
+            Ok(node) => snapshot.resolve(&desktop, node),
+            Err(_) => None,
+        };
+        // Gone or moved since the tree was saved: look again in a new one
+        let element = match element {
+            Some(element) => element,
+            None => {
+                let snapshot = Snapshot::take(&mut desktop, app, snapshot.depth)?;
+                let node = snapshot.find_one(&selector, first)?;
+                snapshot
+                    .resolve(&desktop, node)
+                    .ok_or_else(|| Error::element_not_found(&selector.to_string()))?
+            }
+        };
+        print_ok(element.click()?);
+        return Ok(());
+    }
     let desktop = Desktop::new()?;
     let desktop = match app {
         Some(a) => desktop.in_app(a),
         None => desktop,
     };
-    let result = desktop.locator(selector)?.click()?;
-    print_json(&Output::ok(result));
+    let mut locator = desktop.locator(selector)?;
+    if first {
+        locator = locator.first();
+    }
+    let result = locator.click()?;
+    print_ok(result);
     Ok(())
 }
 
-#[cfg(target_os = "macos")]
 fn cmd_type(text: &str, selector: Option<&str>, app: Option<&str>) -> Result<()> {
     let desktop = Desktop::new()?;
     if let Some(sel) = selector {
@@ -595,15 +1115,14 @@ fn cmd_type(text: &str, selector: Option<&str>, app: Option<&str>) -> Result<()>
             None => desktop,
         };
         let result = desktop.locator(sel)?.type_text(text)?;
-        print_json(&Output::ok(result));
+        print_ok(result);
     } else {
         desktop.type_text(text)?;
-        print_json(&Output::ok(serde_json::json!({"typed": text})));
+        print_ok(serde_json::json!({"typed": text}));
     }
     Ok(())
 }
 
-#[cfg(target_os = "macos")]
 fn cmd_scroll(direction: &str, pages: u32, app: Option<&str>) -> Result<()> {
     let desktop = Desktop::new()?;
     if let Some(a) = app {
@@ -615,20 +1134,64 @@ fn cmd_scroll(direction: &str, pages: u32, app: Option<&str>) -> Result<()> {
         "down" => desktop.scroll_down(pages)?,
         _ => return Err(Error::new(ErrorCode::Unknown, format!("Unknown direction: {}", direction)).into()),
     }
-    print_json(&Output::ok(serde_json::json!({"direction": direction, "pages": pages})));
+    print_ok(serde_json::json!({"direction": direction, "pages": pages}));
+    Ok(())
+}
+
+fn cmd_scrape(app: &str, depth: usize) -> Result<()> {
+    let desktop = Desktop::new()?;
+    let result = desktop.scrape(app, depth)?;
+    print_ok(result);
+    Ok(())
+}
+
+fn cmd_activate(app: &str) -> Result<()> {
+    let desktop = Desktop::new()?;
+    desktop.activate(app)?;
+    print_ok(serde_json::json!({"activated": app}));
+    Ok(())
+}
+
+fn cmd_applescript(script: &str, jxa: bool) -> Result<()> {
+    let script = match script {
+        "-" => io::read_to_string(io::stdin())?,
+        script => script.to_string(),
+    };
+    let desktop = Desktop::new()?;
+    let result = if jxa { desktop.run_jxa(&script)? } else { desktop.run_applescript(&script)? };
+    print_ok(serde_json::json!({"result": result}));
+    Ok(())
+}
+
+fn cmd_run_shortcut(name: &str, input: Option<String>) -> Result<()> {
+    let input = match input.as_deref() {
+        Some("-") => Some(io::read_to_string(io::stdin())?),
+        _ => input,
+    };
+    let input = input.map(|text| match serde_json::from_str(&text) {
+        Ok(value) => value,
+        Err(_) => serde_json::Value::String(text),
+    });
+    let desktop = Desktop::new()?;
+    let output = desktop.run_shortcut(name, input.as_ref())?;
+    print_ok(serde_json::json!({"shortcut": name, "output": output}));
     Ok(())
 }
 
+// ══════════════════════════════════════════════════════════════════════════════
+//  macOS automation commands
+// ══════════════════════════════════════════════════════════════════════════════
+
 #[cfg(target_os = "macos")]
 fn cmd_press(key: &str, repeat: u32, delay: u64) -> Result<()> {
-    let code = key_name_to_code(key).ok_or_else(|| Error::new(ErrorCode::Unknown, format!("Unknown key: {}", key)))?;
+    let code = Key::from_name(key).map(|k| k.to_macos() as u8).ok_or_else(|| Error::new(ErrorCode::Unknown, format!("Unknown key: {}", key)))?;
     for i in 0..repeat {
         input::press_key(code).map_err(Error::from)?;
         if i < repeat - 1 {
             std::thread::sleep(std::time::Duration::from_millis(delay));
         }
     }
-    print_json(&Output::ok(serde_json::json!({"key": key, "repeat": repeat})));
+    print_ok(serde_json::json!({"key": key, "repeat": repeat}));
     Ok(())
 }
 
@@ -663,11 +1226,11 @@ fn cmd_open(url: &str, background: bool) -> Result<()> {
         let desktop = Desktop::new()?;
         desktop.open_url(url)?;
     }
-    print_json(&Output::ok(serde_json::json!({"opened": url, "background": background})));
+    print_ok(serde_json::json!({"opened": url, "background": background}));
     Ok(())
 }
 
-#[cfg(target_os = "macos")]
+#[cfg(any(target_os = "macos", target_os = "windows"))]
 fn cmd_wait(idle: Option<u64>, selector: Option<&str>, app: Option<&str>, timeout: u64) -> Result<()> {
     let desktop = Desktop::new()?;
     let desktop = match app {
@@ -676,31 +1239,25 @@ fn cmd_wait(idle: Option<u64>, selector: Option<&str>, app: Option<&str>, timeou
     };
     if let Some(ms) = idle {
         desktop.wait_idle(ms)?;
-        print_json(&Output::ok(serde_json::json!({"waited_ms": ms})));
+        print_ok(serde_json::json!({"waited_ms": ms}));
     } else if let Some(sel) = selector {
         let element = desktop.locator(sel)?.timeout(timeout).wait()?;
-        print_json(&Output::ok(element.info()));
+        print_ok(element.info());
     } else {
-        print_json(&Output::ok(serde_json::json!({"waited_ms": 0})));
+        print_ok(serde_json::json!({"waited_ms": 0}));
     }
     Ok(())
 }
 
 #[cfg(target_os = "macos")]
 fn cmd_screenshot(output: &str) -> Result<()> {
+    // screencapture succeeds without it, with a blank image
+    bigbrother::ensure_screen_recording()?;
     let status = std::process::Command::new("screencapture")
         .args(["-x", output])
         .status()?;
     if !status.success() { anyhow::bail!("screencapture failed"); }
-    print_json(&Output::ok(serde_json::json!({"path": output})));
-    Ok(())
-}
-
-#[cfg(target_os = "macos")]
-fn cmd_scrape(app: &str, depth: usize) -> Result<()> {
-    let desktop = Desktop::new()?;
-    let result = desktop.scrape(app, depth)?;
-    print_json(&Output::ok(result));
+    print_ok(serde_json::json!({"path": output}));
     Ok(())
 }
 
@@ -714,22 +1271,14 @@ fn cmd_shortcut(key: &str, modifiers: &str) -> Result<()> {
         _ => "command",
     }).collect();
     input::shortcut(key, &mods).map_err(Error::from)?;
-    print_json(&Output::ok(serde_json::json!({"key": key, "modifiers": modifiers})));
-    Ok(())
-}
-
-#[cfg(target_os = "macos")]
-fn cmd_activate(app: &str) -> Result<()> {
-    let desktop = Desktop::new()?;
-    desktop.activate(app)?;
-    print_json(&Output::ok(serde_json::json!({"activated": app})));
+    print_ok(serde_json::json!({"key": key, "modifiers": modifiers}));
     Ok(())
 }
 
 #[cfg(target_os = "macos")]
 fn cmd_click_at(x: i32, y: i32, button: &str) -> Result<()> {
     input::click_at(x, y, button).map_err(Error::from)?;
-    print_json(&Output::ok(serde_json::json!({"clicked": {"x": x, "y": y, "button": button}})));
+    print_ok(serde_json::json!({"clicked": {"x": x, "y": y, "button": button}}));
     Ok(())
 }
 
@@ -742,7 +1291,7 @@ fn cmd_send(text: &str, app: &str, no_enter: bool) -> Result<()> {
     if !no_enter {
         input::press_key(input::key_codes::RETURN).map_err(Error::from)?;
     }
-    print_json(&Output::ok(serde_json::json!({"sent": text, "app": app, "enter": !no_enter})));
+    print_ok(serde_json::json!({"sent": text, "app": app, "enter": !no_enter}));
     Ok(())
 }
 
@@ -751,126 +1300,15 @@ fn cmd_send(text: &str, app: &str, no_enter: bool) -> Result<()> {
 // ══════════════════════════════════════════════════════════════════════════════
 
 #[cfg(target_os = "windows")]
-fn cmd_apps() -> Result<()> {
-    let windows = get_windows()?;
-    let apps: Vec<_> = windows.iter().filter_map(|w| {
-        w.name().map(|n| serde_json::json!({"name": n, "pid": w.process_id()}))
-    }).collect();
-    print_json(&Output::ok(apps));
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-fn cmd_browser() -> Result<()> {
-    let browsers = ["chrome", "firefox", "msedge", "brave", "opera"];
-    for b in &browsers {
-        if let Ok(Some(w)) = find_window(b) {
-            print_json(&Output::ok(serde_json::json!({
-                "name": w.name(),
-                "browser": b,
-                "pid": w.process_id(),
-            })));
-            return Ok(());
-        }
-    }
-    print_json(&Output::ok(serde_json::json!({"browser": serde_json::Value::Null})));
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-fn cmd_tree(app: &str, depth: usize) -> Result<()> {
-    let automation = Automation::new()?;
-    let window = find_app_window(app)?;
-    let walker = automation.tree_walker()?;
-    let tree = build_tree(&walker, &window, 0, depth);
-    let element_count = count_nodes(&tree);
-    print_json(&Output::ok(serde_json::json!({
-        "tree": tree,
-        "element_count": element_count,
-    })));
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-fn count_nodes(node: &TreeNode) -> usize {
-    1 + node.children.iter().map(count_nodes).sum::<usize>()
-}
-
-#[cfg(target_os = "windows")]
-fn cmd_find(selector: &str, app: Option<&str>, _timeout: u64) -> Result<()> {
-    let automation = Automation::new()?;
-    let root = if let Some(a) = app {
-        find_app_window(a)?
-    } else {
-        automation.root()?
-    };
-    let walker = automation.tree_walker()?;
-    let mut results = Vec::new();
-    find_elements_matching(&walker, &root, selector, 30, &mut results, 0);
-    print_json(&Output::ok(results));
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-fn cmd_click(selector: &str, app: Option<&str>) -> Result<()> {
-    let automation = Automation::new()?;
-    let root = if let Some(a) = app {
-        find_app_window(a)?
-    } else {
-        automation.root()?
-    };
-    let walker = automation.tree_walker()?;
-    let mut results = Vec::new();
-    find_elements_matching(&walker, &root, selector, 30, &mut results, 0);
-
-    if results.is_empty() {
-        return Err(Error::new(ErrorCode::ElementNotFound, format!("Element not found: {}", selector)).into());
-    }
-
-    // Use the first match's clickable point or center of bounds
-    let info = &results[0];
-    if let Some((x, y, w, h)) = info.bounds {
-        click_at(x + w / 2, y + h / 2)?;
-        print_json(&Output::ok(serde_json::json!({"clicked": info.name, "at": [x + w/2, y + h/2]})));
-    } else {
-        return Err(Error::new(ErrorCode::ActionFailed, "Element has no bounds".to_string()).into());
-    }
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-fn cmd_type(text: &str, _selector: Option<&str>, _app: Option<&str>) -> Result<()> {
-    type_text(text)?;
-    print_json(&Output::ok(serde_json::json!({"typed": text})));
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-fn cmd_scroll(direction: &str, pages: u32, app: Option<&str>) -> Result<()> {
-    if let Some(a) = app {
-        cmd_activate(a)?;
-        std::thread::sleep(std::time::Duration::from_millis(300));
-    }
-    let delta = match direction.to_lowercase().as_str() {
-        "up" => pages as i32,
-        "down" => -(pages as i32),
-        _ => return Err(Error::new(ErrorCode::Unknown, format!("Unknown direction: {}", direction)).into()),
-    };
-    scroll(delta)?;
-    print_json(&Output::ok(serde_json::json!({"direction": direction, "pages": pages})));
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-fn cmd_press(key: &str, repeat: u32, delay: u64) -> Result<()> {
-    let vk_code = key_name_to_vk(key).ok_or_else(|| Error::new(ErrorCode::Unknown, format!("Unknown key: {}", key)))?;
-    for i in 0..repeat {
-        press_key(vk_code)?;
-        if i < repeat - 1 {
-            std::thread::sleep(std::time::Duration::from_millis(delay));
-        }
-    }
-    print_json(&Output::ok(serde_json::json!({"key": key, "repeat": repeat})));
+fn cmd_press(key: &str, repeat: u32, delay: u64) -> Result<()> {
+    let vk_code = Key::from_name(key).map(Key::to_windows).ok_or_else(|| Error::new(ErrorCode::Unknown, format!("Unknown key: {}", key)))?;
+    for i in 0..repeat {
+        press_key(vk_code)?;
+        if i < repeat - 1 {
+            std::thread::sleep(std::time::Duration::from_millis(delay));
+        }
+    }
+    print_ok(serde_json::json!({"key": key, "repeat": repeat}));
     Ok(())
 }
 
@@ -879,47 +1317,7 @@ fn cmd_open(url: &str, background: bool) -> Result<()> {
     std::process::Command::new("cmd")
         .args(["/c", "start", url])
         .spawn()?;
-    print_json(&Output::ok(serde_json::json!({"opened": url, "background": background})));
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-fn cmd_wait(idle: Option<u64>, selector: Option<&str>, app: Option<&str>, timeout: u64) -> Result<()> {
-    if let Some(ms) = idle {
-        std::thread::sleep(std::time::Duration::from_millis(ms));
-        print_json(&Output::ok(serde_json::json!({"waited_ms": ms})));
-        return Ok(());
-    }
-
-    if let Some(sel) = selector {
-        let automation = Automation::new()?;
-        let start = std::time::Instant::now();
-        loop {
-            let root = if let Some(a) = app {
-                find_app_window(a)?
-            } else {
-                automation.root()?
-            };
-            let walker = automation.tree_walker()?;
-            let mut results = Vec::new();
-            find_elements_matching(&walker, &root, sel, 30, &mut results, 0);
-
-            if !results.is_empty() {
-                print_json(&Output::ok(serde_json::json!({
-                    "found": results.first(),
-                    "waited_ms": start.elapsed().as_millis(),
-                })));
-                return Ok(());
-            }
-
-            if start.elapsed().as_millis() > timeout as u128 {
-                return Err(Error::new(ErrorCode::Timeout, format!("Timed out waiting for: {}", sel)).into());
-            }
-            std::thread::sleep(std::time::Duration::from_millis(500));
-        }
-    }
-
-    print_json(&Output::ok(serde_json::json!({"waited_ms": 0})));
+    print_ok(serde_json::json!({"opened": url, "background": background}));
     Ok(())
 }
 
@@ -936,76 +1334,16 @@ fn cmd_screenshot(output: &str) -> Result<()> {
     if !status.success() {
         anyhow::bail!("screenshot capture failed");
     }
-    print_json(&Output::ok(serde_json::json!({"path": output})));
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-fn cmd_scrape(app: &str, depth: usize) -> Result<()> {
-    let automation = Automation::new()?;
-    let window = find_app_window(app)?;
-    let walker = automation.tree_walker()?;
-    let mut items = Vec::new();
-    collect_text(&walker, &window, 0, depth, &mut items);
-    print_json(&Output::ok(serde_json::json!({"items": items})));
+    print_ok(serde_json::json!({"path": output}));
     Ok(())
 }
 
 #[cfg(target_os = "windows")]
 fn cmd_shortcut(key: &str, modifiers: &str) -> Result<()> {
-    let key_vk = key_name_to_vk(key).ok_or_else(|| Error::new(ErrorCode::Unknown, format!("Unknown key: {}", key)))?;
+    let key_vk = Key::from_name(key).map(Key::to_windows).ok_or_else(|| Error::new(ErrorCode::Unknown, format!("Unknown key: {}", key)))?;
     let mod_vks: Vec<u16> = modifiers.split(',').map(|m| modifier_name_to_vk(m)).collect();
     shortcut(key_vk, &mod_vks)?;
-    print_json(&Output::ok(serde_json::json!({"key": key, "modifiers": modifiers})));
-    Ok(())
-}
-
-#[cfg(target_os = "windows")]
-fn cmd_activate(app: &str) -> Result<()> {
-    use windows::Win32::UI::WindowsAndMessaging::{SetForegroundWindow, ShowWindow, SW_RESTORE};
-
-    let window = find_app_window(app)?;
-    if let Some((x, y, _, _)) = window.bounds() {
-        // Get the HWND by finding the window via UI Automation
-        // Use SetForegroundWindow via element's native handle
-        // Fallback: click the window center
-        if let Some((cx, cy)) = window.clickable_point() {
-            // Use Windows API to bring window to front
-            unsafe {
-                // Get the window handle from the element's process
-                let pid = window.process_id() as u32;
-                use windows::Win32::UI::WindowsAndMessaging::{
-                    EnumWindows, GetWindowThreadProcessId, IsWindowVisible, WNDENUMPROC,
-                };
-                use windows::Win32::Foundation::{BOOL, HWND, LPARAM};
-
-                // Find HWND by PID
-                static mut TARGET_PID: u32 = 0;
-                static mut FOUND_HWND: isize = 0;
-                TARGET_PID = pid;
-                FOUND_HWND = 0;
-
-                unsafe extern "system" fn enum_callback(hwnd: HWND, _: LPARAM) -> BOOL {
-                    let mut proc_pid: u32 = 0;
-                    GetWindowThreadProcessId(hwnd, Some(&mut proc_pid));
-                    if proc_pid == TARGET_PID && IsWindowVisible(hwnd).as_bool() {
-                        FOUND_HWND = hwnd.0 as isize;
-                        return BOOL(0); // stop enumerating
-                    }
-                    BOOL(1)
-                }
-
-                let _ = EnumWindows(Some(enum_callback), LPARAM(0));
-
-                if FOUND_HWND != 0 {
-                    let hwnd = HWND(FOUND_HWND as *mut _);
-                    let _ = ShowWindow(hwnd, SW_RESTORE);
-                    let _ = SetForegroundWindow(hwnd);
-                }
-            }
-        }
-    }
-    print_json(&Output::ok(serde_json::json!({"activated": app})));
+    print_ok(serde_json::json!({"key": key, "modifiers": modifiers}));
     Ok(())
 }
 
@@ -1019,19 +1357,100 @@ fn cmd_click_at(x: i32, y: i32, button: &str) -> Result<()> {
         "middle" => middle_click()?,
         _ => click()?,
     }
-    print_json(&Output::ok(serde_json::json!({"clicked": {"x": x, "y": y, "button": button}})));
+    print_ok(serde_json::json!({"clicked": {"x": x, "y": y, "button": button}}));
     Ok(())
 }
 
 #[cfg(target_os = "windows")]
 fn cmd_send(text: &str, app: &str, no_enter: bool) -> Result<()> {
-    cmd_activate(app)?;
+    Desktop::new()?.activate(app)?;
     std::thread::sleep(std::time::Duration::from_millis(300));
     type_text(text)?;
     if !no_enter {
         press_key(vk::RETURN)?;
     }
-    print_json(&Output::ok(serde_json::json!({"sent": text, "app": app, "enter": !no_enter})));
+    print_ok(serde_json::json!({"sent": text, "app": app, "enter": !no_enter}));
+    Ok(())
+}
+
+// ══════════════════════════════════════════════════════════════════════════════
+//  Linux automation commands (no accessibility backend yet)
+// ══════════════════════════════════════════════════════════════════════════════
+
+#[cfg(target_os = "linux")]
+fn linux_unsupported(command: &str) -> Result<()> {
+    Err(Error::new(
+        ErrorCode::NotImplemented,
+        format!("'{}' is not supported on Linux yet (record and replay are)", command),
+    )
+    .into())
+}
+
+#[cfg(target_os = "linux")]
+fn cmd_press(_key: &str, _repeat: u32, _delay: u64) -> Result<()> { linux_unsupported("press") }
+
+#[cfg(target_os = "linux")]
+fn cmd_open(url: &str, background: bool) -> Result<()> {
+    std::process::Command::new("xdg-open").arg(url).spawn()?;
+    print_ok(serde_json::json!({"opened": url, "background": background}));
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn cmd_wait(idle: Option<u64>, selector: Option<&str>, _app: Option<&str>, _timeout: u64) -> Result<()> {
+    if selector.is_some() {
+        return linux_unsupported("wait --selector");
+    }
+    let ms = idle.unwrap_or(0);
+    std::thread::sleep(std::time::Duration::from_millis(ms));
+    print_ok(serde_json::json!({"waited_ms": ms}));
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn cmd_screenshot(_output: &str) -> Result<()> { linux_unsupported("screenshot") }
+
+#[cfg(target_os = "linux")]
+fn cmd_shortcut(_key: &str, _modifiers: &str) -> Result<()> { linux_unsupported("shortcut") }
+
+#[cfg(target_os = "linux")]
+fn cmd_click_at(_x: i32, _y: i32, _button: &str) -> Result<()> { linux_unsupported("click-at") }
+
+#[cfg(target_os = "linux")]
+fn cmd_send(_text: &str, _app: &str, _no_enter: bool) -> Result<()> { linux_unsupported("send") }
+
+// ── Batch ───────────────────────────────────────────────────────────────────
+
+/// Progress goes to stderr, the results to stdout; a failed step fails the
+/// run with its error, the results as context
+fn cmd_run(file: &std::path::Path) -> Result<()> {
+    let text = std::fs::read_to_string(file)
+        .map_err(|e| anyhow::anyhow!("Can't read {}: {}", file.display(), e))?;
+    // A file run by hand is as trusted as a shell script
+    let batch = Batch::parse(&text)?.allow_exec(true);
+    let desktop = Desktop::new()?;
+    let total = batch.steps.len();
+    let result = batch.run_with(&desktop, |step| {
+        if !human() {
+            return;
+        }
+        let status = if step.ok { "ok" } else { "FAILED" };
+        eprint!("[{}/{}] {} {} ({}ms", step.index + 1, total, step.step, status, step.ms);
+        if step.attempts > 1 {
+            eprint!(", {} attempts", step.attempts);
+        }
+        eprintln!(")");
+    });
+    if let Some(failed) = result.steps.iter().find(|s| !s.ok) {
+        let error = failed.error.clone().unwrap_or_else(|| Error::new(ErrorCode::Unknown, ""));
+        return Err(Error {
+            message: format!("Step {} ({}): {}", failed.index + 1, failed.step, error.message),
+            context: Some(serde_json::to_value(&result)?),
+            ..error
+        }
+        .into());
+    }
+    print_ok(result);
     Ok(())
 }
 
@@ -1094,17 +1513,25 @@ fn cmd_web(action: WebAction) -> Result<()> {
         Ok(s) if s.success() => Ok(()),
         Ok(s) => {
             let code = s.code().unwrap_or(1);
-            match code {
-                2 => eprintln!("hint: run 'bb web profiles' to see available browser profiles"),
-                3 => eprintln!("hint: install Chrome or run 'npx playwright install chromium'"),
-                4 => eprintln!("hint: allow keychain access when prompted by the system"),
-                _ => {}
+            let hint = match code {
+                2 => Some("run 'bb web profiles' to see available browser profiles"),
+                3 => Some("install Chrome or run 'npx playwright install chromium'"),
+                4 => Some("allow keychain access when prompted by the system"),
+                _ => None,
+            };
+            if json() {
+                let message = format!("bb-browser exited with code {}", code);
+                let err = Error::new(ErrorCode::ActionFailed, message)
+                    .with_suggestions(hint.into_iter().map(String::from).collect());
+                print_json(&Output::<()>::err(err));
+            } else if let Some(hint) = hint {
+                eprintln!("hint: {}", hint);
             }
             std::process::exit(code);
         }
         Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-            eprintln!("Error: 'node' not found. Install Node.js (https://nodejs.org) or Bun (https://bun.sh)");
-            std::process::exit(1);
+            let install = "Install Node.js (https://nodejs.org) or Bun (https://bun.sh)";
+            anyhow::bail!("'node' not found. {}", install)
         }
         Err(e) => Err(e.into()),
     }
@@ -1162,7 +1589,7 @@ fn cmd_wezterm(action: WeztermAction) -> Result<()> {
                     Ok(out) => {
                         let json: serde_json::Value = serde_json::from_slice(&out.stdout)
                             .unwrap_or(serde_json::json!({"raw": String::from_utf8_lossy(&out.stdout)}));
-                        print_json(&Output::ok(json));
+                        print_ok(json);
                     }
                     Err(e) => print_json(&Output::<()>::err(Error::new(ErrorCode::Unknown, format!("{}", e)))),
                 }
@@ -1178,7 +1605,7 @@ fn cmd_wezterm(action: WeztermAction) -> Result<()> {
                     if !no_enter {
                         input::press_key(input::key_codes::RETURN).map_err(Error::from)?;
                     }
-                    print_json(&Output::ok(serde_json::json!({"pane_id": pane_id, "sent": text})));
+                    print_ok(serde_json::json!({"pane_id": pane_id, "sent": text}));
                     Ok(())
                 });
             }
@@ -1187,7 +1614,7 @@ fn cmd_wezterm(action: WeztermAction) -> Result<()> {
                     .args(["cli", "activate-pane", "--pane-id", &pane_id.to_string()])
                     .output()
                 {
-                    Ok(_) => print_json(&Output::ok(serde_json::json!({"focused": pane_id}))),
+                    Ok(_) => print_ok(serde_json::json!({"focused": pane_id})),
                     Err(e) => print_json(&Output::<()>::err(Error::new(ErrorCode::Unknown, format!("{}", e)))),
                 }
             }
@@ -1203,75 +1630,450 @@ fn cmd_wezterm(action: WeztermAction) -> Result<()> {
 
 // ── Recording Functions (cross-platform) ────────────────────────────────────
 
-fn record(name: &str, capture_context: bool, threshold: f64) -> Result<()> {
+fn record(args: RecordArgs) -> Result<()> {
+    let name = args.name.as_str();
     let config = RecorderConfig {
-        capture_context,
-        mouse_move_threshold: threshold,
+        capture_context: !args.no_context,
+        mouse_move_threshold: args.threshold,
+        app_allowlist: args.allow_apps,
+        app_blocklist: args.block_apps,
+        redactors: if args.scrub { default_redactors() } else { Vec::new() },
+        capture_screenshots: args.screenshots,
+        clipboard_preview_len: args.clipboard_preview,
+        save_clipboard: args.save_clipboard,
+        watch_dirs: args.watch_dirs,
+        #[cfg(feature = "video")]
+        record_video: args.video,
+        annotation_hotkey: args.annotate_key,
+        pause_hotkey: args.pause_key,
+        stop_hotkey: args.stop_key,
+        absolute_timestamps: args.absolute_timestamps,
+        collapse_idle_ms: args.collapse_idle,
+        max_duration: args.max_duration.map(std::time::Duration::from_secs),
+        max_events: args.max_events,
+        rotate: args.rotate,
+        overflow: if args.spill {
+            Overflow::Disk(std::env::temp_dir().join(format!("bb-record-{}.jsonl", std::process::id())))
+        } else {
+            Overflow::Drop
+        },
         ..Default::default()
     };
+    let screen = config.captures_screen();
     let recorder = WorkflowRecorder::with_config(config);
     let perms = recorder.check_permissions();
-    if !perms.accessibility {
-        eprintln!("Accessibility permission required.");
+    if !perms.all_granted() || (screen && !perms.screen_recording) {
+        // In order, so screen recording only when the others are there
+        let missing = perms.missing().next();
+        let missing = missing.map_or("Screen Recording", |p| permission_label(p.name));
         recorder.request_permissions();
-        return Ok(());
+        return Err(missing_permission(missing).into());
     }
-    if !perms.input_monitoring {
-        eprintln!("Input Monitoring permission required.");
-        recorder.request_permissions();
-        return Ok(());
+    say!("Recording: {} (Ctrl+C to stop)", name);
+    if let Some(hotkey) = args.annotate_key {
+        say!("Press {} to annotate", hotkey);
+    }
+    if let Some(hotkey) = args.pause_key {
+        say!("Press {} to pause or resume", hotkey);
+    }
+    if let Some(hotkey) = args.stop_key {
+        say!("Press {} to stop", hotkey);
+    }
+    let storage = WorkflowStorage::new()?.format(args.format).compression(args.compress);
+    let (mut workflow, mut handle) = recorder.start(name)?;
+    workflow.description = args.description.clone();
+    workflow.tags = args.tags.clone();
+    if args.stream || args.rotate {
+        handle.stream_to(storage.writer(&workflow)?);
     }
-    println!("Recording: {} (Ctrl+C to stop)", name);
-    let (mut workflow, handle) = recorder.start(name)?;
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
     ctrlc::set_handler(move || { r.store(false, Ordering::SeqCst); })?;
     let mut count = 0;
+    let mut rotated = Vec::new();
     while running.load(Ordering::SeqCst) && handle.is_running() {
         handle.drain(&mut workflow);
-        if workflow.events.len() != count {
-            count = workflow.events.len();
+        for path in handle.take_rotated() {
+            say!("\nSaved: {}", path.display());
+            rotated.push(path);
+        }
+        let sent = handle.stats().sent;
+        if sent != count && human() {
+            count = sent;
             print!("\r{} events", count);
             io::stdout().flush()?;
         }
         std::thread::sleep(std::time::Duration::from_millis(50));
     }
-    handle.stop(&mut workflow);
-    println!("\n{} events recorded", workflow.events.len());
+    let stats = handle.stats();
+    let health = handle.health();
+    let streamed = handle.stop(&mut workflow);
+    if stats.dropped > 0 {
+        eprintln!("\nWarning: {} events dropped (buffer full), try --spill", stats.dropped);
+    }
+    if health.interruptions > 0 {
+        eprintln!("\nWarning: input capture was cut off {} times, events are missing", health.interruptions);
+    }
+    let path = match streamed {
+        Some(path) => {
+            say!("\nSaved: {}", path.display());
+            path
+        }
+        // Not streaming, or streaming failed partway and the rest is in memory
+        None => {
+            say!("\n{} events recorded", workflow.events.len());
+            let path = storage.save(&workflow)?;
+            say!("Saved: {}", path.display());
+            path
+        }
+    };
+    report(serde_json::json!({
+        "path": path,
+        "rotated": rotated,
+        "events": stats.sent,
+        "dropped": stats.dropped,
+        "interruptions": health.interruptions,
+    }));
+    Ok(())
+}
+
+fn missing_permission(permission: &str) -> Error {
+    Error::permission_denied(format!("{} permission required", permission))
+        .with_suggestions(vec!["bb permissions --request".to_string()])
+}
+
+fn replay(args: ReplayArgs) -> Result<()> {
     let storage = WorkflowStorage::new()?;
-    let path = storage.save(&workflow)?;
-    println!("Saved: {}", path.display());
+    let workflow = storage.load(&args.file)?;
+    say!("Replaying {} ({} events) at {}x speed...", workflow.name, workflow.events.len(), args.speed);
+    if let Some(url) = &args.remote {
+        say!("Injecting through the agent at {}", url);
+    }
+    if !args.no_guard {
+        say!("Move the mouse or press Escape to abort.");
+    }
+    // Stepping starts with a prompt, scheduled runs have no one to warn
+    if !args.step && args.every.is_none() {
+        say!("Starting in 2 seconds...");
+        std::thread::sleep(std::time::Duration::from_secs(2));
+    }
+    let sync = (args.sync_timeout > 0).then(|| std::time::Duration::from_millis(args.sync_timeout));
+    // Ctrl+C stops cleanly, releasing any keys the replay holds down
+    let cancel = CancellationToken::new();
+    let token = cancel.clone();
+    ctrlc::set_handler(move || token.cancel())?;
+    let replayer = match &args.remote {
+        Some(url) => {
+            let path = args.token_file.as_ref().ok_or_else(|| anyhow::anyhow!("No --token-file"))?;
+            let token = std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("Can't read the token in {}: {}", path.display(), e))?;
+            Replayer::with_transport(serve::Remote::connect(url, token.trim())?)
+        }
+        None => Replayer::new(),
+    };
+    let mut replayer = replayer
+        .cancel_with(cancel.clone())
+        .speed(args.speed)
+        .sync(sync)
+        .activate_apps(!args.no_activate)
+        .abort_on_input(!args.no_guard)
+        .strict_apps(args.strict)
+        .fail_fast(args.fail_fast)
+        .fast_forward_moves(args.fast_moves)
+        .breakpoints(args.breakpoints)
+        .with_vars(args.vars);
+    if args.step {
+        replayer = replayer.step_mode();
+    }
+    let from = args.from.as_deref().map(|s| s.parse::<usize>().map_err(|_| s));
+    let to = args.to.as_deref().map(|s| s.parse::<usize>().map_err(|_| s));
+    let start = match from { Some(Ok(i)) => i, _ => 0 };
+    let end = match to { Some(Ok(i)) => i, _ => usize::MAX };
+    replayer = replayer.range(start, end);
+    if let Some(Err(label)) = from { replayer = replayer.from_label(label); }
+    if let Some(Err(label)) = to { replayer = replayer.to_label(label); }
+    if let Some(ms) = args.max_wait {
+        replayer = replayer.max_wait(std::time::Duration::from_millis(ms));
+    }
+    if let Some(ms) = args.min_click_delay {
+        replayer = replayer.min_delay_between_clicks(std::time::Duration::from_millis(ms));
+    }
+    if let Some(cps) = args.typing_cps {
+        replayer = replayer.typing_cps(cps);
+    }
+    if let Some(ms) = args.key_delay {
+        replayer = replayer.key_delay(std::time::Duration::from_millis(ms));
+    }
+    if let Some(chars) = args.paste_over {
+        replayer = replayer.paste_text_over(chars);
+    }
+    if let Some(n) = args.repeat {
+        replayer = replayer.repeat(n);
+    }
+    if args.retry > 0 || args.relocate || args.skip_failed || args.failure_screenshot.is_some() {
+        let (retry, relocate, skip) = (args.retry, args.relocate, args.skip_failed);
+        let screenshot = args.failure_screenshot;
+        replayer = replayer.on_failure(move |failure| match failure.attempt {
+            n if n <= retry => Recovery::Retry(retry),
+            n if n == retry + 1 && relocate => Recovery::Relocate,
+            _ if skip => Recovery::Skip,
+            _ => screenshot.clone().map_or(Recovery::Abort, Recovery::Screenshot),
+        });
+    }
+    let Some(schedule) = args.every else {
+        return report_replay(&replayer.play(&workflow)?);
+    };
+    loop {
+        let now = chrono::Local::now();
+        let next = schedule
+            .next_after(now)
+            .ok_or_else(|| anyhow::anyhow!("The schedule never fires"))?;
+        say!("Next replay at {} (Ctrl+C to stop)", next.format("%a %Y-%m-%d %H:%M"));
+        while chrono::Local::now() < next && !cancel.is_cancelled() {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+        }
+        if cancel.is_cancelled() {
+            return Ok(());
+        }
+        // A failed run doesn't stop the schedule
+        if let Err(e) = replayer.play(&workflow).and_then(|stats| report_replay(&stats)) {
+            if cancel.is_cancelled() {
+                return Ok(());
+            }
+            if json() {
+                print_json(&error_output(&e));
+            } else {
+                eprintln!("Replay failed: {:#}", e);
+            }
+        }
+    }
+}
+
+/// Print replay stats and assertion results; fails if an assertion did,
+/// with the stats as the error's context
+fn report_replay(stats: &ReplayStats) -> Result<()> {
+    if stats.runs > 1 {
+        say!("{} runs", stats.runs);
+    }
+    say!("Done! {} app switches, {} clicks, {} keys, {} chars typed", stats.apps, stats.clicks, stats.keys, stats.text_chars);
+    if stats.sync_timeouts > 0 {
+        eprintln!("Warning: {} waits for an app or element timed out", stats.sync_timeouts);
+    }
+    if stats.recoveries > 0 {
+        eprintln!("Recovered from {} failed events", stats.recoveries);
+    }
+    for a in &stats.assertions {
+        let status = if a.passed { "PASS" } else { "FAIL" };
+        match &a.found {
+            Some(found) => say!("{} [{}] {} (found {:?})", status, a.index, a.assertion, found),
+            None => say!("{} [{}] {}", status, a.index, a.assertion),
+        }
+    }
+    let data = replay_json(stats);
+    if !stats.passed() {
+        let failed = stats.assertions.iter().filter(|a| !a.passed).count();
+        let message = format!("{} of {} assertions failed", failed, stats.assertions.len());
+        return Err(Error::new(ErrorCode::ActionFailed, message).with_context(data).into());
+    }
+    report(data);
     Ok(())
 }
 
-fn replay(file: &str, speed: f64) -> Result<()> {
+fn replay_json(stats: &ReplayStats) -> serde_json::Value {
+    let assertions: Vec<_> = stats.assertions.iter().map(|a| serde_json::json!({
+        "index": a.index,
+        "run": a.run,
+        "assertion": a.assertion.to_string(),
+        "passed": a.passed,
+        "found": a.found,
+    })).collect();
+    serde_json::json!({
+        "runs": stats.runs,
+        "apps": stats.apps,
+        "clicks": stats.clicks,
+        "moves": stats.moves,
+        "drags": stats.drags,
+        "scrolls": stats.scrolls,
+        "keys": stats.keys,
+        "text_chars": stats.text_chars,
+        "sync_timeouts": stats.sync_timeouts,
+        "recoveries": stats.recoveries,
+        "assertions": assertions,
+    })
+}
+
+fn plan(file: &str, run: bool) -> Result<()> {
     let storage = WorkflowStorage::new()?;
-    let workflow = storage.load(file)?;
-    println!("Replaying {} ({} events) at {}x speed...", workflow.name, workflow.events.len(), speed);
-    println!("Starting in 2 seconds...");
-    std::thread::sleep(std::time::Duration::from_secs(2));
-    let replayer = Replayer::new().speed(speed);
-    let stats = replayer.play(&workflow)?;
-    println!("Done! {} clicks, {} keys, {} chars typed", stats.clicks, stats.keys, stats.text_chars);
+    let plan = bigbrother::plan::compile(&storage.load(file)?);
+    if !run {
+        match json() {
+            true => report(&plan),
+            false => println!("{}", serde_json::to_string_pretty(&plan)?),
+        }
+        return Ok(());
+    }
+    let desktop = Desktop::new()?;
+    for (i, step) in plan.steps.iter().enumerate() {
+        say!("[{}/{}] {}", i + 1, plan.steps.len(), step);
+        step.run(&desktop)?;
+    }
+    say!("Done! {} steps", plan.steps.len());
+    report(serde_json::json!({"steps": plan.steps.len()}));
     Ok(())
 }
 
-fn list() -> Result<()> {
+fn export(file: &str, format: ExportFormat, output: Option<std::path::PathBuf>) -> Result<()> {
     let storage = WorkflowStorage::new()?;
-    let files = storage.list()?;
-    if files.is_empty() { println!("No workflows saved."); } else { for f in files { println!("{}", f); } }
+    let format = match format {
+        ExportFormat::Script(format) => format,
+        ExportFormat::Table(format) => return export_table(&storage, file, format, output),
+    };
+    let plan = bigbrother::plan::compile(&storage.load(file)?);
+    let script = bigbrother::export::script(&plan, format);
+    match output {
+        Some(path) => {
+            std::fs::write(&path, script)?;
+            say!("Saved: {}", path.display());
+            report(serde_json::json!({"path": path}));
+        }
+        None if json() => report(serde_json::json!({"script": script})),
+        None => print!("{}", script),
+    }
     Ok(())
 }
 
-fn show(file: &str, all: bool) -> Result<()> {
+fn export_table(
+    storage: &WorkflowStorage,
+    file: &str,
+    format: TableFormat,
+    output: Option<std::path::PathBuf>,
+) -> Result<()> {
+    match output {
+        Some(path) => {
+            let w = std::io::BufWriter::new(std::fs::File::create(&path)?);
+            let rows = storage.export_table(file, format, w)?;
+            say!("Saved: {} ({} rows)", path.display(), rows);
+            report(serde_json::json!({"path": path, "rows": rows}));
+        }
+        None if format == TableFormat::Csv && json() => {
+            let mut csv = Vec::new();
+            let rows = storage.export_table(file, format, &mut csv)?;
+            report(serde_json::json!({"csv": String::from_utf8(csv)?, "rows": rows}));
+        }
+        None if format == TableFormat::Csv => {
+            storage.export_table(file, format, std::io::stdout())?;
+        }
+        None => anyhow::bail!("Pass -o to write {}", format.extension()),
+    }
+    Ok(())
+}
+
+fn dataset(
+    files: &[String],
+    schema: bigbrother::recorder::training::Schema,
+    history: usize,
+    negatives: usize,
+    output: Option<std::path::PathBuf>,
+) -> Result<()> {
+    use bigbrother::recorder::training::TrainingExporter;
+    let storage = WorkflowStorage::new()?;
+    let exporter = TrainingExporter::new().schema(schema).history(history).negatives(negatives);
+    let mut jsonl = Vec::new();
+    let mut examples = 0;
+    for file in files {
+        examples += exporter.export(&storage.load(file)?, &mut jsonl)?;
+    }
+    match output {
+        Some(path) => {
+            std::fs::write(&path, jsonl)?;
+            say!("Saved: {} ({} examples)", path.display(), examples);
+            report(serde_json::json!({"path": path, "examples": examples}));
+        }
+        None if json() => {
+            let lines = String::from_utf8(jsonl)?;
+            let rendered: Vec<serde_json::Value> =
+                lines.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+            report(serde_json::json!({"examples": rendered}));
+        }
+        None => io::stdout().write_all(&jsonl)?,
+    }
+    Ok(())
+}
+
+fn list(tags: Vec<String>, app: Option<String>, search: Option<String>) -> Result<()> {
+    let storage = WorkflowStorage::new()?;
+    let mut query = WorkflowQuery::new();
+    for tag in tags {
+        query = query.tag(tag);
+    }
+    if let Some(app) = app {
+        query = query.app(app);
+    }
+    if let Some(text) = search {
+        query = query.text(text);
+    }
+    let found = storage.search(&query)?;
+    if !human() {
+        report(found);
+        return Ok(());
+    }
+    if found.is_empty() {
+        println!("No workflows found.");
+    }
+    for info in found {
+        let mut line = info.file;
+        if let Some(ms) = info.duration {
+            line.push_str(&format!("  {}", format_duration(ms)));
+        }
+        if let Some(events) = info.events {
+            line.push_str(&format!("  {} events", events));
+        }
+        if !info.tags.is_empty() {
+            line.push_str(&format!("  [{}]", info.tags.join(", ")));
+        }
+        if !info.apps.is_empty() {
+            line.push_str(&format!("  {}", info.apps.join(", ")));
+        }
+        println!("{}", line);
+        if let Some(description) = info.description {
+            println!("    {}", description);
+        }
+    }
+    Ok(())
+}
+
+/// `1h02m`, `3m05s` or `12s`
+fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m{:02}s", m, s),
+        (h, m, _) => format!("{}h{:02}m", h, m),
+    }
+}
+
+fn summarize(file: &str, max_tokens: Option<usize>, max_text: usize) -> Result<()> {
+    use bigbrother::recorder::summary::Summarizer;
+    let workflow = WorkflowStorage::new()?.load(file)?;
+    let mut summarizer = Summarizer::new().max_text(max_text);
+    if let Some(max) = max_tokens {
+        summarizer = summarizer.max_tokens(max);
+    }
+    let summary = summarizer.summarize(&workflow);
+    say!("{}", summary);
+    let (narrative, tokens) = (summary.to_string(), summary.tokens());
+    report(serde_json::json!({"summary": summary, "narrative": narrative, "tokens": tokens}));
+    Ok(())
+}
+
+fn show(file: &str, all: bool, actions: bool) -> Result<()> {
     let storage = WorkflowStorage::new()?;
     let workflow = storage.load(file)?;
-    println!("Name: {}", workflow.name);
-    println!("Events: {}", workflow.events.len());
-    let (mut clicks, mut moves, mut scrolls, mut keys, mut text, mut apps, mut windows, mut pastes) = (0,0,0,0,0,0,0,0);
+    let (mut clicks, mut drags, mut moves, mut scrolls, mut keys, mut text, mut apps, mut windows, mut pastes) = (0,0,0,0,0,0,0,0,0);
     for e in &workflow.events {
         match &e.data {
             bigbrother::EventData::Click { .. } => clicks += 1,
+            bigbrother::EventData::Drag { .. } => drags += 1,
             bigbrother::EventData::Move { .. } => moves += 1,
             bigbrother::EventData::Scroll { .. } => scrolls += 1,
             bigbrother::EventData::Key { .. } => keys += 1,
@@ -1282,7 +2084,57 @@ fn show(file: &str, all: bool) -> Result<()> {
             _ => {}
         }
     }
-    println!("\nSummary: {} clicks, {} moves, {} scrolls, {} keys, {} text, {} apps, {} windows, {} clipboard", clicks, moves, scrolls, keys, text, apps, windows, pastes);
+    let annotations: Vec<_> = workflow.events.iter().filter_map(|e| match &e.data {
+        bigbrother::EventData::Annotation { s } => Some((e.t, s)),
+        _ => None,
+    }).collect();
+    if !human() {
+        let annotations: Vec<_> =
+            annotations.iter().map(|(t, s)| serde_json::json!({"t": t, "text": s})).collect();
+        let mut data = serde_json::json!({
+            "name": workflow.name,
+            "description": workflow.description,
+            "tags": workflow.tags,
+            "events": workflow.events.len(),
+            "duration": workflow.duration(),
+            "meta": workflow.meta,
+            "summary": {
+                "clicks": clicks, "drags": drags, "moves": moves, "scrolls": scrolls, "keys": keys,
+                "text": text, "apps": apps, "windows": windows, "clipboard": pastes,
+            },
+            "annotations": annotations,
+        });
+        if actions {
+            data["actions"] = bigbrother::recorder::semantics::actions(&workflow)
+                .iter()
+                .map(|action| serde_json::json!({"t": action.t(), "action": action.to_string()}))
+                .collect();
+        }
+        if all {
+            data["all"] = serde_json::to_value(&workflow.events)?;
+        }
+        report(data);
+        return Ok(());
+    }
+    println!("Name: {}", workflow.name);
+    if let Some(description) = &workflow.description {
+        println!("Description: {}", description);
+    }
+    if !workflow.tags.is_empty() {
+        println!("Tags: {}", workflow.tags.join(", "));
+    }
+    println!("Events: {} over {}", workflow.events.len(), format_duration(workflow.duration()));
+    if let Some(meta) = &workflow.meta {
+        println!("Recorded: {} on {} {}", meta.started_at.to_rfc3339(), meta.os, meta.os_version.as_deref().unwrap_or(""));
+        for d in &meta.displays { println!("Display: {}x{} at ({}, {}) scale {}", d.w, d.h, d.x, d.y, d.scale); }
+        if let Some(layout) = &meta.keyboard_layout { println!("Keyboard: {}", layout); }
+    }
+    println!("\nSummary: {} clicks, {} drags, {} moves, {} scrolls, {} keys, {} text, {} apps, {} windows, {} clipboard", clicks, drags, moves, scrolls, keys, text, apps, windows, pastes);
+    for (t, s) in annotations { println!("  [{}ms] {}", t, s); }
+    if actions {
+        println!("\nActions:");
+        for action in bigbrother::recorder::semantics::actions(&workflow) { println!("  [{}ms] {}", action.t(), action); }
+    }
     if all { for (i, e) in workflow.events.iter().enumerate() { println!("{}: {:?}", i, e); } }
     Ok(())
 }
@@ -1290,15 +2142,246 @@ fn show(file: &str, all: bool) -> Result<()> {
 fn delete(file: &str) -> Result<()> {
     let storage = WorkflowStorage::new()?;
     storage.delete(file)?;
-    println!("Deleted: {}", file);
+    say!("Deleted: {}", file);
+    report(serde_json::json!({"deleted": file}));
+    Ok(())
+}
+
+fn recover() -> Result<()> {
+    let storage = WorkflowStorage::new()?;
+    let recovered = storage.recover()?;
+    if recovered.is_empty() { say!("Nothing to recover."); }
+    for path in &recovered { say!("Recovered: {}", path.display()); }
+    report(serde_json::json!({"recovered": recovered}));
+    Ok(())
+}
+
+fn edit(
+    file: &str,
+    mut deletes: Vec<std::ops::Range<usize>>,
+    retimes: Vec<(std::ops::Range<usize>, f64)>,
+    trim_idle: bool,
+    strip_moves: bool,
+    merge_text: bool,
+    screenpipe: Option<Option<std::path::PathBuf>>,
+) -> Result<()> {
+    let storage = WorkflowStorage::new()?;
+    let mut workflow = storage.load(file)?;
+    let before = workflow.events.len();
+    if let Some(db) = screenpipe {
+        let added = screen_text(&mut workflow, db)?;
+        say!("Screen text for {} events", added);
+    }
+    // Indices refer to the file as given: retime first, then delete the
+    // last ranges first
+    let mut editor = WorkflowEditor::new(workflow);
+    for (range, factor) in retimes {
+        editor = editor.retime(range, factor);
+    }
+    deletes.sort_by_key(|range| std::cmp::Reverse(range.start));
+    for range in deletes {
+        editor = editor.delete(range);
+    }
+    if strip_moves {
+        editor = editor.strip_moves();
+    }
+    if merge_text {
+        editor = editor.merge_text();
+    }
+    if trim_idle {
+        editor = editor.trim_idle();
+    }
+    let workflow = editor.finish();
+    let path = storage.save(&workflow)?;
+    say!("{} -> {} events", before, workflow.events.len());
+    say!("Saved: {}", path.display());
+    report(serde_json::json!({"path": path, "before": before, "events": workflow.events.len()}));
+    Ok(())
+}
+
+#[cfg(feature = "screenpipe")]
+fn screen_text(workflow: &mut RecordedWorkflow, db: Option<std::path::PathBuf>) -> Result<usize> {
+    use bigbrother::recorder::screenpipe::Screenpipe;
+    let screenpipe = match db {
+        Some(db) => Screenpipe::open_at(db)?,
+        None => Screenpipe::open()?,
+    };
+    screenpipe.enrich(workflow)
+}
+
+#[cfg(not(feature = "screenpipe"))]
+fn screen_text(_: &mut RecordedWorkflow, _: Option<std::path::PathBuf>) -> Result<usize> {
+    anyhow::bail!("Screen text needs the `screenpipe` feature")
+}
+
+fn import(file: &std::path::Path, from: Option<ImportFormat>) -> Result<()> {
+    let workflow = bigbrother::recorder::import::import(file, from)?;
+    let path = WorkflowStorage::new()?.save(&workflow)?;
+    say!("Imported {} events", workflow.events.len());
+    say!("Saved: {}", path.display());
+    report(serde_json::json!({"path": path, "events": workflow.events.len()}));
+    let by_selector = workflow.events.iter().any(|e| {
+        matches!(&e.data, EventData::Click { x: 0, y: 0, c: Some(c), .. } if !c.s.is_empty())
+    });
+    if by_selector {
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        say!("Clicks go by element, run with: bb plan {} --run", name);
+    }
+    Ok(())
+}
+
+fn merge(files: &[String], output: Option<&str>) -> Result<()> {
+    let storage = WorkflowStorage::new()?;
+    let mut workflow = storage.load(&files[0])?;
+    for file in &files[1..] {
+        workflow.append(storage.load(file)?);
+    }
+    let path = match output {
+        Some(output) => storage.save_as(&workflow, output)?,
+        None => storage.save(&workflow)?,
+    };
+    say!("{} workflows, {} events", files.len(), workflow.events.len());
+    say!("Saved: {}", path.display());
+    report(serde_json::json!({
+        "path": path,
+        "files": files.len(),
+        "events": workflow.events.len(),
+    }));
+    Ok(())
+}
+
+fn retention(keep_days: Option<u64>, max_mb: Option<u64>) -> RetentionPolicy {
+    RetentionPolicy {
+        max_total_bytes: max_mb.map(|mb| mb * 1_000_000),
+        ..keep_days.map_or_else(RetentionPolicy::default, RetentionPolicy::keep_days)
+    }
+}
+
+fn prune(
+    keep_days: Option<u64>,
+    max_mb: Option<u64>,
+    dir: Option<std::path::PathBuf>,
+) -> Result<()> {
+    if keep_days.is_none() && max_mb.is_none() {
+        anyhow::bail!("Nothing to prune by, pass --keep-days or --max-mb");
+    }
+    let storage = match dir {
+        Some(dir) => WorkflowStorage::with_dir(dir)?,
+        None => WorkflowStorage::new()?,
+    };
+    let pruned = storage.prune(&retention(keep_days, max_mb))?;
+    for path in &pruned.deleted {
+        say!("Deleted {}", path.display());
+    }
+    say!("{} deleted ({} assets), {} kept ({:.1} MB)",
+        pruned.deleted.len(), pruned.assets, pruned.kept, pruned.bytes as f64 / 1e6);
+    report(serde_json::json!({
+        "deleted": pruned.deleted,
+        "assets": pruned.assets,
+        "kept": pruned.kept,
+        "bytes": pruned.bytes,
+    }));
+    Ok(())
+}
+
+fn convert(file: &str, to: Format, compression: Compression) -> Result<()> {
+    let storage = WorkflowStorage::new()?.compression(compression);
+    let path = storage.convert(file, to)?;
+    say!("Saved: {}", path.display());
+    report(serde_json::json!({"path": path}));
+    Ok(())
+}
+
+fn daemon(socket: Option<std::path::PathBuf>, action: DaemonAction) -> Result<()> {
+    use bigbrother::recorder::service::{RecordingService, ServiceConfig};
+
+    let defaults = ServiceConfig::default();
+    let socket = socket.or(defaults.socket.clone());
+    let args = match action {
+        DaemonAction::Start(args) => args,
+        #[cfg(unix)]
+        command => {
+            let Some(socket) = &socket else { anyhow::bail!("No control socket") };
+            let command = match command {
+                DaemonAction::Pause => "pause",
+                DaemonAction::Resume => "resume",
+                DaemonAction::Stop => "stop",
+                _ => "status",
+            };
+            let status = bigbrother::recorder::service::send(socket, command)?;
+            let state = if !status.running { "stopping" } else if status.paused { "paused" } else { "recording" };
+            say!("{}: {} events ({} dropped), {} files, {:.1} MB",
+                state, status.events, status.dropped, status.files, status.bytes as f64 / 1e6);
+            report(status);
+            return Ok(());
+        }
+        #[cfg(not(unix))]
+        _ => anyhow::bail!("The daemon control socket is only available on Unix"),
+    };
+
+    let recorder = RecorderConfig {
+        app_blocklist: args.block_apps,
+        redactors: if args.scrub { default_redactors() } else { Vec::new() },
+        pause_hotkey: args.pause_key,
+        ..Default::default()
+    };
+    let perms = WorkflowRecorder::with_config(recorder.clone()).check_permissions();
+    if !perms.all_granted() {
+        let missing = if perms.accessibility { "Input Monitoring" } else { "Accessibility" };
+        return Err(missing_permission(missing).into());
+    }
+    let config = ServiceConfig {
+        recorder,
+        name: args.name,
+        format: args.format,
+        compression: args.compress,
+        dir: args.dir.unwrap_or(defaults.dir),
+        rotate_every: args.rotate,
+        retention: retention(args.keep_days, args.quota_mb),
+        socket,
+    };
+    say!("Recording to {} (Ctrl+C or bb daemon stop to stop)", config.dir.display());
+    let dir = config.dir.clone();
+    let service = RecordingService::new(config);
+    let control = service.control();
+    ctrlc::set_handler(move || control.stop())?;
+    service.run()?;
+    say!("Stopped");
+    report(serde_json::json!({"dir": dir, "stopped": true}));
     Ok(())
 }
 
 fn permissions(request: bool) -> Result<()> {
-    let recorder = WorkflowRecorder::new();
+    // Screenshots on, so requesting asks for screen recording too
+    let recorder = WorkflowRecorder::with_config(RecorderConfig {
+        capture_screenshots: true,
+        ..Default::default()
+    });
     let perms = if request { recorder.request_permissions() } else { recorder.check_permissions() };
-    println!("Accessibility: {}", if perms.accessibility { "OK" } else { "DENIED" });
-    println!("Input Monitoring: {}", if perms.input_monitoring { "OK" } else { "DENIED" });
-    if !perms.all_granted() && !request { println!("\nRun with --request to request permissions"); }
+    for p in &perms.permissions {
+        let state = match p.state {
+            PermissionState::Granted => "OK",
+            PermissionState::Denied => "DENIED",
+            PermissionState::NotDetermined => "NOT ASKED",
+        };
+        say!("{}: {}", permission_label(p.name), state);
+        if let Some(remediation) = &p.remediation {
+            say!("  {}", remediation);
+        }
+        if let (false, Some(url)) = (p.granted(), p.settings_url) {
+            say!("  open \"{}\"", url);
+        }
+    }
+    if !perms.all_granted() && !request { say!("\nRun with --request to request permissions"); }
+    report(&perms);
     Ok(())
 }
+
+fn permission_label(name: &str) -> &str {
+    match name {
+        "accessibility" => "Accessibility",
+        "input_monitoring" => "Input Monitoring",
+        "screen_recording" => "Screen Recording",
+        other => other,
+    }
+}
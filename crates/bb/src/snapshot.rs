@@ -0,0 +1,238 @@
+//! Accessibility trees kept on disk between `bb` runs
+//!
+//! `bb tree` saves the tree it built, one file per app, and returns it
+//! again while it's younger than [`TTL`]. `bb find --cached` and
+//! `bb click --cached` match selectors against that tree instead of walking
+//! the app; a click then follows the node's path down from the app, and
+//! rebuilds the tree once if the element there no longer has the node's
+//! role and text. The generation goes up with every rebuild, so indices
+//! from an older tree can be told apart. `bb tree --refresh` always
+//! rebuilds.
+//!
+//! Trees hold whatever text the app shows, so they're kept in the user's
+//! cache directory, readable by them only.
+
+use anyhow::{anyhow, Result};
+use bigbrother::desktop::{TreeNode, TreeResult};
+use bigbrother::error::Error;
+use bigbrother::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a saved tree is used
+pub const TTL: Duration = Duration::from_secs(30);
+
+/// Depth of trees built for `--cached` lookups, as `bb tree` defaults to
+pub const DEPTH: usize = 15;
+
+/// A tree as saved
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    /// How many times this app's tree was built
+    pub generation: u64,
+    /// When it was built (unix ms)
+    pub taken_ms: u64,
+    pub depth: usize,
+    #[serde(flatten)]
+    pub tree: TreeResult,
+}
+
+impl Snapshot {
+    /// `app`'s saved tree however old, if `depth` deep when given
+    pub fn saved(app: &str, depth: Option<usize>) -> Option<Snapshot> {
+        Self::saved_in(&dir().ok()?, app, depth)
+    }
+
+    /// `app`'s saved tree if it's fresh
+    pub fn load(app: &str, depth: Option<usize>) -> Option<Snapshot> {
+        Self::saved(app, depth).filter(|s| s.age() < TTL)
+    }
+
+    /// Build `app`'s tree and save it
+    pub fn take(desktop: &mut Desktop, app: &str, depth: usize) -> Result<Snapshot> {
+        let tree = desktop.tree(app, depth)?;
+        Self::save_in(&dir()?, app, depth, tree)
+    }
+
+    /// The saved tree when fresh, a new one otherwise
+    pub fn get(desktop: &mut Desktop, app: &str) -> Result<Snapshot> {
+        match Self::load(app, None) {
+            Some(snapshot) => Ok(snapshot),
+            None => Self::take(desktop, app, DEPTH),
+        }
+    }
+
+    pub fn find(&self, selector: &Selector) -> Vec<&TreeNode> {
+        self.tree.nodes.iter().filter(|n| n.matches(selector)).collect()
+    }
+
+    /// The single node matching `selector`, or the first with `first`
+    pub fn find_one(&self, selector: &Selector, first: bool) -> Result<&TreeNode> {
+        match self.find(selector)[..] {
+            [] => Err(Error::element_not_found(&selector.to_string()).into()),
+            [node] => Ok(node),
+            [node, ..] if first => Ok(node),
+            ref nodes => Err(Error::multiple_matches(&selector.to_string(), nodes.len()).into()),
+        }
+    }
+
+    /// The live element `node` was, if it's still there: whatever is at its
+    /// path now must have its role, name, title and value, or a reordered
+    /// list would hand back a neighbour
+    pub fn resolve(&self, desktop: &Desktop, node: &TreeNode) -> Option<UIElement> {
+        let path = self.tree.path(node.index)?;
+        let element = desktop.element_at_path(&self.tree.app, &path).ok()?;
+        node.describes(&element.info()).then_some(element)
+    }
+
+    fn age(&self) -> Duration {
+        Duration::from_millis(now_ms().saturating_sub(self.taken_ms))
+    }
+
+    fn saved_in(dir: &Path, app: &str, depth: Option<usize>) -> Option<Snapshot> {
+        let file = std::fs::read(dir.join(file_name(app))).ok()?;
+        let snapshot: Snapshot = serde_json::from_slice(&file).ok()?;
+        depth.is_none_or(|d| d == snapshot.depth).then_some(snapshot)
+    }
+
+    /// Save `tree` as the next generation of `app`'s, readable by its owner only
+    fn save_in(dir: &Path, app: &str, depth: usize, tree: TreeResult) -> Result<Snapshot> {
+        let generation = Self::saved_in(dir, app, None).map_or(0, |s| s.generation) + 1;
+        let snapshot = Snapshot { generation, taken_ms: now_ms(), depth, tree };
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(dir.join(file_name(app)))?.write_all(&serde_json::to_vec(&snapshot)?)?;
+        Ok(snapshot)
+    }
+}
+
+/// Where trees are saved
+fn dir() -> Result<PathBuf> {
+    let cache = cache_home().ok_or_else(|| anyhow!("No cache directory to keep trees in"))?;
+    private_dir(cache.join("bb").join("trees"))
+}
+
+/// The user's cache directory
+fn cache_home() -> Option<PathBuf> {
+    let var = |name| std::env::var_os(name).map(PathBuf::from).filter(|p| p.is_absolute());
+    if cfg!(windows) {
+        var("LOCALAPPDATA")
+    } else if cfg!(target_os = "macos") {
+        var("HOME").map(|home| home.join("Library").join("Caches"))
+    } else {
+        var("XDG_CACHE_HOME").or_else(|| var("HOME").map(|home| home.join(".cache")))
+    }
+}
+
+/// `path`, created if needed and open to its owner only. Fails when it's
+/// someone else's, so trees they planted aren't trusted.
+fn private_dir(path: PathBuf) -> Result<PathBuf> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(&path)?;
+    // Only the owner can change the mode of one that already existed
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700)).map_err(|e| {
+            anyhow!("Can't keep trees in {}, it isn't yours: {}", path.display(), e)
+        })?;
+    }
+    Ok(path)
+}
+
+fn file_name(app: &str) -> String {
+    let name: String = app
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    format!("{}.json", name)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(titles: &[&str]) -> TreeResult {
+        let node = |index, title: &&str| TreeNode {
+            index,
+            role: if index == 0 { "AXApplication" } else { "AXButton" }.into(),
+            name: None,
+            title: Some(title.to_string()),
+            value: None,
+            depth: index.min(1),
+            children_count: if index == 0 { titles.len() - 1 } else { 0 },
+        };
+        let nodes: Vec<_> = titles.iter().enumerate().map(|(i, t)| node(i, t)).collect();
+        TreeResult { app: "Notes".into(), element_count: nodes.len(), nodes, next: None }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bb-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        private_dir(dir).unwrap()
+    }
+
+    #[test]
+    fn finds_one_node_or_says_why_not() {
+        let snapshot = Snapshot {
+            generation: 1,
+            taken_ms: now_ms(),
+            depth: DEPTH,
+            tree: tree(&["Notes", "Save", "Open", "Open"]),
+        };
+        let find = |selector: &str, first| {
+            let selector = Selector::parse(selector).unwrap();
+            let code = |e: anyhow::Error| e.downcast::<Error>().unwrap().code;
+            snapshot.find_one(&selector, first).map(|n| n.index).map_err(code)
+        };
+        assert_eq!(find("title:Save", false), Ok(1));
+        assert_eq!(find("title:Open", false), Err(ErrorCode::MultipleMatches));
+        assert_eq!(find("title:Open", true), Ok(2));
+        assert_eq!(find("title:Quit", true), Err(ErrorCode::ElementNotFound));
+    }
+
+    #[test]
+    fn rebuilds_count_generations_and_age_out() {
+        let dir = temp_dir("trees");
+        let first = Snapshot::save_in(&dir, "Notes", 3, tree(&["Notes", "Save"])).unwrap();
+        let second = Snapshot::save_in(&dir, "Notes", 3, tree(&["Notes", "Open"])).unwrap();
+        assert_eq!((first.generation, second.generation), (1, 2));
+
+        let saved = Snapshot::saved_in(&dir, "Notes", Some(3)).unwrap();
+        assert_eq!((saved.generation, saved.tree.nodes[1].title.as_deref()), (2, Some("Open")));
+        assert!(saved.age() < TTL);
+        assert!(Snapshot::saved_in(&dir, "Notes", Some(5)).is_none());
+        assert!(Snapshot::saved_in(&dir, "Mail", None).is_none());
+
+        let old = Snapshot { taken_ms: now_ms() - TTL.as_millis() as u64 - 1, ..saved };
+        assert!(old.age() >= TTL);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_trees_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        let dir = temp_dir("private");
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let dir = private_dir(dir).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+        Snapshot::save_in(&dir, "Notes", 3, tree(&["Notes"])).unwrap();
+        assert_eq!(mode(&dir.join(file_name("Notes"))), 0o600);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,7 +1,131 @@
 //! Accessibility API helpers for working with UI elements
 
+use crate::platform::macos::native::NativeElement;
+use crate::walk::Walk;
 use cidre::ax;
 use cidre::arc::R;
+use cidre::cf;
+use std::ffi::c_void;
+
+// Not wrapped by cidre
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXUIElementCopyMultipleAttributeValues(
+        element: *const c_void,
+        attributes: *const c_void,
+        options: u32,
+        values: *mut *const c_void,
+    ) -> i32;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFTypeArrayCallBacks: u8;
+    fn CFArrayCreate(
+        allocator: *const c_void,
+        values: *const *const c_void,
+        count: isize,
+        callbacks: *const c_void,
+    ) -> *const c_void;
+    fn CFArrayGetCount(array: *const c_void) -> isize;
+    fn CFArrayGetValueAtIndex(array: *const c_void, index: isize) -> *const c_void;
+    fn CFRelease(cf: *const c_void);
+    fn CFHash(cf: *const c_void) -> usize;
+    fn CFEqual(cf1: *const c_void, cf2: *const c_void) -> u8;
+}
+
+/// What an `ElementInfo` shows of an element
+#[derive(Debug, Clone, Default)]
+pub struct ElementAttributes {
+    pub role: Option<String>,
+    pub role_desc: Option<String>,
+    pub title: Option<String>,
+    pub value: Option<String>,
+    pub description: Option<String>,
+    pub identifier: Option<String>,
+    pub bounds: Option<(f64, f64, f64, f64)>,
+}
+
+/// Attributes fetched by [`get_attributes`], in order
+const ATTRIBUTES: [&str; 8] = [
+    "AXRole",
+    "AXRoleDescription",
+    "AXTitle",
+    "AXValue",
+    "AXDescription",
+    "AXIdentifier",
+    "AXPosition",
+    "AXSize",
+];
+
+/// Everything an `ElementInfo` needs in one round trip to the app, instead
+/// of one per attribute
+pub fn get_attributes(element: &ax::UiElement) -> ElementAttributes {
+    copy_attributes(element).unwrap_or_else(|| ElementAttributes {
+        role: get_role(element),
+        role_desc: get_role_desc(element),
+        title: get_title(element),
+        value: get_value(element),
+        description: get_description(element),
+        identifier: get_identifier(element),
+        bounds: get_bounds(element),
+    })
+}
+
+fn copy_attributes(element: &ax::UiElement) -> Option<ElementAttributes> {
+    let names: Vec<R<cf::String>> = ATTRIBUTES.iter().map(|a| cf::String::from_str(a)).collect();
+    let pointers: Vec<*const c_void> =
+        names.iter().map(|n| &**n as *const cf::String as *const c_void).collect();
+    unsafe {
+        let attributes = CFArrayCreate(
+            std::ptr::null(),
+            pointers.as_ptr(),
+            pointers.len() as isize,
+            &kCFTypeArrayCallBacks as *const u8 as *const c_void,
+        );
+        if attributes.is_null() {
+            return None;
+        }
+        let mut values = std::ptr::null();
+        let element = element as *const ax::UiElement as *const c_void;
+        // Options 0: missing attributes come back as error values, not a failure
+        let status = AXUIElementCopyMultipleAttributeValues(element, attributes, 0, &mut values);
+        CFRelease(attributes);
+        if status != 0 || values.is_null() {
+            return None;
+        }
+        let count = CFArrayGetCount(values).min(ATTRIBUTES.len() as isize);
+        let value = |i: isize| {
+            let value = CFArrayGetValueAtIndex(values, i);
+            (!value.is_null()).then(|| &*(value as *const cf::Type))
+        };
+        let string = |i: isize| {
+            let value = value(i)?;
+            (value.get_type_id() == cf::String::type_id())
+                .then(|| (*(value as *const cf::Type as *const cf::String)).to_string())
+        };
+        let ax_value = |i: isize| {
+            let value = value(i)?;
+            (value.get_type_id() == ax::Value::type_id())
+                .then(|| &*(value as *const cf::Type as *const ax::Value))
+        };
+        let attributes = (count == ATTRIBUTES.len() as isize).then(|| {
+            let origin = ax_value(6).and_then(|v| v.cg_point());
+            let size = ax_value(7).and_then(|v| v.cg_size());
+            ElementAttributes {
+                role: string(0),
+                role_desc: string(1),
+                title: string(2),
+                value: string(3),
+                description: string(4),
+                identifier: string(5).filter(|id| !id.is_empty()),
+                bounds: origin.zip(size).map(|(o, s)| (o.x, o.y, s.width, s.height)),
+            }
+        });
+        CFRelease(values);
+        attributes
+    }
+}
 
 /// Get a string attribute from a UI element
 pub fn get_string_attr(element: &ax::UiElement, attr: &ax::Attr) -> Option<String> {
@@ -44,6 +168,11 @@ pub fn get_description(element: &ax::UiElement) -> Option<String> {
     get_string_attr(element, ax::attr::desc())
 }
 
+/// Get the developer-assigned identifier of an element (AXIdentifier)
+pub fn get_identifier(element: &ax::UiElement) -> Option<String> {
+    get_string_attr(element, ax::attr::identifier()).filter(|id| !id.is_empty())
+}
+
 /// Get the role of an element as a string
 pub fn get_role(element: &ax::UiElement) -> Option<String> {
     element.role().ok().map(|r| extract_role_name(&r))
@@ -54,6 +183,32 @@ pub fn get_role_desc(element: &ax::UiElement) -> Option<String> {
     element.role_desc().ok().map(|s| s.to_string())
 }
 
+/// Get the on-screen frame of an element from its position and size attributes
+pub fn get_bounds(element: &ax::UiElement) -> Option<(f64, f64, f64, f64)> {
+    let pos = element.attr_value(ax::attr::pos()).ok()?;
+    let size = element.attr_value(ax::attr::size()).ok()?;
+    if pos.get_type_id() != ax::Value::type_id() || size.get_type_id() != ax::Value::type_id() {
+        return None;
+    }
+    let pos: &ax::Value = unsafe { std::mem::transmute(&*pos) };
+    let size: &ax::Value = unsafe { std::mem::transmute(&*size) };
+    let origin = pos.cg_point()?;
+    let size = size.cg_size()?;
+    Some((origin.x, origin.y, size.width, size.height))
+}
+
+/// Whether two references are to the same element
+pub fn same_element(a: &ax::UiElement, b: &ax::UiElement) -> bool {
+    let a = a as *const ax::UiElement as *const c_void;
+    let b = b as *const ax::UiElement as *const c_void;
+    unsafe { CFEqual(a, b) != 0 }
+}
+
+/// Hash of an element, the same for references to the same element
+pub fn element_hash(element: &ax::UiElement) -> usize {
+    unsafe { CFHash(element as *const ax::UiElement as *const c_void) }
+}
+
 /// Get all children of an element
 pub fn get_children(element: &ax::UiElement) -> Vec<R<ax::UiElement>> {
     element
@@ -69,30 +224,16 @@ where
     F: Fn(&ax::UiElement) -> bool + Copy,
 {
     let mut results = Vec::new();
-    find_elements_recursive(root, predicate, max_depth, 0, &mut results);
-    results
-}
-
-fn find_elements_recursive<F>(
-    element: &ax::UiElement,
-    predicate: F,
-    max_depth: usize,
-    current_depth: usize,
-    results: &mut Vec<R<ax::UiElement>>,
-) where
-    F: Fn(&ax::UiElement) -> bool + Copy,
-{
-    if current_depth > max_depth {
-        return;
-    }
-
-    if predicate(element) {
-        results.push(element.retained());
-    }
-
-    for child in get_children(element) {
-        find_elements_recursive(&child, predicate, max_depth, current_depth + 1, results);
+    let mut walk = Walk::depth_first(NativeElement::new(root.retained()), ());
+    while let Some((element, depth, ())) = walk.next() {
+        if predicate(element.ax()) {
+            results.push(element.ax().retained());
+        }
+        if depth < max_depth {
+            walk.descend(element.children(), |_| ());
+        }
     }
+    results
 }
 
 /// Find elements by role
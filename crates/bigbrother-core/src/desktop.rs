@@ -1,14 +1,25 @@
 //! Desktop - main entry point for automation
 
-use crate::apps;
-use crate::element::UIElement;
+use crate::element::{Bounds, ElementInfo, UIElement};
 use crate::error::{Error, Result};
-use crate::input;
 use crate::locator::Locator;
-use crate::selector::Selector;
+use crate::platform::current::native;
+use crate::script::ScriptLanguage;
+use crate::selector::{Attribute, Selector};
+use crate::text::truncate;
+use crate::walk::Walk;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
+/// Entry point for automation. It stays on the thread it was made on, as
+/// its elements do; [`DesktopHandle`](crate::DesktopHandle) shares one
+/// between threads.
+///
+/// ```compile_fail
+/// fn send<T: Send>(_: T) {}
+/// send(bigbrother_core::Desktop::default());
+/// ```
+#[derive(Default)]
 pub struct Desktop {
     app_filter: Option<String>,
     tree_cache: Vec<UIElement>,
@@ -37,23 +48,293 @@ pub struct TreeNode {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TreeResult {
     pub app: String,
+    /// Nodes in this result, a page of the tree when `next` is set
     pub element_count: usize,
     pub nodes: Vec<TreeNode>,
+    /// Index of the node the next page starts at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next: Option<usize>,
+}
+
+impl TreeResult {
+    /// Child indices from the app down to node `index`, for
+    /// [`Desktop::element_at_path`]. An element met twice is only in the
+    /// tree once, so after one, the path can be off
+    pub fn path(&self, index: usize) -> Option<Vec<usize>> {
+        // Needs the ancestors, so the tree from its start
+        if self.nodes.first()?.index != 0 {
+            return None;
+        }
+        let mut path: Vec<usize> = Vec::new();
+        // Nodes are depth-first, so each one is the first child of the one
+        // before or the next sibling of one on the way up
+        for node in self.nodes.get(..=index)? {
+            if node.depth > path.len() {
+                path.push(0);
+            } else if node.depth > 0 {
+                path.truncate(node.depth);
+                *path.last_mut()? += 1;
+            }
+        }
+        Some(path)
+    }
+
+    /// Up to `max_nodes` nodes from index `start` on
+    pub fn page(mut self, start: usize, max_nodes: usize) -> TreeResult {
+        let end = start.saturating_add(max_nodes).min(self.nodes.len());
+        self.next = (end < self.nodes.len()).then_some(end);
+        self.nodes.truncate(end);
+        self.nodes.drain(..start.min(end));
+        self.element_count = self.nodes.len();
+        self
+    }
+}
+
+/// Characters of an element's value kept in a [`TreeNode`]
+const TREE_VALUE_LEN: usize = 100;
+
+impl TreeNode {
+    /// Whether an element still reads as this node did: same role, name,
+    /// title and value
+    pub fn describes(&self, info: &ElementInfo) -> bool {
+        let value = info.value.as_deref().map(|v| truncate(v, TREE_VALUE_LEN));
+        info.role == self.role
+            && info.name == self.name
+            && info.title == self.title
+            && value.as_deref() == self.value.as_deref()
+    }
+
+    /// Whether the node matches every condition of `selector`, `index:` being
+    /// its index in the tree
+    pub fn matches(&self, selector: &Selector) -> bool {
+        selector.conditions.iter().all(|c| match c.attr {
+            Attribute::Index => c.value.parse() == Ok(self.index),
+            _ => c.matches(
+                Some(&self.role),
+                self.name.as_deref(),
+                self.title.as_deref(),
+                self.value.as_deref(),
+                None,
+            ),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScrapeResult {
     pub app: String,
+    /// Max depth the scrape was taken at
+    #[serde(default)]
+    pub depth: usize,
+    /// Id of the element the scrape started from, when it's not the app
+    /// (see [`Desktop::scrape_at`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
     pub items: Vec<ScrapeItem>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ScrapeItem {
     pub index: usize,
+    /// Child-index path from the app root (e.g. "0.3.1"), stable across scrapes
+    /// as long as the surrounding layout doesn't change
+    pub id: String,
     pub role: String,
     pub text: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub context: Option<String>,
+    pub depth: usize,
+    /// Index of the nearest scraped ancestor
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<Bounds>,
+}
+
+/// Changes between two scrapes of the same app (see [`ScrapeResult::diff`])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScrapeDiff {
+    pub app: String,
+    /// New items, and everything scraped under them
+    pub added: Vec<ScrapeItem>,
+    /// Items gone, and everything that was scraped under them
+    pub removed: Vec<ScrapeItem>,
+    /// Items in the place of an earlier one with the same role, whose text
+    /// differs (new version)
+    pub changed: Vec<ScrapeItem>,
+    /// Full result of this scrape, to pass as the snapshot for the next diff
+    #[serde(skip)]
+    pub snapshot: Option<ScrapeResult>,
+}
+
+impl ScrapeResult {
+    /// Compare this scrape against an earlier one. Items are matched by role
+    /// and text among the children of items that matched, in order, so a
+    /// sibling inserted or removed doesn't shift the ones after it. Items
+    /// left over between the same two matches are paired by role as changed.
+    pub fn diff(&self, previous: &ScrapeResult) -> ScrapeDiff {
+        let (before, after) = (previous.children(), self.children());
+        let (mut added, mut removed, mut changed) = (Vec::new(), Vec::new(), Vec::new());
+        // Matched parents, by index; None for the top level
+        let mut parents = vec![(None, None)];
+        while let Some((old, new)) = parents.pop() {
+            let old_children = &before[old.unwrap_or(previous.items.len())];
+            let new_children = &after[new.unwrap_or(self.items.len())];
+            let old_items: Vec<_> = old_children.iter().map(|&i| &previous.items[i]).collect();
+            let new_items: Vec<_> = new_children.iter().map(|&i| &self.items[i]).collect();
+            for pair in align(&old_items, &new_items) {
+                match pair {
+                    (Some(o), Some(n)) => {
+                        let (o, n) = (old_children[o], new_children[n]);
+                        if previous.items[o].text != self.items[n].text {
+                            changed.push(n);
+                        }
+                        parents.push((Some(o), Some(n)));
+                    }
+                    (None, Some(n)) => added.extend(subtree(&after, new_children[n])),
+                    (Some(o), None) => removed.extend(subtree(&before, old_children[o])),
+                    (None, None) => {}
+                }
+            }
+        }
+        let items = |mut indices: Vec<usize>, result: &ScrapeResult| -> Vec<ScrapeItem> {
+            indices.sort_unstable();
+            indices.into_iter().map(|i| result.items[i].clone()).collect()
+        };
+
+        ScrapeDiff {
+            app: self.app.clone(),
+            added: items(added, self),
+            removed: items(removed, previous),
+            changed: items(changed, self),
+            snapshot: None,
+        }
+    }
+
+    /// Indices of each item's children, then of the top level items
+    fn children(&self) -> Vec<Vec<usize>> {
+        let mut children = vec![Vec::new(); self.items.len() + 1];
+        for item in &self.items {
+            children[item.parent.unwrap_or(self.items.len())].push(item.index);
+        }
+        children
+    }
+}
+
+/// `index` and every item under it
+fn subtree(children: &[Vec<usize>], index: usize) -> Vec<usize> {
+    let mut items = vec![index];
+    let mut i = 0;
+    while i < items.len() {
+        items.extend(&children[items[i]]);
+        i += 1;
+    }
+    items
+}
+
+/// Siblings of two scrapes paired up by position: the longest run of items
+/// with the same role and text, in order, then what's left between two of
+/// those by role. Unpaired ones were added or removed.
+fn align(old: &[&ScrapeItem], new: &[&ScrapeItem]) -> Vec<(Option<usize>, Option<usize>)> {
+    let same = |i: usize, j: usize| old[i].role == new[j].role && old[i].text == new[j].text;
+    // Longest common subsequence lengths of the suffixes
+    let (n, m) = (old.len(), new.len());
+    let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[at(i, j)] = if same(i, j) {
+                lcs[at(i + 1, j + 1)] + 1
+            } else {
+                lcs[at(i + 1, j)].max(lcs[at(i, j + 1)])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut gap_old, mut gap_new) = (Vec::new(), Vec::new());
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && same(i, j) {
+            pair_gap(old, new, &mut gap_old, &mut gap_new, &mut pairs);
+            pairs.push((Some(i), Some(j)));
+            (i, j) = (i + 1, j + 1);
+        } else if j < m && (i == n || lcs[at(i, j + 1)] >= lcs[at(i + 1, j)]) {
+            gap_new.push(j);
+            j += 1;
+        } else {
+            gap_old.push(i);
+            i += 1;
+        }
+    }
+    pair_gap(old, new, &mut gap_old, &mut gap_new, &mut pairs);
+    pairs
+}
+
+/// Pair the items between two matches that have the same role, in order
+fn pair_gap(
+    old: &[&ScrapeItem],
+    new: &[&ScrapeItem],
+    gap_old: &mut Vec<usize>,
+    gap_new: &mut Vec<usize>,
+    pairs: &mut Vec<(Option<usize>, Option<usize>)>,
+) {
+    for j in gap_new.drain(..) {
+        match gap_old.iter().position(|&i| old[i].role == new[j].role) {
+            Some(k) => pairs.push((Some(gap_old.remove(k)), Some(j))),
+            None => pairs.push((None, Some(j))),
+        }
+    }
+    pairs.extend(gap_old.drain(..).map(|i| (Some(i), None)));
+}
+
+impl ScrapeDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Text items under `root`, the app's root unless `id` says where it is
+fn scrape_from(root: UIElement, app: &str, id: Option<&str>, max_depth: usize) -> ScrapeResult {
+    let mut items = Vec::new();
+    let mut texts = std::collections::HashSet::new();
+    let top = id.map_or(0, |id| id.matches('.').count());
+
+    // With each element, its path and the item it's under
+    let path = id.unwrap_or("0").to_string();
+    let mut walk = Walk::depth_first(root, (path, None));
+    while let Some((element, depth, (path, mut parent))) = walk.next() {
+        let info = element.info();
+        // As UIElement::text
+        let text = info.value.or(info.title).or(info.description).or(info.name.clone());
+        if let Some(text) = text {
+            if text.len() > 2 && texts.insert(text.clone()) {
+                let index = items.len();
+                items.push(ScrapeItem {
+                    index,
+                    id: path.clone(),
+                    role: info.role,
+                    text,
+                    context: info.name,
+                    depth: top + depth,
+                    parent,
+                    bounds: info.bounds,
+                });
+                parent = Some(index);
+            }
+        }
+        if depth < max_depth {
+            walk.descend(element.children(), |i| (format!("{}.{}", path, i), parent));
+        }
+    }
+    tracing::Span::current().record("items", items.len());
+
+    ScrapeResult {
+        app: app.to_string(),
+        depth: max_depth,
+        root: id.map(str::to_string),
+        items,
+    }
 }
 
 impl Desktop {
@@ -72,21 +353,16 @@ impl Desktop {
 
     // Discovery
 
+    #[tracing::instrument(level = "debug", skip_all, err)]
     pub fn apps(&self) -> Result<Vec<AppInfo>> {
-        let names = apps::list_running_apps().map_err(|e| Error::from(e))?;
-        let mut result = Vec::new();
-
-        for name in names {
-            if let Ok(pid) = apps::find_app_pid(&name) {
-                result.push(AppInfo { name, pid });
-            }
-        }
-
-        Ok(result)
+        Ok(native::running_apps()?
+            .into_iter()
+            .map(|(name, pid)| AppInfo { name, pid })
+            .collect())
     }
 
     pub fn find_app(&self, name: &str) -> Result<AppInfo> {
-        let pid = apps::find_app_pid(name).map_err(|_| Error::app_not_running(name))?;
+        let pid = native::find_app_pid(name)?;
         Ok(AppInfo {
             name: name.to_string(),
             pid,
@@ -94,7 +370,7 @@ impl Desktop {
     }
 
     pub fn browser(&self) -> Result<AppInfo> {
-        let (name, pid) = apps::find_browser().map_err(|e| Error::from(e))?;
+        let (name, pid) = native::find_browser()?;
         Ok(AppInfo { name, pid })
     }
 
@@ -104,7 +380,7 @@ impl Desktop {
         let mut loc = Locator::parse(selector)?;
         if let Some(ref app) = self.app_filter {
             let root = self.app_root(app)?;
-            loc = loc.with_root(root);
+            loc = loc.with_root(root).app(app);
         }
         Ok(loc)
     }
@@ -113,131 +389,171 @@ impl Desktop {
         let mut loc = Locator::new(selector);
         if let Some(ref app) = self.app_filter {
             if let Ok(root) = self.app_root(app) {
-                loc = loc.with_root(root);
+                loc = loc.with_root(root).app(app);
             }
         }
         loc
     }
 
     fn app_root(&self, app: &str) -> Result<UIElement> {
-        let element = apps::get_app_by_name(app).map_err(|_| Error::app_not_running(app))?;
-        Ok(UIElement::new(element))
+        Ok(UIElement::new(native::app_root(app)?))
+    }
+
+    /// Element that currently has keyboard focus
+    #[tracing::instrument(level = "debug", skip_all, err)]
+    pub fn focused(&self) -> Result<UIElement> {
+        Ok(UIElement::new(native::focused_element()?))
+    }
+
+    /// Topmost element at a screen point
+    #[tracing::instrument(level = "debug", skip(self), err)]
+    pub fn element_at(&self, x: f64, y: f64) -> Result<UIElement> {
+        Ok(UIElement::new(native::element_at(x, y)?))
     }
 
     // Tree inspection
 
     pub fn tree(&mut self, app: &str, max_depth: usize) -> Result<TreeResult> {
+        self.tree_page(app, max_depth, 0, usize::MAX)
+    }
+
+    /// Up to `max_nodes` nodes of [`Desktop::tree`] from index `start` on,
+    /// walking no further than that. Nodes keep their index in the whole
+    /// tree, and `next` is where the following page starts
+    #[tracing::instrument(skip(self), fields(elements = tracing::field::Empty), err)]
+    pub fn tree_page(
+        &mut self,
+        app: &str,
+        max_depth: usize,
+        start: usize,
+        max_nodes: usize,
+    ) -> Result<TreeResult> {
         let root = self.app_root(app)?;
+        let end = start.saturating_add(max_nodes);
         let mut nodes = Vec::new();
-        let mut index = 0;
-
+        let mut next = None;
         self.tree_cache.clear();
-        self.build_tree(&root, 0, max_depth, &mut nodes, &mut index);
+        let mut walk = Walk::depth_first(root, ());
+        let mut index = 0;
+        while let Some((element, depth, ())) = walk.next() {
+            if index == end {
+                next = Some(index);
+                break;
+            }
+            let children = element.children();
+            // Nodes before the page are only walked through
+            if index >= start {
+                let info = element.info();
+                nodes.push(TreeNode {
+                    index,
+                    role: info.role,
+                    name: info.name,
+                    title: info.title,
+                    value: info.value.map(|v| truncate(&v, TREE_VALUE_LEN).into_owned()),
+                    depth,
+                    children_count: children.len(),
+                });
+                self.tree_cache.push(element.with_index(index));
+            }
+            if depth < max_depth {
+                walk.descend(children, |_| ());
+            }
+            index += 1;
+        }
+        tracing::Span::current().record("elements", nodes.len());
 
         Ok(TreeResult {
             app: app.to_string(),
             element_count: nodes.len(),
             nodes,
+            next,
         })
     }
 
-    fn build_tree(
-        &mut self,
-        element: &UIElement,
-        depth: usize,
-        max_depth: usize,
-        nodes: &mut Vec<TreeNode>,
-        index: &mut usize,
-    ) {
-        if depth > max_depth {
-            return;
+    /// How many nodes [`Desktop::tree`] would have, without reading any
+    #[tracing::instrument(skip(self), err)]
+    pub fn tree_count(&self, app: &str, max_depth: usize) -> Result<usize> {
+        let mut walk = Walk::depth_first(self.app_root(app)?, ());
+        let mut count = 0;
+        while let Some((element, depth, ())) = walk.next() {
+            count += 1;
+            if depth < max_depth {
+                walk.descend(element.children(), |_| ());
+            }
         }
+        Ok(count)
+    }
 
-        let children = element.children();
-        let node = TreeNode {
-            index: *index,
-            role: element.role().unwrap_or_else(|| "Unknown".to_string()),
-            name: element.name(),
-            title: element.title(),
-            value: element.value().map(|v| {
-                if v.len() > 100 {
-                    format!("{}...", &v[..100])
-                } else {
-                    v
-                }
-            }),
-            depth,
-            children_count: children.len(),
-        };
-
-        nodes.push(node);
-        self.tree_cache.push(element.clone().with_index(*index));
-        *index += 1;
-
-        for child in children {
-            self.build_tree(&child, depth + 1, max_depth, nodes, index);
+    /// The element down `path` from the app's root, as [`TreeResult::path`]
+    /// gives it
+    pub fn element_at_path(&self, app: &str, path: &[usize]) -> Result<UIElement> {
+        let mut element = self.app_root(app)?;
+        for (depth, &i) in path.iter().enumerate() {
+            element = element.children().into_iter().nth(i).ok_or_else(|| {
+                let at: Vec<String> = path[..=depth].iter().map(|i| i.to_string()).collect();
+                Error::element_not_found(&format!("path {}", at.join(".")))
+            })?;
         }
+        Ok(element)
     }
 
     pub fn element_by_index(&self, index: usize) -> Result<UIElement> {
         self.tree_cache
-            .get(index)
+            .iter()
+            .find(|e| e.index == Some(index))
             .cloned()
             .ok_or_else(|| Error::element_not_found(&format!("index:{}", index)))
     }
 
     // Scraping
 
+    #[tracing::instrument(skip(self), fields(items = tracing::field::Empty), err)]
     pub fn scrape(&self, app: &str, max_depth: usize) -> Result<ScrapeResult> {
         let root = self.app_root(app)?;
-        let mut items = Vec::new();
-        let mut seen = std::collections::HashSet::new();
-
-        self.scrape_recursive(&root, max_depth, 0, &mut items, &mut seen);
-
-        Ok(ScrapeResult {
-            app: app.to_string(),
-            items,
-        })
+        Ok(scrape_from(root, app, None, max_depth))
     }
 
-    fn scrape_recursive(
-        &self,
-        element: &UIElement,
-        max_depth: usize,
-        depth: usize,
-        items: &mut Vec<ScrapeItem>,
-        seen: &mut std::collections::HashSet<String>,
-    ) {
-        if depth > max_depth {
-            return;
-        }
-
-        if let Some(text) = element.text() {
-            if text.len() > 2 && !seen.contains(&text) {
-                seen.insert(text.clone());
-                items.push(ScrapeItem {
-                    index: items.len(),
-                    role: element.role().unwrap_or_else(|| "Unknown".to_string()),
-                    text,
-                    context: element.name(),
-                });
-            }
-        }
+    /// [`Desktop::scrape`] of only what's under the element with item id
+    /// `id` (e.g. a chat's message list from an earlier scrape), `max_depth`
+    /// levels below it. Items keep the ids and depths they'd have in a
+    /// scrape of the whole app.
+    #[tracing::instrument(skip(self), fields(items = tracing::field::Empty), err)]
+    pub fn scrape_at(&self, app: &str, id: &str, max_depth: usize) -> Result<ScrapeResult> {
+        let path: Vec<usize> = id
+            .split('.')
+            .skip(1)
+            .map(|i| i.parse())
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|_| Error::element_not_found(&format!("path {}", id)))?;
+        let root = self.element_at_path(app, &path)?;
+        Ok(scrape_from(root, app, Some(id), max_depth))
+    }
 
-        for child in element.children() {
-            self.scrape_recursive(&child, max_depth, depth + 1, items, seen);
-        }
+    /// Scrape again and return only what changed since `since_snapshot`.
+    /// The returned diff carries the new snapshot for the next call. Only
+    /// the part of the app `since_snapshot` covered is walked again, so
+    /// watching one pane is cheaper with a snapshot from
+    /// [`Desktop::scrape_at`].
+    pub fn scrape_diff(&self, app: &str, since_snapshot: &ScrapeResult) -> Result<ScrapeDiff> {
+        let current = match &since_snapshot.root {
+            Some(id) => self.scrape_at(app, id, since_snapshot.depth)?,
+            None => self.scrape(app, since_snapshot.depth)?,
+        };
+        let mut diff = current.diff(since_snapshot);
+        diff.snapshot = Some(current);
+        Ok(diff)
     }
 
     // Actions
 
+    #[tracing::instrument(skip(self), err)]
     pub fn open_url(&self, url: &str) -> Result<()> {
-        apps::open_url(url).map_err(|e| Error::from(e))
+        native::open_url(url)
     }
 
+    #[tracing::instrument(skip(self), err)]
     pub fn activate(&self, app: &str) -> Result<()> {
-        apps::activate_app(app).map_err(|e| Error::from(e))
+        native::activate_app(app)
     }
 
     pub fn wait_idle(&self, ms: u64) -> Result<()> {
@@ -245,32 +561,242 @@ impl Desktop {
         Ok(())
     }
 
+    #[tracing::instrument(skip(self), err)]
     pub fn scroll_up(&self, pages: u32) -> Result<()> {
-        input::scroll_up(pages).map_err(|e| Error::from(e))
+        native::scroll_up(pages)
     }
 
+    #[tracing::instrument(skip(self), err)]
     pub fn scroll_down(&self, pages: u32) -> Result<()> {
-        input::scroll_down(pages).map_err(|e| Error::from(e))
+        native::scroll_down(pages)
     }
 
+    /// Press a platform keycode (macOS virtual keycode, Windows VK)
+    #[tracing::instrument(skip(self), err)]
     pub fn press_key(&self, key_code: u8) -> Result<()> {
-        input::press_key(key_code).map_err(|e| Error::from(e))
+        native::press_key(key_code)
     }
 
+    // Only the length, the text may be a password
+    #[tracing::instrument(skip_all, fields(chars = text.chars().count()), err)]
     pub fn type_text(&self, text: &str) -> Result<()> {
-        input::type_text(text).map_err(|e| Error::from(e))
+        native::type_text(text)
     }
 
+    /// Cmd+key on macOS, Ctrl+key elsewhere
+    #[tracing::instrument(skip(self), err)]
     pub fn cmd(&self, key: &str) -> Result<()> {
-        input::cmd(key).map_err(|e| Error::from(e))
+        native::primary_shortcut(key)
+    }
+
+    // Scripting, for what the accessibility tree doesn't reach (macOS only)
+
+    /// Run AppleScript and return its result as JSON; see [`crate::script`]
+    #[tracing::instrument(skip_all, fields(lines = script.lines().count()), err)]
+    pub fn run_applescript(&self, script: &str) -> Result<serde_json::Value> {
+        native::run_script(script, ScriptLanguage::AppleScript)
+    }
+
+    /// Run JavaScript for Automation; the last expression's value comes
+    /// back through `JSON.stringify`
+    #[tracing::instrument(skip_all, fields(lines = script.lines().count()), err)]
+    pub fn run_jxa(&self, script: &str) -> Result<serde_json::Value> {
+        native::run_script(script, ScriptLanguage::JavaScript)
+    }
+
+    /// Run a shortcut from the Shortcuts app and return its output, e.g.
+    /// `run_shortcut("Resize Image", Some(&json!({"width": 800})))`
+    #[tracing::instrument(skip(self, input), err)]
+    pub fn run_shortcut(
+        &self,
+        name: &str,
+        input: Option<&serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        native::run_shortcut(name, input)
     }
 }
 
-impl Default for Desktop {
-    fn default() -> Self {
-        Self {
-            app_filter: None,
-            tree_cache: Vec::new(),
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_and_pages() {
+        let node = |index, depth, role: &str| TreeNode {
+            index,
+            role: role.to_string(),
+            name: None,
+            title: Some(format!("n{}", index)),
+            value: None,
+            depth,
+            children_count: 0,
+        };
+        // app > [window > [button, group > [text]], menubar]
+        let depths = [(0, "AXApplication"), (1, "AXWindow"), (2, "AXButton"), (2, "AXGroup"),
+            (3, "AXStaticText"), (1, "AXMenuBar")];
+        let nodes = depths.iter().enumerate().map(|(i, &(d, r))| node(i, d, r)).collect();
+        let tree = TreeResult { app: "App".into(), element_count: 6, nodes, next: None };
+        assert_eq!(tree.path(0), Some(vec![]));
+        assert_eq!(tree.path(3), Some(vec![0, 1]));
+        assert_eq!(tree.path(4), Some(vec![0, 1, 0]));
+        assert_eq!(tree.path(5), Some(vec![1]));
+        assert_eq!(tree.path(6), None);
+
+        let page = tree.clone().page(2, 3);
+        let indices: Vec<usize> = page.nodes.iter().map(|n| n.index).collect();
+        assert_eq!((indices, page.next), (vec![2, 3, 4], Some(5)));
+        assert_eq!(page.path(3), None);
+        assert_eq!(tree.clone().page(4, 10).next, None);
+
+        let buttons = Selector::parse("role:AXButton AND title:n2").unwrap();
+        assert!(tree.nodes[2].matches(&buttons));
+        assert!(!tree.nodes[3].matches(&buttons));
+        assert!(tree.nodes[5].matches(&Selector::index(5)));
+    }
+
+    #[test]
+    fn nodes_describe_only_elements_with_their_text() {
+        let node = TreeNode {
+            index: 3,
+            role: "AXButton".into(),
+            name: None,
+            title: Some("Save".into()),
+            value: Some(truncate(&"x".repeat(120), TREE_VALUE_LEN).into_owned()),
+            depth: 2,
+            children_count: 0,
+        };
+        let info = |title: &str| ElementInfo {
+            index: None,
+            role: "AXButton".into(),
+            name: None,
+            title: Some(title.into()),
+            value: Some("x".repeat(120)),
+            description: None,
+            automation_id: None,
+            class_name: None,
+            bounds: None,
+        };
+        assert!(node.describes(&info("Save")));
+        assert!(!node.describes(&info("Delete")));
+        assert!(!node.describes(&ElementInfo { role: "AXLink".into(), ..info("Save") }));
+    }
+
+    /// A scrape of (role, text, parent) items, ids made up from their place
+    fn scrape(items: &[(&str, &str, Option<usize>)]) -> ScrapeResult {
+        let mut ids: Vec<String> = Vec::new();
+        let items = items.iter().enumerate().map(|(index, &(role, text, parent))| {
+            let siblings = items[..index].iter().filter(|i| i.2 == parent).count();
+            let id = match parent {
+                Some(p) => format!("{}.{}", ids[p], siblings),
+                None => format!("0.{}", siblings),
+            };
+            ids.push(id.clone());
+            ScrapeItem {
+                index,
+                id,
+                role: role.into(),
+                text: text.into(),
+                context: None,
+                depth: parent.map_or(1, |_| 2),
+                parent,
+                bounds: None,
+            }
+        });
+        ScrapeResult { app: "Slack".into(), depth: 10, root: None, items: items.collect() }
+    }
+
+    fn texts(items: &[ScrapeItem]) -> Vec<&str> {
+        items.iter().map(|i| i.text.as_str()).collect()
+    }
+
+    #[test]
+    fn diff_matches_siblings_by_content() {
+        let before = scrape(&[
+            ("AXList", "Messages", None),
+            ("AXRow", "hello there", Some(0)),
+            ("AXRow", "how are you", Some(0)),
+            ("AXRow", "see you soon", Some(0)),
+            ("AXButton", "Send", None),
+        ]);
+
+        // A message in the middle: only it is new, not everything after it
+        let inserted = scrape(&[
+            ("AXList", "Messages", None),
+            ("AXRow", "hello there", Some(0)),
+            ("AXRow", "quick question", Some(0)),
+            ("AXRow", "how are you", Some(0)),
+            ("AXRow", "see you soon", Some(0)),
+            ("AXButton", "Send", None),
+        ]);
+        let diff = inserted.diff(&before);
+        assert_eq!(texts(&diff.added), ["quick question"]);
+        assert!(diff.removed.is_empty() && diff.changed.is_empty());
+        assert!(before.diff(&before).is_empty());
+
+        // And taken out again
+        let diff = before.diff(&inserted);
+        assert_eq!(texts(&diff.removed), ["quick question"]);
+        assert!(diff.added.is_empty() && diff.changed.is_empty());
+
+        // An edited message stays in its place
+        let edited = scrape(&[
+            ("AXList", "Messages", None),
+            ("AXRow", "hello there", Some(0)),
+            ("AXRow", "how are you doing", Some(0)),
+            ("AXRow", "see you soon", Some(0)),
+            ("AXButton", "Send", None),
+        ]);
+        let diff = edited.diff(&before);
+        assert_eq!(texts(&diff.changed), ["how are you doing"]);
+        assert_eq!(diff.changed[0].id, "0.0.1");
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+    }
+
+    #[test]
+    fn diff_reports_whole_subtrees() {
+        let before = scrape(&[("AXButton", "Send", None)]);
+        let after = scrape(&[
+            ("AXGroup", "Thread", None),
+            ("AXRow", "first reply", Some(0)),
+            ("AXButton", "Send", None),
+        ]);
+        let diff = after.diff(&before);
+        assert_eq!(texts(&diff.added), ["Thread", "first reply"]);
+        assert_eq!(texts(&before.diff(&after).removed), ["Thread", "first reply"]);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn scrape_diff_walks_only_what_the_snapshot_covered() {
+        use crate::platform::mock;
+
+        let _fixture = mock::lock();
+        let slack = |send: &str, messages: &[&str]| {
+            let rows: Vec<_> =
+                messages.iter().map(|m| serde_json::json!({"role": "AXRow", "value": m})).collect();
+            let fixture = serde_json::json!({"apps": [{"name": "Slack", "children": [
+                {"role": "AXWindow", "title": "general", "children": [
+                    {"role": "AXButton", "title": send},
+                    {"role": "AXList", "title": "Messages", "children": rows}
+                ]}
+            ]}]});
+            mock::set(&serde_json::from_value(fixture).unwrap());
+        };
+        slack("Send", &["hello there", "see you soon"]);
+
+        let desktop = Desktop::new().unwrap();
+        let whole = desktop.scrape("Slack", 10).unwrap();
+        let list = whole.items.iter().find(|i| i.text == "Messages").unwrap();
+        let pane = desktop.scrape_at("Slack", &list.id, 10).unwrap();
+        assert_eq!(texts(&pane.items), ["Messages", "hello there", "see you soon"]);
+        assert!(pane.items.iter().all(|i| i.id.starts_with(&list.id)));
+        assert_eq!(pane.items[1].depth, list.depth + 1);
+
+        // A change outside the pane isn't looked at
+        slack("Send now", &["hello there", "new message", "see you soon"]);
+        let diff = desktop.scrape_diff("Slack", &pane).unwrap();
+        assert_eq!(texts(&diff.added), ["new message"]);
+        assert!(diff.removed.is_empty() && diff.changed.is_empty());
+        assert_eq!(diff.snapshot.unwrap().root.as_deref(), Some(list.id.as_str()));
     }
 }
@@ -1,16 +1,19 @@
 //! UI Element representation with structured output
 
-use crate::accessibility::*;
 use crate::error::{Error, Result};
-use crate::input;
-use cidre::arc::R;
-use cidre::ax;
+use crate::platform::current::native::{self, NativeElement};
+use crate::selector::Attribute;
 use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
 
+/// An element of an app's UI, bound to the thread it was found on like the
+/// accessibility handle it wraps
 #[derive(Clone)]
 pub struct UIElement {
-    inner: R<ax::UiElement>,
+    inner: NativeElement,
     pub index: Option<usize>,
+    /// Not `Send` or `Sync` on any platform
+    _thread: PhantomData<*const ()>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +30,10 @@ pub struct ElementInfo {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub automation_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub bounds: Option<Bounds>,
 }
 
@@ -48,8 +55,12 @@ pub struct ActionResult {
 }
 
 impl UIElement {
-    pub fn new(inner: R<ax::UiElement>) -> Self {
-        Self { inner, index: None }
+    pub fn new(inner: impl Into<NativeElement>) -> Self {
+        Self {
+            inner: inner.into(),
+            index: None,
+            _thread: PhantomData,
+        }
     }
 
     pub fn with_index(mut self, index: usize) -> Self {
@@ -57,28 +68,53 @@ impl UIElement {
         self
     }
 
-    pub fn raw(&self) -> &ax::UiElement {
+    /// Platform element (AX element on macOS, UIA element on Windows)
+    pub fn raw(&self) -> &NativeElement {
         &self.inner
     }
 
     pub fn role(&self) -> Option<String> {
-        get_role(&self.inner)
+        self.inner.role()
     }
 
     pub fn name(&self) -> Option<String> {
-        get_role_desc(&self.inner)
+        self.inner.name()
     }
 
     pub fn title(&self) -> Option<String> {
-        get_title(&self.inner)
+        self.inner.title()
     }
 
     pub fn value(&self) -> Option<String> {
-        get_value(&self.inner)
+        self.inner.value()
     }
 
     pub fn description(&self) -> Option<String> {
-        get_description(&self.inner)
+        self.inner.description()
+    }
+
+    /// UIA AutomationId on Windows, AXIdentifier on macOS
+    pub fn automation_id(&self) -> Option<String> {
+        self.inner.automation_id()
+    }
+
+    /// UIA ClassName (Windows only)
+    pub fn class_name(&self) -> Option<String> {
+        self.inner.class_name()
+    }
+
+    /// Value of a selector attribute (None for `Index`)
+    pub fn attribute(&self, attr: Attribute) -> Option<String> {
+        match attr {
+            Attribute::Role => self.role(),
+            Attribute::Name => self.name(),
+            Attribute::Title => self.title(),
+            Attribute::Value => self.value(),
+            Attribute::Description => self.description(),
+            Attribute::AutomationId => self.automation_id(),
+            Attribute::Class => self.class_name(),
+            Attribute::Index => None,
+        }
     }
 
     pub fn text(&self) -> Option<String> {
@@ -89,24 +125,20 @@ impl UIElement {
     }
 
     pub fn bounds(&self) -> Option<Bounds> {
-        // TODO: implement bounds extraction from AX API
-        None
+        self.inner
+            .bounds()
+            .map(|(x, y, width, height)| Bounds { x, y, width, height })
     }
 
+    /// All of it at once, cheaper than attribute by attribute where the
+    /// platform can fetch several in one call (macOS)
     pub fn info(&self) -> ElementInfo {
-        ElementInfo {
-            index: self.index,
-            role: self.role().unwrap_or_else(|| "Unknown".to_string()),
-            name: self.name(),
-            title: self.title(),
-            value: self.value(),
-            description: self.description(),
-            bounds: self.bounds(),
-        }
+        ElementInfo { index: self.index, ..self.inner.info() }
     }
 
     pub fn children(&self) -> Vec<UIElement> {
-        get_children(&self.inner)
+        self.inner
+            .children()
             .into_iter()
             .map(UIElement::new)
             .collect()
@@ -115,10 +147,8 @@ impl UIElement {
     pub fn click(&self) -> Result<ActionResult> {
         let start = std::time::Instant::now();
 
-        // Try to perform AX press action
-        if let Err(e) = self.inner.perform_action(ax::action::press()) {
-            return Err(Error::action_failed("click", &format!("{:?}", e)));
-        }
+        // Default action: AXPress on macOS, UIA patterns on Windows
+        self.inner.press()?;
 
         Ok(ActionResult {
             success: true,
@@ -133,9 +163,7 @@ impl UIElement {
 
         // Try to set value via AX API
         // For now, fall back to typing
-        if let Err(e) = input::type_text(text) {
-            return Err(Error::action_failed("set_value", &e.to_string()));
-        }
+        native::type_text(text).map_err(|e| Error::action_failed("set_value", &e.message))?;
 
         Ok(ActionResult {
             success: true,
@@ -146,6 +174,21 @@ impl UIElement {
     }
 }
 
+/// The same element, whatever the index
+impl PartialEq for UIElement {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl Eq for UIElement {}
+
+impl std::hash::Hash for UIElement {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.inner.hash(state)
+    }
+}
+
 impl std::fmt::Debug for UIElement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("UIElement")
@@ -0,0 +1,107 @@
+//! A desktop shared between threads
+//!
+//! Accessibility handles belong to the thread that got them: AX elements
+//! aren't safe to use from two threads at once, and UIA elements are bound
+//! to their COM apartment. So [`Desktop`], [`UIElement`](crate::UIElement)
+//! and [`Locator`](crate::Locator) aren't `Send` or `Sync`, and the compiler
+//! keeps each on the thread that made it.
+//!
+//! [`DesktopHandle`] is how servers and other multi-threaded hosts use one:
+//! it keeps a `Desktop` on a thread of its own and runs closures there, one
+//! at a time. Clones share that thread.
+//!
+//! ```rust,no_run
+//! use bigbrother_core::DesktopHandle;
+//!
+//! # fn main() -> bigbrother_core::Result<()> {
+//! let desktop = DesktopHandle::new()?;
+//! let worker = desktop.clone();
+//! std::thread::spawn(move || worker.run(|d| d.locator("role:AXButton AND name:Save")?.click()))
+//!     .join()
+//!     .unwrap()??;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::desktop::Desktop;
+use crate::error::{Error, ErrorCode, Result};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::mpsc;
+use std::thread;
+
+type Job = Box<dyn FnOnce(&mut Desktop) + Send>;
+
+/// A [`Desktop`] on its own thread, usable from any
+#[derive(Clone)]
+pub struct DesktopHandle {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl DesktopHandle {
+    /// Start a thread with a [`Desktop::new`]
+    pub fn new() -> Result<Self> {
+        Self::with(Desktop::new)
+    }
+
+    /// Start a thread with the desktop `make` returns, made on that thread
+    pub fn with(make: impl FnOnce() -> Result<Desktop> + Send + 'static) -> Result<Self> {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (ready, made) = mpsc::channel();
+        thread::Builder::new().name("bigbrother-desktop".into()).spawn(move || {
+            let mut desktop = match make() {
+                Ok(desktop) => desktop,
+                Err(e) => return drop(ready.send(Err(e))),
+            };
+            let _ = ready.send(Ok(()));
+            // Until the last handle is dropped
+            for job in queue {
+                // A panic only loses its own result
+                let _ = catch_unwind(AssertUnwindSafe(|| job(&mut desktop)));
+            }
+        })?;
+        made.recv().map_err(|_| gone())??;
+        Ok(Self { jobs })
+    }
+
+    /// Run `f` on the desktop's thread and wait for what it returns.
+    /// Elements can't come back; return what was read from them.
+    pub fn run<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Desktop) -> T + Send + 'static,
+    ) -> Result<T> {
+        let (reply, result) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move |desktop| {
+            let _ = reply.send(f(desktop));
+        });
+        self.jobs.send(job).map_err(|_| gone())?;
+        result.recv().map_err(|_| Error::new(ErrorCode::Unknown, "Desktop call panicked"))
+    }
+}
+
+fn gone() -> Error {
+    Error::new(ErrorCode::Unknown, "Desktop thread is gone")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_calls_from_any_thread() {
+        let desktop = DesktopHandle::with(|| Ok(Desktop::default().in_app("Notes"))).unwrap();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let desktop = desktop.clone();
+                thread::spawn(move || desktop.run(|d| d.element_by_index(0).is_err()).unwrap())
+            })
+            .collect();
+        assert!(threads.into_iter().all(|t| t.join().unwrap()));
+
+        // The thread outlives a panicking call
+        assert!(desktop.run(|_| -> () { panic!("boom") }).is_err());
+        assert_eq!(desktop.run(|_| 1).unwrap(), 1);
+
+        let failed = DesktopHandle::with(|| Err(Error::new(ErrorCode::NotImplemented, "no")));
+        assert_eq!(failed.err().map(|e| e.code), Some(ErrorCode::NotImplemented));
+    }
+}
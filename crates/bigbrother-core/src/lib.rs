@@ -8,59 +8,53 @@
 //! - **macOS**: Full support via Accessibility API
 //! - **Windows**: Full support via UI Automation + SendInput
 //! - **Linux**: Coming soon (AT-SPI2)
+//! - **Mock** (`mock` feature): a fixture's element tree, for CI
 
-pub mod error;
+pub mod desktop;
+pub mod element;
+pub mod handle;
+pub mod locator;
 pub mod platform;
+pub mod script;
+mod walk;
+
+// Platform-free types, usable on their own from `bigbrother-types`
+pub use bigbrother_types::{error, selector, text};
 
 #[cfg(target_os = "macos")]
 pub mod accessibility;
 #[cfg(target_os = "macos")]
 pub mod apps;
 #[cfg(target_os = "macos")]
-pub mod desktop;
-#[cfg(target_os = "macos")]
-pub mod element;
-#[cfg(target_os = "macos")]
 pub mod input;
-#[cfg(target_os = "macos")]
-pub mod locator;
-#[cfg(target_os = "macos")]
-pub mod selector;
 
-// macOS exports
-#[cfg(target_os = "macos")]
+// Cross-platform facade, backed by `platform::current::native`
 pub use desktop::Desktop;
-#[cfg(target_os = "macos")]
 pub use element::UIElement;
+pub use handle::DesktopHandle;
 pub use error::{Error, ErrorCode, Result};
-#[cfg(target_os = "macos")]
 pub use locator::Locator;
-#[cfg(target_os = "macos")]
 pub use selector::Selector;
 
 // Windows exports
 #[cfg(target_os = "windows")]
 pub use platform::windows::{
-    Automation, Element, TreeWalker,
+    Automation, ClickMethod, Element, ToggleState, TreeWalker,
     find_window, get_windows,
     move_mouse, click, click_at, double_click, right_click, middle_click,
     scroll, press_key, key_down, key_up, type_text, shortcut, vk,
 };
 
 pub mod prelude {
-    #[cfg(target_os = "macos")]
     pub use crate::desktop::Desktop;
-    #[cfg(target_os = "macos")]
     pub use crate::element::UIElement;
     pub use crate::error::{Error, ErrorCode, Result};
-    #[cfg(target_os = "macos")]
     pub use crate::locator::Locator;
-    #[cfg(target_os = "macos")]
     pub use crate::selector::Selector;
 
     #[cfg(target_os = "windows")]
     pub use crate::platform::windows::{
-        Automation, Element, TreeWalker,
+        Automation, ClickMethod, Element, ToggleState, TreeWalker,
         find_window, get_windows,
         move_mouse, click, click_at, double_click, right_click, middle_click,
         scroll, press_key, key_down, key_up, type_text, shortcut, vk,
@@ -76,3 +70,13 @@ pub fn has_accessibility() -> bool {
 pub fn ensure_accessibility() -> Result<()> {
     platform::current::ensure_accessibility()
 }
+
+/// Check if the process may capture the screen, which screenshots need
+pub fn has_screen_recording() -> bool {
+    platform::current::has_screen_recording()
+}
+
+/// Ensure screen recording permission is granted, asking for it if not
+pub fn ensure_screen_recording() -> Result<()> {
+    platform::current::ensure_screen_recording()
+}
@@ -1,17 +1,23 @@
 //! Locator - fluent API for finding and interacting with elements
 
-use crate::accessibility::*;
 use crate::element::{ActionResult, UIElement};
 use crate::error::{Error, Result};
-use crate::selector::{Attribute, Selector};
-use cidre::ax;
+use crate::platform::current::native::{self, NativeElement};
+use crate::selector::{Attribute, MatchOp, Selector};
+use crate::walk::Walk;
 use std::time::{Duration, Instant};
 
 pub struct Locator {
     selector: Selector,
     root: Option<UIElement>,
+    /// App the root belongs to, for tracing
+    app: Option<String>,
     timeout_ms: u64,
     max_depth: usize,
+    /// Take the first match instead of failing on several
+    first: bool,
+    /// Skip subtrees that can't hold the role asked for
+    prune: bool,
 }
 
 impl Locator {
@@ -19,8 +25,11 @@ impl Locator {
         Self {
             selector,
             root: None,
+            app: None,
             timeout_ms: 5000,
             max_depth: 30,
+            first: false,
+            prune: false,
         }
     }
 
@@ -33,6 +42,11 @@ impl Locator {
         self
     }
 
+    pub(crate) fn app(mut self, app: &str) -> Self {
+        self.app = Some(app.to_string());
+        self
+    }
+
     pub fn timeout(mut self, ms: u64) -> Self {
         self.timeout_ms = ms;
         self
@@ -43,14 +57,41 @@ impl Locator {
         self
     }
 
+    /// Make [`find`](Self::find), and the actions, take the first match
+    /// (the shallowest) and stop looking, instead of failing when there are
+    /// several as shallow. Turns on [`prune`](Self::prune) too
+    pub fn first(mut self) -> Self {
+        self.first = true;
+        self.prune = true;
+        self
+    }
+
+    /// With an exact role to look for, don't search below elements that
+    /// don't usually hold it, such as menus for a button or text for a
+    /// window. Faster, but misses matches where apps nest them anyway
+    pub fn prune(mut self, prune: bool) -> Self {
+        self.prune = prune;
+        self
+    }
+
+    /// The shallowest match; fails when there's none, or several as
+    /// shallow. Deeper matches don't make it ambiguous, so the walk ends
+    /// with the level the first match is on instead of going through the
+    /// whole tree
+    #[tracing::instrument(skip_all, fields(selector = %self.selector, app = self.app), err)]
     pub fn find(&self) -> Result<UIElement> {
-        let elements = self.find_all()?;
+        if self.first {
+            return self.find_first();
+        }
+        // Two are enough to know it's ambiguous; all of them only for the error
+        let mut elements = self.search(Some(2), true)?;
 
         if elements.is_empty() {
             return Err(Error::element_not_found(&self.selector.to_string()));
         }
 
         if elements.len() > 1 {
+            elements = self.find_all()?;
             return Err(Error::multiple_matches(&self.selector.to_string(), elements.len())
                 .with_suggestions(vec![
                     "Add more conditions to narrow the match".to_string(),
@@ -64,18 +105,47 @@ impl Locator {
         Ok(elements.into_iter().next().unwrap())
     }
 
+    /// The shallowest match, without looking any further
+    pub fn find_first(&self) -> Result<UIElement> {
+        self.search(Some(1), false)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::element_not_found(&self.selector.to_string()))
+    }
+
+    /// Every match, the shallowest first
     pub fn find_all(&self) -> Result<Vec<UIElement>> {
+        self.search(None, false)
+    }
+
+    /// Matches in breadth-first order, stopping once there are `limit`, or
+    /// with `same_level` once the level of the first one is done
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            selector = %self.selector,
+            app = self.app,
+            limit,
+            same_level,
+            matches = tracing::field::Empty
+        ),
+        err
+    )]
+    fn search(&self, limit: Option<usize>, same_level: bool) -> Result<Vec<UIElement>> {
+        let limit = limit.unwrap_or(usize::MAX);
         let root = match &self.root {
             Some(r) => r.clone(),
-            None => {
-                // Get system-wide element as root
-                let sys = ax::UiElement::sys_wide();
-                UIElement::new(sys)
-            }
+            // System-wide element (desktop root on Windows)
+            None => UIElement::new(native::system_root()?),
         };
 
-        let mut results = Vec::new();
-        self.find_recursive(root.raw(), 0, &mut results);
+        // Let the platform search natively when it can, re-checking each
+        // candidate for the conditions it couldn't express
+        let results = match native::find_candidates(root.raw(), &self.selector, self.max_depth)? {
+            Some(candidates) => self.filter_candidates(candidates, limit, same_level),
+            None => self.find_breadth_first(root, limit, same_level),
+        };
 
         // Add indices
         let results: Vec<UIElement> = results
@@ -84,57 +154,122 @@ impl Locator {
             .map(|(i, e)| e.with_index(i))
             .collect();
 
+        tracing::Span::current().record("matches", results.len());
         Ok(results)
     }
 
-    fn find_recursive(&self, element: &ax::UiElement, depth: usize, results: &mut Vec<UIElement>) {
-        if depth > self.max_depth {
-            return;
+    /// Level by level, so a button in a toolbar turns up before the walk
+    /// reaches deep into a document or an outline. When pruning with an
+    /// exact role to look for, subtrees that can't hold it are skipped
+    fn find_breadth_first(&self, root: UIElement, limit: usize, same_level: bool) -> Vec<UIElement> {
+        let wanted = self.role_hint().filter(|_| self.prune);
+        let mut results = Vec::new();
+        // Deepest level still searched
+        let mut max_depth = self.max_depth;
+        let mut walk = Walk::breadth_first(root, ());
+        while let Some((element, depth, ())) = walk.next() {
+            if depth > max_depth {
+                break;
+            }
+            // Fetched once, for both matching and pruning
+            let role = wanted.map(|_| element.role());
+            let matched = self.matches_with(|attr| match (attr, &role) {
+                (Attribute::Role, Some(role)) => role.clone(),
+                _ => element.attribute(attr),
+            });
+            if matched {
+                results.push(element.clone());
+                if results.len() >= limit {
+                    break;
+                }
+                if same_level {
+                    max_depth = depth;
+                }
+            }
+            let pruned = match (wanted, role.as_ref().and_then(|r| r.as_deref())) {
+                (Some(wanted), Some(role)) => depth > 0 && !can_contain(role, wanted),
+                _ => false,
+            };
+            if depth < max_depth && !pruned {
+                walk.descend(element.children(), |_| ());
+            }
         }
+        results
+    }
 
-        if self.matches(element) {
-            results.push(UIElement::new(element.retained()));
-        }
+    /// What the platform found natively, held to the walk's rules: no
+    /// deeper than `max_depth`, nothing below a pruned element, the
+    /// shallowest first, and with `same_level` only those as shallow as the
+    /// first. Each candidate comes as its path from the root
+    fn filter_candidates(
+        &self,
+        candidates: Vec<Vec<NativeElement>>,
+        limit: usize,
+        same_level: bool,
+    ) -> Vec<UIElement> {
+        let wanted = self.role_hint().filter(|_| self.prune);
+        let mut found: Vec<(usize, UIElement)> = candidates
+            .into_iter()
+            .filter(|path| !path.is_empty() && path.len() <= self.max_depth + 1)
+            .filter_map(|path| {
+                let depth = path.len() - 1;
+                let mut path = path.into_iter().map(UIElement::new);
+                let element = path.next_back()?;
+                // The root is never pruned, as in the walk
+                let pruned = wanted.is_some_and(|wanted| {
+                    path.skip(1).any(|a| a.role().is_some_and(|r| !can_contain(&r, wanted)))
+                });
+                (!pruned && self.matches(&element)).then_some((depth, element))
+            })
+            .collect();
+        // Stable, so each level keeps the document order the walk has too
+        found.sort_by_key(|(depth, _)| *depth);
+        let shallowest = found.first().map(|(depth, _)| *depth);
+        found
+            .into_iter()
+            .take_while(|(depth, _)| !same_level || Some(*depth) == shallowest)
+            .take(limit)
+            .map(|(_, element)| element)
+            .collect()
+    }
 
-        for child in get_children(element) {
-            self.find_recursive(&child, depth + 1, results);
-        }
+    /// The role asked for exactly, if any
+    fn role_hint(&self) -> Option<&str> {
+        self.selector
+            .conditions
+            .iter()
+            .find(|c| c.attr == Attribute::Role && c.op == MatchOp::Equals)
+            .map(|c| c.value.as_str())
     }
 
-    fn matches(&self, element: &ax::UiElement) -> bool {
-        let role = get_role(element);
-        let name = get_role_desc(element);
-        let title = get_title(element);
-        let value = get_value(element);
-        let desc = get_description(element);
+    fn matches(&self, element: &UIElement) -> bool {
+        self.matches_with(|attr| element.attribute(attr))
+    }
 
-        for cond in &self.selector.conditions {
-            if cond.attr == Attribute::Index {
-                continue; // Index handled separately
-            }
-            if !cond.matches(
-                role.as_deref(),
-                name.as_deref(),
-                title.as_deref(),
-                value.as_deref(),
-                desc.as_deref(),
-            ) {
-                return false;
-            }
-        }
-        true
+    fn matches_with(&self, attribute: impl Fn(Attribute) -> Option<String>) -> bool {
+        // Only fetch the attributes the selector actually uses
+        self.selector
+            .conditions
+            .iter()
+            .filter(|c| c.attr != Attribute::Index) // Index handled separately
+            .all(|c| c.matches_value(attribute(c.attr).as_deref()))
     }
 
     pub fn exists(&self) -> bool {
-        self.find_all().map(|v| !v.is_empty()).unwrap_or(false)
+        self.search(Some(1), false).map(|v| !v.is_empty()).unwrap_or(false)
     }
 
+    #[tracing::instrument(
+        skip_all,
+        fields(selector = %self.selector, app = self.app, timeout_ms = self.timeout_ms),
+        err
+    )]
     pub fn wait(&self) -> Result<UIElement> {
         let start = Instant::now();
         let timeout = Duration::from_millis(self.timeout_ms);
 
         loop {
-            match self.find_all() {
+            match self.search(Some(1), false) {
                 Ok(elements) if !elements.is_empty() => {
                     return Ok(elements.into_iter().next().unwrap());
                 }
@@ -148,6 +283,11 @@ impl Locator {
         }
     }
 
+    #[tracing::instrument(
+        skip_all,
+        fields(selector = %self.selector, app = self.app, timeout_ms = self.timeout_ms),
+        err
+    )]
     pub fn wait_gone(&self) -> Result<()> {
         let start = Instant::now();
         let timeout = Duration::from_millis(self.timeout_ms);
@@ -168,10 +308,18 @@ impl Locator {
 
     // Actions - find then act
 
+    #[tracing::instrument(skip_all, fields(selector = %self.selector, app = self.app), ret, err)]
     pub fn click(&self) -> Result<ActionResult> {
         self.find()?.click()
     }
 
+    // The text may be a password, so only its length goes in the span
+    #[tracing::instrument(
+        skip_all,
+        fields(selector = %self.selector, app = self.app, chars = text.chars().count()),
+        ret,
+        err
+    )]
     pub fn type_text(&self, text: &str) -> Result<ActionResult> {
         let element = self.find()?;
         element.click()?;
@@ -179,3 +327,143 @@ impl Locator {
         element.set_value(text)
     }
 }
+
+/// Whether an element with role `container` can have a `wanted` one below
+/// it. Roles as macOS (AXMenu) or Windows (Menu) name them
+fn can_contain(container: &str, wanted: &str) -> bool {
+    // Windows are the app's children on macOS; UIA nests dialogs in them
+    if wanted == "AXWindow" {
+        return container == "AXApplication";
+    }
+    let container = container.strip_prefix("AX").unwrap_or(container);
+    let wanted = wanted.strip_prefix("AX").unwrap_or(wanted);
+    let menu = |role: &str| role.starts_with("Menu");
+    match container {
+        _ if menu(container) => menu(wanted),
+        "StaticText" | "Text" | "Image" => matches!(wanted, "StaticText" | "Text" | "Image"),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prunes_subtrees_that_cant_hold_the_role() {
+        assert!(can_contain("AXApplication", "AXWindow"));
+        assert!(!can_contain("AXWindow", "AXWindow"));
+        assert!(!can_contain("AXMenuBar", "AXButton"));
+        assert!(can_contain("AXMenuBarItem", "AXMenuItem"));
+        assert!(!can_contain("AXStaticText", "AXButton"));
+        assert!(can_contain("AXGroup", "AXButton"));
+        assert!(!can_contain("Menu", "Button"));
+        assert!(can_contain("Pane", "Button"));
+        assert!(can_contain("Window", "Window"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn prunes_only_when_asked() {
+        use crate::platform::mock;
+
+        let _fixture = mock::lock();
+        // Links in text and check boxes in menus, as UIA trees have them
+        let fixture = r#"{"apps": [{"name": "Mail", "children": [{"role": "Window", "children": [
+            {"role": "Text", "children": [{"role": "Hyperlink", "name": "Unsubscribe"}]},
+            {"role": "Menu", "children": [{"role": "CheckBox", "name": "Bold"}]},
+            {"role": "CheckBox", "name": "Flagged"}
+        ]}]}]}"#;
+        mock::set(&serde_json::from_str(fixture).unwrap());
+
+        let found = |selector: &str, prune: bool| {
+            let found = Locator::parse(selector).unwrap().prune(prune).find_all().unwrap();
+            found.iter().map(|e| e.name().unwrap_or_default()).collect::<Vec<_>>()
+        };
+        assert_eq!(found("role:CheckBox", false), ["Flagged", "Bold"]);
+        assert_eq!(found("role:CheckBox", true), ["Flagged"]);
+        assert_eq!(found("role:Hyperlink", false), ["Unsubscribe"]);
+        assert!(found("role:Hyperlink", true).is_empty());
+        assert_eq!(Locator::parse("role:CheckBox").unwrap().find_all().unwrap().len(), 2);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn finds_the_shallowest_and_stops_at_its_level() {
+        use crate::platform::mock;
+
+        let _fixture = mock::lock();
+        // A button deep in a document comes first depth first, the toolbar's
+        // breadth first
+        let fixture = r#"{"apps": [{"name": "Xcode", "children": [{"role": "AXWindow", "children": [
+            {"role": "AXGroup", "children": [{"role": "AXGroup", "name": "pane", "children": [
+                {"role": "AXGroup", "children": [{"role": "AXButton", "title": "Deep"}]}
+            ]}]},
+            {"role": "AXToolbar", "children": [
+                {"role": "AXButton", "title": "Run"},
+                {"role": "AXGroup", "name": "pane", "children": [{"role": "AXButton", "title": "Stop"}]}
+            ]}
+        ]}]}]}"#;
+        mock::set(&serde_json::from_str(fixture).unwrap());
+
+        let titles = |found: Vec<UIElement>| {
+            found.iter().map(|e| e.title().unwrap_or_default()).collect::<Vec<_>>()
+        };
+        let button = Locator::parse("role:AXButton").unwrap();
+        mock::take_expanded();
+        assert_eq!(titles(button.find_all().unwrap()), ["Run", "Stop", "Deep"]);
+        assert_eq!(mock::take_expanded(), 11);
+
+        // Deeper buttons don't make it ambiguous, and aren't looked for: only
+        // what's above Run, and the group on its level before it, is opened
+        assert_eq!(button.find().unwrap().title().as_deref(), Some("Run"));
+        assert_eq!(mock::take_expanded(), 6);
+        assert_eq!(button.find_first().unwrap().title().as_deref(), Some("Run"));
+
+        // Two as shallow do
+        let pane = Locator::parse("role:AXGroup AND name:pane").unwrap();
+        assert_eq!(pane.find().unwrap_err().code, crate::ErrorCode::MultipleMatches);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn native_candidates_follow_the_walks_rules() {
+        use crate::platform::mock;
+
+        let _fixture = mock::lock();
+        // A field nested in another, and a list deeper still; found both
+        // ways, walking and as FindAll gives them on Windows
+        let mut fixture = serde_json::json!({"apps": [{"name": "Teams", "children": [
+            {"role": "Window", "children": [
+                {"role": "Pane", "children": [{"role": "Edit", "name": "Reply", "children": [
+                    {"role": "Edit", "name": "Reply"}
+                ]}]},
+                {"role": "Edit", "name": "Search"},
+                {"role": "Menu", "children": [{"role": "Edit", "name": "Filter"}]}
+            ]}
+        ]}]});
+        for native in [false, true] {
+            fixture["native_find"] = native.into();
+            mock::set(&serde_json::from_value(fixture.clone()).unwrap());
+
+            let names = |locator: Locator| {
+                let found = locator.find_all().unwrap();
+                found.iter().map(|e| e.name().unwrap_or_default()).collect::<Vec<_>>()
+            };
+            let edit = || Locator::parse("role:Edit").unwrap();
+            // System, app, window, then level by level
+            assert_eq!(names(edit()), ["Search", "Reply", "Filter", "Reply"], "native {}", native);
+            assert_eq!(names(edit().depth(3)), ["Search"], "native {}", native);
+            assert_eq!(names(edit().prune(true)), ["Search", "Reply", "Reply"], "native {}", native);
+
+            // The nested Reply is deeper, so it doesn't make find() ambiguous
+            let reply = Locator::parse("role:Edit AND name:Reply").unwrap();
+            assert_eq!(reply.find().unwrap().name().as_deref(), Some("Reply"));
+            assert_eq!(reply.find_all().unwrap().len(), 2);
+            assert_eq!(edit().find().unwrap().name().as_deref(), Some("Search"));
+            assert_eq!(edit().first().find().unwrap().name().as_deref(), Some("Search"));
+            let none = Locator::parse("role:Edit AND name:Reply").unwrap().depth(3);
+            assert_eq!(none.find().unwrap_err().code, crate::ErrorCode::ElementNotFound);
+        }
+    }
+}
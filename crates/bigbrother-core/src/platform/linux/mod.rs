@@ -7,25 +7,40 @@
 //! - XTest or libevdev for input injection
 //! - D-Bus for app enumeration
 
-use crate::{Error, Result};
+pub mod native;
+
+use crate::{Error, ErrorCode, Result};
 
 /// Check if the process has accessibility permissions
 pub fn has_accessibility() -> bool {
-    // Linux typically doesn't require explicit permissions for AT-SPI
-    // But we might need to check if AT-SPI is available
-    todo!("Linux: Check AT-SPI2 availability")
+    // No AT-SPI2 backend yet
+    false
 }
 
 /// Request accessibility permissions
 pub fn request_accessibility() -> bool {
     // Linux doesn't have a permission prompt like macOS
-    todo!("Linux: AT-SPI2 setup instructions")
+    false
 }
 
 /// Ensure accessibility is available
 pub fn ensure_accessibility() -> Result<()> {
-    Err(Error::new(
-        crate::ErrorCode::NotImplemented,
+    Err(not_implemented())
+}
+
+/// Check if the process may capture the screen (no permission on Linux)
+pub fn has_screen_recording() -> bool {
+    true
+}
+
+/// Ensure screen capture is allowed
+pub fn ensure_screen_recording() -> Result<()> {
+    Ok(())
+}
+
+pub(crate) fn not_implemented() -> Error {
+    Error::new(
+        ErrorCode::NotImplemented,
         "Linux support coming soon. Contributions welcome!".to_string(),
-    ))
+    )
 }
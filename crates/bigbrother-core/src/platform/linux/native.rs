@@ -0,0 +1,146 @@
+//! Backend for the cross-platform `Desktop`/`UIElement`/`Locator` API
+//!
+//! No accessibility backend yet, so no element can ever be obtained and
+//! every entry point reports NotImplemented.
+
+use super::not_implemented;
+use crate::element::ElementInfo;
+use crate::script::ScriptLanguage;
+use crate::selector::Selector;
+use crate::Result;
+
+/// Placeholder - uninhabited until AT-SPI2 support lands
+#[derive(Clone)]
+pub enum NativeElement {}
+
+impl NativeElement {
+    pub fn role(&self) -> Option<String> {
+        match *self {}
+    }
+
+    pub fn name(&self) -> Option<String> {
+        match *self {}
+    }
+
+    pub fn title(&self) -> Option<String> {
+        match *self {}
+    }
+
+    pub fn value(&self) -> Option<String> {
+        match *self {}
+    }
+
+    pub fn description(&self) -> Option<String> {
+        match *self {}
+    }
+
+    pub fn automation_id(&self) -> Option<String> {
+        match *self {}
+    }
+
+    pub fn class_name(&self) -> Option<String> {
+        match *self {}
+    }
+
+    pub fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        match *self {}
+    }
+
+    pub fn info(&self) -> ElementInfo {
+        match *self {}
+    }
+
+    pub fn children(&self) -> Vec<NativeElement> {
+        match *self {}
+    }
+
+    pub fn press(&self) -> Result<()> {
+        match *self {}
+    }
+}
+
+impl PartialEq for NativeElement {
+    fn eq(&self, _other: &Self) -> bool {
+        match *self {}
+    }
+}
+
+impl Eq for NativeElement {}
+
+impl std::hash::Hash for NativeElement {
+    fn hash<H: std::hash::Hasher>(&self, _state: &mut H) {
+        match *self {}
+    }
+}
+
+pub fn find_candidates(
+    root: &NativeElement,
+    _selector: &Selector,
+    _max_depth: usize,
+) -> Result<Option<Vec<Vec<NativeElement>>>> {
+    match *root {}
+}
+
+pub fn system_root() -> Result<NativeElement> {
+    Err(not_implemented())
+}
+
+pub fn focused_element() -> Result<NativeElement> {
+    Err(not_implemented())
+}
+
+pub fn element_at(_x: f64, _y: f64) -> Result<NativeElement> {
+    Err(not_implemented())
+}
+
+pub fn app_root(_app: &str) -> Result<NativeElement> {
+    Err(not_implemented())
+}
+
+pub fn running_apps() -> Result<Vec<(String, i32)>> {
+    Err(not_implemented())
+}
+
+pub fn find_app_pid(_app: &str) -> Result<i32> {
+    Err(not_implemented())
+}
+
+pub fn find_browser() -> Result<(String, i32)> {
+    Err(not_implemented())
+}
+
+pub fn open_url(_url: &str) -> Result<()> {
+    Err(not_implemented())
+}
+
+pub fn activate_app(_app: &str) -> Result<()> {
+    Err(not_implemented())
+}
+
+pub fn run_script(_script: &str, _language: ScriptLanguage) -> Result<serde_json::Value> {
+    Err(crate::script::unsupported())
+}
+
+pub fn run_shortcut(_name: &str, _input: Option<&serde_json::Value>) -> Result<serde_json::Value> {
+    Err(crate::script::unsupported())
+}
+
+pub fn scroll_up(_pages: u32) -> Result<()> {
+    Err(not_implemented())
+}
+
+pub fn scroll_down(_pages: u32) -> Result<()> {
+    Err(not_implemented())
+}
+
+pub fn press_key(_key_code: u8) -> Result<()> {
+    Err(not_implemented())
+}
+
+pub fn type_text(_text: &str) -> Result<()> {
+    Err(not_implemented())
+}
+
+pub fn primary_shortcut(_key: &str) -> Result<()> {
+    Err(not_implemented())
+}
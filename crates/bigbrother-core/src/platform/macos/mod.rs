@@ -2,6 +2,8 @@
 //!
 //! Uses Accessibility API (AX) and Core Graphics (CG) via cidre.
 
+pub mod native;
+
 use cidre::ax;
 
 /// Check if the process has accessibility permissions
@@ -24,3 +26,32 @@ pub fn ensure_accessibility() -> crate::Result<()> {
         "Accessibility permissions required. Enable in System Settings > Privacy & Security > Accessibility"
     ))
 }
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+/// Check if the process may capture the screen; without it captures come
+/// out black (or show only the desktop) instead of failing
+pub fn has_screen_recording() -> bool {
+    unsafe { CGPreflightScreenCaptureAccess() }
+}
+
+/// Request screen recording permission with a prompt
+pub fn request_screen_recording() -> bool {
+    unsafe { CGRequestScreenCaptureAccess() }
+}
+
+/// Ensure screen recording permission is granted
+pub fn ensure_screen_recording() -> crate::Result<()> {
+    if has_screen_recording() {
+        return Ok(());
+    }
+    request_screen_recording();
+    Err(crate::Error::permission_denied(
+        "Screen Recording permission required. Enable in System Settings > Privacy & Security > \
+         Screen Recording, then restart the app",
+    ))
+}
@@ -0,0 +1,203 @@
+//! Backend for the cross-platform `Desktop`/`UIElement`/`Locator` API
+
+use crate::accessibility::*;
+use crate::element::{Bounds, ElementInfo};
+use crate::script::ScriptLanguage;
+use crate::selector::Selector;
+use crate::{apps, input};
+use crate::{Error, Result};
+use cidre::arc::R;
+use cidre::ax;
+
+/// An AX UI element
+#[derive(Clone)]
+pub struct NativeElement(R<ax::UiElement>);
+
+impl NativeElement {
+    pub fn new(element: R<ax::UiElement>) -> Self {
+        Self(element)
+    }
+
+    /// The underlying AX element
+    pub fn ax(&self) -> &ax::UiElement {
+        &self.0
+    }
+
+    pub fn role(&self) -> Option<String> {
+        get_role(&self.0)
+    }
+
+    pub fn name(&self) -> Option<String> {
+        get_role_desc(&self.0)
+    }
+
+    pub fn title(&self) -> Option<String> {
+        get_title(&self.0)
+    }
+
+    pub fn value(&self) -> Option<String> {
+        get_value(&self.0)
+    }
+
+    pub fn description(&self) -> Option<String> {
+        get_description(&self.0)
+    }
+
+    pub fn automation_id(&self) -> Option<String> {
+        get_identifier(&self.0)
+    }
+
+    pub fn class_name(&self) -> Option<String> {
+        None
+    }
+
+    pub fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        get_bounds(&self.0)
+    }
+
+    /// Everything at once, in one round trip to the app
+    pub fn info(&self) -> ElementInfo {
+        let attributes = get_attributes(&self.0);
+        ElementInfo {
+            index: None,
+            role: attributes.role.unwrap_or_else(|| "Unknown".to_string()),
+            name: attributes.role_desc,
+            title: attributes.title,
+            value: attributes.value,
+            description: attributes.description,
+            automation_id: attributes.identifier,
+            class_name: None,
+            bounds: attributes.bounds.map(|(x, y, width, height)| Bounds { x, y, width, height }),
+        }
+    }
+
+    pub fn children(&self) -> Vec<NativeElement> {
+        get_children(&self.0).into_iter().map(NativeElement).collect()
+    }
+
+    /// Perform the AXPress action
+    pub fn press(&self) -> Result<()> {
+        self.0
+            .perform_action(ax::action::press())
+            .map_err(|e| Error::action_failed("click", &format!("{:?}", e)))
+    }
+}
+
+impl PartialEq for NativeElement {
+    fn eq(&self, other: &Self) -> bool {
+        same_element(&self.0, &other.0)
+    }
+}
+
+impl Eq for NativeElement {}
+
+impl std::hash::Hash for NativeElement {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        element_hash(&self.0).hash(state)
+    }
+}
+
+impl From<R<ax::UiElement>> for NativeElement {
+    fn from(element: R<ax::UiElement>) -> Self {
+        Self(element)
+    }
+}
+
+/// AX has no in-process search, so the locator walks the tree itself
+pub fn find_candidates(
+    _root: &NativeElement,
+    _selector: &Selector,
+    _max_depth: usize,
+) -> Result<Option<Vec<Vec<NativeElement>>>> {
+    Ok(None)
+}
+
+/// System-wide element
+pub fn system_root() -> Result<NativeElement> {
+    Ok(NativeElement(ax::UiElement::sys_wide()))
+}
+
+/// Element with keyboard focus, anywhere on the system
+pub fn focused_element() -> Result<NativeElement> {
+    let value = ax::UiElement::sys_wide()
+        .attr_value(ax::attr::focused_ui_element())
+        .map_err(|_| Error::element_not_found("focused element"))?;
+    if value.get_type_id() != ax::UiElement::type_id() {
+        return Err(Error::element_not_found("focused element"));
+    }
+    let element: &ax::UiElement = unsafe { std::mem::transmute(&*value) };
+    Ok(NativeElement(element.retained()))
+}
+
+/// Topmost element at a screen point (global display coordinates)
+pub fn element_at(x: f64, y: f64) -> Result<NativeElement> {
+    ax::UiElement::sys_wide()
+        .element_at_pos(x as f32, y as f32)
+        .map(NativeElement)
+        .map_err(|_| Error::element_not_found(&format!("element at {},{}", x, y)))
+}
+
+/// Application element by app name
+pub fn app_root(app: &str) -> Result<NativeElement> {
+    apps::get_app_by_name(app)
+        .map(NativeElement)
+        .map_err(|_| Error::app_not_running(app))
+}
+
+/// Running (non-background) applications: (name, pid)
+pub fn running_apps() -> Result<Vec<(String, i32)>> {
+    let names = apps::list_running_apps().map_err(Error::from)?;
+    Ok(names
+        .into_iter()
+        .filter_map(|name| {
+            let pid = apps::find_app_pid(&name).ok()?;
+            Some((name, pid))
+        })
+        .collect())
+}
+
+pub fn find_app_pid(app: &str) -> Result<i32> {
+    apps::find_app_pid(app).map_err(|_| Error::app_not_running(app))
+}
+
+pub fn find_browser() -> Result<(String, i32)> {
+    apps::find_browser().map_err(Error::from)
+}
+
+pub fn open_url(url: &str) -> Result<()> {
+    apps::open_url(url).map_err(Error::from)
+}
+
+pub fn activate_app(app: &str) -> Result<()> {
+    apps::activate_app(app).map_err(Error::from)
+}
+
+pub fn run_script(script: &str, language: ScriptLanguage) -> Result<serde_json::Value> {
+    crate::script::run(script, language)
+}
+
+pub fn run_shortcut(name: &str, input: Option<&serde_json::Value>) -> Result<serde_json::Value> {
+    crate::script::shortcut(name, input)
+}
+
+pub fn scroll_up(pages: u32) -> Result<()> {
+    input::scroll_up(pages).map_err(Error::from)
+}
+
+pub fn scroll_down(pages: u32) -> Result<()> {
+    input::scroll_down(pages).map_err(Error::from)
+}
+
+/// Press a macOS virtual keycode
+pub fn press_key(key_code: u8) -> Result<()> {
+    input::press_key(key_code).map_err(Error::from)
+}
+
+pub fn type_text(text: &str) -> Result<()> {
+    input::type_text(text).map_err(Error::from)
+}
+
+/// Cmd+key
+pub fn primary_shortcut(key: &str) -> Result<()> {
+    input::cmd(key).map_err(Error::from)
+}
@@ -0,0 +1,281 @@
+//! Mock platform implementation (`mock` feature)
+//!
+//! Stands in for the OS so automation runs without a GUI session or
+//! permissions, e.g. in CI: `Desktop` works on a synthetic element tree
+//! loaded from a fixture, and input is logged as [`Action`]s instead of
+//! being sent. The fixture comes from [`load`] or [`set`], or from the file
+//! `BIGBROTHER_MOCK` names the first time it's needed.
+//!
+//! ```json
+//! {"apps": [{"name": "Notes", "pid": 42, "children": [
+//!     {"role": "AXWindow", "title": "Notes", "bounds": [0, 0, 800, 600], "children": [
+//!         {"role": "AXButton", "title": "New Note", "bounds": [10, 10, 80, 24]},
+//!         {"role": "AXTextArea", "value": "Groceries", "focused": true}
+//!     ]}
+//! ]}]}
+//! ```
+//!
+//! Apps' roots are `AXApplication` elements titled with their name, all
+//! children of the system root, so locators without an app search them all.
+
+pub mod native;
+
+use crate::element::{Bounds, ElementInfo};
+use crate::{Error, ErrorCode, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Environment variable naming a fixture file to load on first use
+pub const FIXTURE_ENV: &str = "BIGBROTHER_MOCK";
+
+/// The desktop a mock session sees
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fixture {
+    #[serde(default)]
+    pub apps: Vec<MockApp>,
+    /// Answer locators the way Windows does, every candidate at once in
+    /// document order as UIA's FindAll gives them, instead of letting them
+    /// walk the tree
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub native_find: bool,
+}
+
+/// A running app and its UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockApp {
+    pub name: String,
+    #[serde(default)]
+    pub pid: i32,
+    #[serde(default)]
+    pub children: Vec<MockNode>,
+}
+
+/// An element, with the attributes `ElementInfo` reports
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MockNode {
+    pub role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub automation_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class_name: Option<String>,
+    /// x, y, width, height
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<[f64; 4]>,
+    /// Has keyboard focus
+    #[serde(default)]
+    pub focused: bool,
+    #[serde(default)]
+    pub children: Vec<MockNode>,
+}
+
+/// Input that would have been sent
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    Press { role: String, title: Option<String> },
+    TypeText { text: String },
+    PressKey { key_code: u8 },
+    Shortcut { key: String },
+    ScrollUp { pages: u32 },
+    ScrollDown { pages: u32 },
+    OpenUrl { url: String },
+    Activate { app: String },
+}
+
+/// A fixture flattened into elements addressed by index, 0 being the
+/// system root
+pub(crate) struct Tree {
+    nodes: Vec<Node>,
+    apps: Vec<(String, i32, usize)>,
+    native_find: bool,
+}
+
+struct Node {
+    info: ElementInfo,
+    focused: bool,
+    children: Vec<usize>,
+}
+
+impl Tree {
+    fn new(fixture: &Fixture) -> Self {
+        let mut tree = Tree { nodes: Vec::new(), apps: Vec::new(), native_find: fixture.native_find };
+        let system = MockNode { role: "AXSystemWide".into(), ..Default::default() };
+        tree.add(&system);
+        for app in &fixture.apps {
+            let root = MockNode {
+                role: "AXApplication".into(),
+                title: Some(app.name.clone()),
+                children: app.children.clone(),
+                ..Default::default()
+            };
+            let id = tree.add(&root);
+            tree.nodes[0].children.push(id);
+            tree.apps.push((app.name.clone(), app.pid, id));
+        }
+        tree
+    }
+
+    /// `node` and what's under it, in preorder; returns its index
+    fn add(&mut self, node: &MockNode) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            info: ElementInfo {
+                index: None,
+                role: node.role.clone(),
+                name: node.name.clone(),
+                title: node.title.clone(),
+                value: node.value.clone(),
+                description: node.description.clone(),
+                automation_id: node.automation_id.clone(),
+                class_name: node.class_name.clone(),
+                bounds: node.bounds.map(|[x, y, width, height]| Bounds { x, y, width, height }),
+            },
+            focused: node.focused,
+            children: Vec::new(),
+        });
+        for child in &node.children {
+            let child = self.add(child);
+            self.nodes[id].children.push(child);
+        }
+        id
+    }
+}
+
+struct State {
+    tree: Option<Arc<Tree>>,
+    actions: Vec<Action>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State { tree: None, actions: Vec::new() });
+
+fn state() -> MutexGuard<'static, State> {
+    // Nothing is left half-updated by a panic
+    STATE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Held by tests that set a fixture, since it's global
+#[cfg(test)]
+pub(crate) fn lock() -> MutexGuard<'static, ()> {
+    static LOCK: Mutex<()> = Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Use `fixture` from now on; elements found before keep the old one
+pub fn set(fixture: &Fixture) {
+    state().tree = Some(Arc::new(Tree::new(fixture)));
+}
+
+/// Use the fixture in the JSON file at `path`
+pub fn load(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let invalid = |e: &dyn std::fmt::Display| {
+        Error::new(ErrorCode::Unknown, format!("Can't load fixture {}: {}", path.display(), e))
+    };
+    let json = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+    let fixture: Fixture = serde_json::from_str(&json).map_err(|e| invalid(&e))?;
+    set(&fixture);
+    Ok(())
+}
+
+/// The input logged since the last call
+pub fn take_actions() -> Vec<Action> {
+    std::mem::take(&mut state().actions)
+}
+
+/// Elements whose children were asked for since the last call, to see how
+/// much of the tree a search walked
+pub fn take_expanded() -> usize {
+    EXPANDED.swap(0, Ordering::Relaxed)
+}
+
+static EXPANDED: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn expanded() {
+    EXPANDED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn record(action: Action) {
+    tracing::info!(?action, "Mock input");
+    state().actions.push(action);
+}
+
+/// The current tree, loading `BIGBROTHER_MOCK` if there's none yet
+pub(crate) fn tree() -> Result<Arc<Tree>> {
+    if let Some(tree) = &state().tree {
+        return Ok(tree.clone());
+    }
+    match std::env::var_os(FIXTURE_ENV) {
+        Some(path) => load(path)?,
+        None => {
+            return Err(Error::new(
+                ErrorCode::NotImplemented,
+                format!("No mock fixture: set {} to a fixture file", FIXTURE_ENV),
+            ))
+        }
+    }
+    tree()
+}
+
+/// A fixture is loaded, or can be
+pub fn has_accessibility() -> bool {
+    tree().is_ok()
+}
+
+pub fn request_accessibility() -> bool {
+    has_accessibility()
+}
+
+pub fn ensure_accessibility() -> Result<()> {
+    tree().map(|_| ())
+}
+
+pub fn has_screen_recording() -> bool {
+    true
+}
+
+pub fn ensure_screen_recording() -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Desktop;
+
+    #[test]
+    fn desktop_runs_on_a_fixture() {
+        let _fixture = lock();
+        let fixture = r#"{"apps": [{"name": "Notes", "pid": 42, "children": [
+            {"role": "AXWindow", "title": "Notes", "bounds": [0, 0, 800, 600], "children": [
+                {"role": "AXButton", "title": "New Note", "bounds": [10, 10, 80, 24]},
+                {"role": "AXTextArea", "value": "Groceries", "focused": true}
+            ]}
+        ]}]}"#;
+        set(&serde_json::from_str(fixture).unwrap());
+        take_actions();
+
+        let mut desktop = Desktop::new().unwrap();
+        assert_eq!(desktop.find_app("notes").unwrap().pid, 42);
+        assert_eq!(desktop.tree("Notes", 5).unwrap().nodes.len(), 4);
+
+        desktop.locator("role:AXButton").unwrap().app("Notes").click().unwrap();
+        desktop.type_text("milk").unwrap();
+        let press = Action::Press { role: "AXButton".into(), title: Some("New Note".into()) };
+        assert_eq!(take_actions(), [press, Action::TypeText { text: "milk".into() }]);
+
+        assert_eq!(desktop.focused().unwrap().value().as_deref(), Some("Groceries"));
+        assert_eq!(desktop.element_at(20.0, 20.0).unwrap().role().as_deref(), Some("AXButton"));
+        assert_eq!(desktop.element_at(500.0, 500.0).unwrap().role().as_deref(), Some("AXWindow"));
+        assert!(desktop.element_at(900.0, 0.0).is_err());
+    }
+}
@@ -0,0 +1,218 @@
+//! Backend for the cross-platform `Desktop`/`UIElement`/`Locator` API
+//!
+//! Elements are nodes of the loaded fixture; input is logged, not sent.
+
+use super::{expanded, record, tree, Action, Tree};
+use crate::element::ElementInfo;
+use crate::script::ScriptLanguage;
+use crate::selector::Selector;
+use crate::{Error, Result};
+use std::sync::Arc;
+
+/// Browsers `find_browser` recognizes, as on macOS
+const BROWSERS: &[&str] = &[
+    "Safari",
+    "Google Chrome",
+    "Arc",
+    "Firefox",
+    "Microsoft Edge",
+    "Brave Browser",
+    "Chromium",
+];
+
+/// A node of a fixture
+#[derive(Clone)]
+pub struct NativeElement {
+    tree: Arc<Tree>,
+    id: usize,
+}
+
+impl NativeElement {
+    fn info_ref(&self) -> &ElementInfo {
+        &self.tree.nodes[self.id].info
+    }
+
+    pub fn role(&self) -> Option<String> {
+        Some(self.info_ref().role.clone())
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.info_ref().name.clone()
+    }
+
+    pub fn title(&self) -> Option<String> {
+        self.info_ref().title.clone()
+    }
+
+    pub fn value(&self) -> Option<String> {
+        self.info_ref().value.clone()
+    }
+
+    pub fn description(&self) -> Option<String> {
+        self.info_ref().description.clone()
+    }
+
+    pub fn automation_id(&self) -> Option<String> {
+        self.info_ref().automation_id.clone()
+    }
+
+    pub fn class_name(&self) -> Option<String> {
+        self.info_ref().class_name.clone()
+    }
+
+    pub fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        self.info_ref().bounds.as_ref().map(|b| (b.x, b.y, b.width, b.height))
+    }
+
+    pub fn info(&self) -> ElementInfo {
+        self.info_ref().clone()
+    }
+
+    pub fn children(&self) -> Vec<NativeElement> {
+        expanded();
+        let children = &self.tree.nodes[self.id].children;
+        children.iter().map(|&id| NativeElement { tree: self.tree.clone(), id }).collect()
+    }
+
+    pub fn press(&self) -> Result<()> {
+        let info = self.info_ref();
+        record(Action::Press { role: info.role.clone(), title: info.title.clone() });
+        Ok(())
+    }
+}
+
+impl PartialEq for NativeElement {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.tree, &other.tree) && self.id == other.id
+    }
+}
+
+impl Eq for NativeElement {}
+
+impl std::hash::Hash for NativeElement {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state)
+    }
+}
+
+/// The locator walks fixtures like AX trees, unless the fixture asks for
+/// `native_find`: then every element below `root` (and `root`) within
+/// `max_depth` is a candidate, with its path, in document order. The
+/// locator re-checks the selector on each, as it does on Windows.
+pub fn find_candidates(
+    root: &NativeElement,
+    _selector: &Selector,
+    max_depth: usize,
+) -> Result<Option<Vec<Vec<NativeElement>>>> {
+    if !root.tree.native_find {
+        return Ok(None);
+    }
+    let mut paths = Vec::new();
+    let mut stack = vec![vec![root.clone()]];
+    while let Some(path) = stack.pop() {
+        let children = path.last().map(|e| e.children()).unwrap_or_default();
+        if path.len() <= max_depth {
+            for child in children.into_iter().rev() {
+                let mut below = path.clone();
+                below.push(child);
+                stack.push(below);
+            }
+        }
+        paths.push(path);
+    }
+    Ok(Some(paths))
+}
+
+pub fn system_root() -> Result<NativeElement> {
+    Ok(NativeElement { tree: tree()?, id: 0 })
+}
+
+pub fn focused_element() -> Result<NativeElement> {
+    let tree = tree()?;
+    let id = tree.nodes.iter().position(|n| n.focused);
+    let id = id.ok_or_else(|| Error::element_not_found("focused element"))?;
+    Ok(NativeElement { tree, id })
+}
+
+/// The last element in document order whose bounds hold the point, so the
+/// deepest, and the frontmost of siblings listed back to front
+pub fn element_at(x: f64, y: f64) -> Result<NativeElement> {
+    let tree = tree()?;
+    let id = tree.nodes.iter().rposition(|n| {
+        n.info.bounds.as_ref().is_some_and(|b| {
+            x >= b.x && y >= b.y && x < b.x + b.width && y < b.y + b.height
+        })
+    });
+    let at = format!("element at ({}, {})", x, y);
+    let id = id.ok_or_else(|| Error::element_not_found(&at))?;
+    Ok(NativeElement { tree, id })
+}
+
+pub fn app_root(app: &str) -> Result<NativeElement> {
+    let tree = tree()?;
+    let id = tree.apps.iter().find(|(name, _, _)| name.eq_ignore_ascii_case(app)).map(|a| a.2);
+    let id = id.ok_or_else(|| Error::app_not_running(app))?;
+    Ok(NativeElement { tree, id })
+}
+
+pub fn running_apps() -> Result<Vec<(String, i32)>> {
+    Ok(tree()?.apps.iter().map(|(name, pid, _)| (name.clone(), *pid)).collect())
+}
+
+pub fn find_app_pid(app: &str) -> Result<i32> {
+    let tree = tree()?;
+    let found = tree.apps.iter().find(|(name, _, _)| name.eq_ignore_ascii_case(app));
+    found.map(|a| a.1).ok_or_else(|| Error::app_not_running(app))
+}
+
+pub fn find_browser() -> Result<(String, i32)> {
+    let tree = tree()?;
+    let browser = tree.apps.iter().find(|(name, _, _)| BROWSERS.contains(&name.as_str()));
+    let browser = browser.ok_or_else(|| Error::app_not_running("browser"))?;
+    Ok((browser.0.clone(), browser.1))
+}
+
+pub fn open_url(url: &str) -> Result<()> {
+    record(Action::OpenUrl { url: url.to_string() });
+    Ok(())
+}
+
+pub fn activate_app(app: &str) -> Result<()> {
+    app_root(app)?;
+    record(Action::Activate { app: app.to_string() });
+    Ok(())
+}
+
+pub fn run_script(_script: &str, _language: ScriptLanguage) -> Result<serde_json::Value> {
+    Err(crate::script::unsupported())
+}
+
+pub fn run_shortcut(_name: &str, _input: Option<&serde_json::Value>) -> Result<serde_json::Value> {
+    Err(crate::script::unsupported())
+}
+
+pub fn scroll_up(pages: u32) -> Result<()> {
+    record(Action::ScrollUp { pages });
+    Ok(())
+}
+
+pub fn scroll_down(pages: u32) -> Result<()> {
+    record(Action::ScrollDown { pages });
+    Ok(())
+}
+
+/// Log a macOS virtual keycode
+pub fn press_key(key_code: u8) -> Result<()> {
+    record(Action::PressKey { key_code });
+    Ok(())
+}
+
+pub fn type_text(text: &str) -> Result<()> {
+    record(Action::TypeText { text: text.to_string() });
+    Ok(())
+}
+
+pub fn primary_shortcut(key: &str) -> Result<()> {
+    record(Action::Shortcut { key: key.to_string() });
+    Ok(())
+}
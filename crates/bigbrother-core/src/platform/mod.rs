@@ -11,12 +11,19 @@ pub mod linux;
 #[cfg(target_os = "windows")]
 pub mod windows;
 
+#[cfg(feature = "mock")]
+pub mod mock;
+
 // Re-export the current platform
-#[cfg(target_os = "macos")]
+#[cfg(all(target_os = "macos", not(feature = "mock")))]
 pub use macos as current;
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "mock")))]
 pub use linux as current;
 
-#[cfg(target_os = "windows")]
+#[cfg(all(target_os = "windows", not(feature = "mock")))]
 pub use windows as current;
+
+// Replaces the OS everywhere, for CI
+#[cfg(feature = "mock")]
+pub use mock as current;
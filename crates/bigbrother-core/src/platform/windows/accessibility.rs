@@ -2,14 +2,49 @@
 //!
 //! Provides access to the Windows accessibility tree.
 
+use serde::Serialize;
+use windows::core::{Interface, BSTR, VARIANT};
 use windows::Win32::UI::Accessibility::{
-    CUIAutomation, IUIAutomation, IUIAutomationElement, IUIAutomationTreeWalker,
+    CUIAutomation, IUIAutomation, IUIAutomationCondition, IUIAutomationElement,
+    IUIAutomationInvokePattern, IUIAutomationSelectionItemPattern, IUIAutomationTogglePattern,
+    IUIAutomationTreeWalker, IUIAutomationValuePattern, PropertyConditionFlags,
+    PropertyConditionFlags_IgnoreCase, PropertyConditionFlags_MatchSubstring,
+    PropertyConditionFlags_None, ToggleState_Indeterminate, ToggleState_On, TreeScope_Subtree,
+    UIA_AutomationIdPropertyId, UIA_ClassNamePropertyId, UIA_ControlTypePropertyId,
+    UIA_InvokePatternId, UIA_NamePropertyId, UIA_SelectionItemPatternId, UIA_TogglePatternId,
+    UIA_ValuePatternId, UIA_PATTERN_ID, UIA_PROPERTY_ID,
 };
 use windows::Win32::System::Com::CoCreateInstance;
 use windows::Win32::System::Com::CLSCTX_INPROC_SERVER;
+use windows::Win32::System::Ole::{
+    SafeArrayDestroy, SafeArrayGetElement, SafeArrayGetLBound, SafeArrayGetUBound,
+};
 
 use crate::{Error, ErrorCode, Result};
 
+/// State of a toggleable element (checkbox, toggle button)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToggleState {
+    Off,
+    On,
+    Indeterminate,
+}
+
+/// How `Element::click` activated an element
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum ClickMethod {
+    /// InvokePattern (buttons, menu items, links)
+    Invoke,
+    /// TogglePattern, with the state after toggling
+    Toggle { state: ToggleState },
+    /// SelectionItemPattern (list items, tabs, radio buttons)
+    Select,
+    /// Synthesized mouse click at screen coordinates
+    Mouse { x: i32, y: i32 },
+}
+
 /// Windows UI Automation instance
 pub struct Automation {
     inner: IUIAutomation,
@@ -19,6 +54,8 @@ impl Automation {
     /// Create a new UI Automation instance
     pub fn new() -> Result<Self> {
         super::init_com()?;
+        // Bounding rectangles in physical pixels on every monitor
+        super::enable_dpi_awareness();
 
         let automation: IUIAutomation = unsafe {
             CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER)
@@ -34,7 +71,7 @@ impl Automation {
             self.inner.GetRootElement()
                 .map_err(|e| Error::new(ErrorCode::Unknown, format!("Failed to get root: {:?}", e)))?
         };
-        Ok(Element { inner: root })
+        Ok(Element::new(root))
     }
 
     /// Get the focused element
@@ -43,7 +80,7 @@ impl Automation {
             self.inner.GetFocusedElement()
                 .map_err(|e| Error::new(ErrorCode::Unknown, format!("Failed to get focused: {:?}", e)))?
         };
-        Ok(Element { inner: focused })
+        Ok(Element::new(focused))
     }
 
     /// Get element at point
@@ -55,7 +92,62 @@ impl Automation {
             self.inner.ElementFromPoint(point)
                 .map_err(|e| Error::new(ErrorCode::Unknown, format!("Failed to get element at point: {:?}", e)))?
         };
-        Ok(Element { inner: element })
+        Ok(Element::new(element))
+    }
+
+    /// Condition on a string property, exact or case-insensitive substring
+    /// (substring matching needs Windows 10 1809 or later)
+    pub fn string_condition(&self, property: StringProperty, value: &str, contains: bool) -> Result<IUIAutomationCondition> {
+        let flags = if contains {
+            PropertyConditionFlags(
+                PropertyConditionFlags_IgnoreCase.0 | PropertyConditionFlags_MatchSubstring.0,
+            )
+        } else {
+            PropertyConditionFlags_None
+        };
+        let value = VARIANT::from(BSTR::from(value));
+        unsafe {
+            self.inner.CreatePropertyConditionEx(property.id(), &value, flags)
+                .map_err(|e| Error::new(ErrorCode::Unknown, format!("Failed to create condition: {:?}", e)))
+        }
+    }
+
+    /// Condition matching a control type id (see `control_type_id`)
+    pub fn control_type_condition(&self, control_type: i32) -> Result<IUIAutomationCondition> {
+        let value = VARIANT::from(control_type);
+        unsafe {
+            self.inner.CreatePropertyCondition(UIA_ControlTypePropertyId, &value)
+                .map_err(|e| Error::new(ErrorCode::Unknown, format!("Failed to create condition: {:?}", e)))
+        }
+    }
+
+    /// AND of all conditions (true condition when empty)
+    pub fn and_condition(&self, conditions: Vec<IUIAutomationCondition>) -> Result<IUIAutomationCondition> {
+        let err = |e: windows::core::Error| Error::new(ErrorCode::Unknown, format!("Failed to create condition: {:?}", e));
+        unsafe {
+            let mut iter = conditions.into_iter();
+            let Some(mut combined) = iter.next() else {
+                return self.inner.CreateTrueCondition().map_err(err);
+            };
+            for c in iter {
+                combined = self.inner.CreateAndCondition(&combined, &c).map_err(err)?;
+            }
+            Ok(combined)
+        }
+    }
+
+    /// Find all elements in `root`'s subtree (root included) matching `condition`.
+    /// The search runs inside UI Automation rather than walking from Rust.
+    pub fn find_all(&self, root: &Element, condition: &IUIAutomationCondition) -> Result<Vec<Element>> {
+        unsafe {
+            let array = root.inner.FindAll(TreeScope_Subtree, condition)
+                .map_err(|e| Error::new(ErrorCode::Unknown, format!("FindAll failed: {:?}", e)))?;
+            let len = array.Length().unwrap_or(0);
+            Ok((0..len)
+                .filter_map(|i| array.GetElement(i).ok())
+                .map(Element::new)
+                .collect())
+        }
     }
 
     /// Get the tree walker for traversing elements
@@ -68,12 +160,38 @@ impl Automation {
     }
 }
 
+/// String properties usable in native search conditions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StringProperty {
+    Name,
+    AutomationId,
+    ClassName,
+}
+
+impl StringProperty {
+    fn id(self) -> UIA_PROPERTY_ID {
+        match self {
+            Self::Name => UIA_NamePropertyId,
+            Self::AutomationId => UIA_AutomationIdPropertyId,
+            Self::ClassName => UIA_ClassNamePropertyId,
+        }
+    }
+}
+
 /// A UI element
+#[derive(Clone)]
 pub struct Element {
     inner: IUIAutomationElement,
+    /// Read once, so comparing and hashing don't go back to the app
+    runtime_id: Option<Vec<i32>>,
 }
 
 impl Element {
+    fn new(inner: IUIAutomationElement) -> Self {
+        let runtime_id = read_runtime_id(&inner);
+        Self { inner, runtime_id }
+    }
+
     /// Get the element's name
     pub fn name(&self) -> Option<String> {
         unsafe {
@@ -92,48 +210,7 @@ impl Element {
 
     /// Get the control type as a string
     pub fn control_type_name(&self) -> &'static str {
-        match self.control_type() {
-            50000 => "Button",
-            50001 => "Calendar",
-            50002 => "CheckBox",
-            50003 => "ComboBox",
-            50004 => "Edit",
-            50005 => "Hyperlink",
-            50006 => "Image",
-            50007 => "ListItem",
-            50008 => "List",
-            50009 => "Menu",
-            50010 => "MenuBar",
-            50011 => "MenuItem",
-            50012 => "ProgressBar",
-            50013 => "RadioButton",
-            50014 => "ScrollBar",
-            50015 => "Slider",
-            50016 => "Spinner",
-            50017 => "StatusBar",
-            50018 => "Tab",
-            50019 => "TabItem",
-            50020 => "Text",
-            50021 => "ToolBar",
-            50022 => "ToolTip",
-            50023 => "Tree",
-            50024 => "TreeItem",
-            50025 => "Custom",
-            50026 => "Group",
-            50027 => "Thumb",
-            50028 => "DataGrid",
-            50029 => "DataItem",
-            50030 => "Document",
-            50031 => "SplitButton",
-            50032 => "Window",
-            50033 => "Pane",
-            50034 => "Header",
-            50035 => "HeaderItem",
-            50036 => "Table",
-            50037 => "TitleBar",
-            50038 => "Separator",
-            _ => "Unknown",
-        }
+        control_type_name(self.control_type())
     }
 
     /// Get the bounding rectangle
@@ -166,6 +243,19 @@ impl Element {
         }
     }
 
+    /// Get the value from ValuePattern (edit fields, combo boxes)
+    pub fn value(&self) -> Option<String> {
+        let pattern: IUIAutomationValuePattern = self.pattern(UIA_ValuePatternId)?;
+        unsafe { pattern.CurrentValue().ok().map(|s| s.to_string()) }
+    }
+
+    /// Get the help text (tooltip-style description)
+    pub fn help_text(&self) -> Option<String> {
+        unsafe {
+            self.inner.CurrentHelpText().ok().map(|s| s.to_string()).filter(|s| !s.is_empty())
+        }
+    }
+
     /// Check if element is enabled
     pub fn is_enabled(&self) -> bool {
         unsafe {
@@ -197,10 +287,188 @@ impl Element {
         }
     }
 
+    fn pattern<T: Interface>(&self, id: UIA_PATTERN_ID) -> Option<T> {
+        unsafe { self.inner.GetCurrentPatternAs::<T>(id).ok() }
+    }
+
+    /// Invoke the element's default action via InvokePattern
+    pub fn invoke(&self) -> Result<()> {
+        let pattern: IUIAutomationInvokePattern = self
+            .pattern(UIA_InvokePatternId)
+            .ok_or_else(|| Error::action_failed("invoke", "Element does not support InvokePattern"))?;
+        unsafe {
+            pattern
+                .Invoke()
+                .map_err(|e| Error::action_failed("invoke", &format!("{:?}", e)))
+        }
+    }
+
+    /// Current toggle state, if the element supports TogglePattern
+    pub fn toggle_state(&self) -> Option<ToggleState> {
+        let pattern: IUIAutomationTogglePattern = self.pattern(UIA_TogglePatternId)?;
+        let state = unsafe { pattern.CurrentToggleState().ok()? };
+        Some(match state {
+            ToggleState_On => ToggleState::On,
+            ToggleState_Indeterminate => ToggleState::Indeterminate,
+            _ => ToggleState::Off,
+        })
+    }
+
+    /// Toggle the element and return the resulting state
+    pub fn toggle(&self) -> Result<ToggleState> {
+        let pattern: IUIAutomationTogglePattern = self
+            .pattern(UIA_TogglePatternId)
+            .ok_or_else(|| Error::action_failed("toggle", "Element does not support TogglePattern"))?;
+        unsafe {
+            pattern
+                .Toggle()
+                .map_err(|e| Error::action_failed("toggle", &format!("{:?}", e)))?;
+        }
+        self.toggle_state()
+            .ok_or_else(|| Error::action_failed("toggle", "Could not read toggle state"))
+    }
+
+    /// Select the element via SelectionItemPattern
+    pub fn select(&self) -> Result<()> {
+        let pattern: IUIAutomationSelectionItemPattern = self
+            .pattern(UIA_SelectionItemPatternId)
+            .ok_or_else(|| Error::action_failed("select", "Element does not support SelectionItemPattern"))?;
+        unsafe {
+            pattern
+                .Select()
+                .map_err(|e| Error::action_failed("select", &format!("{:?}", e)))
+        }
+    }
+
+    /// Whether the element is selected, if it supports SelectionItemPattern
+    pub fn is_selected(&self) -> Option<bool> {
+        let pattern: IUIAutomationSelectionItemPattern = self.pattern(UIA_SelectionItemPatternId)?;
+        unsafe { pattern.CurrentIsSelected().ok().map(|b| b.as_bool()) }
+    }
+
+    /// Activate the element, preferring UIA patterns over a mouse click.
+    ///
+    /// Patterns work on occluded or offscreen elements and don't depend on
+    /// coordinates; the mouse is only used when none is supported.
+    pub fn click(&self) -> Result<ClickMethod> {
+        if self.invoke().is_ok() {
+            return Ok(ClickMethod::Invoke);
+        }
+        if let Ok(state) = self.toggle() {
+            return Ok(ClickMethod::Toggle { state });
+        }
+        if self.select().is_ok() {
+            return Ok(ClickMethod::Select);
+        }
+
+        let (x, y) = self
+            .clickable_point()
+            .ok_or_else(|| Error::action_failed("click", "Element has no bounds"))?;
+        super::click_at(x, y)?;
+        Ok(ClickMethod::Mouse { x, y })
+    }
+
     /// Get the inner IUIAutomationElement (for advanced usage)
     pub fn raw(&self) -> &IUIAutomationElement {
         &self.inner
     }
+
+    /// The runtime id, unique on the desktop for as long as the element lives
+    pub fn runtime_id(&self) -> Option<&[i32]> {
+        self.runtime_id.as_deref()
+    }
+}
+
+fn read_runtime_id(element: &IUIAutomationElement) -> Option<Vec<i32>> {
+    unsafe {
+        let ids = element.GetRuntimeId().ok()?;
+        let lower = SafeArrayGetLBound(ids, 1).unwrap_or(0);
+        let upper = SafeArrayGetUBound(ids, 1).unwrap_or(-1);
+        let mut id = Vec::new();
+        for i in lower..=upper {
+            let mut part = 0i32;
+            if SafeArrayGetElement(ids, &i, &mut part as *mut i32 as *mut _).is_ok() {
+                id.push(part);
+            }
+        }
+        let _ = SafeArrayDestroy(ids);
+        Some(id)
+    }
+}
+
+/// The same element by runtime id, or the same COM pointer when neither has
+/// one; matches `Hash`, which uses the same key
+impl PartialEq for Element {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.runtime_id, &other.runtime_id) {
+            (Some(a), Some(b)) => a == b,
+            (None, None) => self.inner.as_raw() == other.inner.as_raw(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Element {}
+
+impl std::hash::Hash for Element {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        match &self.runtime_id {
+            Some(id) => id.hash(state),
+            None => self.inner.as_raw().hash(state),
+        }
+    }
+}
+
+/// Control type name for a UIA control type id
+pub fn control_type_name(id: i32) -> &'static str {
+    match id {
+
+        50000 => "Button",
+        50001 => "Calendar",
+        50002 => "CheckBox",
+        50003 => "ComboBox",
+        50004 => "Edit",
+        50005 => "Hyperlink",
+        50006 => "Image",
+        50007 => "ListItem",
+        50008 => "List",
+        50009 => "Menu",
+        50010 => "MenuBar",
+        50011 => "MenuItem",
+        50012 => "ProgressBar",
+        50013 => "RadioButton",
+        50014 => "ScrollBar",
+        50015 => "Slider",
+        50016 => "Spinner",
+        50017 => "StatusBar",
+        50018 => "Tab",
+        50019 => "TabItem",
+        50020 => "Text",
+        50021 => "ToolBar",
+        50022 => "ToolTip",
+        50023 => "Tree",
+        50024 => "TreeItem",
+        50025 => "Custom",
+        50026 => "Group",
+        50027 => "Thumb",
+        50028 => "DataGrid",
+        50029 => "DataItem",
+        50030 => "Document",
+        50031 => "SplitButton",
+        50032 => "Window",
+        50033 => "Pane",
+        50034 => "Header",
+        50035 => "HeaderItem",
+        50036 => "Table",
+        50037 => "TitleBar",
+        50038 => "Separator",
+        _ => "Unknown",
+    }
+}
+
+/// UIA control type id for a control type name (case-insensitive)
+pub fn control_type_id(name: &str) -> Option<i32> {
+    (50000..=50038).find(|&id| control_type_name(id).eq_ignore_ascii_case(name))
 }
 
 /// Tree walker for traversing the UI tree
@@ -214,7 +482,7 @@ impl TreeWalker {
         unsafe {
             self.inner.GetFirstChildElement(&element.inner)
                 .ok()
-                .map(|e| Element { inner: e })
+                .map(Element::new)
         }
     }
 
@@ -223,7 +491,7 @@ impl TreeWalker {
         unsafe {
             self.inner.GetNextSiblingElement(&element.inner)
                 .ok()
-                .map(|e| Element { inner: e })
+                .map(Element::new)
         }
     }
 
@@ -232,7 +500,7 @@ impl TreeWalker {
         unsafe {
             self.inner.GetParentElement(&element.inner)
                 .ok()
-                .map(|e| Element { inner: e })
+                .map(Element::new)
         }
     }
 }
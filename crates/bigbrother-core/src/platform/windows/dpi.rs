@@ -0,0 +1,37 @@
+//! Per-monitor DPI awareness
+//!
+//! A DPI-unaware process gets virtualized coordinates on scaled monitors:
+//! UIA reports physical pixels while SetCursorPos takes scaled ones, so clicks
+//! drift off target. Once the process is per-monitor aware, bounding
+//! rectangles, cursor positions and SendInput all use physical pixels.
+
+use std::sync::Once;
+use windows::Win32::Foundation::POINT;
+use windows::Win32::Graphics::Gdi::{MonitorFromPoint, MONITOR_DEFAULTTONEAREST};
+use windows::Win32::UI::HiDpi::{
+    GetDpiForMonitor, SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+    MDT_EFFECTIVE_DPI,
+};
+
+static DPI_AWARE: Once = Once::new();
+
+/// Declare the process per-monitor DPI aware (idempotent)
+pub fn enable_dpi_awareness() {
+    DPI_AWARE.call_once(|| unsafe {
+        // Fails if the awareness was already set (e.g. by a manifest), which is fine
+        let _ = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+    });
+}
+
+/// Scale factor of the monitor containing a physical point (1.0 = 96 DPI)
+pub fn scale_factor_at(x: i32, y: i32) -> f64 {
+    enable_dpi_awareness();
+    unsafe {
+        let monitor = MonitorFromPoint(POINT { x, y }, MONITOR_DEFAULTTONEAREST);
+        let (mut dpi_x, mut dpi_y) = (0u32, 0u32);
+        match GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y) {
+            Ok(()) if dpi_x > 0 => dpi_x as f64 / 96.0,
+            _ => 1.0,
+        }
+    }
+}
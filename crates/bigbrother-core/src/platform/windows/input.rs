@@ -15,6 +15,8 @@ use crate::{Error, ErrorCode, Result};
 
 /// Move the mouse to absolute coordinates
 pub fn move_mouse(x: i32, y: i32) -> Result<()> {
+    // Coordinates are physical pixels, matching UIA bounds
+    super::enable_dpi_awareness();
     unsafe {
         SetCursorPos(x, y)
             .map_err(|e| Error::new(ErrorCode::ActionFailed, format!("Failed to move mouse: {:?}", e)))?;
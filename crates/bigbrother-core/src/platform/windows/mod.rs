@@ -3,9 +3,12 @@
 //! Uses UI Automation API for accessibility and Win32 for input.
 
 mod accessibility;
+mod dpi;
 mod input;
+pub mod native;
 
 pub use accessibility::*;
+pub use dpi::*;
 pub use input::*;
 
 use crate::{Error, ErrorCode, Result};
@@ -29,6 +32,16 @@ pub fn ensure_accessibility() -> Result<()> {
     Ok(())
 }
 
+/// Check if the process may capture the screen (no permission on Windows)
+pub fn has_screen_recording() -> bool {
+    true
+}
+
+/// Ensure screen capture is allowed
+pub fn ensure_screen_recording() -> Result<()> {
+    Ok(())
+}
+
 /// Initialize COM for the current thread
 pub fn init_com() -> Result<()> {
     use windows::Win32::System::Com::{CoInitializeEx, COINIT_MULTITHREADED};
@@ -0,0 +1,324 @@
+//! Backend for the cross-platform `Desktop`/`UIElement`/`Locator` API
+//!
+//! Maps UI Automation elements onto the shared element model:
+//! role = control type, name = UIA Name, value = ValuePattern (or toggle
+//! state), description = help text.
+
+use super::accessibility::{
+    control_type_id, find_window, get_windows, Automation, Element, StringProperty, ToggleState,
+    TreeWalker,
+};
+use super::input;
+use crate::element::{Bounds, ElementInfo};
+use crate::script::ScriptLanguage;
+use crate::selector::{Attribute, MatchOp, Selector};
+use crate::{Error, Result};
+use std::cell::RefCell;
+use std::process::Command;
+
+use windows::Win32::Foundation::{BOOL, HWND, LPARAM};
+use windows::Win32::UI::WindowsAndMessaging::{
+    EnumWindows, GetWindowThreadProcessId, IsWindowVisible, SetForegroundWindow, ShowWindow,
+    SW_RESTORE,
+};
+
+/// Common browser process names
+pub const BROWSERS: &[&str] = &["chrome", "firefox", "msedge", "brave", "opera"];
+
+thread_local! {
+    // UIA objects are apartment-bound, so each thread keeps its own walker
+    static WALKER: RefCell<Option<TreeWalker>> = const { RefCell::new(None) };
+}
+
+fn with_walker<T>(f: impl FnOnce(&TreeWalker) -> T) -> Option<T> {
+    WALKER.with(|cell| {
+        let mut walker = cell.borrow_mut();
+        if walker.is_none() {
+            *walker = Automation::new().and_then(|a| a.tree_walker()).ok();
+        }
+        walker.as_ref().map(f)
+    })
+}
+
+/// A UI Automation element
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct NativeElement(Element);
+
+impl NativeElement {
+    pub fn new(element: Element) -> Self {
+        Self(element)
+    }
+
+    /// The underlying UIA element
+    pub fn element(&self) -> &Element {
+        &self.0
+    }
+
+    pub fn role(&self) -> Option<String> {
+        Some(self.0.control_type_name().to_string())
+    }
+
+    pub fn name(&self) -> Option<String> {
+        self.0.name().filter(|n| !n.is_empty())
+    }
+
+    pub fn title(&self) -> Option<String> {
+        // Only windows have a title distinct from their content
+        if self.0.control_type() == 50032 {
+            self.name()
+        } else {
+            None
+        }
+    }
+
+    pub fn value(&self) -> Option<String> {
+        self.0.value().or_else(|| {
+            self.0.toggle_state().map(|state| {
+                match state {
+                    ToggleState::On => "on",
+                    ToggleState::Off => "off",
+                    ToggleState::Indeterminate => "indeterminate",
+                }
+                .to_string()
+            })
+        })
+    }
+
+    pub fn description(&self) -> Option<String> {
+        self.0.help_text()
+    }
+
+    pub fn automation_id(&self) -> Option<String> {
+        self.0.automation_id().filter(|id| !id.is_empty())
+    }
+
+    pub fn class_name(&self) -> Option<String> {
+        self.0.class_name().filter(|c| !c.is_empty())
+    }
+
+    pub fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        self.0
+            .bounds()
+            .map(|(x, y, w, h)| (x as f64, y as f64, w as f64, h as f64))
+    }
+
+    pub fn info(&self) -> ElementInfo {
+        ElementInfo {
+            index: None,
+            role: self.role().unwrap_or_else(|| "Unknown".to_string()),
+            name: self.name(),
+            title: self.title(),
+            value: self.value(),
+            description: self.description(),
+            automation_id: self.automation_id(),
+            class_name: self.class_name(),
+            bounds: self.bounds().map(|(x, y, width, height)| Bounds { x, y, width, height }),
+        }
+    }
+
+    pub fn children(&self) -> Vec<NativeElement> {
+        with_walker(|walker| {
+            let mut children = Vec::new();
+            let mut child = walker.first_child(&self.0);
+            while let Some(c) = child {
+                child = walker.next_sibling(&c);
+                children.push(NativeElement(c));
+            }
+            children
+        })
+        .unwrap_or_default()
+    }
+
+    /// Perform the element's default action (UIA patterns, then mouse)
+    pub fn press(&self) -> Result<()> {
+        self.0.click().map(|_| ())
+    }
+}
+
+/// Candidate matches for `selector` under `root`, searched by UI Automation
+/// itself with FindAll instead of walking the tree element by element.
+///
+/// Role, name, automation id and class conditions become UIA property
+/// conditions; the rest are left to the caller, which re-checks every
+/// candidate. FindAll knows nothing of depth, so each candidate comes with
+/// its path from `root` down to it, for the caller to order and prune by;
+/// those more than `max_depth` below `root` are dropped.
+pub fn find_candidates(
+    root: &NativeElement,
+    selector: &Selector,
+    max_depth: usize,
+) -> Result<Option<Vec<Vec<NativeElement>>>> {
+    let automation = Automation::new()?;
+    let mut conditions = Vec::new();
+    for c in &selector.conditions {
+        let contains = c.op == MatchOp::Contains;
+        let property = match c.attr {
+            Attribute::Role => {
+                if let Some(id) = control_type_id(&c.value).filter(|_| !contains) {
+                    conditions.push(automation.control_type_condition(id)?);
+                }
+                continue;
+            }
+            Attribute::Name => StringProperty::Name,
+            Attribute::AutomationId => StringProperty::AutomationId,
+            Attribute::Class => StringProperty::ClassName,
+            _ => continue,
+        };
+        conditions.push(automation.string_condition(property, &c.value, contains)?);
+    }
+    let condition = automation.and_condition(conditions)?;
+    let found = automation.find_all(&root.0, &condition)?;
+    let paths: Option<Vec<_>> = with_walker(|walker| {
+        found
+            .into_iter()
+            .filter_map(|element| path_from(walker, &root.0, element, max_depth))
+            .collect()
+    });
+    Ok(Some(paths.unwrap_or_default()))
+}
+
+/// `element` and its ancestors up to `root`, root first; None when it's
+/// more than `max_depth` below it, or not below it at all
+fn path_from(
+    walker: &TreeWalker,
+    root: &Element,
+    element: Element,
+    max_depth: usize,
+) -> Option<Vec<NativeElement>> {
+    let mut path = vec![element];
+    while path.last()? != root {
+        if path.len() > max_depth {
+            return None;
+        }
+        let parent = walker.parent(path.last()?)?;
+        path.push(parent);
+    }
+    Some(path.into_iter().rev().map(NativeElement).collect())
+}
+
+/// Desktop root element
+pub fn system_root() -> Result<NativeElement> {
+    Ok(NativeElement(Automation::new()?.root()?))
+}
+
+/// Element with keyboard focus
+pub fn focused_element() -> Result<NativeElement> {
+    Ok(NativeElement(Automation::new()?.focused()?))
+}
+
+/// Topmost element at a screen point (physical pixels)
+pub fn element_at(x: f64, y: f64) -> Result<NativeElement> {
+    Ok(NativeElement(Automation::new()?.element_at(x.round() as i32, y.round() as i32)?))
+}
+
+/// Top-level window of an application, by window title or process name
+pub fn app_root(app: &str) -> Result<NativeElement> {
+    find_window(app)?
+        .map(NativeElement)
+        .ok_or_else(|| Error::app_not_running(app))
+}
+
+/// Running applications with a top-level window: (name, pid)
+pub fn running_apps() -> Result<Vec<(String, i32)>> {
+    Ok(get_windows()?
+        .iter()
+        .filter_map(|w| Some((w.name().filter(|n| !n.is_empty())?, w.process_id())))
+        .collect())
+}
+
+pub fn find_app_pid(app: &str) -> Result<i32> {
+    find_window(app)?
+        .map(|w| w.process_id())
+        .ok_or_else(|| Error::app_not_running(app))
+}
+
+/// Find a running browser: (process name, pid)
+pub fn find_browser() -> Result<(String, i32)> {
+    for browser in BROWSERS {
+        if let Ok(Some(w)) = find_window(browser) {
+            return Ok((browser.to_string(), w.process_id()));
+        }
+    }
+    Err(Error::app_not_running("browser"))
+}
+
+pub fn open_url(url: &str) -> Result<()> {
+    Command::new("cmd")
+        .args(["/c", "start", url])
+        .spawn()
+        .map_err(|e| Error::action_failed("open_url", &e.to_string()))?;
+    Ok(())
+}
+
+/// Bring an application's main window to the foreground
+pub fn activate_app(app: &str) -> Result<()> {
+    let window = find_window(app)?.ok_or_else(|| Error::app_not_running(app))?;
+    let hwnd = find_visible_window(window.process_id() as u32)
+        .ok_or_else(|| Error::action_failed("activate", "No visible window for process"))?;
+    unsafe {
+        let _ = ShowWindow(hwnd, SW_RESTORE);
+        let _ = SetForegroundWindow(hwnd);
+    }
+    Ok(())
+}
+
+/// AppleScript is macOS only
+pub fn run_script(_script: &str, _language: ScriptLanguage) -> Result<serde_json::Value> {
+    Err(crate::script::unsupported())
+}
+
+/// So are Shortcuts
+pub fn run_shortcut(_name: &str, _input: Option<&serde_json::Value>) -> Result<serde_json::Value> {
+    Err(crate::script::unsupported())
+}
+
+fn find_visible_window(pid: u32) -> Option<HWND> {
+    struct Search {
+        pid: u32,
+        found: Option<HWND>,
+    }
+
+    unsafe extern "system" fn callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let search = &mut *(lparam.0 as *mut Search);
+        let mut proc_pid: u32 = 0;
+        GetWindowThreadProcessId(hwnd, Some(&mut proc_pid));
+        if proc_pid == search.pid && IsWindowVisible(hwnd).as_bool() {
+            search.found = Some(hwnd);
+            return BOOL(0); // stop enumerating
+        }
+        BOOL(1)
+    }
+
+    let mut search = Search { pid, found: None };
+    unsafe {
+        let _ = EnumWindows(Some(callback), LPARAM(&mut search as *mut Search as isize));
+    }
+    search.found
+}
+
+pub fn scroll_up(pages: u32) -> Result<()> {
+    input::scroll(pages as i32)
+}
+
+pub fn scroll_down(pages: u32) -> Result<()> {
+    input::scroll(-(pages as i32))
+}
+
+/// Press a virtual key
+pub fn press_key(key_code: u8) -> Result<()> {
+    input::press_key(key_code as u16)
+}
+
+pub fn type_text(text: &str) -> Result<()> {
+    input::type_text(text)
+}
+
+/// Ctrl+key, the Windows counterpart of Cmd+key
+pub fn primary_shortcut(key: &str) -> Result<()> {
+    let c = key
+        .chars()
+        .next()
+        .filter(|c| key.len() == 1 && c.is_ascii_alphanumeric())
+        .ok_or_else(|| Error::action_failed("shortcut", &format!("Unsupported key: {}", key)))?;
+    input::shortcut(c.to_ascii_uppercase() as u16, &[input::vk::CONTROL])
+}
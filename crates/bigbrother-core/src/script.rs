@@ -0,0 +1,392 @@
+//! AppleScript, JXA and Shortcuts
+//!
+//! For what the accessibility tree can't reach: Mail rules, Numbers cells,
+//! anything an app exposes to scripting, and the Shortcuts users already
+//! have. Script results come back as JSON, with
+//! AppleScript lists as arrays, records as objects and `missing value` as
+//! null; dates, object references and other values as their text. Errors
+//! carry the AppleScript error number and map onto [`ErrorCode`]s: not
+//! allowed to control the app is `PermissionDenied`, an app that isn't
+//! running `AppNotRunning`, a missing object `ElementNotFound`.
+//!
+//! Shortcuts run through the `shortcuts` command line tool, their input
+//! passed as a file and their output read back as JSON when it is, as text
+//! otherwise; output that isn't text (an image, a PDF) stays in a file and
+//! comes back as `{"path", "bytes"}`.
+
+use crate::error::{Error, ErrorCode};
+use serde_json::{Map, Value};
+
+/// What a script is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptLanguage {
+    AppleScript,
+    /// JavaScript for Automation; the last expression is the result
+    JavaScript,
+}
+
+/// Run a script and return its result
+#[cfg(target_os = "macos")]
+pub fn run(script: &str, language: ScriptLanguage) -> crate::Result<Value> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut command = Command::new("osascript");
+    let source = match language {
+        // Results in source form, to tell lists and strings apart
+        ScriptLanguage::AppleScript => {
+            command.args(["-l", "AppleScript", "-s", "s"]);
+            script.to_string()
+        }
+        ScriptLanguage::JavaScript => {
+            command.args(["-l", "JavaScript"]);
+            jxa(script)
+        }
+    };
+    let failed = |e: std::io::Error| Error::action_failed("osascript", &e.to_string());
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(failed)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(source.as_bytes()).map_err(failed)?;
+    }
+    let output = child.wait_with_output().map_err(failed)?;
+    if !output.status.success() {
+        return Err(script_error(&String::from_utf8_lossy(&output.stderr)));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stdout = stdout.trim_end_matches('\n');
+    Ok(match language {
+        ScriptLanguage::AppleScript => parse_applescript(stdout),
+        ScriptLanguage::JavaScript => serde_json::from_str(stdout)
+            .unwrap_or_else(|_| Value::String(stdout.to_string())),
+    })
+}
+
+/// The script evaluated and its result passed back through JSON
+#[cfg(target_os = "macos")]
+fn jxa(script: &str) -> String {
+    let literal = serde_json::to_string(script).unwrap_or_default();
+    format!(
+        "(function () {{ var result = eval({}); \
+         var json = result === undefined ? undefined : JSON.stringify(result); \
+         return json === undefined ? 'null' : json; }})()",
+        literal
+    )
+}
+
+/// Run the shortcut `name` with `input`, a string passed as text and
+/// anything else as JSON
+#[cfg(target_os = "macos")]
+pub fn shortcut(name: &str, input: Option<&Value>) -> crate::Result<Value> {
+    use std::process::Command;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+    let stem = format!("bigbrother-shortcut-{}-{}", std::process::id(), nanos);
+    let output_path = std::env::temp_dir().join(format!("{}-output", stem));
+    let mut command = Command::new("shortcuts");
+    command.args(["run", name, "--output-path"]).arg(&output_path);
+    let input_path = match input {
+        Some(input) => {
+            let (extension, text) = match input {
+                Value::String(text) => ("txt", text.clone()),
+                other => ("json", other.to_string()),
+            };
+            let path = std::env::temp_dir().join(format!("{}-input.{}", stem, extension));
+            std::fs::write(&path, text)?;
+            command.arg("--input-path").arg(&path);
+            Some(path)
+        }
+        None => None,
+    };
+    let output = command.output();
+    if let Some(path) = input_path {
+        let _ = std::fs::remove_file(path);
+    }
+    let output = output.map_err(|e| Error::action_failed("shortcuts", &e.to_string()))?;
+    if !output.status.success() {
+        let _ = std::fs::remove_file(&output_path);
+        return Err(shortcut_error(name, &String::from_utf8_lossy(&output.stderr)));
+    }
+    // Nothing written: the shortcut has no output
+    let Ok(bytes) = std::fs::read(&output_path) else {
+        return Ok(Value::Null);
+    };
+    match shortcut_output(&bytes) {
+        Some(value) => {
+            let _ = std::fs::remove_file(&output_path);
+            Ok(value)
+        }
+        None => Ok(serde_json::json!({"path": output_path, "bytes": bytes.len()})),
+    }
+}
+
+/// A shortcut's output as JSON when it is, as text otherwise; None when it
+/// isn't text
+pub fn shortcut_output(bytes: &[u8]) -> Option<Value> {
+    let text = std::str::from_utf8(bytes).ok()?.trim_end_matches('\n');
+    if text.trim().is_empty() {
+        return Some(Value::Null);
+    }
+    Some(serde_json::from_str(text).unwrap_or_else(|_| Value::String(text.to_string())))
+}
+
+/// An error from what `shortcuts run` printed
+pub fn shortcut_error(name: &str, stderr: &str) -> Error {
+    let message = stderr.trim().trim_start_matches("Error: ");
+    let lower = message.to_lowercase().replace('’', "'");
+    if lower.contains("couldn't find") || lower.contains("not found") {
+        let suggestion = "See the shortcuts there are with `shortcuts list`".to_string();
+        return Error::new(ErrorCode::ElementNotFound, format!("No shortcut named '{}'", name))
+            .with_suggestions(vec![suggestion]);
+    }
+    Error::action_failed(&format!("Shortcut '{}'", name), message)
+}
+
+/// Reported where scripts and shortcuts can't run
+pub fn unsupported() -> Error {
+    Error::new(ErrorCode::NotImplemented, "AppleScript, JXA and Shortcuts need macOS")
+}
+
+/// A value as `osascript -s s` prints it, as JSON
+pub fn parse_applescript(text: &str) -> Value {
+    let text = text.trim();
+    if text.is_empty() {
+        return Value::Null;
+    }
+    let mut parser = Parser { chars: text.chars().collect(), at: 0 };
+    let value = parser.value();
+    parser.skip_spaces();
+    if parser.at < parser.chars.len() {
+        // Not something we know how to read; the text beats a wrong value
+        return Value::String(text.to_string());
+    }
+    value
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.at += 1;
+        }
+    }
+
+    fn value(&mut self) -> Value {
+        self.skip_spaces();
+        match self.peek() {
+            Some('{') => self.list_or_record(),
+            Some('"') => Value::String(self.string()),
+            _ => self.bare(),
+        }
+    }
+
+    fn string(&mut self) -> String {
+        let mut out = String::new();
+        self.at += 1;
+        while let Some(c) = self.peek() {
+            self.at += 1;
+            match c {
+                '"' => break,
+                '\\' => {
+                    let escaped = self.peek().unwrap_or('\\');
+                    self.at += 1;
+                    out.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        other => other,
+                    });
+                }
+                c => out.push(c),
+            }
+        }
+        out
+    }
+
+    fn list_or_record(&mut self) -> Value {
+        self.at += 1;
+        let mut items = Vec::new();
+        let mut fields = Map::new();
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some('}') | None => {
+                    self.at += 1;
+                    break;
+                }
+                Some(',') => self.at += 1,
+                _ => match self.key() {
+                    Some(key) => {
+                        let value = self.value();
+                        fields.insert(key, value);
+                    }
+                    None => items.push(self.value()),
+                },
+            }
+        }
+        if fields.is_empty() {
+            Value::Array(items)
+        } else {
+            Value::Object(fields)
+        }
+    }
+
+    /// A record label and its colon, or nothing with the position unchanged
+    fn key(&mut self) -> Option<String> {
+        let start = self.at;
+        let key = if self.peek() == Some('|') {
+            self.at += 1;
+            let key = self.take_while(|c| c != '|');
+            self.at += 1;
+            key
+        } else {
+            self.take_while(|c| c.is_alphanumeric() || c == '_' || c == ' ')
+        };
+        if key.trim().is_empty() || self.peek() != Some(':') {
+            self.at = start;
+            return None;
+        }
+        self.at += 1;
+        Some(key.trim().to_string())
+    }
+
+    fn take_while(&mut self, keep: impl Fn(char) -> bool) -> String {
+        let start = self.at;
+        while self.peek().is_some_and(&keep) {
+            self.at += 1;
+        }
+        self.chars[start..self.at].iter().collect()
+    }
+
+    /// Numbers, booleans and `missing value`; anything else (dates, object
+    /// references) up to the next comma or brace as text
+    fn bare(&mut self) -> Value {
+        let start = self.at;
+        let (mut depth, mut quoted) = (0, false);
+        while let Some(c) = self.peek() {
+            match c {
+                '"' => quoted = !quoted,
+                '\\' if quoted => self.at += 1,
+                '{' | '«' if !quoted => depth += 1,
+                '}' | '»' if !quoted && depth > 0 => depth -= 1,
+                ',' | '}' if !quoted && depth == 0 => break,
+                _ => {}
+            }
+            self.at += 1;
+        }
+        let text: String = self.chars[start..self.at.min(self.chars.len())].iter().collect();
+        let text = text.trim();
+        match text {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            "missing value" | "null" => Value::Null,
+            _ => match (text.parse::<i64>(), text.parse::<f64>()) {
+                (Ok(n), _) => n.into(),
+                (_, Ok(n)) => serde_json::Number::from_f64(n).map_or(Value::Null, Value::Number),
+                _ => Value::String(text.to_string()),
+            },
+        }
+    }
+}
+
+/// An error from what `osascript` printed, e.g.
+/// `0:5: execution error: Mail got an error: Can’t get account "x". (-1728)`
+pub fn script_error(stderr: &str) -> Error {
+    let line = stderr.trim();
+    let (kind, message) = match line.find("syntax error: ") {
+        Some(at) => ("syntax", &line[at + "syntax error: ".len()..]),
+        None => match line.find("execution error: ") {
+            Some(at) => ("execution", &line[at + "execution error: ".len()..]),
+            None => ("execution", line),
+        },
+    };
+    let number = message
+        .rsplit_once(" (")
+        .and_then(|(text, number)| Some((text, number.strip_suffix(')')?.parse::<i32>().ok()?)));
+    let (message, number) = match number {
+        Some((text, number)) => (text, Some(number)),
+        None => (message, None),
+    };
+    let code = match number {
+        Some(-1743) => ErrorCode::PermissionDenied,
+        Some(-600) | Some(-609) => ErrorCode::AppNotRunning,
+        Some(-1712) => ErrorCode::Timeout,
+        Some(-1728) | Some(-1719) => ErrorCode::ElementNotFound,
+        _ => ErrorCode::ActionFailed,
+    };
+    let mut error = Error::new(code, format!("AppleScript {} error: {}", kind, message))
+        .with_context(serde_json::json!({"kind": kind, "number": number}));
+    if code == ErrorCode::PermissionDenied {
+        error = error.with_suggestions(vec![
+            "Allow controlling the app in System Settings > Privacy & Security > Automation"
+                .to_string(),
+        ]);
+    }
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_results_and_errors() {
+        let text = r#"{{name:"Inbox", unread:3, |last sync|:missing value}, "say \"hi\"", 1.5,
+            true, date "Friday, 16 October 2026 at 09:00:00"}"#;
+        assert_eq!(
+            parse_applescript(text),
+            json!([
+                {"name": "Inbox", "unread": 3, "last sync": null},
+                "say \"hi\"",
+                1.5,
+                true,
+                "date \"Friday, 16 October 2026 at 09:00:00\"",
+            ])
+        );
+        let reference = "document 1 of application \"TextEdit\"";
+        assert_eq!(parse_applescript(reference), json!(reference));
+        assert_eq!(parse_applescript(""), Value::Null);
+
+        let error = script_error(
+            "0:42: execution error: Mail got an error: Can’t get account \"x\". (-1728)\n",
+        );
+        assert_eq!(error.code, ErrorCode::ElementNotFound);
+        assert_eq!(
+            error.message,
+            "AppleScript execution error: Mail got an error: Can’t get account \"x\"."
+        );
+        assert_eq!(error.context, Some(json!({"kind": "execution", "number": -1728})));
+        let denied = "execution error: Not authorized to send Apple events to Mail. (-1743)";
+        assert_eq!(script_error(denied).code, ErrorCode::PermissionDenied);
+        let syntax = "1:4: syntax error: Expected end of line. (-2741)";
+        assert_eq!(script_error(syntax).code, ErrorCode::ActionFailed);
+    }
+
+    #[test]
+    fn reads_shortcut_output() {
+        assert_eq!(shortcut_output(b"{\"width\": 800}\n"), Some(json!({"width": 800})));
+        assert_eq!(shortcut_output(b"Resized\n"), Some(json!("Resized")));
+        assert_eq!(shortcut_output(b""), Some(Value::Null));
+        assert_eq!(shortcut_output(&[0x89, b'P', b'N', b'G', 0xff]), None);
+
+        let missing = shortcut_error("Resize", "Error: Couldn’t find shortcut “Resize”\n");
+        assert_eq!(missing.code, ErrorCode::ElementNotFound);
+        let failed = shortcut_error("Resize", "Error: The operation couldn’t be completed.");
+        let reason = "The operation couldn’t be completed.";
+        assert_eq!(failed.message, format!("Shortcut 'Resize' failed: {}", reason));
+    }
+}
@@ -0,0 +1,108 @@
+//! Walking element trees
+//!
+//! [`Walk`] goes through a tree from a work queue instead of recursing, so
+//! the deepest trees (some Electron apps nest thousands of levels) can't
+//! overflow the stack, and visits each element once, so a tree that loops
+//! back on itself still ends. Trees, scrapes and locators all walk with it.
+//! Callers ask for children themselves, they usually need them anyway, and
+//! decide whether to go further down.
+
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+
+/// Elements still to visit, each with its depth and what the caller keeps
+/// for it
+pub(crate) struct Walk<E, T = ()> {
+    queue: VecDeque<(E, usize, T)>,
+    seen: HashSet<E>,
+    breadth_first: bool,
+    /// Of the element returned last
+    depth: usize,
+}
+
+impl<E: Clone + Eq + Hash, T> Walk<E, T> {
+    /// Each element before its children, a subtree finished before the next
+    /// sibling's, as recursing would
+    pub fn depth_first(root: E, data: T) -> Self {
+        Self::new(root, data, false)
+    }
+
+    /// Level by level
+    pub fn breadth_first(root: E, data: T) -> Self {
+        Self::new(root, data, true)
+    }
+
+    fn new(root: E, data: T, breadth_first: bool) -> Self {
+        Self {
+            queue: VecDeque::from([(root, 0, data)]),
+            seen: HashSet::new(),
+            breadth_first,
+            depth: 0,
+        }
+    }
+
+    /// The next element not visited yet, with its depth and data
+    pub fn next(&mut self) -> Option<(E, usize, T)> {
+        while let Some((element, depth, data)) = self.queue.pop_front() {
+            if self.seen.insert(element.clone()) {
+                self.depth = depth;
+                return Some((element, depth, data));
+            }
+        }
+        None
+    }
+
+    /// Visit the children of the element returned last, `data` making each
+    /// one's from its position
+    pub fn descend(&mut self, children: impl IntoIterator<Item = E>, data: impl Fn(usize) -> T) {
+        let depth = self.depth + 1;
+        let children = children.into_iter().enumerate().map(|(i, c)| (c, depth, data(i)));
+        if self.breadth_first {
+            self.queue.extend(children);
+        } else {
+            let children: Vec<_> = children.collect();
+            for child in children.into_iter().rev() {
+                self.queue.push_front(child);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 0 > [1 > [3, 0], 2 > [10 > [11 > ... 100000]]], 0 looping back to
+    /// the root
+    fn children(n: u32) -> Vec<u32> {
+        match n {
+            0 => vec![1, 2],
+            1 => vec![3, 0],
+            2 => vec![10],
+            10..100_000 => vec![n + 1],
+            _ => vec![],
+        }
+    }
+
+    fn walk(mut walk: Walk<u32>, max: usize) -> Vec<(u32, usize)> {
+        let mut out = Vec::new();
+        while let Some((n, depth, ())) = walk.next() {
+            out.push((n, depth));
+            walk.descend(children(n), |_| ());
+            if out.len() == max {
+                break;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn visits_each_element_once() {
+        let order = [(0, 0), (1, 1), (3, 2), (2, 1), (10, 2), (11, 3)];
+        assert_eq!(walk(Walk::depth_first(0, ()), 6), order);
+        let order = [(0, 0), (1, 1), (2, 1), (3, 2), (10, 2), (11, 3)];
+        assert_eq!(walk(Walk::breadth_first(0, ()), 6), order);
+        // Deeper than recursion would get on a thread's stack
+        assert_eq!(walk(Walk::depth_first(0, ()), usize::MAX).len(), 4 + 99_991);
+    }
+}
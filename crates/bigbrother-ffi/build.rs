@@ -0,0 +1,16 @@
+//! Writes `bigbrother.h` from the `extern "C"` functions into `OUT_DIR`; a
+//! test checks `include/bigbrother.h` against it
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    let dir = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let out = std::path::PathBuf::from(std::env::var("OUT_DIR").unwrap());
+    let config = cbindgen::Config::from_file(dir.join("cbindgen.toml")).unwrap();
+    cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(dir.join("src/lib.rs"))
+        .generate()
+        .expect("Unable to generate the C header")
+        .write_to_file(out.join("bigbrother.h"));
+}
@@ -0,0 +1,85 @@
+/* Generated by cbindgen from crates/bigbrother-ffi, do not edit */
+
+#ifndef BIGBROTHER_H
+#define BIGBROTHER_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Bumped when functions are added
+ */
+#define BB_ABI_VERSION 2
+
+/**
+ * A desktop connection, made on first use, that keeps the last tree for
+ * clicks by index. Not thread-safe: one call at a time per session.
+ */
+typedef struct BbSession BbSession;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * A new session; free it with [`bb_session_free`]
+ */
+struct BbSession *bb_session_new(void);
+
+/**
+ * Free a session. Null is ignored.
+ *
+ * # Safety
+ *
+ * `session` must come from [`bb_session_new`] and not be used afterwards.
+ */
+void bb_session_free(struct BbSession *session);
+
+/**
+ * Let `run` batches in this session have `exec` and `applescript` steps,
+ * which run shell commands and scripts; they're refused by default. Null is ignored. Since ABI 2.
+ *
+ * # Safety
+ *
+ * `session` must be a live session or null.
+ */
+void bb_session_allow_exec(struct BbSession *session, bool allow);
+
+/**
+ * Run `method` with `params`, a JSON object (or null for none). Returns the
+ * JSON envelope, to free with [`bb_string_free`]; never null.
+ *
+ * # Safety
+ *
+ * `session` must be a live session, `method` and `params` null or
+ * NUL-terminated strings.
+ */
+char *bb_call(struct BbSession *session, const char *method, const char *params);
+
+/**
+ * Free a string returned by this library. Null is ignored.
+ *
+ * # Safety
+ *
+ * `string` must come from this library and not be used afterwards.
+ */
+void bb_string_free(char *string);
+
+/**
+ * The library version, e.g. "0.1.0"; static, not to be freed
+ */
+const char *bb_version(void);
+
+/**
+ * [`BB_ABI_VERSION`] of the library actually loaded
+ */
+uint32_t bb_abi_version(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* BIGBROTHER_H */
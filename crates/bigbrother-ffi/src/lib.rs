@@ -0,0 +1,205 @@
+//! C API for BigBrother
+//!
+//! A flat `extern "C"` surface so Swift, C#, Go and other hosts can link
+//! the automation engine. Parameters go in as a JSON object and results
+//! come back as the same `{success, data, error}` envelope as `bb --json`,
+//! with the operations of `bb serve` ([`bigbrother::ops`]):
+//!
+//! ```c
+//! #include "bigbrother.h"
+//!
+//! BbSession *session = bb_session_new();
+//! char *found = bb_call(session, "find", "{\"selector\": \"role:Button\"}");
+//! puts(found);
+//! bb_string_free(found);
+//! bb_session_free(session);
+//! ```
+//!
+//! The header is `include/bigbrother.h`, generated by cbindgen. After
+//! changing the functions, update it with
+//! `UPDATE_HEADER=1 cargo test -p bigbrother-ffi header`; the test fails
+//! while it's out of date.
+//! Functions are only ever added: [`BB_ABI_VERSION`] goes up when they
+//! are, and existing ones keep their signatures.
+
+use bigbrother::error::{Error, ErrorCode};
+use bigbrother::ops;
+use bigbrother::Result;
+use serde_json::{json, Value};
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// Bumped when functions are added
+pub const BB_ABI_VERSION: u32 = 2;
+
+/// A desktop connection, made on first use, that keeps the last tree for
+/// clicks by index. Not thread-safe: one call at a time per session.
+pub struct BbSession {
+    ops: ops::Session,
+}
+
+/// A new session; free it with [`bb_session_free`]
+#[no_mangle]
+pub extern "C" fn bb_session_new() -> *mut BbSession {
+    Box::into_raw(Box::new(BbSession { ops: ops::Session::new() }))
+}
+
+/// Free a session. Null is ignored.
+///
+/// # Safety
+///
+/// `session` must come from [`bb_session_new`] and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn bb_session_free(session: *mut BbSession) {
+    if !session.is_null() {
+        drop(Box::from_raw(session));
+    }
+}
+
+/// Let `run` batches in this session have `exec` and `applescript` steps,
+/// which run shell commands and scripts; they're refused by default. Null is ignored. Since ABI 2.
+///
+/// # Safety
+///
+/// `session` must be a live session or null.
+#[no_mangle]
+pub unsafe extern "C" fn bb_session_allow_exec(session: *mut BbSession, allow: bool) {
+    if let Some(session) = session.as_mut() {
+        session.ops = std::mem::take(&mut session.ops).allow_exec(allow);
+    }
+}
+
+/// Run `method` with `params`, a JSON object (or null for none). Returns the
+/// JSON envelope, to free with [`bb_string_free`]; never null.
+///
+/// # Safety
+///
+/// `session` must be a live session, `method` and `params` null or
+/// NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn bb_call(
+    session: *mut BbSession,
+    method: *const c_char,
+    params: *const c_char,
+) -> *mut c_char {
+    let output = match catch_unwind(AssertUnwindSafe(|| call(session, method, params))) {
+        Ok(Ok(data)) => json!({"success": true, "data": data}),
+        Ok(Err(e)) => json!({"success": false, "error": e}),
+        Err(_) => {
+            let e = Error::new(ErrorCode::Unknown, format!("Panicked running {}", text(method)));
+            json!({"success": false, "error": e})
+        }
+    };
+    // JSON escapes control characters, so there's no NUL to trip on
+    CString::new(output.to_string()).unwrap().into_raw()
+}
+
+/// Free a string returned by this library. Null is ignored.
+///
+/// # Safety
+///
+/// `string` must come from this library and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn bb_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// The library version, e.g. "0.1.0"; static, not to be freed
+#[no_mangle]
+pub extern "C" fn bb_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast()
+}
+
+/// [`BB_ABI_VERSION`] of the library actually loaded
+#[no_mangle]
+pub extern "C" fn bb_abi_version() -> u32 {
+    BB_ABI_VERSION
+}
+
+/// A C string argument, lossily, or "" for null
+unsafe fn text<'a>(string: *const c_char) -> std::borrow::Cow<'a, str> {
+    if string.is_null() {
+        return "".into();
+    }
+    CStr::from_ptr(string).to_string_lossy()
+}
+
+unsafe fn call(
+    session: *mut BbSession,
+    method: *const c_char,
+    params: *const c_char,
+) -> Result<Value> {
+    let Some(session) = session.as_mut() else {
+        return Err(invalid("No session, create one with bb_session_new"));
+    };
+    let method = text(method);
+    let params = match text(params).trim() {
+        "" => json!({}),
+        params => serde_json::from_str(params)
+            .map_err(|e| invalid(format!("Invalid JSON parameters: {}", e)))?,
+    };
+    session.ops.call(&method, &params)
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::new(ErrorCode::Unknown, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call_json(session: *mut BbSession, method: &str, params: &str) -> Value {
+        let method = CString::new(method).unwrap();
+        let params = CString::new(params).unwrap();
+        unsafe {
+            let output = bb_call(session, method.as_ptr(), params.as_ptr());
+            let value = serde_json::from_str(CStr::from_ptr(output).to_str().unwrap()).unwrap();
+            bb_string_free(output);
+            value
+        }
+    }
+
+    #[test]
+    fn answers_with_envelopes() {
+        let session = bb_session_new();
+        let output = call_json(session, "fly", "{}");
+        assert_eq!(output["success"], false);
+        assert_eq!(output["error"]["code"], "UNKNOWN");
+        assert!(output["error"]["suggestions"].as_array().unwrap().len() == ops::OPERATIONS.len());
+
+        let output = call_json(session, "find", "{not json");
+        assert!(output["error"]["message"].as_str().unwrap().starts_with("Invalid JSON"));
+        let output = call_json(session, "tree", "");
+        assert_eq!(output["error"]["message"], "Missing parameter: app");
+
+        let output = call_json(std::ptr::null_mut(), "apps", "");
+        assert_eq!(output["success"], false);
+
+        let output = call_json(session, "run", r#"{"steps": [{"exec": "echo hi"}]}"#);
+        assert_eq!(output["error"]["code"], "PERMISSION_DENIED");
+        let script = r#"{"steps": [{"applescript": "do shell script \"echo hi\""}]}"#;
+        let output = call_json(session, "run", script);
+        assert_eq!(output["error"]["code"], "PERMISSION_DENIED");
+        unsafe { bb_session_free(session) };
+
+        let version = unsafe { CStr::from_ptr(bb_version()) };
+        assert_eq!(version.to_str().unwrap(), env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn header_is_up_to_date() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/bigbrother.h"));
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/include/bigbrother.h");
+        if std::env::var_os("UPDATE_HEADER").is_some() {
+            std::fs::write(path, generated).unwrap();
+        }
+        let checked_in = std::fs::read_to_string(path).unwrap();
+        assert!(
+            checked_in == generated,
+            "include/bigbrother.h is out of date, run UPDATE_HEADER=1 cargo test -p bigbrother-ffi"
+        );
+    }
+}
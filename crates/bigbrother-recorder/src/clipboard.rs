@@ -0,0 +1,97 @@
+//! Clipboard content recorded with Paste events
+//!
+//! Platforms report the whole clipboard content; it's written to
+//! `clipboard_dir` here when `save_clipboard` is on, then cut down to
+//! `clipboard_preview_len` characters for the event itself.
+
+use crate::events::{ClipboardKind, EventData};
+use crate::recorder::RecorderConfig;
+use crate::text;
+use std::path::PathBuf;
+
+pub(crate) struct ClipboardStore {
+    preview_len: usize,
+    /// Where full contents go, with `save_clipboard`
+    dir: Option<PathBuf>,
+    /// Session start, keeps file names unique across sessions
+    prefix: String,
+}
+
+impl ClipboardStore {
+    pub fn new(config: &RecorderConfig) -> Self {
+        let dir = config.save_clipboard.then(|| config.clipboard_dir.clone()).filter(|dir| {
+            std::fs::create_dir_all(dir)
+                .inspect_err(|e| {
+                    let dir = dir.display();
+                    let message = "Clipboard contents not saved, can't create directory";
+                    tracing::warn!(%dir, error = %e, message)
+                })
+                .is_ok()
+        });
+        Self {
+            preview_len: config.clipboard_preview_len,
+            dir,
+            prefix: chrono::Local::now().format("%Y%m%d_%H%M%S").to_string(),
+        }
+    }
+
+    /// Fill in the size of a Paste event's content, save it whole if
+    /// configured, and shorten it to the preview length
+    pub fn process(&self, t: u64, data: &mut EventData) {
+        let EventData::Paste { o, s, k, l, f, .. } = data else {
+            return;
+        };
+        if *l == 0 {
+            *l = s.len() as u64;
+        }
+        if let (Some(dir), None) = (&self.dir, &f) {
+            // Images only have a placeholder preview to save
+            if *k != Some(ClipboardKind::Image) {
+                let path = dir.join(format!("{}_{}_{}.txt", self.prefix, t, o));
+                match std::fs::write(&path, s.as_bytes()) {
+                    Ok(()) => *f = Some(path.to_string_lossy().into_owned()),
+                    Err(e) => {
+                        tracing::warn!(path = %path.display(), error = %e, "Can't save clipboard")
+                    }
+                }
+            }
+        }
+        let end = text::prefix(s, self.preview_len).len();
+        if end < s.len() {
+            s.truncate(end);
+            s.push_str("...");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saves_full_content_and_shortens_preview() {
+        let dir = std::env::temp_dir().join(format!("bb-clipboard-{}", std::process::id()));
+        let config = RecorderConfig {
+            clipboard_preview_len: 4,
+            save_clipboard: true,
+            clipboard_dir: dir.clone(),
+            ..Default::default()
+        };
+        let store = ClipboardStore::new(&config);
+        let mut data = EventData::Paste {
+            o: 'c',
+            s: "héllo world".into(),
+            k: Some(ClipboardKind::Text),
+            m: None,
+            l: 0,
+            f: None,
+        };
+
+        store.process(3, &mut data);
+        let EventData::Paste { s, l, f, .. } = data else { unreachable!() };
+        assert_eq!(s, "héll...");
+        assert_eq!(l, 12);
+        assert_eq!(std::fs::read_to_string(f.unwrap()).unwrap(), "héllo world");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,150 @@
+//! Recording failures, by kind
+//!
+//! Starting a recording returns a [`RecorderError`]. Elsewhere the crate
+//! returns `anyhow::Error`, which keeps one made underneath:
+//! [`RecorderError::from`] gets it back, and the structured [`Error`] the
+//! automation side uses comes from either, so agents read the same codes
+//! from recording as from clicking.
+
+use bigbrother_types::error::{Error, ErrorCode};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RecorderError {
+    /// Input Monitoring or Accessibility isn't granted, or (Linux) no input
+    /// device is readable
+    PermissionDenied(String),
+    /// The system wouldn't install the input tap or hooks
+    TapCreationFailed(String),
+    /// Whoever received the events is gone
+    ChannelClosed,
+    /// Reading or writing a recording failed
+    StorageIo { path: Option<PathBuf>, source: io::Error },
+    /// Not a recording, or one from a newer version
+    InvalidRecording(String),
+    Other(anyhow::Error),
+}
+
+impl RecorderError {
+    pub(crate) fn storage(path: &Path, source: io::Error) -> Self {
+        Self::StorageIo { path: Some(path.to_path_buf()), source }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::PermissionDenied(_) => ErrorCode::PermissionDenied,
+            Self::TapCreationFailed(_) | Self::ChannelClosed => ErrorCode::ActionFailed,
+            Self::StorageIo { .. } | Self::InvalidRecording(_) => ErrorCode::ActionFailed,
+            Self::Other(_) => ErrorCode::Unknown,
+        }
+    }
+
+    /// The variant, for telling failures with the same code apart
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::PermissionDenied(_) => "PERMISSION_DENIED",
+            Self::TapCreationFailed(_) => "TAP_CREATION_FAILED",
+            Self::ChannelClosed => "CHANNEL_CLOSED",
+            Self::StorageIo { .. } => "STORAGE_IO",
+            Self::InvalidRecording(_) => "INVALID_RECORDING",
+            Self::Other(_) => "OTHER",
+        }
+    }
+}
+
+impl fmt::Display for RecorderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PermissionDenied(message) => write!(f, "Permission denied: {}", message),
+            Self::TapCreationFailed(message) => write!(f, "Can't capture input: {}", message),
+            Self::ChannelClosed => write!(f, "Event channel closed"),
+            Self::StorageIo { path: Some(path), source } => {
+                write!(f, "{}: {}", path.display(), source)
+            }
+            Self::StorageIo { path: None, source } => write!(f, "{}", source),
+            Self::InvalidRecording(message) => write!(f, "{}", message),
+            Self::Other(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+impl std::error::Error for RecorderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::StorageIo { source, .. } => Some(source),
+            Self::Other(e) => e.source(),
+            _ => None,
+        }
+    }
+}
+
+/// The `RecorderError` inside, or one for an I/O failure inside, or `Other`
+impl From<anyhow::Error> for RecorderError {
+    fn from(e: anyhow::Error) -> Self {
+        if e.is::<RecorderError>() {
+            return e.downcast().expect("checked");
+        }
+        match e.chain().find_map(|cause| cause.downcast_ref::<io::Error>()) {
+            // With the context around it, which usually names the file
+            Some(io) => {
+                let source = io::Error::new(io.kind(), format!("{:#}", e));
+                Self::StorageIo { path: None, source }
+            }
+            None => Self::Other(e),
+        }
+    }
+}
+
+impl From<io::Error> for RecorderError {
+    fn from(source: io::Error) -> Self {
+        Self::StorageIo { path: None, source }
+    }
+}
+
+impl From<RecorderError> for Error {
+    fn from(e: RecorderError) -> Self {
+        Self::from(&e)
+    }
+}
+
+impl From<&RecorderError> for Error {
+    fn from(e: &RecorderError) -> Self {
+        let mut context = serde_json::json!({"kind": e.kind()});
+        if let RecorderError::StorageIo { path: Some(path), .. } = e {
+            context["path"] = path.display().to_string().into();
+        }
+        let error = Error::new(e.code(), e.to_string()).with_context(context);
+        match e {
+            RecorderError::PermissionDenied(_) => error.with_suggestions(vec![
+                "Grant Accessibility and Input Monitoring (macOS), or join the input group (Linux)"
+                    .to_string(),
+            ]),
+            _ => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn kinds_survive_anyhow() {
+        let denied = anyhow::Error::from(RecorderError::PermissionDenied("no devices".into()));
+        let error = Error::from(RecorderError::from(denied.context("Starting")));
+        assert_eq!(error.code, ErrorCode::PermissionDenied);
+        assert_eq!(error.context.unwrap()["kind"], "PERMISSION_DENIED");
+
+        let missing = std::fs::File::open("/nonexistent/rec.jsonl").context("Reading rec.jsonl");
+        let error = RecorderError::from(missing.unwrap_err());
+        assert_eq!(error.kind(), "STORAGE_IO");
+        assert!(error.to_string().starts_with("Reading rec.jsonl: "));
+
+        let other = RecorderError::from(anyhow::anyhow!("odd"));
+        assert_eq!(Error::from(other).code, ErrorCode::Unknown);
+    }
+}
@@ -0,0 +1,603 @@
+//! Recordings made with web tools, converted to workflows
+//!
+//! [`import`] reads rrweb sessions (JSON), Selenium IDE projects (`.side`)
+//! and Playwright traces (`trace.zip`, or the `.trace` file in it) into a
+//! [`RecordedWorkflow`]:
+//!
+//! - rrweb records positions, in page coordinates, so its clicks land where
+//!   they did only with the browser window where it was
+//! - Selenium IDE and Playwright record selectors instead. Those with a
+//!   desktop equivalent (link text, accessible name or label, id) become
+//!   clicks on an element to run with a plan (`bb plan --run`); raw replay
+//!   has no position for them. Steps that can't be carried over are kept as
+//!   annotations saying so.
+
+use crate::events::{ElementContext, Event, EventData, RecordedWorkflow};
+use crate::keys::{Hotkey, Key};
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+/// App name on window events of imported web recordings
+const WEB_APP: &str = "Browser";
+
+/// Time between Selenium IDE commands, which aren't timed
+const STEP_MS: u64 = 500;
+
+/// Tool an imported recording was made with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    Rrweb,
+    SeleniumIde,
+    Playwright,
+}
+
+impl ImportFormat {
+    /// Guess from the extension: `.side`, `.zip` or `.trace`, `.json`
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "side" => Some(ImportFormat::SeleniumIde),
+            "zip" | "trace" => Some(ImportFormat::Playwright),
+            "json" => Some(ImportFormat::Rrweb),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rrweb" => Ok(ImportFormat::Rrweb),
+            "selenium" | "side" | "selenium-ide" => Ok(ImportFormat::SeleniumIde),
+            "playwright" | "trace" => Ok(ImportFormat::Playwright),
+            other => {
+                Err(format!("unknown import format: {} (rrweb, selenium or playwright)", other))
+            }
+        }
+    }
+}
+
+/// Convert the recording at `path`, made with `format` or the tool its
+/// extension names
+pub fn import(path: &Path, format: Option<ImportFormat>) -> Result<RecordedWorkflow> {
+    let format = format
+        .or_else(|| ImportFormat::from_path(path))
+        .with_context(|| format!("Can't tell what made {}, pass the format", path.display()))?;
+    let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("imported");
+    match format {
+        ImportFormat::Rrweb => rrweb(name, &std::fs::read_to_string(path)?),
+        ImportFormat::SeleniumIde => selenium_ide(&std::fs::read_to_string(path)?),
+        ImportFormat::Playwright => {
+            let trace = match path.extension().and_then(|e| e.to_str()) {
+                Some("zip") => read_trace_zip(path)?,
+                _ => std::fs::read_to_string(path)?,
+            };
+            playwright_trace(name, &trace)
+        }
+    }
+}
+
+/// Events from an rrweb session: an array of events, or an object with one
+/// under `events`
+pub fn rrweb(name: &str, json: &str) -> Result<RecordedWorkflow> {
+    let root: Value = serde_json::from_str(json)?;
+    let Some(recorded) = root.as_array().or_else(|| root["events"].as_array()) else {
+        bail!("Not an rrweb recording: expected an array of events");
+    };
+    let start = recorded.iter().filter_map(|e| e["timestamp"].as_i64()).min().unwrap_or(0);
+    let mut session = Rrweb::default();
+    for event in recorded {
+        let Some(timestamp) = event["timestamp"].as_i64() else {
+            continue;
+        };
+        session.event((timestamp - start).max(0), &event["type"], &event["data"]);
+    }
+    // Mouse positions are stamped before the batch they arrive in
+    session.events.sort_by_key(|e| e.t);
+    let mut workflow = RecordedWorkflow::new(name);
+    workflow.events = session.events;
+    Ok(workflow)
+}
+
+#[derive(Default)]
+struct Rrweb {
+    events: Vec<Event>,
+    /// Nodes whose input isn't typed text: selects, checkboxes and the like
+    untyped: HashSet<u64>,
+    passwords: HashSet<u64>,
+    values: HashMap<u64, String>,
+    scroll: HashMap<u64, (i64, i64)>,
+    mouse: (i32, i32),
+}
+
+impl Rrweb {
+    fn push(&mut self, t: i64, data: EventData) {
+        self.events.push(Event { t: t as u64, ts: None, data });
+    }
+
+    fn event(&mut self, t: i64, kind: &Value, data: &Value) {
+        match kind.as_u64() {
+            // Full snapshot
+            Some(2) => self.index(&data["node"]),
+            // Incremental snapshot
+            Some(3) => self.incremental(t, data),
+            // Meta
+            Some(4) => {
+                if let Some(href) = data["href"].as_str() {
+                    let u = Some(href.to_string());
+                    self.push(t, EventData::Window { a: WEB_APP.to_string(), w: None, u });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn incremental(&mut self, t: i64, data: &Value) {
+        let id = data["id"].as_u64().unwrap_or(0);
+        let position = |v: &Value| -> Option<(i32, i32)> {
+            Some((v["x"].as_f64()? as i32, v["y"].as_f64()? as i32))
+        };
+        match data["source"].as_u64() {
+            // Mutation
+            Some(0) => {
+                for added in data["adds"].as_array().into_iter().flatten() {
+                    self.index(&added["node"]);
+                }
+            }
+            // Mouse and touch moves
+            Some(1 | 6) => {
+                for p in data["positions"].as_array().into_iter().flatten() {
+                    let Some((x, y)) = position(p) else { continue };
+                    self.mouse = (x, y);
+                    self.push(t + p["timeOffset"].as_i64().unwrap_or(0), EventData::Move { x, y });
+                }
+            }
+            // Mouse interaction: click, context menu, double click
+            Some(2) => {
+                let Some((x, y)) = position(data) else { return };
+                self.mouse = (x, y);
+                let click = |b, n| EventData::Click { x, y, b, n, m: 0, c: None };
+                match data["type"].as_u64() {
+                    Some(2) => self.push(t, click(0, 1)),
+                    Some(3) => self.push(t, click(1, 1)),
+                    Some(4) => {
+                        // The browser reported both clicks before this
+                        for _ in 0..2 {
+                            match self.events.last() {
+                                Some(Event {
+                                    data: EventData::Click { x: cx, y: cy, n: 1, .. }, ..
+                                }) if (*cx, *cy) == (x, y) => {
+                                    self.events.pop();
+                                }
+                                _ => break,
+                            }
+                        }
+                        self.push(t, click(0, 2));
+                    }
+                    _ => {}
+                }
+            }
+            // Scroll: offsets, turned into pixel deltas (positive is up)
+            Some(3) => {
+                let (Some(x), Some(y)) = (data["x"].as_f64(), data["y"].as_f64()) else { return };
+                let (x, y) = (x as i64, y as i64);
+                let (px, py) = self.scroll.insert(id, (x, y)).unwrap_or((0, 0));
+                let delta = |d: i64| d.clamp(i16::MIN as i64, i16::MAX as i64) as i16;
+                let (dx, dy) = (delta(px - x), delta(py - y));
+                if (dx, dy) != (0, 0) {
+                    let (x, y) = self.mouse;
+                    self.push(t, EventData::Scroll { x, y, dx, dy, px: true });
+                }
+            }
+            // Input: the field's whole value, typed as what it gained
+            Some(5) => {
+                let Some(text) = data["text"].as_str() else { return };
+                if self.untyped.contains(&id) {
+                    return;
+                }
+                if self.passwords.contains(&id) {
+                    if self.values.insert(id, String::new()).is_none() {
+                        let s = "A password typed here wasn't recorded".to_string();
+                        self.push(t, EventData::Annotation { s });
+                    }
+                    return;
+                }
+                let before = self.values.insert(id, text.to_string()).unwrap_or_default();
+                if let Some(added) = text.strip_prefix(before.as_str()) {
+                    if !added.is_empty() {
+                        self.push(t, EventData::Text { s: added.to_string() });
+                    }
+                } else if let Some(removed) = before.strip_prefix(text) {
+                    for _ in removed.chars() {
+                        self.push(t, EventData::Key { k: Key::Backspace, m: 0 });
+                    }
+                } else {
+                    self.push(t, EventData::Text { s: text.to_string() });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Note the form fields in a snapshot node and its children
+    fn index(&mut self, node: &Value) {
+        if let Some(id) = node["id"].as_u64() {
+            let tag = node["tagName"].as_str().unwrap_or_default().to_lowercase();
+            let kind = node["attributes"]["type"].as_str().unwrap_or("text").to_lowercase();
+            match (tag.as_str(), kind.as_str()) {
+                ("select", _) => {
+                    self.untyped.insert(id);
+                }
+                ("input", "password") => {
+                    self.passwords.insert(id);
+                }
+                ("input", "text" | "search" | "email" | "url" | "tel" | "number") => {}
+                ("input", _) => {
+                    self.untyped.insert(id);
+                }
+                _ => {}
+            }
+        }
+        for child in node["childNodes"].as_array().into_iter().flatten() {
+            self.index(child);
+        }
+    }
+}
+
+/// Every test of a Selenium IDE project, one after the other
+pub fn selenium_ide(json: &str) -> Result<RecordedWorkflow> {
+    let project: Value = serde_json::from_str(json)?;
+    let Some(tests) = project["tests"].as_array() else {
+        bail!("Not a Selenium IDE project: no tests");
+    };
+    let base = project["url"].as_str().unwrap_or_default().trim_end_matches('/');
+    let mut workflow = RecordedWorkflow::new(project["name"].as_str().unwrap_or("selenium"));
+    let mut steps = Steps::default();
+    for test in tests {
+        if tests.len() > 1 {
+            let name = test["name"].as_str().unwrap_or("test");
+            steps.push(EventData::Annotation { s: format!("Test: {}", name) });
+        }
+        for command in test["commands"].as_array().into_iter().flatten() {
+            let name = command["command"].as_str().unwrap_or_default();
+            let target = command["target"].as_str().unwrap_or_default();
+            let value = command["value"].as_str().unwrap_or_default();
+            // The target, then the alternatives the IDE recorded
+            let targets = std::iter::once(target).chain(
+                command["targets"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|t| t[0].as_str()),
+            );
+            let selector = targets.filter_map(web_selector).next();
+            match name {
+                "open" => {
+                    let url = if target.starts_with('/') {
+                        format!("{}{}", base, target)
+                    } else {
+                        target.to_string()
+                    };
+                    steps.open(url);
+                }
+                "click" | "clickAt" | "check" | "uncheck" => steps.click(selector, 1, 0, target),
+                "doubleClick" | "doubleClickAt" => steps.click(selector, 2, 0, target),
+                "type" => {
+                    steps.click(selector, 1, 0, target);
+                    steps.push(EventData::Text { s: value.to_string() });
+                }
+                "sendKeys" => {
+                    steps.click(selector, 1, 0, target);
+                    steps.send_keys(value);
+                }
+                "pause" => steps.t += value.parse::<u64>().unwrap_or(0),
+                "" => {}
+                _ => steps.not_imported(format!("{} {}", name, target).trim_end().to_string()),
+            }
+            steps.t += STEP_MS;
+        }
+    }
+    workflow.events = steps.events;
+    Ok(workflow)
+}
+
+/// Playwright trace events (JSON lines), old and new layouts
+pub fn playwright_trace(name: &str, trace: &str) -> Result<RecordedWorkflow> {
+    let mut steps = Steps::default();
+    let mut start = None;
+    let mut actions = 0;
+    for line in trace.lines().filter(|l| !l.trim().is_empty()) {
+        let entry: Value = serde_json::from_str(line).context("Not a Playwright trace")?;
+        // Newer traces: "before" events; older: "action" with the call in metadata
+        let call = match entry["type"].as_str() {
+            Some("before") => &entry,
+            Some("action") => &entry["metadata"],
+            _ => continue,
+        };
+        let (Some(method), Some(time)) = (call["method"].as_str(), call["startTime"].as_f64())
+        else {
+            continue;
+        };
+        actions += 1;
+        steps.t = (time - *start.get_or_insert(time)).max(0.0) as u64;
+        let params = &call["params"];
+        let text = |key: &str| params[key].as_str().unwrap_or_default().to_string();
+        let selector = params["selector"].as_str();
+        let target = selector.and_then(web_selector);
+        let what = selector.unwrap_or_default();
+        match method {
+            "goto" => steps.open(text("url")),
+            "click" | "check" | "uncheck" | "tap" => {
+                let n = params["clickCount"].as_u64().unwrap_or(1) as u8;
+                let b = if params["button"].as_str() == Some("right") { 1 } else { 0 };
+                steps.click(target, n, b, what);
+            }
+            "dblclick" => steps.click(target, 2, 0, what),
+            "fill" | "type" | "pressSequentially" => {
+                steps.click(target, 1, 0, what);
+                let s = text(if method == "fill" { "value" } else { "text" });
+                steps.push(EventData::Text { s });
+            }
+            "press" | "keyboardPress" => {
+                if selector.is_some() {
+                    steps.click(target, 1, 0, what);
+                }
+                steps.press(&text("key"));
+            }
+            "keyboardType" | "keyboardInsertText" => {
+                steps.push(EventData::Text { s: text("text") });
+            }
+            _ => actions -= 1,
+        }
+    }
+    if actions == 0 {
+        bail!("No actions in the Playwright trace");
+    }
+    let mut workflow = RecordedWorkflow::new(name);
+    workflow.events = steps.events;
+    Ok(workflow)
+}
+
+/// The `.trace` files in a Playwright `trace.zip`, joined
+fn read_trace_zip(path: &Path) -> Result<String> {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+    let mut names: Vec<String> =
+        archive.file_names().filter(|n| n.ends_with(".trace")).map(String::from).collect();
+    if names.is_empty() {
+        bail!("No .trace file in {}", path.display());
+    }
+    names.sort();
+    let mut trace = String::new();
+    for name in names {
+        archive.by_name(&name)?.read_to_string(&mut trace)?;
+        trace.push('\n');
+    }
+    Ok(trace)
+}
+
+/// Untimed or selector-driven steps as events
+#[derive(Default)]
+struct Steps {
+    events: Vec<Event>,
+    t: u64,
+}
+
+impl Steps {
+    fn push(&mut self, data: EventData) {
+        self.events.push(Event { t: self.t, ts: None, data });
+    }
+
+    fn open(&mut self, url: String) {
+        self.push(EventData::Window { a: WEB_APP.to_string(), w: None, u: Some(url) });
+    }
+
+    fn not_imported(&mut self, what: String) {
+        self.push(EventData::Annotation { s: format!("Not imported: {}", what) });
+    }
+
+    /// A click on the element `selector` finds, or a note that the web
+    /// selector `target` has no desktop equivalent
+    fn click(&mut self, selector: Option<String>, n: u8, b: u8, target: &str) {
+        match selector {
+            Some(s) => {
+                let c = Some(Box::new(ElementContext { s, ..Default::default() }));
+                self.push(EventData::Click { x: 0, y: 0, b, n, m: 0, c });
+            }
+            None if target.is_empty() => {}
+            None => self.not_imported(format!("click on {}, no desktop equivalent", target)),
+        }
+    }
+
+    /// Playwright key names: `Enter`, `Control+A`, `ArrowDown`
+    fn press(&mut self, key: &str) {
+        let primary = if cfg!(target_os = "macos") { "Meta" } else { "Control" };
+        let key = key.replace("ControlOrMeta", primary);
+        let mut parts: Vec<&str> = key.split('+').collect();
+        let last = parts.pop().unwrap_or_default();
+        let last = last.strip_prefix("Arrow").unwrap_or(last);
+        parts.push(last);
+        match parts.join("+").parse::<Hotkey>() {
+            Ok(Hotkey { key: k, m }) => self.push(EventData::Key { k, m }),
+            Err(_) => self.not_imported(format!("press {}", key)),
+        }
+    }
+
+    /// Selenium `sendKeys` text, with keys as `${KEY_ENTER}`
+    fn send_keys(&mut self, value: &str) {
+        let mut rest = value;
+        while let Some(start) = rest.find("${KEY_") {
+            let Some(len) = rest[start..].find('}') else { break };
+            if start > 0 {
+                self.push(EventData::Text { s: rest[..start].to_string() });
+            }
+            let name = rest[start + 6..start + len].to_lowercase();
+            let key = match name.as_str() {
+                "pgup" => "pageup",
+                "pgdn" => "pagedown",
+                "bksp" => "backspace",
+                "del" => "delete",
+                name => name,
+            };
+            match Key::from_name(key) {
+                Some(k) => self.push(EventData::Key { k, m: 0 }),
+                None => self.not_imported(format!("key {}", name)),
+            }
+            rest = &rest[start + len + 1..];
+        }
+        if !rest.is_empty() {
+            self.push(EventData::Text { s: rest.to_string() });
+        }
+    }
+}
+
+/// A web selector (Selenium or Playwright) as a desktop one, when it names
+/// the element by text, label or id, which browsers expose to accessibility
+fn web_selector(selector: &str) -> Option<String> {
+    // Chained Playwright selectors: the last part is the element
+    let s = selector.rsplit(" >> ").next()?.trim();
+    let name = |(value, exact): (String, bool)| {
+        if exact {
+            format!("name:{}", value)
+        } else {
+            format!("name~:{}", value)
+        }
+    };
+    if let Some(text) = s.strip_prefix("linkText=") {
+        return Some(format!("name:{}", text));
+    }
+    if let Some(text) = s.strip_prefix("partialLinkText=") {
+        return Some(format!("name~:{}", text));
+    }
+    if let Some(id) = s.strip_prefix("id=") {
+        return Some(format!("automationid:{}", id));
+    }
+    if let Some(text) = s.strip_prefix("internal:text=").or_else(|| s.strip_prefix("text=")) {
+        return Some(name(unquote(text)));
+    }
+    if let Some(label) = s.strip_prefix("internal:label=") {
+        return Some(name(unquote(label)));
+    }
+    if let Some(role) = s.strip_prefix("internal:role=").or_else(|| s.strip_prefix("role=")) {
+        let (_, rest) = role.split_once("[name=")?;
+        return Some(name(unquote(rest.trim_end_matches(']'))));
+    }
+    if let Some(xpath) = s.strip_prefix("xpath=") {
+        for (pattern, exact) in [
+            ("text()=", true),
+            ("@aria-label=", true),
+            ("@title=", true),
+            ("contains(text(),", false),
+            ("contains(.,", false),
+        ] {
+            if let Some(text) = quoted_after(xpath, pattern) {
+                return Some(name((text.to_string(), exact)));
+            }
+        }
+        return quoted_after(xpath, "@id=").map(|id| format!("automationid:{}", id));
+    }
+    let css = s.strip_prefix("css=").unwrap_or(s);
+    if let Some(label) = quoted_after(css, "[aria-label=") {
+        return Some(format!("name:{}", label));
+    }
+    let id = css.strip_prefix('#')?;
+    id.chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        .then(|| format!("automationid:{}", id))
+}
+
+/// A Playwright selector value: `"Save"` or `"Save"s` matches exactly,
+/// `"Save"i` and unquoted text ignore case and match part of the name
+fn unquote(s: &str) -> (String, bool) {
+    match s.strip_prefix('"').and_then(|s| s.rsplit_once('"')) {
+        Some((value, flags)) => (value.replace("\\\"", "\""), flags != "i"),
+        None => (s.to_string(), false),
+    }
+}
+
+/// The quoted string right after `pattern`, in single or double quotes
+fn quoted_after<'a>(s: &'a str, pattern: &str) -> Option<&'a str> {
+    let rest = s[s.find(pattern)? + pattern.len()..].trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let rest = &rest[1..];
+    Some(&rest[..rest.find(quote)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventKind;
+
+    fn kinds(workflow: &RecordedWorkflow) -> Vec<EventKind> {
+        workflow.events.iter().map(|e| e.data.kind()).collect()
+    }
+
+    #[test]
+    fn converts_web_selectors() {
+        assert_eq!(web_selector("linkText=Sign in").unwrap(), "name:Sign in");
+        assert_eq!(web_selector("internal:role=button[name=\"Save\"i]").unwrap(), "name~:Save");
+        assert_eq!(web_selector("xpath=//a[contains(text(),'Docs')]").unwrap(), "name~:Docs");
+        assert_eq!(web_selector("css=#submit").unwrap(), "automationid:submit");
+        assert_eq!(web_selector("css=div > .btn:nth-child(2)"), None);
+    }
+
+    #[test]
+    fn imports_recordings() {
+        let rrweb_json = r#"[
+            {"type": 4, "data": {"href": "https://example.com"}, "timestamp": 1000},
+            {"type": 2, "data": {"node": {"id": 1, "childNodes": [
+                {"id": 5, "tagName": "input", "attributes": {"type": "checkbox"}}
+            ]}}, "timestamp": 1000},
+            {"type": 3, "data": {"source": 2, "type": 2, "id": 4, "x": 10, "y": 20},
+                "timestamp": 1500},
+            {"type": 3, "data": {"source": 5, "id": 4, "text": "he"}, "timestamp": 1600},
+            {"type": 3, "data": {"source": 5, "id": 4, "text": "hey"}, "timestamp": 1700},
+            {"type": 3, "data": {"source": 5, "id": 5, "text": "on"}, "timestamp": 1800},
+            {"type": 3, "data": {"source": 3, "id": 1, "x": 0, "y": 300}, "timestamp": 1900}
+        ]"#;
+        let workflow = rrweb("session", rrweb_json).unwrap();
+        let t: Vec<u64> = workflow.events.iter().map(|e| e.t).collect();
+        assert_eq!(t, [0, 500, 600, 700, 900]);
+        assert!(matches!(&workflow.events[3].data, EventData::Text { s } if s == "y"));
+        assert!(matches!(workflow.events[4].data, EventData::Scroll { dy: -300, .. }));
+
+        let side = r#"{"name": "login", "url": "https://example.com/", "tests": [{"name": "t",
+            "commands": [
+                {"command": "open", "target": "/login", "value": ""},
+                {"command": "click", "target": "css=.x", "value": "",
+                    "targets": [["linkText=Sign in", "linkText"]]},
+                {"command": "sendKeys", "target": "id=q", "value": "hi${KEY_ENTER}"},
+                {"command": "assertTitle", "target": "Home", "value": ""}
+            ]}]}"#;
+        let workflow = selenium_ide(side).unwrap();
+        let k = EventKind::Click;
+        assert_eq!(
+            kinds(&workflow),
+            [EventKind::Window, k, k, EventKind::Text, EventKind::Key, EventKind::Annotation]
+        );
+        assert!(matches!(&workflow.events[0].data,
+            EventData::Window { u: Some(u), .. } if u == "https://example.com/login"));
+
+        let email = "internal:label=\"Email\"i";
+        let before = |method: &str, time: f64, params: Value| {
+            serde_json::json!({
+                "type": "before", "method": method, "startTime": time, "params": params
+            })
+            .to_string()
+        };
+        let trace = [
+            r#"{"type": "context-options", "browserName": "chromium"}"#.to_string(),
+            before("goto", 100.5, serde_json::json!({ "url": "https://example.com" })),
+            before("fill", 300.5, serde_json::json!({ "selector": email, "value": "a@b.c" })),
+            before("press", 400.5, serde_json::json!({ "selector": email, "key": "Control+A" })),
+        ]
+        .join("\n");
+        let workflow = playwright_trace("trace", &trace).unwrap();
+        let t: Vec<u64> = workflow.events.iter().map(|e| e.t).collect();
+        assert_eq!(t, [0, 200, 200, 300, 300]);
+        assert!(matches!(&workflow.events[4].data, EventData::Key { k: Key::A, m } if *m != 0));
+    }
+}
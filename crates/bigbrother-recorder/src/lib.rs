@@ -6,53 +6,92 @@
 //! ## Platform Support
 //!
 //! - **macOS**: Full support via CGEventTap
-//! - **Windows**: Full support via rdev + SendInput
-//! - **Linux**: Coming soon (libevdev)
+//! - **Windows**: Full support via low-level hooks + SendInput
+//! - **Linux**: Recording via evdev, replay via uinput or the RemoteDesktop
+//!   portal (X11 and Wayland)
 
-pub mod events;
+mod clipboard;
+pub mod error;
+pub mod import;
+pub mod normalize;
 pub mod platform;
-pub mod storage;
-
-#[cfg(target_os = "macos")]
 pub mod recorder;
-#[cfg(target_os = "macos")]
+pub mod redact;
+pub mod remote;
 pub mod replay;
+pub mod schedule;
+#[cfg(feature = "screenpipe")]
+pub mod screenpipe;
+mod screenshot;
+pub mod semantics;
+pub mod service;
+mod spill;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod storage;
+pub mod stream;
+pub mod summary;
+pub mod table;
+pub mod training;
+#[cfg(feature = "video")]
+mod video;
 
+// Platform-free types, usable on their own from `bigbrother-types`
+pub use bigbrother_types::{events, keys, text};
+
+pub use error::RecorderError;
 pub use events::*;
+pub use import::ImportFormat;
+pub use keys::{Hotkey, Key};
 
-// macOS exports
-#[cfg(target_os = "macos")]
 pub use recorder::{
-    EventStream, PermissionStatus, RecorderConfig, RecordingHandle, Receiver, Sender,
-    WorkflowRecorder,
+    CaptureHealth, DragMode, EventStream, Overflow, Permission, PermissionState, PermissionStatus,
+    RecorderConfig, RecordingHandle, RecordingStats, Receiver, Sender, Subscription,
+    WorkflowRecorder, REDACTED,
 };
-#[cfg(target_os = "macos")]
-pub use replay::Replayer;
-
-// Windows exports
-#[cfg(target_os = "windows")]
-pub use platform::windows::{
-    EventStream, PermissionStatus, RecorderConfig, RecordingHandle, ReplayStats, Replayer,
-    WorkflowRecorder,
+#[cfg(feature = "async")]
+pub use recorder::AsyncEventStream;
+pub use redact::{Redactor, Scrubber};
+pub use replay::{
+    AssertionResult, CancellationToken, Failure, Injector, Progress, Recovery, ReplayObserver,
+    ReplayStats, Replayer, Step,
 };
-
-pub use storage::WorkflowStorage;
+pub use schedule::Schedule;
+pub use service::{RecordingService, ServiceConfig, ServiceControl, ServiceStatus};
+#[cfg(feature = "sqlite")]
+pub use sqlite::{EventQuery, SqliteStore, StoredEvent, StoredWorkflow};
+pub use storage::{
+    Compression, EventReader, Format, Pruned, RetentionPolicy, WorkflowInfo, WorkflowQuery,
+    WorkflowStorage, WorkflowWriter,
+};
+pub use stream::EventIterExt;
+pub use table::{EventRow, TableFormat};
 
 pub mod prelude {
+    pub use crate::error::RecorderError;
     pub use crate::events::*;
-    pub use crate::storage::WorkflowStorage;
+    pub use crate::keys::{Hotkey, Key};
+    pub use crate::storage::{
+        Compression, EventReader, Format, Pruned, RetentionPolicy, WorkflowInfo, WorkflowQuery,
+        WorkflowStorage, WorkflowWriter,
+    };
 
-    #[cfg(target_os = "macos")]
     pub use crate::recorder::{
-        EventStream, PermissionStatus, RecorderConfig, RecordingHandle, Receiver, Sender,
-        WorkflowRecorder,
+        CaptureHealth, DragMode, EventStream, Overflow, Permission, PermissionState,
+        PermissionStatus, RecorderConfig, RecordingHandle, RecordingStats, Receiver, Sender,
+        Subscription, WorkflowRecorder, REDACTED,
     };
-    #[cfg(target_os = "macos")]
-    pub use crate::replay::Replayer;
-
-    #[cfg(target_os = "windows")]
-    pub use crate::platform::windows::{
-        EventStream, PermissionStatus, RecorderConfig, RecordingHandle, ReplayStats, Replayer,
-        WorkflowRecorder,
+    #[cfg(feature = "async")]
+    pub use crate::recorder::AsyncEventStream;
+    pub use crate::redact::{Redactor, Scrubber};
+    pub use crate::replay::{
+        AssertionResult, CancellationToken, Failure, Injector, Progress, Recovery,
+        ReplayObserver, ReplayStats, Replayer, Step,
     };
+    pub use crate::schedule::Schedule;
+    pub use crate::service::{RecordingService, ServiceConfig, ServiceControl};
+    #[cfg(feature = "sqlite")]
+    pub use crate::sqlite::{EventQuery, SqliteStore, StoredEvent};
+    pub use crate::stream::EventIterExt;
+    pub use crate::table::TableFormat;
 }
@@ -0,0 +1,242 @@
+//! Cross-platform workflow normalization
+//!
+//! Converts a recording into a [`PortableWorkflow`] and back, so a workflow
+//! recorded on one platform can be replayed on another. Mapping is best
+//! effort:
+//!
+//! - Keys are already portable (see [`Key`](crate::keys::Key))
+//! - Coordinates become fractions of the screen, in [`PORTABLE_EXTENT`] units
+//! - Modifiers follow the macOS convention, where Cmd is the primary shortcut
+//!   modifier; Ctrl and Cmd swap on Windows and Linux, so Cmd+C becomes Ctrl+C
+//! - Well-known apps get a portable id ("chrome"); unknown names pass through
+
+use crate::events::*;
+use serde::{Deserialize, Serialize};
+
+/// Portable coordinates span 0..PORTABLE_EXTENT on both axes
+pub const PORTABLE_EXTENT: i32 = 10_000;
+
+/// Operating system a workflow was recorded or replayed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    Macos,
+    Windows,
+    Linux,
+}
+
+impl Platform {
+    /// Platform this binary was built for
+    pub fn current() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::Macos
+        } else if cfg!(target_os = "windows") {
+            Self::Windows
+        } else {
+            Self::Linux
+        }
+    }
+}
+
+/// Where a workflow was recorded or will be replayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Environment {
+    pub platform: Platform,
+    /// Screen size in the coordinate space the platform records in
+    pub screen: (u32, u32),
+}
+
+impl Environment {
+    pub fn new(platform: Platform, width: u32, height: u32) -> Self {
+        Self {
+            platform,
+            screen: (width, height),
+        }
+    }
+}
+
+/// A workflow in platform-neutral form
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PortableWorkflow {
+    pub name: String,
+    /// Platform the events were originally recorded on
+    pub source: Platform,
+    pub events: Vec<Event>,
+}
+
+/// Convert a recording into portable form
+pub fn to_portable(workflow: &RecordedWorkflow, env: &Environment) -> PortableWorkflow {
+    PortableWorkflow {
+        name: workflow.name.clone(),
+        source: env.platform,
+        events: workflow
+            .events
+            .iter()
+            .map(|e| map_event(e, env, Direction::ToPortable))
+            .collect(),
+    }
+}
+
+/// Convert a portable workflow into events for `env`
+pub fn from_portable(portable: &PortableWorkflow, env: &Environment) -> RecordedWorkflow {
+    RecordedWorkflow {
+        name: portable.name.clone(),
+        // The source environment no longer applies
+        meta: None,
+        description: None,
+        tags: Vec::new(),
+        events: portable
+            .events
+            .iter()
+            .map(|e| map_event(e, env, Direction::FromPortable))
+            .collect(),
+    }
+}
+
+/// Convert a recording from one environment to another
+pub fn convert(workflow: &RecordedWorkflow, from: &Environment, to: &Environment) -> RecordedWorkflow {
+    from_portable(&to_portable(workflow, from), to)
+}
+
+#[derive(Clone, Copy)]
+enum Direction {
+    ToPortable,
+    FromPortable,
+}
+
+fn map_event(event: &Event, env: &Environment, dir: Direction) -> Event {
+    let point = |x: i32, y: i32| map_point(x, y, env.screen, dir);
+    let mods = |m: u8| map_modifiers(m, env.platform);
+    let app = |name: &str| map_app(name, env.platform, dir);
+
+    let data = match &event.data {
+        EventData::Click { x, y, b, n, m, c } => {
+            let (x, y) = point(*x, *y);
+            let c = c.as_ref().map(|c| {
+                Box::new(ElementContext {
+                    b: c.b.map(|(bx, by, w, h)| {
+                        let (bx, by) = point(bx, by);
+                        let (w, h) = point(w, h);
+                        (bx, by, w, h)
+                    }),
+                    a: c.a.as_deref().map(app),
+                    ..(**c).clone()
+                })
+            });
+            EventData::Click { x, y, b: *b, n: *n, m: mods(*m), c }
+        }
+        EventData::Move { x, y } => {
+            let (x, y) = point(*x, *y);
+            EventData::Move { x, y }
+        }
+        EventData::Drag { x, y, x2, y2, b, p, m, d } => {
+            let (x, y) = point(*x, *y);
+            let (x2, y2) = point(*x2, *y2);
+            let p = p.iter().map(|&(px, py)| point(px, py)).collect();
+            EventData::Drag { x, y, x2, y2, b: *b, p, m: mods(*m), d: *d }
+        }
+        EventData::MouseUp { x, y, b } => {
+            let (x, y) = point(*x, *y);
+            EventData::MouseUp { x, y, b: *b }
+        }
+        EventData::Scroll { x, y, dx, dy, px } => {
+            let (x, y) = point(*x, *y);
+            EventData::Scroll { x, y, dx: *dx, dy: *dy, px: *px }
+        }
+        EventData::Key { k, m } => EventData::Key { k: *k, m: mods(*m) },
+        EventData::KeyUp { k, m, d } => EventData::KeyUp { k: *k, m: mods(*m), d: *d },
+        EventData::App { n, p } => EventData::App { n: app(n), p: *p },
+        EventData::Window { a, w, u } => EventData::Window { a: app(a), w: w.clone(), u: u.clone() },
+        other => other.clone(),
+    };
+    Event { t: event.t, ts: event.ts, data }
+}
+
+fn map_point(x: i32, y: i32, (width, height): (u32, u32), dir: Direction) -> (i32, i32) {
+    let scale = |v: i32, size: u32| {
+        let size = size.max(1) as f64;
+        let extent = PORTABLE_EXTENT as f64;
+        match dir {
+            Direction::ToPortable => (v as f64 * extent / size).round() as i32,
+            Direction::FromPortable => (v as f64 * size / extent).round() as i32,
+        }
+    };
+    (scale(x, width), scale(y, height))
+}
+
+/// Swap Ctrl and Cmd off macOS; the swap is its own inverse
+fn map_modifiers(m: u8, platform: Platform) -> u8 {
+    if platform == Platform::Macos {
+        return m;
+    }
+    let mut out = m & !(Modifiers::CTRL | Modifiers::CMD);
+    if m & Modifiers::CTRL != 0 {
+        out |= Modifiers::CMD;
+    }
+    if m & Modifiers::CMD != 0 {
+        out |= Modifiers::CTRL;
+    }
+    out
+}
+
+/// (portable id, macOS name, macOS bundle id, Windows process, Linux process)
+const APPS: &[(&str, &str, &str, &str, &str)] = &[
+    ("chrome", "Google Chrome", "com.google.Chrome", "chrome.exe", "chrome"),
+    ("firefox", "Firefox", "org.mozilla.firefox", "firefox.exe", "firefox"),
+    ("edge", "Microsoft Edge", "com.microsoft.edgemac", "msedge.exe", "msedge"),
+    ("brave", "Brave Browser", "com.brave.Browser", "brave.exe", "brave"),
+    ("vscode", "Code", "com.microsoft.VSCode", "Code.exe", "code"),
+    ("slack", "Slack", "com.tinyspeck.slackmacgap", "slack.exe", "slack"),
+    ("terminal", "Terminal", "com.apple.Terminal", "WindowsTerminal.exe", "gnome-terminal-server"),
+    ("files", "Finder", "com.apple.finder", "explorer.exe", "nautilus"),
+    ("text-editor", "TextEdit", "com.apple.TextEdit", "notepad.exe", "gedit"),
+];
+
+fn map_app(name: &str, platform: Platform, dir: Direction) -> String {
+    let found = match dir {
+        Direction::ToPortable => APPS.iter().find(|a| {
+            let native = match platform {
+                Platform::Macos => a.1,
+                Platform::Windows => a.3,
+                Platform::Linux => a.4,
+            };
+            native.eq_ignore_ascii_case(name) || a.2 == name
+        }),
+        Direction::FromPortable => APPS.iter().find(|a| a.0 == name),
+    };
+
+    match (found, dir) {
+        (Some(a), Direction::ToPortable) => a.0.to_string(),
+        (Some(a), Direction::FromPortable) => match platform {
+            Platform::Macos => a.1,
+            Platform::Windows => a.3,
+            Platform::Linux => a.4,
+        }
+        .to_string(),
+        (None, _) => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::Key;
+
+    #[test]
+    fn converts_macos_recording_for_windows() {
+        let mut workflow = RecordedWorkflow::new("copy");
+        workflow.events = vec![
+            Event { t: 0, ts: None, data: EventData::App { n: "Google Chrome".into(), p: 42 } },
+            Event { t: 10, ts: None, data: EventData::Click { x: 720, y: 450, b: 0, n: 1, m: 0, c: None } },
+            Event { t: 20, ts: None, data: EventData::Key { k: Key::C, m: Modifiers::CMD } },
+        ];
+
+        let mac = Environment::new(Platform::Macos, 1440, 900);
+        let windows = Environment::new(Platform::Windows, 1920, 1080);
+        let converted = convert(&workflow, &mac, &windows);
+
+        assert!(matches!(&converted.events[0].data, EventData::App { n, .. } if n == "chrome.exe"));
+        assert!(matches!(converted.events[1].data, EventData::Click { x: 960, y: 540, .. }));
+        assert!(matches!(converted.events[2].data, EventData::Key { k: Key::C, m: Modifiers::CTRL }));
+    }
+}
@@ -0,0 +1,173 @@
+//! AT-SPI window lookups
+//!
+//! GNOME and KDE have no compositor IPC for the focused window, but their
+//! apps publish an accessibility tree on the AT-SPI bus, where each app's
+//! top level windows say whether they're active. Apps that don't expose
+//! accessibility (Electron without `--force-renderer-accessibility`) aren't
+//! seen.
+
+use std::time::Duration;
+use zbus::blocking::Connection;
+use zbus::zvariant::{OwnedObjectPath, OwnedValue};
+
+/// An app that stops answering shouldn't stall the recorder
+const CALL_TIMEOUT: Duration = Duration::from_millis(250);
+
+const ACCESSIBLE: &str = "org.a11y.atspi.Accessible";
+const REGISTRY: &str = "org.a11y.atspi.Registry";
+const ROOT: &str = "/org/a11y/atspi/accessible/root";
+
+// AtspiStateType bits
+const STATE_ACTIVE: u32 = 1;
+const STATE_FOCUSED: u32 = 12;
+const STATE_SHOWING: u32 = 25;
+
+// AtspiRole
+const ROLE_PASSWORD_TEXT: u32 = 40;
+
+/// Elements visited looking for the focused one before giving up
+const MAX_FOCUS_SEARCH: usize = 2000;
+
+/// An element: the bus name of its app and its object path
+type Node = (String, OwnedObjectPath);
+
+/// Connection to the accessibility bus
+pub struct Atspi {
+    conn: Connection,
+}
+
+impl Atspi {
+    /// Connect to the session's accessibility bus; None when there's no
+    /// session bus or accessibility is off
+    pub fn connect() -> Option<Self> {
+        let session = Connection::session().ok()?;
+        let bus = Some("org.a11y.Bus");
+        let reply = session.call_method(bus, "/org/a11y/bus", bus, "GetAddress", &()).ok()?;
+        let address: String = reply.body().deserialize().ok()?;
+        let conn = zbus::blocking::connection::Builder::address(address.as_str())
+            .ok()?
+            .method_timeout(CALL_TIMEOUT)
+            .build()
+            .ok()?;
+        Some(Self { conn })
+    }
+
+    /// Get current focused app info: (process name, pid, window title)
+    pub fn focused_app(&self) -> Option<(String, u32, Option<String>)> {
+        let (bus, window) = self.active_window()?;
+        let pid = self.pid(&bus)?;
+        let name = super::get_process_name(pid)?;
+        let title = self.name(&(bus, window)).filter(|t| !t.is_empty());
+        Some((name, pid, title))
+    }
+
+    /// Focus a window of the app with this process name; false if none
+    /// was found or the app wouldn't. GTK raises the window, other toolkits
+    /// may only move keyboard focus.
+    pub fn activate(&self, app: &str) -> bool {
+        let windows = self.windows().into_iter().filter(|(bus, _)| {
+            self.pid(bus).and_then(super::get_process_name).as_deref() == Some(app)
+        });
+        for window in windows {
+            let grabbed = self
+                .call::<bool>(&window, "org.a11y.atspi.Component", "GrabFocus", &())
+                .unwrap_or(false);
+            if grabbed {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Whether the focused element of the active window is a password
+    /// field. Apps that expose no tree have none.
+    pub fn focused_is_password(&self) -> bool {
+        self.focused().is_some_and(|node| {
+            self.call::<u32>(&node, ACCESSIBLE, "GetRole", &()) == Some(ROLE_PASSWORD_TEXT)
+        })
+    }
+
+    /// The focused element under the active window, breadth first through
+    /// what's on screen
+    fn focused(&self) -> Option<Node> {
+        let mut queue = std::collections::VecDeque::from([self.active_window()?]);
+        let mut visited = 0;
+        while let Some(node) = queue.pop_front() {
+            visited += 1;
+            if visited > MAX_FOCUS_SEARCH {
+                break;
+            }
+            let state = self.state(&node).unwrap_or(0);
+            if state & (1 << STATE_FOCUSED) != 0 {
+                return Some(node);
+            }
+            if visited == 1 || state & (1 << STATE_SHOWING) != 0 {
+                queue.extend(self.children(&node));
+            }
+        }
+        None
+    }
+
+    /// Top level windows of every app on the bus
+    fn windows(&self) -> Vec<Node> {
+        let root = (REGISTRY.to_string(), OwnedObjectPath::try_from(ROOT).expect("valid path"));
+        self.children(&root).iter().flat_map(|app| self.children(app)).collect()
+    }
+
+    /// The window with the active state
+    fn active_window(&self) -> Option<Node> {
+        self.windows().into_iter().find(|w| self.has_state(w, STATE_ACTIVE))
+    }
+
+    fn children(&self, node: &Node) -> Vec<Node> {
+        self.call(node, ACCESSIBLE, "GetChildren", &()).unwrap_or_default()
+    }
+
+    /// Low 32 bits of the state set, where every state used here is
+    fn state(&self, node: &Node) -> Option<u32> {
+        let states: Vec<u32> = self.call(node, ACCESSIBLE, "GetState", &())?;
+        states.first().copied()
+    }
+
+    fn has_state(&self, node: &Node, state: u32) -> bool {
+        self.state(node).is_some_and(|s| s & (1 << state) != 0)
+    }
+
+    fn name(&self, node: &Node) -> Option<String> {
+        let name: OwnedValue =
+            self.call(node, "org.freedesktop.DBus.Properties", "Get", &(ACCESSIBLE, "Name"))?;
+        name.try_into().ok()
+    }
+
+    /// PID of the app owning a bus name
+    fn pid(&self, bus: &str) -> Option<u32> {
+        let reply = self
+            .conn
+            .call_method(
+                Some("org.freedesktop.DBus"),
+                "/org/freedesktop/DBus",
+                Some("org.freedesktop.DBus"),
+                "GetConnectionUnixProcessID",
+                &(bus,),
+            )
+            .ok()?;
+        reply.body().deserialize().ok()
+    }
+
+    fn call<T>(
+        &self,
+        (bus, path): &Node,
+        interface: &str,
+        method: &str,
+        body: &(impl serde::Serialize + zbus::zvariant::DynamicType),
+    ) -> Option<T>
+    where
+        T: for<'d> serde::Deserialize<'d> + zbus::zvariant::Type,
+    {
+        let reply = self
+            .conn
+            .call_method(Some(bus.as_str()), path, Some(interface), method, body)
+            .ok()?;
+        reply.body().deserialize().ok()
+    }
+}
@@ -0,0 +1,121 @@
+//! File change events via inotify
+//!
+//! inotify isn't recursive, so every directory under the watched ones gets
+//! its own watch, including directories created while recording.
+
+use crate::events::{Event, EventData};
+use crate::recorder::CaptureContext;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+const MASK: u32 = libc::IN_CREATE
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO;
+
+struct Watcher {
+    fd: i32,
+    /// Watch descriptor -> directory
+    dirs: HashMap<i32, PathBuf>,
+}
+
+impl Watcher {
+    fn add(&mut self, dir: &Path) {
+        let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+            return;
+        };
+        let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), MASK) };
+        if wd < 0 {
+            let error = std::io::Error::last_os_error();
+            tracing::warn!(dir = %dir.display(), %error, "Can't watch directory");
+            return;
+        }
+        self.dirs.insert(wd, dir.to_path_buf());
+    }
+
+    fn add_tree(&mut self, dir: &Path) {
+        self.add(dir);
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                self.add_tree(&entry.path());
+            }
+        }
+    }
+}
+
+pub(crate) fn run_file_watcher(ctx: CaptureContext) {
+    let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+    if fd < 0 {
+        tracing::warn!(error = %std::io::Error::last_os_error(), "File watching disabled");
+        return;
+    }
+    let mut watcher = Watcher { fd, dirs: HashMap::new() };
+    for dir in &ctx.config.watch_dirs {
+        watcher.add_tree(dir);
+    }
+    // Created files also get a close-after-write; one event is enough
+    let mut created = HashSet::new();
+    let mut buf = vec![0u8; 16 * 1024];
+
+    while !ctx.stop.load(Ordering::Relaxed) {
+        let mut pfd = libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(&mut pfd, 1, 100) } <= 0 {
+            continue;
+        }
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        if n <= 0 {
+            continue;
+        }
+
+        let mut offset = 0;
+        while offset + size_of::<libc::inotify_event>() <= n as usize {
+            let header: libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr().cast()) };
+            let name_start = offset + size_of::<libc::inotify_event>();
+            offset = name_start + header.len as usize;
+            let name = &buf[name_start..offset.min(n as usize)];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            let Some(dir) = watcher.dirs.get(&header.wd) else {
+                continue;
+            };
+            let path = dir.join(std::ffi::OsStr::from_bytes(name));
+
+            let is_dir = header.mask & libc::IN_ISDIR != 0;
+            if is_dir && header.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                watcher.add_tree(&path);
+            }
+            let o = if header.mask & libc::IN_CREATE != 0 {
+                if !is_dir {
+                    created.insert(path.clone());
+                }
+                'c'
+            } else if header.mask & libc::IN_CLOSE_WRITE != 0 {
+                if created.remove(&path) {
+                    continue;
+                }
+                'm'
+            } else if header.mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+                created.remove(&path);
+                'd'
+            } else if header.mask & libc::IN_MOVED_TO != 0 {
+                'r'
+            } else {
+                continue;
+            };
+            let _ = ctx.tx.try_send(Event {
+                t: ctx.start.elapsed().as_millis() as u64,
+                ts: None,
+                data: EventData::FileChanged { f: path.to_string_lossy().into_owned(), o },
+            });
+        }
+    }
+
+    unsafe { libc::close(fd) };
+}
@@ -0,0 +1,264 @@
+//! xdg-desktop-portal input and screenshots
+//!
+//! The RemoteDesktop portal is how GNOME and KDE let an app inject input
+//! without write access to /dev/uinput: the user agrees once in a dialog,
+//! and the grant's restore token is kept so later replays don't ask again
+//! until it's revoked. A ScreenCast source in the same session gives each
+//! monitor's position, which absolute pointer motion is relative to.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use zbus::blocking::{Connection, Proxy};
+use zbus::zvariant::{DynamicType, ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+const DESKTOP: &str = "org.freedesktop.portal.Desktop";
+const DESKTOP_PATH: &str = "/org/freedesktop/portal/desktop";
+const REMOTE_DESKTOP: &str = "org.freedesktop.portal.RemoteDesktop";
+const SCREEN_CAST: &str = "org.freedesktop.portal.ScreenCast";
+const SCREENSHOT: &str = "org.freedesktop.portal.Screenshot";
+
+// Device and source types, persist modes
+const KEYBOARD: u32 = 1;
+const POINTER: u32 = 2;
+const MONITOR: u32 = 1;
+const PERSIST_UNTIL_REVOKED: u32 = 2;
+
+// Scroll axes
+const VERTICAL: u32 = 0;
+const HORIZONTAL: u32 = 1;
+
+type Options = HashMap<&'static str, Value<'static>>;
+type Results = HashMap<String, OwnedValue>;
+
+/// A monitor shared with the session, where absolute motion lands
+struct Stream {
+    node: u32,
+    position: (i32, i32),
+    size: (i32, i32),
+}
+
+/// A RemoteDesktop session with a keyboard and a pointer
+pub struct RemoteDesktop {
+    conn: Connection,
+    session: OwnedObjectPath,
+    streams: Vec<Stream>,
+}
+
+impl RemoteDesktop {
+    /// Start a session, asking the user unless an earlier grant still holds
+    pub fn start() -> Result<Self> {
+        let conn = Connection::session().context("No D-Bus session bus")?;
+        let created = request(&conn, REMOTE_DESKTOP, "CreateSession", |mut options| {
+            options.insert("session_handle_token", token().into());
+            (options,)
+        })?;
+        let session: String = result(&created, "session_handle")?;
+        let session = OwnedObjectPath::try_from(session)?;
+
+        let restore_token = std::fs::read_to_string(token_path()).ok();
+        request(&conn, REMOTE_DESKTOP, "SelectDevices", |mut options| {
+            options.insert("types", (KEYBOARD | POINTER).into());
+            options.insert("persist_mode", PERSIST_UNTIL_REVOKED.into());
+            if let Some(restore_token) = restore_token {
+                options.insert("restore_token", restore_token.trim().to_string().into());
+            }
+            (session.as_ref(), options)
+        })?;
+        request(&conn, SCREEN_CAST, "SelectSources", |mut options| {
+            options.insert("types", MONITOR.into());
+            options.insert("multiple", true.into());
+            (session.as_ref(), options)
+        })?;
+        let started = request(&conn, REMOTE_DESKTOP, "Start", |options| {
+            (session.as_ref(), "", options)
+        })?;
+
+        if let Ok(restore_token) = result::<String>(&started, "restore_token") {
+            let path = token_path();
+            let saved = path
+                .parent()
+                .map_or(Ok(()), std::fs::create_dir_all)
+                .and_then(|()| std::fs::write(&path, restore_token));
+            if let Err(e) = saved {
+                tracing::debug!(error = %e, "Can't keep the remote desktop grant");
+            }
+        }
+        let streams = result::<Vec<(u32, Results)>>(&started, "streams")
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(node, props)| Stream {
+                node,
+                position: result(&props, "position").unwrap_or((0, 0)),
+                size: result(&props, "size").unwrap_or((0, 0)),
+            })
+            .collect();
+        Ok(Self { conn, session, streams })
+    }
+
+    /// Press or release an evdev keycode
+    pub fn key(&self, code: u16, down: bool) -> Result<()> {
+        self.notify("NotifyKeyboardKeycode", |s, o| (s, o, code as i32, down as u32))
+    }
+
+    /// Press or release the key for an X keysym, which the compositor looks
+    /// up in the current layout
+    pub fn keysym(&self, keysym: u32, down: bool) -> Result<()> {
+        self.notify("NotifyKeyboardKeysym", |s, o| (s, o, keysym as i32, down as u32))
+    }
+
+    /// Press or release an evdev button code (BTN_LEFT...)
+    pub fn button(&self, code: u16, down: bool) -> Result<()> {
+        self.notify("NotifyPointerButton", |s, o| (s, o, code as i32, down as u32))
+    }
+
+    /// Move the pointer to desktop coordinates, on the monitor they're in
+    pub fn move_to(&self, x: i32, y: i32) -> Result<()> {
+        let stream = self
+            .streams
+            .iter()
+            .find(|s| {
+                let ((sx, sy), (w, h)) = (s.position, s.size);
+                (sx..sx + w).contains(&x) && (sy..sy + h).contains(&y)
+            })
+            .or(self.streams.first())
+            .context("The remote desktop session shares no monitor to move the pointer on")?;
+        let (dx, dy) = ((x - stream.position.0) as f64, (y - stream.position.1) as f64);
+        self.notify("NotifyPointerMotionAbsolute", |s, o| (s, o, stream.node, dx, dy))
+    }
+
+    /// Scroll by wheel clicks, positive `dy` up as evdev has it
+    pub fn scroll_lines(&self, dx: i32, dy: i32) -> Result<()> {
+        // The portal's steps go down and right
+        for (axis, steps) in [(VERTICAL, -dy), (HORIZONTAL, dx)] {
+            if steps != 0 {
+                self.notify("NotifyPointerAxisDiscrete", |s, o| (s, o, axis, steps))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Scroll by pixels, positive `dy` up
+    pub fn scroll_pixels(&self, dx: i32, dy: i32) -> Result<()> {
+        self.notify("NotifyPointerAxis", |s, mut o| {
+            o.insert("finish", true.into());
+            (s, o, dx as f64, -dy as f64)
+        })
+    }
+
+    /// Call a RemoteDesktop method; `body` gets the session and empty options
+    fn notify<'s, B>(
+        &'s self,
+        method: &str,
+        body: impl FnOnce(ObjectPath<'s>, Options) -> B,
+    ) -> Result<()>
+    where
+        B: serde::Serialize + DynamicType,
+    {
+        let body = body(self.session.as_ref(), Options::new());
+        self.conn
+            .call_method(Some(DESKTOP), DESKTOP_PATH, Some(REMOTE_DESKTOP), method, &body)
+            .with_context(|| format!("Remote desktop portal refused {}", method))?;
+        Ok(())
+    }
+}
+
+impl Drop for RemoteDesktop {
+    fn drop(&mut self) {
+        let _ = self.conn.call_method(
+            Some(DESKTOP),
+            &self.session,
+            Some("org.freedesktop.portal.Session"),
+            "Close",
+            &(),
+        );
+    }
+}
+
+/// Take a screenshot of every monitor with the Screenshot portal, saved as
+/// PNG; where it was saved
+pub fn screenshot() -> Result<PathBuf> {
+    let conn = Connection::session().context("No D-Bus session bus")?;
+    let taken = request(&conn, SCREENSHOT, "Screenshot", |mut options| {
+        options.insert("interactive", false.into());
+        ("", options)
+    })?;
+    let uri: String = result(&taken, "uri")?;
+    let path = uri.strip_prefix("file://").context("Screenshot saved somewhere not a file")?;
+    Ok(PathBuf::from(percent_decode(path)))
+}
+
+/// Where the remote desktop grant is kept between replays
+fn token_path() -> PathBuf {
+    crate::recorder::recordings_subdir("remote-desktop-token")
+}
+
+/// A handle token unique to this process
+fn token() -> String {
+    static NEXT: AtomicU32 = AtomicU32::new(0);
+    format!("bigbrother{}_{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Call a portal method that answers through a Request object, waiting for
+/// its Response. `body` gets the options with the handle token set.
+fn request<B>(
+    conn: &Connection,
+    interface: &str,
+    method: &str,
+    body: impl FnOnce(Options) -> B,
+) -> Result<Results>
+where
+    B: serde::Serialize + DynamicType,
+{
+    let token = token();
+    let sender = conn.unique_name().context("Not connected to the session bus")?;
+    let sender = sender.trim_start_matches(':').replace('.', "_");
+    // Listen before calling, so the response can't come first
+    let path = format!("{}/request/{}/{}", DESKTOP_PATH, sender, token);
+    let request = Proxy::new(conn, DESKTOP, path.as_str(), "org.freedesktop.portal.Request")?;
+    let mut responses = request.receive_signal("Response")?;
+
+    let mut options = Options::new();
+    options.insert("handle_token", token.into());
+    conn.call_method(Some(DESKTOP), DESKTOP_PATH, Some(interface), method, &body(options))
+        .with_context(|| format!("No {} portal", interface))?;
+
+    let response = responses.next().context("The portal closed the request")?;
+    let (code, results): (u32, Results) = response.body().deserialize()?;
+    match code {
+        0 => Ok(results),
+        1 => bail!("{} was cancelled", method),
+        _ => bail!("{} failed", method),
+    }
+}
+
+fn result<T>(results: &Results, key: &str) -> Result<T>
+where
+    T: TryFrom<OwnedValue>,
+    T::Error: Into<zbus::zvariant::Error>,
+{
+    let value = results.get(key).with_context(|| format!("No {} in the portal's answer", key))?;
+    T::try_from(value.try_clone()?).map_err(|e| anyhow::anyhow!("Bad {}: {}", key, e.into()))
+}
+
+/// `%20` and the like in a file URI
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,481 @@
+//! Linux event capture using evdev
+//!
+//! Reads every keyboard/pointer device under /dev/input in a single poll()
+//! loop so the stop flag is honoured promptly.
+
+use super::{Atspi, Display, DisplayServer};
+use crate::error::RecorderError;
+use crate::events::*;
+use crate::keys::Key as PortableKey;
+use crate::recorder::{
+    CaptureContext, EventSink, GestureTracker, KeyTracker, Permission, PermissionState,
+    PermissionStatus, RecorderConfig,
+};
+use evdev::{Device, InputEventKind, Key, RelativeAxisType};
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+
+/// Max delay between two presses to count as a double click
+const DOUBLE_CLICK_MS: u128 = 400;
+
+pub fn check_permissions() -> PermissionStatus {
+    PermissionStatus::new(
+        Permission::new("accessibility", DisplayServer::detect().is_some().into())
+            .remediation("Run inside an X11 or Wayland session"),
+        Permission::new("input_monitoring", (!input_devices().is_empty()).into())
+            .restart_required()
+            .remediation(
+                "Add yourself to the input group: sudo usermod -aG input $USER, then log in again",
+            ),
+        // Capture tools run as the user
+        Permission::new("screen_recording", PermissionState::Granted),
+    )
+}
+
+pub fn request_permissions(_screen: bool) -> PermissionStatus {
+    // No prompt on Linux - access is granted via the `input` group
+    let status = check_permissions();
+    if !status.input_monitoring {
+        tracing::warn!(
+            "Add yourself to the input group: sudo usermod -aG input $USER, then log in again"
+        );
+    }
+    if std::fs::OpenOptions::new().write(true).open("/dev/uinput").is_err() {
+        tracing::warn!(
+            "Replay needs write access to /dev/uinput, e.g. a udev rule for the input group, \
+             or a RemoteDesktop portal (GNOME and KDE on Wayland)"
+        );
+    }
+    status
+}
+
+/// Open all readable devices that produce keys or pointer motion
+fn input_devices() -> Vec<Device> {
+    evdev::enumerate()
+        .map(|(_, device)| device)
+        .filter(|d| {
+            let keys = d
+                .supported_keys()
+                .map(|k| k.contains(Key::KEY_A) || k.contains(Key::BTN_LEFT))
+                .unwrap_or(false);
+            let rel = d
+                .supported_relative_axes()
+                .map(|r| r.contains(RelativeAxisType::REL_X))
+                .unwrap_or(false);
+            keys || rel
+        })
+        .collect()
+}
+
+/// Spawn the capture threads for a session
+pub(crate) fn start_capture(
+    ctx: CaptureContext,
+) -> Result<Vec<thread::JoinHandle<()>>, RecorderError> {
+    let devices = input_devices();
+    if devices.is_empty() {
+        let message = "No readable input devices in /dev/input (is the user in the input group?)";
+        return Err(RecorderError::PermissionDenied(message.into()));
+    }
+    for d in &devices {
+        set_nonblocking(d).map_err(|e| {
+            RecorderError::TapCreationFailed(format!("Failed to configure input device: {}", e))
+        })?;
+    }
+
+    if ctx.config.redact_secure_input && Atspi::connect().is_none() {
+        tracing::warn!(
+            "No AT-SPI bus to tell password fields apart, so all typed text is redacted; \
+             turn on accessibility or set redact_secure_input to false"
+        );
+    }
+
+    let mut threads = Vec::new();
+
+    // Thread 1: evdev reader
+    let reader_ctx = ctx.clone();
+    threads.push(thread::spawn(move || {
+        run_evdev_reader(devices, reader_ctx);
+    }));
+
+    // Thread 2: File changes in `watch_dirs`
+    if !ctx.config.watch_dirs.is_empty() {
+        let files_ctx = ctx.clone();
+        threads.push(thread::spawn(move || super::files::run_file_watcher(files_ctx)));
+    }
+
+    // Thread 3: App/window observer
+    threads.push(thread::spawn(move || {
+        run_app_observer(ctx.tx, ctx.stop, ctx.start);
+    }));
+
+    Ok(threads)
+}
+
+fn set_nonblocking(device: &Device) -> std::io::Result<()> {
+    let fd = device.as_raw_fd();
+    unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+// ============================================================================
+// evdev Reader
+// ============================================================================
+
+struct ReaderState {
+    tx: EventSink,
+    start: Instant,
+    config: RecorderConfig,
+    display: Option<Display>,
+    /// Desktop size, used to clamp integrated motion
+    bounds: Option<(f64, f64)>,
+    /// Pointer position - from X11 when available, else integrated REL motion
+    pointer: (f64, f64),
+    last_move: (f64, f64),
+    moved: bool,
+    mods: u8,
+    last_click: Option<(Instant, u8, (f64, f64), u8)>,
+    gestures: GestureTracker,
+    keys: KeyTracker,
+}
+
+impl ReaderState {
+    fn t(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+
+    fn send(&self, data: EventData) {
+        let _ = self.tx.try_send(Event { t: self.t(), ts: None, data });
+    }
+
+    fn refresh_pointer(&mut self) {
+        if let Some((x, y)) = self.display.as_ref().and_then(|d| d.pointer()) {
+            self.pointer = (x as f64, y as f64);
+        }
+    }
+
+    fn move_by(&mut self, dx: f64, dy: f64) {
+        let (max_x, max_y) = self.bounds.unwrap_or((f64::MAX, f64::MAX));
+        self.pointer.0 = (self.pointer.0 + dx).clamp(0.0, max_x);
+        self.pointer.1 = (self.pointer.1 + dy).clamp(0.0, max_y);
+        self.moved = true;
+    }
+
+    fn on_button(&mut self, b: u8) {
+        // The click may move focus; what was typed goes first
+        self.tx.flush_text();
+        self.refresh_pointer();
+        let now = Instant::now();
+        let (x, y) = self.pointer;
+        let n = match self.last_click {
+            Some((at, lb, (lx, ly), ln))
+                if lb == b
+                    && now.duration_since(at).as_millis() < DOUBLE_CLICK_MS
+                    && (lx - x).abs() < 4.0
+                    && (ly - y).abs() < 4.0 =>
+            {
+                ln.saturating_add(1)
+            }
+            _ => 1,
+        };
+        self.last_click = Some((now, b, (x, y), n));
+        // No accessibility backend to resolve the clicked element on Linux
+        let press = self.gestures.press(self.t(), (x as i32, y as i32), b, n, self.mods, None);
+        if let Some(event) = press {
+            let _ = self.tx.try_send(event);
+        }
+    }
+
+    fn on_release(&mut self, b: u8) {
+        self.refresh_pointer();
+        let (x, y) = self.pointer;
+        if let Some(event) = self.gestures.release(self.t(), (x as i32, y as i32), b) {
+            let _ = self.tx.try_send(event);
+        }
+    }
+
+    fn on_key(&mut self, code: u16) {
+        let mods = Modifiers(self.mods);
+        let key = PortableKey::from_evdev(code);
+        let typed = !(mods.any_modifier() || mods.0 & Modifiers::OPT != 0)
+            && keycode_to_char(code, mods).is_some_and(|c| self.tx.type_char(c));
+        if !typed {
+            self.send(EventData::Key { k: key, m: self.mods });
+        }
+        self.keys.press(self.t(), key, !typed);
+    }
+
+    fn on_key_up(&mut self, code: u16) {
+        let key = PortableKey::from_evdev(code);
+        if let Some(data) = self.keys.release(self.t(), key, self.mods) {
+            self.send(data);
+        }
+    }
+
+    fn on_sync(&mut self) {
+        if !self.moved {
+            return;
+        }
+        self.moved = false;
+        self.refresh_pointer();
+        let (x, y) = self.pointer;
+        if self.gestures.moved((x as i32, y as i32)) {
+            self.last_move = (x, y);
+            return;
+        }
+        let dx = x - self.last_move.0;
+        let dy = y - self.last_move.1;
+        if (dx * dx + dy * dy).sqrt() >= self.config.mouse_move_threshold {
+            self.last_move = (x, y);
+            self.send(EventData::Move {
+                x: x as i32,
+                y: y as i32,
+            });
+        }
+    }
+}
+
+fn modifier_bit(key: Key) -> Option<u8> {
+    match key {
+        Key::KEY_LEFTSHIFT | Key::KEY_RIGHTSHIFT => Some(Modifiers::SHIFT),
+        Key::KEY_LEFTCTRL | Key::KEY_RIGHTCTRL => Some(Modifiers::CTRL),
+        Key::KEY_LEFTALT | Key::KEY_RIGHTALT => Some(Modifiers::OPT),
+        Key::KEY_LEFTMETA | Key::KEY_RIGHTMETA => Some(Modifiers::CMD),
+        _ => None,
+    }
+}
+
+fn run_evdev_reader(mut devices: Vec<Device>, ctx: CaptureContext) {
+    let display = Display::connect();
+    let bounds = display
+        .as_ref()
+        .and_then(|d| d.size())
+        .map(|(w, h)| (w.saturating_sub(1) as f64, h.saturating_sub(1) as f64));
+    let mut state = ReaderState {
+        tx: ctx.tx,
+        start: ctx.start,
+        gestures: GestureTracker::new(&ctx.config),
+        keys: KeyTracker::new(&ctx.config),
+        config: ctx.config,
+        display,
+        bounds,
+        // Wayland won't tell us where the pointer starts - assume the center
+        pointer: bounds.map(|(w, h)| (w / 2.0, h / 2.0)).unwrap_or_default(),
+        last_move: (0.0, 0.0),
+        moved: false,
+        mods: 0,
+        last_click: None,
+    };
+    state.refresh_pointer();
+    state.last_move = state.pointer;
+
+    let mut fds: Vec<libc::pollfd> = devices
+        .iter()
+        .map(|d| libc::pollfd {
+            fd: d.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+
+    while !ctx.stop.load(Ordering::Relaxed) {
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, 50) };
+
+        if ready > 0 {
+            for (i, device) in devices.iter_mut().enumerate() {
+                if fds[i].revents & libc::POLLIN == 0 {
+                    continue;
+                }
+                let Ok(events) = device.fetch_events() else {
+                    continue;
+                };
+                let events: Vec<_> = events.collect();
+                for ev in events {
+                    handle_event(&mut state, ev.kind(), ev.value());
+                }
+            }
+        }
+
+        // Check text buffer timeout
+        state.tx.flush_idle_text();
+    }
+
+    // Final flush
+    state.tx.flush_text();
+}
+
+fn handle_event(state: &mut ReaderState, kind: InputEventKind, value: i32) {
+    match kind {
+        InputEventKind::Key(key) => {
+            if let Some(bit) = modifier_bit(key) {
+                let down = value != 0;
+                if value != 2 {
+                    let key = PortableKey::from_evdev(key.code());
+                    let m = state.mods & !bit;
+                    if let Some(data) = state.keys.modifier(state.t(), key, down, m) {
+                        state.send(data);
+                    }
+                }
+                if down {
+                    state.mods |= bit;
+                } else {
+                    state.mods &= !bit;
+                }
+                return;
+            }
+            if key == Key::KEY_CAPSLOCK && value == 1 {
+                state.mods ^= Modifiers::CAPS;
+                return;
+            }
+            let button = match key {
+                Key::BTN_LEFT => Some(0),
+                Key::BTN_RIGHT => Some(1),
+                Key::BTN_MIDDLE => Some(2),
+                _ => None,
+            };
+            // 1 = press, 2 = autorepeat, 0 = release
+            match (button, value) {
+                (Some(b), 1) => state.on_button(b),
+                (Some(b), 0) => state.on_release(b),
+                (None, 1 | 2) if key.code() < Key::BTN_0.code() => state.on_key(key.code()),
+                (None, 0) if key.code() < Key::BTN_0.code() => state.on_key_up(key.code()),
+                _ => {}
+            }
+        }
+        InputEventKind::RelAxis(axis) => match axis {
+            RelativeAxisType::REL_X => state.move_by(value as f64, 0.0),
+            RelativeAxisType::REL_Y => state.move_by(0.0, value as f64),
+            RelativeAxisType::REL_WHEEL | RelativeAxisType::REL_HWHEEL => {
+                state.refresh_pointer();
+                let (dx, dy) = if axis == RelativeAxisType::REL_WHEEL {
+                    (0, value as i16)
+                } else {
+                    (value as i16, 0)
+                };
+                state.send(EventData::Scroll {
+                    x: state.pointer.0 as i32,
+                    y: state.pointer.1 as i32,
+                    dx,
+                    dy,
+                    px: false,
+                });
+            }
+            _ => {}
+        },
+        InputEventKind::Synchronization(_) => state.on_sync(),
+        _ => {}
+    }
+}
+
+/// Whether keyboard input is going to a password field, by AT-SPI role.
+/// Without an accessibility bus that can't be told, so it's assumed to be.
+pub(crate) fn secure_input_active() -> bool {
+    thread_local! {
+        // Connected once on the checking thread
+        static ATSPI: Option<Atspi> = Atspi::connect();
+    }
+    ATSPI.with(|atspi| atspi.as_ref().is_none_or(|a| a.focused_is_password()))
+}
+
+// ============================================================================
+// App Observer
+// ============================================================================
+
+fn run_app_observer(tx: EventSink, stop: Arc<AtomicBool>, start: Instant) {
+    let Some(display) = Display::connect() else {
+        tracing::warn!(
+            "No X11 display, Wayland compositor IPC or AT-SPI, app and window events disabled"
+        );
+        return;
+    };
+
+    let mut last_app: Option<String> = None;
+    let mut last_pid: u32 = 0;
+    let mut last_window: Option<String> = None;
+
+    while !stop.load(Ordering::Relaxed) {
+        if let Some((name, pid, title)) = display.focused_app() {
+            let app_changed = last_app.as_ref() != Some(&name) || last_pid != pid;
+
+            if app_changed {
+                let _ = tx.try_send(Event {
+                    t: start.elapsed().as_millis() as u64,
+                    ts: None,
+                    data: EventData::App { n: name.clone(), p: pid as i32 },
+                });
+                last_app = Some(name.clone());
+                last_pid = pid;
+            }
+
+            if title != last_window || app_changed {
+                // Tab switches change the title, so this tracks the active tab
+                let url = super::browser_url(&name);
+                let _ = tx.try_send(Event {
+                    t: start.elapsed().as_millis() as u64,
+                    ts: None,
+                    data: EventData::Window {
+                        a: name,
+                        w: title.clone(),
+                        u: url,
+                    },
+                });
+                last_window = title;
+            }
+        }
+
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+}
+
+// ============================================================================
+// Keycode Mapping (evdev codes, US layout)
+// ============================================================================
+
+fn keycode_to_char(code: u16, mods: Modifiers) -> Option<char> {
+    let shift = mods.0 & Modifiers::SHIFT != 0;
+    let caps = mods.0 & Modifiers::CAPS != 0;
+
+    let letter = |c: char| -> char {
+        if shift ^ caps {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        }
+    };
+    let pick = |plain: char, shifted: char| if shift { shifted } else { plain };
+
+    Some(match code {
+        // Letters
+        16 => letter('q'), 17 => letter('w'), 18 => letter('e'), 19 => letter('r'),
+        20 => letter('t'), 21 => letter('y'), 22 => letter('u'), 23 => letter('i'),
+        24 => letter('o'), 25 => letter('p'), 30 => letter('a'), 31 => letter('s'),
+        32 => letter('d'), 33 => letter('f'), 34 => letter('g'), 35 => letter('h'),
+        36 => letter('j'), 37 => letter('k'), 38 => letter('l'), 44 => letter('z'),
+        45 => letter('x'), 46 => letter('c'), 47 => letter('v'), 48 => letter('b'),
+        49 => letter('n'), 50 => letter('m'),
+        // Numbers
+        2 => pick('1', '!'), 3 => pick('2', '@'), 4 => pick('3', '#'),
+        5 => pick('4', '$'), 6 => pick('5', '%'), 7 => pick('6', '^'),
+        8 => pick('7', '&'), 9 => pick('8', '*'), 10 => pick('9', '('),
+        11 => pick('0', ')'),
+        // Punctuation
+        12 => pick('-', '_'), 13 => pick('=', '+'), 26 => pick('[', '{'),
+        27 => pick(']', '}'), 39 => pick(';', ':'), 40 => pick('\'', '"'),
+        41 => pick('`', '~'), 43 => pick('\\', '|'), 51 => pick(',', '<'),
+        52 => pick('.', '>'), 53 => pick('/', '?'),
+        // Whitespace
+        28 => '\n',
+        15 => '\t',
+        57 => ' ',
+        // Backspace - special handling
+        14 => '\x08',
+        _ => return None,
+    })
+}
@@ -0,0 +1,414 @@
+//! Linux input injection for replay
+//!
+//! Events go through uinput virtual devices. The kernel hands them to the
+//! display server like real hardware, so the same path works under X11 and
+//! any Wayland compositor without a portal session. Recorded keys are mapped
+//! to evdev codes. On Wayland, the RemoteDesktop portal comes first when
+//! there is one (GNOME, KDE), as it doesn't need write access to
+//! /dev/uinput and can type text no key on the layout makes.
+
+use super::portal::RemoteDesktop;
+use super::{Display, DisplayServer, X11};
+use crate::events::Modifiers;
+use crate::replay::Injector;
+use anyhow::{Context, Result};
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{
+    AbsInfo, AbsoluteAxisType, AttributeSet, EventType, InputEvent, Key, RelativeAxisType,
+    UinputAbsSetup,
+};
+use parking_lot::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Virtual keyboard and absolute pointer
+struct Devices {
+    keyboard: VirtualDevice,
+    pointer: VirtualDevice,
+}
+
+impl Devices {
+    fn create() -> Result<Self> {
+        let (width, height) = Display::connect()
+            .and_then(|d| d.size())
+            .context("Cannot determine the desktop size for pointer injection")?;
+
+        let mut keys = AttributeSet::<Key>::new();
+        for code in 1..Key::BTN_0.code() {
+            keys.insert(Key::new(code));
+        }
+        let keyboard = VirtualDeviceBuilder::new()
+            .context("Cannot open /dev/uinput (replay needs write access)")?
+            .name("bigbrother keyboard")
+            .with_keys(&keys)?
+            .build()?;
+
+        // An absolute pointer spanning the desktop, like a VM tablet, so
+        // positions don't depend on pointer acceleration
+        let mut buttons = AttributeSet::<Key>::new();
+        for button in [Key::BTN_LEFT, Key::BTN_RIGHT, Key::BTN_MIDDLE] {
+            buttons.insert(button);
+        }
+        let mut wheels = AttributeSet::<RelativeAxisType>::new();
+        wheels.insert(RelativeAxisType::REL_WHEEL);
+        wheels.insert(RelativeAxisType::REL_HWHEEL);
+        let axis = |code, max: u32| {
+            UinputAbsSetup::new(code, AbsInfo::new(0, 0, max.saturating_sub(1) as i32, 0, 0, 0))
+        };
+        let pointer = VirtualDeviceBuilder::new()?
+            .name("bigbrother pointer")
+            .with_keys(&buttons)?
+            .with_absolute_axis(&axis(AbsoluteAxisType::ABS_X, width))?
+            .with_absolute_axis(&axis(AbsoluteAxisType::ABS_Y, height))?
+            .with_relative_axes(&wheels)?
+            .build()?;
+
+        // Give the display server time to pick up the new devices
+        std::thread::sleep(Duration::from_millis(300));
+
+        Ok(Self { keyboard, pointer })
+    }
+}
+
+/// Where injected input goes
+enum Backend {
+    Uinput(Devices),
+    Portal(RemoteDesktop),
+}
+
+impl Backend {
+    /// The portal on Wayland if it starts, uinput otherwise
+    fn open() -> Result<Self> {
+        if DisplayServer::detect() == Some(DisplayServer::Wayland) {
+            match RemoteDesktop::start() {
+                Ok(portal) => return Ok(Self::Portal(portal)),
+                // wlroots compositors have no RemoteDesktop portal
+                Err(e) => tracing::debug!(error = %e, "No remote desktop portal, using uinput"),
+            }
+        }
+        Ok(Self::Uinput(Devices::create()?))
+    }
+
+    /// Press or release a key or button
+    fn key(&mut self, key: Key, down: bool) -> Result<()> {
+        match self {
+            Self::Uinput(d) => {
+                let device = match key.code() < Key::BTN_0.code() {
+                    true => &mut d.keyboard,
+                    false => &mut d.pointer,
+                };
+                device.emit(&[key_event(key, down)])?;
+            }
+            Self::Portal(p) if key.code() < Key::BTN_0.code() => p.key(key.code(), down)?,
+            Self::Portal(p) => p.button(key.code(), down)?,
+        }
+        Ok(())
+    }
+
+    fn move_to(&mut self, x: i32, y: i32) -> Result<()> {
+        match self {
+            Self::Uinput(d) => d.pointer.emit(&[
+                InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, x.max(0)),
+                InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_Y.0, y.max(0)),
+            ])?,
+            Self::Portal(p) => p.move_to(x, y)?,
+        }
+        Ok(())
+    }
+
+    /// Scroll by `dx`, `dy` pixels or lines, positive up and right
+    fn scroll(&mut self, dx: i32, dy: i32, pixels: bool) -> Result<()> {
+        // The wheel only takes whole lines, but a pixel scroll never rounds
+        // down to nothing
+        let lines = |d: i32| match (pixels, d / crate::platform::PIXELS_PER_LINE) {
+            (false, _) => d,
+            (true, 0) => d.signum(),
+            (true, n) => n,
+        };
+        match self {
+            Self::Uinput(d) => d.pointer.emit(&[
+                InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_WHEEL.0, lines(dy)),
+                InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_HWHEEL.0, lines(dx)),
+            ])?,
+            Self::Portal(p) if pixels => p.scroll_pixels(dx, dy)?,
+            Self::Portal(p) => p.scroll_lines(dx, dy)?,
+        }
+        Ok(())
+    }
+
+    /// Type `text`: by key where the US layout has one, by keysym through
+    /// the portal otherwise
+    fn type_text(&mut self, text: &str) -> Result<()> {
+        if let Self::Uinput(_) = self {
+            let mut untypable: Vec<char> =
+                text.chars().filter(|&c| char_to_keycode(c).is_none()).collect();
+            if !untypable.is_empty() {
+                untypable.sort_unstable();
+                untypable.dedup();
+                let chars: String = untypable.into_iter().collect();
+                anyhow::bail!("No key types {:?} on a US keyboard layout", chars);
+            }
+        }
+        for c in text.chars() {
+            match (char_to_keycode(c), &*self) {
+                (Some((keycode, shift)), _) => {
+                    let key = Key::new(keycode);
+                    if shift {
+                        self.key(Key::KEY_LEFTSHIFT, true)?;
+                    }
+                    self.key(key, true)?;
+                    self.key(key, false)?;
+                    if shift {
+                        self.key(Key::KEY_LEFTSHIFT, false)?;
+                    }
+                }
+                (None, Self::Portal(p)) => {
+                    p.keysym(keysym(c), true)?;
+                    p.keysym(keysym(c), false)?;
+                }
+                (None, Self::Uinput(_)) => unreachable!("checked above"),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// X keysym for a character: Latin-1 as is, the rest as Unicode keysyms
+fn keysym(c: char) -> u32 {
+    match c {
+        '\n' => 0xff0d,
+        '\t' => 0xff09,
+        '\x08' => 0xff08,
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u32,
+        _ => 0x0100_0000 | c as u32,
+    }
+}
+
+/// Injects input through uinput or the RemoteDesktop portal, opened on
+/// first use
+#[derive(Default)]
+pub struct NativeInjector {
+    backend: Mutex<Option<Backend>>,
+    /// For pointer and key state, only on X11: Wayland doesn't share them
+    x11: OnceLock<Option<X11>>,
+}
+
+impl NativeInjector {
+    fn with_backend(&self, f: impl FnOnce(&mut Backend) -> Result<()>) -> Result<()> {
+        let mut backend = self.backend.lock();
+        if backend.is_none() {
+            *backend = Some(Backend::open()?);
+        }
+        f(backend.as_mut().expect("backend opened above"))
+    }
+
+    fn x11(&self) -> Option<&X11> {
+        self.x11
+            .get_or_init(|| match DisplayServer::detect() {
+                Some(DisplayServer::X11) => X11::connect(),
+                _ => None,
+            })
+            .as_ref()
+    }
+
+    /// Run `f` with the modifier keys in packed `Modifiers` held down,
+    /// releasing them even if it fails
+    fn with_modifiers(&self, modifiers: u8, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        let mods = modifier_keys(modifiers);
+        if mods.is_empty() {
+            return f();
+        }
+        self.with_backend(|b| mods.iter().try_for_each(|m| b.key(*m, true)))?;
+        let result = f();
+        self.with_backend(|b| mods.iter().rev().try_for_each(|m| b.key(*m, false)))?;
+        result
+    }
+
+    /// Press and release an evdev keycode
+    fn press(&self, keycode: u16, modifiers: u8) -> Result<()> {
+        let mods = modifier_keys(modifiers);
+        let key = Key::new(keycode);
+        self.with_backend(|b| {
+            for m in &mods {
+                b.key(*m, true)?;
+            }
+            b.key(key, true)?;
+            b.key(key, false)?;
+            for m in mods.iter().rev() {
+                b.key(*m, false)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+fn key_event(key: Key, down: bool) -> InputEvent {
+    InputEvent::new(EventType::KEY, key.code(), down as i32)
+}
+
+fn modifier_keys(modifiers: u8) -> Vec<Key> {
+    [
+        (Modifiers::SHIFT, Key::KEY_LEFTSHIFT),
+        (Modifiers::CTRL, Key::KEY_LEFTCTRL),
+        (Modifiers::OPT, Key::KEY_LEFTALT),
+        (Modifiers::CMD, Key::KEY_LEFTMETA),
+    ]
+    .into_iter()
+    .filter(|(bit, _)| modifiers & bit != 0)
+    .map(|(_, key)| key)
+    .collect()
+}
+
+impl Injector for NativeInjector {
+    fn click(&self, x: i32, y: i32, button: u8, clicks: u8, modifiers: u8) -> Result<()> {
+        self.move_to(x, y)?;
+        let button = match button {
+            1 => Key::BTN_RIGHT,
+            2 => Key::BTN_MIDDLE,
+            _ => Key::BTN_LEFT,
+        };
+
+        self.with_modifiers(modifiers, || {
+            for _ in 0..clicks.max(1) {
+                self.with_backend(|b| {
+                    b.key(button, true)?;
+                    b.key(button, false)
+                })?;
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Ok(())
+        })
+    }
+
+    fn move_to(&self, x: i32, y: i32) -> Result<()> {
+        self.with_backend(|b| b.move_to(x, y))
+    }
+
+    fn drag(
+        &self,
+        path: &[(i32, i32)],
+        button: u8,
+        modifiers: u8,
+        duration: Duration,
+    ) -> Result<()> {
+        let Some((&(x, y), rest)) = path.split_first() else {
+            return Ok(());
+        };
+        let button = match button {
+            1 => Key::BTN_RIGHT,
+            2 => Key::BTN_MIDDLE,
+            _ => Key::BTN_LEFT,
+        };
+        let step = duration / rest.len().max(1) as u32;
+
+        self.move_to(x, y)?;
+        self.with_modifiers(modifiers, || {
+            self.with_backend(|b| b.key(button, true))?;
+            for &(x, y) in rest {
+                std::thread::sleep(step);
+                self.move_to(x, y)?;
+            }
+            self.with_backend(|b| b.key(button, false))
+        })
+    }
+
+    fn scroll(&self, x: i32, y: i32, dx: i16, dy: i16, pixels: bool) -> Result<()> {
+        self.move_to(x, y)?;
+        self.with_backend(|b| b.scroll(dx as i32, dy as i32, pixels))
+    }
+
+    fn key(&self, key: crate::keys::Key, modifiers: u8) -> Result<()> {
+        self.press(key.to_evdev(), modifiers)
+    }
+
+    // Modifiers held alongside are recorded as their own key events
+    fn key_down(&self, key: crate::keys::Key, _modifiers: u8) -> Result<()> {
+        let key = Key::new(key.to_evdev());
+        self.with_backend(|b| b.key(key, true))
+    }
+
+    fn key_up(&self, key: crate::keys::Key, _modifiers: u8) -> Result<()> {
+        let key = Key::new(key.to_evdev());
+        self.with_backend(|b| b.key(key, false))
+    }
+
+    /// A report per key change with nothing waited between them; replay's
+    /// key delay slows it down for apps that drop keys. Through uinput,
+    /// only what the US layout has a key for can be typed; text with
+    /// anything else fails, naming it, instead of going in with characters
+    /// left out.
+    fn type_text(&self, text: &str) -> Result<()> {
+        self.with_backend(|b| b.type_text(text))
+    }
+
+    fn activate(&self, app: &str) -> Result<()> {
+        let display = Display::connect().context("No display to activate apps on")?;
+        if !display.activate(app) {
+            anyhow::bail!("No window of {} to bring to the front", app);
+        }
+        Ok(())
+    }
+
+    fn frontmost_app(&self) -> Option<String> {
+        super::get_focused_app().map(|(name, _, _)| name)
+    }
+
+    fn window_title(&self) -> Option<String> {
+        super::get_focused_app().and_then(|(_, _, title)| title)
+    }
+
+    fn cursor_position(&self) -> Option<(i32, i32)> {
+        self.x11()?.pointer()
+    }
+
+    fn escape_pressed(&self) -> bool {
+        // X keycodes are evdev codes offset by 8
+        let keycode = crate::keys::Key::Escape.to_evdev() + 8;
+        self.x11().is_some_and(|x11| x11.key_down(keycode as u8))
+    }
+
+    fn capture_screen(&self, path: &std::path::Path) -> Result<()> {
+        super::capture_screen(path, crate::platform::REPLAY_SCREENSHOT_SIZE)
+    }
+}
+
+/// Convert char to (evdev keycode, needs_shift), US layout
+fn char_to_keycode(c: char) -> Option<(u16, bool)> {
+    const LETTERS: &[u16; 26] = &[
+        30, 48, 46, 32, 18, 33, 34, 35, 23, 36, 37, 38, 50, 49, 24, 25, 16, 19, 31, 20, 22, 47,
+        17, 45, 21, 44,
+    ];
+    if c.is_ascii_alphabetic() {
+        let i = (c.to_ascii_lowercase() as u8 - b'a') as usize;
+        return Some((LETTERS[i], c.is_ascii_uppercase()));
+    }
+
+    Some(match c {
+        '1' => (2, false), '!' => (2, true),
+        '2' => (3, false), '@' => (3, true),
+        '3' => (4, false), '#' => (4, true),
+        '4' => (5, false), '$' => (5, true),
+        '5' => (6, false), '%' => (6, true),
+        '6' => (7, false), '^' => (7, true),
+        '7' => (8, false), '&' => (8, true),
+        '8' => (9, false), '*' => (9, true),
+        '9' => (10, false), '(' => (10, true),
+        '0' => (11, false), ')' => (11, true),
+        '-' => (12, false), '_' => (12, true),
+        '=' => (13, false), '+' => (13, true),
+        '[' => (26, false), '{' => (26, true),
+        ']' => (27, false), '}' => (27, true),
+        ';' => (39, false), ':' => (39, true),
+        '\'' => (40, false), '"' => (40, true),
+        '`' => (41, false), '~' => (41, true),
+        '\\' => (43, false), '|' => (43, true),
+        ',' => (51, false), '<' => (51, true),
+        '.' => (52, false), '>' => (52, true),
+        '/' => (53, false), '?' => (53, true),
+        '\n' => (28, false),
+        '\t' => (15, false),
+        ' ' => (57, false),
+        '\x08' => (14, false),
+        _ => return None,
+    })
+}
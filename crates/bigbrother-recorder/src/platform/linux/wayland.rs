@@ -0,0 +1,149 @@
+//! Wayland window tracking
+//!
+//! Wayland has no protocol for asking which window is focused, so we go
+//! through the compositor's own IPC where one exists (sway, Hyprland).
+//! Other compositors fall back to AT-SPI, then XWayland, which only sees
+//! X11 clients.
+
+use serde_json::Value;
+use std::process::Command;
+
+/// Compositors we know how to query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compositor {
+    Sway,
+    Hyprland,
+}
+
+impl Compositor {
+    /// Detect the running compositor from its IPC environment variables
+    pub fn detect() -> Option<Self> {
+        if std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE").is_some() {
+            Some(Self::Hyprland)
+        } else if std::env::var_os("SWAYSOCK").is_some() {
+            Some(Self::Sway)
+        } else {
+            None
+        }
+    }
+
+    /// Get current focused app info: (process name, pid, window title)
+    pub fn focused_app(&self) -> Option<(String, u32, Option<String>)> {
+        let (pid, title) = match self {
+            Self::Sway => {
+                let tree = query("swaymsg", &["-t", "get_tree"])?;
+                let node = find_focused(&tree)?;
+                (node["pid"].as_u64()?, node["name"].as_str().map(String::from))
+            }
+            Self::Hyprland => {
+                let window = query("hyprctl", &["activewindow", "-j"])?;
+                (window["pid"].as_u64()?, window["title"].as_str().map(String::from))
+            }
+        };
+        let pid = pid as u32;
+        let name = super::get_process_name(pid)?;
+        Some((name, pid, title.filter(|t| !t.is_empty())))
+    }
+
+    /// Focus a window of the app with this process name; false if none
+    /// was found
+    pub fn activate(&self, app: &str) -> bool {
+        let is_app = |pid: Option<u64>| {
+            pid.and_then(|p| super::get_process_name(p as u32)).as_deref() == Some(app)
+        };
+        let command = match self {
+            Self::Sway => {
+                let Some(tree) = query("swaymsg", &["-t", "get_tree"]) else {
+                    return false;
+                };
+                let Some(id) = find_node(&tree, &|n| is_app(n["pid"].as_u64()))
+                    .and_then(|n| n["id"].as_u64())
+                else {
+                    return false;
+                };
+                Command::new("swaymsg").arg(format!("[con_id={}] focus", id)).status()
+            }
+            Self::Hyprland => {
+                let clients = query("hyprctl", &["clients", "-j"]);
+                let Some(pid) = clients
+                    .as_ref()
+                    .and_then(|c| c.as_array())
+                    .and_then(|c| c.iter().find(|c| is_app(c["pid"].as_u64())))
+                    .and_then(|c| c["pid"].as_u64())
+                else {
+                    return false;
+                };
+                Command::new("hyprctl")
+                    .args(["dispatch", "focuswindow", &format!("pid:{}", pid)])
+                    .status()
+            }
+        };
+        command.is_ok_and(|status| status.success())
+    }
+
+    /// Size of the bounding box around all outputs, in logical pixels
+    pub fn desktop_size(&self) -> Option<(u32, u32)> {
+        let outputs = match self {
+            Self::Sway => query("swaymsg", &["-t", "get_outputs"])?,
+            Self::Hyprland => query("hyprctl", &["monitors", "-j"])?,
+        };
+
+        let mut size = (0u32, 0u32);
+        for output in outputs.as_array()? {
+            let (x, y, w, h) = match self {
+                Self::Sway => {
+                    if output["active"].as_bool() == Some(false) {
+                        continue;
+                    }
+                    let rect = &output["rect"];
+                    (
+                        rect["x"].as_f64()?,
+                        rect["y"].as_f64()?,
+                        rect["width"].as_f64()?,
+                        rect["height"].as_f64()?,
+                    )
+                }
+                Self::Hyprland => {
+                    // Monitor sizes are physical pixels, positions are logical
+                    let scale = output["scale"].as_f64().unwrap_or(1.0).max(0.1);
+                    (
+                        output["x"].as_f64()?,
+                        output["y"].as_f64()?,
+                        output["width"].as_f64()? / scale,
+                        output["height"].as_f64()? / scale,
+                    )
+                }
+            };
+            size.0 = size.0.max((x + w) as u32);
+            size.1 = size.1.max((y + h) as u32);
+        }
+
+        (size.0 > 0 && size.1 > 0).then_some(size)
+    }
+}
+
+/// Run a compositor IPC command and parse its JSON output
+fn query(program: &str, args: &[&str]) -> Option<Value> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+/// Depth-first search for the focused node in a sway tree
+fn find_focused(node: &Value) -> Option<&Value> {
+    find_node(node, &|n| n["focused"].as_bool() == Some(true) && n["pid"].is_u64())
+}
+
+/// Depth-first search for a node in a sway tree
+fn find_node<'a>(node: &'a Value, matches: &dyn Fn(&Value) -> bool) -> Option<&'a Value> {
+    if matches(node) {
+        return Some(node);
+    }
+    ["nodes", "floating_nodes"]
+        .iter()
+        .filter_map(|key| node[*key].as_array())
+        .flatten()
+        .find_map(|child| find_node(child, matches))
+}
@@ -0,0 +1,178 @@
+//! File change events via FSEvents
+//!
+//! The stream is created with per-file events and scheduled on this thread's
+//! run loop, which is spun in short slices so the stop flag is honoured.
+
+use crate::events::{Event, EventData};
+use crate::recorder::{CaptureContext, EventSink};
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+type CFRef = *const c_void;
+
+#[repr(C)]
+struct FSEventStreamContext {
+    version: isize,
+    info: *mut c_void,
+    retain: *const c_void,
+    release: *const c_void,
+    copy_description: *const c_void,
+}
+
+type FSEventStreamCallback = extern "C" fn(
+    stream: CFRef,
+    info: *mut c_void,
+    count: usize,
+    paths: *mut c_void,
+    flags: *const u32,
+    ids: *const u64,
+);
+
+const NOW: u64 = u64::MAX; // kFSEventStreamEventIdSinceNow
+const CREATE_NO_DEFER: u32 = 0x02;
+const CREATE_FILE_EVENTS: u32 = 0x10;
+const ITEM_CREATED: u32 = 0x100;
+const ITEM_RENAMED: u32 = 0x800;
+const ITEM_MODIFIED: u32 = 0x1000;
+const UTF8: u32 = 0x0800_0100; // kCFStringEncodingUTF8
+
+#[link(name = "CoreServices", kind = "framework")]
+extern "C" {
+    fn FSEventStreamCreate(
+        allocator: CFRef,
+        callback: FSEventStreamCallback,
+        context: *const FSEventStreamContext,
+        paths: CFRef,
+        since: u64,
+        latency: f64,
+        flags: u32,
+    ) -> CFRef;
+    fn FSEventStreamScheduleWithRunLoop(stream: CFRef, run_loop: CFRef, mode: CFRef);
+    fn FSEventStreamStart(stream: CFRef) -> u8;
+    fn FSEventStreamStop(stream: CFRef);
+    fn FSEventStreamInvalidate(stream: CFRef);
+    fn FSEventStreamRelease(stream: CFRef);
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    static kCFRunLoopDefaultMode: CFRef;
+    static kCFTypeArrayCallBacks: c_void;
+    fn CFRunLoopGetCurrent() -> CFRef;
+    fn CFRunLoopRunInMode(mode: CFRef, seconds: f64, return_after_source: u8) -> i32;
+    fn CFStringCreateWithCString(allocator: CFRef, s: *const c_char, encoding: u32) -> CFRef;
+    fn CFArrayCreate(
+        allocator: CFRef,
+        values: *const CFRef,
+        count: isize,
+        callbacks: *const c_void,
+    ) -> CFRef;
+    fn CFRelease(cf: CFRef);
+}
+
+/// Handed to the callback through the stream context
+struct Watch {
+    tx: EventSink,
+    start: Instant,
+}
+
+pub(crate) fn run_file_watcher(ctx: CaptureContext) {
+    let watch = Watch {
+        tx: ctx.tx.clone(),
+        start: ctx.start,
+    };
+    let context = FSEventStreamContext {
+        version: 0,
+        info: &watch as *const Watch as *mut c_void,
+        retain: std::ptr::null(),
+        release: std::ptr::null(),
+        copy_description: std::ptr::null(),
+    };
+
+    unsafe {
+        let paths: Vec<CFRef> = ctx
+            .config
+            .watch_dirs
+            .iter()
+            .filter_map(|dir| CString::new(dir.as_os_str().as_bytes()).ok())
+            .map(|dir| CFStringCreateWithCString(std::ptr::null(), dir.as_ptr(), UTF8))
+            .filter(|s| !s.is_null())
+            .collect();
+        let array = CFArrayCreate(
+            std::ptr::null(),
+            paths.as_ptr(),
+            paths.len() as isize,
+            &kCFTypeArrayCallBacks,
+        );
+        for s in paths {
+            CFRelease(s);
+        }
+        let stream = FSEventStreamCreate(
+            std::ptr::null(),
+            on_events,
+            &context,
+            array,
+            NOW,
+            0.1,
+            CREATE_NO_DEFER | CREATE_FILE_EVENTS,
+        );
+        CFRelease(array);
+        if stream.is_null() {
+            tracing::warn!("File watching disabled: can't create an FSEvents stream");
+            return;
+        }
+
+        FSEventStreamScheduleWithRunLoop(stream, CFRunLoopGetCurrent(), kCFRunLoopDefaultMode);
+        if FSEventStreamStart(stream) == 0 {
+            tracing::warn!("File watching disabled: can't start the FSEvents stream");
+        } else {
+            while !ctx.stop.load(Ordering::Relaxed) {
+                CFRunLoopRunInMode(kCFRunLoopDefaultMode, 0.1, 0);
+            }
+            FSEventStreamStop(stream);
+        }
+        FSEventStreamInvalidate(stream);
+        FSEventStreamRelease(stream);
+    }
+}
+
+extern "C" fn on_events(
+    _stream: CFRef,
+    info: *mut c_void,
+    count: usize,
+    paths: *mut c_void,
+    flags: *const u32,
+    _ids: *const u64,
+) {
+    let watch = unsafe { &*(info as *const Watch) };
+    let paths = unsafe { std::slice::from_raw_parts(paths as *const *const c_char, count) };
+    let flags = unsafe { std::slice::from_raw_parts(flags, count) };
+
+    for (&path, &flags) in paths.iter().zip(flags) {
+        let path = unsafe { CStr::from_ptr(path) }
+            .to_string_lossy()
+            .into_owned();
+        // Flags accumulate while events are coalesced, so what's on disk
+        // now decides between the ones that conflict
+        let o = if !Path::new(&path).exists() {
+            'd'
+        } else if flags & ITEM_RENAMED != 0 {
+            'r'
+        } else if flags & ITEM_CREATED != 0 {
+            'c'
+        } else if flags & ITEM_MODIFIED != 0 {
+            'm'
+        } else {
+            // Metadata, ownership, xattrs
+            continue;
+        };
+        let _ = watch.tx.try_send(Event {
+            t: watch.start.elapsed().as_millis() as u64,
+            ts: None,
+            data: EventData::FileChanged { f: path, o },
+        });
+    }
+}
@@ -2,5 +2,186 @@
 //!
 //! Uses CGEventTap for input capture and CGEventPost for replay.
 
-// The actual implementation is in recorder.rs and replay.rs
-// This module exists for future refactoring to fully separate platform code
+mod files;
+mod pasteboard;
+mod recorder;
+mod replay;
+
+pub use recorder::*;
+pub use replay::*;
+
+/// Active tab URL when `app` is a known browser, read through AppleScript
+pub(crate) fn browser_url(app: &str) -> Option<String> {
+    let script = match app {
+        "Safari" | "Safari Technology Preview" => {
+            format!(r#"tell application "{app}" to get URL of front document"#)
+        }
+        "Google Chrome" | "Chromium" | "Brave Browser" | "Microsoft Edge" | "Arc" | "Vivaldi"
+        | "Opera" => format!(r#"tell application "{app}" to get URL of active tab of front window"#),
+        _ => return None,
+    };
+    let out = std::process::Command::new("osascript")
+        .args(["-e", &script])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    let url = String::from_utf8(out.stdout).ok()?.trim().to_string();
+    (out.status.success() && !url.is_empty() && url != "missing value").then_some(url)
+}
+
+/// macOS product version, e.g. "14.5"
+pub(crate) fn os_version() -> Option<String> {
+    let out = std::process::Command::new("sw_vers").arg("-productVersion").output().ok()?;
+    let version = String::from_utf8(out.stdout).ok()?.trim().to_string();
+    (!version.is_empty()).then_some(version)
+}
+
+#[repr(C)]
+struct CGRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+// Raw FFI for display enumeration (not exposed by cidre)
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGGetActiveDisplayList(max: u32, displays: *mut u32, count: *mut u32) -> i32;
+    fn CGDisplayBounds(display: u32) -> CGRect;
+    fn CGDisplayCopyDisplayMode(display: u32) -> *const std::ffi::c_void;
+    fn CGDisplayModeGetPixelWidth(mode: *const std::ffi::c_void) -> usize;
+    fn CGDisplayModeRelease(mode: *const std::ffi::c_void);
+}
+
+/// Active displays in global points, scale being pixels per point
+pub(crate) fn displays() -> Vec<crate::events::DisplayInfo> {
+    let mut ids = [0u32; 16];
+    let mut count = 0;
+    if unsafe { CGGetActiveDisplayList(ids.len() as u32, ids.as_mut_ptr(), &mut count) } != 0 {
+        return Vec::new();
+    }
+    ids[..count as usize]
+        .iter()
+        .map(|&id| {
+            let bounds = unsafe { CGDisplayBounds(id) };
+            let scale = unsafe {
+                let mode = CGDisplayCopyDisplayMode(id);
+                if mode.is_null() {
+                    1.0
+                } else {
+                    let pixels = CGDisplayModeGetPixelWidth(mode) as f64;
+                    CGDisplayModeRelease(mode);
+                    if bounds.width > 0.0 { pixels / bounds.width } else { 1.0 }
+                }
+            };
+            crate::events::DisplayInfo {
+                x: bounds.x as i32,
+                y: bounds.y as i32,
+                w: bounds.width as u32,
+                h: bounds.height as u32,
+                scale,
+            }
+        })
+        .collect()
+}
+
+/// Current input source, e.g. "com.apple.keylayout.US"
+pub(crate) fn keyboard_layout() -> Option<String> {
+    let out = std::process::Command::new("defaults")
+        .args(["read", "com.apple.HIToolbox", "AppleCurrentKeyboardLayoutInputSourceID"])
+        .stderr(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    let layout = String::from_utf8(out.stdout).ok()?.trim().to_string();
+    (!layout.is_empty()).then_some(layout)
+}
+
+/// Ask for a line of text in a dialog; `None` when cancelled
+pub(crate) fn prompt_text(message: &str) -> anyhow::Result<Option<String>> {
+    let script = format!(
+        r#"text returned of (display dialog "{}" default answer "" with title "bigbrother")"#,
+        message.replace('"', "\\\""),
+    );
+    let out = std::process::Command::new("osascript")
+        .args(["-e", &script])
+        .stderr(std::process::Stdio::null())
+        .output()?;
+    // osascript fails with "User canceled"
+    Ok(out.status.success().then(|| String::from_utf8_lossy(&out.stdout).into_owned()))
+}
+
+/// Bring the running app with this name (as App events record it) to the
+/// front; false if it isn't running
+pub(crate) fn activate_app(app: &str) -> bool {
+    let apps = cidre::ns::Workspace::shared().running_apps();
+    let Some(pid) = apps
+        .iter()
+        .find(|a| a.localized_name().is_some_and(|n| n.to_string() == app))
+        .map(|a| a.pid())
+    else {
+        return false;
+    };
+    // By pid: `tell application` would launch, or ask for, an app it can't find
+    let script = format!(
+        "tell application \"System Events\" to set frontmost of (first process whose unix id is {}) to true",
+        pid,
+    );
+    std::process::Command::new("osascript")
+        .args(["-e", &script])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+/// Without it captures don't fail, they come out black
+pub(crate) fn has_screen_recording() -> bool {
+    unsafe { CGPreflightScreenCaptureAccess() }
+}
+
+/// Prompt for screen recording; granting it takes a restart
+pub(crate) fn request_screen_recording() -> bool {
+    unsafe { CGRequestScreenCaptureAccess() }
+}
+
+/// Capture the main display as a JPEG, longest side at most `max_size`
+pub(crate) fn capture_screen(path: &std::path::Path, max_size: u32) -> anyhow::Result<()> {
+    use std::process::{Command, Stdio};
+
+    if !has_screen_recording() {
+        anyhow::bail!("Screen Recording permission isn't granted, the screenshot would be blank");
+    }
+    let status = Command::new("screencapture")
+        .args(["-x", "-t", "jpg"])
+        .arg(path)
+        .status()?;
+    if !status.success() || !path.exists() {
+        anyhow::bail!("screencapture failed");
+    }
+    // sips -Z only ever shrinks to fit the box
+    Command::new("sips")
+        .arg("-Z")
+        .arg(max_size.to_string())
+        .arg(path)
+        .stdout(Stdio::null())
+        .status()?;
+    Ok(())
+}
+
+/// Screen recorder for the `video` feature (screencapture records through
+/// ScreenCaptureKit). It doesn't report frames, so its videos only line up
+/// with events to about a second (see [`crate::video`]).
+#[cfg(feature = "video")]
+pub(crate) fn video_command(
+    path: &std::path::Path,
+) -> anyhow::Result<(std::process::Command, bool)> {
+    let mut cmd = std::process::Command::new("screencapture");
+    cmd.args(["-v", "-x"]).arg(path);
+    Ok((cmd, false))
+}
@@ -0,0 +1,209 @@
+//! NSPasteboard access through the Objective-C runtime (cidre doesn't wrap
+//! AppKit's pasteboard)
+
+use crate::events::{ClipboardKind, EventData};
+use std::ffi::{c_char, c_void, CStr, CString};
+
+type Id = *mut c_void;
+type Sel = *mut c_void;
+
+#[link(name = "AppKit", kind = "framework")]
+extern "C" {}
+
+#[link(name = "objc")]
+extern "C" {
+    fn objc_getClass(name: *const c_char) -> Id;
+    fn sel_registerName(name: *const c_char) -> Sel;
+    fn objc_msgSend();
+    fn objc_autoreleasePoolPush() -> *mut c_void;
+    fn objc_autoreleasePoolPop(pool: *mut c_void);
+}
+
+unsafe fn class(name: &CStr) -> Id {
+    objc_getClass(name.as_ptr())
+}
+
+/// `[obj sel]`
+unsafe fn send(obj: Id, sel: &CStr) -> Id {
+    let f: unsafe extern "C" fn(Id, Sel) -> Id =
+        std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+    f(obj, sel_registerName(sel.as_ptr()))
+}
+
+/// `[obj sel:arg]`
+unsafe fn send_with(obj: Id, sel: &CStr, arg: Id) -> Id {
+    let f: unsafe extern "C" fn(Id, Sel, Id) -> Id =
+        std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+    f(obj, sel_registerName(sel.as_ptr()), arg)
+}
+
+/// `[obj sel]` returning an NSInteger/NSUInteger
+unsafe fn send_int(obj: Id, sel: &CStr) -> isize {
+    let f: unsafe extern "C" fn(Id, Sel) -> isize =
+        std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+    f(obj, sel_registerName(sel.as_ptr()))
+}
+
+/// `[obj sel:arg]` returning a BOOL
+unsafe fn send_bool(obj: Id, sel: &CStr, arg: Id) -> bool {
+    let f: unsafe extern "C" fn(Id, Sel, Id) -> i8 =
+        std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+    f(obj, sel_registerName(sel.as_ptr()), arg) != 0
+}
+
+unsafe fn ns_string(s: &CStr) -> Id {
+    send_with(
+        class(c"NSString"),
+        c"stringWithUTF8String:",
+        s.as_ptr() as Id,
+    )
+}
+
+unsafe fn to_string(ns: Id) -> Option<String> {
+    if ns.is_null() {
+        return None;
+    }
+    let p = send(ns, c"UTF8String") as *const c_char;
+    (!p.is_null()).then(|| CStr::from_ptr(p).to_string_lossy().into_owned())
+}
+
+unsafe fn general() -> Id {
+    send(class(c"NSPasteboard"), c"generalPasteboard")
+}
+
+/// Bumped by every write to the clipboard, whichever app made it
+pub(crate) fn change_count() -> isize {
+    unsafe {
+        let pool = objc_autoreleasePoolPush();
+        let count = send_int(general(), c"changeCount");
+        objc_autoreleasePoolPop(pool);
+        count
+    }
+}
+
+/// What the clipboard holds
+pub(crate) struct Contents {
+    kind: ClipboardKind,
+    mime: &'static str,
+    /// The text, file paths one per line, or "[image]"
+    text: String,
+    /// Size of the data itself
+    len: u64,
+}
+
+impl Contents {
+    fn text(kind: ClipboardKind, mime: &'static str, text: String) -> Self {
+        let len = text.len() as u64;
+        Self { kind, mime, text, len }
+    }
+
+    /// Paste event for operation `o`, with the full content (the recorder
+    /// shortens it)
+    pub fn into_paste(self, o: char) -> EventData {
+        EventData::Paste {
+            o,
+            s: self.text,
+            k: Some(self.kind),
+            m: Some(self.mime.to_string()),
+            l: self.len,
+            f: None,
+        }
+    }
+}
+
+pub(crate) fn contents() -> Option<Contents> {
+    unsafe {
+        let pool = objc_autoreleasePoolPush();
+        let contents = read(general());
+        objc_autoreleasePoolPop(pool);
+        contents
+    }
+}
+
+/// Replace the clipboard with plain text
+pub(crate) fn set_text(text: &str) -> bool {
+    let Ok(text) = CString::new(text) else {
+        return false;
+    };
+    unsafe {
+        let pool = objc_autoreleasePoolPush();
+        let pasteboard = general();
+        send(pasteboard, c"clearContents");
+        // [pasteboard setString:text forType:NSPasteboardTypeString]
+        let f: unsafe extern "C" fn(Id, Sel, Id, Id) -> i8 =
+            std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+        let ok = f(
+            pasteboard,
+            sel_registerName(c"setString:forType:".as_ptr()),
+            ns_string(&text),
+            ns_string(c"public.utf8-plain-text"),
+        ) != 0;
+        objc_autoreleasePoolPop(pool);
+        ok
+    }
+}
+
+unsafe fn read(pasteboard: Id) -> Option<Contents> {
+    let types = send(pasteboard, c"types");
+    if types.is_null() {
+        return None;
+    }
+    let has = |t: &CStr| send_bool(types, c"containsObject:", ns_string(t));
+
+    // Copied files also carry their names as text and icons as images
+    if has(c"public.file-url") {
+        let classes = send_with(class(c"NSArray"), c"arrayWithObject:", class(c"NSURL"));
+        let urls = read_objects(pasteboard, classes);
+        if !urls.is_null() {
+            let paths: Vec<String> = (0..send_int(urls, c"count"))
+                .filter_map(|i| to_string(send(object_at(urls, i), c"path")))
+                .collect();
+            if !paths.is_empty() {
+                let paths = paths.join("\n");
+                return Some(Contents::text(ClipboardKind::Files, "text/uri-list", paths));
+            }
+        }
+    }
+    if has(c"public.utf8-plain-text") {
+        let text = to_string(send_with(
+            pasteboard,
+            c"stringForType:",
+            ns_string(c"public.utf8-plain-text"),
+        ));
+        if let Some(text) = text.filter(|t| !t.is_empty()) {
+            return Some(Contents::text(ClipboardKind::Text, "text/plain", text));
+        }
+    }
+    for (uti, mime) in [(c"public.png", "image/png"), (c"public.tiff", "image/tiff")] {
+        if has(uti) {
+            let data = send_with(pasteboard, c"dataForType:", ns_string(uti));
+            let len = if data.is_null() { 0 } else { send_int(data, c"length") as u64 };
+            let text = "[image]".to_string();
+            return Some(Contents { kind: ClipboardKind::Image, mime, text, len });
+        }
+    }
+    None
+}
+
+/// `[pasteboard readObjectsForClasses:classes options:nil]`
+unsafe fn read_objects(pasteboard: Id, classes: Id) -> Id {
+    let f: unsafe extern "C" fn(Id, Sel, Id, Id) -> Id =
+        std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+    f(
+        pasteboard,
+        sel_registerName(c"readObjectsForClasses:options:".as_ptr()),
+        classes,
+        std::ptr::null_mut(),
+    )
+}
+
+/// `[array objectAtIndex:i]`
+unsafe fn object_at(array: Id, i: isize) -> Id {
+    let f: unsafe extern "C" fn(Id, Sel, usize) -> Id =
+        std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+    f(
+        array,
+        sel_registerName(c"objectAtIndex:".as_ptr()),
+        i as usize,
+    )
+}
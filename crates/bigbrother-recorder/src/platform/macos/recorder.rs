@@ -0,0 +1,994 @@
+//! macOS capture using CGEventTap + NSWorkspace polling
+//!
+//! Optimized for minimal CPU/memory usage while capturing everything.
+
+use super::pasteboard;
+use crate::error::RecorderError;
+use crate::events::*;
+use crate::keys::Key;
+use crate::text::truncate;
+use crate::recorder::{
+    CallbackState, CaptureContext, EventSink, GestureTracker, KeyTracker, Permission,
+    PermissionState, PermissionStatus, RecorderConfig,
+};
+use parking_lot::Mutex;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cidre::{cf, cg, ns};
+use cidre::cg::event::access as cg_access;
+
+// Keycodes for clipboard operations
+const KEY_X: u16 = 7;
+const KEY_V: u16 = 9;
+
+/// A clipboard change this soon after Cmd+X is recorded as a cut
+const CUT_WINDOW_MS: u64 = 1000;
+
+/// How long a click waits for its element context, and a Cmd+V for the
+/// clipboard (ms), well under the time after which the system turns a slow
+/// tap off
+const CONTEXT_WAIT_MS: u64 = 250;
+
+/// Clicks recorded without context after a lookup timed out (ms), so a hung
+/// app doesn't cost every click the full wait
+const CONTEXT_PAUSE_MS: u64 = 5000;
+
+const ACCESSIBILITY_SETTINGS: &str =
+    "x-apple.systempreferences:com.apple.preference.security?Privacy_Accessibility";
+const INPUT_MONITORING_SETTINGS: &str =
+    "x-apple.systempreferences:com.apple.preference.security?Privacy_ListenEvent";
+const SCREEN_RECORDING_SETTINGS: &str =
+    "x-apple.systempreferences:com.apple.preference.security?Privacy_ScreenCapture";
+
+// IOHIDRequestType and IOHIDAccessType
+const LISTEN_EVENT: u32 = 1;
+const ACCESS_GRANTED: u32 = 0;
+const ACCESS_DENIED: u32 = 1;
+
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOHIDCheckAccess(request: u32) -> u32;
+}
+
+pub fn check_permissions() -> PermissionStatus {
+    let screen = super::has_screen_recording();
+    status(cidre::ax::is_process_trusted(), input_monitoring(), screen)
+}
+
+/// Prompts for screen recording only with `screen`
+pub fn request_permissions(screen: bool) -> PermissionStatus {
+    let accessibility = cidre::ax::is_process_trusted_with_prompt(true);
+    let input_monitoring = if cg_access::listen_request() {
+        PermissionState::Granted
+    } else {
+        self::input_monitoring()
+    };
+    let screen = if screen {
+        super::request_screen_recording()
+    } else {
+        super::has_screen_recording()
+    };
+    status(accessibility, input_monitoring, screen)
+}
+
+/// Input Monitoring as TCC has it, which unlike the CoreGraphics preflight
+/// tells never asked from denied
+fn input_monitoring() -> PermissionState {
+    match unsafe { IOHIDCheckAccess(LISTEN_EVENT) } {
+        ACCESS_GRANTED => PermissionState::Granted,
+        ACCESS_DENIED => PermissionState::Denied,
+        _ => PermissionState::NotDetermined,
+    }
+}
+
+fn status(
+    accessibility: bool,
+    input_monitoring: PermissionState,
+    screen: bool,
+) -> PermissionStatus {
+    PermissionStatus::new(
+        Permission::new("accessibility", accessibility.into())
+            .settings_url(ACCESSIBILITY_SETTINGS)
+            .remediation(
+                "Turn on the app running bb in System Settings > Privacy & Security > \
+                 Accessibility",
+            ),
+        Permission::new("input_monitoring", input_monitoring)
+            .settings_url(INPUT_MONITORING_SETTINGS)
+            .restart_required()
+            .remediation(
+                "Turn on the app running bb in System Settings > Privacy & Security > \
+                 Input Monitoring, then restart it",
+            ),
+        Permission::new("screen_recording", screen.into())
+            .settings_url(SCREEN_RECORDING_SETTINGS)
+            .restart_required()
+            .remediation(
+                "For screenshots and video, turn on the app running bb in System Settings > \
+                 Privacy & Security > Screen Recording, then restart it",
+            ),
+    )
+}
+
+/// Spawn the capture threads for a session
+pub(crate) fn start_capture(
+    ctx: CaptureContext,
+) -> Result<Vec<thread::JoinHandle<()>>, RecorderError> {
+    let mut threads = Vec::new();
+
+    // `t` of the last Cmd+X
+    let cut_at = Arc::new(AtomicU64::new(u64::MAX));
+
+    // Thread 1: CGEventTap for input events (and pastes via Cmd+V)
+    let tap_ctx = ctx.clone();
+    let tap_cut_at = cut_at.clone();
+    let (ready, tap_ready) = mpsc::channel();
+    threads.push(thread::spawn(move || {
+        run_event_tap(tap_ctx.tx, tap_ctx.stop, tap_ctx.start, tap_ctx.config, tap_cut_at, ready);
+    }));
+    // Nothing else starts without input
+    tap_ready.recv().unwrap_or_else(|_| {
+        Err(RecorderError::TapCreationFailed("The event tap thread exited".into()))
+    })?;
+
+    // Thread 2: Clipboard changes, however they were made
+    let clip_ctx = ctx.clone();
+    threads.push(thread::spawn(move || {
+        run_clipboard_watcher(clip_ctx.tx, clip_ctx.stop, clip_ctx.start, cut_at);
+    }));
+
+    // Thread 3: File changes in `watch_dirs`
+    if !ctx.config.watch_dirs.is_empty() {
+        let files_ctx = ctx.clone();
+        threads.push(thread::spawn(move || super::files::run_file_watcher(files_ctx)));
+    }
+
+    // Thread 4: App/window switch notifications
+    threads.push(thread::spawn(move || {
+        run_app_observer(ctx.tx, ctx.stop, ctx.start);
+    }));
+
+    Ok(threads)
+}
+
+// ============================================================================
+// Event Tap Thread
+// ============================================================================
+
+struct TapState {
+    tx: EventSink,
+    start: Instant,
+    config: RecorderConfig,
+    last_mouse: Mutex<(f64, f64)>,
+    gestures: Mutex<GestureTracker>,
+    keys: Mutex<KeyTracker>,
+    cut_at: Arc<AtomicU64>,
+    /// The tap's mach port, set once it's created
+    tap: AtomicPtr<c_void>,
+    /// The tap is disabled and turning it back on failed so far
+    down: AtomicBool,
+    /// No click context until then (ms since start)
+    context_paused_until: AtomicU64,
+    contexts: ContextWorker,
+}
+
+/// What the worker is asked to look up
+enum Lookup {
+    /// Context of the element at (x, y)
+    Element(f64, f64),
+    /// What a Cmd+V is pasting
+    Pasteboard,
+}
+
+/// What it found
+enum Found {
+    Element(Option<ElementContext>),
+    Pasteboard(Option<pasteboard::Contents>),
+}
+
+/// Looks up click context and pasted contents on its own thread, so an app
+/// that doesn't answer accessibility requests, or is slow to hand over
+/// what it put on the clipboard, holds up that thread and not the tap.
+/// Requests and replies carry an id: a reply that comes after its event
+/// gave up waiting is dropped rather than attached to a later one.
+struct ContextWorker {
+    next_id: AtomicU64,
+    /// Requests (id, lookup) out, (id, found) back
+    channel: Mutex<(mpsc::Sender<(u64, Lookup)>, mpsc::Receiver<(u64, Found)>)>,
+}
+
+impl ContextWorker {
+    /// Start the thread; it ends once the worker is dropped and any lookup
+    /// in progress returns
+    fn spawn() -> Self {
+        let (requests, queue) = mpsc::channel::<(u64, Lookup)>();
+        let (answer, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for (id, lookup) in queue {
+                let found = match lookup {
+                    Lookup::Element(x, y) => Found::Element(element_context(x, y)),
+                    Lookup::Pasteboard => Found::Pasteboard(pasteboard::contents()),
+                };
+                if answer.send((id, found)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { next_id: AtomicU64::new(0), channel: Mutex::new((requests, replies)) }
+    }
+
+    /// The element at (x, y), or Err if it took longer than `wait`
+    fn element(&self, x: f64, y: f64, wait: Duration) -> Result<Option<ElementContext>, ()> {
+        match self.lookup(Lookup::Element(x, y), wait)? {
+            Found::Element(context) => Ok(context),
+            Found::Pasteboard(_) => Err(()),
+        }
+    }
+
+    /// The clipboard's contents, or Err if it took longer than `wait`
+    fn pasteboard(&self, wait: Duration) -> Result<Option<pasteboard::Contents>, ()> {
+        match self.lookup(Lookup::Pasteboard, wait)? {
+            Found::Pasteboard(contents) => Ok(contents),
+            Found::Element(_) => Err(()),
+        }
+    }
+
+    fn lookup(&self, lookup: Lookup, wait: Duration) -> Result<Found, ()> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let channel = self.channel.lock();
+        channel.0.send((id, lookup)).map_err(|_| ())?;
+        let deadline = Instant::now() + wait;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match channel.1.recv_timeout(left) {
+                Ok((reply, found)) if reply == id => return Ok(found),
+                // For an earlier event that stopped waiting
+                Ok(_) => continue,
+                Err(_) => return Err(()),
+            }
+        }
+    }
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventTapEnable(tap: *mut c_void, enable: bool);
+    fn CGEventTapIsEnabled(tap: *mut c_void) -> bool;
+    fn CGEventGetIntegerValueField(event: *const c_void, field: u32) -> i64;
+}
+
+// Scroll fields cidre doesn't name
+const SCROLL_IS_CONTINUOUS: u32 = 88;
+const SCROLL_POINT_DELTA_AXIS1: u32 = 96;
+const SCROLL_POINT_DELTA_AXIS2: u32 = 97;
+
+impl TapState {
+    /// Turn the tap back on after the system disabled it, leaving a
+    /// TapInterrupted marker
+    fn reenable(&self, reason: &str) {
+        let tap = self.tap.load(Ordering::Relaxed);
+        if tap.is_null() {
+            return;
+        }
+        let enabled = unsafe {
+            CGEventTapEnable(tap, true);
+            CGEventTapIsEnabled(tap)
+        };
+        self.down.store(!enabled, Ordering::Relaxed);
+        self.tx.capture_interrupted(reason, enabled);
+    }
+
+    /// Catch a disabled tap whose notification was missed, and retry one
+    /// that couldn't be turned back on
+    fn check(&self) {
+        let tap = self.tap.load(Ordering::Relaxed);
+        if tap.is_null() {
+            return;
+        }
+        if unsafe { CGEventTapIsEnabled(tap) } {
+            if self.down.swap(false, Ordering::Relaxed) {
+                self.tx.capture_restored();
+            }
+        } else if self.down.load(Ordering::Relaxed) {
+            unsafe { CGEventTapEnable(tap, true) };
+        } else {
+            self.reenable("disabled");
+        }
+    }
+}
+
+fn run_event_tap(
+    tx: EventSink,
+    stop: Arc<AtomicBool>,
+    start: Instant,
+    config: RecorderConfig,
+    cut_at: Arc<AtomicU64>,
+    ready: mpsc::Sender<Result<(), RecorderError>>,
+) {
+    // Build event mask - capture everything
+    let mask = cg::EventType::LEFT_MOUSE_DOWN.mask()
+        | cg::EventType::LEFT_MOUSE_UP.mask()
+        | cg::EventType::RIGHT_MOUSE_DOWN.mask()
+        | cg::EventType::RIGHT_MOUSE_UP.mask()
+        | cg::EventType::MOUSE_MOVED.mask()
+        | cg::EventType::LEFT_MOUSE_DRAGGED.mask()
+        | cg::EventType::RIGHT_MOUSE_DRAGGED.mask()
+        | cg::EventType::KEY_DOWN.mask()
+        | cg::EventType::KEY_UP.mask()
+        | cg::EventType::FLAGS_CHANGED.mask()
+        | cg::EventType::SCROLL_WHEEL.mask();
+
+    // Owned here and dropped last, after the tap that points at it is gone
+    let state = CallbackState::new(TapState {
+        tx,
+        start,
+        config: config.clone(),
+        last_mouse: Mutex::new((0.0, 0.0)),
+        gestures: Mutex::new(GestureTracker::new(&config)),
+        keys: Mutex::new(KeyTracker::new(&config)),
+        cut_at,
+        tap: AtomicPtr::new(std::ptr::null_mut()),
+        down: AtomicBool::new(false),
+        context_paused_until: AtomicU64::new(0),
+        contexts: ContextWorker::spawn(),
+    });
+
+    let tap = cg::EventTap::new(
+        cg::EventTapLocation::Session,
+        cg::EventTapPlacement::TailAppend,
+        cg::EventTapOpts::LISTEN_ONLY,
+        mask,
+        tap_callback,
+        state.as_ptr(),
+    );
+
+    let Some(tap) = tap else {
+        let error = if cg_access::listen_preflight() {
+            RecorderError::TapCreationFailed("The system refused the event tap".into())
+        } else {
+            RecorderError::PermissionDenied(
+                "Allow this app under Privacy & Security > Input Monitoring and Accessibility"
+                    .into(),
+            )
+        };
+        let _ = ready.send(Err(error));
+        return;
+    };
+    state.tap.store(&*tap as *const cf::MachPort as *mut c_void, Ordering::Relaxed);
+
+    let Some(src) = cf::MachPort::run_loop_src(&tap, 0) else {
+        let error = "Failed to create the event tap's run loop source";
+        let _ = ready.send(Err(RecorderError::TapCreationFailed(error.into())));
+        return;
+    };
+
+    let rl = cf::RunLoop::current();
+    rl.add_src(&src, cf::RunLoopMode::default());
+    let _ = ready.send(Ok(()));
+    let _hotkeys = SystemHotkeys::register(&config);
+
+    while !stop.load(Ordering::Relaxed) {
+        cf::RunLoop::run_in_mode(cf::RunLoopMode::default(), 0.05, true);
+
+        state.check();
+        state.tx.flush_idle_text();
+    }
+
+    // Final flush
+    state.tx.flush_text();
+
+    // Callbacks only run from this thread's run loop, so none can reach
+    // the state once the tap is off and its source removed
+    unsafe { CGEventTapEnable(state.tap.swap(std::ptr::null_mut(), Ordering::Relaxed), false) };
+    rl.remove_src(&src, cf::RunLoopMode::default());
+    drop(src);
+    drop(tap);
+    drop(state);
+}
+
+/// `pause_hotkey` and `stop_hotkey`, registered so the focused app doesn't
+/// receive them. Nothing handles the Carbon events: the listen-only tap
+/// still sees the keys and the sink acts on them.
+struct SystemHotkeys(Vec<*mut std::ffi::c_void>);
+
+#[repr(C)]
+struct EventHotKeyID {
+    signature: u32,
+    id: u32,
+}
+
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    fn GetApplicationEventTarget() -> *mut std::ffi::c_void;
+    fn RegisterEventHotKey(
+        code: u32,
+        modifiers: u32,
+        id: EventHotKeyID,
+        target: *mut std::ffi::c_void,
+        options: u32,
+        out: *mut *mut std::ffi::c_void,
+    ) -> i32;
+    fn UnregisterEventHotKey(hotkey: *mut std::ffi::c_void) -> i32;
+}
+
+impl SystemHotkeys {
+    fn register(config: &RecorderConfig) -> Self {
+        let mut registered = Vec::new();
+        for (id, hotkey) in [config.pause_hotkey, config.stop_hotkey].into_iter().enumerate() {
+            let Some(hotkey) = hotkey else {
+                continue;
+            };
+            // Carbon's cmdKey, shiftKey, optionKey and controlKey
+            let modifiers = [
+                (Modifiers::CMD, 0x100),
+                (Modifiers::SHIFT, 0x200),
+                (Modifiers::OPT, 0x800),
+                (Modifiers::CTRL, 0x1000),
+            ]
+            .iter()
+            .filter(|(bit, _)| hotkey.m & bit != 0)
+            .fold(0, |acc, (_, carbon)| acc | carbon);
+            let id = EventHotKeyID { signature: u32::from_be_bytes(*b"bbHK"), id: id as u32 };
+            let mut out = std::ptr::null_mut();
+            let status = unsafe {
+                RegisterEventHotKey(
+                    hotkey.key.to_macos() as u32,
+                    modifiers,
+                    id,
+                    GetApplicationEventTarget(),
+                    0,
+                    &mut out,
+                )
+            };
+            if status == 0 {
+                registered.push(out);
+            } else {
+                let message = "Can't register hotkey system-wide (taken?), it also reaches apps";
+                tracing::warn!(%hotkey, message);
+            }
+        }
+        Self(registered)
+    }
+}
+
+impl Drop for SystemHotkeys {
+    fn drop(&mut self) {
+        for &hotkey in &self.0 {
+            unsafe { UnregisterEventHotKey(hotkey) };
+        }
+    }
+}
+
+extern "C" fn tap_callback(
+    _proxy: *mut cg::EventTapProxy,
+    event_type: cg::EventType,
+    event: &mut cg::Event,
+    user_info: *mut TapState,
+) -> Option<&cg::Event> {
+    let state = unsafe { &*user_info };
+    // Sent instead of input when the system turns the tap off: after a
+    // callback took too long, or while secure input is on
+    if event_type == cg::EventType::TAP_DISABLED_BY_TIMEOUT {
+        state.reenable("timeout");
+        return Some(event);
+    }
+    if event_type == cg::EventType::TAP_DISABLED_BY_USER_INPUT {
+        state.reenable("user");
+        return Some(event);
+    }
+    let t = state.start.elapsed().as_millis() as u64;
+    let loc = event.location();
+    let flags = event.flags().0;
+    let mods = Modifiers::from_cg_flags(flags);
+
+    match event_type {
+        cg::EventType::LEFT_MOUSE_DOWN | cg::EventType::RIGHT_MOUSE_DOWN => {
+            // The click may move focus; what was typed goes first
+            state.tx.flush_text();
+            let btn = if event_type == cg::EventType::LEFT_MOUSE_DOWN { 0 } else { 1 };
+            let clicks = event.field_i64(cg::EventField::MOUSE_EVENT_CLICK_STATE) as u8;
+            let pos = (loc.x as i32, loc.y as i32);
+            // Resolved at press, before the click changes what's under the
+            // cursor, and waited for so it stays part of the Click
+            let paused = t < state.context_paused_until.load(Ordering::Relaxed);
+            let context = if state.config.capture_context && !paused {
+                let wait = Duration::from_millis(CONTEXT_WAIT_MS);
+                state.contexts.element(loc.x, loc.y, wait).unwrap_or_else(|()| {
+                    let ms = CONTEXT_WAIT_MS;
+                    tracing::warn!(ms, "Click context timed out, pausing it for 5s");
+                    state.context_paused_until.store(t + CONTEXT_PAUSE_MS, Ordering::Relaxed);
+                    None
+                })
+            } else {
+                None
+            }
+            .map(Box::new);
+            let press = state.gestures.lock().press(t, pos, btn, clicks, mods.0, context);
+            if let Some(event) = press {
+                let _ = state.tx.try_send(event);
+            }
+        }
+
+        cg::EventType::LEFT_MOUSE_UP | cg::EventType::RIGHT_MOUSE_UP => {
+            let btn = if event_type == cg::EventType::LEFT_MOUSE_UP { 0 } else { 1 };
+            let release = state.gestures.lock().release(t, (loc.x as i32, loc.y as i32), btn);
+            if let Some(event) = release {
+                let _ = state.tx.try_send(event);
+            }
+        }
+
+        cg::EventType::MOUSE_MOVED
+        | cg::EventType::LEFT_MOUSE_DRAGGED
+        | cg::EventType::RIGHT_MOUSE_DRAGGED => {
+            let mut last = state.last_mouse.lock();
+            if state.gestures.lock().moved((loc.x as i32, loc.y as i32)) {
+                // Part of a press or drag, reported on release
+                *last = (loc.x, loc.y);
+                return Some(event);
+            }
+            let dx = loc.x - last.0;
+            let dy = loc.y - last.1;
+            let dist = (dx * dx + dy * dy).sqrt();
+
+            if dist >= state.config.mouse_move_threshold {
+                *last = (loc.x, loc.y);
+                let _ = state.tx.try_send(Event {
+                    t,
+                    ts: None,
+                    data: EventData::Move {
+                        x: loc.x as i32,
+                        y: loc.y as i32,
+                    },
+                });
+            }
+        }
+
+        cg::EventType::SCROLL_WHEEL => {
+            let raw = &*event as *const cg::Event as *const c_void;
+            let field = |f| unsafe { CGEventGetIntegerValueField(raw, f) };
+            // Trackpads and precise wheels scroll by pixels, often less than
+            // a line at a time
+            let px = field(SCROLL_IS_CONTINUOUS) != 0;
+            let (dy, dx) = if px {
+                (field(SCROLL_POINT_DELTA_AXIS1), field(SCROLL_POINT_DELTA_AXIS2))
+            } else {
+                (
+                    event.field_i64(cg::EventField::SCROLL_WHEEL_EVENT_DELTA_AXIS1),
+                    event.field_i64(cg::EventField::SCROLL_WHEEL_EVENT_DELTA_AXIS2),
+                )
+            };
+            let (dx, dy) = (dx as i16, dy as i16);
+            if dx != 0 || dy != 0 {
+                let _ = state.tx.try_send(Event {
+                    t,
+                    ts: None,
+                    data: EventData::Scroll {
+                        x: loc.x as i32,
+                        y: loc.y as i32,
+                        dx,
+                        dy,
+                        px,
+                    },
+                });
+            }
+        }
+
+        cg::EventType::KEY_DOWN => {
+            let keycode = event.field_i64(cg::EventField::KEYBOARD_EVENT_KEYCODE) as u16;
+            let typed = !mods.any_modifier()
+                && keycode_to_char(keycode, mods).is_some_and(|c| state.tx.type_char(c));
+            state.keys.lock().press(t, Key::from_macos(keycode), !typed);
+
+            // Copies and cuts are picked up by the clipboard watcher
+            if mods.has_cmd() && !mods.has_ctrl() {
+                match keycode {
+                    KEY_X => state.cut_at.store(t, Ordering::Relaxed),
+                    KEY_V => {
+                        // Paste - capture what's being pasted, ahead of the
+                        // key that replays it
+                        let wait = Duration::from_millis(CONTEXT_WAIT_MS);
+                        match state.contexts.pasteboard(wait) {
+                            Ok(Some(contents)) => {
+                                let data = contents.into_paste('v');
+                                let _ = state.tx.try_send(Event { t, ts: None, data });
+                            }
+                            Ok(None) => {}
+                            Err(()) => {
+                                let ms = CONTEXT_WAIT_MS;
+                                tracing::warn!(ms, "Clipboard read timed out, paste not recorded");
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                let _ = state.tx.try_send(Event {
+                    t,
+                    ts: None,
+                    data: EventData::Key { k: Key::from_macos(keycode), m: mods.0 },
+                });
+            } else if mods.any_modifier() {
+                // Other modifier combo
+                let _ = state.tx.try_send(Event {
+                    t,
+                    ts: None,
+                    data: EventData::Key { k: Key::from_macos(keycode), m: mods.0 },
+                });
+            } else if !typed {
+                // Unknown key (or a backspace past the typed text)
+                let _ = state.tx.try_send(Event {
+                    t,
+                    ts: None,
+                    data: EventData::Key { k: Key::from_macos(keycode), m: mods.0 },
+                });
+            }
+        }
+
+        cg::EventType::KEY_UP => {
+            let keycode = event.field_i64(cg::EventField::KEYBOARD_EVENT_KEYCODE) as u16;
+            let release = state.keys.lock().release(t, Key::from_macos(keycode), mods.0);
+            if let Some(data) = release {
+                let _ = state.tx.try_send(Event { t, ts: None, data });
+            }
+        }
+
+        cg::EventType::FLAGS_CHANGED => {
+            let keycode = event.field_i64(cg::EventField::KEYBOARD_EVENT_KEYCODE) as u16;
+            let key = Key::from_macos(keycode);
+            let bit = match key {
+                Key::Shift => Modifiers::SHIFT,
+                Key::Control => Modifiers::CTRL,
+                Key::Alt => Modifiers::OPT,
+                Key::Meta => Modifiers::CMD,
+                _ => return Some(event),
+            };
+            // Flags already reflect the change
+            let down = mods.0 & bit != 0;
+            let modifier = state.keys.lock().modifier(t, key, down, mods.0 & !bit);
+            if let Some(data) = modifier {
+                let _ = state.tx.try_send(Event { t, ts: None, data });
+            }
+        }
+
+        _ => {}
+    }
+
+    Some(event)
+}
+
+// ============================================================================
+// Clipboard Watcher Thread
+// ============================================================================
+
+/// Report every clipboard write - menu and context-menu copies, and apps
+/// writing it themselves, not just Cmd+C - by polling the change count
+fn run_clipboard_watcher(
+    tx: EventSink,
+    stop: Arc<AtomicBool>,
+    start: Instant,
+    cut_at: Arc<AtomicU64>,
+) {
+    let mut last = pasteboard::change_count();
+
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        let count = pasteboard::change_count();
+        if count == last {
+            continue;
+        }
+        last = count;
+        let Some(contents) = pasteboard::contents() else {
+            continue;
+        };
+        let t = start.elapsed().as_millis() as u64;
+        let cut = t
+            .checked_sub(cut_at.load(Ordering::Relaxed))
+            .is_some_and(|since| since < CUT_WINDOW_MS);
+        let data = contents.into_paste(if cut { 'x' } else { 'c' });
+        let _ = tx.try_send(Event { t, ts: None, data });
+    }
+}
+
+/// Ancestors walked above a clicked element
+const MAX_ANCESTORS: usize = 16;
+
+pub(super) fn element_context(x: f64, y: f64) -> Option<ElementContext> {
+    use cidre::ax;
+
+    let sys = ax::UiElement::sys_wide();
+    let elem = sys.element_at_pos(x as f32, y as f32).ok()?;
+
+    let role = role_name(&elem)?;
+    let title = get_str_attr(&elem, ax::attr::title());
+    let name = title.clone().or_else(|| get_str_attr(&elem, ax::attr::desc()));
+    let value = get_str_attr(&elem, ax::attr::value());
+    let id = get_str_attr(&elem, ax::attr::identifier()).filter(|id| !id.is_empty());
+
+    // Walk up to the application, collecting the window on the way
+    let mut ancestors = Vec::new();
+    let (mut app, mut window) = (None, None);
+    let mut current = get_parent(&elem);
+    while let Some(parent) = current {
+        if ancestors.len() >= MAX_ANCESTORS {
+            break;
+        }
+        let parent_role = role_name(&parent).unwrap_or_default();
+        let parent_title = get_str_attr(&parent, ax::attr::title());
+        match parent_role.as_str() {
+            "AXApplication" => {
+                app = parent_title;
+                break;
+            }
+            "AXWindow" if window.is_none() => window = parent_title.clone(),
+            _ => {}
+        }
+        ancestors.push(selector(&parent_role, parent_title.as_deref()));
+        current = get_parent(&parent);
+    }
+    ancestors.reverse();
+
+    Some(ElementContext {
+        s: selector(&role, title.as_deref()),
+        r: role,
+        n: name.map(|s| truncate(&s, 50).into_owned()),
+        v: value.map(|s| truncate(&s, 50).into_owned()),
+        id,
+        b: get_bounds(&elem),
+        p: ancestors,
+        a: app,
+        w: window.map(|s| truncate(&s, 100).into_owned()),
+        // Filled in from the latest Window event
+        u: None,
+    })
+}
+
+/// Elements visited looking for a recorded one before giving up
+const MAX_SEARCH: usize = 5000;
+
+/// Bounds of the first element in the recorded app (or the frontmost one)
+/// whose role and selector match `context`, breadth first. An AXIdentifier
+/// recorded with it has to match too.
+pub(super) fn find_element(context: &ElementContext) -> Option<(i32, i32, i32, i32)> {
+    use cidre::ax;
+
+    let apps = cidre::ns::Workspace::shared().running_apps();
+    let app = apps.iter().find(|app| match &context.a {
+        Some(name) => app.localized_name().is_some_and(|n| n.to_string() == *name),
+        None => app.is_active(),
+    })?;
+    let mut queue = std::collections::VecDeque::from([ax::UiElement::with_app_pid(app.pid())]);
+    let mut visited = 0;
+    while let Some(elem) = queue.pop_front() {
+        visited += 1;
+        if visited > MAX_SEARCH {
+            break;
+        }
+        if let Some(role) = role_name(&elem).filter(|r| *r == context.r) {
+            let title = get_str_attr(&elem, ax::attr::title());
+            let id = || get_str_attr(&elem, ax::attr::identifier());
+            if selector(&role, title.as_deref()) == context.s
+                && (context.id.is_none() || id() == context.id)
+            {
+                if let Some(bounds) = get_bounds(&elem) {
+                    return Some(bounds);
+                }
+            }
+        }
+        if let Ok(children) = elem.children() {
+            queue.extend(children.iter().map(|c| c.retained()));
+        }
+    }
+    None
+}
+
+/// `role:AXButton AND title:Save`, matching the core AX backend, where
+/// `name` is the role description
+fn selector(role: &str, title: Option<&str>) -> String {
+    match title.filter(|t| !t.is_empty()) {
+        Some(title) => format!("role:{} AND title:{}", role, title),
+        None => format!("role:{}", role),
+    }
+}
+
+fn role_name(elem: &cidre::ax::UiElement) -> Option<String> {
+    elem.role().ok().map(|r| {
+        let s = format!("{:?}", r);
+        s.find("AX").map(|i| {
+            let rest = &s[i..];
+            rest.find(|c| c == ')' || c == '"').map(|j| rest[..j].to_string()).unwrap_or(rest.to_string())
+        }).unwrap_or_else(|| "?".to_string())
+    })
+}
+
+fn get_parent(elem: &cidre::ax::UiElement) -> Option<cidre::arc::R<cidre::ax::UiElement>> {
+    use cidre::ax;
+
+    let value = elem.attr_value(ax::attr::parent()).ok()?;
+    if value.get_type_id() != ax::UiElement::type_id() {
+        return None;
+    }
+    let parent: &ax::UiElement = unsafe { std::mem::transmute(&*value) };
+    Some(parent.retained())
+}
+
+fn get_bounds(elem: &cidre::ax::UiElement) -> Option<(i32, i32, i32, i32)> {
+    use cidre::ax;
+
+    let pos = elem.attr_value(ax::attr::pos()).ok()?;
+    let size = elem.attr_value(ax::attr::size()).ok()?;
+    if pos.get_type_id() != ax::Value::type_id() || size.get_type_id() != ax::Value::type_id() {
+        return None;
+    }
+    let pos: &ax::Value = unsafe { std::mem::transmute(&*pos) };
+    let size: &ax::Value = unsafe { std::mem::transmute(&*size) };
+    let origin = pos.cg_point()?;
+    let size = size.cg_size()?;
+    Some((origin.x as i32, origin.y as i32, size.width as i32, size.height as i32))
+}
+
+#[link(name = "Carbon", kind = "framework")]
+extern "C" {
+    fn IsSecureEventInputEnabled() -> u8;
+}
+
+/// Whether keyboard input is going to a password field: secure event input
+/// is on, or the focused element is an AXSecureTextField
+pub(crate) fn secure_input_active() -> bool {
+    use cidre::ax;
+
+    if unsafe { IsSecureEventInputEnabled() } != 0 {
+        return true;
+    }
+    let Ok(value) = ax::UiElement::sys_wide().attr_value(ax::attr::focused_ui_element()) else {
+        return false;
+    };
+    if value.get_type_id() != ax::UiElement::type_id() {
+        return false;
+    }
+    let focused: &ax::UiElement = unsafe { std::mem::transmute(&*value) };
+    get_str_attr(focused, ax::attr::subrole()).as_deref() == Some("AXSecureTextField")
+}
+
+fn get_str_attr(elem: &cidre::ax::UiElement, attr: &cidre::ax::Attr) -> Option<String> {
+    elem.attr_value(attr).ok().and_then(|v| {
+        if v.get_type_id() == cidre::cf::String::type_id() {
+            let s: &cidre::cf::String = unsafe { std::mem::transmute(&*v) };
+            Some(s.to_string())
+        } else {
+            None
+        }
+    })
+}
+
+// ============================================================================
+// App/Window Observer Thread (polling-based for reliability)
+// ============================================================================
+
+fn run_app_observer(tx: EventSink, stop: Arc<AtomicBool>, start: Instant) {
+    let workspace = ns::Workspace::shared();
+
+    let mut last_app: Option<String> = None;
+    let mut last_pid: i32 = 0;
+    let mut last_window: Option<String> = None;
+
+    while !stop.load(Ordering::Relaxed) {
+        // Find the active (frontmost) application
+        let apps = workspace.running_apps();
+        let active_app = apps.iter().find(|app| app.is_active());
+
+        if let Some(app) = active_app {
+            let name = app.localized_name()
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| "?".to_string());
+            let pid = app.pid();
+
+            // Check if app changed
+            let app_changed = last_app.as_ref() != Some(&name) || last_pid != pid;
+
+            if app_changed {
+                let _ = tx.try_send(Event {
+                    t: start.elapsed().as_millis() as u64,
+                    ts: None,
+                    data: EventData::App { n: name.clone(), p: pid },
+                });
+                last_app = Some(name.clone());
+                last_pid = pid;
+            }
+
+            // Check if window changed (even within same app - catches tab switches)
+            let window_title = get_focused_window_title(pid);
+            if window_title != last_window || app_changed {
+                // Tab switches change the title, so this tracks the active tab
+                let url = super::browser_url(&name);
+                let _ = tx.try_send(Event {
+                    t: start.elapsed().as_millis() as u64,
+                    ts: None,
+                    data: EventData::Window {
+                        a: name,
+                        w: window_title.as_ref().map(|s| truncate(s, 100).into_owned()),
+                        u: url,
+                    },
+                });
+                last_window = window_title;
+            }
+        }
+
+        // Poll every 100ms
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+}
+
+/// Get the focused window title for a given app PID
+pub(super) fn get_focused_window_title(pid: i32) -> Option<String> {
+    use cidre::ax;
+
+    let app = ax::UiElement::with_app_pid(pid);
+
+    // Get focused window via attribute
+    let focused_window_val = app.attr_value(ax::attr::focused_window()).ok()?;
+
+    // Cast to UiElement
+    if focused_window_val.get_type_id() == ax::UiElement::type_id() {
+        let focused_window: &ax::UiElement = unsafe { std::mem::transmute(&*focused_window_val) };
+        get_str_attr(focused_window, ax::attr::title())
+    } else {
+        None
+    }
+}
+
+// ============================================================================
+// Keycode Mapping
+// ============================================================================
+
+fn keycode_to_char(keycode: u16, mods: Modifiers) -> Option<char> {
+    let shift = mods.0 & Modifiers::SHIFT != 0 || mods.0 & Modifiers::CAPS != 0;
+
+    let c = match keycode {
+        // Letters
+        0 => 'a', 1 => 's', 2 => 'd', 3 => 'f', 4 => 'h', 5 => 'g', 6 => 'z', 7 => 'x',
+        8 => 'c', 9 => 'v', 11 => 'b', 12 => 'q', 13 => 'w', 14 => 'e', 15 => 'r',
+        16 => 'y', 17 => 't', 31 => 'o', 32 => 'u', 34 => 'i', 35 => 'p', 37 => 'l',
+        38 => 'j', 40 => 'k', 45 => 'n', 46 => 'm',
+        // Numbers
+        18 => if shift { '!' } else { '1' },
+        19 => if shift { '@' } else { '2' },
+        20 => if shift { '#' } else { '3' },
+        21 => if shift { '$' } else { '4' },
+        22 => if shift { '^' } else { '6' },
+        23 => if shift { '%' } else { '5' },
+        24 => if shift { '+' } else { '=' },
+        25 => if shift { '(' } else { '9' },
+        26 => if shift { '&' } else { '7' },
+        27 => if shift { '_' } else { '-' },
+        28 => if shift { '*' } else { '8' },
+        29 => if shift { ')' } else { '0' },
+        // Punctuation
+        30 => if shift { '}' } else { ']' },
+        33 => if shift { '{' } else { '[' },
+        39 => if shift { '"' } else { '\'' },
+        41 => if shift { ':' } else { ';' },
+        42 => if shift { '|' } else { '\\' },
+        43 => if shift { '<' } else { ',' },
+        44 => if shift { '?' } else { '/' },
+        47 => if shift { '>' } else { '.' },
+        50 => if shift { '~' } else { '`' },
+        // Whitespace
+        36 => '\n',
+        48 => '\t',
+        49 => ' ',
+        // Backspace - special handling
+        51 => '\x08',
+        _ => return None,
+    };
+
+    // Handle shift for letters
+    if shift && c.is_ascii_lowercase() {
+        Some(c.to_ascii_uppercase())
+    } else {
+        Some(c)
+    }
+}
@@ -0,0 +1,345 @@
+//! Input injection using CGEvent
+
+use crate::events::*;
+use crate::keys::Key;
+use crate::replay::Injector;
+use anyhow::Result;
+use std::time::Duration;
+
+use cidre::cg;
+
+// Raw FFI for CGEventPost and pointer/key state (not exposed by cidre)
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGEventPost(tap: u32, event: *const std::ffi::c_void);
+    fn CGEventCreate(source: *const std::ffi::c_void) -> *mut std::ffi::c_void;
+    fn CGEventGetLocation(event: *const std::ffi::c_void) -> cg::Point;
+    fn CGEventSourceKeyState(state: i32, key: u16) -> bool;
+    fn CFRelease(cf: *const std::ffi::c_void);
+    fn CGEventKeyboardSetUnicodeString(
+        event: *mut std::ffi::c_void,
+        length: usize,
+        string: *const u16,
+    );
+    fn CGEventCreateScrollWheelEvent2(
+        source: *const std::ffi::c_void,
+        units: u32,
+        wheel_count: u32,
+        wheel1: i32,
+        wheel2: i32,
+        wheel3: i32,
+    ) -> *mut std::ffi::c_void;
+}
+
+/// kCGScrollEventUnitPixel and kCGScrollEventUnitLine
+const SCROLL_UNIT_PIXEL: u32 = 0;
+const SCROLL_UNIT_LINE: u32 = 1;
+
+/// kCGEventSourceStateHIDSystemState: what the hardware reports
+const HID_SYSTEM_STATE: i32 = 1;
+
+/// Post an event to the system
+fn post_event(event: &cg::Event, location: u32) {
+    unsafe {
+        CGEventPost(location, event as *const _ as *const std::ffi::c_void);
+    }
+}
+
+const HID_EVENT_TAP: u32 = 0;
+
+/// UTF-16 units a key event carries at most
+const UNICODE_CHUNK: usize = 20;
+
+/// Injects input through CGEventPost
+#[derive(Debug, Default)]
+pub struct NativeInjector;
+
+impl NativeInjector {
+    /// Press and release a macOS virtual keycode
+    fn press(&self, keycode: u16, modifiers: u8) -> Result<()> {
+        post_key(keycode, true, modifiers);
+        std::thread::sleep(Duration::from_millis(10));
+        post_key(keycode, false, modifiers);
+        Ok(())
+    }
+}
+
+/// Packed `Modifiers` as event flags
+fn event_flags(modifiers: u8) -> cg::EventFlags {
+    let mut flags = cg::EventFlags(0);
+    if modifiers & Modifiers::SHIFT != 0 { flags.0 |= 0x20000; }
+    if modifiers & Modifiers::CTRL != 0 { flags.0 |= 0x40000; }
+    if modifiers & Modifiers::OPT != 0 { flags.0 |= 0x80000; }
+    if modifiers & Modifiers::CMD != 0 { flags.0 |= 0x100000; }
+    flags
+}
+
+/// Post a single key down or up with packed `Modifiers` as event flags
+fn post_key(keycode: u16, down: bool, modifiers: u8) {
+    if let Some(mut evt) = cg::Event::keyboard(None, keycode, down) {
+        evt.set_flags(event_flags(modifiers));
+        post_event(&evt, HID_EVENT_TAP);
+    }
+}
+
+/// Type `string` with a key down and up carrying it
+fn post_string(string: &[u16]) {
+    if string.is_empty() {
+        return;
+    }
+    for down in [true, false] {
+        if let Some(evt) = cg::Event::keyboard(None, 0, down) {
+            let raw = &*evt as *const cg::Event as *mut std::ffi::c_void;
+            unsafe { CGEventKeyboardSetUnicodeString(raw, string.len(), string.as_ptr()) };
+            post_event(&evt, HID_EVENT_TAP);
+        }
+    }
+}
+
+/// Post a mouse event with packed `Modifiers` as event flags, so a
+/// cmd-click stays one
+fn post_mouse(kind: cg::EventType, pos: cg::Point, button: cg::MouseButton, modifiers: u8) {
+    if let Some(mut evt) = cg::Event::mouse(None, kind, pos, button) {
+        evt.set_flags(event_flags(modifiers));
+        post_event(&evt, HID_EVENT_TAP);
+    }
+}
+
+impl Injector for NativeInjector {
+    fn click(&self, x: i32, y: i32, button: u8, clicks: u8, modifiers: u8) -> Result<()> {
+        let pos = cg::Point { x: x as f64, y: y as f64 };
+        let btn = match button {
+            0 => cg::MouseButton::Left,
+            1 => cg::MouseButton::Right,
+            _ => cg::MouseButton::Center,
+        };
+
+        let down_type = match button {
+            0 => cg::EventType::LEFT_MOUSE_DOWN,
+            1 => cg::EventType::RIGHT_MOUSE_DOWN,
+            _ => cg::EventType::OHTER_MOUSE_DOWN,
+        };
+        let up_type = match button {
+            0 => cg::EventType::LEFT_MOUSE_UP,
+            1 => cg::EventType::RIGHT_MOUSE_UP,
+            _ => cg::EventType::OHTER_MOUSE_UP,
+        };
+
+        for _ in 0..clicks.max(1) {
+            post_mouse(down_type, pos, btn, modifiers);
+            std::thread::sleep(Duration::from_millis(10));
+            post_mouse(up_type, pos, btn, modifiers);
+            if clicks > 1 {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn move_to(&self, x: i32, y: i32) -> Result<()> {
+        let pos = cg::Point { x: x as f64, y: y as f64 };
+        if let Some(evt) = cg::Event::mouse(None, cg::EventType::MOUSE_MOVED, pos, cg::MouseButton::Left) {
+            post_event(&evt, HID_EVENT_TAP);
+        }
+        Ok(())
+    }
+
+    fn scroll(&self, x: i32, y: i32, dx: i16, dy: i16, pixels: bool) -> Result<()> {
+        // Move to position first
+        self.move_to(x, y)?;
+
+        // cidre's wheel_2 takes unsigned deltas, which loses the direction
+        let units = if pixels { SCROLL_UNIT_PIXEL } else { SCROLL_UNIT_LINE };
+        unsafe {
+            let evt =
+                CGEventCreateScrollWheelEvent2(std::ptr::null(), units, 2, dy as i32, dx as i32, 0);
+            if evt.is_null() {
+                anyhow::bail!("Can't create a scroll event");
+            }
+            CGEventPost(HID_EVENT_TAP, evt);
+            CFRelease(evt);
+        }
+        Ok(())
+    }
+
+    fn drag(
+        &self,
+        path: &[(i32, i32)],
+        button: u8,
+        modifiers: u8,
+        duration: Duration,
+    ) -> Result<()> {
+        let Some((&(x, y), rest)) = path.split_first() else {
+            return Ok(());
+        };
+        let (btn, down_type, drag_type, up_type) = match button {
+            0 => (cg::MouseButton::Left, cg::EventType::LEFT_MOUSE_DOWN, cg::EventType::LEFT_MOUSE_DRAGGED, cg::EventType::LEFT_MOUSE_UP),
+            1 => (cg::MouseButton::Right, cg::EventType::RIGHT_MOUSE_DOWN, cg::EventType::RIGHT_MOUSE_DRAGGED, cg::EventType::RIGHT_MOUSE_UP),
+            _ => (cg::MouseButton::Center, cg::EventType::OHTER_MOUSE_DOWN, cg::EventType::OHTER_MOUSE_DRAGGED, cg::EventType::OHTER_MOUSE_UP),
+        };
+        let point = |x: i32, y: i32| cg::Point { x: x as f64, y: y as f64 };
+        let step = duration / rest.len().max(1) as u32;
+
+        post_mouse(down_type, point(x, y), btn, modifiers);
+        let mut last = (x, y);
+        for &(x, y) in rest {
+            std::thread::sleep(step);
+            post_mouse(drag_type, point(x, y), btn, modifiers);
+            last = (x, y);
+        }
+        post_mouse(up_type, point(last.0, last.1), btn, modifiers);
+        Ok(())
+    }
+
+    fn key(&self, key: Key, modifiers: u8) -> Result<()> {
+        self.press(key.to_macos(), modifiers)
+    }
+
+    fn key_down(&self, key: Key, modifiers: u8) -> Result<()> {
+        post_key(key.to_macos(), true, modifiers);
+        Ok(())
+    }
+
+    fn key_up(&self, key: Key, modifiers: u8) -> Result<()> {
+        post_key(key.to_macos(), false, modifiers);
+        Ok(())
+    }
+
+    /// As strings attached to key events, a chunk per event, so any
+    /// character types and nothing waits; returns and tabs as the keys, for
+    /// forms that act on them
+    fn type_text(&self, text: &str) -> Result<()> {
+        let mut chunk: Vec<u16> = Vec::new();
+        for c in text.chars() {
+            if c == '\n' || c == '\t' || chunk.len() + c.len_utf16() > UNICODE_CHUNK {
+                post_string(&chunk);
+                chunk.clear();
+            }
+            match (c, char_to_keycode(c)) {
+                ('\n' | '\t', Some((keycode, _))) => self.press(keycode, 0)?,
+                _ => chunk.extend(c.encode_utf16(&mut [0; 2]).iter()),
+            }
+        }
+        post_string(&chunk);
+        Ok(())
+    }
+
+    fn activate(&self, app: &str) -> Result<()> {
+        if !super::activate_app(app) {
+            anyhow::bail!("{} isn't running", app);
+        }
+        Ok(())
+    }
+
+    fn frontmost_app(&self) -> Option<String> {
+        let apps = cidre::ns::Workspace::shared().running_apps();
+        let app = apps.iter().find(|app| app.is_active())?;
+        Some(app.localized_name().map(|s| s.to_string()).unwrap_or_else(|| "?".to_string()))
+    }
+
+    fn element_at(&self, x: i32, y: i32) -> Option<ElementContext> {
+        super::recorder::element_context(x as f64, y as f64)
+    }
+
+    fn window_title(&self) -> Option<String> {
+        let apps = cidre::ns::Workspace::shared().running_apps();
+        let app = apps.iter().find(|app| app.is_active())?;
+        super::recorder::get_focused_window_title(app.pid())
+    }
+
+    fn element_ready(&self, x: i32, y: i32, context: &ElementContext) -> bool {
+        super::recorder::element_context(x as f64, y as f64)
+            .is_some_and(|found| context.matches(&found))
+    }
+
+    fn cursor_position(&self) -> Option<(i32, i32)> {
+        unsafe {
+            let event = CGEventCreate(std::ptr::null());
+            if event.is_null() {
+                return None;
+            }
+            let point = CGEventGetLocation(event);
+            CFRelease(event);
+            Some((point.x as i32, point.y as i32))
+        }
+    }
+
+    fn escape_pressed(&self) -> bool {
+        unsafe { CGEventSourceKeyState(HID_SYSTEM_STATE, Key::Escape.to_macos()) }
+    }
+
+    fn set_clipboard(&self, text: &str) -> Result<()> {
+        if !super::pasteboard::set_text(text) {
+            anyhow::bail!("Can't set the clipboard");
+        }
+        Ok(())
+    }
+
+    fn locate(&self, context: &ElementContext) -> Option<(i32, i32)> {
+        let (x, y, w, h) = super::recorder::find_element(context)?;
+        Some((x + w / 2, y + h / 2))
+    }
+
+    fn capture_screen(&self, path: &std::path::Path) -> Result<()> {
+        super::capture_screen(path, crate::platform::REPLAY_SCREENSHOT_SIZE)
+    }
+}
+
+/// Convert char to (keycode, needs_shift)
+fn char_to_keycode(c: char) -> Option<(u16, bool)> {
+    Some(match c {
+        'a' | 'A' => (0, c.is_uppercase()),
+        'b' | 'B' => (11, c.is_uppercase()),
+        'c' | 'C' => (8, c.is_uppercase()),
+        'd' | 'D' => (2, c.is_uppercase()),
+        'e' | 'E' => (14, c.is_uppercase()),
+        'f' | 'F' => (3, c.is_uppercase()),
+        'g' | 'G' => (5, c.is_uppercase()),
+        'h' | 'H' => (4, c.is_uppercase()),
+        'i' | 'I' => (34, c.is_uppercase()),
+        'j' | 'J' => (38, c.is_uppercase()),
+        'k' | 'K' => (40, c.is_uppercase()),
+        'l' | 'L' => (37, c.is_uppercase()),
+        'm' | 'M' => (46, c.is_uppercase()),
+        'n' | 'N' => (45, c.is_uppercase()),
+        'o' | 'O' => (31, c.is_uppercase()),
+        'p' | 'P' => (35, c.is_uppercase()),
+        'q' | 'Q' => (12, c.is_uppercase()),
+        'r' | 'R' => (15, c.is_uppercase()),
+        's' | 'S' => (1, c.is_uppercase()),
+        't' | 'T' => (17, c.is_uppercase()),
+        'u' | 'U' => (32, c.is_uppercase()),
+        'v' | 'V' => (9, c.is_uppercase()),
+        'w' | 'W' => (13, c.is_uppercase()),
+        'x' | 'X' => (7, c.is_uppercase()),
+        'y' | 'Y' => (16, c.is_uppercase()),
+        'z' | 'Z' => (6, c.is_uppercase()),
+        '0' | ')' => (29, c == ')'),
+        '1' | '!' => (18, c == '!'),
+        '2' | '@' => (19, c == '@'),
+        '3' | '#' => (20, c == '#'),
+        '4' | '$' => (21, c == '$'),
+        '5' | '%' => (23, c == '%'),
+        '6' | '^' => (22, c == '^'),
+        '7' | '&' => (26, c == '&'),
+        '8' | '*' => (28, c == '*'),
+        '9' | '(' => (25, c == '('),
+        ' ' => (49, false),
+        '\n' => (36, false),
+        '\t' => (48, false),
+        '\x08' => (51, false), // backspace
+        '-' | '_' => (27, c == '_'),
+        '=' | '+' => (24, c == '+'),
+        '[' | '{' => (33, c == '{'),
+        ']' | '}' => (30, c == '}'),
+        '\\' | '|' => (42, c == '|'),
+        ';' | ':' => (41, c == ':'),
+        '\'' | '"' => (39, c == '"'),
+        ',' | '<' => (43, c == '<'),
+        '.' | '>' => (47, c == '>'),
+        '/' | '?' => (44, c == '?'),
+        '`' | '~' => (50, c == '~'),
+        _ => return None,
+    })
+}
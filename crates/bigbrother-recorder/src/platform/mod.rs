@@ -11,6 +11,14 @@ pub mod linux;
 #[cfg(target_os = "windows")]
 pub mod windows;
 
+/// Roughly how far one wheel line scrolls, to replay pixel scrolls where
+/// the OS takes wheel units
+#[cfg(not(target_os = "macos"))]
+pub(crate) const PIXELS_PER_LINE: i32 = 10;
+
+/// Longest side of screenshots taken when a replay fails
+pub(crate) const REPLAY_SCREENSHOT_SIZE: u32 = 1920;
+
 // Re-export the current platform
 #[cfg(target_os = "macos")]
 pub use macos as current;
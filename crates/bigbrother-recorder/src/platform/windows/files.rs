@@ -0,0 +1,177 @@
+//! File change events via ReadDirectoryChangesW
+//!
+//! Every watched directory has an overlapped read pending; one thread waits
+//! on all of them with a short timeout so the stop flag is honoured.
+
+use crate::events::{Event, EventData};
+use crate::recorder::CaptureContext;
+use std::collections::HashMap;
+use std::os::windows::ffi::OsStrExt;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{CloseHandle, BOOL, HANDLE, WAIT_OBJECT_0};
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, ReadDirectoryChangesW, FILE_ACTION_ADDED, FILE_ACTION_MODIFIED,
+    FILE_ACTION_REMOVED, FILE_ACTION_RENAMED_NEW_NAME, FILE_ACTION_RENAMED_OLD_NAME,
+    FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OVERLAPPED, FILE_LIST_DIRECTORY,
+    FILE_NOTIFY_CHANGE_DIR_NAME, FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE,
+    FILE_NOTIFY_INFORMATION, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+};
+use windows::Win32::System::Threading::{CreateEventW, WaitForMultipleObjects};
+use windows::Win32::System::IO::{CancelIoEx, GetOverlappedResult, OVERLAPPED};
+
+/// Writes fire a burst of modifications; one per file per this long is kept
+const MODIFIED_QUIET: Duration = Duration::from_secs(1);
+
+struct Dir {
+    path: PathBuf,
+    handle: HANDLE,
+    overlapped: Box<OVERLAPPED>,
+    /// DWORD aligned, as FILE_NOTIFY_INFORMATION requires
+    buf: Box<[u32; 4096]>,
+}
+
+impl Dir {
+    fn open(path: PathBuf) -> windows::core::Result<Self> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+        unsafe {
+            let handle = CreateFileW(
+                PCWSTR(wide.as_ptr()),
+                FILE_LIST_DIRECTORY.0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+                None,
+                OPEN_EXISTING,
+                FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OVERLAPPED,
+                HANDLE::default(),
+            )?;
+            // Manual reset, as overlapped I/O expects
+            let event = CreateEventW(None, BOOL::from(true), BOOL::from(false), PCWSTR::null());
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    let _ = CloseHandle(handle);
+                    return Err(e);
+                }
+            };
+            let overlapped = Box::new(OVERLAPPED { hEvent: event, ..Default::default() });
+            Ok(Self { path, handle, overlapped, buf: Box::new([0; 4096]) })
+        }
+    }
+
+    /// Queue the next read; completion signals `overlapped.hEvent`
+    fn read(&mut self) -> windows::core::Result<()> {
+        unsafe {
+            ReadDirectoryChangesW(
+                self.handle,
+                self.buf.as_mut_ptr().cast(),
+                std::mem::size_of_val(&*self.buf) as u32,
+                BOOL::from(true),
+                FILE_NOTIFY_CHANGE_FILE_NAME
+                    | FILE_NOTIFY_CHANGE_DIR_NAME
+                    | FILE_NOTIFY_CHANGE_LAST_WRITE,
+                None,
+                Some(&mut *self.overlapped as *mut OVERLAPPED),
+                None,
+            )
+        }
+    }
+
+    /// Changes from the completed read, as (path, operation)
+    fn changes(&self) -> Vec<(PathBuf, char)> {
+        let mut len = 0;
+        let wait = BOOL::from(false);
+        if unsafe { GetOverlappedResult(self.handle, &*self.overlapped, &mut len, wait) }.is_err() {
+            return Vec::new();
+        }
+        let mut changes = Vec::new();
+        let base = self.buf.as_ptr() as *const u8;
+        let mut offset = 0;
+        // `len` is 0 when the buffer overflowed and the changes were lost
+        while len > 0 {
+            let info = unsafe { &*(base.add(offset) as *const FILE_NOTIFY_INFORMATION) };
+            let name = unsafe {
+                std::slice::from_raw_parts(info.FileName.as_ptr(), info.FileNameLength as usize / 2)
+            };
+            let o = match info.Action {
+                FILE_ACTION_ADDED => Some('c'),
+                FILE_ACTION_MODIFIED => Some('m'),
+                FILE_ACTION_REMOVED | FILE_ACTION_RENAMED_OLD_NAME => Some('d'),
+                FILE_ACTION_RENAMED_NEW_NAME => Some('r'),
+                _ => None,
+            };
+            if let Some(o) = o {
+                changes.push((self.path.join(String::from_utf16_lossy(name)), o));
+            }
+            if info.NextEntryOffset == 0 {
+                break;
+            }
+            offset += info.NextEntryOffset as usize;
+        }
+        changes
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = CancelIoEx(self.handle, None);
+            let _ = CloseHandle(self.handle);
+            let _ = CloseHandle(self.overlapped.hEvent);
+        }
+    }
+}
+
+pub(crate) fn run_file_watcher(ctx: CaptureContext) {
+    let mut dirs: Vec<Dir> = ctx
+        .config
+        .watch_dirs
+        .iter()
+        .filter_map(|path| {
+            let mut dir = Dir::open(path.clone())
+                .inspect_err(|e| {
+                    tracing::warn!(dir = %path.display(), error = %e, "Can't watch directory")
+                })
+                .ok()?;
+            dir.read().ok()?;
+            Some(dir)
+        })
+        .collect();
+    if dirs.is_empty() {
+        return;
+    }
+    let mut modified: HashMap<PathBuf, Instant> = HashMap::new();
+
+    while !ctx.stop.load(Ordering::Relaxed) {
+        let events: Vec<HANDLE> = dirs.iter().map(|d| d.overlapped.hEvent).collect();
+        let ready = unsafe { WaitForMultipleObjects(&events, BOOL::from(false), 100) };
+        let i = ready.0.wrapping_sub(WAIT_OBJECT_0.0) as usize;
+        let Some(dir) = dirs.get_mut(i) else {
+            continue;
+        };
+
+        for (path, o) in dir.changes() {
+            if o == 'm' {
+                let now = Instant::now();
+                match modified.get(&path) {
+                    Some(at) if now.duration_since(*at) < MODIFIED_QUIET => continue,
+                    _ => modified.insert(path.clone(), now),
+                };
+            }
+            let _ = ctx.tx.try_send(Event {
+                t: ctx.start.elapsed().as_millis() as u64,
+                ts: None,
+                data: EventData::FileChanged { f: path.to_string_lossy().into_owned(), o },
+            });
+        }
+        if let Err(e) = dir.read() {
+            tracing::warn!(dir = %dir.path.display(), error = %e, "Stopped watching directory");
+            dirs.remove(i);
+            if dirs.is_empty() {
+                return;
+            }
+        }
+    }
+}
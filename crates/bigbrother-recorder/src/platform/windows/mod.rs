@@ -1,19 +1,36 @@
 //! Windows recording implementation
 //!
-//! Uses rdev for global event capture and Windows APIs for context.
+//! Uses low-level keyboard/mouse hooks for global event capture and Windows
+//! APIs for context.
 
+mod files;
 mod recorder;
 mod replay;
 
 pub use recorder::*;
 pub use replay::*;
 
+use std::sync::Once;
+use windows::Win32::UI::HiDpi::{
+    SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+};
 use windows::Win32::UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowTextW, GetWindowThreadProcessId};
 use windows::Win32::System::Diagnostics::ToolHelp::{
     CreateToolhelp32Snapshot, Process32FirstW, Process32NextW,
     PROCESSENTRY32W, TH32CS_SNAPPROCESS,
 };
 
+static DPI_AWARE: Once = Once::new();
+
+/// Declare the process per-monitor DPI aware, so hook coordinates and
+/// SetCursorPos both use physical pixels on scaled monitors
+pub fn enable_dpi_awareness() {
+    DPI_AWARE.call_once(|| unsafe {
+        // Fails if the awareness was already set (e.g. by a manifest), which is fine
+        let _ = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+    });
+}
+
 /// Get the currently focused window title
 pub fn get_focused_window_title() -> Option<String> {
     unsafe {
@@ -79,6 +96,73 @@ pub fn get_process_name(pid: u32) -> Option<String> {
     }
 }
 
+/// PIDs of running processes with this executable name
+fn process_ids(name: &str) -> Vec<u32> {
+    let mut pids = Vec::new();
+    unsafe {
+        let Ok(snapshot) = CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) else {
+            return pids;
+        };
+        let mut entry = PROCESSENTRY32W {
+            dwSize: std::mem::size_of::<PROCESSENTRY32W>() as u32,
+            ..Default::default()
+        };
+        if Process32FirstW(snapshot, &mut entry).is_ok() {
+            loop {
+                let len = entry.szExeFile.iter().position(|&c| c == 0).unwrap_or(entry.szExeFile.len());
+                if String::from_utf16_lossy(&entry.szExeFile[..len]).eq_ignore_ascii_case(name) {
+                    pids.push(entry.th32ProcessID);
+                }
+                if Process32NextW(snapshot, &mut entry).is_err() {
+                    break;
+                }
+            }
+        }
+        let _ = windows::Win32::Foundation::CloseHandle(snapshot);
+    }
+    pids
+}
+
+/// Bring a visible window of the process with this executable name (as App
+/// events record it) to the front; false if there's none
+pub(crate) fn activate_app(app: &str) -> bool {
+    use windows::Win32::Foundation::{BOOL, HWND, LPARAM};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, IsIconic, IsWindowVisible, SetForegroundWindow, ShowWindow, SW_RESTORE,
+    };
+
+    struct Search {
+        pids: Vec<u32>,
+        found: Option<HWND>,
+    }
+
+    unsafe extern "system" fn callback(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let search = &mut *(lparam.0 as *mut Search);
+        let mut pid: u32 = 0;
+        GetWindowThreadProcessId(hwnd, Some(&mut pid));
+        if search.pids.contains(&pid) && IsWindowVisible(hwnd).as_bool() {
+            search.found = Some(hwnd);
+            return BOOL(0); // stop enumerating
+        }
+        BOOL(1)
+    }
+
+    let mut search = Search { pids: process_ids(app), found: None };
+    if search.pids.is_empty() {
+        return false;
+    }
+    unsafe {
+        let _ = EnumWindows(Some(callback), LPARAM(&mut search as *mut Search as isize));
+        let Some(hwnd) = search.found else {
+            return false;
+        };
+        if IsIconic(hwnd).as_bool() {
+            let _ = ShowWindow(hwnd, SW_RESTORE);
+        }
+        SetForegroundWindow(hwnd).as_bool()
+    }
+}
+
 /// Get current focused app info
 pub fn get_focused_app() -> Option<(String, u32, Option<String>)> {
     let pid = get_focused_window_pid()?;
@@ -86,3 +170,150 @@ pub fn get_focused_app() -> Option<(String, u32, Option<String>)> {
     let title = get_focused_window_title();
     Some((name, pid, title))
 }
+
+/// Active tab URL when `app` is a known browser, read from the address bar
+/// through UI Automation
+pub(crate) fn browser_url(app: &str) -> Option<String> {
+    use windows::core::VARIANT;
+    use windows::Win32::System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED};
+    use windows::Win32::UI::Accessibility::{
+        CUIAutomation, IUIAutomation, IUIAutomationValuePattern, TreeScope_Descendants,
+        UIA_ControlTypePropertyId, UIA_EditControlTypeId, UIA_ValuePatternId,
+    };
+
+    const BROWSERS: &[&str] = &["chrome.exe", "msedge.exe", "firefox.exe", "brave.exe", "opera.exe", "vivaldi.exe"];
+    if !BROWSERS.iter().any(|b| app.eq_ignore_ascii_case(b)) {
+        return None;
+    }
+    unsafe {
+        // Already initialised on this thread is fine
+        let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+        let automation: IUIAutomation = CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER).ok()?;
+        let window = automation.ElementFromHandle(GetForegroundWindow()).ok()?;
+        let condition = automation
+            .CreatePropertyCondition(UIA_ControlTypePropertyId, &VARIANT::from(UIA_EditControlTypeId.0))
+            .ok()?;
+        // The first edit field in a browser window is its address bar
+        let bar = window.FindFirst(TreeScope_Descendants, &condition).ok()?;
+        let value = bar.GetCurrentPatternAs::<IUIAutomationValuePattern>(UIA_ValuePatternId).ok()?;
+        let url = value.CurrentValue().ok()?.to_string();
+        (!url.is_empty()).then_some(url)
+    }
+}
+
+/// Windows version as reported by `ver`, e.g. "10.0.22631.4317"
+pub(crate) fn os_version() -> Option<String> {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let out = std::process::Command::new("cmd")
+        .args(["/C", "ver"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .ok()?;
+    let ver = String::from_utf8_lossy(&out.stdout);
+    let version = ver.split("Version ").nth(1)?.trim().trim_end_matches(']');
+    Some(version.to_string())
+}
+
+/// Monitors in physical pixels (the recorder is per-monitor DPI aware),
+/// scale being effective DPI / 96
+pub(crate) fn displays() -> Vec<crate::events::DisplayInfo> {
+    use windows::Win32::Foundation::{BOOL, LPARAM, RECT};
+    use windows::Win32::Graphics::Gdi::{EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO};
+    use windows::Win32::UI::HiDpi::{GetDpiForMonitor, MDT_EFFECTIVE_DPI};
+
+    unsafe extern "system" fn add(monitor: HMONITOR, _: HDC, _: *mut RECT, data: LPARAM) -> BOOL {
+        let displays = &mut *(data.0 as *mut Vec<crate::events::DisplayInfo>);
+        let mut info = MONITORINFO {
+            cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+            ..Default::default()
+        };
+        if GetMonitorInfoW(monitor, &mut info).as_bool() {
+            let (mut dpi_x, mut dpi_y) = (96, 96);
+            let _ = GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y);
+            let r = info.rcMonitor;
+            displays.push(crate::events::DisplayInfo {
+                x: r.left,
+                y: r.top,
+                w: (r.right - r.left) as u32,
+                h: (r.bottom - r.top) as u32,
+                scale: dpi_x as f64 / 96.0,
+            });
+        }
+        BOOL(1)
+    }
+
+    enable_dpi_awareness();
+    let mut displays = Vec::new();
+    unsafe {
+        let _ = EnumDisplayMonitors(HDC::default(), None, Some(add), LPARAM(&mut displays as *mut _ as isize));
+    }
+    displays
+}
+
+/// Active keyboard layout id, e.g. "00000409" for US English
+pub(crate) fn keyboard_layout() -> Option<String> {
+    use windows::Win32::UI::Input::KeyboardAndMouse::GetKeyboardLayoutNameW;
+
+    let mut name = [0u16; 9];
+    unsafe { GetKeyboardLayoutNameW(&mut name) }.ok()?;
+    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    Some(String::from_utf16_lossy(&name[..len]))
+}
+
+/// Ask for a line of text in a dialog; `None` when cancelled
+pub(crate) fn prompt_text(message: &str) -> anyhow::Result<Option<String>> {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let script = format!(
+        "Add-Type -AssemblyName Microsoft.VisualBasic; [Microsoft.VisualBasic.Interaction]::InputBox('{}', 'bigbrother')",
+        message.replace('\'', "''"),
+    );
+    let out = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-Command", &script])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()?;
+    // InputBox returns an empty string when cancelled
+    let text = String::from_utf8_lossy(&out.stdout).into_owned();
+    Ok((out.status.success() && !text.trim().is_empty()).then_some(text))
+}
+
+/// Capture the primary screen as a JPEG, longest side at most `max_size`
+pub(crate) fn capture_screen(path: &std::path::Path, max_size: u32) -> anyhow::Result<()> {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let script = format!(
+        r#"Add-Type -AssemblyName System.Windows.Forms, System.Drawing; $b = [System.Windows.Forms.Screen]::PrimaryScreen.Bounds; $bmp = New-Object System.Drawing.Bitmap($b.Width, $b.Height); [System.Drawing.Graphics]::FromImage($bmp).CopyFromScreen($b.Location, [System.Drawing.Point]::Empty, $b.Size); $s = [Math]::Min(1.0, {max} / [Math]::Max($b.Width, $b.Height)); $out = New-Object System.Drawing.Bitmap($bmp, [int]($b.Width * $s), [int]($b.Height * $s)); $out.Save('{path}', [System.Drawing.Imaging.ImageFormat]::Jpeg)"#,
+        max = max_size,
+        path = path.display().to_string().replace('\'', "''"),
+    );
+    let status = std::process::Command::new("powershell")
+        .args(["-NoProfile", "-Command", &script])
+        .creation_flags(CREATE_NO_WINDOW)
+        .status()?;
+    if !status.success() {
+        anyhow::bail!("screenshot capture failed");
+    }
+    Ok(())
+}
+
+/// Screen recorder for the `video` feature: ffmpeg's DXGI desktop duplication
+/// source, which reports frames (see [`crate::video`])
+#[cfg(feature = "video")]
+pub(crate) fn video_command(
+    path: &std::path::Path,
+) -> anyhow::Result<(std::process::Command, bool)> {
+    use std::os::windows::process::CommandExt;
+
+    const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+    let mut cmd = std::process::Command::new("ffmpeg");
+    cmd.args(["-hide_banner", "-y", "-f", "lavfi", "-i", "ddagrab=framerate=10"])
+        .args(["-vf", "hwdownload,format=bgra", "-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        .args(crate::video::FFMPEG_PROGRESS)
+        .arg(path)
+        .creation_flags(CREATE_NO_WINDOW);
+    Ok((cmd, true))
+}
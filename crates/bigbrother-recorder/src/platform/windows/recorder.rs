@@ -1,477 +1,622 @@
-//! Windows event recorder using rdev
+//! Windows event recorder using low-level hooks
 //!
-//! Captures global keyboard and mouse events.
+//! Installs WH_KEYBOARD_LL and WH_MOUSE_LL hooks on a dedicated thread that
+//! pumps messages until the session is stopped. The hooks only pass what
+//! they see on: Windows removes hooks that take too long, so events, and
+//! the UI Automation lookups for clicks, are made on an input thread.
 
+use crate::error::RecorderError;
 use crate::events::*;
+use crate::keys::{Hotkey, Key};
+use crate::recorder::{
+    CaptureContext, EventSink, GestureTracker, KeyTracker, Permission, PermissionState,
+    PermissionStatus, RecorderConfig,
+};
+use crate::text::truncate;
 use anyhow::Result;
-use crossbeam_channel::{bounded, Receiver, Sender};
+use std::cell::RefCell;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
 use std::thread;
-use std::time::Instant;
-
-/// Recorder configuration
-#[derive(Debug, Clone)]
-pub struct RecorderConfig {
-    /// Mouse move sampling - record every N pixels moved
-    pub mouse_move_threshold: f64,
-    /// Text aggregation timeout in ms
-    pub text_timeout_ms: u64,
-    /// Max events before auto-flush
-    pub max_buffer: usize,
-    /// Capture element context on clicks
-    pub capture_context: bool,
+use std::time::{Duration, Instant};
+
+use windows::core::{Interface, BSTR};
+use windows::Win32::Foundation::{BOOL, HINSTANCE, HWND, LPARAM, LRESULT, POINT, WPARAM};
+use windows::Win32::System::Com::{
+    CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED,
+};
+use windows::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows::Win32::UI::Accessibility::{
+    CUIAutomation, CUIAutomation8, IUIAutomation, IUIAutomation2, IUIAutomationValuePattern,
+    UIA_ValuePatternId,
+};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    GetDoubleClickTime, GetKeyState, GetKeyboardLayout, RegisterHotKey, ToUnicodeEx,
+    UnregisterHotKey, HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN,
+    VK_CAPITAL, VK_SHIFT,
+};
+use windows::Win32::UI::WindowsAndMessaging::{
+    CallNextHookEx, DispatchMessageW, GetForegroundWindow, GetGUIThreadInfo, GetWindowLongW,
+    GetWindowThreadProcessId, GUITHREADINFO, GWL_STYLE, MsgWaitForMultipleObjects, PeekMessageW, SetWindowsHookExW,
+    TranslateMessage, UnhookWindowsHookEx, HHOOK, KBDLLHOOKSTRUCT, MSG, MSLLHOOKSTRUCT,
+    PM_REMOVE, QS_ALLINPUT, WH_KEYBOARD_LL, WH_MOUSE_LL, WM_KEYDOWN, WM_KEYUP, WM_LBUTTONDOWN,
+    WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSEMOVE, WM_MOUSEWHEEL,
+    WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
+};
+
+pub fn check_permissions() -> PermissionStatus {
+    // Windows doesn't require explicit permissions
+    PermissionStatus::new(
+        Permission::new("accessibility", PermissionState::Granted),
+        Permission::new("input_monitoring", PermissionState::Granted),
+        Permission::new("screen_recording", PermissionState::Granted),
+    )
 }
 
-impl Default for RecorderConfig {
-    fn default() -> Self {
-        Self {
-            mouse_move_threshold: 5.0,
-            text_timeout_ms: 300,
-            max_buffer: 10000,
-            capture_context: false, // Disabled by default on Windows for now
-        }
-    }
-}
-
-/// Permission status
-#[derive(Debug, Clone)]
-pub struct PermissionStatus {
-    pub accessibility: bool,
-    pub input_monitoring: bool,
+pub fn request_permissions(_screen: bool) -> PermissionStatus {
+    check_permissions()
 }
 
-impl PermissionStatus {
-    pub fn all_granted(&self) -> bool {
-        self.accessibility && self.input_monitoring
+/// Spawn the capture threads for a session
+pub(crate) fn start_capture(
+    ctx: CaptureContext,
+) -> Result<Vec<thread::JoinHandle<()>>, RecorderError> {
+    super::enable_dpi_awareness();
+    let mut threads = Vec::new();
+
+    // Thread 1: input thread, turning what the hooks see into events
+    let state = InputState {
+        tx: ctx.tx.clone(),
+        config: ctx.config.clone(),
+        last_move: (0, 0),
+        mods: 0,
+        last_click: None,
+        double_click_ms: unsafe { GetDoubleClickTime() } as u64,
+        context_paused_until: 0,
+        gestures: GestureTracker::new(&ctx.config),
+        keys: KeyTracker::new(&ctx.config),
+    };
+    let (inputs, queue) = mpsc::channel();
+    threads.push(thread::spawn(move || run_input_thread(state, queue)));
+
+    // Thread 2: hook thread (hooks are delivered to the installing thread)
+    let hook_ctx = ctx.clone();
+    let (ready, hooks_ready) = mpsc::channel();
+    threads.push(thread::spawn(move || {
+        if let Err(e) = run_hook_thread(hook_ctx, inputs, &ready) {
+            let error = format!("Failed to install input hooks: {}", e);
+            let _ = ready.send(Err(RecorderError::TapCreationFailed(error)));
+        }
+    }));
+    // Nothing else starts without input
+    hooks_ready.recv().unwrap_or_else(|_| {
+        Err(RecorderError::TapCreationFailed("The input hook thread exited".into()))
+    })?;
+
+    // Thread 3: File changes in `watch_dirs`
+    if !ctx.config.watch_dirs.is_empty() {
+        let files_ctx = ctx.clone();
+        threads.push(thread::spawn(move || super::files::run_file_watcher(files_ctx)));
     }
-}
 
-/// Recording handle
-pub struct RecordingHandle {
-    stop: Arc<AtomicBool>,
-    events_rx: Receiver<Event>,
-    threads: Vec<thread::JoinHandle<()>>,
+    // Thread 4: App/window observer
+    threads.push(thread::spawn(move || {
+        run_app_observer(ctx.tx, ctx.stop, ctx.start);
+    }));
+
+    Ok(threads)
 }
 
-impl RecordingHandle {
-    pub fn stop(self, workflow: &mut RecordedWorkflow) {
-        self.stop.store(true, Ordering::SeqCst);
-        while let Ok(e) = self.events_rx.try_recv() {
-            workflow.events.push(e);
-        }
-        for t in self.threads {
-            let _ = t.join();
-        }
-    }
+// ============================================================================
+// Low-level Hooks
+// ============================================================================
 
-    pub fn drain(&self, workflow: &mut RecordedWorkflow) {
-        while let Ok(e) = self.events_rx.try_recv() {
-            workflow.events.push(e);
-        }
-    }
+const LLKHF_INJECTED: u32 = 0x10;
+const MAX_DOUBLE_CLICK_DISTANCE: i32 = 4;
 
-    pub fn is_running(&self) -> bool {
-        !self.stop.load(Ordering::Relaxed)
-    }
+/// Longest a UI Automation call for a click's context may take (ms); the
+/// input thread waits for it, the hooks never do
+const CONTEXT_WAIT_MS: u32 = 250;
 
-    pub fn receiver(&self) -> &Receiver<Event> {
-        &self.events_rx
-    }
+/// Clicks recorded without context after a lookup timed out (ms), so a hung
+/// app doesn't cost every click the full wait
+const CONTEXT_PAUSE_MS: u64 = 5000;
 
-    pub fn try_recv(&self) -> Option<Event> {
-        self.events_rx.try_recv().ok()
-    }
+/// Input as the hooks saw it, stamped with its time since the session
+/// started
+enum Input {
+    Key { t: u64, msg: u32, vk: u32, scan: u32, injected: bool, caps_on: bool },
+    Mouse { t: u64, msg: u32, pos: (i32, i32), data: u32 },
+}
 
-    pub fn recv(&self) -> Option<Event> {
-        self.events_rx.recv().ok()
-    }
+thread_local! {
+    // Hook procs are plain functions, so they find the session through the
+    // hook thread's local state
+    static HOOK: RefCell<Option<(mpsc::Sender<Input>, Instant)>> = const { RefCell::new(None) };
+}
 
-    pub fn recv_timeout(&self, timeout: std::time::Duration) -> Option<Event> {
-        self.events_rx.recv_timeout(timeout).ok()
-    }
+/// Hand what a hook saw to the input thread
+fn forward(input: impl FnOnce(u64) -> Input) {
+    HOOK.with(|hook| {
+        if let Some((tx, start)) = hook.borrow().as_ref() {
+            let _ = tx.send(input(start.elapsed().as_millis() as u64));
+        }
+    });
 }
 
-/// Event stream for consuming events
-pub struct EventStream {
-    stop: Arc<AtomicBool>,
-    events_rx: Receiver<Event>,
-    threads: Vec<thread::JoinHandle<()>>,
+/// Turns hook input into events on its own thread, so UI Automation lookups
+/// can't slow the hooks down enough for Windows to remove them
+struct InputState {
+    tx: EventSink,
+    config: RecorderConfig,
+    last_move: (i32, i32),
+    mods: u8,
+    last_click: Option<(u64, u8, (i32, i32), u8)>,
+    double_click_ms: u64,
+    context_paused_until: u64,
+    gestures: GestureTracker,
+    keys: KeyTracker,
 }
 
-impl EventStream {
-    pub fn stop(self) {
-        self.stop.store(true, Ordering::SeqCst);
-        for t in self.threads {
-            let _ = t.join();
+impl InputState {
+    fn send(&self, t: u64, data: EventData) {
+        let _ = self.tx.try_send(Event { t, ts: None, data });
+    }
+
+    fn handle(&mut self, input: Input) {
+        match input {
+            Input::Key { t, msg, vk, scan, injected, caps_on } => {
+                self.on_key_input(t, msg, vk, scan, injected, caps_on)
+            }
+            Input::Mouse { t, msg, pos, data } => self.on_mouse_input(t, msg, pos, data),
         }
     }
 
-    pub fn is_running(&self) -> bool {
-        !self.stop.load(Ordering::Relaxed)
+    fn on_key_input(&mut self, t: u64, msg: u32, vk: u32, scan: u32, injected: bool, caps_on: bool) {
+        let down = msg == WM_KEYDOWN || msg == WM_SYSKEYDOWN;
+        let up = msg == WM_KEYUP || msg == WM_SYSKEYUP;
+        if let Some(bit) = modifier_bit(vk) {
+            if (down || up) && !injected {
+                let key = Key::from_windows(vk as u16);
+                if let Some(data) = self.keys.modifier(t, key, down, self.mods & !bit) {
+                    self.send(t, data);
+                }
+            }
+            if down {
+                self.mods |= bit;
+            } else if up {
+                self.mods &= !bit;
+            }
+            return;
+        }
+        if vk == VK_CAPITAL.0 as u32 {
+            if down && !caps_on {
+                self.mods |= Modifiers::CAPS;
+            } else if down {
+                self.mods &= !Modifiers::CAPS;
+            }
+            return;
+        }
+        if down && !injected {
+            self.on_key(t, vk, scan);
+        } else if up && !injected {
+            self.on_key_up(t, vk);
+        }
     }
 
-    pub fn receiver(&self) -> &Receiver<Event> {
-        &self.events_rx
+    fn on_mouse_input(&mut self, t: u64, msg: u32, pos: (i32, i32), data: u32) {
+        match msg {
+            WM_LBUTTONDOWN => self.on_button(t, 0, pos),
+            WM_RBUTTONDOWN => self.on_button(t, 1, pos),
+            WM_MBUTTONDOWN => self.on_button(t, 2, pos),
+            WM_LBUTTONUP => self.on_release(t, 0, pos),
+            WM_RBUTTONUP => self.on_release(t, 1, pos),
+            WM_MBUTTONUP => self.on_release(t, 2, pos),
+            WM_MOUSEMOVE => self.on_move(t, pos),
+            WM_MOUSEWHEEL | WM_MOUSEHWHEEL => {
+                // High word is a signed delta in WHEEL_DELTA (120) units
+                let delta = (data >> 16) as u16 as i16;
+                let notches = match delta / 120 {
+                    0 => delta.signum(),
+                    n => n,
+                };
+                let (dx, dy) = if msg == WM_MOUSEWHEEL { (0, notches) } else { (notches, 0) };
+                self.send(t, EventData::Scroll { x: pos.0, y: pos.1, dx, dy, px: false });
+            }
+            _ => {}
+        }
     }
 
-    pub fn try_recv(&self) -> Option<Event> {
-        self.events_rx.try_recv().ok()
+    fn on_button(&mut self, t: u64, b: u8, (x, y): (i32, i32)) {
+        // The click may move focus; what was typed goes first
+        self.tx.flush_text();
+        let n = match self.last_click {
+            Some((at, lb, (lx, ly), ln))
+                if lb == b
+                    && t.saturating_sub(at) < self.double_click_ms
+                    && (lx - x).abs() <= MAX_DOUBLE_CLICK_DISTANCE
+                    && (ly - y).abs() <= MAX_DOUBLE_CLICK_DISTANCE =>
+            {
+                ln.saturating_add(1)
+            }
+            _ => 1,
+        };
+        self.last_click = Some((t, b, (x, y), n));
+        let context = if self.config.capture_context && t >= self.context_paused_until {
+            let asked = Instant::now();
+            let context = with_automation(|automation| element_context(automation, x, y));
+            if context.is_none() && asked.elapsed().as_millis() >= CONTEXT_WAIT_MS as u128 {
+                let ms = CONTEXT_WAIT_MS;
+                tracing::warn!(ms, "Click context timed out, pausing it for 5s");
+                self.context_paused_until = t + CONTEXT_PAUSE_MS;
+            }
+            context.map(Box::new)
+        } else {
+            None
+        };
+        if let Some(event) = self.gestures.press(t, (x, y), b, n, self.mods, context) {
+            let _ = self.tx.try_send(event);
+        }
     }
 
-    pub fn recv(&self) -> Option<Event> {
-        self.events_rx.recv().ok()
+    fn on_release(&mut self, t: u64, b: u8, pos: (i32, i32)) {
+        if let Some(event) = self.gestures.release(t, pos, b) {
+            let _ = self.tx.try_send(event);
+        }
     }
 
-    pub fn recv_timeout(&self, timeout: std::time::Duration) -> Option<Event> {
-        self.events_rx.recv_timeout(timeout).ok()
+    fn on_move(&mut self, t: u64, (x, y): (i32, i32)) {
+        if self.gestures.moved((x, y)) {
+            self.last_move = (x, y);
+            return;
+        }
+        let dx = (x - self.last_move.0) as f64;
+        let dy = (y - self.last_move.1) as f64;
+        if (dx * dx + dy * dy).sqrt() >= self.config.mouse_move_threshold {
+            self.last_move = (x, y);
+            self.send(t, EventData::Move { x, y });
+        }
     }
-}
 
-impl Iterator for EventStream {
-    type Item = Event;
+    fn on_key(&mut self, t: u64, vk: u32, scan: u32) {
+        let mods = Modifiers(self.mods);
+        let key = Key::from_windows(vk as u16);
+        let typed = !(mods.any_modifier() || mods.0 & Modifiers::OPT != 0)
+            && key_to_char(vk, scan, self.mods).is_some_and(|c| self.tx.type_char(c));
+        if !typed {
+            self.send(t, EventData::Key { k: key, m: self.mods });
+        }
+        self.keys.press(t, key, !typed);
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.stop.load(Ordering::Relaxed) {
-            return None;
+    fn on_key_up(&mut self, t: u64, vk: u32) {
+        let key = Key::from_windows(vk as u16);
+        if let Some(data) = self.keys.release(t, key, self.mods) {
+            self.send(t, data);
         }
-        self.events_rx.recv().ok()
     }
 }
 
-/// Workflow recorder
-pub struct WorkflowRecorder {
-    config: RecorderConfig,
+fn modifier_bit(vk: u32) -> Option<u8> {
+    match vk {
+        0x10 | 0xA0 | 0xA1 => Some(Modifiers::SHIFT),
+        0x11 | 0xA2 | 0xA3 => Some(Modifiers::CTRL),
+        0x12 | 0xA4 | 0xA5 => Some(Modifiers::OPT),
+        0x5B | 0x5C => Some(Modifiers::CMD),
+        _ => None,
+    }
 }
 
-impl WorkflowRecorder {
-    pub fn new() -> Self {
-        Self::with_config(RecorderConfig::default())
+unsafe extern "system" fn keyboard_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0 {
+        let info = &*(lparam.0 as *const KBDLLHOOKSTRUCT);
+        // The toggle state flips after the hook returns, so it's read here
+        let caps_on =
+            info.vkCode == VK_CAPITAL.0 as u32 && GetKeyState(VK_CAPITAL.0 as i32) & 1 != 0;
+        forward(|t| Input::Key {
+            t,
+            msg: wparam.0 as u32,
+            vk: info.vkCode,
+            scan: info.scanCode,
+            injected: info.flags.0 & LLKHF_INJECTED != 0,
+            caps_on,
+        });
     }
+    CallNextHookEx(HHOOK::default(), code, wparam, lparam)
+}
 
-    pub fn with_config(config: RecorderConfig) -> Self {
-        Self { config }
+unsafe extern "system" fn mouse_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0 {
+        let info = &*(lparam.0 as *const MSLLHOOKSTRUCT);
+        forward(|t| Input::Mouse {
+            t,
+            msg: wparam.0 as u32,
+            pos: (info.pt.x, info.pt.y),
+            data: info.mouseData,
+        });
     }
+    CallNextHookEx(HHOOK::default(), code, wparam, lparam)
+}
 
-    pub fn check_permissions(&self) -> PermissionStatus {
-        // Windows doesn't require explicit permissions
-        PermissionStatus {
-            accessibility: true,
-            input_monitoring: true,
+/// Register `pause_hotkey` and `stop_hotkey` for this thread, so the focused
+/// app doesn't receive them. The WM_HOTKEY messages are ignored: the
+/// keyboard hook still sees the keys and the sink acts on them. Returns the
+/// registered ids.
+fn register_hotkeys(hotkeys: [Option<Hotkey>; 2]) -> Vec<i32> {
+    let mut registered = Vec::new();
+    for (id, hotkey) in hotkeys.into_iter().enumerate() {
+        let Some(hotkey) = hotkey else {
+            continue;
+        };
+        let modifiers = [
+            (Modifiers::SHIFT, MOD_SHIFT),
+            (Modifiers::CTRL, MOD_CONTROL),
+            (Modifiers::OPT, MOD_ALT),
+            (Modifiers::CMD, MOD_WIN),
+        ]
+        .iter()
+        .filter(|(bit, _)| hotkey.m & bit != 0)
+        .fold(MOD_NOREPEAT, |acc, (_, m)| HOT_KEY_MODIFIERS(acc.0 | m.0));
+        let id = id as i32 + 1;
+        let vk = hotkey.key.to_windows() as u32;
+        match unsafe { RegisterHotKey(HWND::default(), id, modifiers, vk) } {
+            Ok(()) => registered.push(id),
+            Err(e) => tracing::warn!(
+                %hotkey,
+                error = %e,
+                "Can't register hotkey system-wide, it also reaches apps"
+            ),
         }
     }
+    registered
+}
 
-    pub fn request_permissions(&self) -> PermissionStatus {
-        self.check_permissions()
+/// Handle hook input until the hook thread goes away
+fn run_input_thread(mut state: InputState, inputs: mpsc::Receiver<Input>) {
+    loop {
+        match inputs.recv_timeout(Duration::from_millis(50)) {
+            Ok(input) => state.handle(input),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+        // Check text buffer timeout
+        state.tx.flush_idle_text();
     }
+    // Final flush
+    state.tx.flush_text();
+}
 
-    pub fn start(&self, name: impl Into<String>) -> Result<(RecordedWorkflow, RecordingHandle)> {
-        let workflow = RecordedWorkflow::new(name);
-        let (internals, rx) = self.start_capture()?;
-
-        let handle = RecordingHandle {
-            stop: internals.1,
-            events_rx: rx,
-            threads: internals.0,
+/// Install the hooks, say so on `ready`, and pump messages until stopped,
+/// passing what the hooks see on to `inputs`
+fn run_hook_thread(
+    ctx: CaptureContext,
+    inputs: mpsc::Sender<Input>,
+    ready: &mpsc::Sender<Result<(), RecorderError>>,
+) -> Result<()> {
+    let hotkeys = [ctx.config.pause_hotkey, ctx.config.stop_hotkey];
+    HOOK.with(|hook| *hook.borrow_mut() = Some((inputs, ctx.start)));
+
+    unsafe {
+        let module = GetModuleHandleW(None)?;
+        let instance = HINSTANCE(module.0);
+        let keyboard = SetWindowsHookExW(WH_KEYBOARD_LL, Some(keyboard_proc), instance, 0)?;
+        let mouse = match SetWindowsHookExW(WH_MOUSE_LL, Some(mouse_proc), instance, 0) {
+            Ok(hook) => hook,
+            Err(e) => {
+                let _ = UnhookWindowsHookEx(keyboard);
+                return Err(e.into());
+            }
         };
+        let hotkeys = register_hotkeys(hotkeys);
+        let _ = ready.send(Ok(()));
+
+        // Hooks only fire while this thread pumps messages
+        let mut msg = MSG::default();
+        while !ctx.stop.load(Ordering::Relaxed) {
+            while PeekMessageW(&mut msg, HWND::default(), 0, 0, PM_REMOVE).as_bool() {
+                let _ = TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+            MsgWaitForMultipleObjects(None, BOOL::from(false), 50, QS_ALLINPUT);
+        }
 
-        Ok((workflow, handle))
+        let _ = UnhookWindowsHookEx(mouse);
+        let _ = UnhookWindowsHookEx(keyboard);
+        for id in hotkeys {
+            let _ = UnregisterHotKey(HWND::default(), id);
+        }
     }
 
-    pub fn stream(&self) -> Result<EventStream> {
-        let (internals, rx) = self.start_capture()?;
+    // Lets the input thread finish
+    HOOK.with(|hook| hook.borrow_mut().take());
+    Ok(())
+}
 
-        Ok(EventStream {
-            stop: internals.1,
-            events_rx: rx,
-            threads: internals.0,
-        })
+/// Translate a key press with the foreground window's keyboard layout
+fn key_to_char(vk: u32, scan: u32, mods: u8) -> Option<char> {
+    // The hook thread's own key state is stale, so build it from what we track
+    let mut state = [0u8; 256];
+    if mods & Modifiers::SHIFT != 0 {
+        state[VK_SHIFT.0 as usize] = 0x80;
+    }
+    if mods & Modifiers::CAPS != 0 {
+        state[VK_CAPITAL.0 as usize] = 0x01;
     }
 
-    fn start_capture(&self) -> Result<((Vec<thread::JoinHandle<()>>, Arc<AtomicBool>), Receiver<Event>)> {
-        let (tx, rx) = bounded::<Event>(self.config.max_buffer);
-        let stop = Arc::new(AtomicBool::new(false));
-        let start_time = Instant::now();
-
-        let mut threads = Vec::new();
-
-        // Thread 1: rdev event listener
-        let tx1 = tx.clone();
-        let stop1 = stop.clone();
-        let config1 = self.config.clone();
-        threads.push(thread::spawn(move || {
-            run_rdev_listener(tx1, stop1, start_time, config1);
-        }));
-
-        // Thread 2: App/window observer
-        let tx2 = tx.clone();
-        let stop2 = stop.clone();
-        threads.push(thread::spawn(move || {
-            run_app_observer(tx2, stop2, start_time);
-        }));
-
-        Ok(((threads, stop), rx))
+    let mut buf = [0u16; 8];
+    let len = unsafe {
+        let thread = GetWindowThreadProcessId(GetForegroundWindow(), None);
+        // Flag 0x4: don't change the keyboard state (keeps dead keys intact)
+        ToUnicodeEx(vk, scan, &state, &mut buf, 0x4, GetKeyboardLayout(thread))
+    };
+    if len != 1 {
+        return None;
     }
-}
 
-impl Default for WorkflowRecorder {
-    fn default() -> Self {
-        Self::new()
+    match char::from_u32(buf[0] as u32)? {
+        '\r' => Some('\n'),
+        c @ ('\t' | '\x08') => Some(c),
+        c if c.is_control() => None,
+        c => Some(c),
     }
 }
 
-// ============================================================================
-// rdev Event Listener
-// ============================================================================
+/// ES_PASSWORD edit control style
+const ES_PASSWORD: i32 = 0x20;
 
-fn run_rdev_listener(tx: Sender<Event>, stop: Arc<AtomicBool>, start: Instant, config: RecorderConfig) {
-    use rdev::{listen, Event as RdevEvent, EventType};
-    use parking_lot::Mutex;
-
-    struct State {
-        tx: Sender<Event>,
-        start: Instant,
-        config: RecorderConfig,
-        last_mouse: (f64, f64),
-        text_buf: String,
-        last_text_time: Option<Instant>,
+/// Whether the focused control is a password field: a classic Win32 edit
+/// with ES_PASSWORD, or an element UI Automation reports as IsPassword (WPF,
+/// UWP, and browser inputs when the browser exposes its accessibility tree)
+pub(crate) fn secure_input_active() -> bool {
+    let mut info = GUITHREADINFO {
+        cbSize: std::mem::size_of::<GUITHREADINFO>() as u32,
+        ..Default::default()
+    };
+    unsafe {
+        // Thread 0 = the foreground thread
+        if GetGUIThreadInfo(0, &mut info).is_ok()
+            && !info.hwndFocus.0.is_null()
+            && GetWindowLongW(info.hwndFocus, GWL_STYLE) & ES_PASSWORD != 0
+        {
+            return true;
+        }
     }
+    focused_is_password()
+}
 
-    let state = Arc::new(Mutex::new(State {
-        tx,
-        start,
-        config,
-        last_mouse: (0.0, 0.0),
-        text_buf: String::new(),
-        last_text_time: None,
-    }));
-
-    let state_clone = state.clone();
-    let stop_clone = stop.clone();
-
-    // rdev::listen blocks, so we need to handle stop differently
-    let callback = move |event: RdevEvent| {
-        if stop_clone.load(Ordering::Relaxed) {
-            return;
-        }
+/// UI Automation's IsPassword on the focused element
+fn focused_is_password() -> bool {
+    with_automation(|automation| unsafe {
+        let focused = automation.GetFocusedElement().ok()?;
+        Some(focused.CurrentIsPassword().ok()?.as_bool())
+    })
+    .unwrap_or(false)
+}
 
-        let mut s = state_clone.lock();
-        let t = s.start.elapsed().as_millis() as u64;
-
-        match event.event_type {
-            EventType::ButtonPress(button) => {
-                let (x, y) = s.last_mouse;
-                let b = match button {
-                    rdev::Button::Left => 0,
-                    rdev::Button::Right => 1,
-                    rdev::Button::Middle => 2,
-                    _ => 0,
-                };
-                let _ = s.tx.try_send(Event {
-                    t,
-                    data: EventData::Click {
-                        x: x as i32,
-                        y: y as i32,
-                        b,
-                        n: 1,
-                        m: 0,
-                    },
-                });
-            }
-            EventType::MouseMove { x, y } => {
-                let dx = x - s.last_mouse.0;
-                let dy = y - s.last_mouse.1;
-                let dist = (dx * dx + dy * dy).sqrt();
-
-                if dist >= s.config.mouse_move_threshold {
-                    s.last_mouse = (x, y);
-                    let _ = s.tx.try_send(Event {
-                        t,
-                        data: EventData::Move {
-                            x: x as i32,
-                            y: y as i32,
-                        },
-                    });
-                }
-            }
-            EventType::Wheel { delta_x, delta_y } => {
-                let (x, y) = s.last_mouse;
-                let _ = s.tx.try_send(Event {
-                    t,
-                    data: EventData::Scroll {
-                        x: x as i32,
-                        y: y as i32,
-                        dx: delta_x as i16,
-                        dy: delta_y as i16,
-                    },
-                });
+/// Run `f` with this thread's UI Automation client, made on first use.
+/// Its calls give up after [`CONTEXT_WAIT_MS`], so a hung app holds up one
+/// lookup at most.
+fn with_automation<T>(f: impl FnOnce(&IUIAutomation) -> Option<T>) -> Option<T> {
+    thread_local! {
+        // Made once on each thread that asks: the input thread for clicks,
+        // the secure input check for typed runs
+        static AUTOMATION: Option<IUIAutomation> = unsafe {
+            // Already initialised on this thread is fine
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            let automation: Option<IUIAutomation> =
+                CoCreateInstance(&CUIAutomation8, None, CLSCTX_INPROC_SERVER)
+                    .or_else(|_| CoCreateInstance(&CUIAutomation, None, CLSCTX_INPROC_SERVER))
+                    .ok();
+            // Timeouts need Windows 8's IUIAutomation2
+            let automation2 = automation.as_ref().and_then(|a| a.cast::<IUIAutomation2>().ok());
+            if let Some(automation) = automation2 {
+                let _ = automation.SetConnectionTimeout(CONTEXT_WAIT_MS);
+                let _ = automation.SetTransactionTimeout(CONTEXT_WAIT_MS);
             }
-            EventType::KeyPress(key) => {
-                let keycode = key_to_code(&key);
-
-                // Check for Ctrl+C/X/V
-                // For now, just record key events
-                let _ = s.tx.try_send(Event {
-                    t,
-                    data: EventData::Key { k: keycode, m: 0 },
-                });
+            automation
+        };
+    }
+    AUTOMATION.with(|automation| automation.as_ref().and_then(f))
+}
 
-                // Try to get character for text aggregation
-                if let Some(c) = key_to_char(&key) {
-                    s.text_buf.push(c);
-                    s.last_text_time = Some(Instant::now());
+/// Ancestors walked above a clicked element
+const MAX_ANCESTORS: usize = 16;
+
+/// UIA_WindowControlTypeId
+const WINDOW_CONTROL_TYPE: i32 = 50032;
+
+/// The element at (x, y) as UI Automation sees it, with its AutomationId
+/// and the control types and names above it up to the desktop
+fn element_context(automation: &IUIAutomation, x: i32, y: i32) -> Option<ElementContext> {
+    unsafe {
+        let elem = automation.ElementFromPoint(POINT { x, y }).ok()?;
+        let control_type = elem.CurrentControlType().ok()?.0;
+        let role = control_type_name(control_type).to_string();
+        let name = bstr(elem.CurrentName());
+        let value = elem
+            .GetCurrentPatternAs::<IUIAutomationValuePattern>(UIA_ValuePatternId)
+            .ok()
+            .and_then(|pattern| bstr(pattern.CurrentValue()));
+        let bounds = elem.CurrentBoundingRectangle().ok().map(|r| {
+            (r.left, r.top, r.right - r.left, r.bottom - r.top)
+        });
+
+        let mut ancestors = Vec::new();
+        let mut window = None;
+        if let Ok(walker) = automation.ControlViewWalker() {
+            let mut current = walker.GetParentElement(&elem).ok();
+            while let Some(parent) = current {
+                if ancestors.len() >= MAX_ANCESTORS {
+                    break;
                 }
-            }
-            _ => {}
-        }
-
-        // Check text buffer timeout
-        if let Some(last_time) = s.last_text_time {
-            if last_time.elapsed().as_millis() as u64 >= s.config.text_timeout_ms && !s.text_buf.is_empty() {
-                let text = std::mem::take(&mut s.text_buf);
-                let _ = s.tx.try_send(Event {
-                    t,
-                    data: EventData::Text { s: text },
-                });
-                s.last_text_time = None;
+                let parent_type = parent.CurrentControlType().map_or(0, |t| t.0);
+                let parent_name = bstr(parent.CurrentName());
+                let next = walker.GetParentElement(&parent).ok();
+                // The desktop itself has no parent and says nothing
+                if next.is_none() {
+                    break;
+                }
+                if parent_type == WINDOW_CONTROL_TYPE && window.is_none() {
+                    window = parent_name.clone();
+                }
+                ancestors.push(selector(control_type_name(parent_type), parent_name.as_deref()));
+                current = next;
             }
         }
-    };
-
-    // rdev::listen blocks forever, but we need to respect the stop flag
-    // Unfortunately rdev doesn't have a clean way to stop
-    // We'll just let the thread run until the process exits
-    if let Err(e) = listen(callback) {
-        eprintln!("rdev listen error: {:?}", e);
+        ancestors.reverse();
+
+        let app = elem.CurrentProcessId().ok().and_then(|pid| super::get_process_name(pid as u32));
+        Some(ElementContext {
+            s: selector(&role, name.as_deref()),
+            r: role,
+            n: name.map(|s| truncate(&s, 50).into_owned()),
+            v: value.map(|s| truncate(&s, 50).into_owned()),
+            id: bstr(elem.CurrentAutomationId()),
+            b: bounds,
+            p: ancestors,
+            a: app,
+            w: window.map(|s| truncate(&s, 100).into_owned()),
+            // Filled in from the latest Window event
+            u: None,
+        })
     }
 }
 
-fn key_to_code(key: &rdev::Key) -> u16 {
-    use rdev::Key;
-    match key {
-        Key::Alt => 0x12,
-        Key::AltGr => 0x12,
-        Key::Backspace => 0x08,
-        Key::CapsLock => 0x14,
-        Key::ControlLeft | Key::ControlRight => 0x11,
-        Key::Delete => 0x2E,
-        Key::DownArrow => 0x28,
-        Key::End => 0x23,
-        Key::Escape => 0x1B,
-        Key::F1 => 0x70,
-        Key::F2 => 0x71,
-        Key::F3 => 0x72,
-        Key::F4 => 0x73,
-        Key::F5 => 0x74,
-        Key::F6 => 0x75,
-        Key::F7 => 0x76,
-        Key::F8 => 0x77,
-        Key::F9 => 0x78,
-        Key::F10 => 0x79,
-        Key::F11 => 0x7A,
-        Key::F12 => 0x7B,
-        Key::Home => 0x24,
-        Key::LeftArrow => 0x25,
-        Key::MetaLeft | Key::MetaRight => 0x5B,
-        Key::PageDown => 0x22,
-        Key::PageUp => 0x21,
-        Key::Return => 0x0D,
-        Key::RightArrow => 0x27,
-        Key::ShiftLeft | Key::ShiftRight => 0x10,
-        Key::Space => 0x20,
-        Key::Tab => 0x09,
-        Key::UpArrow => 0x26,
-        Key::Num0 => 0x30,
-        Key::Num1 => 0x31,
-        Key::Num2 => 0x32,
-        Key::Num3 => 0x33,
-        Key::Num4 => 0x34,
-        Key::Num5 => 0x35,
-        Key::Num6 => 0x36,
-        Key::Num7 => 0x37,
-        Key::Num8 => 0x38,
-        Key::Num9 => 0x39,
-        Key::KeyA => 0x41,
-        Key::KeyB => 0x42,
-        Key::KeyC => 0x43,
-        Key::KeyD => 0x44,
-        Key::KeyE => 0x45,
-        Key::KeyF => 0x46,
-        Key::KeyG => 0x47,
-        Key::KeyH => 0x48,
-        Key::KeyI => 0x49,
-        Key::KeyJ => 0x4A,
-        Key::KeyK => 0x4B,
-        Key::KeyL => 0x4C,
-        Key::KeyM => 0x4D,
-        Key::KeyN => 0x4E,
-        Key::KeyO => 0x4F,
-        Key::KeyP => 0x50,
-        Key::KeyQ => 0x51,
-        Key::KeyR => 0x52,
-        Key::KeyS => 0x53,
-        Key::KeyT => 0x54,
-        Key::KeyU => 0x55,
-        Key::KeyV => 0x56,
-        Key::KeyW => 0x57,
-        Key::KeyX => 0x58,
-        Key::KeyY => 0x59,
-        Key::KeyZ => 0x5A,
-        _ => 0,
-    }
+/// A UIA string, None when empty
+fn bstr(s: windows::core::Result<BSTR>) -> Option<String> {
+    s.ok().map(|s| s.to_string()).filter(|s| !s.is_empty())
 }
 
-fn key_to_char(key: &rdev::Key) -> Option<char> {
-    use rdev::Key;
-    match key {
-        Key::KeyA => Some('a'),
-        Key::KeyB => Some('b'),
-        Key::KeyC => Some('c'),
-        Key::KeyD => Some('d'),
-        Key::KeyE => Some('e'),
-        Key::KeyF => Some('f'),
-        Key::KeyG => Some('g'),
-        Key::KeyH => Some('h'),
-        Key::KeyI => Some('i'),
-        Key::KeyJ => Some('j'),
-        Key::KeyK => Some('k'),
-        Key::KeyL => Some('l'),
-        Key::KeyM => Some('m'),
-        Key::KeyN => Some('n'),
-        Key::KeyO => Some('o'),
-        Key::KeyP => Some('p'),
-        Key::KeyQ => Some('q'),
-        Key::KeyR => Some('r'),
-        Key::KeyS => Some('s'),
-        Key::KeyT => Some('t'),
-        Key::KeyU => Some('u'),
-        Key::KeyV => Some('v'),
-        Key::KeyW => Some('w'),
-        Key::KeyX => Some('x'),
-        Key::KeyY => Some('y'),
-        Key::KeyZ => Some('z'),
-        Key::Num0 => Some('0'),
-        Key::Num1 => Some('1'),
-        Key::Num2 => Some('2'),
-        Key::Num3 => Some('3'),
-        Key::Num4 => Some('4'),
-        Key::Num5 => Some('5'),
-        Key::Num6 => Some('6'),
-        Key::Num7 => Some('7'),
-        Key::Num8 => Some('8'),
-        Key::Num9 => Some('9'),
-        Key::Space => Some(' '),
-        Key::Return => Some('\n'),
-        Key::Tab => Some('\t'),
-        _ => None,
+/// `role:Button AND name:Save`, matching the core UIA backend
+fn selector(role: &str, name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("role:{} AND name:{}", role, name),
+        None => format!("role:{}", role),
     }
 }
 
+/// Control type names as the core UIA backend gives them, from
+/// UIA_ButtonControlTypeId (50000) on
+const CONTROL_TYPES: [&str; 39] = [
+    "Button", "Calendar", "CheckBox", "ComboBox", "Edit", "Hyperlink", "Image", "ListItem",
+    "List", "Menu", "MenuBar", "MenuItem", "ProgressBar", "RadioButton", "ScrollBar", "Slider",
+    "Spinner", "StatusBar", "Tab", "TabItem", "Text", "ToolBar", "ToolTip", "Tree", "TreeItem",
+    "Custom", "Group", "Thumb", "DataGrid", "DataItem", "Document", "SplitButton", "Window",
+    "Pane", "Header", "HeaderItem", "Table", "TitleBar", "Separator",
+];
+
+fn control_type_name(id: i32) -> &'static str {
+    usize::try_from(id - 50000)
+        .ok()
+        .and_then(|i| CONTROL_TYPES.get(i).copied())
+        .unwrap_or("Unknown")
+}
+
 // ============================================================================
 // App Observer
 // ============================================================================
 
-fn run_app_observer(tx: Sender<Event>, stop: Arc<AtomicBool>, start: Instant) {
+fn run_app_observer(tx: EventSink, stop: Arc<AtomicBool>, start: Instant) {
     let mut last_app: Option<String> = None;
     let mut last_pid: u32 = 0;
     let mut last_window: Option<String> = None;
@@ -483,6 +628,7 @@ fn run_app_observer(tx: Sender<Event>, stop: Arc<AtomicBool>, start: Instant) {
             if app_changed {
                 let _ = tx.try_send(Event {
                     t: start.elapsed().as_millis() as u64,
+                    ts: None,
                     data: EventData::App { n: name.clone(), p: pid as i32 },
                 });
                 last_app = Some(name.clone());
@@ -490,11 +636,15 @@ fn run_app_observer(tx: Sender<Event>, stop: Arc<AtomicBool>, start: Instant) {
             }
 
             if title != last_window || app_changed {
+                // Tab switches change the title, so this tracks the active tab
+                let url = super::browser_url(&name);
                 let _ = tx.try_send(Event {
                     t: start.elapsed().as_millis() as u64,
+                    ts: None,
                     data: EventData::Window {
                         a: name,
                         w: title.clone(),
+                        u: url,
                     },
                 });
                 last_window = title;
@@ -1,76 +1,54 @@
-//! Windows workflow replay
+//! Windows input injection for replay
 //!
 //! Uses SendInput for input injection.
 
-use crate::events::*;
+use crate::events::Modifiers;
+use crate::keys::Key;
+use crate::replay::Injector;
 use anyhow::Result;
 use std::time::Duration;
 
+use windows::Win32::Foundation::POINT;
 use windows::Win32::UI::Input::KeyboardAndMouse::{
-    SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT, MOUSEINPUT,
-    KEYEVENTF_KEYUP, KEYEVENTF_UNICODE, MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP,
+    GetAsyncKeyState, SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT,
+    MOUSEINPUT, KEYEVENTF_KEYUP, KEYEVENTF_UNICODE, MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP,
     MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP,
-    MOUSEEVENTF_WHEEL, VIRTUAL_KEY,
+    MOUSEEVENTF_HWHEEL, MOUSEEVENTF_WHEEL, VIRTUAL_KEY, VK_CONTROL, VK_LWIN, VK_MENU, VK_SHIFT,
 };
-use windows::Win32::UI::WindowsAndMessaging::SetCursorPos;
+use windows::Win32::UI::WindowsAndMessaging::{GetCursorPos, SetCursorPos};
 
-/// Replay recorded workflows
-pub struct Replayer {
-    speed: f64,
-}
-
-impl Replayer {
-    pub fn new() -> Self {
-        Self { speed: 1.0 }
-    }
-
-    pub fn speed(mut self, speed: f64) -> Self {
-        self.speed = speed;
-        self
-    }
-
-    pub fn play(&self, workflow: &RecordedWorkflow) -> Result<ReplayStats> {
-        let mut stats = ReplayStats::default();
-        let mut last_t = 0u64;
-
-        for event in &workflow.events {
-            if event.t > last_t {
-                let delay_ms = ((event.t - last_t) as f64 / self.speed) as u64;
-                if delay_ms > 0 {
-                    std::thread::sleep(Duration::from_millis(delay_ms));
-                }
-            }
-            last_t = event.t;
+/// Injects input through SendInput
+#[derive(Debug, Default)]
+pub struct NativeInjector;
 
-            match &event.data {
-                EventData::Click { x, y, b, n, .. } => {
-                    self.click(*x, *y, *b, *n)?;
-                    stats.clicks += 1;
-                }
-                EventData::Move { x, y } => {
-                    self.move_to(*x, *y)?;
-                    stats.moves += 1;
-                }
-                EventData::Scroll { x, y, dy, .. } => {
-                    self.scroll(*x, *y, *dy)?;
-                    stats.scrolls += 1;
-                }
-                EventData::Key { k, .. } => {
-                    self.key(*k)?;
-                    stats.keys += 1;
-                }
-                EventData::Text { s } => {
-                    self.type_text(s)?;
-                    stats.text_chars += s.len();
-                }
-                _ => {}
-            }
+impl NativeInjector {
+    /// Run `f` with the modifier keys in packed `Modifiers` held down,
+    /// releasing them even if it fails
+    fn with_modifiers(&self, modifiers: u8, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        let keys: Vec<u16> = [
+            (Modifiers::SHIFT, VK_SHIFT),
+            (Modifiers::CTRL, VK_CONTROL),
+            (Modifiers::OPT, VK_MENU),
+            (Modifiers::CMD, VK_LWIN),
+        ]
+        .into_iter()
+        .filter(|(bit, _)| modifiers & bit != 0)
+        .map(|(_, vk)| vk.0)
+        .collect();
+        if keys.is_empty() {
+            return f();
         }
-
-        Ok(stats)
+        let down: Vec<INPUT> = keys.iter().map(|&vk| make_key_input(vk, false)).collect();
+        let up: Vec<INPUT> = keys.iter().rev().map(|&vk| make_key_input(vk, true)).collect();
+        send_inputs(&down)?;
+        let result = f();
+        send_inputs(&up)?;
+        result
     }
+}
 
-    fn click(&self, x: i32, y: i32, button: u8, clicks: u8) -> Result<()> {
+impl Injector for NativeInjector {
+    fn click(&self, x: i32, y: i32, button: u8, clicks: u8, modifiers: u8) -> Result<()> {
         self.move_to(x, y)?;
         std::thread::sleep(Duration::from_millis(10));
 
@@ -80,22 +58,25 @@ impl Replayer {
             _ => (MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP),
         };
 
-        for _ in 0..clicks.max(1) {
-            let inputs = [
-                make_mouse_input(down_flags, 0),
-                make_mouse_input(up_flags, 0),
-            ];
-            send_inputs(&inputs)?;
+        self.with_modifiers(modifiers, || {
+            for _ in 0..clicks.max(1) {
+                let inputs = [
+                    make_mouse_input(down_flags, 0),
+                    make_mouse_input(up_flags, 0),
+                ];
+                send_inputs(&inputs)?;
 
-            if clicks > 1 {
-                std::thread::sleep(Duration::from_millis(50));
+                if clicks > 1 {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
             }
-        }
-
-        Ok(())
+            Ok(())
+        })
     }
 
     fn move_to(&self, x: i32, y: i32) -> Result<()> {
+        // Recorded coordinates are physical pixels
+        super::enable_dpi_awareness();
         unsafe {
             SetCursorPos(x, y)
                 .map_err(|e| anyhow::anyhow!("Failed to move cursor: {:?}", e))?;
@@ -103,13 +84,56 @@ impl Replayer {
         Ok(())
     }
 
-    fn scroll(&self, x: i32, y: i32, dy: i16) -> Result<()> {
+    fn drag(
+        &self,
+        path: &[(i32, i32)],
+        button: u8,
+        modifiers: u8,
+        duration: Duration,
+    ) -> Result<()> {
+        let Some((&(x, y), rest)) = path.split_first() else {
+            return Ok(());
+        };
+        let (down_flags, up_flags) = match button {
+            0 => (MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP),
+            1 => (MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP),
+            _ => (MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP),
+        };
+        let step = duration / rest.len().max(1) as u32;
+
+        self.move_to(x, y)?;
+        self.with_modifiers(modifiers, || {
+            send_inputs(&[make_mouse_input(down_flags, 0)])?;
+            for &(x, y) in rest {
+                std::thread::sleep(step);
+                self.move_to(x, y)?;
+            }
+            send_inputs(&[make_mouse_input(up_flags, 0)])
+        })
+    }
+
+    fn scroll(&self, x: i32, y: i32, dx: i16, dy: i16, pixels: bool) -> Result<()> {
         self.move_to(x, y)?;
-        let inputs = [make_mouse_input(MOUSEEVENTF_WHEEL, dy as i32 * 120)];
+        // WHEEL_DELTA (120) a line; pixel scrolls go in fractions of it
+        let delta = |d: i16| match pixels {
+            true => d as i32 * 120 / crate::platform::PIXELS_PER_LINE,
+            false => d as i32 * 120,
+        };
+        let mut inputs = Vec::new();
+        if dy != 0 {
+            inputs.push(make_mouse_input(MOUSEEVENTF_WHEEL, delta(dy)));
+        }
+        if dx != 0 {
+            inputs.push(make_mouse_input(MOUSEEVENTF_HWHEEL, delta(dx)));
+        }
+        if inputs.is_empty() {
+            return Ok(());
+        }
         send_inputs(&inputs)
     }
 
-    fn key(&self, keycode: u16) -> Result<()> {
+    fn key(&self, key: Key, _modifiers: u8) -> Result<()> {
+        let keycode = key.to_windows();
         let inputs = [
             make_key_input(keycode, false),
             make_key_input(keycode, true),
@@ -119,6 +143,14 @@ impl Replayer {
         Ok(())
     }
 
+    fn key_down(&self, key: Key, _modifiers: u8) -> Result<()> {
+        send_inputs(&[make_key_input(key.to_windows(), false)])
+    }
+
+    fn key_up(&self, key: Key, _modifiers: u8) -> Result<()> {
+        send_inputs(&[make_key_input(key.to_windows(), true)])
+    }
+
     fn type_text(&self, text: &str) -> Result<()> {
         let mut inputs = Vec::new();
 
@@ -131,21 +163,36 @@ impl Replayer {
         send_inputs(&inputs)?;
         Ok(())
     }
-}
 
-impl Default for Replayer {
-    fn default() -> Self {
-        Self::new()
+    fn activate(&self, app: &str) -> Result<()> {
+        if !super::activate_app(app) {
+            anyhow::bail!("No window of {} to bring to the front", app);
+        }
+        Ok(())
     }
-}
 
-#[derive(Debug, Default)]
-pub struct ReplayStats {
-    pub clicks: usize,
-    pub moves: usize,
-    pub scrolls: usize,
-    pub keys: usize,
-    pub text_chars: usize,
+    fn frontmost_app(&self) -> Option<String> {
+        super::get_focused_app().map(|(name, _, _)| name)
+    }
+
+    fn window_title(&self) -> Option<String> {
+        super::get_focused_window_title()
+    }
+
+    fn cursor_position(&self) -> Option<(i32, i32)> {
+        let mut point = POINT::default();
+        unsafe { GetCursorPos(&mut point) }.ok()?;
+        Some((point.x, point.y))
+    }
+
+    fn escape_pressed(&self) -> bool {
+        // High bit: down right now
+        unsafe { GetAsyncKeyState(Key::Escape.to_windows() as i32) } < 0
+    }
+
+    fn capture_screen(&self, path: &std::path::Path) -> Result<()> {
+        super::capture_screen(path, crate::platform::REPLAY_SCREENSHOT_SIZE)
+    }
 }
 
 // Helper functions
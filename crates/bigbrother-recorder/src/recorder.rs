@@ -1,47 +1,1191 @@
-//! Efficient workflow recorder using CGEventTap + NSWorkspace notifications
+//! Cross-platform recording session types
 //!
-//! Optimized for minimal CPU/memory usage while capturing everything.
+//! The platform modules only know how to capture raw input; everything that
+//! owns a session (config, handles, streams) lives here so every OS exposes
+//! the same API.
 
+use crate::clipboard::ClipboardStore;
+use crate::error::RecorderError;
 use crate::events::*;
+use crate::keys::{Hotkey, Key};
+use crate::platform;
+use crate::redact::{redact_event, Redactor};
+use crate::screenshot::Screenshotter;
+use crate::spill::{Sent, Spill};
+use crate::storage::WorkflowWriter;
 use anyhow::Result;
+use crossbeam_channel::{bounded, TrySendError};
 pub use crossbeam_channel::{Receiver, Sender};
-use crossbeam_channel::bounded;
 use parking_lot::Mutex;
-use std::sync::atomic::{AtomicBool, Ordering};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use cidre::{cf, cg, ns};
-use cidre::cg::event::access as cg_access;
+/// Recorder configuration
+#[derive(Debug, Clone)]
+pub struct RecorderConfig {
+    /// Mouse move sampling - record every N pixels moved
+    pub mouse_move_threshold: f64,
+    /// Text aggregation timeout in ms
+    pub text_timeout_ms: u64,
+    /// Max events before auto-flush to disk
+    pub max_buffer: usize,
+    /// Capture element context on clicks (slower but richer)
+    pub capture_context: bool,
+    /// How button releases and drags are recorded
+    pub drag_mode: DragMode,
+    /// Pixels the pointer must travel with a button held to count as a drag
+    pub drag_threshold: f64,
+    /// Record modifier presses and KeyUp events with hold durations, so
+    /// held keys and chords can be reconstructed
+    pub capture_key_up: bool,
+    /// Only record while one of these apps is frontmost (empty = all apps)
+    pub app_allowlist: Vec<String>,
+    /// Never record while one of these apps is frontmost
+    pub app_blocklist: Vec<String>,
+    /// Replace text typed into password fields with [`REDACTED`]. Detected
+    /// on macOS through secure event input and AXSecureTextField, on Windows
+    /// through ES_PASSWORD and UI Automation's IsPassword, which browsers
+    /// only report with their accessibility tree on, and on Linux through
+    /// AT-SPI's password text role. Without an AT-SPI bus every run is
+    /// redacted. Fields are checked off the capture threads, and a run
+    /// whose check isn't back when it ends is redacted too.
+    pub redact_secure_input: bool,
+    /// Scrub Text, Paste and element context before they are sent, e.g.
+    /// [`default_redactors`](crate::redact::default_redactors)
+    pub redactors: Vec<Arc<dyn Redactor>>,
+    /// Save a screenshot on every click and app switch, referenced by a
+    /// Screenshot event
+    pub capture_screenshots: bool,
+    /// Where screenshots are written
+    pub screenshot_dir: PathBuf,
+    /// Screenshots are downscaled so their longest side fits in this many pixels
+    pub screenshot_max_size: u32,
+    /// Characters of clipboard content kept in Paste events
+    pub clipboard_preview_len: usize,
+    /// Write the full content of every text or file clipboard change to
+    /// `clipboard_dir`, referenced by the Paste event
+    pub save_clipboard: bool,
+    /// Where clipboard contents are written
+    pub clipboard_dir: PathBuf,
+    /// Report changes to files under these directories (e.g. ~/Downloads)
+    /// as FileChanged events
+    pub watch_dirs: Vec<PathBuf>,
+    /// Record the screen for the whole session, announced by a Video event.
+    /// Lined up with events by one offset, not frame by frame: to within
+    /// ~100ms with ffmpeg, about a second with screencapture or wf-recorder.
+    #[cfg(feature = "video")]
+    pub record_video: bool,
+    /// Where screen recordings are written
+    #[cfg(feature = "video")]
+    pub video_dir: PathBuf,
+    /// Chord that asks for a note and records it as an Annotation event.
+    /// Needs Ctrl or Cmd, otherwise it's typed as text.
+    pub annotation_hotkey: Option<Hotkey>,
+    /// Chord that pauses and resumes recording. On macOS and Windows it's
+    /// registered system-wide, so the focused app doesn't get it either.
+    pub pause_hotkey: Option<Hotkey>,
+    /// Chord that ends the session, like [`RecordingHandle::stop`] does
+    /// without needing to switch to the app that started it. Registered
+    /// like `pause_hotkey`.
+    pub stop_hotkey: Option<Hotkey>,
+    /// Stamp every event with its wall-clock time (`Event::ts`)
+    pub absolute_timestamps: bool,
+    /// What happens when `max_buffer` events are waiting to be drained
+    pub overflow: Overflow,
+    /// Mark gaps longer than this with an Idle event, so replay waits this
+    /// long instead of the whole gap
+    pub collapse_idle_ms: Option<u64>,
+    /// End the session after this long, with a SessionEnd event
+    pub max_duration: Option<Duration>,
+    /// End the session after this many events, with a SessionEnd event
+    pub max_events: Option<u64>,
+    /// When a limit is reached, start a new segment instead of stopping.
+    /// A [`RecordingHandle`] streaming to a file starts a new file; in
+    /// memory the SessionEnd events mark where segments end.
+    pub rotate: bool,
+}
+
+/// How button presses, moves and releases are recorded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DragMode {
+    /// Assemble press/move/release into a Click or a Drag event
+    #[default]
+    Aggregated,
+    /// Click on press, MouseUp on release, moves as usual
+    Raw,
+}
+
+impl Default for RecorderConfig {
+    fn default() -> Self {
+        Self {
+            mouse_move_threshold: 5.0,
+            text_timeout_ms: 300,
+            max_buffer: 10000,
+            // Linux can't resolve the element under the cursor yet
+            capture_context: cfg!(any(target_os = "macos", target_os = "windows")),
+            drag_mode: DragMode::Aggregated,
+            drag_threshold: 5.0,
+            capture_key_up: false,
+            app_allowlist: Vec::new(),
+            app_blocklist: Vec::new(),
+            redact_secure_input: true,
+            redactors: Vec::new(),
+            capture_screenshots: false,
+            screenshot_dir: recordings_subdir("screenshots"),
+            screenshot_max_size: 1280,
+            clipboard_preview_len: 100,
+            save_clipboard: false,
+            clipboard_dir: recordings_subdir("clipboard"),
+            watch_dirs: Vec::new(),
+            #[cfg(feature = "video")]
+            record_video: false,
+            #[cfg(feature = "video")]
+            video_dir: recordings_subdir("videos"),
+            annotation_hotkey: None,
+            pause_hotkey: None,
+            stop_hotkey: None,
+            absolute_timestamps: false,
+            overflow: Overflow::Drop,
+            collapse_idle_ms: None,
+            max_duration: None,
+            max_events: None,
+            rotate: false,
+        }
+    }
+}
+
+impl RecorderConfig {
+    /// Screenshots or video are on, which need screen recording permission
+    pub fn captures_screen(&self) -> bool {
+        #[cfg(feature = "video")]
+        if self.record_video {
+            return true;
+        }
+        self.capture_screenshots
+    }
+}
+
+/// What happens to events that arrive while the session channel is full
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Drop them, leaving a Dropped marker where the gap is
+    #[default]
+    Drop,
+    /// Wait for room. This stalls the capture thread: macOS disables an
+    /// event tap that doesn't answer in time and Windows removes slow hooks.
+    Block,
+    /// Append them to this file until the next drain. Only
+    /// [`RecordingHandle`] reads them back, so streams refuse it.
+    Disk(PathBuf),
+}
+
+/// Delivery counters for a recording session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RecordingStats {
+    /// Events handed to the session, including spilled ones
+    pub sent: u64,
+    /// Events lost because the channel was full
+    pub dropped: u64,
+    /// Events that went through the overflow file
+    pub spilled: u64,
+}
+
+/// Whether the OS is delivering input to a session. Only macOS reports
+/// interruptions: it disables an event tap whose callback is too slow, and
+/// the recorder turns it back on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureHealth {
+    /// Times input capture was cut off, each leaving a TapInterrupted marker
+    pub interruptions: u64,
+    /// `t` of the latest interruption
+    pub last_interruption: Option<u64>,
+    /// Capture is cut off right now and couldn't be turned back on
+    pub interrupted: bool,
+}
+
+#[derive(Default)]
+struct Counters {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    spilled: AtomicU64,
+    /// Drops not yet reported by a Dropped marker
+    gap: AtomicU64,
+    interruptions: AtomicU64,
+    /// `t + 1` of the latest interruption, 0 before the first
+    interrupted_at: AtomicU64,
+    interrupted: AtomicBool,
+}
+
+impl Counters {
+    fn stats(&self) -> RecordingStats {
+        RecordingStats {
+            sent: self.sent.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            spilled: self.spilled.load(Ordering::Relaxed),
+        }
+    }
+
+    fn health(&self) -> CaptureHealth {
+        let at = self.interrupted_at.load(Ordering::Relaxed);
+        CaptureHealth {
+            interruptions: self.interruptions.load(Ordering::Relaxed),
+            last_interruption: at.checked_sub(1),
+            interrupted: self.interrupted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Extra receivers attached to a session with `subscribe`. Each gets its
+/// own bounded channel; a subscriber that falls behind misses events instead
+/// of holding up the others.
+pub(crate) struct Subscribers {
+    capacity: usize,
+    list: Mutex<Vec<Subscriber>>,
+}
+
+/// Channel, and the kinds it wants (all when `None`)
+type Subscriber = (Sender<Event>, Option<Vec<EventKind>>);
+
+impl Subscribers {
+    fn new(capacity: usize) -> Self {
+        Self { capacity, list: Mutex::new(Vec::new()) }
+    }
+
+    fn add(&self, kinds: Option<&[EventKind]>) -> Subscription {
+        let (tx, rx) = bounded(self.capacity);
+        self.list.lock().push((tx, kinds.map(<[_]>::to_vec)));
+        Subscription { events_rx: rx }
+    }
+
+    fn send(&self, event: &Event) {
+        let mut list = self.list.lock();
+        list.retain(|(tx, kinds)| {
+            if kinds.as_ref().is_some_and(|k| !k.contains(&event.data.kind())) {
+                return true;
+            }
+            !matches!(tx.try_send(event.clone()), Err(TrySendError::Disconnected(_)))
+        });
+    }
+
+    /// Disconnect everyone, ending their iterators
+    fn close(&self) {
+        self.list.lock().clear();
+    }
+}
+
+/// `max_duration` and `max_events`, counted per segment
+struct Limits {
+    max_ms: Option<u64>,
+    max_events: Option<u64>,
+    rotate: bool,
+    segment: Mutex<Segment>,
+}
+
+#[derive(Default)]
+struct Segment {
+    /// `t` it started at
+    start: u64,
+    events: u64,
+    /// The session was stopped by a limit
+    ended: bool,
+}
+
+impl Limits {
+    fn new(config: &RecorderConfig) -> Option<Self> {
+        if config.max_duration.is_none() && config.max_events.is_none() {
+            return None;
+        }
+        Some(Self {
+            max_ms: config.max_duration.map(|d| d.as_millis() as u64),
+            max_events: config.max_events,
+            rotate: config.rotate,
+            segment: Mutex::default(),
+        })
+    }
+
+    /// Count an event at `t` (or just check the clock) and return which
+    /// limit was reached, starting a new segment or ending the session
+    fn reached(&self, t: u64, counted: bool) -> Option<&'static str> {
+        let mut segment = self.segment.lock();
+        if segment.ended {
+            return None;
+        }
+        segment.events += counted as u64;
+        let reason = if self.max_events.is_some_and(|n| segment.events >= n) {
+            "events"
+        } else if self.max_ms.is_some_and(|ms| t >= segment.start + ms) {
+            "duration"
+        } else {
+            return None;
+        };
+        *segment = Segment { start: t, events: 0, ended: !self.rotate };
+        Some(reason)
+    }
+}
+
+/// `~/.workflow-recorder/<name>`, next to saved recordings
+pub(crate) fn recordings_subdir(name: &str) -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(".workflow-recorder")
+        .join(name)
+}
+
+/// Permission status
+#[derive(Debug, Clone, Serialize)]
+pub struct PermissionStatus {
+    pub accessibility: bool,
+    pub input_monitoring: bool,
+    /// Only screenshots and video need it
+    pub screen_recording: bool,
+    /// Each permission recording needs, with how to grant it
+    pub permissions: Vec<Permission>,
+}
 
-// Keycodes for clipboard operations
-const KEY_C: u16 = 8;
-const KEY_X: u16 = 7;
-const KEY_V: u16 = 9;
+impl PermissionStatus {
+    pub(crate) fn new(
+        accessibility: Permission,
+        input_monitoring: Permission,
+        screen_recording: Permission,
+    ) -> Self {
+        Self {
+            accessibility: accessibility.granted(),
+            input_monitoring: input_monitoring.granted(),
+            screen_recording: screen_recording.granted(),
+            permissions: vec![accessibility, input_monitoring, screen_recording],
+        }
+    }
+
+    /// What recording input needs; see `screen_recording` for the rest
+    pub fn all_granted(&self) -> bool {
+        self.accessibility && self.input_monitoring
+    }
+
+    /// The permissions not granted yet, screen recording included
+    pub fn missing(&self) -> impl Iterator<Item = &Permission> {
+        self.permissions.iter().filter(|p| !p.granted())
+    }
+}
+
+/// One permission and what granting it takes
+#[derive(Debug, Clone, Serialize)]
+pub struct Permission {
+    /// `accessibility`, `input_monitoring` or `screen_recording`
+    pub name: &'static str,
+    pub state: PermissionState,
+    /// Opens the settings page where it's granted (macOS)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings_url: Option<&'static str>,
+    /// Granting it only takes effect once the app asking for it restarts
+    /// (or the user logs in again, for Linux groups)
+    pub restart_required: bool,
+    /// What to do, for people
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remediation: Option<String>,
+}
+
+/// As the OS's privacy database has it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PermissionState {
+    Granted,
+    Denied,
+    /// Never asked for, so requesting it prompts
+    NotDetermined,
+}
+
+impl From<bool> for PermissionState {
+    fn from(granted: bool) -> Self {
+        if granted {
+            Self::Granted
+        } else {
+            Self::Denied
+        }
+    }
+}
+
+impl Permission {
+    pub fn new(name: &'static str, state: PermissionState) -> Self {
+        Self { name, state, settings_url: None, restart_required: false, remediation: None }
+    }
+
+    pub fn settings_url(mut self, url: &'static str) -> Self {
+        self.settings_url = Some(url);
+        self
+    }
+
+    pub fn restart_required(mut self) -> Self {
+        self.restart_required = true;
+        self
+    }
+
+    /// Kept only while it's not granted
+    pub fn remediation(mut self, remediation: impl Into<String>) -> Self {
+        if !self.granted() {
+            self.remediation = Some(remediation.into());
+        }
+        self
+    }
+
+    pub fn granted(&self) -> bool {
+        self.state == PermissionState::Granted
+    }
+}
+
+/// Where platform captures send events. App filtering and redaction happen
+/// here, on the capturing thread, so dropped or scrubbed data never reaches
+/// the session channel; screenshots are triggered from here too.
+///
+/// Typed characters are buffered here as well, so pending text can be sent
+/// ahead of whatever ends it: a click, a key, or an App/Window change from
+/// the observer thread.
+#[derive(Clone)]
+pub(crate) struct EventSink {
+    tx: Outlet,
+    start: Instant,
+    text: Arc<Mutex<TextBuffer>>,
+    filter: Option<Arc<Mutex<AppFilter>>>,
+    redactors: Arc<[Arc<dyn Redactor>]>,
+    screenshots: Option<Arc<Screenshotter>>,
+    clipboard: Arc<ClipboardStore>,
+    /// Browser URL from the latest Window event, stamped on clicks
+    url: Arc<Mutex<Option<String>>>,
+    annotation_hotkey: Option<Hotkey>,
+    pause_hotkey: Option<Hotkey>,
+    stop_hotkey: Option<Hotkey>,
+    /// An annotation prompt is open; input goes to the prompt, not the session
+    annotating: Arc<AtomicBool>,
+    /// Recording is paused; input is dropped
+    paused: Arc<AtomicBool>,
+    /// Session start, for `absolute_timestamps`
+    clock: Option<Instant>,
+    block: bool,
+    spill: Option<Arc<Spill>>,
+    counters: Arc<Counters>,
+    subscribers: Arc<Subscribers>,
+    limits: Option<Arc<Limits>>,
+    /// Set when a limit ends the session
+    stop: Arc<AtomicBool>,
+    /// `collapse_idle_ms`, and the latest `t` sent
+    idle: Option<(u64, Arc<AtomicU64>)>,
+}
+
+impl EventSink {
+    pub fn new(tx: impl Into<Outlet>, config: &RecorderConfig, start: Instant) -> Self {
+        Self {
+            tx: tx.into(),
+            start,
+            text: Arc::new(Mutex::new(TextBuffer::new(config))),
+            filter: AppFilter::new(config).map(|f| Arc::new(Mutex::new(f))),
+            redactors: config.redactors.clone().into(),
+            screenshots: Screenshotter::new(config).map(Arc::new),
+            clipboard: Arc::new(ClipboardStore::new(config)),
+            url: Arc::new(Mutex::new(None)),
+            annotation_hotkey: config.annotation_hotkey,
+            pause_hotkey: config.pause_hotkey,
+            stop_hotkey: config.stop_hotkey,
+            annotating: Arc::new(AtomicBool::new(false)),
+            paused: Arc::new(AtomicBool::new(false)),
+            clock: config.absolute_timestamps.then_some(start),
+            block: config.overflow == Overflow::Block,
+            spill: match &config.overflow {
+                Overflow::Disk(path) => Some(Arc::new(Spill::new(path.clone()))),
+                _ => None,
+            },
+            counters: Arc::default(),
+            subscribers: Arc::new(Subscribers::new(config.max_buffer)),
+            limits: Limits::new(config).map(Arc::new),
+            stop: Arc::default(),
+            idle: config.collapse_idle_ms.map(|ms| (ms, Arc::default())),
+        }
+    }
+
+    /// Buffer a typed character (see [`TextBuffer::push`]); false when the
+    /// caller should record the key instead
+    pub fn type_char(&self, c: char) -> bool {
+        // Typing into the annotation prompt
+        if self.annotating.load(Ordering::Relaxed) || self.paused.load(Ordering::Relaxed) {
+            return true;
+        }
+        if !self.text.lock().push(c) {
+            return false;
+        }
+        // Tab and Enter can move focus into or out of a password field, so
+        // they end the run and the next one is checked again
+        if matches!(c, '\t' | '\n') {
+            self.flush_text();
+        }
+        true
+    }
+
+    /// Send buffered text once typing has paused for `text_timeout_ms`
+    pub fn flush_idle_text(&self) {
+        if self.text.lock().should_flush() {
+            self.flush_text();
+        }
+    }
+
+    /// Send buffered text now
+    pub fn flush_text(&self) {
+        self.flush_text_at(self.start.elapsed().as_millis() as u64);
+    }
+
+    fn flush_text_at(&self, t: u64) {
+        let text = self.text.lock().flush();
+        if let Some(s) = text {
+            let _ = self.try_send(Event { t, ts: None, data: EventData::Text { s } });
+        }
+    }
+
+    /// The OS stopped delivering input (see [`EventData::TapInterrupted`]);
+    /// `restored` once capture is back on
+    #[cfg(target_os = "macos")]
+    pub fn capture_interrupted(&self, reason: &str, restored: bool) {
+        let t = self.start.elapsed().as_millis() as u64;
+        self.counters.interruptions.fetch_add(1, Ordering::Relaxed);
+        self.counters.interrupted_at.store(t + 1, Ordering::Relaxed);
+        self.counters.interrupted.store(!restored, Ordering::Relaxed);
+        // Keys typed before the gap aren't joined with those after it
+        self.flush_text_at(t);
+        let data = EventData::TapInterrupted { r: reason.into() };
+        let _ = self.deliver(Event { t, ts: None, data });
+    }
+
+    /// Capture cut off earlier is back on
+    #[cfg(target_os = "macos")]
+    pub fn capture_restored(&self) {
+        self.counters.interrupted.store(false, Ordering::Relaxed);
+    }
+
+    /// Drop input until resumed; text typed so far is sent first
+    fn set_paused(&self, paused: bool) {
+        if paused {
+            self.flush_text();
+        }
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn try_send(&self, mut event: Event) -> Result<(), TrySendError<Event>> {
+        if self.annotating.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        // Checked even while paused, so the hotkey can resume
+        if let EventData::Key { k, m } = event.data {
+            if self.pause_hotkey.is_some_and(|h| h.matches(k, m)) {
+                self.set_paused(!self.paused.load(Ordering::Relaxed));
+                return Ok(());
+            }
+            if self.stop_hotkey.is_some_and(|h| h.matches(k, m)) {
+                self.flush_text_at(event.t);
+                self.stop.store(true, Ordering::SeqCst);
+                return Ok(());
+            }
+        }
+        if self.paused.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        // Text typed before this event belongs before it (and to the app
+        // that was focused), not merged with whatever is typed next
+        let ends_text = !matches!(
+            event.data,
+            EventData::Move { .. } | EventData::Text { .. } | EventData::FileChanged { .. }
+        );
+        if ends_text {
+            self.flush_text_at(event.t);
+        }
+        if let Some(filter) = &self.filter {
+            if !filter.lock().keep(&event) {
+                return Ok(());
+            }
+        }
+        match &mut event.data {
+            EventData::Key { k, m } if self.annotation_hotkey.is_some_and(|h| h.matches(*k, *m)) => {
+                self.annotate(event.t);
+                return Ok(());
+            }
+            EventData::Window { u, .. } => *self.url.lock() = u.clone(),
+            EventData::Click { c: Some(c), .. } if c.u.is_none() => c.u = self.url.lock().clone(),
+            _ => {}
+        }
+        if let Some((threshold, last)) = &self.idle {
+            let last = last.fetch_max(event.t, Ordering::Relaxed);
+            if event.t > last + threshold {
+                let t = last + threshold;
+                let _ = self.deliver(Event { t, ts: None, data: EventData::Idle { d: event.t - t } });
+            }
+        }
+        redact_event(&mut event.data, &self.redactors);
+        self.clipboard.process(event.t, &mut event.data);
+        if let Some(start) = self.clock {
+            // Events can be sent well after they happened (clicks on release)
+            let age = start.elapsed().saturating_sub(Duration::from_millis(event.t));
+            event.ts = Some(chrono::Utc::now().timestamp_millis() - age.as_millis() as i64);
+        }
+
+        let t = event.t;
+        let screenshot = matches!(event.data, EventData::Click { .. } | EventData::App { .. });
+        let sent = self.deliver(event);
+        if let (true, Some(screenshots)) = (screenshot, &self.screenshots) {
+            screenshots.capture(t, self.clone());
+        }
+        sent
+    }
+
+    /// Send `event` to subscribers and the session, preceded by a Dropped
+    /// marker if events were lost since the last one got through
+    pub fn deliver(&self, event: Event) -> Result<(), TrySendError<Event>> {
+        self.subscribers.send(&event);
+        let gap = self.counters.gap.load(Ordering::Relaxed);
+        if gap > 0 {
+            let marker = Event { t: event.t, ts: event.ts, data: EventData::Dropped { n: gap } };
+            if self.push(marker).is_ok() {
+                self.counters.gap.fetch_sub(gap, Ordering::Relaxed);
+            }
+        }
+        let t = event.t;
+        let result = self.push(event);
+        if let Err(TrySendError::Full(_)) = &result {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            self.counters.gap.fetch_add(1, Ordering::Relaxed);
+        }
+        self.check_limits(t, true);
+        result
+    }
+
+    /// Send a SessionEnd if a limit is reached at `t`, and stop the session
+    /// unless rotating. `counted` is false for clock checks without an event.
+    fn check_limits(&self, t: u64, counted: bool) {
+        let Some(limits) = &self.limits else {
+            return;
+        };
+        let Some(reason) = limits.reached(t, counted) else {
+            return;
+        };
+        // Text typed so far belongs to the segment that's ending
+        self.flush_text_at(t);
+        let end = Event { t, ts: None, data: EventData::SessionEnd { r: reason.into() } };
+        self.subscribers.send(&end);
+        let _ = self.push(end);
+        if !limits.rotate {
+            self.stop.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn push(&self, event: Event) -> Result<(), TrySendError<Event>> {
+        let result = if let Some(spill) = &self.spill {
+            spill.send(&self.tx, event).map(|sent| matches!(sent, Sent::Disk))
+        } else if self.block {
+            self.tx.send(event).map(|_| false)
+        } else {
+            self.tx.try_send(event).map(|_| false)
+        };
+        if let Ok(spilled) = result {
+            self.counters.sent.fetch_add(1, Ordering::Relaxed);
+            if spilled {
+                self.counters.spilled.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result.map(|_| ())
+    }
+
+    /// Ask for a note on another thread, dropping input until it's answered
+    fn annotate(&self, t: u64) {
+        self.annotating.store(true, Ordering::Relaxed);
+        let sink = self.clone();
+        thread::spawn(move || {
+            let note = prompt_annotation();
+            sink.annotating.store(false, Ordering::Relaxed);
+            if let Some(s) = note {
+                let _ = sink.deliver(Event { t, ts: None, data: EventData::Annotation { s } });
+            }
+        });
+    }
+}
+
+/// The platform's input dialog, or a line from stdin when there is none
+fn prompt_annotation() -> Option<String> {
+    let note = match platform::current::prompt_text("Annotation") {
+        Ok(note) => note,
+        Err(_) => {
+            use std::io::Write;
+            eprint!("\nAnnotation: ");
+            let _ = std::io::stderr().flush();
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line).ok()?;
+            Some(line)
+        }
+    };
+    note.map(|s| s.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+/// The channel behind a session: crossbeam for handles and [`EventStream`],
+/// tokio for [`AsyncEventStream`]
+#[derive(Clone)]
+pub(crate) enum Outlet {
+    Channel(Sender<Event>),
+    #[cfg(feature = "async")]
+    Async(tokio::sync::mpsc::Sender<Event>),
+}
+
+impl Outlet {
+    pub fn try_send(&self, event: Event) -> Result<(), TrySendError<Event>> {
+        match self {
+            Self::Channel(tx) => tx.try_send(event),
+            #[cfg(feature = "async")]
+            Self::Async(tx) => tx.try_send(event).map_err(|e| match e {
+                tokio::sync::mpsc::error::TrySendError::Full(e) => TrySendError::Full(e),
+                tokio::sync::mpsc::error::TrySendError::Closed(e) => TrySendError::Disconnected(e),
+            }),
+        }
+    }
+
+    /// Wait for room. Only called from capture threads, never from inside
+    /// an async runtime.
+    pub fn send(&self, event: Event) -> Result<(), TrySendError<Event>> {
+        match self {
+            Self::Channel(tx) => tx.send(event).map_err(|e| TrySendError::Disconnected(e.0)),
+            #[cfg(feature = "async")]
+            Self::Async(tx) => tx.blocking_send(event).map_err(|e| TrySendError::Disconnected(e.0)),
+        }
+    }
+}
+
+impl From<Sender<Event>> for Outlet {
+    fn from(tx: Sender<Event>) -> Self {
+        Self::Channel(tx)
+    }
+}
+
+/// Everything a platform capture needs to feed events into a session
+#[derive(Clone)]
+pub(crate) struct CaptureContext {
+    pub tx: EventSink,
+    pub stop: Arc<AtomicBool>,
+    pub start: Instant,
+    pub config: RecorderConfig,
+}
+
+/// State an OS callback reaches through a raw pointer (its user data),
+/// owned by the capture thread that registered the callback. Dropping it
+/// frees the state, so the thread must unregister the callback first.
+// Only the macOS event tap has one outside tests
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+pub(crate) struct CallbackState<S>(Box<S>);
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+impl<S> CallbackState<S> {
+    pub fn new(state: S) -> Self {
+        Self(Box::new(state))
+    }
+
+    /// The pointer to register; valid until this is dropped
+    pub fn as_ptr(&self) -> *mut S {
+        &*self.0 as *const S as *mut S
+    }
+}
+
+impl<S> std::ops::Deref for CallbackState<S> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.0
+    }
+}
+
+/// Aggregates typed characters into Text events
+struct TextBuffer {
+    chars: String,
+    last_time: Option<Instant>,
+    timeout_ms: u64,
+    /// Checks for password fields, None with `redact_secure_input` off
+    secure: Option<SecureFocus>,
+    /// Id of the current run's check
+    run: u64,
+}
+
+impl TextBuffer {
+    pub fn new(config: &RecorderConfig) -> Self {
+        Self {
+            chars: String::new(),
+            last_time: None,
+            timeout_ms: config.text_timeout_ms,
+            secure: config
+                .redact_secure_input
+                .then(|| SecureFocus::spawn(platform::current::secure_input_active)),
+            run: 0,
+        }
+    }
+
+    /// Add a typed character; a backspace deletes the last one instead.
+    /// False for a backspace with nothing left to delete.
+    pub fn push(&mut self, c: char) -> bool {
+        if c == '\x08' {
+            self.last_time = Some(Instant::now());
+            return self.chars.pop().is_some();
+        }
+        // Clicks, Tab and Enter end the run, so focus stays put within one
+        // and it's checked once
+        if self.chars.is_empty() {
+            if let Some(secure) = &self.secure {
+                self.run = secure.ask();
+            }
+        }
+        self.chars.push(c);
+        self.last_time = Some(Instant::now());
+        true
+    }
+
+    /// Whether the run typed into a password field; until its check is
+    /// back it's assumed to be
+    fn is_secure(&self) -> bool {
+        self.secure.as_ref().is_some_and(|s| s.answer(self.run).unwrap_or(true))
+    }
+
+    /// The buffered text, or [`REDACTED`] if it was typed into a password field
+    pub fn flush(&mut self) -> Option<String> {
+        if self.chars.is_empty() {
+            return None;
+        }
+        let secure = self.is_secure();
+        let s = std::mem::take(&mut self.chars);
+        self.last_time = None;
+        if secure {
+            return Some(REDACTED.to_string());
+        }
+        Some(s)
+    }
+
+    /// Typing paused for `timeout_ms`, and the run's check is back (or
+    /// given up on)
+    pub fn should_flush(&self) -> bool {
+        let Some(last) = self.last_time else {
+            return false;
+        };
+        let idle = last.elapsed().as_millis() as u64;
+        let checked = self.secure.as_ref().is_none_or(|s| s.answer(self.run).is_some());
+        idle >= self.timeout_ms && (checked || idle >= self.timeout_ms + SECURE_WAIT_MS)
+    }
+}
+
+/// How long past the text timeout a run waits for its password field check
+/// before it's sent redacted
+const SECURE_WAIT_MS: u64 = 1000;
+
+/// Whether a password field has focus, checked on its own thread: the
+/// platform asks the focused app, which is too slow for an input hook or
+/// event tap. Capture threads only ask and read atomics.
+struct SecureFocus {
+    /// Id of the latest check asked for
+    asked: Arc<AtomicU64>,
+    /// The latest answer, `id << 1 | secure`
+    answer: Arc<AtomicU64>,
+    wake: Sender<()>,
+}
+
+impl SecureFocus {
+    /// Start the thread; it ends once the last sink is dropped and any
+    /// check in progress returns
+    fn spawn(check: fn() -> bool) -> Self {
+        let (wake, woken) = bounded(1);
+        let (asked, answer) = (Arc::new(AtomicU64::new(0)), Arc::new(AtomicU64::new(0)));
+        let (latest, reply) = (asked.clone(), answer.clone());
+        thread::spawn(move || {
+            for () in woken {
+                let id = latest.load(Ordering::Acquire);
+                let secure = check();
+                reply.store(id << 1 | secure as u64, Ordering::Release);
+            }
+        });
+        Self { asked, answer, wake }
+    }
+
+    /// Ask about the focused field; the id to look the answer up by
+    fn ask(&self) -> u64 {
+        let id = self.asked.fetch_add(1, Ordering::AcqRel) + 1;
+        // A wake-up already pending reads this id too
+        let _ = self.wake.try_send(());
+        id
+    }
+
+    /// The answer to check `id`, None until it's in
+    fn answer(&self, id: u64) -> Option<bool> {
+        let answer = self.answer.load(Ordering::Acquire);
+        (answer >> 1 >= id).then_some(answer & 1 != 0)
+    }
+}
+
+/// Text recorded in place of anything typed into a password field
+pub const REDACTED: &str = "[redacted]";
+
+/// Keys aggregated into text only get a KeyUp when held at least this long
+const LONG_PRESS_MS: u64 = 500;
+
+/// Tracks held keys for `RecorderConfig::capture_key_up`
+pub(crate) struct KeyTracker {
+    enabled: bool,
+    /// (key, press time, whether the press was sent as a Key event)
+    held: Vec<(Key, u64, bool)>,
+}
+
+impl KeyTracker {
+    pub fn new(config: &RecorderConfig) -> Self {
+        Self {
+            enabled: config.capture_key_up,
+            held: Vec::new(),
+        }
+    }
+
+    /// Key pressed; `sent` is false when it went into the text buffer.
+    /// Autorepeat keeps the original press time.
+    pub fn press(&mut self, t: u64, key: Key, sent: bool) {
+        if self.enabled && !self.held.iter().any(|(k, ..)| *k == key) {
+            self.held.push((key, t, sent));
+        }
+    }
+
+    /// Key released; the KeyUp to send, if any
+    pub fn release(&mut self, t: u64, key: Key, m: u8) -> Option<EventData> {
+        let i = self.held.iter().position(|(k, ..)| *k == key)?;
+        let (_, pressed_at, sent) = self.held.swap_remove(i);
+        let d = t.saturating_sub(pressed_at);
+        (sent || d >= LONG_PRESS_MS).then_some(EventData::KeyUp { k: key, m, d })
+    }
+
+    /// Modifier pressed or released; modifiers are only recorded as keys
+    /// with key-up capture on
+    pub fn modifier(&mut self, t: u64, key: Key, down: bool, m: u8) -> Option<EventData> {
+        if !self.enabled {
+            return None;
+        }
+        if down {
+            let repeat = self.held.iter().any(|(k, ..)| *k == key);
+            self.press(t, key, true);
+            (!repeat).then_some(EventData::Key { k: key, m })
+        } else {
+            self.release(t, key, m)
+        }
+    }
+}
+
+/// Drops events recorded while a filtered-out app is frontmost.
+///
+/// The frontmost app is only known from the observer's App events, which are
+/// polled, so input in the first ~100ms after switching can still get through.
+pub(crate) struct AppFilter {
+    allow: Vec<String>,
+    block: Vec<String>,
+    recording: bool,
+}
+
+/// App names compare case-insensitively, with or without ".exe"
+fn app_matches(pattern: &str, app: &str) -> bool {
+    fn base(s: &str) -> &str {
+        let s = s.trim();
+        s.strip_suffix(".exe").or_else(|| s.strip_suffix(".EXE")).unwrap_or(s)
+    }
+    base(pattern).eq_ignore_ascii_case(base(app))
+}
+
+impl AppFilter {
+    /// `None` when the config doesn't filter apps
+    pub fn new(config: &RecorderConfig) -> Option<Self> {
+        if config.app_allowlist.is_empty() && config.app_blocklist.is_empty() {
+            return None;
+        }
+        Some(Self {
+            allow: config.app_allowlist.clone(),
+            block: config.app_blocklist.clone(),
+            // Nothing is known to be frontmost yet
+            recording: config.app_allowlist.is_empty(),
+        })
+    }
+
+    pub fn allows(&self, app: &str) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|p| app_matches(p, app)))
+            && !self.block.iter().any(|p| app_matches(p, app))
+    }
+
+    /// Whether to keep `event`; App events switch recording on and off
+    pub fn keep(&mut self, event: &Event) -> bool {
+        match &event.data {
+            EventData::App { n, .. } => {
+                self.recording = self.allows(n);
+                self.recording
+            }
+            EventData::Window { a, .. } => self.recording && self.allows(a),
+            // Session-wide, not tied to whichever app is frontmost
+            EventData::Video { .. } | EventData::VideoStopped { .. } => true,
+            _ => self.recording,
+        }
+    }
+}
+
+/// A button held down, waiting to become a Click or a Drag
+struct Press {
+    t: u64,
+    start: (i32, i32),
+    b: u8,
+    n: u8,
+    m: u8,
+    c: Option<Box<ElementContext>>,
+    path: Vec<(i32, i32)>,
+    dragging: bool,
+}
+
+/// Assembles button presses, moves and releases into Click/Drag events
+pub(crate) struct GestureTracker {
+    mode: DragMode,
+    drag_threshold: f64,
+    sample_distance: f64,
+    pressed: Option<Press>,
+}
+
+fn distance(a: (i32, i32), b: (i32, i32)) -> f64 {
+    let (dx, dy) = ((a.0 - b.0) as f64, (a.1 - b.1) as f64);
+    (dx * dx + dy * dy).sqrt()
+}
+
+impl GestureTracker {
+    pub fn new(config: &RecorderConfig) -> Self {
+        Self {
+            mode: config.drag_mode,
+            drag_threshold: config.drag_threshold,
+            sample_distance: config.mouse_move_threshold,
+            pressed: None,
+        }
+    }
+
+    /// Button pressed, `c` being the element under it; returns the event to
+    /// send right away (raw mode)
+    pub fn press(
+        &mut self,
+        t: u64,
+        (x, y): (i32, i32),
+        b: u8,
+        n: u8,
+        m: u8,
+        c: Option<Box<ElementContext>>,
+    ) -> Option<Event> {
+        match self.mode {
+            DragMode::Raw => Some(Event {
+                t,
+                ts: None,
+                data: EventData::Click { x, y, b, n, m, c },
+            }),
+            DragMode::Aggregated => {
+                self.pressed = Some(Press {
+                    t,
+                    start: (x, y),
+                    b,
+                    n,
+                    m,
+                    c,
+                    path: Vec::new(),
+                    dragging: false,
+                });
+                None
+            }
+        }
+    }
+
+    /// Pointer moved; true when the move belongs to a held button and
+    /// shouldn't be sent as a Move event
+    pub fn moved(&mut self, pos: (i32, i32)) -> bool {
+        let Some(press) = self.pressed.as_mut() else {
+            return false;
+        };
+        if !press.dragging && distance(press.start, pos) >= self.drag_threshold {
+            press.dragging = true;
+        }
+        if press.dragging {
+            let last = press.path.last().copied().unwrap_or(press.start);
+            if distance(last, pos) >= self.sample_distance {
+                press.path.push(pos);
+            }
+        }
+        true
+    }
+
+    /// Button released; returns the Click or Drag (aggregated) or MouseUp (raw)
+    pub fn release(&mut self, t: u64, (x, y): (i32, i32), b: u8) -> Option<Event> {
+        if self.mode == DragMode::Raw {
+            return Some(Event {
+                t,
+                ts: None,
+                data: EventData::MouseUp { x, y, b },
+            });
+        }
+
+        let mut press = self.pressed.take_if(|p| p.b == b)?;
+        let data = if press.dragging {
+            if press.path.last() == Some(&(x, y)) {
+                press.path.pop();
+            }
+            EventData::Drag {
+                x: press.start.0,
+                y: press.start.1,
+                x2: x,
+                y2: y,
+                b,
+                p: press.path,
+                m: press.m,
+                d: t.saturating_sub(press.t),
+            }
+        } else {
+            EventData::Click {
+                x: press.start.0,
+                y: press.start.1,
+                b,
+                n: press.n,
+                m: press.m,
+                c: press.c,
+            }
+        };
+        Some(Event { t: press.t, ts: None, data })
+    }
+}
+
+/// Describe the machine a session is being recorded on
+fn session_metadata(started_at: chrono::DateTime<chrono::Utc>) -> SessionMetadata {
+    SessionMetadata {
+        os: std::env::consts::OS.to_string(),
+        os_version: platform::current::os_version(),
+        displays: platform::current::displays(),
+        keyboard_layout: platform::current::keyboard_layout(),
+        started_at,
+        machine: machine_hash(),
+        recorder_version: env!("CARGO_PKG_VERSION").to_string(),
+    }
+}
 
-/// Recorder configuration
-#[derive(Debug, Clone)]
-pub struct RecorderConfig {
-    /// Mouse move sampling - record every N pixels moved
-    pub mouse_move_threshold: f64,
-    /// Text aggregation timeout in ms
-    pub text_timeout_ms: u64,
-    /// Max events before auto-flush to disk
-    pub max_buffer: usize,
-    /// Capture element context on clicks (slower but richer)
-    pub capture_context: bool,
+/// FNV-1a of "user@host", stable across builds unlike `DefaultHasher`
+fn machine_hash() -> Option<String> {
+    let user = std::env::var("USER").or_else(|_| std::env::var("USERNAME")).ok()?;
+    let id = format!("{}@{}", user, hostname()?);
+    let hash = id.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    Some(format!("{:016x}", hash))
 }
 
-impl Default for RecorderConfig {
-    fn default() -> Self {
-        Self {
-            mouse_move_threshold: 5.0,
-            text_timeout_ms: 300,
-            max_buffer: 10000,
-            capture_context: true,
-        }
+#[cfg(unix)]
+fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
     }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    Some(String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+#[cfg(windows)]
+fn hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
 }
 
 /// Recording handle - owns the recording session
@@ -49,30 +1193,139 @@ pub struct RecordingHandle {
     stop: Arc<AtomicBool>,
     events_rx: Receiver<Event>,
     threads: Vec<thread::JoinHandle<()>>,
+    counters: Arc<Counters>,
+    subscribers: Arc<Subscribers>,
+    spill: Option<Arc<Spill>>,
+    writer: Mutex<Option<WorkflowWriter>>,
+    /// Start a new file at each SessionEnd (`rotate`)
+    rotate: bool,
+    /// Files finished by rotating, not yet taken
+    rotated: Mutex<Vec<PathBuf>>,
+    /// For pausing
+    sink: EventSink,
 }
 
 impl RecordingHandle {
-    pub fn stop(self, workflow: &mut RecordedWorkflow) {
+    /// Append events to `writer` as they're drained instead of keeping them
+    /// in the workflow; [`stop`](Self::stop) finalizes the file
+    pub fn stream_to(&mut self, writer: WorkflowWriter) {
+        *self.writer.get_mut() = Some(writer);
+    }
+
+    /// Stop capturing and collect what's left. When streaming, returns the
+    /// path of the finished recording.
+    pub fn stop(self, workflow: &mut RecordedWorkflow) -> Option<PathBuf> {
         self.stop.store(true, Ordering::SeqCst);
-        // Drain remaining events
-        while let Ok(e) = self.events_rx.try_recv() {
-            workflow.events.push(e);
+        // Drain remaining events; first so blocked captures can finish
+        self.drain(workflow);
+        for t in &self.threads {
+            while !t.is_finished() {
+                self.drain(workflow);
+                thread::sleep(Duration::from_millis(10));
+            }
         }
+        self.drain(workflow);
+        let stats = self.counters.stats();
+        let (sent, dropped, spilled) = (stats.sent, stats.dropped, stats.spilled);
+        tracing::debug!(sent, dropped, spilled, "Recording stopped");
         for t in self.threads {
             let _ = t.join();
         }
+        self.subscribers.close();
+        let writer = self.writer.into_inner()?;
+        let partial = writer.partial_path().to_path_buf();
+        match writer.finish() {
+            Ok(path) => Some(path),
+            Err(e) => {
+                tracing::error!(path = %partial.display(), error = %e, "Can't finish recording");
+                None
+            }
+        }
     }
 
+    /// Move queued events into `workflow`, or to the file when streaming
     pub fn drain(&self, workflow: &mut RecordedWorkflow) {
-        while let Ok(e) = self.events_rx.try_recv() {
-            workflow.events.push(e);
+        let mut writer = self.writer.lock();
+        let Some(w) = writer.as_mut() else {
+            self.drain_into(&mut workflow.events);
+            return;
+        };
+        let mut events = Vec::new();
+        self.drain_into(&mut events);
+        let mut done = 0;
+        if let Err(e) = self.write(w, &events, &mut done) {
+            // Keep the rest in memory; what was written stays in the partial file
+            let path = w.partial_path().display();
+            tracing::error!(%path, error = %e, "Streaming failed, keeping events in memory");
+            *writer = None;
+            workflow.events.extend(events.into_iter().skip(done));
+        }
+    }
+
+    /// Append `events`, rotating after each SessionEnd; `done` counts the
+    /// ones written
+    fn write(&self, w: &mut WorkflowWriter, events: &[Event], done: &mut usize) -> Result<()> {
+        for event in events {
+            w.append(event)?;
+            *done += 1;
+            if self.rotate && matches!(event.data, EventData::SessionEnd { .. }) {
+                let path = w.rotate()?;
+                self.rotated.lock().push(path);
+            }
+        }
+        w.flush()
+    }
+
+    /// Recordings finished by `rotate` since the last call
+    pub fn take_rotated(&self) -> Vec<PathBuf> {
+        std::mem::take(&mut *self.rotated.lock())
+    }
+
+    fn drain_into(&self, events: &mut Vec<Event>) {
+        match &self.spill {
+            Some(spill) => spill.drain(&self.events_rx, events),
+            None => events.extend(self.events_rx.try_iter()),
         }
     }
 
+    /// Events sent, dropped and spilled so far
+    pub fn stats(&self) -> RecordingStats {
+        self.counters.stats()
+    }
+
+    /// Whether input is being captured, and how often it was cut off
+    pub fn health(&self) -> CaptureHealth {
+        self.counters.health()
+    }
+
+    /// Receive a copy of every event from now on, alongside the recording
+    pub fn subscribe(&self) -> Subscription {
+        self.subscribers.add(None)
+    }
+
+    /// [`subscribe`](Self::subscribe) to events of these kinds only
+    pub fn subscribe_to(&self, kinds: &[EventKind]) -> Subscription {
+        self.subscribers.add(Some(kinds))
+    }
+
     pub fn is_running(&self) -> bool {
         !self.stop.load(Ordering::Relaxed)
     }
 
+    /// Stop recording input until [`resume`](Self::resume), keeping the
+    /// session (and its file) open
+    pub fn pause(&self) {
+        self.sink.set_paused(true);
+    }
+
+    pub fn resume(&self) {
+        self.sink.set_paused(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.sink.paused.load(Ordering::Relaxed)
+    }
+
     /// Get the event receiver for streaming consumption
     /// Use this to process events in real-time from another thread/crate
     pub fn receiver(&self) -> &Receiver<Event> {
@@ -100,15 +1353,21 @@ pub struct EventStream {
     stop: Arc<AtomicBool>,
     events_rx: Receiver<Event>,
     threads: Vec<thread::JoinHandle<()>>,
+    counters: Arc<Counters>,
+    subscribers: Arc<Subscribers>,
 }
 
 impl EventStream {
-    /// Stop the event stream
+    /// Stop the event stream. Events not received yet are discarded.
     pub fn stop(self) {
         self.stop.store(true, Ordering::SeqCst);
+        // Unblocks captures waiting for room (Overflow::Block) before they're
+        // waited for
+        drop(self.events_rx);
         for t in self.threads {
             let _ = t.join();
         }
+        self.subscribers.close();
     }
 
     /// Check if stream is still running
@@ -116,6 +1375,26 @@ impl EventStream {
         !self.stop.load(Ordering::Relaxed)
     }
 
+    /// Events sent and dropped so far
+    pub fn stats(&self) -> RecordingStats {
+        self.counters.stats()
+    }
+
+    /// Whether input is being captured, and how often it was cut off
+    pub fn health(&self) -> CaptureHealth {
+        self.counters.health()
+    }
+
+    /// Receive a copy of every event from now on, alongside this stream
+    pub fn subscribe(&self) -> Subscription {
+        self.subscribers.add(None)
+    }
+
+    /// [`subscribe`](Self::subscribe) to events of these kinds only
+    pub fn subscribe_to(&self, kinds: &[EventKind]) -> Subscription {
+        self.subscribers.add(Some(kinds))
+    }
+
     /// Get the underlying receiver (for select! etc)
     pub fn receiver(&self) -> &Receiver<Event> {
         &self.events_rx
@@ -141,29 +1420,138 @@ impl Iterator for EventStream {
     type Item = Event;
 
     fn next(&mut self) -> Option<Self::Item> {
+        // Once stopped, only what's queued (e.g. a SessionEnd)
         if self.stop.load(Ordering::Relaxed) {
-            return None;
+            return self.events_rx.try_recv().ok();
         }
         self.events_rx.recv().ok()
     }
 }
 
-/// Permission status
-#[derive(Debug, Clone)]
-pub struct PermissionStatus {
-    pub accessibility: bool,
-    pub input_monitoring: bool,
+/// Extra event receiver for a running session, from `subscribe`. Ends when
+/// the session is stopped.
+pub struct Subscription {
+    events_rx: Receiver<Event>,
 }
 
-impl PermissionStatus {
-    pub fn all_granted(&self) -> bool {
-        self.accessibility && self.input_monitoring
+impl Subscription {
+    /// Get the underlying receiver (for select! etc)
+    pub fn receiver(&self) -> &Receiver<Event> {
+        &self.events_rx
+    }
+
+    /// Try receive without blocking
+    pub fn try_recv(&self) -> Option<Event> {
+        self.events_rx.try_recv().ok()
+    }
+
+    /// Blocking receive
+    pub fn recv(&self) -> Option<Event> {
+        self.events_rx.recv().ok()
+    }
+
+    /// Receive with timeout
+    pub fn recv_timeout(&self, timeout: std::time::Duration) -> Option<Event> {
+        self.events_rx.recv_timeout(timeout).ok()
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events_rx.recv().ok()
+    }
+}
+
+/// Async event source, from [`WorkflowRecorder::stream_async`]
+#[cfg(feature = "async")]
+pub struct AsyncEventStream {
+    stop: Arc<AtomicBool>,
+    events_rx: tokio::sync::mpsc::Receiver<Event>,
+    threads: Vec<thread::JoinHandle<()>>,
+    counters: Arc<Counters>,
+    subscribers: Arc<Subscribers>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncEventStream {
+    /// Stop the event stream. Waits for the capture threads, which notice
+    /// within ~100ms; events not received yet are discarded.
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Unblocks captures waiting for room (Overflow::Block) before they're
+        // waited for
+        drop(self.events_rx);
+        for t in self.threads {
+            let _ = t.join();
+        }
+        self.subscribers.close();
+    }
+
+    /// Check if stream is still running
+    pub fn is_running(&self) -> bool {
+        !self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Events sent and dropped so far
+    pub fn stats(&self) -> RecordingStats {
+        self.counters.stats()
+    }
+
+    /// Whether input is being captured, and how often it was cut off
+    pub fn health(&self) -> CaptureHealth {
+        self.counters.health()
+    }
+
+    /// Receive a copy of every event from now on, alongside this stream
+    pub fn subscribe(&self) -> Subscription {
+        self.subscribers.add(None)
+    }
+
+    /// [`subscribe`](Self::subscribe) to events of these kinds only
+    pub fn subscribe_to(&self, kinds: &[EventKind]) -> Subscription {
+        self.subscribers.add(Some(kinds))
+    }
+
+    /// Try receive without waiting
+    pub fn try_recv(&mut self) -> Option<Event> {
+        self.events_rx.try_recv().ok()
+    }
+
+    /// Wait for the next event
+    pub async fn recv(&mut self) -> Option<Event> {
+        if self.stop.load(Ordering::Relaxed) {
+            return self.events_rx.try_recv().ok();
+        }
+        self.events_rx.recv().await
+    }
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for AsyncEventStream {
+    type Item = Event;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Event>> {
+        // Once stopped, only what's queued (e.g. a SessionEnd)
+        if self.stop.load(Ordering::Relaxed) {
+            return std::task::Poll::Ready(self.events_rx.try_recv().ok());
+        }
+        self.events_rx.poll_recv(cx)
     }
 }
 
+/// What starts the capture threads, given the session's sink and stop flag
+type CaptureBackend = fn(CaptureContext) -> Result<Vec<thread::JoinHandle<()>>, RecorderError>;
+
 /// The recorder
 pub struct WorkflowRecorder {
     config: RecorderConfig,
+    /// The platform's, except in tests
+    capture: CaptureBackend,
 }
 
 impl WorkflowRecorder {
@@ -172,72 +1560,168 @@ impl WorkflowRecorder {
     }
 
     pub fn with_config(config: RecorderConfig) -> Self {
-        Self { config }
+        Self { config, capture: platform::current::start_capture }
+    }
+
+    /// Capture with `capture` instead of the OS, for tests without input
+    /// devices or permissions
+    #[cfg(test)]
+    fn with_capture(mut self, capture: CaptureBackend) -> Self {
+        self.capture = capture;
+        self
     }
 
     pub fn check_permissions(&self) -> PermissionStatus {
-        PermissionStatus {
-            accessibility: cidre::ax::is_process_trusted(),
-            input_monitoring: cg_access::listen_preflight(),
-        }
+        platform::current::check_permissions()
     }
 
+    /// Prompts for screen recording too when screenshots or video are on
     pub fn request_permissions(&self) -> PermissionStatus {
-        PermissionStatus {
-            accessibility: cidre::ax::is_process_trusted_with_prompt(true),
-            input_monitoring: cg_access::listen_request(),
-        }
+        platform::current::request_permissions(self.config.captures_screen())
     }
 
-    pub fn start(&self, name: impl Into<String>) -> Result<(RecordedWorkflow, RecordingHandle)> {
-        let workflow = RecordedWorkflow::new(name);
-        let (tx, rx) = self.start_capture()?;
+    pub fn start(
+        &self,
+        name: impl Into<String>,
+    ) -> Result<(RecordedWorkflow, RecordingHandle), RecorderError> {
+        let mut workflow = RecordedWorkflow::new(name);
+        let (tx, rx) = bounded::<Event>(self.config.max_buffer);
+        let capture = self.start_capture(tx.into())?;
+        workflow.meta = Some(session_metadata(capture.started_at));
 
         let handle = RecordingHandle {
-            stop: tx.1,
+            stop: capture.stop,
             events_rx: rx,
-            threads: tx.0,
+            threads: capture.threads,
+            counters: capture.counters,
+            subscribers: capture.subscribers,
+            spill: capture.spill,
+            writer: Mutex::new(None),
+            rotate: self.config.rotate,
+            rotated: Mutex::new(Vec::new()),
+            sink: capture.sink,
         };
 
         Ok((workflow, handle))
     }
 
     /// Start streaming events without workflow management
-    /// Use this when you want to consume events from another crate
-    pub fn stream(&self) -> Result<EventStream> {
-        let (internals, rx) = self.start_capture()?;
+    /// Use this when you want to consume events from another crate.
+    /// Fails with [`Overflow::Disk`], which only recordings read back.
+    pub fn stream(&self) -> Result<EventStream, RecorderError> {
+        self.check_stream_overflow()?;
+        let (tx, rx) = bounded::<Event>(self.config.max_buffer);
+        let capture = self.start_capture(tx.into())?;
 
         Ok(EventStream {
-            stop: internals.1,
+            stop: capture.stop,
             events_rx: rx,
-            threads: internals.0,
+            threads: capture.threads,
+            counters: capture.counters,
+            subscribers: capture.subscribers,
         })
     }
 
-    fn start_capture(&self) -> Result<((Vec<thread::JoinHandle<()>>, Arc<AtomicBool>), Receiver<Event>)> {
-        let (tx, rx) = bounded::<Event>(self.config.max_buffer);
-        let stop = Arc::new(AtomicBool::new(false));
-        let start_time = Instant::now();
+    /// [`stream`](Self::stream) for async code: events arrive on a tokio
+    /// channel the capture threads send to directly, so no thread is tied
+    /// up waiting on them
+    #[cfg(feature = "async")]
+    pub fn stream_async(&self) -> Result<AsyncEventStream, RecorderError> {
+        self.check_stream_overflow()?;
+        // Unlike crossbeam, tokio has no zero-capacity channel
+        let (tx, rx) = tokio::sync::mpsc::channel(self.config.max_buffer.max(1));
+        let capture = self.start_capture(Outlet::Async(tx))?;
+
+        Ok(AsyncEventStream {
+            stop: capture.stop,
+            events_rx: rx,
+            threads: capture.threads,
+            counters: capture.counters,
+            subscribers: capture.subscribers,
+        })
+    }
 
-        let mut threads = Vec::new();
+    /// Streams never read spilled events back, so they'd be lost
+    fn check_stream_overflow(&self) -> Result<(), RecorderError> {
+        if let Overflow::Disk(path) = &self.config.overflow {
+            return Err(RecorderError::Other(anyhow::anyhow!(
+                "Can't spill a stream's events to {}, only recordings read them back; \
+                 use Overflow::Drop or Overflow::Block",
+                path.display()
+            )));
+        }
+        Ok(())
+    }
 
-        // Thread 1: CGEventTap for input events (includes clipboard via Cmd+C/X/V)
-        let tx1 = tx.clone();
-        let stop1 = stop.clone();
-        let config1 = self.config.clone();
-        threads.push(thread::spawn(move || {
-            run_event_tap(tx1, stop1, start_time, config1);
-        }));
+    fn start_capture(&self, tx: Outlet) -> Result<Capture, RecorderError> {
+        // Rather than a session of black frames
+        if self.config.captures_screen() && !self.check_permissions().screen_recording {
+            let message = "Screen Recording, for screenshots and video";
+            return Err(RecorderError::PermissionDenied(message.into()));
+        }
+        let (start, started_at) = (Instant::now(), chrono::Utc::now());
+
+        let sink = EventSink::new(tx, &self.config, start);
+        let stop = sink.stop.clone();
+        let (counters, spill) = (sink.counters.clone(), sink.spill.clone());
+        let subscribers = sink.subscribers.clone();
+        let ctx = CaptureContext {
+            tx: sink.clone(),
+            stop: stop.clone(),
+            start,
+            config: self.config.clone(),
+        };
+        let mut threads = Vec::new();
+        if self.config.max_duration.is_some() {
+            threads.push(spawn_duration_limit(ctx.clone()));
+        }
+        let started = (|| -> Result<(), RecorderError> {
+            #[cfg(feature = "video")]
+            if self.config.record_video {
+                threads.push(crate::video::start(&ctx)?);
+            }
+            threads.extend((self.capture)(ctx)?);
+            Ok(())
+        })();
+        if let Err(e) = started {
+            // The threads that did start end with the session
+            stop.store(true, Ordering::SeqCst);
+            return Err(e);
+        }
+        tracing::debug!(threads = threads.len(), "Capture started");
+
+        Ok(Capture {
+            threads,
+            stop,
+            started_at,
+            counters,
+            subscribers,
+            spill,
+            sink,
+        })
+    }
+}
 
-        // Thread 2: App/window switch notifications
-        let tx2 = tx.clone();
-        let stop2 = stop.clone();
-        threads.push(thread::spawn(move || {
-            run_app_observer(tx2, stop2, start_time);
-        }));
+/// Checks `max_duration` while nothing is happening; events check it too
+fn spawn_duration_limit(ctx: CaptureContext) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while !ctx.stop.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(100));
+            ctx.tx.check_limits(ctx.start.elapsed().as_millis() as u64, false);
+        }
+    })
+}
 
-        Ok(((threads, stop), rx))
-    }
+/// A running capture, before it's wrapped in a handle or stream
+struct Capture {
+    threads: Vec<thread::JoinHandle<()>>,
+    stop: Arc<AtomicBool>,
+    /// Wall-clock time of `t` = 0
+    started_at: chrono::DateTime<chrono::Utc>,
+    counters: Arc<Counters>,
+    subscribers: Arc<Subscribers>,
+    spill: Option<Arc<Spill>>,
+    sink: EventSink,
 }
 
 impl Default for WorkflowRecorder {
@@ -246,472 +1730,288 @@ impl Default for WorkflowRecorder {
     }
 }
 
-// ============================================================================
-// Event Tap Thread
-// ============================================================================
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-struct TapState {
-    tx: Sender<Event>,
-    start: Instant,
-    config: RecorderConfig,
-    last_mouse: Mutex<(f64, f64)>,
-    text_buf: Mutex<TextBuffer>,
-}
+    #[test]
+    fn app_filter_drops_events_from_blocked_apps() {
+        let config = RecorderConfig {
+            app_blocklist: vec!["1Password".into()],
+            ..Default::default()
+        };
+        let mut filter = AppFilter::new(&config).unwrap();
+        let event = |data| Event { t: 0, ts: None, data };
+        let key = || event(EventData::Key { k: Key::A, m: 0 });
+
+        assert!(filter.keep(&key()));
+        assert!(!filter.keep(&event(EventData::App { n: "1password.exe".into(), p: 1 })));
+        assert!(!filter.keep(&key()));
+        assert!(filter.keep(&event(EventData::App { n: "Safari".into(), p: 2 })));
+        assert!(filter.keep(&key()));
+    }
 
-struct TextBuffer {
-    chars: String,
-    first_time: Option<Instant>,
-    last_time: Option<Instant>,
-    timeout_ms: u64,
-}
+    /// Per-session state of [`fake_tap`]; `alive` goes with it
+    struct TapState {
+        tx: EventSink,
+        #[allow(dead_code)]
+        alive: Arc<()>,
+    }
 
-impl TextBuffer {
-    fn new(timeout_ms: u64) -> Self {
-        Self {
-            chars: String::new(),
-            first_time: None,
-            last_time: None,
-            timeout_ms,
-        }
+    static TAPS: Mutex<Vec<std::sync::Weak<()>>> = Mutex::new(Vec::new());
+
+    /// A capture thread that holds its state as the macOS event tap thread
+    /// does, in a [`CallbackState`], until the session stops
+    fn fake_tap(ctx: CaptureContext) -> Result<Vec<thread::JoinHandle<()>>, RecorderError> {
+        let alive = Arc::new(());
+        TAPS.lock().push(Arc::downgrade(&alive));
+        Ok(vec![thread::spawn(move || {
+            let state = CallbackState::new(TapState { tx: ctx.tx, alive });
+            // What a callback would be given
+            let user_info = state.as_ptr();
+            while !ctx.stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            unsafe { &*user_info }.tx.flush_text();
+            drop(state);
+        })])
     }
 
-    fn push(&mut self, c: char) {
-        let now = Instant::now();
-        if self.chars.is_empty() {
-            self.first_time = Some(now);
+    #[test]
+    fn stopped_sessions_release_their_state() {
+        let recorder = WorkflowRecorder::new().with_capture(fake_tap);
+        for i in 0..100 {
+            let (mut workflow, handle) = recorder.start(format!("session {}", i)).unwrap();
+            let counters = Arc::downgrade(&handle.counters);
+            let tap = TAPS.lock().pop().unwrap();
+            assert!(tap.upgrade().is_some());
+            handle.stop(&mut workflow);
+            assert!(tap.upgrade().is_none(), "session {} kept its tap state", i);
+            assert!(counters.upgrade().is_none(), "session {} outlived its stop", i);
         }
-        self.chars.push(c);
-        self.last_time = Some(now);
+        assert!(TAPS.lock().is_empty());
     }
 
-    fn flush(&mut self) -> Option<String> {
-        if self.chars.is_empty() {
-            return None;
-        }
-        let s = std::mem::take(&mut self.chars);
-        self.first_time = None;
-        self.last_time = None;
-        Some(s)
+    /// A capture thread sending keys as fast as the channel takes them
+    fn flood(ctx: CaptureContext) -> Result<Vec<thread::JoinHandle<()>>, RecorderError> {
+        Ok(vec![thread::spawn(move || {
+            let key = Event { t: 0, ts: None, data: EventData::Key { k: Key::A, m: 0 } };
+            while !ctx.stop.load(Ordering::Relaxed) {
+                let _ = ctx.tx.try_send(key.clone());
+            }
+        })])
     }
 
-    fn should_flush(&self) -> bool {
-        if let Some(last) = self.last_time {
-            last.elapsed().as_millis() as u64 >= self.timeout_ms
-        } else {
-            false
+    #[test]
+    fn streams_stop_while_blocked_on_a_full_channel() {
+        let config = RecorderConfig { max_buffer: 2, overflow: Overflow::Block, ..Default::default() };
+        let recorder = WorkflowRecorder::with_config(config).with_capture(flood);
+        let stream = recorder.stream().unwrap();
+        while stream.stats().sent < 2 {
+            thread::sleep(Duration::from_millis(1));
         }
+        // Nothing reads, so the capture is stuck sending
+        thread::sleep(Duration::from_millis(20));
+        stream.stop();
+
+        let spill = Overflow::Disk(std::env::temp_dir().join("bb-stream-spill.jsonl"));
+        let config = RecorderConfig { overflow: spill, ..Default::default() };
+        let recorder = WorkflowRecorder::with_config(config).with_capture(flood);
+        assert!(recorder.stream().is_err());
     }
-}
-
-fn run_event_tap(tx: Sender<Event>, stop: Arc<AtomicBool>, start: Instant, config: RecorderConfig) {
-    // Build event mask - capture everything
-    let mask = cg::EventType::LEFT_MOUSE_DOWN.mask()
-        | cg::EventType::LEFT_MOUSE_UP.mask()
-        | cg::EventType::RIGHT_MOUSE_DOWN.mask()
-        | cg::EventType::RIGHT_MOUSE_UP.mask()
-        | cg::EventType::MOUSE_MOVED.mask()
-        | cg::EventType::LEFT_MOUSE_DRAGGED.mask()
-        | cg::EventType::RIGHT_MOUSE_DRAGGED.mask()
-        | cg::EventType::KEY_DOWN.mask()
-        | cg::EventType::SCROLL_WHEEL.mask();
-
-    let state = Box::leak(Box::new(TapState {
-        tx,
-        start,
-        config: config.clone(),
-        last_mouse: Mutex::new((0.0, 0.0)),
-        text_buf: Mutex::new(TextBuffer::new(config.text_timeout_ms)),
-    }));
-
-    let tap = cg::EventTap::new(
-        cg::EventTapLocation::Session,
-        cg::EventTapPlacement::TailAppend,
-        cg::EventTapOpts::LISTEN_ONLY,
-        mask,
-        tap_callback,
-        state as *mut TapState,
-    );
-
-    let Some(tap) = tap else {
-        eprintln!("Failed to create event tap");
-        return;
-    };
 
-    let Some(src) = cf::MachPort::run_loop_src(&tap, 0) else {
-        eprintln!("Failed to create run loop source");
-        return;
-    };
-
-    let rl = cf::RunLoop::current();
-    rl.add_src(&src, cf::RunLoopMode::default());
+    static SECURE: AtomicBool = AtomicBool::new(false);
 
-    while !stop.load(Ordering::Relaxed) {
-        cf::RunLoop::run_in_mode(cf::RunLoopMode::default(), 0.05, true);
-
-        // Check text buffer timeout
-        let mut buf = state.text_buf.lock();
-        if buf.should_flush() {
-            if let Some(s) = buf.flush() {
-                let _ = state.tx.try_send(Event {
-                    t: state.start.elapsed().as_millis() as u64,
-                    data: EventData::Text { s },
-                });
-            }
+    fn text(e: Event) -> String {
+        match e.data {
+            EventData::Text { s } => s,
+            data => panic!("{:?}", data),
         }
     }
 
-    // Final flush
-    let mut buf = state.text_buf.lock();
-    if let Some(s) = buf.flush() {
-        let _ = state.tx.try_send(Event {
-            t: state.start.elapsed().as_millis() as u64,
-            data: EventData::Text { s },
-        });
+    /// Wait for the current run's password field check
+    fn checked(sink: &EventSink) {
+        let answered = || {
+            let text = sink.text.lock();
+            text.secure.as_ref().unwrap().answer(text.run).is_some()
+        };
+        while !answered() {
+            thread::sleep(Duration::from_millis(1));
+        }
     }
 
-    rl.remove_src(&src, cf::RunLoopMode::default());
-}
+    #[test]
+    fn tab_into_a_password_field_redacts_what_follows() {
+        let (tx, rx) = bounded(8);
+        let sink = EventSink::new(tx, &RecorderConfig::default(), Instant::now());
+        sink.text.lock().secure = Some(SecureFocus::spawn(|| SECURE.load(Ordering::Relaxed)));
+
+        "user".chars().for_each(|c| assert!(sink.type_char(c)));
+        checked(&sink);
+        assert!(sink.type_char('\t'));
+        SECURE.store(true, Ordering::Relaxed);
+        "hunter2".chars().for_each(|c| assert!(sink.type_char(c)));
+        checked(&sink);
+        sink.flush_text();
+        assert_eq!(text(rx.recv().unwrap()), "user\t");
+        assert_eq!(text(rx.recv().unwrap()), REDACTED);
+        assert!(rx.try_recv().is_err());
+    }
 
-extern "C" fn tap_callback(
-    _proxy: *mut cg::EventTapProxy,
-    event_type: cg::EventType,
-    event: &mut cg::Event,
-    user_info: *mut TapState,
-) -> Option<&cg::Event> {
-    let state = unsafe { &*user_info };
-    let t = state.start.elapsed().as_millis() as u64;
-    let loc = event.location();
-    let flags = event.flags().0;
-    let mods = Modifiers::from_cg_flags(flags);
+    static ANSWER: AtomicBool = AtomicBool::new(false);
 
-    match event_type {
-        cg::EventType::LEFT_MOUSE_DOWN | cg::EventType::RIGHT_MOUSE_DOWN => {
-            let btn = if event_type == cg::EventType::LEFT_MOUSE_DOWN { 0 } else { 1 };
-            let clicks = event.field_i64(cg::EventField::MOUSE_EVENT_CLICK_STATE) as u8;
+    #[test]
+    fn text_is_redacted_until_its_field_is_checked() {
+        let (tx, rx) = bounded(8);
+        let config = RecorderConfig { text_timeout_ms: 0, ..Default::default() };
+        let sink = EventSink::new(tx, &config, Instant::now());
+        let slow = || {
+            while !ANSWER.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+            false
+        };
+        sink.text.lock().secure = Some(SecureFocus::spawn(slow));
+
+        "typed".chars().for_each(|c| assert!(sink.type_char(c)));
+        assert!(!sink.text.lock().should_flush());
+        sink.flush_text();
+        assert_eq!(text(rx.recv().unwrap()), REDACTED);
+
+        ANSWER.store(true, Ordering::Relaxed);
+        "typed".chars().for_each(|c| assert!(sink.type_char(c)));
+        checked(&sink);
+        assert!(sink.text.lock().should_flush());
+        sink.flush_idle_text();
+        assert_eq!(text(rx.recv().unwrap()), "typed");
+    }
 
-            let _ = state.tx.try_send(Event {
-                t,
-                data: EventData::Click {
-                    x: loc.x as i32,
-                    y: loc.y as i32,
-                    b: btn,
-                    n: clicks,
-                    m: mods.0,
-                },
-            });
+    #[test]
+    fn full_channel_leaves_a_dropped_marker() {
+        let (tx, rx) = bounded(2);
+        let sink = EventSink::new(tx, &RecorderConfig::default(), Instant::now());
+        let key = |t| Event { t, ts: None, data: EventData::Key { k: Key::A, m: 0 } };
 
-            // Capture element context in background (non-blocking)
-            if state.config.capture_context {
-                let tx = state.tx.clone();
-                let x = loc.x;
-                let y = loc.y;
-                let start = state.start;
-                std::thread::spawn(move || {
-                    if let Some(ctx) = get_element_context(x, y) {
-                        let _ = tx.try_send(Event {
-                            t: start.elapsed().as_millis() as u64,
-                            data: ctx,
-                        });
-                    }
-                });
-            }
+        for t in 0..4 {
+            let _ = sink.try_send(key(t));
         }
+        assert_eq!(rx.try_iter().map(|e| e.t).collect::<Vec<_>>(), [0, 1]);
+        sink.try_send(key(4)).unwrap();
+        assert!(matches!(rx.recv().unwrap().data, EventData::Dropped { n: 2 }));
+        assert_eq!(rx.recv().unwrap().t, 4);
+        assert_eq!(sink.counters.stats().dropped, 2);
+    }
 
-        cg::EventType::MOUSE_MOVED
-        | cg::EventType::LEFT_MOUSE_DRAGGED
-        | cg::EventType::RIGHT_MOUSE_DRAGGED => {
-            let mut last = state.last_mouse.lock();
-            let dx = loc.x - last.0;
-            let dy = loc.y - last.1;
-            let dist = (dx * dx + dy * dy).sqrt();
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_outlet_leaves_a_dropped_marker() {
+        let (tx, mut rx) = tokio::sync::mpsc::channel(2);
+        let sink = EventSink::new(Outlet::Async(tx), &RecorderConfig::default(), Instant::now());
+        let key = |t| Event { t, ts: None, data: EventData::Key { k: Key::A, m: 0 } };
+
+        sink.try_send(key(0)).unwrap();
+        sink.try_send(key(1)).unwrap();
+        assert!(matches!(sink.try_send(key(2)), Err(TrySendError::Full(_))));
+        assert_eq!(rx.try_recv().unwrap().t, 0);
+        assert_eq!(rx.try_recv().unwrap().t, 1);
+        sink.try_send(key(3)).unwrap();
+        assert!(matches!(rx.try_recv().unwrap().data, EventData::Dropped { n: 1 }));
+        assert_eq!(rx.try_recv().unwrap().t, 3);
+    }
 
-            if dist >= state.config.mouse_move_threshold {
-                *last = (loc.x, loc.y);
-                let _ = state.tx.try_send(Event {
-                    t,
-                    data: EventData::Move {
-                        x: loc.x as i32,
-                        y: loc.y as i32,
-                    },
-                });
-            }
-        }
+    #[test]
+    fn subscribers_get_filtered_copies() {
+        let (tx, rx) = bounded(10);
+        let sink = EventSink::new(tx, &RecorderConfig::default(), Instant::now());
+        let all = sink.subscribers.add(None);
+        let apps = sink.subscribers.add(Some(&[EventKind::App]));
+        let key = Event { t: 0, ts: None, data: EventData::Key { k: Key::A, m: 0 } };
+        let app = Event { t: 1, ts: None, data: EventData::App { n: "Notes".into(), p: 2 } };
+
+        sink.try_send(key).unwrap();
+        sink.try_send(app).unwrap();
+        assert_eq!(rx.try_iter().count(), 2);
+        assert_eq!(all.receiver().try_iter().count(), 2);
+        assert_eq!(apps.receiver().try_iter().map(|e| e.t).collect::<Vec<_>>(), [1]);
+
+        sink.subscribers.close();
+        assert!(all.recv().is_none());
+    }
 
-        cg::EventType::SCROLL_WHEEL => {
-            let dy = event.field_i64(cg::EventField::SCROLL_WHEEL_EVENT_DELTA_AXIS1) as i16;
-            let dx = event.field_i64(cg::EventField::SCROLL_WHEEL_EVENT_DELTA_AXIS2) as i16;
-            if dx != 0 || dy != 0 {
-                let _ = state.tx.try_send(Event {
-                    t,
-                    data: EventData::Scroll {
-                        x: loc.x as i32,
-                        y: loc.y as i32,
-                        dx,
-                        dy,
-                    },
-                });
-            }
-        }
+    #[test]
+    fn event_limit_ends_or_rotates_the_session() {
+        let key = |t| Event { t, ts: None, data: EventData::Key { k: Key::A, m: 0 } };
+        for rotate in [false, true] {
+            let (tx, rx) = bounded(10);
+            let config = RecorderConfig { max_events: Some(2), rotate, ..Default::default() };
+            let sink = EventSink::new(tx, &config, Instant::now());
 
-        cg::EventType::KEY_DOWN => {
-            let keycode = event.field_i64(cg::EventField::KEYBOARD_EVENT_KEYCODE) as u16;
-
-            // Check for clipboard operations (Cmd+C, Cmd+X, Cmd+V)
-            if mods.has_cmd() && !mods.has_ctrl() {
-                match keycode {
-                    KEY_C => {
-                        // Copy - capture clipboard after a short delay
-                        let tx = state.tx.clone();
-                        let start = state.start;
-                        std::thread::spawn(move || {
-                            // Wait for clipboard to be populated
-                            std::thread::sleep(std::time::Duration::from_millis(50));
-                            if let Some(content) = get_clipboard() {
-                                let _ = tx.try_send(Event {
-                                    t: start.elapsed().as_millis() as u64,
-                                    data: EventData::Paste { o: 'c', s: truncate(&content, 100) },
-                                });
-                            }
-                        });
-                        // Also record the key event
-                        let _ = state.tx.try_send(Event {
-                            t,
-                            data: EventData::Key { k: keycode, m: mods.0 },
-                        });
-                    }
-                    KEY_X => {
-                        // Cut - capture clipboard after a short delay
-                        let tx = state.tx.clone();
-                        let start = state.start;
-                        std::thread::spawn(move || {
-                            std::thread::sleep(std::time::Duration::from_millis(50));
-                            if let Some(content) = get_clipboard() {
-                                let _ = tx.try_send(Event {
-                                    t: start.elapsed().as_millis() as u64,
-                                    data: EventData::Paste { o: 'x', s: truncate(&content, 100) },
-                                });
-                            }
-                        });
-                        let _ = state.tx.try_send(Event {
-                            t,
-                            data: EventData::Key { k: keycode, m: mods.0 },
-                        });
-                    }
-                    KEY_V => {
-                        // Paste - capture what's being pasted
-                        if let Some(content) = get_clipboard() {
-                            let _ = state.tx.try_send(Event {
-                                t,
-                                data: EventData::Paste { o: 'v', s: truncate(&content, 100) },
-                            });
-                        }
-                        let _ = state.tx.try_send(Event {
-                            t,
-                            data: EventData::Key { k: keycode, m: mods.0 },
-                        });
-                    }
-                    _ => {
-                        // Other Cmd combo
-                        let _ = state.tx.try_send(Event {
-                            t,
-                            data: EventData::Key { k: keycode, m: mods.0 },
-                        });
-                    }
-                }
-            } else if mods.any_modifier() {
-                // Other modifier combo
-                let _ = state.tx.try_send(Event {
-                    t,
-                    data: EventData::Key { k: keycode, m: mods.0 },
-                });
-            } else if let Some(c) = keycode_to_char(keycode, mods) {
-                // Aggregate into text buffer
-                state.text_buf.lock().push(c);
-            } else {
-                // Unknown key, record as key event
-                let _ = state.tx.try_send(Event {
-                    t,
-                    data: EventData::Key { k: keycode, m: mods.0 },
-                });
+            for t in 0..3 {
+                sink.try_send(key(t)).unwrap();
             }
+            let events: Vec<_> = rx.try_iter().collect();
+            assert!(matches!(&events[2].data, EventData::SessionEnd { r } if r == "events"));
+            assert_eq!(sink.stop.load(Ordering::SeqCst), !rotate);
+            // The capture threads do the stopping, so the third key still arrives
+            assert_eq!(events.len(), 4);
         }
-
-        _ => {}
     }
 
-    Some(event)
-}
-
-/// Get clipboard content via pbpaste
-fn get_clipboard() -> Option<String> {
-    std::process::Command::new("pbpaste")
-        .output()
-        .ok()
-        .filter(|o| o.status.success())
-        .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
-        .filter(|s| !s.is_empty())
-}
-
-fn get_element_context(x: f64, y: f64) -> Option<EventData> {
-    use cidre::ax;
-
-    let sys = ax::UiElement::sys_wide();
-    let elem = sys.element_at_pos(x as f32, y as f32).ok()?;
-
-    let role = elem.role().ok().map(|r| {
-        let s = format!("{:?}", r);
-        s.find("AX").map(|i| {
-            let rest = &s[i..];
-            rest.find(|c| c == ')' || c == '"').map(|j| rest[..j].to_string()).unwrap_or(rest.to_string())
-        }).unwrap_or_else(|| "?".to_string())
-    })?;
-
-    let name = get_str_attr(&elem, ax::attr::title())
-        .or_else(|| get_str_attr(&elem, ax::attr::desc()));
-    let value = get_str_attr(&elem, ax::attr::value());
-
-    Some(EventData::Context {
-        r: role,
-        n: name.map(|s| truncate(&s, 50)),
-        v: value.map(|s| truncate(&s, 50)),
-    })
-}
+    #[test]
+    fn hotkeys_pause_and_stop() {
+        let (tx, rx) = bounded(10);
+        let pause = Hotkey::new(Key::P, Modifiers::CTRL | Modifiers::SHIFT);
+        let stop = Hotkey::new(Key::S, Modifiers::CTRL | Modifiers::SHIFT);
+        let config = RecorderConfig {
+            pause_hotkey: Some(pause),
+            stop_hotkey: Some(stop),
+            ..Default::default()
+        };
+        let sink = EventSink::new(tx, &config, Instant::now());
+        let key = |t, h: Hotkey| Event { t, ts: None, data: EventData::Key { k: h.key, m: h.m } };
+        let a = Hotkey::new(Key::A, Modifiers::CTRL);
 
-fn get_str_attr(elem: &cidre::ax::UiElement, attr: &cidre::ax::Attr) -> Option<String> {
-    elem.attr_value(attr).ok().and_then(|v| {
-        if v.get_type_id() == cidre::cf::String::type_id() {
-            let s: &cidre::cf::String = unsafe { std::mem::transmute(&*v) };
-            Some(s.to_string())
-        } else {
-            None
+        for (t, hotkey) in [(0, a), (1, pause), (2, a), (3, pause), (4, a), (5, stop)] {
+            sink.try_send(key(t, hotkey)).unwrap();
         }
-    })
-}
-
-fn truncate(s: &str, max: usize) -> String {
-    if s.len() <= max { s.to_string() } else { format!("{}...", &s[..max-3]) }
-}
-
-// ============================================================================
-// App/Window Observer Thread (polling-based for reliability)
-// ============================================================================
-
-fn run_app_observer(tx: Sender<Event>, stop: Arc<AtomicBool>, start: Instant) {
-    let workspace = ns::Workspace::shared();
-
-    let mut last_app: Option<String> = None;
-    let mut last_pid: i32 = 0;
-    let mut last_window: Option<String> = None;
-
-    while !stop.load(Ordering::Relaxed) {
-        // Find the active (frontmost) application
-        let apps = workspace.running_apps();
-        let active_app = apps.iter().find(|app| app.is_active());
-
-        if let Some(app) = active_app {
-            let name = app.localized_name()
-                .map(|s| s.to_string())
-                .unwrap_or_else(|| "?".to_string());
-            let pid = app.pid();
-
-            // Check if app changed
-            let app_changed = last_app.as_ref() != Some(&name) || last_pid != pid;
+        assert_eq!(rx.try_iter().map(|e| e.t).collect::<Vec<_>>(), [0, 4]);
+        assert!(sink.stop.load(Ordering::SeqCst));
+    }
 
-            if app_changed {
-                let _ = tx.try_send(Event {
-                    t: start.elapsed().as_millis() as u64,
-                    data: EventData::App { n: name.clone(), p: pid },
-                });
-                last_app = Some(name.clone());
-                last_pid = pid;
-            }
+    #[test]
+    fn long_gaps_become_idle_events() {
+        let (tx, rx) = bounded(10);
+        let config = RecorderConfig { collapse_idle_ms: Some(1000), ..Default::default() };
+        let sink = EventSink::new(tx, &config, Instant::now());
+        let key = |t| Event { t, ts: None, data: EventData::Key { k: Key::A, m: 0 } };
 
-            // Check if window changed (even within same app - catches tab switches)
-            let window_title = get_focused_window_title(pid);
-            if window_title != last_window || app_changed {
-                let _ = tx.try_send(Event {
-                    t: start.elapsed().as_millis() as u64,
-                    data: EventData::Window {
-                        a: name,
-                        w: window_title.as_ref().map(|s| truncate(s, 100)),
-                    },
-                });
-                last_window = window_title;
-            }
+        for t in [0, 800, 5800] {
+            sink.try_send(key(t)).unwrap();
         }
+        let events: Vec<_> = rx.try_iter().collect();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[2].t, 1800);
+        assert!(matches!(events[2].data, EventData::Idle { d: 4000 }));
+    }
 
-        // Poll every 100ms
-        std::thread::sleep(std::time::Duration::from_millis(100));
-    }
-}
-
-/// Get the focused window title for a given app PID
-fn get_focused_window_title(pid: i32) -> Option<String> {
-    use cidre::ax;
-
-    let app = ax::UiElement::with_app_pid(pid);
-
-    // Get focused window via attribute
-    let focused_window_val = app.attr_value(ax::attr::focused_window()).ok()?;
-
-    // Cast to UiElement
-    if focused_window_val.get_type_id() == ax::UiElement::type_id() {
-        let focused_window: &ax::UiElement = unsafe { std::mem::transmute(&*focused_window_val) };
-        get_str_attr(focused_window, ax::attr::title())
-    } else {
-        None
-    }
-}
-
-// ============================================================================
-// Keycode Mapping
-// ============================================================================
-
-fn keycode_to_char(keycode: u16, mods: Modifiers) -> Option<char> {
-    let shift = mods.0 & Modifiers::SHIFT != 0 || mods.0 & Modifiers::CAPS != 0;
-
-    let c = match keycode {
-        // Letters
-        0 => 'a', 1 => 's', 2 => 'd', 3 => 'f', 4 => 'h', 5 => 'g', 6 => 'z', 7 => 'x',
-        8 => 'c', 9 => 'v', 11 => 'b', 12 => 'q', 13 => 'w', 14 => 'e', 15 => 'r',
-        16 => 'y', 17 => 't', 31 => 'o', 32 => 'u', 34 => 'i', 35 => 'p', 37 => 'l',
-        38 => 'j', 40 => 'k', 45 => 'n', 46 => 'm',
-        // Numbers
-        18 => if shift { '!' } else { '1' },
-        19 => if shift { '@' } else { '2' },
-        20 => if shift { '#' } else { '3' },
-        21 => if shift { '$' } else { '4' },
-        22 => if shift { '^' } else { '6' },
-        23 => if shift { '%' } else { '5' },
-        24 => if shift { '+' } else { '=' },
-        25 => if shift { '(' } else { '9' },
-        26 => if shift { '&' } else { '7' },
-        27 => if shift { '_' } else { '-' },
-        28 => if shift { '*' } else { '8' },
-        29 => if shift { ')' } else { '0' },
-        // Punctuation
-        30 => if shift { '}' } else { ']' },
-        33 => if shift { '{' } else { '[' },
-        39 => if shift { '"' } else { '\'' },
-        41 => if shift { ':' } else { ';' },
-        42 => if shift { '|' } else { '\\' },
-        43 => if shift { '<' } else { ',' },
-        44 => if shift { '?' } else { '/' },
-        47 => if shift { '>' } else { '.' },
-        50 => if shift { '~' } else { '`' },
-        // Whitespace
-        36 => '\n',
-        48 => '\t',
-        49 => ' ',
-        // Backspace - special handling
-        51 => '\x08',
-        _ => return None,
-    };
+    #[test]
+    fn typed_text_is_edited_and_flushed_on_focus_change() {
+        let (tx, rx) = bounded(10);
+        let config = RecorderConfig { redact_secure_input: false, ..Default::default() };
+        let sink = EventSink::new(tx, &config, Instant::now());
 
-    // Handle shift for letters
-    if shift && c.is_ascii_lowercase() {
-        Some(c.to_ascii_uppercase())
-    } else {
-        Some(c)
+        for c in "fooo\x08".chars() {
+            assert!(sink.type_char(c));
+        }
+        let app = EventData::App { n: "Notes".into(), p: 2 };
+        sink.try_send(Event { t: 7, ts: None, data: app }).unwrap();
+        // Nothing left to delete: the caller records the key
+        assert!(!sink.type_char('\x08'));
+
+        let events: Vec<_> = rx.try_iter().collect();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].t, 7);
+        assert!(matches!(&events[0].data, EventData::Text { s } if s == "foo"));
+        assert!(matches!(events[1].data, EventData::App { .. }));
     }
 }
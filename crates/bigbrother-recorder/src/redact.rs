@@ -0,0 +1,266 @@
+//! PII scrubbing for captured text
+//!
+//! Redactors run inside the recorder, on Text, Paste, element context and URLs
+//! before they are sent, so raw secrets never reach the session buffer or
+//! disk. The built-in [`Scrubber`]s cover emails, credit card numbers and
+//! API keys; implement [`Redactor`] for anything else.
+
+use crate::events::EventData;
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Rewrites captured text before it is recorded
+pub trait Redactor: Send + Sync {
+    /// `text` with sensitive parts replaced, borrowed back when nothing matched
+    fn redact<'a>(&self, text: &'a str) -> Cow<'a, str>;
+}
+
+impl fmt::Debug for dyn Redactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Redactor")
+    }
+}
+
+impl<F> Redactor for F
+where
+    F: Fn(&str) -> String + Send + Sync,
+{
+    fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        Cow::Owned(self(text))
+    }
+}
+
+/// Built-in scrubbers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scrubber {
+    /// `user@example.com` becomes `[email]`
+    Email,
+    /// 13-19 digit numbers passing the Luhn check become `[card]`
+    CreditCard,
+    /// Tokens with a well-known key prefix (`sk-`, `ghp_`, `AKIA`...) become `[api-key]`
+    ApiKey,
+}
+
+impl Scrubber {
+    pub const ALL: [Scrubber; 3] = [Scrubber::Email, Scrubber::CreditCard, Scrubber::ApiKey];
+
+    fn marker(self) -> &'static str {
+        match self {
+            Scrubber::Email => "[email]",
+            Scrubber::CreditCard => "[card]",
+            Scrubber::ApiKey => "[api-key]",
+        }
+    }
+
+    fn find(self, text: &str) -> Vec<Range<usize>> {
+        match self {
+            Scrubber::Email => find_emails(text),
+            Scrubber::CreditCard => find_cards(text),
+            Scrubber::ApiKey => find_api_keys(text),
+        }
+    }
+}
+
+impl Redactor for Scrubber {
+    fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        replace(text, &self.find(text), self.marker())
+    }
+}
+
+/// Every built-in scrubber, for `RecorderConfig::redactors`
+pub fn default_redactors() -> Vec<Arc<dyn Redactor>> {
+    Scrubber::ALL
+        .into_iter()
+        .map(|s| Arc::new(s) as Arc<dyn Redactor>)
+        .collect()
+}
+
+/// Run `redactors` over the free text of an event
+pub(crate) fn redact_event(data: &mut EventData, redactors: &[Arc<dyn Redactor>]) {
+    if redactors.is_empty() {
+        return;
+    }
+    let apply = |s: &mut String| {
+        for r in redactors {
+            let redacted = match r.redact(s) {
+                Cow::Owned(out) => Some(out),
+                Cow::Borrowed(_) => None,
+            };
+            if let Some(out) = redacted {
+                *s = out;
+            }
+        }
+    };
+    match data {
+        EventData::Text { s } | EventData::Paste { s, .. } | EventData::ScreenText { s, .. } => {
+            apply(s)
+        }
+        EventData::Context { n, v, .. } => n.iter_mut().chain(v.iter_mut()).for_each(apply),
+        EventData::Click { c: Some(c), .. } => {
+            c.n.iter_mut().chain(c.v.iter_mut()).chain(c.u.iter_mut()).for_each(apply);
+            apply(&mut c.s);
+        }
+        // URLs can carry tokens in their query strings
+        EventData::Window { u: Some(u), .. } => apply(u),
+        _ => {}
+    }
+}
+
+fn replace<'a>(text: &'a str, spans: &[Range<usize>], marker: &str) -> Cow<'a, str> {
+    if spans.is_empty() {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for span in spans {
+        out.push_str(&text[last..span.start]);
+        out.push_str(marker);
+        last = span.end;
+    }
+    out.push_str(&text[last..]);
+    Cow::Owned(out)
+}
+
+// The scanners below only split on ASCII bytes, so every span boundary is a
+// char boundary.
+
+fn find_emails(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let local = |b: u8| b.is_ascii_alphanumeric() || b"._%+-".contains(&b);
+    let domain = |b: u8| b.is_ascii_alphanumeric() || b == b'.' || b == b'-';
+
+    let mut spans = Vec::new();
+    let mut next = 0;
+    for (at, _) in text.match_indices('@') {
+        if at < next {
+            continue;
+        }
+        let start = bytes[next..at]
+            .iter()
+            .rposition(|&b| !local(b))
+            .map_or(next, |i| next + i + 1);
+        let mut end = bytes[at + 1..]
+            .iter()
+            .position(|&b| !domain(b))
+            .map_or(bytes.len(), |i| at + 1 + i);
+        // A trailing dot ends the sentence, not the address
+        while end > at + 1 && bytes[end - 1] == b'.' {
+            end -= 1;
+        }
+        let has_tld = text[at + 1..end].rsplit_once('.').is_some_and(|(name, tld)| {
+            !name.is_empty() && tld.len() >= 2 && tld.bytes().all(|b| b.is_ascii_alphabetic())
+        });
+        if start < at && has_tld {
+            spans.push(start..end);
+            next = end;
+        }
+    }
+    spans
+}
+
+fn find_cards(text: &str) -> Vec<Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+        // Digits, with single spaces or dashes between groups
+        let start = i;
+        let mut end = i;
+        let mut digits = Vec::new();
+        for j in start..bytes.len() {
+            match bytes[j] {
+                b @ b'0'..=b'9' => {
+                    digits.push(b - b'0');
+                    end = j + 1;
+                }
+                b' ' | b'-' if bytes[j - 1].is_ascii_digit()
+                    && bytes.get(j + 1).is_some_and(|b| b.is_ascii_digit()) => {}
+                _ => break,
+            }
+        }
+        let attached = (start > 0 && bytes[start - 1].is_ascii_alphanumeric())
+            || bytes.get(end).is_some_and(|b| b.is_ascii_alphanumeric());
+        if !attached && (13..=19).contains(&digits.len()) && luhn(&digits) {
+            spans.push(start..end);
+        }
+        i = end;
+    }
+    spans
+}
+
+fn luhn(digits: &[u8]) -> bool {
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            let d = d as u32;
+            match (i % 2 == 1, d * 2) {
+                (true, doubled) if doubled > 9 => doubled - 9,
+                (true, doubled) => doubled,
+                (false, _) => d,
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Key prefixes of common services (OpenAI/Stripe, GitHub, GitLab, Slack,
+/// AWS, Google)
+const KEY_PREFIXES: &[&str] = &[
+    "sk-", "sk_live_", "sk_test_", "rk_live_", "ghp_", "gho_", "ghu_", "ghs_", "github_pat_",
+    "glpat-", "xoxb-", "xoxp-", "AKIA", "AIza",
+];
+const MIN_KEY_LEN: usize = 20;
+
+fn find_api_keys(text: &str) -> Vec<Range<usize>> {
+    let token = |b: u8| b.is_ascii_alphanumeric() || b == b'_' || b == b'-';
+    let bytes = text.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !token(bytes[i]) {
+            i += 1;
+            continue;
+        }
+        let end = bytes[i..]
+            .iter()
+            .position(|&b| !token(b))
+            .map_or(bytes.len(), |n| i + n);
+        let word = &text[i..end];
+        if word.len() >= MIN_KEY_LEN && KEY_PREFIXES.iter().any(|p| word.starts_with(p)) {
+            spans.push(i..end);
+        }
+        i = end;
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scrub(text: &str) -> String {
+        let mut data = EventData::Text { s: text.to_string() };
+        redact_event(&mut data, &default_redactors());
+        match data {
+            EventData::Text { s } => s,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn scrubs_builtin_patterns() {
+        assert_eq!(scrub("mail jane.doe+x@example.co.uk."), "mail [email].");
+        assert_eq!(scrub("card 4111 1111 1111 1111 exp"), "card [card] exp");
+        assert_eq!(scrub("key=sk-abcdefghijklmnopqrstuv"), "key=[api-key]");
+        // Not a valid card number, and not an address
+        assert_eq!(scrub("order 1234567890123 @home"), "order 1234567890123 @home");
+    }
+}
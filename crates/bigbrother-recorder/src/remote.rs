@@ -0,0 +1,327 @@
+//! Replay on another machine
+//!
+//! A [`RemoteInjector`] turns each [`Injector`] call into a [`Command`],
+//! sent as JSON over a [`Transport`] to an [`Agent`] on the other machine,
+//! which runs it with that machine's native injector and answers with a
+//! [`Reply`]. Timing, sync and recovery stay on the replaying side:
+//!
+//! ```rust,no_run
+//! use bigbrother_recorder::prelude::*;
+//! use bigbrother_recorder::remote::Transport;
+//!
+//! /// Whatever carries text both ways: a WebSocket, HTTP, a pipe
+//! struct Socket;
+//!
+//! impl Transport for Socket {
+//!     fn send(&mut self, message: &str) -> anyhow::Result<String> {
+//!         unimplemented!("send {} and return the answer", message)
+//!     }
+//! }
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let workflow = WorkflowStorage::new()?.load("invoices")?;
+//! Replayer::with_transport(Socket).play(&workflow)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `bb serve` is an agent, at `ws://host:port/inject`, and `bb replay
+//! --remote` its client, sending the agent's token. Every call is a round trip, so sync checks and
+//! assertions cost one each.
+
+use crate::events::ElementContext;
+use crate::keys::Key;
+use crate::platform;
+use crate::replay::Injector;
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Carries a message to an [`Agent`] and brings back its answer
+pub trait Transport: Send {
+    fn send(&mut self, message: &str) -> Result<String>;
+}
+
+/// An [`Injector`] call, as sent to an [`Agent`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Command {
+    Click { x: i32, y: i32, button: u8, clicks: u8, modifiers: u8 },
+    MoveTo { x: i32, y: i32 },
+    Drag { path: Vec<(i32, i32)>, button: u8, modifiers: u8, ms: u64 },
+    Scroll { x: i32, y: i32, dx: i16, dy: i16, pixels: bool },
+    Key { key: Key, modifiers: u8 },
+    KeyDown { key: Key, modifiers: u8 },
+    KeyUp { key: Key, modifiers: u8 },
+    TypeText { text: String },
+    Activate { app: String },
+    FrontmostApp,
+    ElementReady { x: i32, y: i32, context: ElementContext },
+    ElementAt { x: i32, y: i32 },
+    WindowTitle,
+    CursorPosition,
+    EscapePressed,
+    SetClipboard { text: String },
+    Locate { context: ElementContext },
+}
+
+/// An [`Agent`]'s answer: what the call returned (null for actions), or why
+/// it failed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reply {
+    Ok(Value),
+    Error(String),
+}
+
+/// Runs [`Command`]s from a [`RemoteInjector`] on this machine
+pub struct Agent {
+    injector: Box<dyn Injector>,
+}
+
+impl Agent {
+    // Not every platform's injector is a unit struct
+    #[allow(clippy::default_constructed_unit_structs)]
+    pub fn new() -> Self {
+        Self::with_injector(platform::current::NativeInjector::default())
+    }
+
+    /// Run commands through a custom injector instead of the native one
+    pub fn with_injector(injector: impl Injector + 'static) -> Self {
+        Self { injector: Box::new(injector) }
+    }
+
+    /// Answer one message from a [`Transport`]
+    pub fn handle(&self, message: &str) -> String {
+        let reply = match serde_json::from_str(message) {
+            Ok(command) => match self.execute(&command) {
+                Ok(value) => Reply::Ok(value),
+                Err(e) => Reply::Error(e.to_string()),
+            },
+            Err(e) => Reply::Error(format!("Invalid command: {}", e)),
+        };
+        serde_json::to_string(&reply).unwrap()
+    }
+
+    pub fn execute(&self, command: &Command) -> Result<Value> {
+        let injector = &self.injector;
+        Ok(match command {
+            Command::Click { x, y, button, clicks, modifiers } => {
+                json!(injector.click(*x, *y, *button, *clicks, *modifiers)?)
+            }
+            Command::MoveTo { x, y } => json!(injector.move_to(*x, *y)?),
+            Command::Drag { path, button, modifiers, ms } => {
+                json!(injector.drag(path, *button, *modifiers, Duration::from_millis(*ms))?)
+            }
+            Command::Scroll { x, y, dx, dy, pixels } => {
+                json!(injector.scroll(*x, *y, *dx, *dy, *pixels)?)
+            }
+            Command::Key { key, modifiers } => json!(injector.key(*key, *modifiers)?),
+            Command::KeyDown { key, modifiers } => json!(injector.key_down(*key, *modifiers)?),
+            Command::KeyUp { key, modifiers } => json!(injector.key_up(*key, *modifiers)?),
+            Command::TypeText { text } => json!(injector.type_text(text)?),
+            Command::Activate { app } => json!(injector.activate(app)?),
+            Command::FrontmostApp => json!(injector.frontmost_app()),
+            Command::ElementReady { x, y, context } => {
+                json!(injector.element_ready(*x, *y, context))
+            }
+            Command::ElementAt { x, y } => json!(injector.element_at(*x, *y)),
+            Command::WindowTitle => json!(injector.window_title()),
+            Command::CursorPosition => json!(injector.cursor_position()),
+            Command::EscapePressed => json!(injector.escape_pressed()),
+            Command::SetClipboard { text } => json!(injector.set_clipboard(text)?),
+            Command::Locate { context } => json!(injector.locate(context)),
+        })
+    }
+}
+
+impl Default for Agent {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Injects through an [`Agent`] on another machine. Queries that fail to
+/// get an answer return what an injector that can't tell would.
+pub struct RemoteInjector<T> {
+    transport: Mutex<T>,
+}
+
+impl<T: Transport> RemoteInjector<T> {
+    pub fn new(transport: T) -> Self {
+        Self { transport: Mutex::new(transport) }
+    }
+
+    fn call<R: DeserializeOwned>(&self, command: Command) -> Result<R> {
+        let message = serde_json::to_string(&command)?;
+        let answer = self.transport.lock().send(&message)?;
+        match serde_json::from_str(&answer)? {
+            Reply::Ok(value) => Ok(serde_json::from_value(value)?),
+            Reply::Error(e) => Err(anyhow!("{}", e)),
+        }
+    }
+
+    fn act(&self, command: Command) -> Result<()> {
+        self.call::<Value>(command).map(drop)
+    }
+}
+
+impl<T: Transport> Injector for RemoteInjector<T> {
+    fn click(&self, x: i32, y: i32, button: u8, clicks: u8, modifiers: u8) -> Result<()> {
+        self.act(Command::Click { x, y, button, clicks, modifiers })
+    }
+
+    fn move_to(&self, x: i32, y: i32) -> Result<()> {
+        self.act(Command::MoveTo { x, y })
+    }
+
+    fn drag(
+        &self,
+        path: &[(i32, i32)],
+        button: u8,
+        modifiers: u8,
+        duration: Duration,
+    ) -> Result<()> {
+        let ms = duration.as_millis() as u64;
+        self.act(Command::Drag { path: path.to_vec(), button, modifiers, ms })
+    }
+
+    fn scroll(&self, x: i32, y: i32, dx: i16, dy: i16, pixels: bool) -> Result<()> {
+        self.act(Command::Scroll { x, y, dx, dy, pixels })
+    }
+
+    fn key(&self, key: Key, modifiers: u8) -> Result<()> {
+        self.act(Command::Key { key, modifiers })
+    }
+
+    fn key_down(&self, key: Key, modifiers: u8) -> Result<()> {
+        self.act(Command::KeyDown { key, modifiers })
+    }
+
+    fn key_up(&self, key: Key, modifiers: u8) -> Result<()> {
+        self.act(Command::KeyUp { key, modifiers })
+    }
+
+    fn type_text(&self, text: &str) -> Result<()> {
+        self.act(Command::TypeText { text: text.to_string() })
+    }
+
+    fn activate(&self, app: &str) -> Result<()> {
+        self.act(Command::Activate { app: app.to_string() })
+    }
+
+    fn frontmost_app(&self) -> Option<String> {
+        self.call(Command::FrontmostApp).ok().flatten()
+    }
+
+    fn element_ready(&self, x: i32, y: i32, context: &ElementContext) -> bool {
+        let context = context.clone();
+        self.call(Command::ElementReady { x, y, context }).unwrap_or(true)
+    }
+
+    fn element_at(&self, x: i32, y: i32) -> Option<ElementContext> {
+        self.call(Command::ElementAt { x, y }).ok().flatten()
+    }
+
+    fn window_title(&self) -> Option<String> {
+        self.call(Command::WindowTitle).ok().flatten()
+    }
+
+    fn cursor_position(&self) -> Option<(i32, i32)> {
+        self.call(Command::CursorPosition).ok().flatten()
+    }
+
+    fn escape_pressed(&self) -> bool {
+        self.call(Command::EscapePressed).unwrap_or(false)
+    }
+
+    fn set_clipboard(&self, text: &str) -> Result<()> {
+        self.act(Command::SetClipboard { text: text.to_string() })
+    }
+
+    fn locate(&self, context: &ElementContext) -> Option<(i32, i32)> {
+        self.call(Command::Locate { context: context.clone() }).ok().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{Event, EventData, RecordedWorkflow};
+    use crate::replay::Replayer;
+    use std::sync::Arc;
+
+    /// Writes down what it's asked to do
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<String>>>);
+
+    impl Injector for Log {
+        fn click(&self, x: i32, y: i32, _: u8, clicks: u8, _: u8) -> Result<()> {
+            self.0.lock().push(format!("click {},{} x{}", x, y, clicks));
+            Ok(())
+        }
+        fn move_to(&self, _: i32, _: i32) -> Result<()> {
+            Ok(())
+        }
+        fn drag(&self, _: &[(i32, i32)], _: u8, _: u8, _: Duration) -> Result<()> {
+            Ok(())
+        }
+        fn scroll(&self, _: i32, _: i32, _: i16, _: i16, _: bool) -> Result<()> {
+            Ok(())
+        }
+        fn key(&self, key: Key, _: u8) -> Result<()> {
+            self.0.lock().push(format!("key {}", key));
+            Ok(())
+        }
+        fn key_down(&self, _: Key, _: u8) -> Result<()> {
+            Ok(())
+        }
+        fn key_up(&self, _: Key, _: u8) -> Result<()> {
+            Ok(())
+        }
+        fn type_text(&self, text: &str) -> Result<()> {
+            if text == "fail" {
+                anyhow::bail!("no keyboard");
+            }
+            self.0.lock().push(format!("type {}", text));
+            Ok(())
+        }
+        fn frontmost_app(&self) -> Option<String> {
+            Some("Notes".into())
+        }
+    }
+
+    /// Hands messages straight to an agent, as a network would
+    struct Loopback(Agent);
+
+    impl Transport for Loopback {
+        fn send(&mut self, message: &str) -> Result<String> {
+            Ok(self.0.handle(message))
+        }
+    }
+
+    #[test]
+    fn replays_through_an_agent() {
+        let log = Log::default();
+        let mut workflow = RecordedWorkflow::new("remote");
+        workflow.events = [
+            EventData::Click { x: 10, y: 20, b: 0, n: 2, m: 0, c: None },
+            EventData::Text { s: "hi".into() },
+            EventData::Key { k: Key::Return, m: 0 },
+        ]
+        .into_iter()
+        .map(|data| Event { t: 0, ts: None, data })
+        .collect();
+        let transport = Loopback(Agent::with_injector(log.clone()));
+        Replayer::with_transport(transport).abort_on_input(false).play(&workflow).unwrap();
+        assert_eq!(*log.0.lock(), ["click 10,20 x2", "type hi", "key return"]);
+
+        let remote = RemoteInjector::new(Loopback(Agent::with_injector(log)));
+        assert_eq!(remote.frontmost_app().as_deref(), Some("Notes"));
+        assert_eq!(remote.type_text("fail").unwrap_err().to_string(), "no keyboard");
+        assert!(Agent::default().handle("{\"op\": \"fly\"}").starts_with("{\"error\""));
+    }
+}
@@ -1,34 +1,359 @@
-//! Workflow replay using CGEvent injection
+//! Workflow replay
+//!
+//! Timing and bookkeeping are shared; the actual input injection is done by
+//! an [`Injector`], which defaults to the current platform's native one.
+//!
+//! App switches are replayed by bringing the app to the front. Input waits
+//! for the app that was frontmost when it was recorded, and clicks for the
+//! element they landed on, so a slow app doesn't get its input early. See
+//! [`Replayer::sync`].
+//!
+//! For debugging, [`Replayer::range`] replays part of a workflow, and
+//! [`Replayer::step_mode`] and [`Replayer::breakpoints`] pause before events
+//! and ask what to do with them.
+//!
+//! Moving the mouse or pressing Escape aborts a replay, see
+//! [`Replayer::abort_on_input`].
+//!
+//! [`EventData::Assert`] events check the replay worked, see
+//! [`ReplayStats::assertions`].
+//!
+//! [`Replayer::repeat`] and [`Replayer::repeat_until`] run a workflow over
+//! and over; see [`Schedule`](crate::schedule::Schedule) for running it at
+//! set times.
+//!
+//! Typed and pasted text can hold `{{name}}` placeholders, filled in from
+//! [`Replayer::with_vars`] so one recording can be re-run with other inputs.
+//!
+//! To show progress and stop a replay from elsewhere, as a GUI or server
+//! would, use [`Replayer::play_with`] and [`Replayer::cancel_with`].
+//!
+//! An event that fails, or a click whose element doesn't show up, stops the
+//! replay unless [`Replayer::on_failure`] says how to [`Recovery`] from it.
+//!
+//! [`Replayer::with_transport`] replays on another machine.
 
 use crate::events::*;
-use anyhow::Result;
-use std::time::Duration;
+use crate::keys::Key;
+use crate::platform;
+use crate::remote::{RemoteInjector, Transport};
+use crate::text::fill_placeholders;
+use anyhow::{bail, Result};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use cidre::cg;
+/// How often sync conditions are checked
+const SYNC_POLL: Duration = Duration::from_millis(50);
+/// How far the pointer may be from where replay left it
+const CURSOR_SLACK: i32 = 4;
+/// How long after replaying Escape it may still read as pressed
+const ESCAPE_GRACE: Duration = Duration::from_millis(300);
+/// Pause before retrying a failed event
+const RETRY_DELAY: Duration = Duration::from_millis(500);
 
-// Raw FFI for CGEventPost (not exposed by cidre)
-#[link(name = "CoreGraphics", kind = "framework")]
-extern "C" {
-    fn CGEventPost(tap: u32, event: *const std::ffi::c_void);
+/// Low-level input injection used by the replayer
+pub trait Injector: Send {
+    /// Click `clicks` times at screen coordinates (button: 0=left, 1=right,
+    /// 2=middle) with packed `Modifiers` held
+    fn click(&self, x: i32, y: i32, button: u8, clicks: u8, modifiers: u8) -> Result<()>;
+    fn move_to(&self, x: i32, y: i32) -> Result<()>;
+    /// Press `button` at the first point, move through the rest over
+    /// `duration`, release at the last, with packed `Modifiers` held
+    fn drag(
+        &self,
+        path: &[(i32, i32)],
+        button: u8,
+        modifiers: u8,
+        duration: Duration,
+    ) -> Result<()>;
+    /// Scroll by `dx`, `dy` lines, or pixels with `pixels`
+    fn scroll(&self, x: i32, y: i32, dx: i16, dy: i16, pixels: bool) -> Result<()>;
+    /// Press and release a key with packed `Modifiers`
+    fn key(&self, key: Key, modifiers: u8) -> Result<()>;
+    /// Press a key and keep it held (recordings with key-up capture)
+    fn key_down(&self, key: Key, modifiers: u8) -> Result<()>;
+    /// Release a key pressed with `key_down`
+    fn key_up(&self, key: Key, modifiers: u8) -> Result<()>;
+    fn type_text(&self, text: &str) -> Result<()>;
+
+    /// Bring the running app with this name, as App events record it, to
+    /// the front. Injectors that can't do this leave focus alone.
+    fn activate(&self, _app: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Name of the frontmost app, as App events record it; `None` if unknown
+    fn frontmost_app(&self) -> Option<String> {
+        None
+    }
+
+    /// Whether the element a click at `x`, `y` was recorded on is there.
+    /// Injectors that can't tell say yes.
+    fn element_ready(&self, _x: i32, _y: i32, _context: &ElementContext) -> bool {
+        true
+    }
+
+    /// The element at `x`, `y`, for assertions; `None` if there's none or
+    /// the injector can't tell
+    fn element_at(&self, _x: i32, _y: i32) -> Option<ElementContext> {
+        None
+    }
+
+    /// Title of the frontmost window, for assertions; `None` if unknown
+    fn window_title(&self) -> Option<String> {
+        None
+    }
+
+    /// Where the pointer is, to notice the user moving it; `None` if unknown
+    fn cursor_position(&self) -> Option<(i32, i32)> {
+        None
+    }
+
+    /// Whether Escape is down right now. Injectors that can't tell say no.
+    fn escape_pressed(&self) -> bool {
+        false
+    }
+
+    /// Replace the clipboard with `text`, for pastes of substituted text
+    fn set_clipboard(&self, _text: &str) -> Result<()> {
+        bail!("Setting the clipboard isn't supported by this injector")
+    }
+
+    /// Where the element `context` was recorded on is now, found by its
+    /// selector, for [`Recovery::Relocate`]; `None` if it isn't there or
+    /// the injector can't search
+    fn locate(&self, _context: &ElementContext) -> Option<(i32, i32)> {
+        None
+    }
+
+    /// Save a screenshot to `path`, for [`Recovery::Screenshot`]
+    fn capture_screen(&self, _path: &Path) -> Result<()> {
+        bail!("Screenshots aren't supported by this injector")
+    }
+}
+
+/// What to do with the event replay is paused at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    /// Replay it, pausing again at the next event in step mode
+    Run,
+    /// Don't replay it
+    Skip,
+    /// Replay it and carry on without pausing until the next breakpoint
+    Continue,
+    /// Stop the replay; [`Replayer::play`] returns an error
+    Abort,
+}
+
+/// Where a replay is, for [`ReplayObserver`]s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Which run, counting from 0, when repeating
+    pub run: usize,
+    /// Index of the event in the workflow
+    pub index: usize,
+    /// Events of this run handled before this one
+    pub done: usize,
+    /// Events this run replays
+    pub total: usize,
+}
+
+/// Told about each event as it's replayed, see [`Replayer::play_with`].
+/// Closures taking `(&Progress, &Event)` observe events starting.
+pub trait ReplayObserver {
+    /// About to wait for and replay an event
+    fn event_started(&mut self, _progress: &Progress, _event: &Event) {}
+
+    /// An event was replayed; `stats` include it
+    fn event_finished(&mut self, _progress: &Progress, _event: &Event, _stats: &ReplayStats) {}
+
+    /// A run is over, `stats` covering it and the runs before
+    fn run_finished(&mut self, _run: usize, _stats: &ReplayStats) {}
+}
+
+impl<F: FnMut(&Progress, &Event)> ReplayObserver for F {
+    fn event_started(&mut self, progress: &Progress, event: &Event) {
+        self(progress, event)
+    }
+}
+
+/// Stops a replay from another thread. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop replays watching this token at their next event or wait
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// An event that failed to replay, for [`Replayer::on_failure`]
+#[derive(Debug)]
+pub struct Failure<'a> {
+    /// Index of the event in the workflow
+    pub index: usize,
+    pub event: &'a Event,
+    pub error: &'a anyhow::Error,
+    /// How many times the event has failed so far, from 1
+    pub attempt: u32,
+}
+
+/// What to do about a [`Failure`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recovery {
+    /// Replay the event again, until it has failed more than this many
+    /// times
+    Retry(u32),
+    /// Click where the element's selector finds it now, for clicks recorded
+    /// with element context
+    Relocate,
+    /// Go on with the next event
+    Skip,
+    /// Save a screenshot to this path, then stop
+    Screenshot(PathBuf),
+    /// Stop the replay with the error
+    Abort,
 }
 
-/// Post an event to the system
-fn post_event(event: &cg::Event, location: u32) {
-    unsafe {
-        CGEventPost(location, event as *const _ as *const std::ffi::c_void);
+/// Decides what to do about failed events
+type FailureHandler = Box<dyn Fn(&Failure) -> Recovery + Send>;
+
+/// What [`Replayer::recover`] settled on
+enum Recovered {
+    Retry,
+    Skip,
+    /// Try this copy of the event instead
+    Moved(Event),
+}
+
+/// The user or caller stopped the replay, so there's nothing to recover
+#[derive(Debug)]
+struct Stopped(String);
+
+impl fmt::Display for Stopped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
     }
 }
 
-const HID_EVENT_TAP: u32 = 0;
+impl std::error::Error for Stopped {}
+
+/// Carried from one event to the next within a run
+struct Run {
+    /// Frontmost app as of the latest App or Window event
+    app: Option<String>,
+    /// Keys replay holds down
+    down: HashSet<Key>,
+    last_t: u64,
+    last_click: Option<Instant>,
+}
+
+/// Decides at each pause, given the event's index
+type Prompt = Box<dyn Fn(usize, &Event) -> Step + Send>;
+
+/// Decides after each run whether to stop repeating
+type Until = Box<dyn Fn(&ReplayStats) -> bool + Send>;
+
+/// Where a partial replay starts or ends
+enum Mark {
+    Index(usize),
+    /// The first Annotation containing this text
+    Label(String),
+}
+
+/// Input replay caused itself, to tell it apart from the user's
+#[derive(Debug, Default, Clone, Copy)]
+struct Injected {
+    /// Where replay left the pointer
+    cursor: Option<(i32, i32)>,
+    /// When replay last pressed or released Escape
+    escape: Option<Instant>,
+    escape_held: bool,
+}
 
 /// Replay recorded workflows
 pub struct Replayer {
     speed: f64,
+    max_wait: Option<Duration>,
+    min_click_delay: Option<Duration>,
+    key_delay: Option<Duration>,
+    paste_over: Option<usize>,
+    fast_forward_moves: bool,
+    sync: Option<Duration>,
+    activate_apps: bool,
+    abort_on_input: bool,
+    strict_apps: bool,
+    fail_fast: bool,
+    injected: Cell<Injected>,
+    step: bool,
+    breakpoints: HashSet<usize>,
+    prompt: Prompt,
+    start: Mark,
+    end: Mark,
+    vars: HashMap<String, String>,
+    repeat: Option<usize>,
+    until: Option<Until>,
+    cancel: Option<CancellationToken>,
+    on_failure: Option<FailureHandler>,
+    injector: Box<dyn Injector>,
 }
 
 impl Replayer {
+    // Not every platform's injector is a unit struct
+    #[allow(clippy::default_constructed_unit_structs)]
     pub fn new() -> Self {
-        Self { speed: 1.0 }
+        Self::with_injector(platform::current::NativeInjector::default())
+    }
+
+    /// Replay through a custom injector instead of the native one
+    pub fn with_injector(injector: impl Injector + 'static) -> Self {
+        Self {
+            speed: 1.0,
+            max_wait: None,
+            min_click_delay: None,
+            key_delay: None,
+            paste_over: None,
+            fast_forward_moves: false,
+            sync: Some(Duration::from_secs(5)),
+            activate_apps: true,
+            abort_on_input: true,
+            strict_apps: false,
+            fail_fast: false,
+            injected: Cell::default(),
+            step: false,
+            breakpoints: HashSet::new(),
+            prompt: Box::new(terminal_prompt),
+            start: Mark::Index(0),
+            end: Mark::Index(usize::MAX),
+            vars: HashMap::new(),
+            repeat: None,
+            until: None,
+            cancel: None,
+            on_failure: None,
+            injector: Box::new(injector),
+        }
+    }
+
+    /// Replay on another machine, through an agent at the other end of
+    /// `transport`; see [`remote`](crate::remote)
+    pub fn with_transport(transport: impl Transport + 'static) -> Self {
+        Self::with_injector(RemoteInjector::new(transport))
     }
 
     /// Set playback speed (1.0 = real-time, 2.0 = 2x speed)
@@ -37,147 +362,794 @@ impl Replayer {
         self
     }
 
-    /// Replay a workflow
+    /// Never wait longer than `max` between two events, for recordings
+    /// made without `collapse_idle_ms`
+    pub fn max_wait(mut self, max: Duration) -> Self {
+        self.max_wait = Some(max);
+        self
+    }
+
+    /// Leave at least `delay` between two clicks, whatever the speed, for
+    /// apps that take a fast second click for a double-click
+    pub fn min_delay_between_clicks(mut self, delay: Duration) -> Self {
+        self.min_click_delay = Some(delay);
+        self
+    }
+
+    /// Type text at `cps` characters per second instead of as fast as the
+    /// injector goes, for apps that drop keystrokes
+    pub fn typing_cps(mut self, cps: f64) -> Self {
+        self.key_delay = (cps > 0.0).then(|| Duration::from_secs_f64(1.0 / cps));
+        self
+    }
+
+    /// Type text a character at a time, `delay` apart, instead of handing
+    /// it all to the injector; zero goes back to that
+    pub fn key_delay(mut self, delay: Duration) -> Self {
+        self.key_delay = (!delay.is_zero()).then_some(delay);
+        self
+    }
+
+    /// Paste typed text longer than `chars` characters instead: set the
+    /// clipboard and press Cmd+V (Ctrl+V off macOS), which leaves the text on
+    /// the clipboard. Typing goes on with injectors that can't set it.
+    pub fn paste_text_over(mut self, chars: usize) -> Self {
+        self.paste_over = Some(chars);
+        self
+    }
+
+    /// Don't wait before mouse moves: they only matter for where the pointer
+    /// ends up, so they can go at any speed
+    pub fn fast_forward_moves(mut self, fast: bool) -> Self {
+        self.fast_forward_moves = fast;
+        self
+    }
+
+    /// Wait up to `timeout` (5s by default) for the recorded app to be
+    /// frontmost before input, and for the recorded element before a click.
+    /// Input goes ahead after the timeout, counted in
+    /// [`ReplayStats::sync_timeouts`]. `None` replays on the recorded
+    /// timeline alone.
+    pub fn sync(mut self, timeout: Option<Duration>) -> Self {
+        self.sync = timeout;
+        self
+    }
+
+    /// Bring apps to the front where the recording switched to them (on by
+    /// default). Off, input goes to whatever is frontmost.
+    pub fn activate_apps(mut self, activate: bool) -> Self {
+        self.activate_apps = activate;
+        self
+    }
+
+    /// Abort when the user moves the mouse away from where replay put it or
+    /// presses Escape (on by default), so a replay gone wrong can be stopped
+    /// before it types into the wrong window
+    pub fn abort_on_input(mut self, abort: bool) -> Self {
+        self.abort_on_input = abort;
+        self
+    }
+
+    /// Abort when input's recorded app still isn't frontmost after the
+    /// [`sync`](Self::sync) timeout, instead of going ahead
+    pub fn strict_apps(mut self, strict: bool) -> Self {
+        self.strict_apps = strict;
+        self
+    }
+
+    /// Abort at the first failed assertion instead of reporting them all in
+    /// [`ReplayStats::assertions`]
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    /// Pause before every event, asking on the terminal what to do unless
+    /// [`on_pause`](Self::on_pause) says otherwise
+    pub fn step_mode(mut self) -> Self {
+        self.step = true;
+        self
+    }
+
+    /// Pause before the events at these indices
+    pub fn breakpoints(mut self, indices: impl IntoIterator<Item = usize>) -> Self {
+        self.breakpoints.extend(indices);
+        self
+    }
+
+    /// Decide what to do at pauses with `prompt` instead of asking on the
+    /// terminal
+    pub fn on_pause(mut self, prompt: impl Fn(usize, &Event) -> Step + Send + 'static) -> Self {
+        self.prompt = Box::new(prompt);
+        self
+    }
+
+    /// Only replay events `start..end`. The app that was frontmost at
+    /// `start` is brought to the front first.
+    pub fn range(mut self, start: usize, end: usize) -> Self {
+        self.start = Mark::Index(start);
+        self.end = Mark::Index(end);
+        self
+    }
+
+    /// Start at the first annotation containing `label` (case-insensitive)
+    pub fn from_label(mut self, label: &str) -> Self {
+        self.start = Mark::Label(label.to_string());
+        self
+    }
+
+    /// Stop before the first annotation containing `label` after the start
+    pub fn to_label(mut self, label: &str) -> Self {
+        self.end = Mark::Label(label.to_string());
+        self
+    }
+
+    /// Values for `{{name}}` placeholders in typed and pasted text. Replay
+    /// fails before it starts if the workflow uses one that isn't given.
+    pub fn with_vars<K, V>(mut self, vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.vars.extend(vars.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Replay the workflow `n` times in a row, or at most `n` times with
+    /// [`repeat_until`](Self::repeat_until)
+    pub fn repeat(mut self, n: usize) -> Self {
+        self.repeat = Some(n);
+        self
+    }
+
+    /// Replay the workflow again until `done`, asked after each run with the
+    /// stats so far, says to stop
+    pub fn repeat_until(mut self, done: impl Fn(&ReplayStats) -> bool + Send + 'static) -> Self {
+        self.until = Some(Box::new(done));
+        self
+    }
+
+    /// Stop when `token` is cancelled. Replay fails with "Replay cancelled",
+    /// releasing any keys it holds down.
+    pub fn cancel_with(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Decide what to do when an event fails, instead of stopping. With a
+    /// handler, a click whose element doesn't show up within the
+    /// [`sync`](Self::sync) timeout fails too, rather than going ahead.
+    /// The user taking over or cancelling always stops the replay.
+    pub fn on_failure(mut self, handler: impl Fn(&Failure) -> Recovery + Send + 'static) -> Self {
+        self.on_failure = Some(Box::new(handler));
+        self
+    }
+
+    /// Replay a workflow, once unless repeating. Stats add up over the runs.
     pub fn play(&self, workflow: &RecordedWorkflow) -> Result<ReplayStats> {
+        self.play_with(workflow, |_: &Progress, _: &Event| {})
+    }
+
+    /// [`play`](Self::play), telling `observer` about each event
+    #[tracing::instrument(
+        name = "replay",
+        skip_all,
+        fields(workflow = %workflow.name, events = workflow.events.len()),
+        err
+    )]
+    pub fn play_with(
+        &self,
+        workflow: &RecordedWorkflow,
+        mut observer: impl ReplayObserver,
+    ) -> Result<ReplayStats> {
         let mut stats = ReplayStats::default();
-        let mut last_t = 0u64;
+        let range = self.resolve_range(&workflow.events)?;
+        self.check_vars(&workflow.events[range.clone()])?;
+        let held = held_keys(&workflow.events);
+        let runs = self.repeat.unwrap_or(if self.until.is_some() { usize::MAX } else { 1 });
 
-        for event in &workflow.events {
-            // Wait for the right time
-            if event.t > last_t {
-                let delay_ms = ((event.t - last_t) as f64 / self.speed) as u64;
-                if delay_ms > 0 {
-                    std::thread::sleep(Duration::from_millis(delay_ms));
+        while stats.runs < runs {
+            self.check_cancelled()?;
+            self.play_once(workflow, range.clone(), &held, &mut observer, &mut stats)?;
+            stats.runs += 1;
+            observer.run_finished(stats.runs - 1, &stats);
+            if self.until.as_ref().is_some_and(|done| done(&stats)) {
+                break;
+            }
+        }
+        Ok(stats)
+    }
+
+    fn play_once(
+        &self,
+        workflow: &RecordedWorkflow,
+        range: std::ops::Range<usize>,
+        held: &HashSet<usize>,
+        observer: &mut dyn ReplayObserver,
+        stats: &mut ReplayStats,
+    ) -> Result<()> {
+        self.injected.set(Injected::default());
+        let mut run = Run {
+            // Frontmost app as of the latest App or Window event
+            app: workflow.events[..range.start].iter().rev().find_map(|e| match &e.data {
+                EventData::App { n, .. } | EventData::Window { a: n, .. } => Some(n.clone()),
+                _ => None,
+            }),
+            down: HashSet::new(),
+            last_t: workflow.events.get(range.start).map_or(0, |e| e.t),
+            last_click: None,
+        };
+
+        let result = self.play_events(workflow, range, held, &mut run, observer, stats);
+        // Never leave a key stuck down, even if replay failed halfway
+        for key in run.down {
+            let _ = self.injector.key_up(key, 0);
+        }
+        result
+    }
+
+    fn play_events(
+        &self,
+        workflow: &RecordedWorkflow,
+        range: std::ops::Range<usize>,
+        held: &HashSet<usize>,
+        run: &mut Run,
+        observer: &mut dyn ReplayObserver,
+        stats: &mut ReplayStats,
+    ) -> Result<()> {
+        // Starting partway: get to where the recording was
+        if let Some(app) = run.app.as_deref().filter(|_| self.activate_apps && range.start > 0) {
+            if self.activate(app)? {
+                stats.apps += 1;
+            } else {
+                stats.sync_timeouts += 1;
+            }
+        }
+        let mut stepping = self.step;
+        let events = workflow.events[range.clone()].iter();
+        for (i, event) in (range.start..).zip(events) {
+            let progress =
+                Progress { run: stats.runs, index: i, done: i - range.start, total: range.len() };
+            observer.event_started(&progress, event);
+            if stepping || self.breakpoints.contains(&i) {
+                let step = (self.prompt)(i, event);
+                // The pause took the place of the wait before this event
+                run.last_t = run.last_t.max(event.t);
+                // and the mouse may have moved during it
+                let cursor = self.injector.cursor_position();
+                self.injected.set(Injected { cursor, ..self.injected.get() });
+                match step {
+                    Step::Run => stepping = true,
+                    Step::Skip => {
+                        stepping = true;
+                        continue;
+                    }
+                    Step::Continue => stepping = false,
+                    Step::Abort => bail!("Replay aborted at event {}", i),
                 }
             }
-            last_t = event.t;
 
-            // Replay the event
-            match &event.data {
-                EventData::Click { x, y, b, n, .. } => {
-                    self.click(*x, *y, *b, *n)?;
-                    stats.clicks += 1;
+            // Wait for the right time
+            let fast = self.fast_forward_moves && matches!(event.data, EventData::Move { .. });
+            if event.t > run.last_t && !fast {
+                let delay_ms = ((event.t - run.last_t) as f64 / self.speed) as u64;
+                let delay = Duration::from_millis(delay_ms);
+                let delay = self.max_wait.map_or(delay, |max| delay.min(max));
+                self.sleep(delay)?;
+            }
+            run.last_t = event.t;
+            if let (EventData::Click { .. }, Some(min), Some(last)) =
+                (&event.data, self.min_click_delay, run.last_click)
+            {
+                self.sleep(min.saturating_sub(last.elapsed()))?;
+            }
+
+            let mut event = Cow::Borrowed(event);
+            let mut attempt = 0;
+            loop {
+                let error = match self.replay_event(i, &event, held, run, stats) {
+                    Ok(()) => break,
+                    Err(e) => e,
+                };
+                attempt += 1;
+                tracing::warn!(index = i, attempt, error = %error, "event failed");
+                let relocated = matches!(event, Cow::Owned(_));
+                let recovered = self.recover(i, &event, error, attempt, relocated)?;
+                stats.recoveries += 1;
+                match recovered {
+                    Recovered::Retry => {}
+                    Recovered::Skip => break,
+                    Recovered::Moved(moved) => event = Cow::Owned(moved),
                 }
-                EventData::Move { x, y } => {
-                    self.move_to(*x, *y)?;
-                    stats.moves += 1;
+            }
+            observer.event_finished(&progress, &event, stats);
+        }
+
+        Ok(())
+    }
+
+    /// Wait for what the event at `i` was recorded against, then replay it
+    #[tracing::instrument(
+        name = "event",
+        level = "debug",
+        skip_all,
+        fields(index = i, kind = event.data.kind().name(), app = run.app.as_deref()),
+        err
+    )]
+    fn replay_event(
+        &self,
+        i: usize,
+        event: &Event,
+        held: &HashSet<usize>,
+        run: &mut Run,
+        stats: &mut ReplayStats,
+    ) -> Result<()> {
+        if !self.synchronize(&event.data, run.app.as_deref())? {
+            if let (true, Some(app)) = (self.strict_apps, &run.app) {
+                if let Some(other) = self.injector.frontmost_app().filter(|f| f != app) {
+                    bail!("Replay aborted at event {}: {} is frontmost, not {}", i, other, app);
                 }
-                EventData::Scroll { x, y, dx, dy } => {
-                    self.scroll(*x, *y, *dx, *dy)?;
-                    stats.scrolls += 1;
+            }
+            // With a failure handler, not finding the element is a failure
+            if let (Some(_), EventData::Click { x, y, c: Some(c), .. }) =
+                (&self.on_failure, &event.data)
+            {
+                bail!("{} not found at ({}, {})", c.r, x, y);
+            }
+            stats.sync_timeouts += 1;
+        }
+        self.check_input()?;
+
+        match &event.data {
+            EventData::Click { x, y, b, n, m, .. } => {
+                self.injector.click(*x, *y, *b, *n, *m)?;
+                run.last_click = Some(Instant::now());
+                self.moved_to(*x, *y);
+                stats.clicks += 1;
+            }
+            EventData::Move { x, y } => {
+                self.injector.move_to(*x, *y)?;
+                self.moved_to(*x, *y);
+                stats.moves += 1;
+            }
+            EventData::Drag { x, y, x2, y2, b, p, m, d } => {
+                let mut path = Vec::with_capacity(p.len() + 2);
+                path.push((*x, *y));
+                path.extend_from_slice(p);
+                path.push((*x2, *y2));
+                let duration = Duration::from_millis((*d as f64 / self.speed) as u64);
+                self.injector.drag(&path, *b, *m, duration)?;
+                self.moved_to(*x2, *y2);
+                // The drag itself took `d`, don't wait for it again
+                run.last_t = event.t + d;
+                stats.drags += 1;
+            }
+            EventData::Scroll { x, y, dx, dy, px } => {
+                self.injector.scroll(*x, *y, *dx, *dy, *px)?;
+                self.moved_to(*x, *y);
+                stats.scrolls += 1;
+            }
+            EventData::Key { k, m } if held.contains(&i) => {
+                self.injector.key_down(*k, *m)?;
+                self.pressed(*k, true);
+                run.down.insert(*k);
+                stats.keys += 1;
+            }
+            EventData::Key { k, m } => {
+                self.injector.key(*k, *m)?;
+                self.pressed(*k, false);
+                stats.keys += 1;
+            }
+            EventData::KeyUp { k, m, .. } if run.down.contains(k) => {
+                self.injector.key_up(*k, *m)?;
+                self.pressed(*k, false);
+                run.down.remove(k);
+            }
+            EventData::Text { s } => {
+                let s = self.substitute(s)?;
+                self.type_text(&s)?;
+                stats.text_chars += s.chars().count();
+            }
+            // The cmd+v after it pastes whatever is on the clipboard by
+            // then; only pastes with placeholders put their own text there
+            EventData::Paste { o: 'v', .. } => {
+                if let Some(text) = paste_text(&event.data)? {
+                    if let Cow::Owned(text) = self.substitute(&text)? {
+                        self.injector.set_clipboard(&text)?;
+                    }
                 }
-                EventData::Key { k, m } => {
-                    self.key(*k, *m)?;
-                    stats.keys += 1;
+            }
+            EventData::Assert { a } => {
+                let (passed, found) = self.check(a)?;
+                let found = if passed { None } else { found };
+                let assertion = a.clone();
+                let run = stats.runs;
+                stats.assertions.push(AssertionResult { index: i, run, assertion, passed, found });
+                if !passed && self.fail_fast {
+                    return Err(Stopped(format!("Assertion failed at event {}: {}", i, a)).into());
                 }
-                EventData::Text { s } => {
-                    self.type_text(s)?;
-                    stats.text_chars += s.len();
+            }
+            // Skip the idle stretch
+            EventData::Idle { d } => run.last_t = event.t + d,
+            EventData::App { n, .. } | EventData::Window { a: n, .. } => {
+                run.app = Some(n.clone());
+                if self.activate_apps && self.injector.frontmost_app().as_ref() != Some(n) {
+                    if self.activate(n)? {
+                        stats.apps += 1;
+                    } else {
+                        stats.sync_timeouts += 1;
+                    }
                 }
-                // Context, App, Paste events are informational - skip during replay
-                _ => {}
             }
+            // Context, Paste, MouseUp events are informational - skip during replay
+            _ => {}
         }
-
-        Ok(stats)
+        Ok(())
     }
 
-    fn click(&self, x: i32, y: i32, button: u8, clicks: u8) -> Result<()> {
-        let pos = cg::Point { x: x as f64, y: y as f64 };
-        let btn = match button {
-            0 => cg::MouseButton::Left,
-            1 => cg::MouseButton::Right,
-            _ => cg::MouseButton::Center,
+    /// Ask the failure handler what to do about `error` replaying the event
+    /// at `i`; the error to stop with if it gives up
+    fn recover(
+        &self,
+        i: usize,
+        event: &Event,
+        error: anyhow::Error,
+        attempt: u32,
+        relocated: bool,
+    ) -> Result<Recovered> {
+        let Some(handler) = &self.on_failure else {
+            return Err(error);
         };
-
-        let down_type = match button {
-            0 => cg::EventType::LEFT_MOUSE_DOWN,
-            1 => cg::EventType::RIGHT_MOUSE_DOWN,
-            _ => cg::EventType::OHTER_MOUSE_DOWN,
-        };
-        let up_type = match button {
-            0 => cg::EventType::LEFT_MOUSE_UP,
-            1 => cg::EventType::RIGHT_MOUSE_UP,
-            _ => cg::EventType::OHTER_MOUSE_UP,
-        };
-
-        for _ in 0..clicks.max(1) {
-            // Mouse down
-            if let Some(evt) = cg::Event::mouse(None, down_type, pos, btn) {
-                post_event(&evt, HID_EVENT_TAP);
-            }
-            std::thread::sleep(Duration::from_millis(10));
-            // Mouse up
-            if let Some(evt) = cg::Event::mouse(None, up_type, pos, btn) {
-                post_event(&evt, HID_EVENT_TAP);
+        if error.is::<Stopped>() {
+            return Err(error);
+        }
+        let failure = Failure { index: i, event, error: &error, attempt };
+        let give_up = |error: anyhow::Error| anyhow::anyhow!("Event {} failed: {}", i, error);
+        match handler(&failure) {
+            Recovery::Retry(times) if attempt <= times => {
+                self.sleep(RETRY_DELAY)?;
+                Ok(Recovered::Retry)
             }
-            if clicks > 1 {
-                std::thread::sleep(Duration::from_millis(50));
+            Recovery::Skip => Ok(Recovered::Skip),
+            Recovery::Relocate if !relocated => {
+                let EventData::Click { c: Some(context), .. } = &event.data else {
+                    return Err(give_up(error));
+                };
+                match self.injector.locate(context) {
+                    Some((x, y)) => {
+                        let mut moved = event.clone();
+                        if let EventData::Click { x: cx, y: cy, .. } = &mut moved.data {
+                            (*cx, *cy) = (x, y);
+                        }
+                        Ok(Recovered::Moved(moved))
+                    }
+                    None => {
+                        bail!("Event {} failed: {}, and nothing matches {}", i, error, context.s)
+                    }
+                }
             }
+            Recovery::Screenshot(path) => match self.injector.capture_screen(&path) {
+                Ok(()) => bail!("Event {} failed: {} (screenshot: {})", i, error, path.display()),
+                Err(e) => bail!("Event {} failed: {} (no screenshot: {})", i, error, e),
+            },
+            Recovery::Retry(_) | Recovery::Relocate | Recovery::Abort => Err(give_up(error)),
         }
+    }
 
+    /// Type `text`, a character at a time with a key delay, or paste it when
+    /// it's long
+    fn type_text(&self, text: &str) -> Result<()> {
+        if self.paste_over.is_some_and(|max| text.chars().count() > max)
+            && self.injector.set_clipboard(text).is_ok()
+        {
+            let paste = if cfg!(target_os = "macos") { Modifiers::CMD } else { Modifiers::CTRL };
+            return self.injector.key(Key::V, paste);
+        }
+        let Some(per_char) = self.key_delay else {
+            return self.injector.type_text(text);
+        };
+        let mut buf = [0; 4];
+        for c in text.chars() {
+            let started = Instant::now();
+            self.injector.type_text(c.encode_utf8(&mut buf))?;
+            self.sleep(per_char.saturating_sub(started.elapsed()))?;
+        }
         Ok(())
     }
 
-    fn move_to(&self, x: i32, y: i32) -> Result<()> {
-        let pos = cg::Point { x: x as f64, y: y as f64 };
-        if let Some(evt) = cg::Event::mouse(None, cg::EventType::MOUSE_MOVED, pos, cg::MouseButton::Left) {
-            post_event(&evt, HID_EVENT_TAP);
+    /// Fill in `{{name}}` placeholders from the replay variables
+    fn substitute<'a>(&self, text: &'a str) -> Result<Cow<'a, str>> {
+        let mut missing = None;
+        let text = fill_placeholders(text, |name| {
+            let value = self.vars.get(name).map(|value| Cow::Borrowed(value.as_str()));
+            if value.is_none() && missing.is_none() {
+                missing = Some(name.to_string());
+            }
+            value
+        });
+        match missing {
+            Some(name) => bail!("No value for replay variable {:?}", name),
+            None => Ok(text),
+        }
+    }
+
+    /// Fail early, naming every variable `events` use that has no value
+    fn check_vars(&self, events: &[Event]) -> Result<()> {
+        let mut missing = Vec::new();
+        for event in events {
+            let text = match &event.data {
+                EventData::Text { s } => Cow::Borrowed(s.as_str()),
+                EventData::Paste { o: 'v', s, .. } => match paste_text(&event.data) {
+                    Ok(Some(text)) => Cow::Owned(text),
+                    Ok(None) => continue,
+                    // Clipboard files can go missing; that only matters up
+                    // front for a paste that was to be filled in
+                    Err(e) if has_placeholders(s) => return Err(e),
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+            fill_placeholders(&text, |name| {
+                if !self.vars.contains_key(name) && !missing.iter().any(|m| m == name) {
+                    missing.push(name.to_string());
+                }
+                None
+            });
+        }
+        if !missing.is_empty() {
+            bail!("No value for replay variables: {}", missing.join(", "));
         }
         Ok(())
     }
 
-    fn scroll(&self, x: i32, y: i32, dx: i16, dy: i16) -> Result<()> {
-        // Move to position first
-        self.move_to(x, y)?;
+    /// Event indices to replay
+    fn resolve_range(&self, events: &[Event]) -> Result<std::ops::Range<usize>> {
+        let find = |label: &str, from: usize| {
+            let label = label.to_lowercase();
+            events.iter().enumerate().skip(from).find_map(|(i, e)| match &e.data {
+                EventData::Annotation { s } if s.to_lowercase().contains(&label) => Some(i),
+                _ => None,
+            })
+        };
+        let start = match &self.start {
+            Mark::Index(i) => (*i).min(events.len()),
+            Mark::Label(label) => match find(label, 0) {
+                Some(i) => i,
+                None => bail!("No annotation matching {:?}", label),
+            },
+        };
+        let end = match &self.end {
+            Mark::Index(i) => (*i).min(events.len()),
+            Mark::Label(label) => match find(label, start + 1) {
+                Some(i) => i,
+                None => bail!("No annotation matching {:?} after event {}", label, start),
+            },
+        };
+        Ok(start..end.max(start))
+    }
+
+    /// Bring `app` to the front and wait for it; false if it didn't come up
+    fn activate(&self, app: &str) -> Result<bool> {
+        if self.injector.activate(app).is_err() {
+            return Ok(false);
+        }
+        self.wait_until(|| self.injector.frontmost_app().is_none_or(|f| f == app))
+    }
 
-        // Create scroll event
-        if let Some(evt) = cg::Event::wheel_2(
-            None,
-            cg::ScrollEventUnit::Line,
-            dy.unsigned_abs() as u32,
-            dx.unsigned_abs() as u32,
-        ) {
-            post_event(&evt, HID_EVENT_TAP);
+    /// Wait for what `data` was recorded against; false on timeout
+    fn synchronize(&self, data: &EventData, app: Option<&str>) -> Result<bool> {
+        let input = matches!(
+            data,
+            EventData::Click { .. }
+                | EventData::Drag { .. }
+                | EventData::Scroll { .. }
+                | EventData::Key { .. }
+                | EventData::Text { .. }
+        );
+        if !input {
+            return Ok(true);
+        }
+        let app_ready = || match (app, self.injector.frontmost_app()) {
+            (Some(app), Some(frontmost)) => app == frontmost,
+            _ => true,
+        };
+        match data {
+            EventData::Click { x, y, c: Some(context), .. } => self.wait_until(|| {
+                app_ready() && self.injector.element_ready(*x, *y, context)
+            }),
+            _ => self.wait_until(app_ready),
         }
-        Ok(())
     }
 
-    fn key(&self, keycode: u16, modifiers: u8) -> Result<()> {
-        // Build flags
-        let mut flags = cg::EventFlags(0);
-        if modifiers & Modifiers::SHIFT != 0 { flags.0 |= 0x20000; }
-        if modifiers & Modifiers::CTRL != 0 { flags.0 |= 0x40000; }
-        if modifiers & Modifiers::OPT != 0 { flags.0 |= 0x80000; }
-        if modifiers & Modifiers::CMD != 0 { flags.0 |= 0x100000; }
+    /// Whether `assertion` holds, giving it up to the sync timeout, and what
+    /// was found last
+    fn check(&self, assertion: &Assertion) -> Result<(bool, Option<String>)> {
+        let found = RefCell::new(None);
+        let passed = self.poll(self.sync.unwrap_or_default(), || {
+            let (passed, last) = match assertion {
+                Assertion::Element { x, y, r, n } => {
+                    let expected =
+                        ElementContext { r: r.clone(), n: n.clone(), ..Default::default() };
+                    let element = self.injector.element_at(*x, *y);
+                    let passed = element.as_ref().is_some_and(|e| expected.matches(e));
+                    let last = element.map(|e| match e.n {
+                        Some(name) => format!("{} '{}'", e.r, name),
+                        None => e.r,
+                    });
+                    (passed, last)
+                }
+                Assertion::Value { x, y, v } => {
+                    let value = self.injector.element_at(*x, *y).and_then(|e| e.v);
+                    (value.as_ref() == Some(v), value)
+                }
+                Assertion::Title { w } => {
+                    let title = self.injector.window_title();
+                    (title.as_ref().is_some_and(|t| t.contains(w.as_str())), title)
+                }
+            };
+            *found.borrow_mut() = last;
+            passed
+        })?;
+        Ok((passed, found.into_inner()))
+    }
 
-        // Key down
-        if let Some(mut evt) = cg::Event::keyboard(None, keycode, true) {
-            evt.set_flags(flags);
-            post_event(&evt, HID_EVENT_TAP);
+    /// Poll `ready` for up to the sync timeout
+    fn wait_until(&self, ready: impl Fn() -> bool) -> Result<bool> {
+        match self.sync {
+            Some(timeout) => self.poll(timeout, ready),
+            None => Ok(true),
         }
+    }
 
-        std::thread::sleep(Duration::from_millis(10));
+    /// Poll `ready` for up to `timeout`, at least once
+    fn poll(&self, timeout: Duration, ready: impl Fn() -> bool) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if ready() {
+                return Ok(true);
+            }
+            if Instant::now() >= deadline {
+                return Ok(false);
+            }
+            self.sleep(SYNC_POLL)?;
+        }
+    }
 
-        // Key up
-        if let Some(mut evt) = cg::Event::keyboard(None, keycode, false) {
-            evt.set_flags(flags);
-            post_event(&evt, HID_EVENT_TAP);
+    /// Sleep, watching for the user taking over
+    fn sleep(&self, duration: Duration) -> Result<()> {
+        let deadline = Instant::now() + duration;
+        loop {
+            self.check_input()?;
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            std::thread::sleep(left.min(SYNC_POLL));
         }
+    }
 
+    fn check_cancelled(&self) -> Result<()> {
+        if self.cancel.as_ref().is_some_and(CancellationToken::is_cancelled) {
+            return Err(Stopped("Replay cancelled".into()).into());
+        }
         Ok(())
     }
 
-    fn type_text(&self, text: &str) -> Result<()> {
-        for c in text.chars() {
-            if let Some((keycode, shift)) = char_to_keycode(c) {
-                let mods = if shift { Modifiers::SHIFT } else { 0 };
-                self.key(keycode, mods)?;
-                std::thread::sleep(Duration::from_millis(20));
+    /// Fail if the replay was cancelled, or the user pressed Escape or moved
+    /// the mouse
+    fn check_input(&self) -> Result<()> {
+        self.check_cancelled()?;
+        if !self.abort_on_input {
+            return Ok(());
+        }
+        let injected = self.injected.get();
+        let own_escape =
+            injected.escape_held || injected.escape.is_some_and(|t| t.elapsed() < ESCAPE_GRACE);
+        if !own_escape && self.injector.escape_pressed() {
+            return Err(Stopped("Replay aborted: Escape pressed".into()).into());
+        }
+        if let Some((x, y)) = injected.cursor {
+            let away = || {
+                self.injector.cursor_position().is_some_and(|(cx, cy)| {
+                    (cx - x).abs() > CURSOR_SLACK || (cy - y).abs() > CURSOR_SLACK
+                })
+            };
+            // The pointer can take a moment to catch up with injected moves
+            let moved = (0..4).all(|n| {
+                if n > 0 {
+                    std::thread::sleep(SYNC_POLL);
+                }
+                away()
+            });
+            if moved {
+                return Err(Stopped("Replay aborted: the mouse was moved".into()).into());
             }
         }
         Ok(())
     }
+
+    fn moved_to(&self, x: i32, y: i32) {
+        self.injected.set(Injected { cursor: Some((x, y)), ..self.injected.get() });
+    }
+
+    fn pressed(&self, key: Key, held: bool) {
+        if key == Key::Escape {
+            let escape = Some(Instant::now());
+            self.injected.set(Injected { escape, escape_held: held, ..self.injected.get() });
+        }
+    }
+}
+
+/// Print the event and read a choice from stdin; end of input aborts
+fn terminal_prompt(i: usize, event: &Event) -> Step {
+    let mut stderr = std::io::stderr();
+    let _ = write!(
+        stderr,
+        "[{}] {}ms {}  (Enter: run, s: skip, c: continue, q: abort) ",
+        i, event.t, event.data
+    );
+    let _ = stderr.flush();
+    let mut line = String::new();
+    match std::io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => Step::Abort,
+        Ok(_) => match line.trim() {
+            "s" | "skip" => Step::Skip,
+            "c" | "continue" => Step::Continue,
+            "q" | "quit" | "abort" => Step::Abort,
+            _ => Step::Run,
+        },
+    }
+}
+
+/// Full text of a Paste event: the saved clipboard file, or the preview if
+/// it wasn't shortened. `None` for files, images and text only known by its
+/// preview.
+fn paste_text(data: &EventData) -> Result<Option<String>> {
+    let EventData::Paste { s, k, l, f, .. } = data else {
+        return Ok(None);
+    };
+    if !matches!(k, None | Some(ClipboardKind::Text)) {
+        return Ok(None);
+    }
+    if let Some(path) = f {
+        return match std::fs::read_to_string(path) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) => bail!("Can't read pasted text from {}: {}", path, e),
+        };
+    }
+    Ok((*l == 0 || s.len() as u64 == *l).then(|| s.clone()))
+}
+
+/// Whether `text` has a `{{name}}` placeholder
+fn has_placeholders(text: &str) -> bool {
+    let mut found = false;
+    fill_placeholders(text, |_| {
+        found = true;
+        None
+    });
+    found
+}
+
+/// Indices of Key events whose release was also recorded. Those are replayed
+/// as press ... release so holds and chords keep their timing; any other Key
+/// is a plain tap.
+fn held_keys(events: &[Event]) -> HashSet<usize> {
+    let mut pressed: HashMap<Key, usize> = HashMap::new();
+    let mut held = HashSet::new();
+    for (i, event) in events.iter().enumerate() {
+        match event.data {
+            EventData::Key { k, .. } => {
+                pressed.insert(k, i);
+            }
+            EventData::KeyUp { k, .. } => {
+                if let Some(press) = pressed.remove(&k) {
+                    held.insert(press);
+                }
+            }
+            _ => {}
+        }
+    }
+    held
 }
 
 impl Default for Replayer {
@@ -186,69 +1158,476 @@ impl Default for Replayer {
     }
 }
 
+/// How an [`EventData::Assert`] came out
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssertionResult {
+    /// Index of the Assert event
+    pub index: usize,
+    /// Run it was checked in, counting from 0, when repeating
+    pub run: usize,
+    pub assertion: Assertion,
+    pub passed: bool,
+    /// What was there instead, for failures: the element's role and name,
+    /// its value, or the window title. `None` if nothing was found or the
+    /// injector can't tell.
+    pub found: Option<String>,
+}
+
 #[derive(Debug, Default)]
 pub struct ReplayStats {
+    /// Times the workflow was played through
+    pub runs: usize,
+    /// Apps brought to the front
+    pub apps: usize,
     pub clicks: usize,
     pub moves: usize,
+    pub drags: usize,
     pub scrolls: usize,
     pub keys: usize,
+    /// Characters typed, not bytes
     pub text_chars: usize,
+    /// Waits that gave up: input replayed before its app or element showed
+    /// up, or an app that couldn't be brought to the front
+    pub sync_timeouts: usize,
+    /// Failed events retried, relocated or skipped by
+    /// [`Replayer::on_failure`]
+    pub recoveries: usize,
+    /// Assertions checked, in order
+    pub assertions: Vec<AssertionResult>,
 }
 
-/// Convert char to (keycode, needs_shift)
-fn char_to_keycode(c: char) -> Option<(u16, bool)> {
-    Some(match c {
-        'a' | 'A' => (0, c.is_uppercase()),
-        'b' | 'B' => (11, c.is_uppercase()),
-        'c' | 'C' => (8, c.is_uppercase()),
-        'd' | 'D' => (2, c.is_uppercase()),
-        'e' | 'E' => (14, c.is_uppercase()),
-        'f' | 'F' => (3, c.is_uppercase()),
-        'g' | 'G' => (5, c.is_uppercase()),
-        'h' | 'H' => (4, c.is_uppercase()),
-        'i' | 'I' => (34, c.is_uppercase()),
-        'j' | 'J' => (38, c.is_uppercase()),
-        'k' | 'K' => (40, c.is_uppercase()),
-        'l' | 'L' => (37, c.is_uppercase()),
-        'm' | 'M' => (46, c.is_uppercase()),
-        'n' | 'N' => (45, c.is_uppercase()),
-        'o' | 'O' => (31, c.is_uppercase()),
-        'p' | 'P' => (35, c.is_uppercase()),
-        'q' | 'Q' => (12, c.is_uppercase()),
-        'r' | 'R' => (15, c.is_uppercase()),
-        's' | 'S' => (1, c.is_uppercase()),
-        't' | 'T' => (17, c.is_uppercase()),
-        'u' | 'U' => (32, c.is_uppercase()),
-        'v' | 'V' => (9, c.is_uppercase()),
-        'w' | 'W' => (13, c.is_uppercase()),
-        'x' | 'X' => (7, c.is_uppercase()),
-        'y' | 'Y' => (16, c.is_uppercase()),
-        'z' | 'Z' => (6, c.is_uppercase()),
-        '0' | ')' => (29, c == ')'),
-        '1' | '!' => (18, c == '!'),
-        '2' | '@' => (19, c == '@'),
-        '3' | '#' => (20, c == '#'),
-        '4' | '$' => (21, c == '$'),
-        '5' | '%' => (23, c == '%'),
-        '6' | '^' => (22, c == '^'),
-        '7' | '&' => (26, c == '&'),
-        '8' | '*' => (28, c == '*'),
-        '9' | '(' => (25, c == '('),
-        ' ' => (49, false),
-        '\n' => (36, false),
-        '\t' => (48, false),
-        '\x08' => (51, false), // backspace
-        '-' | '_' => (27, c == '_'),
-        '=' | '+' => (24, c == '+'),
-        '[' | '{' => (33, c == '{'),
-        ']' | '}' => (30, c == '}'),
-        '\\' | '|' => (42, c == '|'),
-        ';' | ':' => (41, c == ':'),
-        '\'' | '"' => (39, c == '"'),
-        ',' | '<' => (43, c == '<'),
-        '.' | '>' => (47, c == '>'),
-        '/' | '?' => (44, c == '?'),
-        '`' | '~' => (50, c == '~'),
-        _ => return None,
-    })
+impl ReplayStats {
+    /// Whether every assertion passed (true if there were none)
+    pub fn passed(&self) -> bool {
+        self.assertions.iter().all(|a| a.passed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    /// Records clicks, text and activations; Slow comes to the front after a
+    /// few checks
+    #[derive(Clone, Default)]
+    struct Mock {
+        checks: Arc<Mutex<u32>>,
+        /// Where the user holds the pointer, if anywhere
+        cursor: Arc<Mutex<Option<(i32, i32)>>>,
+        clicks: Arc<Mutex<Vec<(i32, i32)>>>,
+        /// Modifiers held for each click and drag
+        modifiers: Arc<Mutex<Vec<u8>>>,
+        typed: Arc<Mutex<Vec<String>>>,
+        keys: Arc<Mutex<Vec<(Key, u8)>>>,
+        activated: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Injector for Mock {
+        fn click(&self, x: i32, y: i32, _: u8, _: u8, m: u8) -> Result<()> {
+            self.clicks.lock().push((x, y));
+            self.modifiers.lock().push(m);
+            Ok(())
+        }
+        fn move_to(&self, _: i32, _: i32) -> Result<()> {
+            Ok(())
+        }
+        fn drag(&self, _: &[(i32, i32)], _: u8, m: u8, _: Duration) -> Result<()> {
+            self.modifiers.lock().push(m);
+            Ok(())
+        }
+        fn scroll(&self, _: i32, _: i32, _: i16, _: i16, _: bool) -> Result<()> {
+            Ok(())
+        }
+        fn key(&self, key: Key, m: u8) -> Result<()> {
+            self.keys.lock().push((key, m));
+            Ok(())
+        }
+        fn key_down(&self, _: Key, _: u8) -> Result<()> {
+            Ok(())
+        }
+        fn key_up(&self, _: Key, _: u8) -> Result<()> {
+            Ok(())
+        }
+        fn type_text(&self, text: &str) -> Result<()> {
+            self.typed.lock().push(text.to_string());
+            Ok(())
+        }
+        fn activate(&self, app: &str) -> Result<()> {
+            self.activated.lock().push(app.to_string());
+            Ok(())
+        }
+        fn frontmost_app(&self) -> Option<String> {
+            let mut checks = self.checks.lock();
+            *checks += 1;
+            Some(if *checks > 3 { "Slow" } else { "Terminal" }.to_string())
+        }
+        fn element_ready(&self, x: i32, _: i32, _: &ElementContext) -> bool {
+            // Only the first button ever shows up
+            x == 1
+        }
+        fn set_clipboard(&self, text: &str) -> Result<()> {
+            self.typed.lock().push(format!("clipboard: {}", text));
+            Ok(())
+        }
+        fn cursor_position(&self) -> Option<(i32, i32)> {
+            *self.cursor.lock()
+        }
+        fn element_at(&self, x: i32, _: i32) -> Option<ElementContext> {
+            let (r, n, v) = ("AXTextField".into(), Some("Total".into()), Some("42".into()));
+            (x == 1).then(|| ElementContext { r, n, v, ..Default::default() })
+        }
+        fn window_title(&self) -> Option<String> {
+            Some("Invoice 42 - Editor".into())
+        }
+        fn locate(&self, _: &ElementContext) -> Option<(i32, i32)> {
+            // Buttons that aren't there moved to where the first one is
+            Some((1, 5))
+        }
+    }
+
+    #[test]
+    fn waits_for_app_and_element() {
+        let mock = Mock::default();
+        let click = |t, x| {
+            let c = Some(Box::new(ElementContext { r: "AXButton".into(), ..Default::default() }));
+            Event { t, ts: None, data: EventData::Click { x, y: 0, b: 0, n: 1, m: 0, c } }
+        };
+        let mut workflow = RecordedWorkflow::new("sync");
+        workflow.events = vec![
+            Event { t: 0, ts: None, data: EventData::App { n: "Slow".into(), p: 1 } },
+            click(0, 1),
+            click(0, 2),
+        ];
+
+        let replayer = Replayer::with_injector(mock.clone()).sync(Some(Duration::from_millis(300)));
+        let stats = replayer.play(&workflow).unwrap();
+        // Slow was activated and waited for, the second click gave up on its
+        // element
+        assert!(*mock.checks.lock() > 3);
+        assert_eq!(*mock.activated.lock(), ["Slow"]);
+        assert_eq!(stats.apps, 1);
+        assert_eq!(*mock.clicks.lock(), [(1, 0), (2, 0)]);
+        assert_eq!(stats.sync_timeouts, 1);
+    }
+
+    #[test]
+    fn pauses_at_breakpoints() {
+        let mock = Mock::default();
+        let mut workflow = RecordedWorkflow::new("steps");
+        let click = |x| EventData::Click { x, y: 0, b: 0, n: 1, m: 0, c: None };
+        workflow.events = (0..5).map(|x| Event { t: 0, ts: None, data: click(x) }).collect();
+        let paused = Arc::new(Mutex::new(Vec::new()));
+
+        let replayer = Replayer::with_injector(mock.clone()).breakpoints([1]).on_pause({
+            let paused = paused.clone();
+            move |i, _| {
+                paused.lock().push(i);
+                // Skip the breakpoint, then step through the next one
+                match i {
+                    1 => Step::Skip,
+                    2 => Step::Run,
+                    3 => Step::Continue,
+                    _ => Step::Abort,
+                }
+            }
+        });
+        replayer.play(&workflow).unwrap();
+        assert_eq!(*paused.lock(), [1, 2, 3]);
+        assert_eq!(*mock.clicks.lock(), [(0, 0), (2, 0), (3, 0), (4, 0)]);
+
+        let aborted = Replayer::with_injector(mock).step_mode().on_pause(|_, _| Step::Abort);
+        assert!(aborted.play(&workflow).is_err());
+    }
+
+    #[test]
+    fn replays_a_range() {
+        let mock = Mock::default();
+        let mut workflow = RecordedWorkflow::new("range");
+        let click = |x| EventData::Click { x, y: 0, b: 0, n: 1, m: 0, c: None };
+        let note = |s: &str| EventData::Annotation { s: s.into() };
+        workflow.events = vec![
+            Event { t: 0, ts: None, data: EventData::App { n: "Slow".into(), p: 1 } },
+            Event { t: 0, ts: None, data: click(1) },
+            Event { t: 0, ts: None, data: note("Export the report") },
+            Event { t: 0, ts: None, data: click(2) },
+            Event { t: 0, ts: None, data: click(3) },
+            Event { t: 0, ts: None, data: note("done") },
+            Event { t: 0, ts: None, data: click(4) },
+        ];
+
+        let replayer = Replayer::with_injector(mock.clone()).range(3, 4);
+        replayer.play(&workflow).unwrap();
+        // The app from before the range comes to the front first
+        assert_eq!(*mock.activated.lock(), ["Slow"]);
+        assert_eq!(*mock.clicks.lock(), [(2, 0)]);
+
+        mock.clicks.lock().clear();
+        let replayer = Replayer::with_injector(mock.clone()).from_label("export").to_label("DONE");
+        replayer.play(&workflow).unwrap();
+        assert_eq!(*mock.clicks.lock(), [(2, 0), (3, 0)]);
+        assert!(Replayer::with_injector(mock).from_label("nope").play(&workflow).is_err());
+    }
+
+    #[test]
+    fn fills_in_variables() {
+        let mock = Mock::default();
+        let mut workflow = RecordedWorkflow::new("vars");
+        let paste = |s: &str, l| {
+            EventData::Paste { o: 'v', s: s.into(), k: None, m: None, l, f: None }
+        };
+        workflow.events = vec![
+            Event { t: 0, ts: None, data: EventData::Text { s: "Invoice {{ invoice }}".into() } },
+            Event { t: 0, ts: None, data: EventData::Text { s: "{not} {{a b}}".into() } },
+            Event { t: 0, ts: None, data: paste("{{invoice}}-{{customer}}", 0) },
+            Event { t: 0, ts: None, data: paste("plain", 0) },
+            // Shortened preview, the full text isn't known
+            Event { t: 0, ts: None, data: paste("{{other}}...", 1000) },
+        ];
+
+        let replayer = Replayer::with_injector(mock.clone())
+            .with_vars([("invoice", "№42"), ("customer", "ACME")]);
+        let stats = replayer.play(&workflow).unwrap();
+        assert_eq!(*mock.typed.lock(), ["Invoice №42", "{not} {{a b}}", "clipboard: №42-ACME"]);
+        // Characters, not bytes
+        assert_eq!(stats.text_chars, 11 + 13);
+
+        let err = Replayer::with_injector(mock.clone()).play(&workflow).unwrap_err();
+        assert_eq!(err.to_string(), "No value for replay variables: invoice, customer");
+    }
+
+    #[test]
+    fn only_pastes_with_placeholders_need_their_file_up_front() {
+        let replayer = Replayer::with_injector(Mock::default()).with_vars([("invoice", "42")]);
+        let paste = |s: &str| {
+            let f = Some("/nonexistent/clipboard/1.txt".into());
+            let data = EventData::Paste { o: 'v', s: s.into(), k: None, m: None, l: 1000, f };
+            Event { t: 0, ts: None, data }
+        };
+        assert!(replayer.check_vars(&[paste("plain text...")]).is_ok());
+        let err = replayer.check_vars(&[paste("Invoice {{invoice}}...")]).unwrap_err();
+        assert!(err.to_string().starts_with("Can't read pasted text from"));
+        // Pasting it still needs the file
+        let mut workflow = RecordedWorkflow::new("gone");
+        workflow.events = vec![paste("plain text...")];
+        assert!(replayer.play(&workflow).is_err());
+    }
+
+    #[test]
+    fn aborts_when_the_user_takes_over() {
+        let mock = Mock::default();
+        let mut workflow = RecordedWorkflow::new("guard");
+        let click = |x| EventData::Click { x, y: 0, b: 0, n: 1, m: 0, c: None };
+        workflow.events = (0..3).map(|x| Event { t: 0, ts: None, data: click(x) }).collect();
+
+        *mock.cursor.lock() = Some((500, 500));
+        let err = Replayer::with_injector(mock.clone()).play(&workflow).unwrap_err();
+        assert_eq!(err.to_string(), "Replay aborted: the mouse was moved");
+        assert_eq!(*mock.clicks.lock(), [(0, 0)]);
+
+        mock.clicks.lock().clear();
+        Replayer::with_injector(mock.clone()).abort_on_input(false).play(&workflow).unwrap();
+        assert_eq!(mock.clicks.lock().len(), 3);
+
+        // Slow never comes up in time
+        let app = EventData::App { n: "Slow".into(), p: 1 };
+        workflow.events.insert(0, Event { t: 0, ts: None, data: app });
+        *mock.checks.lock() = 0;
+        let strict = Replayer::with_injector(mock)
+            .activate_apps(false)
+            .abort_on_input(false)
+            .sync(Some(Duration::ZERO))
+            .strict_apps(true);
+        assert!(strict.play(&workflow).is_err());
+    }
+
+    #[test]
+    fn checks_assertions() {
+        let mock = Mock::default();
+        let mut workflow = RecordedWorkflow::new("asserts");
+        let element = |x, n: Option<&str>| Assertion::Element {
+            x,
+            y: 0,
+            r: "AXTextField".into(),
+            n: n.map(Into::into),
+        };
+        let value = |v: &str| Assertion::Value { x: 1, y: 0, v: v.into() };
+        let title = |w: &str| Assertion::Title { w: w.into() };
+        workflow.events = [
+            element(1, Some("Total")),
+            element(1, Some("Subtotal")),
+            element(2, None),
+            value("42"),
+            value("43"),
+            title("Invoice 42"),
+            title("Invoice 43"),
+        ]
+        .into_iter()
+        .map(|a| Event { t: 0, ts: None, data: EventData::Assert { a } })
+        .collect();
+
+        let replayer = Replayer::with_injector(mock.clone()).sync(None);
+        let stats = replayer.play(&workflow).unwrap();
+        let passed: Vec<_> = stats.assertions.iter().map(|a| a.passed).collect();
+        assert_eq!(passed, [true, false, false, true, false, true, false]);
+        assert!(!stats.passed());
+        assert_eq!(stats.assertions[1].found.as_deref(), Some("AXTextField 'Total'"));
+        assert_eq!(stats.assertions[2].found, None);
+        assert_eq!(stats.assertions[4].found.as_deref(), Some("42"));
+
+        let err = Replayer::with_injector(mock).sync(None).fail_fast(true).play(&workflow);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "Assertion failed at event 1: AXTextField 'Subtotal' at (1, 0)"
+        );
+    }
+
+    #[test]
+    fn per_event_type_timing() {
+        let mock = Mock::default();
+        let mut workflow = RecordedWorkflow::new("timing");
+        let click = |x| EventData::Click { x, y: 0, b: 0, n: 1, m: 0, c: None };
+        workflow.events = vec![
+            Event { t: 0, ts: None, data: EventData::Move { x: 0, y: 0 } },
+            Event { t: 5_000, ts: None, data: EventData::Move { x: 1, y: 0 } },
+            Event { t: 5_000, ts: None, data: click(1) },
+            Event { t: 5_000, ts: None, data: click(2) },
+            Event { t: 5_000, ts: None, data: EventData::Text { s: "abcde".into() } },
+        ];
+
+        let replayer = Replayer::with_injector(mock.clone())
+            .fast_forward_moves(true)
+            .min_delay_between_clicks(Duration::from_millis(100))
+            .typing_cps(100.0);
+        let started = Instant::now();
+        replayer.play(&workflow).unwrap();
+        // No 5s wait for the move, 100ms between clicks, 10ms a character
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(140), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+        assert_eq!(*mock.typed.lock(), ["a", "b", "c", "d", "e"]);
+
+        // Long text pasted, the rest typed in one go
+        let mock = Mock::default();
+        let short = EventData::Text { s: "ab".into() };
+        workflow.events.push(Event { t: 5_000, ts: None, data: short });
+        let replayer = Replayer::with_injector(mock.clone())
+            .typing_cps(100.0)
+            .key_delay(Duration::ZERO)
+            .paste_text_over(3);
+        replayer.play(&workflow).unwrap();
+        assert_eq!(*mock.typed.lock(), ["clipboard: abcde", "ab"]);
+        let paste = if cfg!(target_os = "macos") { Modifiers::CMD } else { Modifiers::CTRL };
+        assert_eq!(*mock.keys.lock(), [(Key::V, paste)]);
+    }
+
+    #[test]
+    fn keeps_click_modifiers() {
+        let mock = Mock::default();
+        let mut workflow = RecordedWorkflow::new("modifiers");
+        let cmd_click = EventData::Click { x: 1, y: 0, b: 0, n: 1, m: Modifiers::CMD, c: None };
+        let (x, y, x2, y2, b, p, d) = (0, 0, 5, 5, 0, vec![], 0);
+        let shift_drag = EventData::Drag { x, y, x2, y2, b, p, m: Modifiers::SHIFT, d };
+        workflow.events = vec![
+            Event { t: 0, ts: None, data: cmd_click },
+            Event { t: 0, ts: None, data: shift_drag },
+        ];
+
+        Replayer::with_injector(mock.clone()).play(&workflow).unwrap();
+        assert_eq!(*mock.modifiers.lock(), [Modifiers::CMD, Modifiers::SHIFT]);
+    }
+
+    #[test]
+    fn repeats() {
+        let mock = Mock::default();
+        let mut workflow = RecordedWorkflow::new("repeat");
+        let click = EventData::Click { x: 1, y: 0, b: 0, n: 1, m: 0, c: None };
+        workflow.events = vec![Event { t: 0, ts: None, data: click }];
+
+        let stats = Replayer::with_injector(mock.clone()).repeat(3).play(&workflow).unwrap();
+        assert_eq!((stats.runs, stats.clicks), (3, 3));
+
+        let replayer = Replayer::with_injector(mock.clone()).repeat_until(|s| s.clicks >= 5);
+        assert_eq!(replayer.play(&workflow).unwrap().runs, 5);
+        let capped = Replayer::with_injector(mock).repeat(2).repeat_until(|_| false);
+        assert_eq!(capped.play(&workflow).unwrap().runs, 2);
+    }
+
+    #[test]
+    fn reports_progress_and_cancels() {
+        #[derive(Default)]
+        struct Log(Vec<String>);
+        impl ReplayObserver for &mut Log {
+            fn event_started(&mut self, p: &Progress, _: &Event) {
+                self.0.push(format!("start {}/{} run {}", p.done, p.total, p.run));
+            }
+            fn event_finished(&mut self, p: &Progress, _: &Event, stats: &ReplayStats) {
+                self.0.push(format!("done {} clicks {}", p.index, stats.clicks));
+            }
+            fn run_finished(&mut self, run: usize, _: &ReplayStats) {
+                self.0.push(format!("run {}", run));
+            }
+        }
+        let mock = Mock::default();
+        let mut workflow = RecordedWorkflow::new("progress");
+        let click = EventData::Click { x: 1, y: 0, b: 0, n: 1, m: 0, c: None };
+        workflow.events = vec![Event { t: 0, ts: None, data: click }; 2];
+
+        let mut log = Log::default();
+        Replayer::with_injector(mock.clone()).play_with(&workflow, &mut log).unwrap();
+        assert_eq!(
+            log.0,
+            ["start 0/2 run 0", "done 0 clicks 1", "start 1/2 run 0", "done 1 clicks 2", "run 0"]
+        );
+
+        let token = CancellationToken::new();
+        let replayer = Replayer::with_injector(mock.clone()).cancel_with(token.clone());
+        let err = replayer
+            .play_with(&workflow, |p: &Progress, _: &Event| {
+                if p.index == 1 {
+                    token.cancel();
+                }
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Replay cancelled");
+        // Only the first click went through
+        assert_eq!(mock.clicks.lock().len(), 3);
+    }
+
+    #[test]
+    fn recovers_from_failures() {
+        let mock = Mock::default();
+        let c = Some(Box::new(ElementContext { r: "AXButton".into(), ..Default::default() }));
+        let mut workflow = RecordedWorkflow::new("recover");
+        let click = EventData::Click { x: 2, y: 0, b: 0, n: 1, m: 0, c };
+        workflow.events = vec![Event { t: 0, ts: None, data: click }];
+        let sync = Some(Duration::from_millis(50));
+        let replayer = || Replayer::with_injector(mock.clone()).sync(sync);
+
+        let attempts = Arc::new(Mutex::new(Vec::new()));
+        let seen = attempts.clone();
+        let stats = replayer()
+            .on_failure(move |f| {
+                seen.lock().push(f.attempt);
+                if f.attempt == 1 { Recovery::Retry(1) } else { Recovery::Relocate }
+            })
+            .play(&workflow)
+            .unwrap();
+        assert_eq!(*attempts.lock(), [1, 2]);
+        assert_eq!((stats.clicks, stats.recoveries), (1, 2));
+        assert_eq!(*mock.clicks.lock(), [(1, 5)]);
+
+        let stats = replayer().on_failure(|_| Recovery::Skip).play(&workflow).unwrap();
+        assert_eq!((stats.clicks, stats.recoveries), (0, 1));
+
+        let err = replayer().on_failure(|_| Recovery::Abort).play(&workflow).unwrap_err();
+        assert_eq!(err.to_string(), "Event 0 failed: AXButton not found at (2, 0)");
+        let screenshot = Recovery::Screenshot("failed.jpg".into());
+        let err = replayer().on_failure(move |_| screenshot.clone()).play(&workflow).unwrap_err();
+        let unsupported = "(no screenshot: Screenshots aren't supported by this injector)";
+        assert!(err.to_string().ends_with(unsupported));
+        // Without a handler the click goes ahead after the wait
+        assert_eq!(replayer().play(&workflow).unwrap().sync_timeouts, 1);
+    }
 }
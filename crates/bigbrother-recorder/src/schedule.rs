@@ -0,0 +1,185 @@
+//! Cron-style schedules for unattended replays
+//!
+//! Five fields, `minute hour day-of-month month day-of-week`, each `*`, a
+//! number or name (`MON`, `JAN`), a range (`MON-FRI`), a step (`*/15`,
+//! `0-30/10`) or a comma-separated list of those. `@hourly`, `@daily`,
+//! `@weekly` and `@monthly` are shorthands. As in cron, when both day
+//! fields are restricted a day matching either one fires.
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Datelike, Local, LocalResult, NaiveDateTime, TimeZone, Timelike};
+use std::str::FromStr;
+
+/// How far ahead to look before deciding a schedule never fires: Feb 29 on
+/// a given weekday comes around every 28 years
+const HORIZON_DAYS: i64 = 28 * 366;
+
+const DAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+const MONTHS: [&str; 12] =
+    ["JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC"];
+
+/// When a recurring replay runs, e.g. `"0 9 * * MON-FRI"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    /// Bit n set: value n matches
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Day-of-month or day-of-week was `*`
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// The first time the schedule fires after `after`
+    pub fn next_after(&self, after: DateTime<Local>) -> Option<DateTime<Local>> {
+        let mut t = after.naive_local();
+        loop {
+            t = self.next_naive(t)?;
+            // Skip times the clock jumps over; take the first of repeated ones
+            match Local.from_local_datetime(&t) {
+                LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => return Some(t),
+                LocalResult::None => continue,
+            }
+        }
+    }
+
+    /// The first matching minute after `after`, in wall-clock time
+    fn next_naive(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + chrono::Duration::minutes(1);
+        let end = start + chrono::Duration::days(HORIZON_DAYS);
+        let mut t = start;
+        while t < end {
+            if !self.day_matches(t) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << t.hour()) == 0 {
+                t = t.with_minute(0)? + chrono::Duration::hours(1);
+            } else if self.minutes & (1 << t.minute()) == 0 {
+                t += chrono::Duration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, t: NaiveDateTime) -> bool {
+        if self.months & (1 << t.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << t.day()) != 0;
+        let weekday = self.weekdays & (1 << t.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * SUN",
+            "@monthly" => "0 0 1 * *",
+            s => s,
+        };
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            bail!("Expected 5 fields (minute hour day month weekday), got {:?}", s);
+        };
+        let mut weekdays = parse_field(weekday, 0, 7, &DAYS, 0).context("weekday")?;
+        // 7 is Sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minute, 0, 59, &[], 0).context("minute")?,
+            hours: parse_field(hour, 0, 23, &[], 0).context("hour")?,
+            days: parse_field(day, 1, 31, &[], 0).context("day of month")?,
+            months: parse_field(month, 1, 12, &MONTHS, 1).context("month")?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+/// Parse one field into a bit set of the values in `min..=max`. `names[i]`
+/// stands for `i + offset`.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str], offset: u32) -> Result<u64> {
+    let value = |s: &str| -> Result<u32> {
+        let n = match names.iter().position(|name| name.eq_ignore_ascii_case(s)) {
+            Some(i) => i as u32 + offset,
+            None => s.parse().with_context(|| format!("{:?} isn't a number", s))?,
+        };
+        if !(min..=max).contains(&n) {
+            bail!("{} is out of range {}-{}", n, min, max);
+        }
+        Ok(n)
+    };
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse().context("bad step")?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            bail!("Step can't be 0");
+        }
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (value(from)?, value(to)?),
+            // `5/10` runs from 5 to the end
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if from > to {
+            bail!("Range {} runs backwards", range);
+        }
+        for n in (from..=to).step_by(step) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn finds_the_next_run() {
+        let weekdays: Schedule = "0 9 * * MON-FRI".parse().unwrap();
+        // Friday 2024-03-01 after 9:00 -> Monday
+        assert_eq!(weekdays.next_naive(at("2024-03-01 09:00")), Some(at("2024-03-04 09:00")));
+        assert_eq!(weekdays.next_naive(at("2024-03-04 08:59")), Some(at("2024-03-04 09:00")));
+
+        let quarterly: Schedule = "*/15 8-9 * * *".parse().unwrap();
+        assert_eq!(quarterly.next_naive(at("2024-03-01 09:50")), Some(at("2024-03-02 08:00")));
+        assert_eq!(quarterly.next_naive(at("2024-03-01 08:15")), Some(at("2024-03-01 08:30")));
+
+        // Either day field matches when both are set
+        let either: Schedule = "0 0 13 * FRI".parse().unwrap();
+        assert_eq!(either.next_naive(at("2024-03-02 00:00")), Some(at("2024-03-08 00:00")));
+
+        let leap: Schedule = "0 0 29 feb *".parse().unwrap();
+        assert_eq!(leap.next_naive(at("2024-03-01 00:00")), Some(at("2028-02-29 00:00")));
+        let never: Schedule = "0 0 31 2 *".parse().unwrap();
+        assert_eq!(never.next_naive(at("2024-03-01 00:00")), None);
+
+        assert_eq!("@daily".parse::<Schedule>().unwrap(), "0 0 * * *".parse().unwrap());
+        assert!("0 9 * *".parse::<Schedule>().is_err());
+        assert!("60 * * * *".parse::<Schedule>().is_err());
+        assert!("0 9 * * FRI-MON".parse::<Schedule>().is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! Screen text from screenpipe
+//!
+//! [screenpipe](https://github.com/mediar-ai/screenpipe) records the screen
+//! and OCRs its frames into a local SQLite database. [`Screenpipe::enrich`]
+//! finds the frame nearest each click and window switch of a recording and
+//! adds what was on screen as an [`EventData::ScreenText`] event right
+//! after it, so whoever reads the workflow sees what the user was looking
+//! at, not just where they clicked.
+//!
+//! ```rust,no_run
+//! use bigbrother_recorder::prelude::*;
+//! use bigbrother_recorder::screenpipe::Screenpipe;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let storage = WorkflowStorage::new()?;
+//! let mut workflow = storage.load("invoices")?;
+//! let added = Screenpipe::open()?.enrich(&mut workflow)?;
+//! println!("{} events got screen text", added);
+//! storage.save(&workflow)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Events are matched by wall-clock time, so the recording needs its
+//! metadata (any recent one) or `absolute_timestamps`.
+
+use crate::events::{Event, EventData, RecordedWorkflow};
+use crate::text::prefix;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, SecondsFormat, TimeZone, Utc};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::path::{Path, PathBuf};
+
+/// How far from an event a frame may be (ms)
+const DEFAULT_WINDOW_MS: u64 = 2000;
+
+/// Screen text kept per event, in characters
+const DEFAULT_MAX_CHARS: usize = 4000;
+
+const MS_PER_DAY: f64 = 86_400_000.0;
+
+/// A screenpipe database, opened read-only
+pub struct Screenpipe {
+    db: Connection,
+    window_ms: u64,
+    max_chars: usize,
+}
+
+/// OCR of one frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub id: i64,
+    pub text: String,
+    pub app: Option<String>,
+    pub window: Option<String>,
+}
+
+impl Screenpipe {
+    /// screenpipe's own database, `~/.screenpipe/db.sqlite`
+    pub fn open() -> Result<Self> {
+        let home = std::env::var("HOME").context("HOME not set")?;
+        Self::open_at(PathBuf::from(home).join(".screenpipe").join("db.sqlite"))
+    }
+
+    pub fn open_at(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            bail!("No screenpipe database at {}", path.display());
+        }
+        let db = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        Ok(Self { db, window_ms: DEFAULT_WINDOW_MS, max_chars: DEFAULT_MAX_CHARS })
+    }
+
+    /// Only use frames this close to an event (ms), 2 seconds by default
+    pub fn window(mut self, ms: u64) -> Self {
+        self.window_ms = ms;
+        self
+    }
+
+    /// Cut screen text to this many characters, 4000 by default
+    pub fn max_chars(mut self, max: usize) -> Self {
+        self.max_chars = max;
+        self
+    }
+
+    /// The OCR'd frame nearest `at` within the window, the one from `app`
+    /// when a frame has text from several
+    pub fn frame_near(&self, at: DateTime<Utc>, app: Option<&str>) -> Result<Option<Frame>> {
+        let at = at.to_rfc3339_opts(SecondsFormat::Millis, true);
+        let window = self.window_ms as f64 / MS_PER_DAY;
+        let mut query = self.db.prepare_cached(
+            "SELECT f.id, o.text, o.app_name, o.window_name
+             FROM frames f JOIN ocr_text o ON o.frame_id = f.id
+             WHERE julianday(f.timestamp) BETWEEN julianday(?1) - ?2 AND julianday(?1) + ?2
+             ORDER BY abs(julianday(f.timestamp) - julianday(?1)), o.app_name IS NOT ?3
+             LIMIT 1",
+        )?;
+        let frame = query
+            .query_row(params![at, window, app], |row| {
+                Ok(Frame {
+                    id: row.get(0)?,
+                    text: row.get(1)?,
+                    app: row.get(2)?,
+                    window: row.get(3)?,
+                })
+            })
+            .optional()?;
+        Ok(frame)
+    }
+
+    /// Add a ScreenText event after every click and window switch that has
+    /// a frame nearby, replacing any added before. Returns how many were.
+    pub fn enrich(&self, workflow: &mut RecordedWorkflow) -> Result<usize> {
+        let start = workflow.meta.as_ref().map(|m| m.started_at);
+        if start.is_none() && workflow.events.iter().all(|e| e.ts.is_none()) {
+            bail!("{} has no wall-clock times to match frames by", workflow.name);
+        }
+        let events = std::mem::take(&mut workflow.events);
+        let mut added = 0;
+        for event in events {
+            if let EventData::ScreenText { .. } = event.data {
+                continue;
+            }
+            let app = match &event.data {
+                EventData::Click { c, .. } => Some(c.as_ref().and_then(|c| c.a.clone())),
+                EventData::Window { a, .. } => Some(Some(a.clone())),
+                _ => None,
+            };
+            let at = match event.ts {
+                Some(ms) => Utc.timestamp_millis_opt(ms).single(),
+                None => start.map(|start| start + Duration::milliseconds(event.t as i64)),
+            };
+            let (t, ts) = (event.t, event.ts);
+            workflow.events.push(event);
+            let (Some(app), Some(at)) = (app, at) else { continue };
+            if let Some(frame) = self.frame_near(at, app.as_deref())? {
+                let s = prefix(&frame.text, self.max_chars).to_string();
+                let data = EventData::ScreenText { f: frame.id, s, a: frame.app, w: frame.window };
+                workflow.events.push(Event { t, ts, data });
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{ElementContext, EventKind, SessionMetadata};
+
+    #[test]
+    fn adds_nearby_screen_text() {
+        let path = std::env::temp_dir().join(format!("screenpipe-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch(
+            "CREATE TABLE frames (id INTEGER PRIMARY KEY, timestamp TIMESTAMP NOT NULL);
+             CREATE TABLE ocr_text (frame_id INTEGER, text TEXT, app_name TEXT, window_name TEXT);
+             INSERT INTO frames VALUES (1, '2024-03-01T14:00:00.900+00:00');
+             INSERT INTO frames VALUES (2, '2024-03-01T14:00:10.000000+00:00');
+             INSERT INTO ocr_text VALUES (1, 'Inbox', 'Finder', NULL);
+             INSERT INTO ocr_text VALUES (1, 'Inbox (3) Compose', 'Mail', 'Inbox');
+             INSERT INTO ocr_text VALUES (2, 'Draft', 'Mail', 'New Message');",
+        )
+        .unwrap();
+        drop(db);
+
+        let mut workflow = RecordedWorkflow::new("mail");
+        workflow.meta = Some(SessionMetadata {
+            os: "macos".into(),
+            os_version: None,
+            displays: Vec::new(),
+            keyboard_layout: None,
+            started_at: Utc.with_ymd_and_hms(2024, 3, 1, 14, 0, 0).unwrap(),
+            machine: None,
+            recorder_version: "0.1.0".into(),
+        });
+        let context = ElementContext { a: Some("Mail".into()), ..Default::default() };
+        let click = EventData::Click { x: 1, y: 2, b: 0, n: 1, m: 0, c: Some(Box::new(context)) };
+        let event = |t, data| Event { t, ts: None, data };
+        workflow.events = vec![
+            event(1000, click),
+            event(5000, EventData::Text { s: "hi".into() }),
+            event(6000, EventData::Window { a: "Mail".into(), w: None, u: None }),
+        ];
+
+        let screenpipe = Screenpipe::open_at(&path).unwrap();
+        assert_eq!(screenpipe.enrich(&mut workflow).unwrap(), 1);
+        let kinds: Vec<_> = workflow.events.iter().map(|e| e.data.kind()).collect();
+        use EventKind::{Click, ScreenText, Text, Window};
+        assert_eq!(kinds, [Click, ScreenText, Text, Window]);
+        assert!(matches!(
+            &workflow.events[1].data,
+            EventData::ScreenText { f: 1, s, w: Some(w), .. }
+                if s == "Inbox (3) Compose" && w == "Inbox"
+        ));
+
+        // Again, wider and shorter: the old text goes, the window switch gets some
+        let screenpipe = screenpipe.window(5000).max_chars(5);
+        assert_eq!(screenpipe.enrich(&mut workflow).unwrap(), 2);
+        assert_eq!(workflow.events.len(), 5);
+        let text = |i: usize| match &workflow.events[i].data {
+            EventData::ScreenText { s, .. } => s.clone(),
+            other => panic!("{} isn't screen text", other),
+        };
+        assert_eq!((text(1), text(4)), ("Inbox".into(), "Draft".into()));
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,65 @@
+//! Screenshots taken alongside recorded events
+//!
+//! Captures run on a background thread and are referenced by a Screenshot
+//! event carrying the timestamp of the event that triggered them. The actual
+//! grab is platform specific (`capture_screen` in each platform module).
+
+use crate::events::{Event, EventData};
+use crate::platform;
+use crate::recorder::{EventSink, RecorderConfig};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+pub(crate) struct Screenshotter {
+    dir: PathBuf,
+    max_size: u32,
+    /// Session start, keeps file names unique across sessions
+    prefix: String,
+    /// A capture is running (or capturing failed and is disabled)
+    busy: Arc<AtomicBool>,
+}
+
+impl Screenshotter {
+    /// `None` unless `capture_screenshots` is on
+    pub fn new(config: &RecorderConfig) -> Option<Self> {
+        if !config.capture_screenshots {
+            return None;
+        }
+        if let Err(e) = std::fs::create_dir_all(&config.screenshot_dir) {
+            let dir = config.screenshot_dir.display();
+            tracing::warn!(%dir, error = %e, "Screenshots disabled, can't create directory");
+            return None;
+        }
+        Some(Self {
+            dir: config.screenshot_dir.clone(),
+            max_size: config.screenshot_max_size,
+            prefix: chrono::Local::now().format("%Y%m%d_%H%M%S").to_string(),
+            busy: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Grab the screen for the event at `t`. Triggers while a capture is
+    /// still running are dropped rather than queued.
+    pub fn capture(&self, t: u64, tx: EventSink) {
+        if self.busy.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let path = self.dir.join(format!("{}_{}.jpg", self.prefix, t));
+        let max_size = self.max_size;
+        let busy = self.busy.clone();
+        thread::spawn(move || match platform::current::capture_screen(&path, max_size) {
+            Ok(()) => {
+                let _ = tx.deliver(Event {
+                    t,
+                    ts: None,
+                    data: EventData::Screenshot { f: path.to_string_lossy().into_owned() },
+                });
+                busy.store(false, Ordering::SeqCst);
+            }
+            // Leave `busy` set: a missing tool or permission won't fix itself
+            Err(e) => tracing::warn!(error = %e, "Screenshots disabled"),
+        });
+    }
+}
@@ -0,0 +1,408 @@
+//! Higher-level actions from a raw recording
+//!
+//! [`actions`] turns the click/key/text stream of a [`RecordedWorkflow`]
+//! into what the user did, e.g. typed "hello" into TextField 'Search' in
+//! Safari. Moves are dropped, scroll bursts and text runs merge, clicks
+//! through a menu become one menu path, and text is attributed to the
+//! element clicked before it.
+
+use crate::events::*;
+use crate::keys::Hotkey;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Scroll events closer together than this (ms) are one scroll
+const SCROLL_MERGE_MS: u64 = 500;
+
+/// An element the user acted on, from a click's [`ElementContext`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Target {
+    /// Role without the platform prefix ("TextField", not "AXTextField")
+    pub role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    /// Selector resolving to the element with `Locator`; empty for
+    /// recordings made before clicks carried context
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub selector: String,
+}
+
+impl Target {
+    fn new(role: &str, name: Option<&String>) -> Self {
+        Self {
+            role: role.strip_prefix("AX").unwrap_or(role).to_string(),
+            name: name.filter(|n| !n.is_empty()).cloned(),
+            app: None,
+            selector: String::new(),
+        }
+    }
+
+    fn from_context(c: &ElementContext) -> Self {
+        Self {
+            app: c.a.clone(),
+            selector: c.s.clone(),
+            ..Self::new(&c.r, c.n.as_ref())
+        }
+    }
+
+    fn is_menu(&self) -> bool {
+        matches!(self.role.as_str(), "MenuItem" | "MenuBarItem")
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.role)?;
+        if let Some(name) = &self.name {
+            write!(f, " '{}'", name)?;
+        }
+        if let Some(app) = &self.app {
+            write!(f, " in {}", app)?;
+        }
+        Ok(())
+    }
+}
+
+/// Something the user did, made of one or more raw events. `t` is when it
+/// started.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Clicked an element, or a point when there's no element context
+    Click {
+        t: u64,
+        x: i32,
+        y: i32,
+        button: u8,
+        count: u8,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<Target>,
+    },
+    /// Typed text, into the element clicked before it when known
+    Type {
+        t: u64,
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        target: Option<Target>,
+    },
+    /// Pressed a key or a shortcut, e.g. "cmd+s"
+    Press { t: u64, keys: String },
+    /// Picked a menu item, path from the menu bar down
+    Menu {
+        t: u64,
+        path: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        app: Option<String>,
+    },
+    /// Dragged with a button held
+    Drag { t: u64, from: (i32, i32), to: (i32, i32), button: u8 },
+    /// Scrolled at a point; the distance sums a burst of scroll events, in
+    /// lines or with `pixels` in pixels
+    Scroll {
+        t: u64,
+        x: i32,
+        y: i32,
+        dx: i32,
+        dy: i32,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        pixels: bool,
+    },
+    SwitchApp { t: u64, app: String },
+    /// Focused a window, or a browser tab
+    FocusWindow {
+        t: u64,
+        app: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+    },
+    /// "copy", "cut" or "paste", with a preview of the content
+    Clipboard { t: u64, op: String, preview: String },
+    /// "created", "modified", "deleted" or "renamed" in a watched directory
+    File { t: u64, path: String, op: String },
+    /// Left with the annotation hotkey
+    Note { t: u64, text: String },
+}
+
+impl Action {
+    pub fn t(&self) -> u64 {
+        match self {
+            Action::Click { t, .. }
+            | Action::Type { t, .. }
+            | Action::Press { t, .. }
+            | Action::Menu { t, .. }
+            | Action::Drag { t, .. }
+            | Action::Scroll { t, .. }
+            | Action::SwitchApp { t, .. }
+            | Action::FocusWindow { t, .. }
+            | Action::Clipboard { t, .. }
+            | Action::File { t, .. }
+            | Action::Note { t, .. } => *t,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Click { x, y, button, count, target, .. } => {
+                let verb = match (button, count) {
+                    (_, 2) => "double-clicked",
+                    (1, _) => "right-clicked",
+                    (2, _) => "middle-clicked",
+                    _ => "clicked",
+                };
+                match target {
+                    Some(target) => write!(f, "{} {}", verb, target),
+                    None => write!(f, "{} at ({}, {})", verb, x, y),
+                }
+            }
+            Action::Type { text, target, .. } => {
+                write!(f, "typed {:?}", text)?;
+                match target {
+                    Some(target) => write!(f, " into {}", target),
+                    None => Ok(()),
+                }
+            }
+            Action::Press { keys, .. } => write!(f, "pressed {}", keys),
+            Action::Menu { path, app, .. } => {
+                write!(f, "selected menu {}", path.join(" > "))?;
+                match app {
+                    Some(app) => write!(f, " in {}", app),
+                    None => Ok(()),
+                }
+            }
+            Action::Drag { from, to, .. } => {
+                write!(f, "dragged from ({}, {}) to ({}, {})", from.0, from.1, to.0, to.1)
+            }
+            Action::Scroll { x, y, dx, dy, pixels, .. } => {
+                let unit = if *pixels { " px" } else { "" };
+                write!(f, "scrolled ({}, {}){} at ({}, {})", dx, dy, unit, x, y)
+            }
+            Action::SwitchApp { app, .. } => write!(f, "switched to {}", app),
+            Action::FocusWindow { app, title, url, .. } => {
+                match title {
+                    Some(title) => write!(f, "focused window '{}' in {}", title, app)?,
+                    None => write!(f, "focused a window in {}", app)?,
+                }
+                match url {
+                    Some(url) => write!(f, " ({})", url),
+                    None => Ok(()),
+                }
+            }
+            Action::Clipboard { op, preview, .. } => {
+                let verb = match op.as_str() {
+                    "cut" => "cut",
+                    "paste" => "pasted",
+                    _ => "copied",
+                };
+                write!(f, "{} {:?}", verb, preview)
+            }
+            Action::File { path, op, .. } => write!(f, "file {} {}", path, op),
+            Action::Note { text, .. } => write!(f, "note: {}", text),
+        }
+    }
+}
+
+/// What the user did in `workflow`, in order
+pub fn actions(workflow: &RecordedWorkflow) -> Vec<Action> {
+    let mut out: Vec<Action> = Vec::new();
+    // Element typing goes into: the last one clicked in the current app
+    let mut focus: Option<Target> = None;
+    // Menu titles clicked on the way to an item
+    let mut menu: Vec<String> = Vec::new();
+    let mut last_scroll = 0;
+
+    for event in &workflow.events {
+        let t = event.t;
+        match &event.data {
+            EventData::Click { x, y, b, n, c, .. } => {
+                let target = c.as_deref().map(Target::from_context);
+                if let Some(target) = target.as_ref().filter(|t| t.is_menu()) {
+                    push_menu(&mut out, &mut menu, t, target, c.as_deref().map(|c| &c.p[..]));
+                    continue;
+                }
+                menu.clear();
+                focus = target.clone();
+                out.push(Action::Click { t, x: *x, y: *y, button: *b, count: *n, target });
+            }
+            // Older recordings sent the clicked element separately
+            EventData::Context { r, n, .. } => {
+                if let Some(Action::Click { target: target @ None, .. }) = out.last_mut() {
+                    *target = Some(Target::new(r, n.as_ref()));
+                    focus = target.clone();
+                }
+            }
+            EventData::Text { s } => match out.last_mut() {
+                // Split by typing pauses, not by the user
+                Some(Action::Type { text, .. }) => text.push_str(s),
+                _ => out.push(Action::Type { t, text: s.clone(), target: focus.clone() }),
+            },
+            EventData::Key { k, m } => {
+                out.push(Action::Press { t, keys: Hotkey::new(*k, *m).to_string() })
+            }
+            EventData::Drag { x, y, x2, y2, b, .. } => {
+                out.push(Action::Drag { t, from: (*x, *y), to: (*x2, *y2), button: *b })
+            }
+            EventData::Scroll { x, y, dx, dy, px } => {
+                let merged = match out.last_mut() {
+                    Some(Action::Scroll { dx: sx, dy: sy, pixels, .. })
+                        if *pixels == *px && t.saturating_sub(last_scroll) <= SCROLL_MERGE_MS =>
+                    {
+                        *sx += *dx as i32;
+                        *sy += *dy as i32;
+                        true
+                    }
+                    _ => false,
+                };
+                if !merged {
+                    let (x, y, dx, dy) = (*x, *y, *dx as i32, *dy as i32);
+                    out.push(Action::Scroll { t, x, y, dx, dy, pixels: *px });
+                }
+                last_scroll = t;
+            }
+            EventData::App { n, .. } => {
+                focus = None;
+                menu.clear();
+                out.push(Action::SwitchApp { t, app: n.clone() });
+            }
+            EventData::Window { a, w, u } => {
+                // Titles change as the user works ("Edited"); keep the latest
+                if matches!(out.last(), Some(Action::FocusWindow { app, .. }) if app == a) {
+                    out.pop();
+                }
+                out.push(Action::FocusWindow { t, app: a.clone(), title: w.clone(), url: u.clone() });
+            }
+            EventData::Paste { o, s, .. } => {
+                let op = match o {
+                    'x' => "cut",
+                    'v' => "paste",
+                    _ => "copy",
+                };
+                out.push(Action::Clipboard { t, op: op.to_string(), preview: s.clone() });
+            }
+            EventData::FileChanged { f, o } => {
+                let op = match o {
+                    'c' => "created",
+                    'd' => "deleted",
+                    'r' => "renamed",
+                    _ => "modified",
+                };
+                out.push(Action::File { t, path: f.clone(), op: op.to_string() });
+            }
+            EventData::Annotation { s } => out.push(Action::Note { t, text: s.clone() }),
+            // Moves, releases, key releases, screenshots and markers
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Record a click on a menu title or item. Menu bar titles only open a
+/// menu; an item is the choice, with its path taken from the element's
+/// ancestors or else the titles clicked before it.
+fn push_menu(
+    out: &mut Vec<Action>,
+    menu: &mut Vec<String>,
+    t: u64,
+    target: &Target,
+    ancestors: Option<&[String]>,
+) {
+    let Some(name) = target.name.clone() else {
+        return;
+    };
+    if target.role == "MenuBarItem" {
+        *menu = vec![name];
+        return;
+    }
+    let mut path: Vec<String> = ancestors
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|selector| menu_title(selector))
+        .collect();
+    if path.is_empty() {
+        path = std::mem::take(menu);
+    }
+    path.push(name);
+    menu.clear();
+
+    // Opening a submenu clicks its item too: "File > Open Recent" then
+    // "File > Open Recent > notes.txt" is one choice
+    if let Some(Action::Menu { path: last, .. }) = out.last() {
+        if path.starts_with(last) {
+            out.pop();
+        }
+    }
+    out.push(Action::Menu { t, path, app: target.app.clone() });
+}
+
+/// Title of a menu bar title or menu item ancestor selector
+/// (`role:AXMenuBarItem AND title:File`)
+fn menu_title(selector: &str) -> Option<String> {
+    let mut parts = selector.split(" AND ");
+    let role = parts.next()?.strip_prefix("role:")?;
+    let role = role.strip_prefix("AX").unwrap_or(role);
+    if !matches!(role, "MenuItem" | "MenuBarItem") {
+        return None;
+    }
+    parts.find_map(|p| p.strip_prefix("title:").or_else(|| p.strip_prefix("name:"))).map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::Key;
+
+    fn click(t: u64, r: &str, n: &str, p: &[&str]) -> Event {
+        let c = ElementContext {
+            s: format!("role:{} AND title:{}", r, n),
+            r: r.into(),
+            n: Some(n.into()),
+            p: p.iter().map(|s| s.to_string()).collect(),
+            a: Some("Safari".into()),
+            ..Default::default()
+        };
+        let data = EventData::Click { x: 1, y: 2, b: 0, n: 1, m: 0, c: Some(Box::new(c)) };
+        Event { t, ts: None, data }
+    }
+
+    #[test]
+    fn aggregates_typing_menus_and_scrolls() {
+        let event = |t, data| Event { t, ts: None, data };
+        let mut workflow = RecordedWorkflow::new("test");
+        workflow.events = vec![
+            event(0, EventData::App { n: "Safari".into(), p: 1 }),
+            click(10, "AXTextField", "Search", &[]),
+            event(20, EventData::Move { x: 5, y: 5 }),
+            event(30, EventData::Text { s: "hel".into() }),
+            event(400, EventData::Text { s: "lo".into() }),
+            event(500, EventData::Key { k: Key::S, m: Modifiers::CMD }),
+            event(600, EventData::Scroll { x: 0, y: 0, dx: 0, dy: -3, px: false }),
+            event(700, EventData::Scroll { x: 0, y: 0, dx: 0, dy: -2, px: false }),
+            click(800, "AXMenuBarItem", "File", &[]),
+            click(900, "AXMenuItem", "Open Recent", &["role:AXMenuBarItem AND title:File"]),
+            click(
+                1000,
+                "AXMenuItem",
+                "notes.txt",
+                &["role:AXMenuBarItem AND title:File", "role:AXMenuItem AND title:Open Recent"],
+            ),
+        ];
+
+        let lines: Vec<String> = actions(&workflow).iter().map(|a| a.to_string()).collect();
+        assert_eq!(
+            lines,
+            [
+                "switched to Safari",
+                "clicked TextField 'Search' in Safari",
+                "typed \"hello\" into TextField 'Search' in Safari",
+                "pressed cmd+s",
+                "scrolled (0, -5) at (0, 0)",
+                "selected menu File > Open Recent > notes.txt in Safari",
+            ]
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Always-on recording
+//!
+//! [`RecordingService`] records until it's told to stop, starting a new file
+//! every `rotate_every` and deleting the recordings its `retention` policy
+//! doesn't keep. On Unix it listens on a control socket: one
+//! command per connection (`status`, `pause`, `resume` or `stop`), answered
+//! with a [`ServiceStatus`] as a line of JSON. [`send`] is the client side.
+
+use crate::recorder::{recordings_subdir, RecorderConfig, WorkflowRecorder};
+use crate::storage::{Compression, Format, RetentionPolicy, WorkflowStorage};
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct ServiceConfig {
+    /// `max_duration` and `rotate` are set from `rotate_every`. Screenshots,
+    /// clipboard files and videos go in `dir` by default, so pruning it
+    /// covers them.
+    pub recorder: RecorderConfig,
+    /// Recordings are saved as `<name>_<timestamp>.<ext>`
+    pub name: String,
+    /// Encoding of the recordings; MessagePack takes about half the space
+    pub format: Format,
+    /// Compression of the recordings; zstd makes all-day files a fraction
+    /// of the size
+    pub compression: Compression,
+    /// Where recordings go. Everything in it counts towards the quota.
+    pub dir: PathBuf,
+    /// Start a new file this often
+    pub rotate_every: Duration,
+    /// Which recordings in `dir` to keep, applied at startup and after
+    /// each rotation
+    pub retention: RetentionPolicy,
+    /// Control socket (Unix only)
+    pub socket: Option<PathBuf>,
+}
+
+impl Default for ServiceConfig {
+    fn default() -> Self {
+        let dir = recordings_subdir("daemon");
+        let recorder = RecorderConfig {
+            screenshot_dir: dir.join("screenshots"),
+            clipboard_dir: dir.join("clipboard"),
+            #[cfg(feature = "video")]
+            video_dir: dir.join("videos"),
+            ..RecorderConfig::default()
+        };
+        Self {
+            recorder,
+            name: "activity".to_string(),
+            format: Format::default(),
+            compression: Compression::default(),
+            socket: Some(dir.join("control.sock")),
+            dir,
+            rotate_every: Duration::from_secs(60 * 60),
+            retention: RetentionPolicy::default(),
+        }
+    }
+}
+
+/// What a running service is up to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceStatus {
+    pub running: bool,
+    pub paused: bool,
+    /// Events recorded since the service started
+    pub events: u64,
+    /// Events lost because the channel was full
+    pub dropped: u64,
+    /// Finished recordings in `dir`
+    pub files: usize,
+    /// Their total size
+    pub bytes: u64,
+}
+
+/// Controls a [`RecordingService`] from another thread
+#[derive(Clone, Default)]
+pub struct ServiceControl {
+    inner: Arc<Control>,
+}
+
+#[derive(Default)]
+struct Control {
+    stop: AtomicBool,
+    /// Pause or resume, not yet applied. Not a plain flag: the session can
+    /// also be paused with its `pause_hotkey`.
+    pause: Mutex<Option<bool>>,
+    status: Mutex<ServiceStatus>,
+}
+
+impl ServiceControl {
+    pub fn stop(&self) {
+        self.inner.stop.store(true, Ordering::SeqCst);
+    }
+
+    pub fn pause(&self) {
+        *self.inner.pause.lock() = Some(true);
+    }
+
+    pub fn resume(&self) {
+        *self.inner.pause.lock() = Some(false);
+    }
+
+    pub fn status(&self) -> ServiceStatus {
+        self.inner.status.lock().clone()
+    }
+
+    fn stopped(&self) -> bool {
+        self.inner.stop.load(Ordering::SeqCst)
+    }
+}
+
+pub struct RecordingService {
+    config: ServiceConfig,
+    control: ServiceControl,
+}
+
+impl RecordingService {
+    pub fn new(config: ServiceConfig) -> Self {
+        Self { config, control: ServiceControl::default() }
+    }
+
+    pub fn control(&self) -> ServiceControl {
+        self.control.clone()
+    }
+
+    /// Record until stopped through [`control`](Self::control) or the socket
+    pub fn run(&self) -> Result<()> {
+        let storage = WorkflowStorage::with_dir(&self.config.dir)?
+            .format(self.config.format)
+            .compression(self.config.compression);
+        // Left behind by a crash or a kill
+        for path in storage.recover()? {
+            tracing::warn!(path = %path.display(), "Recovered recording");
+        }
+        let (mut files, mut bytes) = self.prune(&storage)?;
+
+        #[cfg(unix)]
+        let listener = match &self.config.socket {
+            Some(path) => Some(control_socket::listen(path, self.control.clone())?),
+            None => None,
+        };
+
+        let config = RecorderConfig {
+            max_duration: Some(self.config.rotate_every),
+            rotate: true,
+            ..self.config.recorder.clone()
+        };
+        let recorder = WorkflowRecorder::with_config(config);
+        let (mut workflow, mut handle) = recorder.start(&self.config.name)?;
+        handle.stream_to(storage.writer(&workflow)?);
+
+        while !self.control.stopped() && handle.is_running() {
+            match self.control.inner.pause.lock().take() {
+                Some(true) => handle.pause(),
+                Some(false) => handle.resume(),
+                None => {}
+            }
+            handle.drain(&mut workflow);
+            // Streaming failed and events are piling up in memory: save
+            // them and try a new file
+            if !workflow.events.is_empty() {
+                storage.save(&workflow)?;
+                workflow.events.clear();
+                handle.stream_to(storage.writer(&workflow)?);
+            }
+            if !handle.take_rotated().is_empty() {
+                (files, bytes) = self.prune(&storage)?;
+            }
+
+            let stats = handle.stats();
+            *self.control.inner.status.lock() = ServiceStatus {
+                running: true,
+                paused: handle.is_paused(),
+                events: stats.sent,
+                dropped: stats.dropped,
+                files,
+                bytes,
+            };
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        if handle.stop(&mut workflow).is_none() && !workflow.events.is_empty() {
+            storage.save(&workflow)?;
+        }
+        (files, bytes) = self.prune(&storage)?;
+        let mut status = self.control.inner.status.lock();
+        *status = ServiceStatus { running: false, files, bytes, ..status.clone() };
+        drop(status);
+
+        #[cfg(unix)]
+        if let Some(listener) = listener {
+            listener.close();
+        }
+        Ok(())
+    }
+
+    /// Apply the retention policy; returns how many recordings are left
+    /// and their size, assets included
+    fn prune(&self, storage: &WorkflowStorage) -> Result<(usize, u64)> {
+        let pruned = storage.prune(&self.config.retention)?;
+        Ok((pruned.kept, pruned.bytes))
+    }
+}
+
+/// Send `command` to the service listening on `socket`
+#[cfg(unix)]
+pub fn send(socket: &Path, command: &str) -> Result<ServiceStatus> {
+    use anyhow::Context;
+    use std::io::{BufRead, BufReader, Write};
+
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)
+        .with_context(|| format!("No service listening on {}", socket.display()))?;
+    writeln!(stream, "{}", command)?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let reply: serde_json::Value = serde_json::from_str(&line)?;
+    if let Some(error) = reply.get("error").and_then(|e| e.as_str()) {
+        anyhow::bail!("{}", error);
+    }
+    Ok(serde_json::from_value(reply)?)
+}
+
+#[cfg(unix)]
+mod control_socket {
+    use super::ServiceControl;
+    use anyhow::{bail, Result};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    pub struct Listener {
+        path: PathBuf,
+        done: Arc<AtomicBool>,
+        thread: thread::JoinHandle<()>,
+    }
+
+    impl Listener {
+        pub fn close(self) {
+            self.done.store(true, Ordering::SeqCst);
+            let _ = self.thread.join();
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    pub fn listen(path: &Path, control: ServiceControl) -> Result<Listener> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                bail!("A service is already listening on {}", path.display());
+            }
+            // Left by a service that didn't shut down cleanly
+            std::fs::remove_file(path)?;
+        }
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        // Polled, so closing doesn't need a connection to wake it up
+        listener.set_nonblocking(true)?;
+        let done = Arc::new(AtomicBool::new(false));
+        let thread = thread::spawn({
+            let done = done.clone();
+            move || {
+                while !done.load(Ordering::SeqCst) {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            if let Err(e) = answer(stream, &control) {
+                                tracing::warn!(error = %e, "Control socket");
+                            }
+                        }
+                        Err(_) => thread::sleep(Duration::from_millis(100)),
+                    }
+                }
+            }
+        });
+        Ok(Listener { path: path.to_path_buf(), done, thread })
+    }
+
+    fn answer(stream: UnixStream, control: &ServiceControl) -> Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        match line.trim() {
+            "status" => {}
+            "pause" => control.pause(),
+            "resume" => control.resume(),
+            "stop" => control.stop(),
+            other => {
+                let error = serde_json::json!({ "error": format!("Unknown command: {}", other) });
+                writeln!(&stream, "{}", error)?;
+                return Ok(());
+            }
+        }
+        // Reflect the command, without waiting for the service loop
+        let mut status = control.status();
+        if let Some(paused) = *control.inner.pause.lock() {
+            status.paused = paused;
+        }
+        status.running = !control.stopped();
+        writeln!(&stream, "{}", serde_json::to_string(&status)?)?;
+        Ok(())
+    }
+}
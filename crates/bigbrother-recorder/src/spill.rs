@@ -0,0 +1,106 @@
+//! Overflow-to-disk for the session channel
+//!
+//! With [`Overflow::Disk`](crate::recorder::Overflow::Disk), events that
+//! don't fit in the channel are appended to a JSON lines file instead of
+//! being dropped. Once spilling starts every later event goes to the file
+//! too, so channel events always come before spilled ones; the recording
+//! handle reads the file back (and switches to the channel again) when it
+//! drains.
+
+use crate::events::Event;
+use crate::recorder::{Outlet, Receiver};
+use anyhow::Result;
+use crossbeam_channel::TrySendError;
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+pub(crate) struct Spill {
+    path: PathBuf,
+    state: Mutex<State>,
+}
+
+struct State {
+    writer: Option<BufWriter<File>>,
+    /// Events are going to the file, not the channel
+    active: bool,
+}
+
+/// Where [`Spill::send`] put an event
+pub(crate) enum Sent {
+    Channel,
+    Disk,
+}
+
+impl Spill {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            state: Mutex::new(State {
+                writer: None,
+                active: false,
+            }),
+        }
+    }
+
+    /// Send through the channel, or append to the file when it's full or
+    /// already in use
+    pub fn send(&self, tx: &Outlet, event: Event) -> Result<Sent, TrySendError<Event>> {
+        let mut state = self.state.lock();
+        let event = if state.active {
+            event
+        } else {
+            match tx.try_send(event) {
+                Ok(()) => return Ok(Sent::Channel),
+                Err(TrySendError::Full(event)) => event,
+                Err(e) => return Err(e),
+            }
+        };
+        match self.append(&mut state, &event) {
+            Ok(()) => {
+                state.active = true;
+                Ok(Sent::Disk)
+            }
+            Err(_) => Err(TrySendError::Full(event)),
+        }
+    }
+
+    fn append(&self, state: &mut State, event: &Event) -> Result<()> {
+        if state.writer.is_none() {
+            if let Some(dir) = self.path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            state.writer = Some(BufWriter::new(File::create(&self.path)?));
+        }
+        let w = state.writer.as_mut().expect("spill writer was just opened");
+        serde_json::to_writer(&mut *w, event)?;
+        writeln!(w)?;
+        Ok(())
+    }
+
+    /// Everything queued, in order: the channel first, then the file
+    pub fn drain(&self, rx: &Receiver<Event>, out: &mut Vec<Event>) {
+        let mut state = self.state.lock();
+        out.extend(rx.try_iter());
+        if !state.active {
+            return;
+        }
+        state.active = false;
+        if let Some(mut w) = state.writer.take() {
+            let _ = w.flush();
+        }
+        match File::open(&self.path) {
+            Ok(file) => out.extend(
+                BufReader::new(file)
+                    .lines()
+                    .map_while(|line| line.ok())
+                    .filter_map(|line| serde_json::from_str(&line).ok()),
+            ),
+            Err(e) => {
+                tracing::warn!(path = %self.path.display(), error = %e, "Can't read spilled events")
+            }
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,432 @@
+//! Queryable workflow store in SQLite
+//!
+//! [`SqliteStore`] keeps recordings in one database file, an event a row
+//! with its wall-clock time, app, kind and searchable text indexed, so "what
+//! did I do in Xcode yesterday afternoon" is a query instead of a scan over
+//! JSON lines.
+//!
+//! ```rust,no_run
+//! use bigbrother_recorder::prelude::*;
+//! use chrono::{Duration, Local};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let store = WorkflowStorage::sqlite("activity.db")?;
+//! let noon = Local::now().date_naive().and_hms_opt(12, 0, 0).unwrap() - Duration::days(1);
+//! let noon = noon.and_local_timezone(Local).unwrap();
+//! let query = EventQuery::new().between(noon, noon + Duration::hours(6)).app("Xcode");
+//! for found in store.query(&query)? {
+//!     println!("{:?} {}", found.time, found.event.data);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::events::{Event, EventData, EventKind, RecordedWorkflow};
+use crate::storage::WorkflowStorage;
+use crate::table::track_app;
+use anyhow::{bail, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use rusqlite::{params, params_from_iter, Connection, ToSql};
+use std::path::Path;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS workflows (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- Unix ms at t = 0, when the recording has session metadata
+    started_at INTEGER,
+    meta TEXT,
+    description TEXT,
+    -- JSON array
+    tags TEXT NOT NULL DEFAULT '[]',
+    events INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS events (
+    workflow INTEGER NOT NULL REFERENCES workflows(id) ON DELETE CASCADE,
+    seq INTEGER NOT NULL,
+    t INTEGER NOT NULL,
+    -- Unix ms; NULL when the recording has no start time
+    time INTEGER,
+    kind TEXT NOT NULL,
+    -- Frontmost app, or the one clicked in
+    app TEXT COLLATE NOCASE,
+    -- What the event typed, copied, showed or clicked on
+    text TEXT,
+    data TEXT NOT NULL,
+    PRIMARY KEY (workflow, seq)
+);
+CREATE INDEX IF NOT EXISTS events_time ON events(time);
+CREATE INDEX IF NOT EXISTS events_app ON events(app, time);
+CREATE INDEX IF NOT EXISTS events_kind ON events(kind, time);
+";
+
+impl WorkflowStorage {
+    /// A store in the SQLite database at `path`, created if need be
+    pub fn sqlite(path: impl AsRef<Path>) -> Result<SqliteStore> {
+        SqliteStore::open(path)
+    }
+}
+
+/// Recordings in a SQLite database, see the [module docs](self)
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+/// A recording in a [`SqliteStore`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredWorkflow {
+    pub id: i64,
+    pub name: String,
+    pub started_at: Option<DateTime<Utc>>,
+    pub events: usize,
+}
+
+/// An event found by [`SqliteStore::query`]
+#[derive(Debug, Clone)]
+pub struct StoredEvent {
+    /// Id and name of the recording it's from
+    pub workflow_id: i64,
+    pub workflow: String,
+    /// Index in the recording
+    pub index: usize,
+    pub time: Option<DateTime<Utc>>,
+    pub app: Option<String>,
+    pub event: Event,
+}
+
+/// Which events [`SqliteStore::query`] returns; every condition set has to
+/// match
+#[derive(Debug, Clone, Default)]
+pub struct EventQuery {
+    /// Unix ms, `to` excluded
+    from: Option<i64>,
+    to: Option<i64>,
+    app: Option<String>,
+    kinds: Vec<EventKind>,
+    text: Option<String>,
+    limit: Option<usize>,
+}
+
+impl EventQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events from `from` up to (not including) `to`. Recordings without
+    /// session metadata have no wall-clock time and never match.
+    pub fn between<Tz: TimeZone>(mut self, from: DateTime<Tz>, to: DateTime<Tz>) -> Self {
+        self.from = Some(from.timestamp_millis());
+        self.to = Some(to.timestamp_millis());
+        self
+    }
+
+    /// Events in this app, ignoring case
+    pub fn app(mut self, app: impl Into<String>) -> Self {
+        self.app = Some(app.into());
+        self
+    }
+
+    /// Events of this kind; call again to allow more kinds
+    pub fn kind(mut self, kind: EventKind) -> Self {
+        self.kinds.push(kind);
+        self
+    }
+
+    /// Events whose text (typed, copied, window title, clicked element)
+    /// contains `text`, ignoring ASCII case
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// At most this many events, the earliest first
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+}
+
+impl SqliteStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::init(Connection::open(path)?)
+    }
+
+    /// A store that's gone when dropped
+    pub fn in_memory() -> Result<Self> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self> {
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self { conn })
+    }
+
+    /// Save `workflow`; returns its id
+    pub fn save(&self, workflow: &RecordedWorkflow) -> Result<i64> {
+        let started = workflow.meta.as_ref().map(|m| m.started_at.timestamp_millis());
+        let meta = workflow.meta.as_ref().map(serde_json::to_string).transpose()?;
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO workflows (name, started_at, meta, description, tags, events)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                workflow.name,
+                started,
+                meta,
+                workflow.description,
+                serde_json::to_string(&workflow.tags)?,
+                workflow.events.len() as i64,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO events (workflow, seq, t, time, kind, app, text, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )?;
+            let mut app = None;
+            for (seq, event) in workflow.events.iter().enumerate() {
+                let event_app = track_app(&mut app, &event.data);
+                let time = event.ts.or(started.map(|s| s + event.t as i64));
+                insert.execute(params![
+                    id,
+                    seq as i64,
+                    event.t as i64,
+                    time,
+                    format!("{:?}", event.data.kind()),
+                    event_app,
+                    search_text(&event.data),
+                    serde_json::to_string(event)?,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(id)
+    }
+
+    pub fn load(&self, id: i64) -> Result<RecordedWorkflow> {
+        let row = self.conn.query_row(
+            "SELECT name, meta, description, tags FROM workflows WHERE id = ?1",
+            [id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            },
+        );
+        let (name, meta, description, tags) = match row {
+            Ok(row) => row,
+            Err(rusqlite::Error::QueryReturnedNoRows) => bail!("No workflow {}", id),
+            Err(e) => return Err(e.into()),
+        };
+        let mut stmt =
+            self.conn.prepare("SELECT data FROM events WHERE workflow = ?1 ORDER BY seq")?;
+        let events = stmt
+            .query_map([id], |row| row.get::<_, String>(0))?
+            .map(|data| Ok(serde_json::from_str(&data?)?))
+            .collect::<Result<Vec<Event>>>()?;
+        let meta = meta.map(|m| serde_json::from_str(&m)).transpose()?;
+        let tags = serde_json::from_str(&tags)?;
+        Ok(RecordedWorkflow { name, events, meta, description, tags })
+    }
+
+    /// All recordings, oldest first
+    pub fn list(&self) -> Result<Vec<StoredWorkflow>> {
+        let mut stmt =
+            self.conn.prepare("SELECT id, name, started_at, events FROM workflows ORDER BY id")?;
+        let workflows = stmt
+            .query_map([], |row| {
+                Ok(StoredWorkflow {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    started_at: row.get::<_, Option<i64>>(2)?.and_then(from_millis),
+                    events: row.get::<_, i64>(3)? as usize,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(workflows)
+    }
+
+    pub fn delete(&self, id: i64) -> Result<()> {
+        if self.conn.execute("DELETE FROM workflows WHERE id = ?1", [id])? == 0 {
+            bail!("No workflow {}", id);
+        }
+        Ok(())
+    }
+
+    /// Events matching `query`, in time order (those without a time first)
+    pub fn query(&self, query: &EventQuery) -> Result<Vec<StoredEvent>> {
+        let mut sql = String::from(
+            "SELECT e.workflow, w.name, e.seq, e.time, e.app, e.data
+             FROM events e JOIN workflows w ON w.id = e.workflow WHERE 1 = 1",
+        );
+        let mut args: Vec<Box<dyn ToSql>> = Vec::new();
+        if let Some(from) = query.from {
+            sql.push_str(" AND e.time >= ?");
+            args.push(Box::new(from));
+        }
+        if let Some(to) = query.to {
+            sql.push_str(" AND e.time < ?");
+            args.push(Box::new(to));
+        }
+        if let Some(app) = &query.app {
+            sql.push_str(" AND e.app = ?");
+            args.push(Box::new(app.clone()));
+        }
+        if !query.kinds.is_empty() {
+            let marks = vec!["?"; query.kinds.len()].join(", ");
+            sql.push_str(&format!(" AND e.kind IN ({})", marks));
+            args.extend(query.kinds.iter().map(|k| Box::new(format!("{:?}", k)) as Box<dyn ToSql>));
+        }
+        if let Some(text) = &query.text {
+            sql.push_str(" AND e.text LIKE ? ESCAPE '\\'");
+            args.push(Box::new(format!("%{}%", escape_like(text))));
+        }
+        sql.push_str(" ORDER BY e.time, e.workflow, e.seq");
+        if let Some(limit) = query.limit {
+            sql.push_str(" LIMIT ?");
+            args.push(Box::new(limit as i64));
+        }
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(args.iter()), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, Option<i64>>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })?;
+        let mut found = Vec::new();
+        for row in rows {
+            let (workflow_id, workflow, seq, time, app, data) = row?;
+            found.push(StoredEvent {
+                workflow_id,
+                workflow,
+                index: seq as usize,
+                time: time.and_then(from_millis),
+                app,
+                event: serde_json::from_str(&data)?,
+            });
+        }
+        Ok(found)
+    }
+
+    /// Events from `from` up to `to`
+    pub fn events_between<Tz: TimeZone>(
+        &self,
+        from: DateTime<Tz>,
+        to: DateTime<Tz>,
+    ) -> Result<Vec<StoredEvent>> {
+        self.query(&EventQuery::new().between(from, to))
+    }
+
+    /// Events in `app`
+    pub fn events_for_app(&self, app: &str) -> Result<Vec<StoredEvent>> {
+        self.query(&EventQuery::new().app(app))
+    }
+
+    /// Events whose text contains `text`
+    pub fn search_text(&self, text: &str) -> Result<Vec<StoredEvent>> {
+        self.query(&EventQuery::new().text(text))
+    }
+}
+
+/// What [`EventQuery::text`] searches in an event
+fn search_text(data: &EventData) -> Option<String> {
+    match data {
+        EventData::Text { s }
+        | EventData::Annotation { s }
+        | EventData::Paste { s, .. }
+        | EventData::ScreenText { s, .. } => Some(s.clone()),
+        EventData::Window { w, u, .. } => {
+            let parts: Vec<&str> = [w, u].into_iter().flatten().map(String::as_str).collect();
+            (!parts.is_empty()).then(|| parts.join(" "))
+        }
+        EventData::Click { c: Some(c), .. } => c.n.clone().or_else(|| c.v.clone()),
+        EventData::FileChanged { f, .. } => Some(f.clone()),
+        _ => None,
+    }
+}
+
+/// `%` and `_` matched literally
+fn escape_like(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+fn from_millis(ms: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(ms).single()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{ElementContext, SessionMetadata};
+
+    #[test]
+    fn saves_and_queries_events() {
+        let started_at = Utc.with_ymd_and_hms(2024, 3, 1, 14, 0, 0).unwrap();
+        let mut workflow = RecordedWorkflow::new("afternoon");
+        workflow.tags = vec!["coding".into()];
+        workflow.meta = Some(SessionMetadata {
+            os: "macos".into(),
+            os_version: None,
+            displays: Vec::new(),
+            keyboard_layout: None,
+            started_at,
+            machine: None,
+            recorder_version: "0.1.0".into(),
+        });
+        let context = ElementContext {
+            n: Some("Send".into()),
+            a: Some("Mail".into()),
+            ..Default::default()
+        };
+        let click = EventData::Click { x: 1, y: 2, b: 0, n: 1, m: 0, c: Some(Box::new(context)) };
+        let event = |t, data| Event { t, ts: None, data };
+        workflow.events = vec![
+            event(0, EventData::App { n: "Xcode".into(), p: 1 }),
+            event(1000, EventData::Text { s: "fn invoice_total()".into() }),
+            event(60_000, EventData::App { n: "Slack".into(), p: 2 }),
+            event(61_000, EventData::Text { s: "100% done".into() }),
+            event(120_000, click),
+        ];
+
+        let store = SqliteStore::in_memory().unwrap();
+        let id = store.save(&workflow).unwrap();
+        let other = store.save(&RecordedWorkflow::new("no metadata")).unwrap();
+
+        let xcode = store.events_for_app("xcode").unwrap();
+        assert_eq!(xcode.iter().map(|e| e.index).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(xcode[1].time, Some(started_at + chrono::Duration::seconds(1)));
+        let (from, to) = (chrono::Duration::seconds(30), chrono::Duration::minutes(2));
+        let minute = store.events_between(started_at + from, started_at + to).unwrap();
+        assert_eq!(minute.iter().map(|e| e.index).collect::<Vec<_>>(), [2, 3]);
+        assert_eq!(store.search_text("INVOICE").unwrap().len(), 1);
+        // Wildcards are literal
+        assert_eq!(store.search_text("0% d").unwrap().len(), 1);
+        assert_eq!(store.search_text("%x%").unwrap().len(), 0);
+        let clicks = store.query(&EventQuery::new().kind(EventKind::Click).text("send")).unwrap();
+        assert_eq!((clicks.len(), clicks[0].app.as_deref()), (1, Some("Mail")));
+
+        let loaded = store.load(id).unwrap();
+        let json = |w: &RecordedWorkflow| serde_json::to_value(&w.events).unwrap();
+        assert_eq!(json(&loaded), json(&workflow));
+        assert_eq!(loaded.meta, workflow.meta);
+        assert_eq!(loaded.tags, workflow.tags);
+        let names: Vec<String> = store.list().unwrap().into_iter().map(|w| w.name).collect();
+        assert_eq!(names, ["afternoon", "no metadata"]);
+
+        store.delete(id).unwrap();
+        assert!(store.events_for_app("Xcode").unwrap().is_empty());
+        assert!(store.load(id).is_err());
+        assert!(store.delete(id).is_err());
+        store.delete(other).unwrap();
+    }
+}
@@ -1,75 +1,182 @@
-//! Workflow storage - JSON lines format for efficiency
+//! Workflow storage - JSON lines, or MessagePack for long recordings
+//!
+//! Both formats are a header (name, event count, session metadata) followed
+//! by one record per event, so recordings can be written and read as a
+//! stream. Loading goes by extension: `.jsonl`, or `.bbr` for MessagePack.
+//! Either can be compressed with gzip or zstd (`.jsonl.zst`); compressed
+//! files are recognized by their first bytes, whatever they're called.
+//!
+//! The header carries the [`SCHEMA_VERSION`] its events were written with.
+//! Events from older recordings are migrated as they're read, so files keep
+//! loading as [`EventData`] changes.
 
-use crate::events::{RecordedWorkflow, Event};
-use anyhow::{Context, Result};
+use crate::error::RecorderError;
+use crate::events::{Event, EventData, RecordedWorkflow, SessionMetadata};
+use crate::keys::Key;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
 pub struct WorkflowStorage {
     dir: PathBuf,
+    format: Format,
+    compression: Compression,
 }
 
 impl WorkflowStorage {
     pub fn new() -> Result<Self> {
         let home = std::env::var("HOME").context("HOME not set")?;
-        let dir = PathBuf::from(home).join(".workflow-recorder");
-        fs::create_dir_all(&dir)?;
-        Ok(Self { dir })
+        Self::with_dir(PathBuf::from(home).join(".workflow-recorder"))
     }
 
     pub fn with_dir(dir: impl AsRef<Path>) -> Result<Self> {
         let dir = dir.as_ref().to_path_buf();
         fs::create_dir_all(&dir)?;
-        Ok(Self { dir })
+        Ok(Self { dir, format: Format::default(), compression: Compression::default() })
+    }
+
+    /// Format for new recordings (`save` and `writer`)
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Compression for new recordings (`save`, `writer` and `convert`)
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
     }
 
-    /// Save workflow as JSON lines (one event per line for streaming)
+    /// Save workflow, one record per event
     pub fn save(&self, workflow: &RecordedWorkflow) -> Result<PathBuf> {
-        let ts = chrono::Utc::now().format("%Y%m%d_%H%M%S");
-        let name = sanitize(&workflow.name);
-        let filename = format!("{}_{}.jsonl", name, ts);
-        let path = self.dir.join(&filename);
+        let path = new_path(&self.dir, &workflow.name, self.format, self.compression);
+        write_workflow(&path, workflow, self.format, self.compression)?;
+        tracing::debug!(path = %path.display(), events = workflow.events.len(), "Saved recording");
+        Ok(path)
+    }
 
-        let file = File::create(&path)?;
-        let mut w = BufWriter::new(file);
+    /// Save workflow as `filename`, in the format and compression its
+    /// extension names
+    pub fn save_as(&self, workflow: &RecordedWorkflow, filename: &str) -> Result<PathBuf> {
+        let path = self.dir.join(filename);
+        let format = Format::from_path(&path)
+            .with_context(|| format!("Unknown recording format: {}", path.display()))?;
+        write_workflow(&path, workflow, format, Compression::from_path(&path))?;
+        Ok(path)
+    }
 
-        // First line: metadata
-        writeln!(w, r#"{{"name":"{}","events":{}}}"#, workflow.name, workflow.events.len())?;
+    /// Start writing `workflow` incrementally; events are appended to a
+    /// `.partial` file that becomes a regular recording on
+    /// [`WorkflowWriter::finish`]
+    pub fn writer(&self, workflow: &RecordedWorkflow) -> Result<WorkflowWriter> {
+        WorkflowWriter::create(
+            &self.dir,
+            Header {
+                events: None,
+                duration: None,
+                apps: Vec::new(),
+                ..Header::new(workflow)
+            },
+            self.format,
+            self.compression,
+        )
+    }
 
-        // Remaining lines: events
-        for e in &workflow.events {
-            serde_json::to_writer(&mut w, e)?;
-            writeln!(w)?;
+    /// Finalize `.partial` files left behind by recordings that crashed or
+    /// were killed, keeping every event that was written intact. Files
+    /// touched in the last minute are assumed to be still recording and
+    /// skipped. Returns the recovered recordings.
+    pub fn recover(&self) -> Result<Vec<PathBuf>> {
+        let mut recovered = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let partial = entry?.path();
+            let Some(path) = partial.to_str().and_then(|p| p.strip_suffix(".partial")) else {
+                continue;
+            };
+            let recent = fs::metadata(&partial)?
+                .modified()?
+                .elapsed()
+                .is_ok_and(|age| age < RECOVER_MIN_AGE);
+            if recent {
+                continue;
+            }
+            match recover_partial(&partial, Path::new(path)) {
+                Ok(()) => recovered.push(PathBuf::from(path)),
+                Err(e) => {
+                    tracing::warn!(path = %partial.display(), error = %e, "Can't recover recording")
+                }
+            }
         }
-
-        w.flush()?;
-        Ok(path)
+        recovered.sort();
+        Ok(recovered)
     }
 
-    /// Load workflow from JSON lines
+    /// Load a workflow in either format, compressed or not
     pub fn load(&self, filename: &str) -> Result<RecordedWorkflow> {
-        let path = self.dir.join(filename);
-        let file = File::open(&path)?;
-        let reader = BufReader::new(file);
-        let mut lines = reader.lines();
+        read_workflow(&self.dir.join(filename))
+    }
 
-        // First line: metadata
-        let meta_line = lines.next().context("Empty file")??;
-        let meta: serde_json::Value = serde_json::from_str(&meta_line)?;
-        let name = meta["name"].as_str().unwrap_or("unknown").to_string();
+    /// Read a workflow's events one at a time instead of all at once, for
+    /// recordings too big to load
+    pub fn iter(&self, filename: &str) -> Result<EventReader> {
+        EventReader::open(&self.dir.join(filename))
+    }
 
-        // Remaining lines: events
-        let mut events = Vec::new();
-        for line in lines {
-            let line = line?;
-            if !line.is_empty() {
-                let e: Event = serde_json::from_str(&line)?;
-                events.push(e);
-            }
+    /// Write a copy of a recording in `format`, compressed like new
+    /// recordings, next to it with the matching extension; returns the
+    /// copy's path
+    pub fn convert(&self, filename: &str, format: Format) -> Result<PathBuf> {
+        let path = self.dir.join(filename);
+        let compression = Compression::from_path(&path);
+        let stem = filename.strip_suffix(compression.suffix()).unwrap_or(filename);
+        let stem = stem.rsplit_once('.').map_or(stem, |(stem, _)| stem);
+        let out = self.dir.join(format!(
+            "{}.{}{}",
+            stem,
+            format.extension(),
+            self.compression.suffix()
+        ));
+        if out == path {
+            bail!("{} is already {}{}", filename, format.extension(), self.compression.suffix());
         }
+        write_workflow(&out, &read_workflow(&path)?, format, self.compression)?;
+        Ok(out)
+    }
 
-        Ok(RecordedWorkflow { name, events })
+    /// What a recording's header says about it, without reading its events
+    pub fn info(&self, filename: &str) -> Result<WorkflowInfo> {
+        let path = self.dir.join(filename);
+        let format = Format::from_path(&path)
+            .with_context(|| format!("Unknown recording format: {}", path.display()))?;
+        let header = format.read_header(&mut open(&path)?)?;
+        Ok(WorkflowInfo {
+            file: filename.to_string(),
+            started_at: header.meta.map(|m| m.started_at),
+            name: header.name,
+            description: header.description,
+            tags: header.tags,
+            apps: header.apps,
+            duration: header.duration,
+            events: header.events,
+        })
+    }
+
+    /// Recordings matching `query`, by file name. Only headers are read;
+    /// files that can't be are left out.
+    pub fn search(&self, query: &WorkflowQuery) -> Result<Vec<WorkflowInfo>> {
+        let found = self
+            .list()?
+            .iter()
+            .filter_map(|file| self.info(file).ok())
+            .filter(|info| query.matches(info))
+            .collect();
+        Ok(found)
     }
 
     /// List all workflows
@@ -79,7 +186,7 @@ impl WorkflowStorage {
             let entry = entry?;
             let name = entry.file_name();
             if let Some(s) = name.to_str() {
-                if s.ends_with(".jsonl") {
+                if Format::from_path(Path::new(s)).is_some() {
                     files.push(s.to_string());
                 }
             }
@@ -94,13 +201,1303 @@ impl WorkflowStorage {
         Ok(())
     }
 
+    /// Delete the recordings `policy` doesn't keep, oldest first, with the
+    /// screenshots, clipboard files and videos they refer to; then the
+    /// assets no recording refers to that are too old or over the quota.
+    /// Recordings still being written, and what they refer to, aren't
+    /// touched.
+    pub fn prune(&self, policy: &RetentionPolicy) -> Result<Pruned> {
+        let mut files: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+        let mut partials = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if Format::from_path(&path).is_some() {
+                let meta = entry.metadata()?;
+                files.push((meta.modified()?, meta.len(), path));
+            } else if path.extension().is_some_and(|ext| ext == "partial") {
+                partials.push(path);
+            }
+        }
+        files.sort();
+        let mut assets = self.assets()?;
+        let now = SystemTime::now();
+        let expired = |modified: SystemTime| {
+            let age = now.duration_since(modified);
+            policy.max_age.is_some_and(|max| age.is_ok_and(|age| age > max))
+        };
+        let max_bytes = policy.max_total_bytes.unwrap_or(u64::MAX);
+        let asset_bytes: u64 = assets.values().map(|(_, len)| len).sum();
+        let mut pruned = Pruned {
+            deleted: Vec::new(),
+            assets: 0,
+            kept: files.len(),
+            bytes: files.iter().map(|(_, len, _)| len).sum::<u64>() + asset_bytes,
+        };
+        let mut kept = partials;
+        for (i, (modified, len, path)) in files.iter().enumerate() {
+            if !expired(*modified) && pruned.bytes <= max_bytes {
+                kept.extend(files[i..].iter().map(|(_, _, path)| path.clone()));
+                break;
+            }
+            let refers_to = self.assets_of(path);
+            match fs::remove_file(path) {
+                Ok(()) => {
+                    pruned.bytes -= len;
+                    pruned.kept -= 1;
+                    pruned.deleted.push(path.clone());
+                    for asset in refers_to {
+                        if let Some((_, len)) = assets.remove(&asset) {
+                            pruned.delete_asset(&asset, len);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(path = %path.display(), error = %e, "Can't delete recording");
+                    kept.push(path.clone());
+                }
+            }
+        }
+
+        // Left over: assets of the recordings kept, and ones whose recording
+        // is gone (or never got written)
+        let mut assets: Vec<_> =
+            assets.into_iter().map(|(path, (modified, len))| (modified, len, path)).collect();
+        assets.sort();
+        let mut in_use: Option<HashSet<PathBuf>> = None;
+        for (modified, len, path) in assets {
+            if !expired(modified) && pruned.bytes <= max_bytes {
+                break;
+            }
+            // Captured for a recording that hasn't written its event yet
+            if now.duration_since(modified).is_ok_and(|age| age < RECOVER_MIN_AGE) {
+                continue;
+            }
+            let in_use = in_use
+                .get_or_insert_with(|| kept.iter().flat_map(|path| self.assets_of(path)).collect());
+            if !in_use.contains(&path) {
+                pruned.delete_asset(&path, len);
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Files in the asset directories, with when they were last written
+    /// and their size
+    fn assets(&self) -> Result<HashMap<PathBuf, (SystemTime, u64)>> {
+        let mut assets = HashMap::new();
+        for dir in ASSET_DIRS {
+            let entries = match fs::read_dir(self.dir.join(dir)) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for entry in entries {
+                let entry = entry?;
+                let meta = entry.metadata()?;
+                if meta.is_file() {
+                    assets.insert(entry.path(), (meta.modified()?, meta.len()));
+                }
+            }
+        }
+        Ok(assets)
+    }
+
+    /// Files in the asset directories that the recording at `path` (or the
+    /// `.partial` one being written there) refers to. Reading stops at the
+    /// first event that doesn't parse; a recording that can't be read at
+    /// all refers to nothing.
+    fn assets_of(&self, path: &Path) -> Vec<PathBuf> {
+        let recording =
+            path.to_str().and_then(|p| p.strip_suffix(".partial")).map_or(path, Path::new);
+        let Some(format) = Format::from_path(recording) else {
+            return Vec::new();
+        };
+        let Ok(reader) = open(path).and_then(|r| EventReader::new(r, format)) else {
+            return Vec::new();
+        };
+        let dirs: Vec<PathBuf> = ASSET_DIRS.iter().map(|dir| self.dir.join(dir)).collect();
+        reader
+            .map_while(Result::ok)
+            .filter_map(|event| match event.data {
+                EventData::Screenshot { f }
+                | EventData::Video { f, .. }
+                | EventData::Paste { f: Some(f), .. } => Some(self.dir.join(f)),
+                _ => None,
+            })
+            .filter(|asset| dirs.iter().any(|dir| asset.starts_with(dir)))
+            .collect()
+    }
+
     pub fn path(&self) -> &Path {
         &self.dir
     }
 }
 
+/// Which recordings [`WorkflowStorage::prune`] keeps; the default keeps
+/// everything
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Delete recordings last written longer ago than this
+    pub max_age: Option<Duration>,
+    /// Then delete the oldest until the rest, with their assets, take up at
+    /// most this many bytes
+    pub max_total_bytes: Option<u64>,
+}
+
+impl RetentionPolicy {
+    pub fn keep_days(days: u64) -> Self {
+        Self { max_age: Some(Duration::from_secs(days * 24 * 60 * 60)), ..Self::default() }
+    }
+}
+
+/// What [`WorkflowStorage::prune`] did
+#[derive(Debug, Clone, Default)]
+pub struct Pruned {
+    pub deleted: Vec<PathBuf>,
+    /// Screenshots, clipboard files and videos deleted
+    pub assets: usize,
+    /// Recordings left, and the size of them and the assets left
+    pub kept: usize,
+    pub bytes: u64,
+}
+
+impl Pruned {
+    fn delete_asset(&mut self, path: &Path, len: u64) {
+        match fs::remove_file(path) {
+            Ok(()) => {
+                self.bytes -= len;
+                self.assets += 1;
+            }
+            Err(e) => tracing::warn!(path = %path.display(), error = %e, "Can't delete asset"),
+        }
+    }
+}
+
+/// How a recording is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// `.jsonl`: a JSON header line, then one JSON event per line
+    #[default]
+    Jsonl,
+    /// `.bbr`: [`MAGIC`], then MessagePack values, header first. Around half
+    /// the size of JSON lines, for all-day recording.
+    MessagePack,
+}
+
+/// Start of a `.bbr` file; the last byte is the format version
+pub const MAGIC: &[u8; 4] = b"BBR\x01";
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Jsonl => "jsonl",
+            Format::MessagePack => "bbr",
+        }
+    }
+
+    /// From a recording's extension, before any compression suffix (not a
+    /// `.partial` one)
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let name = name.strip_suffix(Compression::from_path(path).suffix()).unwrap_or(name);
+        match name.rsplit_once('.')?.1 {
+            "jsonl" => Some(Format::Jsonl),
+            "bbr" => Some(Format::MessagePack),
+            _ => None,
+        }
+    }
+
+    fn write_header(self, w: &mut impl Write, header: &Header) -> Result<()> {
+        match self {
+            Format::Jsonl => {
+                serde_json::to_writer(&mut *w, header)?;
+                writeln!(w)?;
+            }
+            Format::MessagePack => {
+                w.write_all(MAGIC)?;
+                write_msgpack(w, header)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_event(self, w: &mut impl Write, event: &Event) -> Result<()> {
+        match self {
+            Format::Jsonl => {
+                serde_json::to_writer(&mut *w, event)?;
+                writeln!(w)?;
+            }
+            Format::MessagePack => write_msgpack(w, event)?,
+        }
+        Ok(())
+    }
+
+    /// Read the header; the events follow in `r`
+    fn read_header(self, r: &mut impl BufRead) -> Result<Header> {
+        match self {
+            Format::Jsonl => {
+                let mut line = String::new();
+                if r.read_line(&mut line)? == 0 {
+                    bail!("Empty file");
+                }
+                let header: serde_json::Value = serde_json::from_str(&line)?;
+                Ok(Header {
+                    schema_version: field(&header, "schema_version").unwrap_or(0),
+                    name: header["name"].as_str().unwrap_or("unknown").to_string(),
+                    events: header["events"].as_u64().map(|n| n as usize),
+                    meta: field(&header, "meta"),
+                    description: field(&header, "description"),
+                    tags: field(&header, "tags").unwrap_or_default(),
+                    duration: field(&header, "duration"),
+                    apps: field(&header, "apps").unwrap_or_default(),
+                    legacy_os: None,
+                })
+            }
+            Format::MessagePack => {
+                let mut magic = [0; 4];
+                r.read_exact(&mut magic).context("Empty file")?;
+                if &magic != MAGIC {
+                    let message = "Not a bigbrother recording, or a newer version";
+                    return Err(RecorderError::InvalidRecording(message.into()).into());
+                }
+                Ok(rmp_serde::from_read(r)?)
+            }
+        }
+    }
+
+    /// The next record, `None` at the end
+    fn read_record<T: DeserializeOwned>(self, r: &mut impl BufRead) -> Result<Option<T>> {
+        match self {
+            Format::Jsonl => {
+                let mut line = Vec::new();
+                while line.trim_ascii().is_empty() {
+                    line.clear();
+                    if r.read_until(b'\n', &mut line)? == 0 {
+                        return Ok(None);
+                    }
+                }
+                Ok(Some(serde_json::from_slice(&line)?))
+            }
+            Format::MessagePack => {
+                if r.fill_buf()?.is_empty() {
+                    return Ok(None);
+                }
+                Ok(Some(rmp_serde::from_read(r)?))
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jsonl" | "json" => Ok(Format::Jsonl),
+            "bbr" | "msgpack" | "messagepack" => Ok(Format::MessagePack),
+            other => Err(format!("unknown format: {} (jsonl or bbr)", other)),
+        }
+    }
+}
+
+/// How a recording file is compressed, on top of its [`Format`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// `.gz`
+    Gzip,
+    /// `.zst`: smaller than gzip and faster both ways. Move-heavy JSON lines
+    /// shrink 10 to 20 times.
+    Zstd,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+impl Compression {
+    /// Goes after the format's extension: `foo.jsonl.zst`
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    /// From a recording's name (not a `.partial` one)
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    /// From the first bytes of a file
+    fn detect(head: &[u8]) -> Self {
+        if head.starts_with(ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if head.starts_with(GZIP_MAGIC) {
+            Compression::Gzip
+        } else {
+            Compression::None
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" | "off" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            other => Err(format!("unknown compression: {} (none, gzip or zstd)", other)),
+        }
+    }
+}
+
+/// A recording file being written, through an encoder when compressed
+enum Sink {
+    Plain(BufWriter<File>),
+    Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Sink {
+    fn create(path: &Path, compression: Compression) -> Result<Self> {
+        let file = File::create(path).map_err(|e| RecorderError::storage(path, e))?;
+        let w = BufWriter::new(file);
+        Ok(match compression {
+            Compression::None => Sink::Plain(w),
+            Compression::Gzip => {
+                Sink::Gzip(flate2::write::GzEncoder::new(w, flate2::Compression::default()))
+            }
+            Compression::Zstd => Sink::Zstd(zstd::Encoder::new(w, 0)?),
+        })
+    }
+
+    /// End the compressed stream and write everything out
+    fn finish(self) -> Result<()> {
+        let mut w = match self {
+            Sink::Plain(w) => w,
+            Sink::Gzip(w) => w.finish()?,
+            Sink::Zstd(w) => w.finish()?,
+        };
+        w.flush()?;
+        w.get_ref().sync_all()?;
+        Ok(())
+    }
+}
+
+/// Write `path` through a temporary file that's renamed into place, so a
+/// crash never leaves half a recording under its name
+fn write_atomic(
+    path: &Path,
+    compression: Compression,
+    write: impl FnOnce(&mut Sink) -> Result<()>,
+) -> Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let written = Sink::create(&tmp, compression).and_then(|mut w| {
+        write(&mut w)?;
+        w.finish()
+    });
+    match written.and_then(|()| Ok(fs::rename(&tmp, path)?)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
+/// Flushing ends a compressed block, so what's been flushed can be read back
+/// from an unfinished file
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Plain(w) => w.write(buf),
+            Sink::Gzip(w) => w.write(buf),
+            Sink::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(w) => w.flush(),
+            Sink::Gzip(w) => w.flush(),
+            Sink::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+/// Open a recording (or a partial one) for reading, decompressing it if its
+/// first bytes say it's compressed
+fn open(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path).map_err(|e| RecorderError::storage(path, e))?;
+    let mut r = BufReader::new(file);
+    Ok(match Compression::detect(r.fill_buf()?) {
+        Compression::None => Box::new(r),
+        Compression::Gzip => Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(r))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(r)?)),
+    })
+}
+
+/// Structs as maps: fields are skipped when empty, so positions can't be
+/// relied on
+fn write_msgpack(w: &mut impl Write, value: &impl Serialize) -> Result<()> {
+    value.serialize(&mut rmp_serde::Serializer::new(w).with_struct_map())?;
+    Ok(())
+}
+
+/// A header field that's missing or doesn't parse is left out, so older
+/// and hand-edited recordings still load
+fn field<T: DeserializeOwned>(header: &serde_json::Value, key: &str) -> Option<T> {
+    serde_json::from_value(header.get(key)?.clone()).ok()
+}
+
+/// Version of the event records written by this build. Bump it along with
+/// a [`MIGRATIONS`] entry when a change to [`EventData`] would keep older
+/// recordings from parsing or change what they mean.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// `MIGRATIONS[v]` brings an event from schema version `v` to `v + 1`
+const MIGRATIONS: &[fn(&mut serde_json::Value, &Header)] = &[portable_keys];
+
+const _: () = assert!(MIGRATIONS.len() == SCHEMA_VERSION as usize);
+
+/// 0 to 1: recordings made before keys were portable hold the recording
+/// platform's raw keycodes; the ones with a name get it
+fn portable_keys(event: &mut serde_json::Value, header: &Header) {
+    let from_code = match header.os() {
+        Some("macos") => Key::from_macos,
+        Some("windows") => Key::from_windows,
+        Some("linux") => Key::from_evdev,
+        _ => return,
+    };
+    if !matches!(event["e"].as_str(), Some("k" | "r")) {
+        return;
+    }
+    let Some(code) = event["k"].as_u64().and_then(|c| u16::try_from(c).ok()) else {
+        return;
+    };
+    if let Some(name) = from_code(code).name() {
+        event["k"] = name.into();
+    }
+}
+
+/// First record of a recording. `events`, `duration` and `apps` are only
+/// known once it's been written.
+#[derive(Clone, Serialize, Deserialize)]
+struct Header {
+    /// [`SCHEMA_VERSION`] of the events; 0 for recordings from before it
+    /// was kept
+    #[serde(default)]
+    schema_version: u32,
+    name: String,
+    events: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    meta: Option<SessionMetadata>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Milliseconds from the first event to the last
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration: Option<u64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    apps: Vec<String>,
+    /// OS of a schema 0 recording without `meta`, guessed from its events
+    /// by [`EventReader`]
+    #[serde(skip)]
+    legacy_os: Option<&'static str>,
+}
+
+impl Header {
+    /// Header of all of `workflow`
+    fn new(workflow: &RecordedWorkflow) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            name: workflow.name.clone(),
+            events: Some(workflow.events.len()),
+            meta: workflow.meta.clone(),
+            description: workflow.description.clone(),
+            tags: workflow.tags.clone(),
+            duration: Some(workflow.duration()),
+            apps: workflow.apps(),
+            legacy_os: None,
+        }
+    }
+
+    /// OS the recording was made on, if known
+    fn os(&self) -> Option<&str> {
+        self.meta.as_ref().map(|m| m.os.as_str()).or(self.legacy_os)
+    }
+
+    fn into_workflow(self, events: Vec<Event>) -> RecordedWorkflow {
+        RecordedWorkflow {
+            name: self.name,
+            events,
+            meta: self.meta,
+            description: self.description,
+            tags: self.tags,
+        }
+    }
+}
+
+/// What a recording's header says about it, from [`WorkflowStorage::info`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkflowInfo {
+    /// File name in the storage directory
+    pub file: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    /// Apps switched to, in order. Empty for recordings saved before apps
+    /// were kept in the header, as is `duration`.
+    pub apps: Vec<String>,
+    /// Milliseconds from the first event to the last
+    pub duration: Option<u64>,
+    pub events: Option<usize>,
+    pub started_at: Option<DateTime<Utc>>,
+}
+
+/// Which recordings [`WorkflowStorage::search`] returns; every condition
+/// set has to match, ignoring case
+#[derive(Debug, Clone, Default)]
+pub struct WorkflowQuery {
+    tags: Vec<String>,
+    app: Option<String>,
+    text: Option<String>,
+}
+
+impl WorkflowQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Recordings tagged `tag`; call again to require more tags
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Recordings that switched to `app`
+    pub fn app(mut self, app: impl Into<String>) -> Self {
+        self.app = Some(app.into());
+        self
+    }
+
+    /// Recordings with `text` in their name, description or file name
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    pub fn matches(&self, info: &WorkflowInfo) -> bool {
+        let has = |list: &[String], wanted: &str| {
+            list.iter().any(|s| s.eq_ignore_ascii_case(wanted))
+        };
+        let text = self.text.as_ref().map(|t| t.to_lowercase());
+        let contains = |s: &str| text.as_ref().is_some_and(|t| s.to_lowercase().contains(t));
+        self.tags.iter().all(|tag| has(&info.tags, tag))
+            && self.app.as_ref().is_none_or(|app| has(&info.apps, app))
+            && (text.is_none()
+                || contains(&info.name)
+                || contains(&info.file)
+                || info.description.as_deref().is_some_and(contains))
+    }
+}
+
+fn write_workflow(
+    path: &Path,
+    workflow: &RecordedWorkflow,
+    format: Format,
+    compression: Compression,
+) -> Result<()> {
+    write_atomic(path, compression, |w| {
+        format.write_header(w, &Header::new(workflow))?;
+        for e in &workflow.events {
+            format.write_event(w, e)?;
+        }
+        Ok(())
+    })
+}
+
+/// Corrupt events are skipped and logged
+fn read_workflow(path: &Path) -> Result<RecordedWorkflow> {
+    let mut reader = EventReader::open(path)?;
+    let mut events = Vec::with_capacity(reader.header.events.unwrap_or(0));
+    for event in &mut reader {
+        match event {
+            Ok(event) => events.push(event),
+            Err(e) => {
+                let error = format!("{:#}", e);
+                tracing::warn!(path = %path.display(), error, "Skipping event")
+            }
+        }
+    }
+    Ok(reader.header.into_workflow(events))
+}
+
+/// A recording's events, read as they're asked for, from
+/// [`WorkflowStorage::iter`], migrated to [`SCHEMA_VERSION`]. An event that
+/// was read whole but doesn't parse is an error and reading goes on with the
+/// next; any other error is the last item.
+pub struct EventReader {
+    r: Box<dyn BufRead>,
+    format: Format,
+    header: Header,
+    /// Records read, for error messages
+    read: usize,
+    done: bool,
+    /// Records read ahead to tell a legacy recording's OS, still to be
+    /// returned; reading stops at the first error, which comes last
+    ahead: VecDeque<Result<serde_json::Value>>,
+}
+
+impl EventReader {
+    fn open(path: &Path) -> Result<Self> {
+        let format = Format::from_path(path)
+            .with_context(|| format!("Unknown recording format: {}", path.display()))?;
+        Self::new(open(path)?, format)
+    }
+
+    fn new(mut r: Box<dyn BufRead>, format: Format) -> Result<Self> {
+        let header = format.read_header(&mut r)?;
+        if header.schema_version > SCHEMA_VERSION {
+            let message = format!(
+                "Recording is schema version {}, this build reads up to {}; upgrade bigbrother",
+                header.schema_version, SCHEMA_VERSION
+            );
+            return Err(RecorderError::InvalidRecording(message).into());
+        }
+        let mut reader = Self { r, format, header, read: 0, done: false, ahead: VecDeque::new() };
+        if reader.header.schema_version == 0 && reader.header.meta.is_none() {
+            reader.guess_os();
+        }
+        Ok(reader)
+    }
+
+    /// Recordings from before session metadata don't say where they were
+    /// made. Only the macOS and Windows recorders wrote them, and Windows
+    /// names apps by their executable, so read on to the first app named
+    /// and take macOS unless it's an `.exe`.
+    fn guess_os(&mut self) {
+        let mut os = None;
+        while os.is_none() {
+            let record = match self.format.read_record::<serde_json::Value>(&mut self.r) {
+                Ok(Some(record)) => record,
+                Ok(None) => break,
+                Err(e) => {
+                    self.ahead.push_back(Err(e));
+                    break;
+                }
+            };
+            let app = match record["e"].as_str() {
+                Some("a") => record["n"].as_str(),
+                Some("w") => record["a"].as_str(),
+                _ => None,
+            };
+            os = app.map(|app| {
+                let exe = app.to_ascii_lowercase().ends_with(".exe");
+                if exe { "windows" } else { "macos" }
+            });
+            self.ahead.push_back(Ok(record));
+        }
+        self.header.legacy_os = Some(os.unwrap_or("macos"));
+    }
+
+    /// The next event, migrated if the recording is older than this build
+    fn read_event(&mut self) -> Result<Option<Event>> {
+        let version = self.header.schema_version as usize;
+        if version == MIGRATIONS.len() {
+            return self.format.read_record(&mut self.r);
+        }
+        let record = match self.ahead.pop_front() {
+            Some(record) => Some(record?),
+            None => self.format.read_record(&mut self.r)?,
+        };
+        let Some(mut event) = record else {
+            return Ok(None);
+        };
+        for migrate in &MIGRATIONS[version..] {
+            migrate(&mut event, &self.header);
+        }
+        Ok(Some(serde_json::from_value(event)?))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.header.name
+    }
+
+    /// Event count from the header
+    pub fn len_hint(&self) -> Option<usize> {
+        self.header.events
+    }
+
+    pub fn meta(&self) -> Option<&SessionMetadata> {
+        self.header.meta.as_ref()
+    }
+}
+
+impl Iterator for EventReader {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.read_event().transpose()?;
+        self.read += 1;
+        // JSON errors come from a whole line, or a migrated record, and don't
+        // spoil the next; a bad MessagePack record or a read error leaves
+        // nothing to resync on
+        let parsed_whole = |e: &anyhow::Error| e.downcast_ref::<serde_json::Error>().is_some();
+        self.done = next.as_ref().is_err_and(|e| !parsed_whole(e));
+        Some(next.with_context(|| format!("Corrupt event {}", self.read)))
+    }
+}
+
+impl std::iter::FusedIterator for EventReader {}
+
+/// Appends a recording to disk as it happens, so long sessions don't have
+/// to fit in memory
+pub struct WorkflowWriter {
+    /// Final location
+    path: PathBuf,
+    /// Where events go until `finish`
+    partial: PathBuf,
+    w: Sink,
+    format: Format,
+    compression: Compression,
+    /// What's known at the start; the rest is filled in by `finish`
+    header: Header,
+    apps: Vec<String>,
+    /// Times of the first and last events
+    span: Option<(u64, u64)>,
+    count: usize,
+}
+
+impl WorkflowWriter {
+    fn create(
+        dir: &Path,
+        header: Header,
+        format: Format,
+        compression: Compression,
+    ) -> Result<Self> {
+        let path = new_path(dir, &header.name, format, compression);
+        let partial = partial_path(&path);
+        let mut w = Sink::create(&partial, compression)?;
+        format.write_header(&mut w, &header)?;
+        w.flush()?;
+        Ok(Self {
+            path,
+            partial,
+            w,
+            format,
+            compression,
+            header,
+            apps: Vec::new(),
+            span: None,
+            count: 0,
+        })
+    }
+
+    pub fn append(&mut self, event: &Event) -> Result<()> {
+        self.format.write_event(&mut self.w, event)?;
+        self.count += 1;
+        self.span = Some(self.span.map_or((event.t, event.t), |(first, _)| (first, event.t)));
+        if let EventData::App { n, .. } = &event.data {
+            if !self.apps.contains(n) {
+                self.apps.push(n.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// Push buffered events to the file
+    pub fn flush(&mut self) -> Result<()> {
+        self.w.flush()?;
+        Ok(())
+    }
+
+    /// Events written so far
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// The `.partial` file being written
+    pub fn partial_path(&self) -> &Path {
+        &self.partial
+    }
+
+    /// Write the final header and move the events under it; returns the
+    /// recording's path
+    pub fn finish(self) -> Result<PathBuf> {
+        self.w.finish()?;
+        let header = Header {
+            events: Some(self.count),
+            duration: Some(self.span.map_or(0, |(first, last)| last.saturating_sub(first))),
+            apps: self.apps,
+            ..self.header
+        };
+        finalize(&self.partial, &self.path, &header, self.format, self.compression)?;
+        Ok(self.path)
+    }
+
+    /// Finish this recording and carry on in a new one with the same name,
+    /// session metadata, description and tags; returns the finished
+    /// recording's path
+    pub fn rotate(&mut self) -> Result<PathBuf> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        let next = Self::create(dir, self.header.clone(), self.format, self.compression)?;
+        std::mem::replace(self, next).finish()
+    }
+}
+
+/// `<dir>/<name>_<timestamp>.<ext>`, numbered when that's taken (several
+/// recordings in the same second)
+fn new_path(dir: &Path, name: &str, format: Format, compression: Compression) -> PathBuf {
+    let stem = format!("{}_{}", sanitize(name), chrono::Utc::now().format("%Y%m%d_%H%M%S"));
+    let ext = format!("{}{}", format.extension(), compression.suffix());
+    let mut path = dir.join(format!("{}.{}", stem, ext));
+    let mut n = 1;
+    while path.exists() || partial_path(&path).exists() {
+        n += 1;
+        path = dir.join(format!("{}_{}.{}", stem, n, ext));
+    }
+    path
+}
+
+/// `foo.jsonl` -> `foo.jsonl.partial`
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".partial");
+    PathBuf::from(name)
+}
+
+/// Copy the events of a partial file under a complete header at `path`,
+/// then remove the partial file
+fn finalize(
+    partial: &Path,
+    path: &Path,
+    header: &Header,
+    format: Format,
+    compression: Compression,
+) -> Result<()> {
+    let mut r = open(partial)?;
+    format.read_header(&mut r)?;
+    write_atomic(path, compression, |w| {
+        format.write_header(w, header)?;
+        io::copy(&mut r, w)?;
+        Ok(())
+    })?;
+    fs::remove_file(partial)?;
+    Ok(())
+}
+
+/// Where the recorder keeps screenshots, clipboard files and videos by
+/// default, next to the recordings
+const ASSET_DIRS: [&str; 3] = ["screenshots", "clipboard", "videos"];
+
+/// Partial files younger than this may still be written to
+const RECOVER_MIN_AGE: Duration = Duration::from_secs(60);
+
+/// Finalize a partial file whose writer is gone. A crash can cut the last
+/// event short (or a compressed block), so reading stops at the first event
+/// that doesn't parse.
+fn recover_partial(partial: &Path, path: &Path) -> Result<()> {
+    let format = Format::from_path(path).context("Unknown recording format")?;
+    let mut reader = EventReader::new(open(partial)?, format)?;
+    let events = reader.by_ref().map_while(Result::ok).collect();
+    let workflow = reader.header.into_workflow(events);
+    write_workflow(path, &workflow, format, Compression::from_path(path))?;
+    fs::remove_file(partial)?;
+    Ok(())
+}
+
 fn sanitize(s: &str) -> String {
     s.chars()
         .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::EventData;
+
+    #[test]
+    fn writer_finalizes_header() {
+        let dir = std::env::temp_dir().join(format!("bb-storage-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap();
+        let workflow = RecordedWorkflow::new("stream \"test\"");
+
+        let mut writer = storage.writer(&workflow).unwrap();
+        for t in 0..3 {
+            writer.append(&Event { t, ts: None, data: EventData::Text { s: "hi".into() } }).unwrap();
+        }
+        let partial = writer.partial_path().to_path_buf();
+        let path = writer.finish().unwrap();
+        assert!(!partial.exists());
+
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let loaded = storage.load(name).unwrap();
+        assert_eq!(loaded.name, workflow.name);
+        assert_eq!(loaded.events.len(), 3);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rotate_starts_a_new_recording() {
+        let dir = std::env::temp_dir().join(format!("bb-rotate-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap();
+        let text = |t| Event { t, ts: None, data: EventData::Text { s: "hi".into() } };
+
+        let mut writer = storage.writer(&RecordedWorkflow::new("day")).unwrap();
+        writer.append(&text(0)).unwrap();
+        let first = writer.rotate().unwrap();
+        writer.append(&text(1)).unwrap();
+        writer.append(&text(2)).unwrap();
+        let second = writer.finish().unwrap();
+
+        // Within the same second the new file gets a number
+        assert_ne!(first, second);
+        let load = |p: &Path| storage.load(p.file_name().unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(load(&first).events.len(), 1);
+        assert_eq!(load(&second).events.len(), 2);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn recovers_truncated_partial() {
+        let dir = std::env::temp_dir().join(format!("bb-recover-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap();
+        let partial = dir.join("crashed_20240101_000000.jsonl.partial");
+        fs::write(
+            &partial,
+            "{\"name\":\"crashed\",\"events\":null}\n{\"t\":0,\"e\":\"t\",\"s\":\"a\"}\n{\"t\":5,\"e\":\"t\",\"s",
+        )
+        .unwrap();
+        // Old enough not to look like a live recording
+        let old = std::time::SystemTime::now() - RECOVER_MIN_AGE * 2;
+        File::options().write(true).open(&partial).unwrap().set_modified(old).unwrap();
+
+        let recovered = storage.recover().unwrap();
+        assert_eq!(recovered, [dir.join("crashed_20240101_000000.jsonl")]);
+        let loaded = storage.load("crashed_20240101_000000.jsonl").unwrap();
+        assert_eq!(loaded.events.len(), 1);
+        assert!(!partial.exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn messagepack_roundtrip_and_convert() {
+        let dir = std::env::temp_dir().join(format!("bb-msgpack-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap().format(Format::MessagePack);
+        let mut workflow = RecordedWorkflow::new("compact");
+        let context = crate::events::ElementContext {
+            s: "role:AXButton".into(),
+            r: "AXButton".into(),
+            b: Some((1, 2, 3, 4)),
+            ..Default::default()
+        };
+        let click = EventData::Click { x: 5, y: -6, b: 1, n: 2, m: 0, c: Some(Box::new(context)) };
+        let paste = EventData::Paste { o: 'p', s: "é".into(), k: None, m: None, l: 2, f: None };
+        workflow.events = vec![
+            Event { t: 0, ts: None, data: click },
+            Event { t: 7, ts: Some(1_700_000_000_000), data: EventData::Move { x: 1, y: 2 } },
+            Event { t: 9, ts: None, data: paste },
+            Event { t: 12, ts: None, data: EventData::FileChanged { f: "a.txt".into(), o: 'c' } },
+        ];
+
+        let path = storage.save(&workflow).unwrap();
+        assert_eq!(path.extension().unwrap(), "bbr");
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let loaded = storage.load(name).unwrap();
+        let json = |w: &RecordedWorkflow| serde_json::to_value(&w.events).unwrap();
+        assert_eq!(loaded.name, workflow.name);
+        assert_eq!(json(&loaded), json(&workflow));
+
+        let converted = storage.convert(name, Format::Jsonl).unwrap();
+        let jsonl = storage.load(converted.file_name().unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(json(&jsonl), json(&workflow));
+        assert!(fs::metadata(&path).unwrap().len() < fs::metadata(&converted).unwrap().len());
+        assert_eq!(storage.list().unwrap().len(), 2);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn compressed_roundtrip_and_recovery() {
+        let dir = std::env::temp_dir().join(format!("bb-compressed-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap().compression(Compression::Zstd);
+        let mut workflow = RecordedWorkflow::new("day");
+        let event = |t| Event { t, ts: None, data: EventData::Move { x: 1, y: 2 } };
+        workflow.events = (0..1000).map(event).collect();
+
+        let path = storage.save(&workflow).unwrap();
+        assert!(path.to_str().unwrap().ends_with(".jsonl.zst"));
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(storage.load(name).unwrap().events.len(), 1000);
+        // Already .jsonl.zst
+        assert!(storage.convert(name, Format::Jsonl).is_err());
+        let gzip = WorkflowStorage::with_dir(&dir).unwrap().compression(Compression::Gzip);
+        let gz = gzip.convert(name, Format::MessagePack).unwrap();
+        let gz = gz.file_name().unwrap().to_str().unwrap();
+        assert!(gz.ends_with(".bbr.gz"));
+        assert_eq!(gzip.load(gz).unwrap().events.len(), 1000);
+        assert!(fs::metadata(&path).unwrap().len() < 1000);
+
+        // Flushed blocks of an unfinished file can be read back
+        let mut writer = storage.writer(&RecordedWorkflow::new("crashed")).unwrap();
+        for event in &workflow.events[..3] {
+            writer.append(event).unwrap();
+        }
+        writer.flush().unwrap();
+        let partial = writer.partial_path().to_path_buf();
+        // Killed: the compressed stream is never finished
+        drop(writer);
+        let old = std::time::SystemTime::now() - RECOVER_MIN_AGE * 2;
+        File::options().write(true).open(&partial).unwrap().set_modified(old).unwrap();
+        let recovered = storage.recover().unwrap();
+        assert_eq!(recovered.len(), 1);
+        let loaded = storage.load(recovered[0].file_name().unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(loaded.events.len(), 3);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn iterates_lazily() {
+        let dir = std::env::temp_dir().join(format!("bb-iter-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap().compression(Compression::Zstd);
+        let mut workflow = RecordedWorkflow::new("long");
+        let event = |t| Event { t, ts: None, data: EventData::Move { x: t as i32, y: 0 } };
+        workflow.events = (0..100).map(event).collect();
+        let path = storage.save(&workflow).unwrap();
+        let name = path.file_name().unwrap().to_str().unwrap();
+
+        let events = storage.iter(name).unwrap();
+        assert_eq!((events.name(), events.len_hint()), ("long", Some(100)));
+        let times: Vec<u64> = events.skip(98).map(|e| e.unwrap().t).collect();
+        assert_eq!(times, [98, 99]);
+
+        // A cut-off event is an error, and the end
+        let jsonl = dir.join("cut.jsonl");
+        let cut = "{\"name\":\"cut\",\"events\":2}\n{\"t\":0,\"e\":\"t\",\"s\":\"a\"}\n{\"t\":";
+        fs::write(&jsonl, cut).unwrap();
+        let results: Vec<bool> = storage.iter("cut.jsonl").unwrap().map(|e| e.is_ok()).collect();
+        assert_eq!(results, [true, false]);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn load_skips_corrupt_lines() {
+        let dir = std::env::temp_dir().join(format!("bb-corrupt-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap();
+        let mut workflow = RecordedWorkflow::new("say \"hi\"\n");
+        workflow.events = ["a", "b"]
+            .map(|s| Event { t: 0, ts: None, data: EventData::Text { s: s.into() } })
+            .into();
+        let path = storage.save(&workflow).unwrap();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(!dir.join(format!("{}.tmp", name)).exists());
+
+        let text = fs::read_to_string(&path).unwrap().replacen("\n", "\n{\"t\":\u{ff}\n", 1);
+        fs::write(&path, text).unwrap();
+        let loaded = storage.load(name).unwrap();
+        assert_eq!((loaded.name.as_str(), loaded.events.len()), ("say \"hi\"\n", 2));
+        let results: Vec<bool> = storage.iter(name).unwrap().map(|e| e.is_ok()).collect();
+        assert_eq!(results, [false, true, true]);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn migrates_older_schemas() {
+        let dir = std::env::temp_dir().join(format!("bb-schema-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap();
+        let old = concat!(
+            r#"{"name":"old","events":2,"meta":{"os":"macos","#,
+            r#""started_at":"2024-01-01T00:00:00Z","recorder_version":"0.1"}}"#,
+            "\n",
+            r#"{"t":0,"e":"k","k":36,"m":0}"#,
+            "\n",
+            r#"{"t":5,"e":"k","k":999,"m":0}"#,
+            "\n",
+        );
+        fs::write(dir.join("old.jsonl"), old).unwrap();
+        let key = |w: &RecordedWorkflow, i: usize| match w.events[i].data {
+            EventData::Key { k, .. } => k,
+            _ => panic!("not a key press"),
+        };
+        let loaded = storage.load("old.jsonl").unwrap();
+        assert_eq!((key(&loaded, 0), key(&loaded, 1)), (Key::Return, Key::Other(999)));
+        let bbr = storage.convert("old.jsonl", Format::MessagePack).unwrap();
+        let bbr = storage.load(bbr.file_name().unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(key(&bbr, 0), Key::Return);
+
+        // As the first recorders wrote them, with no session metadata
+        let mac = concat!(
+            r#"{"name":"mac","events":3}"#,
+            "\n",
+            r#"{"t":0,"e":"k","k":36,"m":0}"#,
+            "\n",
+            r#"{"t":3,"e":"a","n":"Safari","p":412}"#,
+            "\n",
+            r#"{"t":5,"e":"k","k":48,"m":0}"#,
+            "\n",
+        );
+        fs::write(dir.join("mac.jsonl"), mac).unwrap();
+        let loaded = storage.load("mac.jsonl").unwrap();
+        assert_eq!(loaded.events.len(), 3);
+        assert_eq!((key(&loaded, 0), key(&loaded, 2)), (Key::Return, Key::Tab));
+        let windows = concat!(
+            r#"{"name":"windows","events":3}"#,
+            "\n",
+            r#"{"t":0,"e":"k","k":13,"m":0}"#,
+            "\n",
+            r#"{"t":1,"e":"a","n":"notepad.exe","p":7100}"#,
+            "\n",
+            r#"{"t":2,"e":"w","a":"notepad.exe","w":"Untitled - Notepad"}"#,
+            "\n",
+        );
+        fs::write(dir.join("windows.jsonl"), windows).unwrap();
+        let loaded = storage.load("windows.jsonl").unwrap();
+        assert_eq!((loaded.events.len(), key(&loaded, 0)), (3, Key::Return));
+        // Nothing to tell by: taken for macOS
+        let keys = concat!(r#"{"name":"keys","events":1}"#, "\n", r#"{"t":0,"e":"k","k":36,"m":0}"#);
+        fs::write(dir.join("keys.jsonl"), keys).unwrap();
+        assert_eq!(key(&storage.load("keys.jsonl").unwrap(), 0), Key::Return);
+
+        fs::write(dir.join("new.jsonl"), r#"{"schema_version":99,"name":"new"}"#).unwrap();
+        let newer = RecorderError::from(storage.load("new.jsonl").unwrap_err());
+        assert!(matches!(newer, RecorderError::InvalidRecording(_)));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn prune_deletes_oldest_recordings() {
+        let dir = std::env::temp_dir().join(format!("bb-prune-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap();
+        let now = SystemTime::now();
+        let days = |n: u64| Duration::from_secs(n * 24 * 60 * 60);
+        let write = |name: &str, age| {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, [0u8; 100]).unwrap();
+            File::options().write(true).open(&path).unwrap().set_modified(now - age).unwrap();
+            path
+        };
+        let event = |data| Event { t: 0, ts: None, data };
+        let screenshot = |f: &Path| event(EventData::Screenshot { f: f.display().to_string() });
+
+        // The oldest recording refers to a screenshot and a clipboard file
+        let (png, txt) = (write("screenshots/b.png", days(0)), write("clipboard/b.txt", days(0)));
+        let mut b = RecordedWorkflow::new("b");
+        let f = Some(txt.display().to_string());
+        let paste = EventData::Paste { o: 'c', s: "x".into(), k: None, m: None, l: 1, f };
+        b.events = vec![screenshot(&png), event(paste)];
+        let path = storage.save_as(&b, "b.jsonl").unwrap();
+        File::options().write(true).open(&path).unwrap().set_modified(now - days(40)).unwrap();
+        for (i, name) in ["a.jsonl", "c.bbr", "d.jsonl.zst"].iter().enumerate() {
+            write(name, days(30 - 10 * i as u64));
+        }
+        // One that's still being written keeps its old screenshot
+        let live_png = write("screenshots/live.png", days(40));
+        let mut writer = storage.writer(&RecordedWorkflow::new("current")).unwrap();
+        writer.append(&screenshot(&live_png)).unwrap();
+        writer.flush().unwrap();
+        let old_mp4 = write("videos/old.mp4", days(50));
+        let new_png = write("screenshots/new.png", days(1));
+
+        let pruned = storage.prune(&RetentionPolicy::keep_days(35)).unwrap();
+        assert_eq!(pruned.deleted, [dir.join("b.jsonl")]);
+        assert_eq!(pruned.assets, 3);
+        assert!(!png.exists() && !txt.exists() && !old_mp4.exists());
+        // Three recordings and two screenshots
+        assert_eq!((pruned.kept, pruned.bytes), (3, 500));
+
+        let quota =
+            |bytes| RetentionPolicy { max_total_bytes: Some(bytes), ..RetentionPolicy::default() };
+        assert_eq!(storage.prune(&quota(350)).unwrap().kept, 1);
+        assert!(!dir.join("a.jsonl").exists() && !dir.join("c.bbr").exists());
+        assert!(dir.join("d.jsonl.zst").exists() && writer.partial_path().exists());
+        // Past the recordings, only assets nothing refers to go
+        let pruned = storage.prune(&quota(50)).unwrap();
+        assert_eq!((pruned.kept, pruned.assets, pruned.bytes), (0, 1, 100));
+        assert!(!new_png.exists() && live_png.exists());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn searches_headers() {
+        let dir = std::env::temp_dir().join(format!("bb-search-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap();
+        let event = |t, data| Event { t, ts: None, data };
+        let app = |n: &str| EventData::App { n: n.into(), p: 1 };
+
+        let mut invoices = RecordedWorkflow::new("invoices");
+        invoices.description = Some("Export last month's invoices".into());
+        invoices.tags = vec!["billing".into(), "monthly".into()];
+        invoices.events = vec![event(500, app("Google Chrome")), event(2500, app("Numbers"))];
+        storage.save(&invoices).unwrap();
+
+        // Streamed recordings get their duration and apps when finished
+        let mut standup = RecordedWorkflow::new("standup");
+        standup.tags = vec!["Daily".into()];
+        let mut writer = storage.writer(&standup).unwrap();
+        writer.append(&event(100, app("Slack"))).unwrap();
+        writer.append(&event(400, EventData::Text { s: "done".into() })).unwrap();
+        writer.append(&event(900, app("Slack"))).unwrap();
+        let path = writer.finish().unwrap();
+
+        let info = storage.info(path.file_name().unwrap().to_str().unwrap()).unwrap();
+        assert_eq!((info.duration, info.events), (Some(800), Some(3)));
+        assert_eq!(info.apps, ["Slack"]);
+        let names = |query: WorkflowQuery| -> Vec<String> {
+            storage.search(&query).unwrap().into_iter().map(|i| i.name).collect()
+        };
+        assert_eq!(names(WorkflowQuery::new()).len(), 2);
+        assert_eq!(names(WorkflowQuery::new().tag("billing").app("google chrome")), ["invoices"]);
+        assert!(names(WorkflowQuery::new().tag("billing").tag("daily")).is_empty());
+        assert_eq!(names(WorkflowQuery::new().tag("daily")), ["standup"]);
+        assert_eq!(names(WorkflowQuery::new().text("MONTH'S")), ["invoices"]);
+        let monthly = storage.search(&WorkflowQuery::new().tag("monthly")).unwrap();
+        assert_eq!(storage.load(&monthly[0].file).unwrap().description, invoices.description);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn recovers_truncated_messagepack_partial() {
+        let dir = std::env::temp_dir().join(format!("bb-recover-bbr-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap().format(Format::MessagePack);
+        let mut writer = storage.writer(&RecordedWorkflow::new("crashed")).unwrap();
+        for t in 0..3 {
+            writer.append(&Event { t, ts: None, data: EventData::Text { s: "hi".into() } }).unwrap();
+        }
+        writer.flush().unwrap();
+        let partial = writer.partial_path().to_path_buf();
+        // Killed mid-write: the last event is cut short
+        drop(writer);
+        let len = fs::metadata(&partial).unwrap().len();
+        let file = File::options().write(true).open(&partial).unwrap();
+        file.set_len(len - 2).unwrap();
+        let old = std::time::SystemTime::now() - RECOVER_MIN_AGE * 2;
+        file.set_modified(old).unwrap();
+
+        let recovered = storage.recover().unwrap();
+        assert_eq!(recovered.len(), 1);
+        let loaded = storage.load(recovered[0].file_name().unwrap().to_str().unwrap()).unwrap();
+        assert_eq!(loaded.events.len(), 2);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,123 @@
+//! Adapters for event iterators
+//!
+//! [`EventIterExt`] works on anything yielding [`Event`]s: a live
+//! [`EventStream`](crate::recorder::EventStream), a receiver's iterator or a
+//! saved workflow's events. Throttling goes by event time (`t`), so it
+//! behaves the same live and on a recording. `map`, `take_while` and friends
+//! are the plain [`Iterator`] ones.
+//!
+//! ```rust,no_run
+//! use bigbrother_recorder::prelude::*;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let stream = WorkflowRecorder::new().stream()?;
+//! let clicks_and_moves = stream
+//!     .filter_types(&[EventKind::Click, EventKind::Move])
+//!     .throttle_moves(100);
+//! for event in clicks_and_moves {
+//!     println!("{:?}", event);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::events::{Event, EventData, EventKind};
+
+pub trait EventIterExt: Iterator<Item = Event> + Sized {
+    /// Only events of these kinds
+    fn filter_types(self, kinds: &[EventKind]) -> FilterTypes<Self> {
+        FilterTypes {
+            inner: self,
+            kinds: kinds.to_vec(),
+        }
+    }
+
+    /// At most one Move per `ms`; everything else passes through
+    fn throttle_moves(self, ms: u64) -> ThrottleMoves<Self> {
+        ThrottleMoves {
+            inner: self,
+            ms,
+            last: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Event>> EventIterExt for I {}
+
+/// See [`EventIterExt::filter_types`]
+pub struct FilterTypes<I> {
+    inner: I,
+    kinds: Vec<EventKind>,
+}
+
+impl<I> FilterTypes<I> {
+    /// The wrapped iterator, e.g. to stop an [`EventStream`](crate::recorder::EventStream)
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I: Iterator<Item = Event>> Iterator for FilterTypes<I> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        self.inner.by_ref().find(|e| self.kinds.contains(&e.data.kind()))
+    }
+}
+
+/// See [`EventIterExt::throttle_moves`]
+pub struct ThrottleMoves<I> {
+    inner: I,
+    ms: u64,
+    /// `t` of the last Move let through
+    last: Option<u64>,
+}
+
+impl<I> ThrottleMoves<I> {
+    /// The wrapped iterator, e.g. to stop an [`EventStream`](crate::recorder::EventStream)
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I: Iterator<Item = Event>> Iterator for ThrottleMoves<I> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        loop {
+            let event = self.inner.next()?;
+            if let EventData::Move { .. } = event.data {
+                if self.last.is_some_and(|last| event.t.saturating_sub(last) < self.ms) {
+                    continue;
+                }
+                self.last = Some(event.t);
+            }
+            return Some(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_and_throttles() {
+        let event = |t, data| Event { t, ts: None, data };
+        let events = vec![
+            event(0, EventData::Move { x: 0, y: 0 }),
+            event(10, EventData::Move { x: 1, y: 0 }),
+            event(20, EventData::Text { s: "a".into() }),
+            event(50, EventData::Move { x: 2, y: 0 }),
+            event(120, EventData::Move { x: 3, y: 0 }),
+        ];
+
+        let kept: Vec<u64> = events
+            .into_iter()
+            .filter_types(&[EventKind::Move])
+            .throttle_moves(100)
+            .map(|e| e.t)
+            .collect();
+        assert_eq!(kept, [0, 120]);
+    }
+}
@@ -0,0 +1,316 @@
+//! A recording told in a few lines, for prompts
+//!
+//! [`Summarizer`] turns a [`RecordedWorkflow`] into a short narrative of
+//! what the user did, built on [`actions`]:
+//!
+//! ```text
+//! [Chrome] visited https://example.com → clicked TextField 'Search' → typed "quarterly report"
+//! → pressed return → [Mail] chose Message > Attach Files → clicked Button 'Send'
+//! ```
+//!
+//! Steps are marked with their app when it changes, repeats collapse
+//! ("pressed down ×5") and long text is cut. Under a token budget the least
+//! telling steps go first: scrolls, then window focus without a URL and
+//! file changes, then plain keys, drags and clicks on bare points. If that
+//! isn't enough, steps from the middle go, keeping how it started and ended.
+//!
+//! ```rust,no_run
+//! use bigbrother_recorder::prelude::*;
+//! use bigbrother_recorder::summary::Summarizer;
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let workflow = WorkflowStorage::new()?.load("invoices")?;
+//! let summary = Summarizer::new().max_tokens(200).summarize(&workflow);
+//! println!("{}", summary);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::events::RecordedWorkflow;
+use crate::semantics::{actions, Action, Target};
+use crate::text::prefix;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Rough characters per token for English text with today's tokenizers
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Typed text and clipboard previews longer than this are cut (chars)
+const DEFAULT_MAX_TEXT: usize = 80;
+
+/// Between steps in the narrative
+const ARROW: &str = " → ";
+
+/// Builds [`Summary`]s; no budget by default
+#[derive(Debug, Clone)]
+pub struct Summarizer {
+    max_tokens: Option<usize>,
+    max_text: usize,
+}
+
+/// What the user did in a recording
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub name: String,
+    pub duration_ms: u64,
+    /// Apps used, in the order they were first used
+    pub apps: Vec<String>,
+    pub steps: Vec<SummaryStep>,
+    /// Steps left out to fit the token budget
+    #[serde(default)]
+    pub omitted: usize,
+}
+
+/// One thing the user did, or the same thing done `count` times in a row
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SummaryStep {
+    pub t: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    pub text: String,
+    #[serde(default = "one", skip_serializing_if = "is_one")]
+    pub count: usize,
+}
+
+fn one() -> usize {
+    1
+}
+
+fn is_one(count: &usize) -> bool {
+    *count == 1
+}
+
+impl Summarizer {
+    pub fn new() -> Self {
+        Self { max_tokens: None, max_text: DEFAULT_MAX_TEXT }
+    }
+
+    /// Keep the narrative within about this many tokens
+    pub fn max_tokens(mut self, max: usize) -> Self {
+        self.max_tokens = Some(max);
+        self
+    }
+
+    /// Cut typed text and clipboard previews to this many characters
+    pub fn max_text(mut self, max: usize) -> Self {
+        self.max_text = max;
+        self
+    }
+
+    pub fn summarize(&self, workflow: &RecordedWorkflow) -> Summary {
+        let mut apps: Vec<String> = Vec::new();
+        let mut app: Option<String> = None;
+        // Each step with how telling it is, 0 being the first to go
+        let mut steps: Vec<(u8, SummaryStep)> = Vec::new();
+        for action in actions(workflow) {
+            match &action {
+                Action::SwitchApp { app: a, .. } | Action::FocusWindow { app: a, .. } => {
+                    app = Some(a.clone())
+                }
+                Action::Click { target: Some(Target { app: Some(a), .. }), .. } => {
+                    app = Some(a.clone())
+                }
+                _ => {}
+            }
+            if let Some(a) = app.as_ref().filter(|a| !apps.contains(a)) {
+                apps.push(a.clone());
+            }
+            let Some(text) = self.describe(&action) else {
+                continue;
+            };
+            let last = steps.last_mut().map(|(_, step)| step);
+            if let Some(last) = last.filter(|last| last.text == text && last.app == app) {
+                last.count += 1;
+                continue;
+            }
+            let step = SummaryStep { t: action.t(), app: app.clone(), text, count: 1 };
+            steps.push((priority(&action), step));
+        }
+
+        let total = steps.len();
+        let fits = |steps: &[(u8, SummaryStep)]| {
+            let text = narrative(steps.iter().map(|(_, s)| s), total - steps.len());
+            self.max_tokens.is_none_or(|max| tokens(&text) <= max)
+        };
+        for least in 0..3 {
+            if fits(&steps) {
+                break;
+            }
+            steps.retain(|(p, _)| *p > least);
+        }
+        while steps.len() > 2 && !fits(&steps) {
+            steps.remove(steps.len() / 2);
+        }
+        Summary {
+            name: workflow.name.clone(),
+            duration_ms: workflow.duration(),
+            apps,
+            omitted: total - steps.len(),
+            steps: steps.into_iter().map(|(_, step)| step).collect(),
+        }
+    }
+
+    /// The step for an action, without its app; None for what the app
+    /// markers already say
+    fn describe(&self, action: &Action) -> Option<String> {
+        let cut = |text: &str| match prefix(text, self.max_text) {
+            start if start.len() < text.len() => format!("{:?}", format!("{}…", start)),
+            _ => format!("{:?}", text),
+        };
+        let element = |target: &Target| match &target.name {
+            Some(name) => format!("{} '{}'", target.role, name),
+            None => target.role.clone(),
+        };
+        Some(match action {
+            Action::Click { button, count, target: Some(target), .. } => {
+                let verb = match (button, count) {
+                    (_, 2) => "double-clicked",
+                    (1, _) => "right-clicked",
+                    (2, _) => "middle-clicked",
+                    _ => "clicked",
+                };
+                format!("{} {}", verb, element(target))
+            }
+            Action::Type { text, target, .. } => {
+                match target.as_ref().and_then(|target| target.name.as_ref()) {
+                    Some(name) => format!("typed {} into '{}'", cut(text), name),
+                    None => format!("typed {}", cut(text)),
+                }
+            }
+            Action::Menu { path, .. } => format!("chose {}", path.join(" > ")),
+            Action::SwitchApp { .. } => return None,
+            Action::FocusWindow { url: Some(url), .. } => format!("visited {}", url),
+            Action::FocusWindow { title: Some(title), .. } => format!("opened '{}'", title),
+            Action::FocusWindow { .. } => return None,
+            Action::Scroll { .. } => "scrolled".to_string(),
+            Action::Clipboard { op, preview, .. } => {
+                let verb = match op.as_str() {
+                    "cut" => "cut",
+                    "paste" => "pasted",
+                    _ => "copied",
+                };
+                format!("{} {}", verb, cut(preview))
+            }
+            Action::File { path, op, .. } => {
+                let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+                format!("{} file {}", op, name)
+            }
+            Action::Note { text, .. } => format!("noted {}", cut(text)),
+            _ => action.to_string(),
+        })
+    }
+}
+
+impl Default for Summarizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How much a step tells about what the user did, 0 the least
+fn priority(action: &Action) -> u8 {
+    match action {
+        Action::Scroll { .. } => 0,
+        Action::FocusWindow { url: None, .. } | Action::File { .. } => 1,
+        Action::Press { keys, .. } if !keys.contains('+') => 2,
+        Action::Click { target: None, .. } | Action::Drag { .. } => 2,
+        _ => 3,
+    }
+}
+
+/// About how many tokens `text` takes
+pub fn tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+fn narrative<'a>(steps: impl Iterator<Item = &'a SummaryStep>, omitted: usize) -> String {
+    let mut out = String::new();
+    let mut app = None;
+    for step in steps {
+        if !out.is_empty() {
+            out.push_str(ARROW);
+        }
+        if step.app.is_some() && step.app != app {
+            app = step.app.clone();
+            out.push_str(&format!("[{}] ", step.app.as_deref().unwrap_or_default()));
+        }
+        out.push_str(&step.text);
+        if step.count > 1 {
+            out.push_str(&format!(" ×{}", step.count));
+        }
+    }
+    if omitted > 0 {
+        out.push_str(&format!(" ({} left out)", omitted));
+    }
+    out
+}
+
+impl Summary {
+    /// About how many tokens the narrative takes
+    pub fn tokens(&self) -> usize {
+        tokens(&self.to_string())
+    }
+}
+
+/// The narrative, steps joined by arrows
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&narrative(self.steps.iter(), self.omitted))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{ElementContext, Event, EventData};
+    use crate::keys::Key;
+
+    #[test]
+    fn tells_what_happened_within_budget() {
+        let event = |t, data| Event { t, ts: None, data };
+        let context = ElementContext {
+            r: "AXTextField".into(),
+            n: Some("Search".into()),
+            a: Some("Chrome".into()),
+            ..Default::default()
+        };
+        let click = EventData::Click { x: 1, y: 2, b: 0, n: 1, m: 0, c: Some(Box::new(context)) };
+        let down = || EventData::Key { k: Key::Down, m: 0 };
+        let url = Some("https://x.com".into());
+        let window = EventData::Window { a: "Chrome".into(), w: None, u: url };
+        let scroll = EventData::Scroll { x: 0, y: 0, dx: 0, dy: -3, px: false };
+        let mut workflow = RecordedWorkflow::new("report");
+        workflow.events = vec![
+            event(0, EventData::App { n: "Chrome".into(), p: 1 }),
+            event(5, window),
+            event(10, click),
+            event(20, EventData::Text { s: "quarterly report, all regions".into() }),
+            event(30, down()),
+            event(40, down()),
+            event(50, scroll),
+            event(60, EventData::App { n: "Mail".into(), p: 2 }),
+            event(70, EventData::Key { k: Key::V, m: crate::events::Modifiers::CMD }),
+        ];
+
+        let summary = Summarizer::new().max_text(17).summarize(&workflow);
+        assert_eq!(summary.apps, ["Chrome", "Mail"]);
+        assert_eq!(
+            summary.to_string(),
+            "[Chrome] visited https://x.com → clicked TextField 'Search' → typed \
+             \"quarterly report,…\" into 'Search' → pressed down ×2 → scrolled → \
+             [Mail] pressed cmd+v"
+        );
+
+        // Scrolls go first, then plain keys
+        let summary = Summarizer::new().max_text(17).max_tokens(35).summarize(&workflow);
+        assert_eq!(summary.omitted, 2);
+        assert!(!summary.to_string().contains("scrolled"));
+        assert!(!summary.to_string().contains("down"));
+        assert!(summary.tokens() <= 35);
+
+        // Then the middle, keeping the ends
+        let summary = Summarizer::new().max_tokens(10).summarize(&workflow);
+        assert_eq!(summary.steps.first().unwrap().text, "visited https://x.com");
+        assert_eq!(summary.steps.last().unwrap().text, "pressed cmd+v");
+    }
+}
@@ -0,0 +1,364 @@
+//! Recordings flattened into tables
+//!
+//! [`rows`] turns events into [`EventRow`]s, one per event with its kind by
+//! name, wall-clock time, app, position, key, text and element in columns of
+//! their own and the whole event as JSON in `data`. [`write_table`] writes
+//! them as CSV, or Parquet with the `parquet` feature, for pandas or DuckDB.
+
+use crate::events::{Event, EventData};
+use crate::keys::Hotkey;
+use crate::storage::WorkflowStorage;
+use anyhow::Result;
+use serde::Serialize;
+use std::io::Write;
+
+/// How an exported table is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    Csv,
+    /// Needs the `parquet` feature
+    Parquet,
+}
+
+impl TableFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Parquet => "parquet",
+        }
+    }
+}
+
+impl std::str::FromStr for TableFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(TableFormat::Csv),
+            "parquet" | "pq" => Ok(TableFormat::Parquet),
+            other => Err(format!("unknown table format: {} (csv or parquet)", other)),
+        }
+    }
+}
+
+/// One event as a table row. Columns that don't apply to the event's kind
+/// are empty.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventRow {
+    pub workflow: String,
+    /// Position in the recording
+    pub index: u64,
+    /// Milliseconds since recording start
+    pub t: u64,
+    /// Unix milliseconds, when the recording has a start time or the event
+    /// a timestamp
+    pub time: Option<i64>,
+    /// [`EventKind::name`](crate::events::EventKind::name)
+    pub kind: &'static str,
+    /// App in front: the last switched to, or the one clicked in
+    pub app: Option<String>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    pub button: Option<u8>,
+    /// Key with modifiers, e.g. `cmd+s`
+    pub key: Option<String>,
+    /// Typed, pasted or noted
+    pub text: Option<String>,
+    pub window: Option<String>,
+    pub url: Option<String>,
+    /// Element clicked
+    pub role: Option<String>,
+    pub name: Option<String>,
+    pub selector: Option<String>,
+    /// File changed, screenshot or video
+    pub file: Option<String>,
+    /// The whole event as JSON
+    pub data: String,
+}
+
+/// `events` of workflow `name` as rows; `started_at` is the recording's
+/// start in Unix milliseconds
+pub fn rows(
+    name: &str,
+    started_at: Option<i64>,
+    events: impl IntoIterator<Item = Event>,
+) -> impl Iterator<Item = EventRow> {
+    let name = name.to_string();
+    let mut app: Option<String> = None;
+    events.into_iter().enumerate().map(move |(index, event)| {
+        let mut row = EventRow {
+            workflow: name.clone(),
+            index: index as u64,
+            t: event.t,
+            time: event.ts.or(started_at.map(|s| s + event.t as i64)),
+            kind: event.data.kind().name(),
+            app: track_app(&mut app, &event.data).map(str::to_string),
+            data: serde_json::to_string(&event).unwrap_or_default(),
+            ..EventRow::default()
+        };
+        fill(&mut row, event.data);
+        row
+    })
+}
+
+/// App the event happened in, given the app in front before it (`current`,
+/// updated on app and window switches)
+pub(crate) fn track_app<'a>(
+    current: &'a mut Option<String>,
+    data: &'a EventData,
+) -> Option<&'a str> {
+    match data {
+        EventData::App { n: a, .. } | EventData::Window { a, .. } => {
+            *current = Some(a.clone());
+        }
+        EventData::Click { c: Some(c), .. } if c.a.is_some() => return c.a.as_deref(),
+        _ => {}
+    }
+    current.as_deref()
+}
+
+fn fill(row: &mut EventRow, data: EventData) {
+    let key = |k, m| Some(Hotkey { key: k, m }.to_string());
+    match data {
+        EventData::Click { x, y, b, c, .. } => {
+            (row.x, row.y, row.button) = (Some(x), Some(y), Some(b));
+            if let Some(c) = c {
+                row.role = Some(c.r);
+                row.name = c.n;
+                row.selector = Some(c.s).filter(|s| !s.is_empty());
+                row.window = c.w;
+                row.url = c.u;
+            }
+        }
+        EventData::Drag { x, y, b, .. } | EventData::MouseUp { x, y, b } => {
+            (row.x, row.y, row.button) = (Some(x), Some(y), Some(b));
+        }
+        EventData::Move { x, y } | EventData::Scroll { x, y, .. } => {
+            (row.x, row.y) = (Some(x), Some(y));
+        }
+        EventData::Key { k, m } | EventData::KeyUp { k, m, .. } => row.key = key(k, m),
+        EventData::Text { s } | EventData::Annotation { s } => row.text = Some(s),
+        EventData::ScreenText { s, w, .. } => (row.text, row.window) = (Some(s), w),
+        EventData::Paste { s, f, .. } => (row.text, row.file) = (Some(s), f),
+        EventData::Window { w, u, .. } => (row.window, row.url) = (w, u),
+        EventData::Context { r, n, .. } => (row.role, row.name) = (Some(r), n),
+        EventData::FileChanged { f, .. }
+        | EventData::Screenshot { f }
+        | EventData::Video { f, .. } => row.file = Some(f),
+        _ => {}
+    }
+}
+
+/// Write `rows` to `w` as a table; returns how many were written
+pub fn write_table(
+    rows: impl Iterator<Item = EventRow>,
+    format: TableFormat,
+    w: impl Write + Send,
+) -> Result<usize> {
+    match format {
+        TableFormat::Csv => {
+            let mut w = csv::Writer::from_writer(w);
+            let mut n = 0;
+            for row in rows {
+                w.serialize(row)?;
+                n += 1;
+            }
+            w.flush()?;
+            Ok(n)
+        }
+        #[cfg(feature = "parquet")]
+        TableFormat::Parquet => parquet_writer::write(rows, w),
+        #[cfg(not(feature = "parquet"))]
+        TableFormat::Parquet => anyhow::bail!("Parquet export needs the `parquet` feature"),
+    }
+}
+
+impl WorkflowStorage {
+    /// Write a recording's events to `w` as a table, reading them one at a
+    /// time; returns how many were written. Corrupt events are skipped and
+    /// logged.
+    pub fn export_table(
+        &self,
+        filename: &str,
+        format: TableFormat,
+        w: impl Write + Send,
+    ) -> Result<usize> {
+        let mut reader = self.iter(filename)?;
+        let name = reader.name().to_string();
+        let started_at = reader.meta().map(|m| m.started_at.timestamp_millis());
+        let events = std::iter::from_fn(|| loop {
+            match reader.next()? {
+                Ok(event) => return Some(event),
+                Err(e) => tracing::warn!(filename, error = format!("{:#}", e), "Skipping event"),
+            }
+        });
+        write_table(rows(&name, started_at, events), format, w)
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_writer {
+    use super::EventRow;
+    use anyhow::Result;
+    use parquet::basic::{Compression, ZstdLevel};
+    use parquet::data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    /// Rows buffered per row group
+    const ROW_GROUP: usize = 64 * 1024;
+
+    const SCHEMA: &str = "message event {
+        REQUIRED BYTE_ARRAY workflow (UTF8);
+        REQUIRED INT64 index;
+        REQUIRED INT64 t;
+        OPTIONAL INT64 time (TIMESTAMP(MILLIS, true));
+        REQUIRED BYTE_ARRAY kind (UTF8);
+        OPTIONAL BYTE_ARRAY app (UTF8);
+        OPTIONAL INT32 x;
+        OPTIONAL INT32 y;
+        OPTIONAL INT32 button;
+        OPTIONAL BYTE_ARRAY key (UTF8);
+        OPTIONAL BYTE_ARRAY text (UTF8);
+        OPTIONAL BYTE_ARRAY window (UTF8);
+        OPTIONAL BYTE_ARRAY url (UTF8);
+        OPTIONAL BYTE_ARRAY role (UTF8);
+        OPTIONAL BYTE_ARRAY name (UTF8);
+        OPTIONAL BYTE_ARRAY selector (UTF8);
+        OPTIONAL BYTE_ARRAY file (UTF8);
+        REQUIRED BYTE_ARRAY data (UTF8);
+    }";
+
+    /// A column's values in a row group, in schema order
+    enum Column {
+        Int32(Vec<Option<i32>>),
+        Int64(Vec<Option<i64>>),
+        Str(Vec<Option<String>>),
+    }
+
+    fn columns(rows: Vec<EventRow>) -> Vec<Column> {
+        let int64 = |f: fn(&EventRow) -> Option<i64>| Column::Int64(rows.iter().map(f).collect());
+        let int32 = |f: fn(&EventRow) -> Option<i32>| Column::Int32(rows.iter().map(f).collect());
+        let str = |f: fn(&EventRow) -> Option<String>| Column::Str(rows.iter().map(f).collect());
+        vec![
+            str(|r| Some(r.workflow.clone())),
+            int64(|r| Some(r.index as i64)),
+            int64(|r| Some(r.t as i64)),
+            int64(|r| r.time),
+            str(|r| Some(r.kind.to_string())),
+            str(|r| r.app.clone()),
+            int32(|r| r.x),
+            int32(|r| r.y),
+            int32(|r| r.button.map(i32::from)),
+            str(|r| r.key.clone()),
+            str(|r| r.text.clone()),
+            str(|r| r.window.clone()),
+            str(|r| r.url.clone()),
+            str(|r| r.role.clone()),
+            str(|r| r.name.clone()),
+            str(|r| r.selector.clone()),
+            str(|r| r.file.clone()),
+            str(|r| Some(r.data.clone())),
+        ]
+    }
+
+    /// Values present, and definition levels (1 present, 0 null)
+    fn split<T, U>(values: Vec<Option<T>>, f: impl Fn(T) -> U) -> (Vec<U>, Vec<i16>) {
+        let levels = values.iter().map(|v| v.is_some() as i16).collect();
+        (values.into_iter().flatten().map(f).collect(), levels)
+    }
+
+    pub(super) fn write(
+        rows: impl Iterator<Item = EventRow>,
+        w: impl Write + Send,
+    ) -> Result<usize> {
+        let schema = Arc::new(parse_message_type(SCHEMA)?);
+        let props = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .build();
+        let mut writer = SerializedFileWriter::new(w, schema, Arc::new(props))?;
+        let mut rows = rows.peekable();
+        let mut n = 0;
+        while rows.peek().is_some() {
+            let group: Vec<EventRow> = rows.by_ref().take(ROW_GROUP).collect();
+            n += group.len();
+            let mut row_group = writer.next_row_group()?;
+            for column in columns(group) {
+                let mut writer = row_group.next_column()?.expect("a column per schema field");
+                match column {
+                    Column::Int32(values) => {
+                        let (values, levels) = split(values, |v| v);
+                        writer.typed::<Int32Type>().write_batch(&values, Some(&levels), None)?;
+                    }
+                    Column::Int64(values) => {
+                        let (values, levels) = split(values, |v| v);
+                        writer.typed::<Int64Type>().write_batch(&values, Some(&levels), None)?;
+                    }
+                    Column::Str(values) => {
+                        let (values, levels) = split(values, |s| ByteArray::from(s.into_bytes()));
+                        writer.typed::<ByteArrayType>().write_batch(&values, Some(&levels), None)?;
+                    }
+                }
+                writer.close()?;
+            }
+            row_group.close()?;
+        }
+        writer.close()?;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{ElementContext, RecordedWorkflow};
+    use crate::keys::Key;
+
+    #[test]
+    fn flattens_events() {
+        let event = |t, data| Event { t, ts: None, data };
+        let context =
+            ElementContext { r: "AXButton".into(), a: Some("Mail".into()), ..Default::default() };
+        let click = EventData::Click { x: 1, y: 2, b: 0, n: 1, m: 0, c: Some(Box::new(context)) };
+        let events = vec![
+            event(0, EventData::App { n: "Notes".into(), p: 1 }),
+            event(10, click),
+            event(20, EventData::Key { k: Key::S, m: crate::events::Modifiers::CMD }),
+            event(30, EventData::Text { s: "a, \"b\"".into() }),
+        ];
+        let rows: Vec<EventRow> = rows("notes", Some(1000), events).collect();
+        assert_eq!(rows[1].kind, "click");
+        assert_eq!(rows[1].app.as_deref(), Some("Mail"));
+        assert_eq!((rows[2].app.as_deref(), rows[2].time), (Some("Notes"), Some(1020)));
+        assert_eq!(rows[1].role.as_deref(), Some("AXButton"));
+
+        let dir = std::env::temp_dir().join(format!("bb-table-{}", std::process::id()));
+        let storage = WorkflowStorage::with_dir(&dir).unwrap();
+        let mut workflow = RecordedWorkflow::new("notes");
+        workflow.events = rows.iter().map(|r| serde_json::from_str(&r.data).unwrap()).collect();
+        let path = storage.save(&workflow).unwrap();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let out = dir.join("notes.csv");
+        let file = std::fs::File::create(&out).unwrap();
+        assert_eq!(storage.export_table(name, TableFormat::Csv, file).unwrap(), 4);
+        let csv = std::fs::read_to_string(&out).unwrap();
+        assert!(csv.starts_with("workflow,index,t,time,kind,app,x,y,button,key,text,"));
+        assert!(csv.contains("notes,3,30,,text,Notes,,,,,\"a, \"\"b\"\"\""));
+
+        #[cfg(feature = "parquet")]
+        {
+            use parquet::file::reader::{FileReader, SerializedFileReader};
+            let out = dir.join("notes.parquet");
+            let file = std::fs::File::create(&out).unwrap();
+            assert_eq!(storage.export_table(name, TableFormat::Parquet, file).unwrap(), 4);
+            let reader = SerializedFileReader::new(std::fs::File::open(&out).unwrap()).unwrap();
+            let rows: Vec<_> = reader.get_row_iter(None).unwrap().map(|r| r.unwrap()).collect();
+            assert_eq!(rows.len(), 4);
+            assert!(rows[3].to_string().contains("kind: \"text\", app: \"Notes\""));
+        }
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,322 @@
+//! Fine-tuning data from recordings
+//!
+//! [`TrainingExporter`] slices a recording into [`Example`]s of what was on
+//! screen and what the user did next, one per [`Action`], written as JSON
+//! lines for training action models. The context is what was known just
+//! before the action: app, window, URL, the latest screen text and
+//! screenshot, and the last few actions. The cursor is left out, it gives
+//! away where the next click lands.
+//!
+//! Mouse moves make the negatives: points the cursor passed through on its
+//! way to the next action, offered in the same context as clicks that
+//! didn't happen, with `label` false.
+//!
+//! ```rust,no_run
+//! use bigbrother_recorder::prelude::*;
+//! use bigbrother_recorder::training::{Schema, TrainingExporter};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let workflow = WorkflowStorage::new()?.load("invoices")?;
+//! let file = std::fs::File::create("invoices.jsonl")?;
+//! let exporter = TrainingExporter::new().schema(Schema::Chat).negatives(2);
+//! let examples = exporter.export(&workflow, file)?;
+//! println!("{} examples", examples);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::events::{EventData, RecordedWorkflow};
+use crate::semantics::{actions, Action};
+use crate::text::prefix;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::Write;
+
+/// Actions before the next one kept in the context
+const DEFAULT_HISTORY: usize = 5;
+
+/// Negatives per action
+const DEFAULT_NEGATIVES: usize = 1;
+
+/// Screen text kept in the context (chars)
+const DEFAULT_SCREEN_TEXT: usize = 2000;
+
+/// Moves closer than this to the click that followed aren't negatives (px)
+const NEAR_PX: i32 = 20;
+
+const DEFAULT_SYSTEM: &str = "You operate a computer. Given what is on screen and what was done \
+     so far, reply with the next action.";
+
+/// How each example is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Schema {
+    /// `{"prompt", "completion", "label"}`
+    Prompt,
+    /// `{"messages": [system, user, assistant], "label"}`, as chat
+    /// fine-tuning APIs take them
+    Chat,
+    /// The [`Example`] itself, context and action as objects
+    Structured,
+}
+
+impl std::str::FromStr for Schema {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "prompt" | "completion" => Ok(Schema::Prompt),
+            "chat" | "messages" => Ok(Schema::Chat),
+            "structured" | "raw" => Ok(Schema::Structured),
+            other => Err(format!("unknown schema: {} (prompt, chat or structured)", other)),
+        }
+    }
+}
+
+/// What was known just before an action
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Context {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub window: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// OCR of the screen, from ScreenText events
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screen_text: Option<String>,
+    /// Path of the latest screenshot
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub screenshot: Option<String>,
+    /// The actions before, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<String>,
+}
+
+/// A context and the action taken in it, or with `label` false, one that
+/// wasn't
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Example {
+    pub workflow: String,
+    pub t: u64,
+    pub context: Context,
+    pub action: Action,
+    pub label: bool,
+}
+
+/// Turns recordings into [`Example`]s
+#[derive(Debug, Clone)]
+pub struct TrainingExporter {
+    schema: Schema,
+    history: usize,
+    negatives: usize,
+    screen_text: usize,
+    system: String,
+}
+
+impl TrainingExporter {
+    pub fn new() -> Self {
+        Self {
+            schema: Schema::Prompt,
+            history: DEFAULT_HISTORY,
+            negatives: DEFAULT_NEGATIVES,
+            screen_text: DEFAULT_SCREEN_TEXT,
+            system: DEFAULT_SYSTEM.to_string(),
+        }
+    }
+
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    /// Keep this many actions before the next one in the context, 5 by default
+    pub fn history(mut self, actions: usize) -> Self {
+        self.history = actions;
+        self
+    }
+
+    /// Sample up to this many moves per action as negatives, 1 by default
+    pub fn negatives(mut self, per_action: usize) -> Self {
+        self.negatives = per_action;
+        self
+    }
+
+    /// Cut screen text to this many characters, 2000 by default
+    pub fn screen_text(mut self, max_chars: usize) -> Self {
+        self.screen_text = max_chars;
+        self
+    }
+
+    /// System message of the [`Schema::Chat`] schema
+    pub fn system(mut self, message: impl Into<String>) -> Self {
+        self.system = message.into();
+        self
+    }
+
+    /// Examples in order, each action's negatives before it
+    pub fn examples(&self, workflow: &RecordedWorkflow) -> Vec<Example> {
+        let events = &workflow.events;
+        let mut context = Context::default();
+        let mut next = 0;
+        let mut done: Vec<String> = Vec::new();
+        let mut out = Vec::new();
+        for action in actions(workflow) {
+            let t = action.t();
+            let mut moves = Vec::new();
+            while let Some(event) = events.get(next).filter(|e| e.t < t) {
+                if let EventData::Move { x, y } = event.data {
+                    moves.push((event.t, x, y));
+                }
+                self.observe(&mut context, &event.data);
+                next += 1;
+            }
+            // What the screen showed at the action is recorded right after it
+            for event in events[next..].iter().take_while(|e| e.t == t) {
+                if let EventData::Screenshot { .. } | EventData::ScreenText { .. } = event.data {
+                    self.observe(&mut context, &event.data);
+                }
+            }
+            if let Action::Note { .. } = action {
+                done.push(action.to_string());
+                continue;
+            }
+            context.history = done[done.len().saturating_sub(self.history)..].to_vec();
+
+            if let Action::Click { x, y, .. } = action {
+                moves.retain(|(_, mx, my)| (mx - x).abs() > NEAR_PX || (my - y).abs() > NEAR_PX);
+            }
+            let picks = self.negatives.min(moves.len());
+            for n in 0..picks {
+                let (t, x, y) = moves[(2 * n + 1) * moves.len() / (2 * picks)];
+                let action = Action::Click { t, x, y, button: 0, count: 1, target: None };
+                let (workflow, context) = (workflow.name.clone(), context.clone());
+                out.push(Example { workflow, t, context, action, label: false });
+            }
+            done.push(action.to_string());
+            let (workflow, context) = (workflow.name.clone(), context.clone());
+            out.push(Example { workflow, t, context, action, label: true });
+        }
+        out
+    }
+
+    fn observe(&self, context: &mut Context, data: &EventData) {
+        match data {
+            EventData::App { n, .. } => {
+                (context.app, context.window, context.url) = (Some(n.clone()), None, None)
+            }
+            EventData::Window { a, w, u } => {
+                (context.app, context.window, context.url) = (Some(a.clone()), w.clone(), u.clone())
+            }
+            EventData::ScreenText { s, .. } => {
+                context.screen_text = Some(prefix(s, self.screen_text).to_string())
+            }
+            EventData::Screenshot { f } => context.screenshot = Some(f.clone()),
+            _ => {}
+        }
+    }
+
+    /// An example in the chosen schema
+    pub fn render(&self, example: &Example) -> Value {
+        let prompt = prompt(&example.context);
+        let (action, label) = (example.action.to_string(), example.label);
+        match self.schema {
+            Schema::Prompt => json!({"prompt": prompt, "completion": action, "label": label}),
+            Schema::Chat => json!({
+                "messages": [
+                    {"role": "system", "content": self.system},
+                    {"role": "user", "content": prompt},
+                    {"role": "assistant", "content": action},
+                ],
+                "label": label,
+            }),
+            Schema::Structured => json!(example),
+        }
+    }
+
+    /// Write `workflow`'s examples to `w` as JSON lines; returns how many
+    pub fn export(&self, workflow: &RecordedWorkflow, mut w: impl Write) -> Result<usize> {
+        let examples = self.examples(workflow);
+        for example in &examples {
+            serde_json::to_writer(&mut w, &self.render(example))?;
+            w.write_all(b"\n")?;
+        }
+        w.flush()?;
+        Ok(examples.len())
+    }
+}
+
+impl Default for TrainingExporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The context as text, ending with the question
+fn prompt(context: &Context) -> String {
+    let mut lines = Vec::new();
+    let fields = [
+        ("App", &context.app),
+        ("Window", &context.window),
+        ("URL", &context.url),
+        ("Screenshot", &context.screenshot),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            lines.push(format!("{}: {}", name, value));
+        }
+    }
+    if let Some(text) = &context.screen_text {
+        lines.push(format!("Screen:\n{}", text));
+    }
+    if !context.history.is_empty() {
+        lines.push("Done so far:".to_string());
+        lines.extend(context.history.iter().map(|action| format!("- {}", action)));
+    }
+    lines.push("Next action:".to_string());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Event;
+
+    #[test]
+    fn pairs_context_with_the_next_action() {
+        let event = |t, data| Event { t, ts: None, data };
+        let mut workflow = RecordedWorkflow::new("mail");
+        workflow.events = vec![
+            event(0, EventData::App { n: "Mail".into(), p: 1 }),
+            event(10, EventData::Move { x: 500, y: 500 }),
+            event(20, EventData::Move { x: 102, y: 199 }),
+            event(30, EventData::Click { x: 100, y: 200, b: 0, n: 1, m: 0, c: None }),
+            event(30, EventData::ScreenText { f: 1, s: "Inbox (3)".into(), a: None, w: None }),
+            event(40, EventData::Text { s: "hi".into() }),
+        ];
+
+        let examples = TrainingExporter::new().examples(&workflow);
+        let summary: Vec<_> = examples.iter().map(|e| (e.label, e.action.to_string())).collect();
+        assert_eq!(
+            summary,
+            [
+                (true, "switched to Mail".to_string()),
+                // The move right by the click isn't one
+                (false, "clicked at (500, 500)".to_string()),
+                (true, "clicked at (100, 200)".to_string()),
+                (true, "typed \"hi\"".to_string()),
+            ]
+        );
+        let click = &examples[2].context;
+        assert_eq!(click.screen_text.as_deref(), Some("Inbox (3)"));
+        assert_eq!(click.history, ["switched to Mail"]);
+
+        let exporter = TrainingExporter::new().schema(Schema::Chat).negatives(0).history(0);
+        let mut out = Vec::new();
+        assert_eq!(exporter.export(&workflow, &mut out).unwrap(), 3);
+        let out = String::from_utf8(out).unwrap();
+        let last: Value = serde_json::from_str(out.lines().nth(2).unwrap()).unwrap();
+        assert_eq!(last["messages"][1]["content"], "App: Mail\nScreen:\nInbox (3)\nNext action:");
+        assert_eq!(last["messages"][2]["content"], "typed \"hi\"");
+    }
+}
@@ -0,0 +1,194 @@
+//! Screen recording for the `video` feature
+//!
+//! Runs the platform's capture tool as a child process for the length of the
+//! session (`video_command` in each platform module) and announces the file
+//! with a Video event once it's recording: at the first frame for tools that
+//! report frames (ffmpeg), with that frame's `t`, otherwise once the tool
+//! has stayed up a moment. A tool that exits before the session stops leaves
+//! a VideoStopped marker. Stopping the session asks the tool to finalize it.
+//!
+//! ## Alignment
+//!
+//! A video is lined up with events by a single offset, not frame by frame:
+//! the tools write ordinary video files and don't say when each frame was
+//! grabbed, so there are no per-frame timestamps. An event at `t` is at
+//! video time `t - o`.
+//!
+//! - ffmpeg (X11, Windows) reports progress every 100ms, so `o` is within
+//!   about 100ms of the first frame. It records at a constant 10 fps,
+//!   repeating or dropping frames to keep video time in step with the clock.
+//! - screencapture (macOS) and wf-recorder (Wayland) report nothing, so there's
+//!   no `o` and video time 0 is taken to be the Video event's `t`, half a
+//!   second after the tool started. How long the tool really took to grab
+//!   its first frame isn't known: expect events to be off by up to a second.
+//!
+//! Frame-exact alignment would need capturing in process (ScreenCaptureKit,
+//! DXGI desktop duplication), which this doesn't do.
+
+use crate::events::{Event, EventData};
+use crate::platform;
+use crate::recorder::{CaptureContext, EventSink};
+use anyhow::{Context, Result};
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Stdio};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// ffmpeg options that report progress on stdout, read by [`first_frame`]
+pub(crate) const FFMPEG_PROGRESS: [&str; 5] =
+    ["-nostats", "-progress", "pipe:1", "-stats_period", "0.1"];
+
+/// How long a tool that doesn't report frames has to stay up before the
+/// video is announced (ms)
+const STARTUP_MS: u64 = 500;
+
+/// Start recording; the returned thread announces the video and stops the
+/// tool when the session stops
+pub(crate) fn start(ctx: &CaptureContext) -> Result<thread::JoinHandle<()>> {
+    let dir = &ctx.config.video_dir;
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(format!("{}.mov", chrono::Local::now().format("%Y%m%d_%H%M%S")));
+
+    let (mut command, reports_frames) = platform::current::video_command(&path)?;
+    let tool = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(if reports_frames { Stdio::piped() } else { Stdio::null() })
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to start screen recording with {}", tool))?;
+
+    let start = ctx.start;
+    let now = move || start.elapsed().as_millis() as u64;
+    let first = child.stdout.take().map(|stdout| {
+        let (found, first) = mpsc::channel();
+        thread::spawn(move || {
+            let mut progress = BufReader::new(stdout);
+            if let Some(t) = first_frame(&mut progress, now) {
+                let _ = found.send(t);
+            }
+            // The tool blocks once the pipe is full
+            let _ = std::io::copy(&mut progress, &mut std::io::sink());
+        });
+        first
+    });
+
+    let video = Video {
+        f: path.to_string_lossy().into_owned(),
+        tx: ctx.tx.clone(),
+        spawned: now(),
+        first,
+        announced: false,
+    };
+    let stop = ctx.stop.clone();
+    Ok(thread::spawn(move || {
+        let mut video = video;
+        while !stop.load(Ordering::Relaxed) {
+            let t = now();
+            video.announce(t, false);
+            if let Ok(Some(status)) = child.try_wait() {
+                tracing::warn!(%status, "Screen recording exited early");
+                video.announce(t, true);
+                let data = EventData::VideoStopped { s: status.to_string() };
+                let _ = video.tx.try_send(Event { t, ts: None, data });
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        finish(&mut child);
+        let _ = child.wait();
+        video.announce(now(), true);
+    }))
+}
+
+/// A video not announced yet
+struct Video {
+    f: String,
+    tx: EventSink,
+    /// `t` the tool was started at
+    spawned: u64,
+    /// The first frame's `t`, for tools that report frames
+    first: Option<mpsc::Receiver<u64>>,
+    announced: bool,
+}
+
+impl Video {
+    /// Send the Video event once the tool is recording, or regardless when
+    /// `ending` so the file isn't left out
+    fn announce(&mut self, t: u64, ending: bool) {
+        if self.announced {
+            return;
+        }
+        let o = match &self.first {
+            Some(first) => match first.try_recv() {
+                Ok(o) => Some(o),
+                Err(_) if ending => None,
+                Err(_) => return,
+            },
+            None if ending || t >= self.spawned + STARTUP_MS => None,
+            None => return,
+        };
+        self.announced = true;
+        let data = EventData::Video { f: self.f.clone(), o };
+        let _ = self.tx.try_send(Event { t, ts: None, data });
+    }
+}
+
+/// `t` of the first frame, from ffmpeg's `-progress` output: the `t` the
+/// first report with frames was read at, less the video time it covers
+fn first_frame(progress: &mut impl BufRead, now: impl Fn() -> u64) -> Option<u64> {
+    let (mut frames, mut out_time_us) = (0u64, 0u64);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if progress.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        match key {
+            "frame" => frames = value.parse().unwrap_or(0),
+            "out_time_us" => out_time_us = value.parse().unwrap_or(0),
+            // Ends each report
+            "progress" if frames > 0 => return Some(now().saturating_sub(out_time_us / 1000)),
+            _ => {}
+        }
+    }
+}
+
+/// Ask the tool to write out the file: SIGINT on Unix, "q" to ffmpeg on Windows
+fn finish(child: &mut Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(child.id() as libc::pid_t, libc::SIGINT);
+    }
+    #[cfg(windows)]
+    {
+        use std::io::Write;
+        if let Some(stdin) = child.stdin.as_mut() {
+            let _ = stdin.write_all(b"q");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_first_frame_in_ffmpeg_progress() {
+        let report = |frame, us, end| {
+            format!("frame={}\nfps=0.0\nout_time_us={}\nprogress={}\n", frame, us, end)
+        };
+        let progress = [report(0, 0, "continue"), report(3, 200_000, "continue")].concat();
+        let first = first_frame(&mut progress.as_bytes(), || 1500);
+        assert_eq!(first, Some(1300));
+
+        let none = report(0, 0, "end");
+        assert_eq!(first_frame(&mut none.as_bytes(), || 1500), None);
+    }
+}
@@ -0,0 +1,820 @@
+//! Simplified event types optimized for AI consumption
+//!
+//! Events are stored as simple structs that serialize to compact JSON lines.
+
+use crate::keys::{Hotkey, Key};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+use serde::{Deserialize, Serialize};
+
+/// Pause between two workflows joined with [`RecordedWorkflow::append`]
+pub const APPEND_GAP_MS: u64 = 1000;
+
+/// A recorded workflow - just a list of events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedWorkflow {
+    pub name: String,
+    pub events: Vec<Event>,
+    /// Where and when it was recorded; `None` for older recordings and
+    /// workflows built by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<SessionMetadata>,
+    /// What the recording does, e.g. "export last month's invoices"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Labels to find it by, e.g. "billing"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl RecordedWorkflow {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            events: Vec::new(),
+            meta: None,
+            description: None,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Milliseconds from the first event to the last
+    pub fn duration(&self) -> u64 {
+        match (self.events.first(), self.events.last()) {
+            (Some(first), Some(last)) => last.t.saturating_sub(first.t),
+            _ => 0,
+        }
+    }
+
+    /// Add `other`'s events after this one's, re-based to start
+    /// [`APPEND_GAP_MS`] after the last event. A session end marker closing
+    /// this workflow is dropped; the tags are combined.
+    pub fn append(&mut self, other: RecordedWorkflow) {
+        let ends = |e: &Event| matches!(e.data, EventData::SessionEnd { .. });
+        while self.events.last().is_some_and(ends) {
+            self.events.pop();
+        }
+        let start = self.events.last().map_or(0, |e| e.t + APPEND_GAP_MS);
+        let first = other.events.first().map_or(0, |e| e.t);
+        self.events.extend(other.events.into_iter().map(|mut e| {
+            e.t = e.t.saturating_sub(first) + start;
+            e
+        }));
+        self.meta = self.meta.take().or(other.meta);
+        self.description = self.description.take().or(other.description);
+        for tag in other.tags {
+            if !self.tags.contains(&tag) {
+                self.tags.push(tag);
+            }
+        }
+    }
+
+    /// Apps switched to, in the order they first came up
+    pub fn apps(&self) -> Vec<String> {
+        let mut apps: Vec<String> = Vec::new();
+        for event in &self.events {
+            if let EventData::App { n, .. } = &event.data {
+                if !apps.contains(n) {
+                    apps.push(n.clone());
+                }
+            }
+        }
+        apps
+    }
+}
+
+/// Cleans up a recording before it's saved or replayed. Each edit returns
+/// the editor; [`finish`](Self::finish) hands the workflow back. Ranges are
+/// event indices, clamped to the events there are.
+pub struct WorkflowEditor {
+    workflow: RecordedWorkflow,
+}
+
+impl WorkflowEditor {
+    pub fn new(workflow: RecordedWorkflow) -> Self {
+        Self { workflow }
+    }
+
+    /// Remove the events in `range`, pulling later events back by the time
+    /// the removed ones took
+    pub fn delete(mut self, range: Range<usize>) -> Self {
+        let events = &mut self.workflow.events;
+        let range = clamp(range, events.len());
+        if range.is_empty() {
+            return self;
+        }
+        let from = events[range.start].t;
+        let cut = events.get(range.end).map_or(0, |e| e.t.saturating_sub(from));
+        events.drain(range.clone());
+        for event in &mut events[range.start..] {
+            event.t = event.t.saturating_sub(cut);
+        }
+        self
+    }
+
+    /// Cut the wait before the first input and after the last: whatever
+    /// comes before (the app, the window) moves to the start, whatever
+    /// comes after to the last input, and idle markers at either end go
+    pub fn trim_idle(mut self) -> Self {
+        let events = &mut self.workflow.events;
+        let Some(first) = events.iter().position(|e| is_input(&e.data)) else {
+            return self;
+        };
+        let last = events.iter().rposition(|e| is_input(&e.data)).unwrap_or(first);
+        let (start, end) = (events[first].t, events[last].t.saturating_sub(events[first].t));
+        let mut i = 0;
+        events.retain(|e| {
+            i += 1;
+            (first..=last).contains(&(i - 1)) || !matches!(e.data, EventData::Idle { .. })
+        });
+        for event in events.iter_mut() {
+            event.t = event.t.saturating_sub(start).min(end);
+        }
+        self
+    }
+
+    /// Drop mouse moves: clicks and drags go to their own positions anyway
+    pub fn strip_moves(mut self) -> Self {
+        self.workflow.events.retain(|e| !matches!(e.data, EventData::Move { .. }));
+        self
+    }
+
+    /// Join each run of consecutive text events into one, at the time of
+    /// its first
+    pub fn merge_text(mut self) -> Self {
+        let events = core::mem::take(&mut self.workflow.events);
+        for event in events {
+            if let (EventData::Text { s }, Some(Event { data: EventData::Text { s: run }, .. })) =
+                (&event.data, self.workflow.events.last_mut())
+            {
+                run.push_str(s);
+                continue;
+            }
+            self.workflow.events.push(event);
+        }
+        self
+    }
+
+    /// Stretch the time between the events in `range` by `factor` (0.5 plays
+    /// them twice as fast), moving later events to match
+    pub fn retime(mut self, range: Range<usize>, factor: f64) -> Self {
+        let events = &mut self.workflow.events;
+        let range = clamp(range, events.len());
+        if range.is_empty() {
+            return self;
+        }
+        let factor = factor.max(0.0);
+        let base = events[range.start].t;
+        let mut shift = 0i64;
+        for event in &mut events[range.clone()] {
+            // Rounded: `f64::round` needs std, and this is never negative
+            let t = base + (event.t.saturating_sub(base) as f64 * factor + 0.5) as u64;
+            shift = t as i64 - event.t as i64;
+            event.t = t;
+        }
+        for event in &mut events[range.end..] {
+            event.t = (event.t as i64 + shift).max(0) as u64;
+        }
+        self
+    }
+
+    pub fn workflow(&self) -> &RecordedWorkflow {
+        &self.workflow
+    }
+
+    pub fn finish(self) -> RecordedWorkflow {
+        self.workflow
+    }
+}
+
+fn clamp(range: Range<usize>, len: usize) -> Range<usize> {
+    range.start.min(len)..range.end.min(len)
+}
+
+/// Mouse and keyboard input, as opposed to context and markers
+fn is_input(data: &EventData) -> bool {
+    matches!(
+        data,
+        EventData::Click { .. }
+            | EventData::Move { .. }
+            | EventData::Drag { .. }
+            | EventData::MouseUp { .. }
+            | EventData::Scroll { .. }
+            | EventData::Key { .. }
+            | EventData::KeyUp { .. }
+            | EventData::Text { .. }
+    )
+}
+
+/// The environment a workflow was recorded in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    /// "macos", "windows" or "linux"
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub displays: Vec<DisplayInfo>,
+    /// Platform layout id, e.g. "com.apple.keylayout.US", "00000409", "us"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyboard_layout: Option<String>,
+    /// Wall-clock time at `t` = 0, serialized as RFC 3339
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// Hash of host and user name: tells machines apart without naming them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine: Option<String>,
+    /// Version of the recorder that wrote the file
+    pub recorder_version: String,
+}
+
+/// A monitor, in the coordinate space events are recorded in
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DisplayInfo {
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+    /// Physical pixels per recorded unit (2.0 on Retina, 1.5 at 150% scaling)
+    pub scale: f64,
+}
+
+/// Single event - flat structure for efficiency
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    /// Milliseconds since recording start
+    pub t: u64,
+    /// Wall-clock time in Unix milliseconds (with `absolute_timestamps`).
+    /// Unlike `started_at + t` it follows clock adjustments made mid-session.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts: Option<i64>,
+    /// Event type and data
+    #[serde(flatten)]
+    pub data: EventData,
+}
+
+/// Event data - simple tagged union
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "e")]
+pub enum EventData {
+    /// Mouse click: x, y, button (0=left, 1=right, 2=middle), clicks (1=single, 2=double),
+    /// element under the cursor at press (with `capture_context`)
+    #[serde(rename = "c")]
+    Click {
+        x: i32,
+        y: i32,
+        b: u8,
+        n: u8,
+        m: u8,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        c: Option<Box<ElementContext>>,
+    },
+
+    /// Mouse move: x, y
+    #[serde(rename = "m")]
+    Move { x: i32, y: i32 },
+
+    /// Drag: start x, y to end x2, y2 with a button held, sampled path in
+    /// between, modifiers at press, duration in ms
+    #[serde(rename = "d")]
+    Drag {
+        x: i32,
+        y: i32,
+        x2: i32,
+        y2: i32,
+        b: u8,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        p: Vec<(i32, i32)>,
+        m: u8,
+        d: u64,
+    },
+
+    /// Mouse button released: x, y, button (raw drag mode only)
+    #[serde(rename = "u")]
+    MouseUp { x: i32, y: i32, b: u8 },
+
+    /// Mouse scroll: x, y, dx, dy in lines, or in pixels with `px`
+    /// (trackpads and other precise scrolling)
+    #[serde(rename = "s")]
+    Scroll {
+        x: i32,
+        y: i32,
+        dx: i16,
+        dy: i16,
+        #[serde(default, skip_serializing_if = "is_false")]
+        px: bool,
+    },
+
+    /// Key down: portable key, modifiers
+    #[serde(rename = "k")]
+    Key { k: Key, m: u8 },
+
+    /// Key released: key, modifiers, ms held (only with key-up capture)
+    #[serde(rename = "r")]
+    KeyUp { k: Key, m: u8, d: u64 },
+
+    /// Text input (aggregated keystrokes)
+    #[serde(rename = "t")]
+    Text { s: String },
+
+    /// App activated: name, pid
+    #[serde(rename = "a")]
+    App { n: String, p: i32 },
+
+    /// Window focused: app name, window title, active tab URL for browsers
+    #[serde(rename = "w")]
+    Window {
+        a: String, // app name
+        #[serde(skip_serializing_if = "Option::is_none")]
+        w: Option<String>, // window title
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        u: Option<String>, // browser URL
+    },
+
+    /// Clipboard changed: operation (c=copy, x=cut, v=paste), content preview,
+    /// and when known the kind, MIME type and size of the content, and the
+    /// file holding all of it (with `save_clipboard`)
+    #[serde(rename = "p")]
+    Paste {
+        o: char,
+        s: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        k: Option<ClipboardKind>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        m: Option<String>, // MIME type
+        #[serde(default, skip_serializing_if = "is_zero")]
+        l: u64, // bytes, 0 if unknown
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        f: Option<String>, // full content
+    },
+
+    /// File created, modified, deleted or renamed (o = c/m/d/r) in one of the
+    /// `watch_dirs`. A rename is a `d` for the old path and an `r` for the
+    /// new one.
+    #[serde(rename = "f")]
+    FileChanged { f: String, o: char },
+
+    /// Element context at last click position. Only found in older
+    /// recordings; context is now part of the Click event.
+    #[serde(rename = "x")]
+    Context {
+        r: String, // role
+        #[serde(skip_serializing_if = "Option::is_none")]
+        n: Option<String>, // name/title
+        #[serde(skip_serializing_if = "Option::is_none")]
+        v: Option<String>, // value
+    },
+
+    /// Screenshot taken for the event at the same timestamp: image path
+    #[serde(rename = "i")]
+    Screenshot { f: String },
+
+    /// Text on screen at the event before it, OCR'd by screenpipe from the
+    /// nearest frame: frame id, text, and the app and window it was read in
+    #[serde(rename = "o")]
+    ScreenText {
+        f: i64,
+        s: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        a: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        w: Option<String>,
+    },
+
+    /// Screen recording started: video path, and the `t` of its first
+    /// frame (o) when the capture tool reports frames, to within ~100ms.
+    /// Without one, video time 0 is this event's `t`, give or take a
+    /// second. Frames carry no timestamps of their own.
+    #[serde(rename = "v")]
+    Video {
+        f: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        o: Option<u64>,
+    },
+
+    /// Screen recording ended before the session did (s = the tool's exit
+    /// status): the video has nothing after this event
+    #[serde(rename = "l")]
+    VideoStopped { s: String },
+
+    /// Note typed by the user after pressing the annotation hotkey, e.g.
+    /// "now I export the report"
+    #[serde(rename = "n")]
+    Annotation { s: String },
+
+    /// Gap in the recording: this many events were dropped before the next
+    /// one because the session channel was full
+    #[serde(rename = "g")]
+    Dropped { n: u64 },
+
+    /// Nothing happened for `d` ms after this event (with
+    /// `collapse_idle_ms`); replay skips the wait
+    #[serde(rename = "z")]
+    Idle { d: u64 },
+
+    /// `max_events` or `max_duration` was reached (r = "events" or
+    /// "duration"): the session stops here, or with `rotate` carries on in
+    /// a new segment
+    #[serde(rename = "e")]
+    SessionEnd { r: String },
+
+    /// The OS stopped delivering input and the recorder turned it back on
+    /// (r = "timeout" when the event tap was too slow, "user" when secure
+    /// input turned it off, "disabled" otherwise): input between the last
+    /// event and this one is lost
+    #[serde(rename = "h")]
+    TapInterrupted { r: String },
+
+    /// Check made during replay, reported in `ReplayStats::assertions`.
+    /// Never recorded: added to workflows to tell whether a replay worked.
+    #[serde(rename = "q")]
+    Assert { a: Assertion },
+}
+
+impl EventData {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            EventData::Click { .. } => EventKind::Click,
+            EventData::Move { .. } => EventKind::Move,
+            EventData::Drag { .. } => EventKind::Drag,
+            EventData::MouseUp { .. } => EventKind::MouseUp,
+            EventData::Scroll { .. } => EventKind::Scroll,
+            EventData::Key { .. } => EventKind::Key,
+            EventData::KeyUp { .. } => EventKind::KeyUp,
+            EventData::Text { .. } => EventKind::Text,
+            EventData::App { .. } => EventKind::App,
+            EventData::Window { .. } => EventKind::Window,
+            EventData::Paste { .. } => EventKind::Paste,
+            EventData::FileChanged { .. } => EventKind::FileChanged,
+            EventData::Context { .. } => EventKind::Context,
+            EventData::Screenshot { .. } => EventKind::Screenshot,
+            EventData::ScreenText { .. } => EventKind::ScreenText,
+            EventData::Video { .. } => EventKind::Video,
+            EventData::VideoStopped { .. } => EventKind::VideoStopped,
+            EventData::Annotation { .. } => EventKind::Annotation,
+            EventData::Dropped { .. } => EventKind::Dropped,
+            EventData::Idle { .. } => EventKind::Idle,
+            EventData::SessionEnd { .. } => EventKind::SessionEnd,
+            EventData::TapInterrupted { .. } => EventKind::TapInterrupted,
+            EventData::Assert { .. } => EventKind::Assert,
+        }
+    }
+}
+
+/// One line for logs and prompts, e.g. `click (10, 20) on AXButton 'Save'`
+impl fmt::Display for EventData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key = |k: &Key, m: &u8| Hotkey { key: *k, m: *m };
+        match self {
+            EventData::Click { x, y, b, n, c, .. } => {
+                let verb = match (b, n) {
+                    (_, 2) => "double-click",
+                    (1, _) => "right-click",
+                    (2, _) => "middle-click",
+                    _ => "click",
+                };
+                write!(f, "{} ({}, {})", verb, x, y)?;
+                match c {
+                    Some(c) => match &c.n {
+                        Some(name) => write!(f, " on {} '{}'", c.r, name),
+                        None => write!(f, " on {}", c.r),
+                    },
+                    None => Ok(()),
+                }
+            }
+            EventData::Move { x, y } => write!(f, "move to ({}, {})", x, y),
+            EventData::Drag { x, y, x2, y2, .. } => {
+                write!(f, "drag ({}, {}) to ({}, {})", x, y, x2, y2)
+            }
+            EventData::MouseUp { x, y, .. } => write!(f, "release mouse at ({}, {})", x, y),
+            EventData::Scroll { x, y, dx, dy, px } => {
+                let unit = if *px { " px" } else { "" };
+                write!(f, "scroll ({}, {}){} at ({}, {})", dx, dy, unit, x, y)
+            }
+            EventData::Key { k, m } => write!(f, "press {}", key(k, m)),
+            EventData::KeyUp { k, m, .. } => write!(f, "release {}", key(k, m)),
+            EventData::Text { s } => write!(f, "type {:?}", s),
+            EventData::App { n, .. } => write!(f, "switch to {}", n),
+            EventData::Window { a, w, .. } => match w {
+                Some(w) => write!(f, "window '{}' in {}", w, a),
+                None => write!(f, "window in {}", a),
+            },
+            EventData::Paste { o, s, .. } => {
+                let verb = match o {
+                    'x' => "cut",
+                    'v' => "paste",
+                    _ => "copy",
+                };
+                write!(f, "{} {:?}", verb, s)
+            }
+            EventData::FileChanged { f: path, o } => write!(f, "file {} ({})", path, o),
+            EventData::Context { r, .. } => write!(f, "context {}", r),
+            EventData::Screenshot { f: path } => write!(f, "screenshot {}", path),
+            EventData::ScreenText { f: frame, s, .. } => {
+                write!(f, "screen text (frame {}): {} chars", frame, s.chars().count())
+            }
+            EventData::Video { f: path, .. } => write!(f, "video {}", path),
+            EventData::VideoStopped { s } => write!(f, "video stopped ({})", s),
+            EventData::Annotation { s } => write!(f, "note: {}", s),
+            EventData::Dropped { n } => write!(f, "{} events dropped", n),
+            EventData::Idle { d } => write!(f, "idle {}ms", d),
+            EventData::SessionEnd { r } => write!(f, "session end ({})", r),
+            EventData::TapInterrupted { r } => write!(f, "input capture interrupted ({})", r),
+            EventData::Assert { a } => write!(f, "assert {}", a),
+        }
+    }
+}
+
+/// [`EventData`] variant, without its data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Click,
+    Move,
+    Drag,
+    MouseUp,
+    Scroll,
+    Key,
+    KeyUp,
+    Text,
+    App,
+    Window,
+    Paste,
+    FileChanged,
+    Context,
+    Screenshot,
+    ScreenText,
+    Video,
+    VideoStopped,
+    Annotation,
+    Dropped,
+    Idle,
+    SessionEnd,
+    TapInterrupted,
+    Assert,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 23] = [
+        EventKind::Click,
+        EventKind::Move,
+        EventKind::Drag,
+        EventKind::MouseUp,
+        EventKind::Scroll,
+        EventKind::Key,
+        EventKind::KeyUp,
+        EventKind::Text,
+        EventKind::App,
+        EventKind::Window,
+        EventKind::Paste,
+        EventKind::FileChanged,
+        EventKind::Context,
+        EventKind::Screenshot,
+        EventKind::ScreenText,
+        EventKind::Video,
+        EventKind::VideoStopped,
+        EventKind::Annotation,
+        EventKind::Dropped,
+        EventKind::Idle,
+        EventKind::SessionEnd,
+        EventKind::TapInterrupted,
+        EventKind::Assert,
+    ];
+
+    /// The kind with this [`name`](Self::name), case-insensitive
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Name for exports and tables, e.g. `mouse_up`
+    pub fn name(self) -> &'static str {
+        match self {
+            EventKind::Click => "click",
+            EventKind::Move => "move",
+            EventKind::Drag => "drag",
+            EventKind::MouseUp => "mouse_up",
+            EventKind::Scroll => "scroll",
+            EventKind::Key => "key",
+            EventKind::KeyUp => "key_up",
+            EventKind::Text => "text",
+            EventKind::App => "app",
+            EventKind::Window => "window",
+            EventKind::Paste => "paste",
+            EventKind::FileChanged => "file_changed",
+            EventKind::Context => "context",
+            EventKind::Screenshot => "screenshot",
+            EventKind::ScreenText => "screen_text",
+            EventKind::Video => "video",
+            EventKind::VideoStopped => "video_stopped",
+            EventKind::Annotation => "annotation",
+            EventKind::Dropped => "dropped",
+            EventKind::Idle => "idle",
+            EventKind::SessionEnd => "session_end",
+            EventKind::TapInterrupted => "tap_interrupted",
+            EventKind::Assert => "assert",
+        }
+    }
+}
+
+/// What an [`EventData::Assert`] checks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "k", rename_all = "lowercase")]
+pub enum Assertion {
+    /// An element with role `r`, and name `n` if given, is at x, y
+    Element {
+        x: i32,
+        y: i32,
+        r: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        n: Option<String>,
+    },
+    /// The element at x, y has value `v`
+    Value { x: i32, y: i32, v: String },
+    /// The frontmost window's title contains `w`
+    Title { w: String },
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::Element { x, y, r, n: Some(n) } => {
+                write!(f, "{} '{}' at ({}, {})", r, n, x, y)
+            }
+            Assertion::Element { x, y, r, n: None } => write!(f, "{} at ({}, {})", r, x, y),
+            Assertion::Value { x, y, v } => write!(f, "value {:?} at ({}, {})", v, x, y),
+            Assertion::Title { w } => write!(f, "window title contains {:?}", w),
+        }
+    }
+}
+
+/// The element a click landed on
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ElementContext {
+    /// Selector resolving to the element with `Locator`, in the recording
+    /// platform's attributes (`role:AXButton AND title:Save`)
+    pub s: String,
+    /// Role
+    pub r: String,
+    /// Name or title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<String>,
+    /// Value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub v: Option<String>,
+    /// Automation id (AutomationId on Windows)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Bounds on screen: x, y, width, height
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub b: Option<(i32, i32, i32, i32)>,
+    /// Ancestors as selectors, outermost (window) first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub p: Vec<String>,
+    /// Owning app
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub a: Option<String>,
+    /// Owning window title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub w: Option<String>,
+    /// Page URL, when the click was in a browser
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub u: Option<String>,
+}
+
+impl ElementContext {
+    /// Whether `found` looks like this element: same role, and same name
+    /// when one was recorded
+    pub fn matches(&self, found: &ElementContext) -> bool {
+        found.r == self.r && (self.n.is_none() || found.n == self.n)
+    }
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+/// What a clipboard change holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardKind {
+    Text,
+    Image,
+    /// File references, previewed as paths
+    Files,
+}
+
+/// Modifier flags packed into a single byte
+/// Bit 0: shift, 1: ctrl, 2: option/alt, 3: command, 4: capslock, 5: fn
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Modifiers(pub u8);
+
+impl Modifiers {
+    pub const SHIFT: u8 = 1 << 0;
+    pub const CTRL: u8 = 1 << 1;
+    pub const OPT: u8 = 1 << 2;
+    pub const CMD: u8 = 1 << 3;
+    pub const CAPS: u8 = 1 << 4;
+    pub const FN: u8 = 1 << 5;
+
+    pub fn from_cg_flags(flags: u64) -> Self {
+        let mut m = 0u8;
+        if flags & 0x20000 != 0 { m |= Self::SHIFT; }
+        if flags & 0x40000 != 0 { m |= Self::CTRL; }
+        if flags & 0x80000 != 0 { m |= Self::OPT; }
+        if flags & 0x100000 != 0 { m |= Self::CMD; }
+        if flags & 0x10000 != 0 { m |= Self::CAPS; }
+        if flags & 0x800000 != 0 { m |= Self::FN; }
+        Self(m)
+    }
+
+    pub fn has_cmd(&self) -> bool { self.0 & Self::CMD != 0 }
+    pub fn has_ctrl(&self) -> bool { self.0 & Self::CTRL != 0 }
+    pub fn any_modifier(&self) -> bool { self.0 & (Self::CMD | Self::CTRL) != 0 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn workflow(events: &[(u64, EventData)]) -> RecordedWorkflow {
+        let mut workflow = RecordedWorkflow::new("edit");
+        workflow.events =
+            events.iter().map(|(t, data)| Event { t: *t, ts: None, data: data.clone() }).collect();
+        workflow
+    }
+
+    fn times(workflow: &RecordedWorkflow) -> Vec<(u64, EventKind)> {
+        workflow.events.iter().map(|e| (e.t, e.data.kind())).collect()
+    }
+
+    #[test]
+    fn edits_workflows() {
+        let text = |s: &str| EventData::Text { s: s.into() };
+        let recording = workflow(&[
+            (0, EventData::App { n: "TextEdit".into(), p: 1 }),
+            (3000, EventData::Move { x: 1, y: 1 }),
+            (3100, text("he")),
+            (3200, text("llo")),
+            (4000, EventData::Key { k: Key::Return, m: 0 }),
+            (5000, EventData::Key { k: Key::Tab, m: 0 }),
+            (6000, EventData::Key { k: Key::Escape, m: 0 }),
+            (9000, EventData::Idle { d: 3000 }),
+        ]);
+
+        let edited = WorkflowEditor::new(recording.clone())
+            .strip_moves()
+            .merge_text()
+            .trim_idle()
+            .finish();
+        let key = EventKind::Key;
+        let kinds = [EventKind::App, EventKind::Text, key, key, key];
+        assert_eq!(
+            times(&edited),
+            [0, 0, 900, 1900, 2900].into_iter().zip(kinds).collect::<Vec<_>>()
+        );
+        assert!(matches!(&edited.events[1].data, EventData::Text { s } if s == "hello"));
+
+        // Escape takes the deleted Tab's place, then moves up with the
+        // halved gap before it
+        let edited = WorkflowEditor::new(recording).delete(5..6).retime(3..5, 0.5).finish();
+        let t: Vec<u64> = edited.events.iter().map(|e| e.t).collect();
+        assert_eq!(t, [0, 3000, 3100, 3200, 3600, 4600, 7600]);
+
+        // An event out of order stays with the range's first
+        let shuffled = workflow(&[(1000, text("a")), (500, text("b")), (2000, text("c"))]);
+        let edited = WorkflowEditor::new(shuffled).retime(0..3, 2.0).finish();
+        let t: Vec<u64> = edited.events.iter().map(|e| e.t).collect();
+        assert_eq!(t, [1000, 1000, 3000]);
+    }
+
+    #[test]
+    fn appends_workflows() {
+        let mut first = workflow(&[
+            (0, EventData::Text { s: "a".into() }),
+            (500, EventData::SessionEnd { r: "stopped".into() }),
+        ]);
+        first.tags = vec!["billing".into()];
+        let mut second = workflow(&[(2000, EventData::Text { s: "b".into() })]);
+        second.tags = vec!["billing".into(), "monthly".into()];
+        first.append(second);
+        let t: Vec<u64> = first.events.iter().map(|e| e.t).collect();
+        assert_eq!(t, [0, APPEND_GAP_MS]);
+        assert_eq!(first.tags, ["billing", "monthly"]);
+    }
+
+    #[test]
+    fn parses_kind_names() {
+        for kind in EventKind::ALL {
+            assert_eq!(EventKind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(EventKind::from_name(" Mouse_Up"), Some(EventKind::MouseUp));
+        assert_eq!(EventKind::from_name("tap"), None);
+    }
+}
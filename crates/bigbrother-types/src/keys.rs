@@ -0,0 +1,359 @@
+//! Cross-platform virtual keys
+//!
+//! Recordings store a [`Key`] instead of the raw platform keycode, so a
+//! workflow recorded on macOS presses the same key when replayed on Windows
+//! or Linux. Each platform maps to and from its own codes:
+//!
+//! - macOS: Carbon virtual keycodes (ANSI layout)
+//! - Windows: virtual-key codes
+//! - Linux: evdev key codes
+//!
+//! Keys without a portable equivalent are kept as [`Key::Other`] with the
+//! recording platform's raw code.
+
+use crate::events::Modifiers;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// (key, name, macOS keycode, Windows VK, evdev code)
+type Entry = (Key, &'static str, u16, u16, u16);
+
+macro_rules! keys {
+    ($($key:ident = $name:literal, $mac:literal, $win:literal, $evdev:literal;)*) => {
+        /// A physical key, independent of the platform it was captured on
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Key {
+            $($key,)*
+            /// Unmapped key: raw code from the recording platform
+            Other(u16),
+        }
+
+        const KEYS: &[Entry] = &[
+            $((Key::$key, $name, $mac, $win, $evdev),)*
+        ];
+    };
+}
+
+keys! {
+    A = "a", 0, 0x41, 30;
+    B = "b", 11, 0x42, 48;
+    C = "c", 8, 0x43, 46;
+    D = "d", 2, 0x44, 32;
+    E = "e", 14, 0x45, 18;
+    F = "f", 3, 0x46, 33;
+    G = "g", 5, 0x47, 34;
+    H = "h", 4, 0x48, 35;
+    I = "i", 34, 0x49, 23;
+    J = "j", 38, 0x4A, 36;
+    K = "k", 40, 0x4B, 37;
+    L = "l", 37, 0x4C, 38;
+    M = "m", 46, 0x4D, 50;
+    N = "n", 45, 0x4E, 49;
+    O = "o", 31, 0x4F, 24;
+    P = "p", 35, 0x50, 25;
+    Q = "q", 12, 0x51, 16;
+    R = "r", 15, 0x52, 19;
+    S = "s", 1, 0x53, 31;
+    T = "t", 17, 0x54, 20;
+    U = "u", 32, 0x55, 22;
+    V = "v", 9, 0x56, 47;
+    W = "w", 13, 0x57, 17;
+    X = "x", 7, 0x58, 45;
+    Y = "y", 16, 0x59, 21;
+    Z = "z", 6, 0x5A, 44;
+    Num0 = "0", 29, 0x30, 11;
+    Num1 = "1", 18, 0x31, 2;
+    Num2 = "2", 19, 0x32, 3;
+    Num3 = "3", 20, 0x33, 4;
+    Num4 = "4", 21, 0x34, 5;
+    Num5 = "5", 23, 0x35, 6;
+    Num6 = "6", 22, 0x36, 7;
+    Num7 = "7", 26, 0x37, 8;
+    Num8 = "8", 28, 0x38, 9;
+    Num9 = "9", 25, 0x39, 10;
+    F1 = "f1", 122, 0x70, 59;
+    F2 = "f2", 120, 0x71, 60;
+    F3 = "f3", 99, 0x72, 61;
+    F4 = "f4", 118, 0x73, 62;
+    F5 = "f5", 96, 0x74, 63;
+    F6 = "f6", 97, 0x75, 64;
+    F7 = "f7", 98, 0x76, 65;
+    F8 = "f8", 100, 0x77, 66;
+    F9 = "f9", 101, 0x78, 67;
+    F10 = "f10", 109, 0x79, 68;
+    F11 = "f11", 103, 0x7A, 87;
+    F12 = "f12", 111, 0x7B, 88;
+    Return = "return", 36, 0x0D, 28;
+    Tab = "tab", 48, 0x09, 15;
+    Space = "space", 49, 0x20, 57;
+    Backspace = "backspace", 51, 0x08, 14;
+    Escape = "escape", 53, 0x1B, 1;
+    Delete = "delete", 117, 0x2E, 111;
+    Home = "home", 115, 0x24, 102;
+    End = "end", 119, 0x23, 107;
+    PageUp = "pageup", 116, 0x21, 104;
+    PageDown = "pagedown", 121, 0x22, 109;
+    Left = "left", 123, 0x25, 105;
+    Right = "right", 124, 0x27, 106;
+    Up = "up", 126, 0x26, 103;
+    Down = "down", 125, 0x28, 108;
+    Shift = "shift", 56, 0x10, 42;
+    Control = "control", 59, 0x11, 29;
+    Alt = "alt", 58, 0x12, 56;
+    Meta = "meta", 55, 0x5B, 125;
+    CapsLock = "capslock", 57, 0x14, 58;
+    Minus = "minus", 27, 0xBD, 12;
+    Equal = "equal", 24, 0xBB, 13;
+    LeftBracket = "leftbracket", 33, 0xDB, 26;
+    RightBracket = "rightbracket", 30, 0xDD, 27;
+    Backslash = "backslash", 42, 0xDC, 43;
+    Semicolon = "semicolon", 41, 0xBA, 39;
+    Quote = "quote", 39, 0xDE, 40;
+    Grave = "grave", 50, 0xC0, 41;
+    Comma = "comma", 43, 0xBC, 51;
+    Period = "period", 47, 0xBE, 52;
+    Slash = "slash", 44, 0xBF, 53;
+}
+
+/// Right-hand modifiers and alternate codes that fold into a generic key
+const MACOS_ALIASES: &[(u16, Key)] = &[
+    (54, Key::Meta),
+    (60, Key::Shift),
+    (61, Key::Alt),
+    (62, Key::Control),
+    (76, Key::Return), // keypad enter
+];
+const WINDOWS_ALIASES: &[(u16, Key)] = &[
+    (0xA0, Key::Shift),
+    (0xA1, Key::Shift),
+    (0xA2, Key::Control),
+    (0xA3, Key::Control),
+    (0xA4, Key::Alt),
+    (0xA5, Key::Alt),
+    (0x5C, Key::Meta),
+];
+const EVDEV_ALIASES: &[(u16, Key)] = &[
+    (54, Key::Shift),
+    (97, Key::Control),
+    (100, Key::Alt),
+    (126, Key::Meta),
+    (96, Key::Return), // keypad enter
+];
+
+/// Alternate names accepted by [`Key::from_name`]
+const NAME_ALIASES: &[(&str, Key)] = &[
+    ("enter", Key::Return),
+    ("esc", Key::Escape),
+    ("page_up", Key::PageUp),
+    ("page_down", Key::PageDown),
+    ("arrow_up", Key::Up),
+    ("arrow_down", Key::Down),
+    ("arrow_left", Key::Left),
+    ("arrow_right", Key::Right),
+    ("forward_delete", Key::Delete),
+    ("ctrl", Key::Control),
+    ("option", Key::Alt),
+    ("cmd", Key::Meta),
+    ("command", Key::Meta),
+    ("win", Key::Meta),
+    ("super", Key::Meta),
+];
+
+impl Key {
+    fn lookup(find: impl Fn(&Entry) -> bool) -> Option<&'static Entry> {
+        KEYS.iter().find(|entry| find(entry))
+    }
+
+    fn entry(self) -> Option<&'static Entry> {
+        Self::lookup(|e| e.0 == self)
+    }
+
+    fn from_code(code: u16, column: fn(&Entry) -> u16, aliases: &[(u16, Key)]) -> Self {
+        Self::lookup(|e| column(e) == code)
+            .map(|e| e.0)
+            .or_else(|| aliases.iter().find(|(c, _)| *c == code).map(|(_, k)| *k))
+            .unwrap_or(Key::Other(code))
+    }
+
+    pub fn from_macos(keycode: u16) -> Self {
+        Self::from_code(keycode, |e| e.2, MACOS_ALIASES)
+    }
+
+    pub fn from_windows(vk: u16) -> Self {
+        Self::from_code(vk, |e| e.3, WINDOWS_ALIASES)
+    }
+
+    pub fn from_evdev(code: u16) -> Self {
+        Self::from_code(code, |e| e.4, EVDEV_ALIASES)
+    }
+
+    /// macOS virtual keycode (`Other` passes through unchanged)
+    pub fn to_macos(self) -> u16 {
+        match self {
+            Key::Other(code) => code,
+            key => key.entry().map_or(0, |e| e.2),
+        }
+    }
+
+    /// Windows virtual-key code (`Other` passes through unchanged)
+    pub fn to_windows(self) -> u16 {
+        match self {
+            Key::Other(code) => code,
+            key => key.entry().map_or(0, |e| e.3),
+        }
+    }
+
+    /// evdev key code (`Other` passes through unchanged)
+    pub fn to_evdev(self) -> u16 {
+        match self {
+            Key::Other(code) => code,
+            key => key.entry().map_or(0, |e| e.4),
+        }
+    }
+
+    /// Lowercase name ("return", "f5", "a"), `None` for `Other`
+    pub fn name(self) -> Option<&'static str> {
+        self.entry().map(|e| e.1)
+    }
+
+    /// Parse a key name, case-insensitive, with common aliases ("enter", "cmd")
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().to_lowercase();
+        Self::lookup(|e| e.1 == name)
+            .map(|e| e.0)
+            .or_else(|| NAME_ALIASES.iter().find(|(n, _)| *n == name).map(|(_, k)| *k))
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "{:?}", self),
+        }
+    }
+}
+
+// Named keys serialize as their name; `Other` and recordings made before
+// keys were portable use the raw numeric code.
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match (self, self.name()) {
+            (Key::Other(code), _) => serializer.serialize_u16(*code),
+            (_, name) => serializer.serialize_str(name.unwrap_or_default()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Code(u16),
+            Name(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Code(code) => Ok(Key::Other(code)),
+            Repr::Name(name) => Key::from_name(&name)
+                .ok_or_else(|| serde::de::Error::custom(format!("unknown key: {}", name))),
+        }
+    }
+}
+
+/// A key chord like `ctrl+shift+a`, matched against recorded Key events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hotkey {
+    pub key: Key,
+    /// [`Modifiers`] bits
+    pub m: u8,
+}
+
+impl Hotkey {
+    pub fn new(key: Key, m: u8) -> Self {
+        Self { key, m }
+    }
+
+    /// Whether a Key event is this chord; caps lock and fn are ignored
+    pub fn matches(&self, key: Key, m: u8) -> bool {
+        key == self.key && m & !(Modifiers::CAPS | Modifiers::FN) == self.m
+    }
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    /// `+`-separated modifiers then a key name: `ctrl+shift+a`, `cmd+f12`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = parts.pop().unwrap_or_default();
+        let key = Key::from_name(key).ok_or_else(|| format!("unknown key: {}", key))?;
+        let mut m = 0;
+        for part in parts {
+            m |= match part.to_lowercase().as_str() {
+                "shift" => Modifiers::SHIFT,
+                "ctrl" | "control" => Modifiers::CTRL,
+                "alt" | "opt" | "option" => Modifiers::OPT,
+                "cmd" | "command" | "meta" | "super" | "win" => Modifiers::CMD,
+                other => return Err(format!("unknown modifier: {}", other)),
+            };
+        }
+        Ok(Self { key, m })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (bit, name) in [
+            (Modifiers::CTRL, "ctrl"),
+            (Modifiers::OPT, "alt"),
+            (Modifiers::SHIFT, "shift"),
+            (Modifiers::CMD, "cmd"),
+        ] {
+            if self.m & bit != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn maps_between_platforms() {
+        // Return is 36 on macOS, VK_RETURN on Windows, KEY_ENTER on Linux
+        let key = Key::from_macos(36);
+        assert_eq!(key, Key::Return);
+        assert_eq!(key.to_windows(), 0x0D);
+        assert_eq!(key.to_evdev(), 28);
+        assert_eq!(Key::from_windows(0xA1), Key::Shift);
+        assert_eq!(Key::from_evdev(999), Key::Other(999));
+    }
+
+    #[test]
+    fn serializes_names_and_reads_legacy_codes() {
+        assert_eq!(serde_json::to_string(&Key::PageDown).unwrap(), "\"pagedown\"");
+        assert_eq!(serde_json::to_string(&Key::Other(200)).unwrap(), "200");
+        assert_eq!(serde_json::from_str::<Key>("\"Enter\"").unwrap(), Key::Return);
+        assert_eq!(serde_json::from_str::<Key>("36").unwrap(), Key::Other(36));
+    }
+
+    #[test]
+    fn parses_hotkeys() {
+        let hotkey: Hotkey = "Ctrl+Shift+A".parse().unwrap();
+        assert_eq!(hotkey, Hotkey::new(Key::A, Modifiers::CTRL | Modifiers::SHIFT));
+        assert!(hotkey.matches(Key::A, Modifiers::CTRL | Modifiers::SHIFT | Modifiers::CAPS));
+        assert!(!hotkey.matches(Key::A, Modifiers::CTRL));
+        assert_eq!(hotkey.to_string(), "ctrl+shift+a");
+        assert!("hyper+a".parse::<Hotkey>().is_err());
+    }
+}
@@ -0,0 +1,26 @@
+//! bigbrother-types - Data types shared by automation and recording
+//!
+//! Recorded workflows and their events, portable keys, selectors,
+//! structured errors and text helpers, with no platform code: browser dashboards (wasm) and
+//! serverless analyzers can parse recordings and build selectors without
+//! the native automation crates. Without the default `std` feature it's
+//! `no_std`, needing only `alloc`.
+//!
+//! `bigbrother-core` and `bigbrother-recorder` re-export these modules, so
+//! `bigbrother_recorder::events::Event` and `bigbrother_types::events::Event`
+//! are the same type.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod error;
+pub mod events;
+pub mod keys;
+pub mod selector;
+pub mod text;
+
+pub use error::{Error, ErrorCode, Result};
+pub use events::{Event, EventData, EventKind, RecordedWorkflow};
+pub use keys::{Hotkey, Key};
+pub use selector::Selector;
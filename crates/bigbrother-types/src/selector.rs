@@ -6,10 +6,16 @@
 //!   name~:screenpipe         - name contains
 //!   title:Login              - exact title match
 //!   value~:hello             - value contains
+//!   automationid:btnSubmit   - exact AutomationId (Windows)
+//!   class:Chrome_WidgetWin_1 - exact class name (Windows)
 //!   index:42                 - element by index from last tree
 //!   role:Button AND name:Sub - compound selector
 
 use crate::error::{Error, Result};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +38,8 @@ pub enum Attribute {
     Title,
     Value,
     Description,
+    AutomationId,
+    Class,
     Index,
 }
 
@@ -111,10 +119,9 @@ impl Condition {
             Error::selector_invalid(s, "expected format 'attr:value' or 'attr~:value'")
         })?;
 
-        let (attr, op) = if attr_str.ends_with('~') {
-            (&attr_str[..attr_str.len() - 1], MatchOp::Contains)
-        } else {
-            (attr_str, MatchOp::Equals)
+        let (attr, op) = match attr_str.strip_suffix('~') {
+            Some(attr) => (attr, MatchOp::Contains),
+            None => (attr_str, MatchOp::Equals),
         };
 
         let attr = match attr.to_lowercase().as_str() {
@@ -123,6 +130,8 @@ impl Condition {
             "title" => Attribute::Title,
             "value" => Attribute::Value,
             "desc" | "description" => Attribute::Description,
+            "automationid" | "automation_id" | "id" => Attribute::AutomationId,
+            "class" | "classname" | "class_name" => Attribute::Class,
             "index" | "idx" => Attribute::Index,
             _ => {
                 return Err(Error::selector_invalid(
@@ -146,9 +155,15 @@ impl Condition {
             Attribute::Title => title,
             Attribute::Value => value,
             Attribute::Description => desc,
+            // Not covered by this signature, see `matches_value`
+            Attribute::AutomationId | Attribute::Class => None,
             Attribute::Index => return false, // handled separately
         };
+        self.matches_value(target)
+    }
 
+    /// Match against the value of this condition's attribute
+    pub fn matches_value(&self, target: Option<&str>) -> bool {
         match (target, &self.op) {
             (Some(t), MatchOp::Equals) => t == self.value,
             (Some(t), MatchOp::Contains) => t.to_lowercase().contains(&self.value.to_lowercase()),
@@ -157,15 +172,15 @@ impl Condition {
     }
 }
 
-impl std::fmt::Display for Selector {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for Selector {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let parts: Vec<String> = self.conditions.iter().map(|c| c.to_string()).collect();
         write!(f, "{}", parts.join(" AND "))
     }
 }
 
-impl std::fmt::Display for Condition {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl core::fmt::Display for Condition {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let op = match self.op {
             MatchOp::Equals => ":",
             MatchOp::Contains => "~:",
@@ -192,6 +207,13 @@ mod tests {
         assert_eq!(s.conditions[0].op, MatchOp::Contains);
     }
 
+    #[test]
+    fn parse_platform_ids() {
+        let s = Selector::parse("automationid:btnOk AND class:Button").unwrap();
+        assert_eq!(s.conditions[0].attr, Attribute::AutomationId);
+        assert_eq!(s.conditions[1].attr, Attribute::Class);
+    }
+
     #[test]
     fn parse_compound() {
         let s = Selector::parse("role:Button AND name:Submit").unwrap();
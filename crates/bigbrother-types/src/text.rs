@@ -0,0 +1,93 @@
+//! Cutting text for display and storage, and filling in placeholders
+//!
+//! Element values, window titles and clipboard previews are shortened
+//! before they're recorded or printed. Cutting at a byte offset panics in
+//! the middle of a multi-byte character, and cutting at a char boundary can
+//! still split an emoji or an accented letter, so these count grapheme
+//! clusters, what a reader sees as one character.
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::String;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The first `max` grapheme clusters of `s`, all of it when shorter
+pub fn prefix(s: &str, max: usize) -> &str {
+    match s.grapheme_indices(true).nth(max) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+/// `s` if it's at most `max` grapheme clusters, or its start followed by
+/// "..." to make `max`
+pub fn truncate(s: &str, max: usize) -> Cow<'_, str> {
+    if prefix(s, max).len() == s.len() {
+        Cow::Borrowed(s)
+    } else {
+        Cow::Owned(format!("{}...", prefix(s, max.saturating_sub(3))))
+    }
+}
+
+/// `text` with each `{{name}}` placeholder `value` has a value for filled
+/// in. Names are letters, digits, `_`, `-` and `.`, spaces around them
+/// allowed; other braces, and names without a value, are left as they are
+pub fn fill_placeholders<'a, 'v>(
+    text: &'a str,
+    mut value: impl FnMut(&str) -> Option<Cow<'v, str>>,
+) -> Cow<'a, str> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find("{{") {
+        let inner = &rest[open + 2..];
+        let Some(close) = inner.find("}}") else {
+            break;
+        };
+        let name = inner[..close].trim();
+        let valid = !name.is_empty()
+            && name.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        let found = if valid { value(name) } else { None };
+        match found {
+            Some(value) => {
+                out.push_str(&rest[..open]);
+                out.push_str(&value);
+                rest = &inner[close + 2..];
+            }
+            None => {
+                out.push_str(&rest[..open + 2]);
+                rest = inner;
+            }
+        }
+    }
+    if rest.len() == text.len() {
+        return Cow::Borrowed(text);
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cuts_between_graphemes() {
+        assert_eq!(truncate("Save", 10), "Save");
+        assert_eq!(truncate("Save as PDF", 8), "Save ...");
+        // A family emoji is seven chars, and é here is two
+        let text = "👨‍👩‍👧‍👦 cafe\u{301} 🎉🎉🎉";
+        assert_eq!(prefix(text, 1), "👨‍👩‍👧‍👦");
+        assert_eq!(truncate(text, 9), "👨‍👩‍👧‍👦 cafe\u{301}...");
+        assert_eq!(truncate(text, 10), text);
+        assert_eq!(truncate(text, 2), "...");
+    }
+
+    #[test]
+    fn fills_known_placeholders_only() {
+        let value = |name: &str| (name == "user").then_some(Cow::Borrowed("ada"));
+        assert_eq!(fill_placeholders("Hi {{ user }}!", value), "Hi ada!");
+        let text = "{{user}} {{other}} {{ not a name }} {{user";
+        assert_eq!(fill_placeholders(text, value), "ada {{other}} {{ not a name }} {{user");
+        assert!(matches!(fill_placeholders("{}", value), Cow::Borrowed(_)));
+    }
+}
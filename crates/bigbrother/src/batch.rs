@@ -0,0 +1,501 @@
+//! Batch scripts: automation steps run in one go
+//!
+//! A [`Batch`] is a list of steps written by hand in YAML (or JSON, which
+//! is YAML too), run against a single [`Desktop`] instead of a process per
+//! step:
+//!
+//! ```yaml
+//! name: save a note
+//! steps:
+//!   - activate: TextEdit
+//!   - click: "role:AXButton AND title:New Document"
+//!     retries: 2
+//!   - type: hello
+//!   - shortcut: s
+//!   - wait: "role:AXSheet"
+//!   - press: return
+//!   - wait: 500
+//!   - assert: "title:Untitled"
+//!     app: TextEdit
+//!   - applescript: tell application "TextEdit" to close front document
+//! ```
+//!
+//! A bare list of steps works as well. Each step is tried `retries` more
+//! times before the batch stops; [`Batch::run`] reports how every step went.
+//!
+//! `exec` runs a shell command, failing unless it exits 0. A batch from
+//! someone else is their code on this machine, so `exec` steps, and
+//! `applescript` ones, which can `do shell script` just as well, are refused
+//! unless [`Batch::allow_exec`] says otherwise. Named with `as`,
+//! its output can be used in the steps after: `{{name}}` is stdout without
+//! the trailing newline, `{{name.stdout}}`, `{{name.stderr}}` and
+//! `{{name.exit}}` the rest. Other braces are left alone. Commands get
+//! outputs as environment variables instead, `BB_NAME`, `BB_NAME_STDOUT`,
+//! `BB_NAME_STDERR` and `BB_NAME_EXIT`, and AppleScript gets none, so no
+//! output is ever run as code:
+//!
+//! ```yaml
+//! - exec: git rev-parse --short HEAD
+//!   as: commit
+//! - type: "Build {{commit}}"
+//! - exec: git tag "build-$BB_COMMIT"
+//! ```
+
+use crate::exec::{exec, ExecOutput};
+use crate::plan::ELEMENT_TIMEOUT_MS;
+use crate::Desktop;
+use bigbrother_core::error::{Error, ErrorCode};
+use bigbrother_core::Result;
+use bigbrother_recorder::text::fill_placeholders;
+use bigbrother_recorder::Key;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Pause before a failed step is tried again
+const RETRY_DELAY_MS: u64 = 500;
+
+/// Steps to run in order
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Batch {
+    #[serde(default)]
+    pub name: String,
+    pub steps: Vec<BatchStep>,
+    /// Run `exec` and `applescript` steps; never from the batch itself
+    #[serde(skip)]
+    allow_exec: bool,
+}
+
+/// One step of a [`Batch`]: an action and how to go about it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchStep {
+    #[serde(flatten)]
+    pub action: Action,
+    /// Look for elements in this app only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    /// Times to try again after failing
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub retries: u32,
+    /// How long to wait for an element (ms), 5 seconds by default; for a
+    /// command, how long it may run, as long as it takes by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    /// Keep the output as `{{name}}` for the steps after
+    #[serde(default, rename = "as", skip_serializing_if = "Option::is_none")]
+    pub save: Option<String>,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+/// What a [`BatchStep`] does
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Bring an app to the front
+    Activate(String),
+    /// Open a URL in the default browser
+    Open(String),
+    /// Press the element matching a selector, waiting for it to appear
+    Click(String),
+    /// Type into whatever has focus
+    Type(String),
+    /// Press a key by name ("return", "tab", "f5")
+    Press(String),
+    /// Cmd+key on macOS, Ctrl+key elsewhere
+    Shortcut(String),
+    /// Sleep, or wait for an element to appear
+    Wait(Wait),
+    /// Fail unless an element matching a selector shows up in time
+    Assert(String),
+    /// Run AppleScript, for what selectors can't reach (macOS only)
+    Applescript(String),
+    /// Run a shell command, failing unless it exits 0
+    Exec(String),
+}
+
+/// What [`Action::Wait`] waits for
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Wait {
+    /// Milliseconds
+    Ms(u64),
+    /// An element matching a selector
+    Selector(String),
+}
+
+/// How a step went, from [`Batch::run`]
+#[derive(Debug, Clone, Serialize)]
+pub struct StepResult {
+    pub index: usize,
+    /// The step as text, e.g. `click name:OK in Safari`
+    pub step: String,
+    pub ok: bool,
+    pub attempts: u32,
+    /// Time spent on the step, retries included
+    pub ms: u64,
+    /// Why the last attempt failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<Error>,
+    /// What the command of an `exec` step printed, last attempt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<ExecOutput>,
+}
+
+/// How a batch went: the steps that ran, up to the first that failed
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    pub name: String,
+    pub passed: bool,
+    pub steps: Vec<StepResult>,
+}
+
+impl Batch {
+    /// Parse a batch from YAML or JSON, either with a name and `steps` or as
+    /// a bare list of steps
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = |e: serde_yaml::Error| {
+            Error::new(ErrorCode::Unknown, format!("Invalid batch: {}", e))
+        };
+        // Parsed again as what it looks like, for errors with a line number
+        let value: serde_yaml::Value = serde_yaml::from_str(text).map_err(invalid)?;
+        if value.is_sequence() {
+            let steps = serde_yaml::from_str(text).map_err(invalid)?;
+            return Ok(Batch { steps, ..Batch::default() });
+        }
+        serde_yaml::from_str(text).map_err(invalid)
+    }
+
+    /// Run `exec` and `applescript` steps, for batches as trusted as a
+    /// shell script; off by default, when a batch with one runs no step at all
+    pub fn allow_exec(mut self, allow: bool) -> Self {
+        self.allow_exec = allow;
+        self
+    }
+
+    /// Fails, naming the first, when there are `exec` or `applescript`
+    /// steps and they aren't allowed
+    pub fn check_exec(&self) -> Result<()> {
+        match self.refused_exec() {
+            Some(index) => Err(exec_refused(index, &self.steps[index])),
+            None => Ok(()),
+        }
+    }
+
+    fn refused_exec(&self) -> Option<usize> {
+        let exec = self.steps.iter().position(|s| s.runs_code());
+        exec.filter(|_| !self.allow_exec)
+    }
+
+    /// Run the steps in order, stopping at the first that fails for good
+    pub fn run(&self, desktop: &Desktop) -> BatchResult {
+        self.run_with(desktop, |_| {})
+    }
+
+    /// [`run`](Self::run), calling `on_step` as each step finishes
+    pub fn run_with(&self, desktop: &Desktop, mut on_step: impl FnMut(&StepResult)) -> BatchResult {
+        if let Some(index) = self.refused_exec() {
+            let refused = StepResult {
+                index,
+                step: self.steps[index].to_string(),
+                ok: false,
+                attempts: 0,
+                ms: 0,
+                error: Some(exec_refused(index, &self.steps[index])),
+                output: None,
+            };
+            on_step(&refused);
+            return BatchResult { name: self.name.clone(), passed: false, steps: vec![refused] };
+        }
+        let mut steps = Vec::new();
+        let mut outputs = HashMap::new();
+        for (index, step) in self.steps.iter().enumerate() {
+            let result = step.run(index, desktop, &outputs);
+            if let (Some(name), Some(output)) = (&step.save, &result.output) {
+                outputs.insert(name.clone(), output.clone());
+            }
+            on_step(&result);
+            let ok = result.ok;
+            steps.push(result);
+            if !ok {
+                break;
+            }
+        }
+        let passed = steps.len() == self.steps.len() && steps.iter().all(|s| s.ok);
+        BatchResult { name: self.name.clone(), passed, steps }
+    }
+}
+
+impl BatchStep {
+    fn run(
+        &self,
+        index: usize,
+        desktop: &Desktop,
+        outputs: &HashMap<String, ExecOutput>,
+    ) -> StepResult {
+        let start = Instant::now();
+        let mut attempts = 0;
+        let mut output = None;
+        let step = self.substitute(outputs);
+        let env = env(outputs);
+        let error = loop {
+            attempts += 1;
+            match step.attempt(desktop, &env, &mut output) {
+                Ok(()) => break None,
+                Err(e) if attempts > self.retries => break Some(e),
+                Err(_) => std::thread::sleep(Duration::from_millis(RETRY_DELAY_MS)),
+            }
+        };
+        StepResult {
+            index,
+            step: self.to_string(),
+            ok: error.is_none(),
+            attempts,
+            ms: start.elapsed().as_millis() as u64,
+            error,
+            output,
+        }
+    }
+
+    /// Whether the step runs code of its own, a command or a script
+    fn runs_code(&self) -> bool {
+        matches!(self.action, Action::Exec(_) | Action::Applescript(_))
+    }
+
+    /// The step with `{{name}}`s replaced by earlier outputs, except in
+    /// commands, which get them from [`env`], and scripts, which don't
+    fn substitute(&self, outputs: &HashMap<String, ExecOutput>) -> BatchStep {
+        let text = |text: &String| substitute(text, outputs);
+        let action = match &self.action {
+            Action::Activate(app) => Action::Activate(text(app)),
+            Action::Open(url) => Action::Open(text(url)),
+            Action::Click(selector) => Action::Click(text(selector)),
+            Action::Type(typed) => Action::Type(text(typed)),
+            Action::Press(key) => Action::Press(text(key)),
+            Action::Shortcut(key) => Action::Shortcut(text(key)),
+            Action::Wait(Wait::Ms(ms)) => Action::Wait(Wait::Ms(*ms)),
+            Action::Wait(Wait::Selector(selector)) => Action::Wait(Wait::Selector(text(selector))),
+            Action::Assert(selector) => Action::Assert(text(selector)),
+            Action::Applescript(script) => Action::Applescript(script.clone()),
+            Action::Exec(command) => Action::Exec(command.clone()),
+        };
+        let app = self.app.as_ref().map(text);
+        BatchStep { action, app, ..self.clone() }
+    }
+
+    fn attempt(
+        &self,
+        desktop: &Desktop,
+        env: &[(String, String)],
+        output: &mut Option<ExecOutput>,
+    ) -> Result<()> {
+        let timeout = self.timeout.unwrap_or(ELEMENT_TIMEOUT_MS);
+        let wait_for = |selector: &str| {
+            let locator = match &self.app {
+                Some(app) => Desktop::default().in_app(app).locator(selector)?,
+                None => desktop.locator(selector)?,
+            };
+            locator.timeout(timeout).wait()
+        };
+        match &self.action {
+            Action::Activate(app) => desktop.activate(app),
+            Action::Open(url) => desktop.open_url(url),
+            Action::Click(selector) => wait_for(selector)?.click().map(drop),
+            Action::Type(text) => desktop.type_text(text),
+            Action::Press(name) => {
+                let unknown = || Error::new(ErrorCode::Unknown, format!("Unknown key: {}", name));
+                let key = Key::from_name(name).ok_or_else(unknown)?;
+                desktop.press_key(key_code(key))
+            }
+            Action::Shortcut(key) => desktop.cmd(key),
+            Action::Wait(Wait::Ms(ms)) => desktop.wait_idle(*ms),
+            Action::Wait(Wait::Selector(selector)) | Action::Assert(selector) => {
+                wait_for(selector).map(drop)
+            }
+            Action::Applescript(script) => desktop.run_applescript(script).map(drop),
+            Action::Exec(command) => {
+                let ran = exec(command, env, self.timeout.map(Duration::from_millis))?;
+                let status = match ran.exit {
+                    Some(0) => None,
+                    Some(code) => Some(format!("exited {}", code)),
+                    None => Some("was killed".to_string()),
+                };
+                let result = match status {
+                    Some(status) => {
+                        let reason = format!("{} ({})", status, ran.stderr.trim());
+                        Err(Error::action_failed(command, &reason))
+                    }
+                    None => Ok(()),
+                };
+                *output = Some(ran);
+                result
+            }
+        }
+    }
+}
+
+fn exec_refused(index: usize, step: &BatchStep) -> Error {
+    let what = match step.action {
+        Action::Applescript(_) => "AppleScript",
+        _ => "a command",
+    };
+    let message = format!("Step {} runs {}, and code isn't allowed to run here", index + 1, what);
+    Error::permission_denied(message)
+}
+
+/// `text` with `{{name}}`, `{{name.stdout}}`, `{{name.stderr}}` and
+/// `{{name.exit}}` filled in for the outputs there are
+fn substitute(text: &str, outputs: &HashMap<String, ExecOutput>) -> String {
+    let filled = fill_placeholders(text, |reference| {
+        let (name, field) = reference.split_once('.').unwrap_or((reference, "text"));
+        let output = outputs.get(name)?;
+        match field {
+            "text" => Some(Cow::Borrowed(output.text())),
+            "stdout" => Some(Cow::Borrowed(output.stdout.as_str())),
+            "stderr" => Some(Cow::Borrowed(output.stderr.as_str())),
+            "exit" => Some(Cow::Owned(output.exit.map_or(String::new(), |c| c.to_string()))),
+            _ => None,
+        }
+    });
+    filled.into_owned()
+}
+
+/// Outputs as environment variables for commands: `BB_NAME` for
+/// `{{name}}`, `BB_NAME_STDOUT` for `{{name.stdout}}` and so on
+fn env(outputs: &HashMap<String, ExecOutput>) -> Vec<(String, String)> {
+    let mut env = Vec::new();
+    for (name, output) in outputs {
+        let var: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        let exit = output.exit.map_or(String::new(), |c| c.to_string());
+        env.push((format!("BB_{}", var), output.text().to_string()));
+        env.push((format!("BB_{}_STDOUT", var), output.stdout.clone()));
+        env.push((format!("BB_{}_STDERR", var), output.stderr.clone()));
+        env.push((format!("BB_{}_EXIT", var), exit));
+    }
+    env
+}
+
+/// The platform keycode [`Desktop::press_key`] takes
+fn key_code(key: Key) -> u8 {
+    if cfg!(target_os = "windows") {
+        key.to_windows() as u8
+    } else {
+        key.to_macos() as u8
+    }
+}
+
+impl fmt::Display for BatchStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.action {
+            Action::Activate(app) => write!(f, "activate {}", app),
+            Action::Open(url) => write!(f, "open {}", url),
+            Action::Click(selector) => write!(f, "click {}", selector),
+            Action::Type(text) => write!(f, "type {:?}", text),
+            Action::Press(key) => write!(f, "press {}", key),
+            Action::Shortcut(key) => write!(f, "shortcut cmd/ctrl+{}", key),
+            Action::Wait(Wait::Ms(ms)) => write!(f, "wait {}ms", ms),
+            Action::Wait(Wait::Selector(selector)) => write!(f, "wait for {}", selector),
+            Action::Assert(selector) => write!(f, "assert {}", selector),
+            Action::Applescript(script) => match script.lines().count() {
+                0 | 1 => write!(f, "applescript {}", script),
+                lines => write!(f, "applescript ({} lines)", lines),
+            },
+            Action::Exec(command) => write!(f, "exec {}", command),
+        }?;
+        if let Some(app) = &self.app {
+            write!(f, " in {}", app)?;
+        }
+        match &self.save {
+            Some(name) => write!(f, " as {}", name),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_yaml_and_json() {
+        let yaml = "
+name: save
+steps:
+  - activate: TextEdit
+  - click: \"role:AXButton AND title:Save\"
+    app: TextEdit
+    retries: 2
+  - type: hello
+  - wait: 500
+  - wait: \"title:Saved\"
+  - press: return
+";
+        let batch = Batch::parse(yaml).unwrap();
+        assert_eq!(batch.name, "save");
+        let steps: Vec<String> = batch.steps.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            steps,
+            [
+                "activate TextEdit",
+                "click role:AXButton AND title:Save in TextEdit",
+                "type \"hello\"",
+                "wait 500ms",
+                "wait for title:Saved",
+                "press return",
+            ]
+        );
+        assert_eq!(batch.steps[1].retries, 2);
+
+        let json = r#"[{"shortcut": "s"}, {"assert": "title:Saved", "timeout": 100},
+            {"applescript": "tell application \"Mail\"\ncheck for new mail\nend tell"}]"#;
+        let batch = Batch::parse(json).unwrap();
+        assert_eq!(batch.steps[1].timeout, Some(100));
+        assert_eq!(batch.steps[0].to_string(), "shortcut cmd/ctrl+s");
+        assert_eq!(batch.steps[2].to_string(), "applescript (3 lines)");
+
+        let err = Batch::parse("steps:\n  - fly: away\n").unwrap_err();
+        assert!(err.message.contains("steps[0]"), "{}", err.message);
+        let err = Batch::parse("- type: a\n- fly: away\n").unwrap_err();
+        assert!(err.message.contains("[1]"), "{}", err.message);
+    }
+
+    #[test]
+    fn runs_commands_with_their_outputs() {
+        let yaml = "
+- exec: echo hi
+  as: greeting
+- exec: echo \"$BB_GREETING\" there {{greeting}}
+  as: both
+- exec: exit 3
+";
+        let batch = Batch::parse(yaml).unwrap();
+        let refused = batch.run(&Desktop::default());
+        assert_eq!(refused.steps.len(), 1);
+        assert_eq!(refused.steps[0].error.as_ref().unwrap().code, ErrorCode::PermissionDenied);
+
+        let result = batch.allow_exec(true).run(&Desktop::default());
+        assert!(!result.passed);
+        let output = |i: usize| result.steps[i].output.as_ref().unwrap();
+        // Output is data to commands, never code
+        assert_eq!(output(1).text(), "hi there {{greeting}}");
+        assert_eq!(output(2).exit, Some(3));
+        assert_eq!(result.steps[2].error.as_ref().unwrap().code, ErrorCode::ActionFailed);
+
+        let outputs = HashMap::from([("greeting".to_string(), output(0).clone())]);
+        let typed = "{{ greeting.exit }} {{greeting}} {{nope}} {{greeting.size}} {x}";
+        assert_eq!(substitute(typed, &outputs), "0 hi {{nope}} {{greeting.size}} {x}");
+        let script = Batch::parse("- applescript: display dialog \"{{greeting}}\"").unwrap();
+        let step = script.steps[0].substitute(&outputs);
+        assert!(matches!(&step.action, Action::Applescript(s) if s.contains("{{greeting}}")));
+        let env = env(&HashMap::from([("my-out".to_string(), output(0).clone())]));
+        assert!(env.contains(&("BB_MY_OUT".to_string(), "hi".to_string())));
+        assert!(env.contains(&("BB_MY_OUT_EXIT".to_string(), "0".to_string())));
+    }
+}
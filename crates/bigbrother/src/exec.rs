@@ -0,0 +1,100 @@
+//! Shell commands between UI steps
+//!
+//! [`exec`] runs a command through the shell (`sh -c`, `cmd /C` on
+//! Windows) and captures what it printed and how it exited, for
+//! [`Batch`](crate::Batch) steps and anything else mixing the command line
+//! with UI automation. A command exiting non-zero isn't an error here, the
+//! [`ExecOutput`] tells.
+
+use bigbrother_core::error::{Error, ErrorCode};
+use bigbrother_core::Result;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// How often a running command is checked on
+const POLL_MS: u64 = 10;
+
+/// What a command printed and how it exited
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExecOutput {
+    pub stdout: String,
+    pub stderr: String,
+    /// None when it was ended by a signal
+    pub exit: Option<i32>,
+    pub ms: u64,
+}
+
+impl ExecOutput {
+    pub fn success(&self) -> bool {
+        self.exit == Some(0)
+    }
+
+    /// Stdout without the trailing newline, as substituted for `{{name}}`
+    pub fn text(&self) -> &str {
+        self.stdout.trim_end_matches(['\n', '\r'])
+    }
+}
+
+/// Run `command` through the shell with `env` added to its environment,
+/// killing it after `timeout`
+pub fn exec(
+    command: &str,
+    env: &[(String, String)],
+    timeout: Option<Duration>,
+) -> Result<ExecOutput> {
+    let mut shell = if cfg!(target_os = "windows") {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let start = Instant::now();
+    let mut child = shell
+        .arg(command)
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::action_failed("exec", &e.to_string()))?;
+    let (stdout, stderr) = (drain(child.stdout.take()), drain(child.stderr.take()));
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+            let _ = child.kill();
+            let _ = child.wait();
+            let ms = start.elapsed().as_millis() as u64;
+            return Err(Error::new(
+                ErrorCode::Timeout,
+                format!("Command still running after {}ms: {}", ms, command),
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(POLL_MS));
+    };
+    Ok(ExecOutput {
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+        exit: status.code(),
+        ms: start.elapsed().as_millis() as u64,
+    })
+}
+
+/// Read a pipe as the command runs, so a full one can't stall it
+fn drain(pipe: Option<impl Read + Send + 'static>) -> JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut out = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut out);
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    })
+}
@@ -0,0 +1,250 @@
+//! Plans exported as standalone scripts
+//!
+//! [`script`] writes a [`Plan`] out as code to edit and run on its own: a
+//! Rust program calling the bigbrother API, or a Python script driving the
+//! `bb` CLI. Clicks with element context go through locators, the rest
+//! through coordinates.
+
+use crate::plan::{Plan, PlanStep};
+use bigbrother_recorder::{Event, EventData, Modifiers};
+use std::fmt::Write;
+
+/// Pauses between raw events shorter than this (ms) aren't written out
+const MIN_PAUSE_MS: u64 = 100;
+
+/// Language of an exported script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptFormat {
+    Rust,
+    Python,
+}
+
+impl std::str::FromStr for ScriptFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rust" | "rs" => Ok(ScriptFormat::Rust),
+            "python" | "py" => Ok(ScriptFormat::Python),
+            other => Err(format!("unknown script format: {} (rust or python)", other)),
+        }
+    }
+}
+
+/// `plan` as a runnable script
+pub fn script(plan: &Plan, format: ScriptFormat) -> String {
+    match format {
+        ScriptFormat::Rust => rust(plan),
+        ScriptFormat::Python => python(plan),
+    }
+}
+
+fn rust(plan: &Plan) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "//! {}, exported from a bigbrother recording", plan.name);
+    out.push_str("//!\n//! Needs `bigbrother` and `anyhow` as dependencies.\n\n");
+    // Raw events need an injector
+    let raw = plan.steps.iter().any(|s| matches!(s, PlanStep::Replay { .. }));
+    out.push_str("use bigbrother::prelude::*;\n");
+    if raw {
+        out.push_str("use bigbrother::recorder::platform::current::NativeInjector;\n");
+        out.push_str("use std::time::Duration;\n");
+    }
+    out.push_str("\nfn main() -> anyhow::Result<()> {\n");
+    out.push_str("    let desktop = Desktop::new()?;\n");
+    if raw {
+        out.push_str("    let input = NativeInjector::default();\n");
+    }
+    for step in &plan.steps {
+        let line = match step {
+            PlanStep::Activate { app } => format!("desktop.activate({:?})?;", app),
+            PlanStep::OpenUrl { url } => format!("desktop.open_url({:?})?;", url),
+            PlanStep::Click { selector, app: Some(app) } => format!(
+                "Desktop::new()?.in_app({:?}).locator({:?})?.timeout(5000).wait()?.click()?;",
+                app, selector
+            ),
+            PlanStep::Click { selector, app: None } => {
+                format!("desktop.locator({:?})?.timeout(5000).wait()?.click()?;", selector)
+            }
+            PlanStep::TypeText { text } => format!("desktop.type_text({:?})?;", text),
+            PlanStep::Shortcut { key } => format!("desktop.cmd({:?})?;", key),
+            PlanStep::Replay { events } => {
+                raw_events(&mut out, events, "    ", rust_event, |ms| {
+                    format!("std::thread::sleep(Duration::from_millis({}));", ms)
+                });
+                continue;
+            }
+        };
+        let _ = writeln!(out, "    {}", line);
+    }
+    out.push_str("    Ok(())\n}\n");
+    out
+}
+
+fn rust_event(data: &EventData) -> String {
+    match data {
+        EventData::Click { x, y, b, n, m, .. } => {
+            format!("input.click({}, {}, {}, {}, {})?;", x, y, b, n, rust_modifiers(*m))
+        }
+        EventData::Drag { x, y, x2, y2, b, p, m, d } => {
+            let path: Vec<String> = std::iter::once(&(*x, *y))
+                .chain(p)
+                .chain(std::iter::once(&(*x2, *y2)))
+                .map(|(x, y)| format!("({}, {})", x, y))
+                .collect();
+            format!(
+                "input.drag(&[{}], {}, {}, Duration::from_millis({}))?;",
+                path.join(", "),
+                b,
+                rust_modifiers(*m),
+                d
+            )
+        }
+        EventData::Scroll { x, y, dx, dy, px } => {
+            format!("input.scroll({}, {}, {}, {}, {})?;", x, y, dx, dy, px)
+        }
+        EventData::Key { k, m } => format!("input.key(Key::{:?}, {})?;", k, rust_modifiers(*m)),
+        other => format!("// {}", other),
+    }
+}
+
+/// Packed `Modifiers` as a Rust expression
+fn rust_modifiers(m: u8) -> String {
+    let names: Vec<&str> = [
+        (Modifiers::SHIFT, "Modifiers::SHIFT"),
+        (Modifiers::CTRL, "Modifiers::CTRL"),
+        (Modifiers::OPT, "Modifiers::OPT"),
+        (Modifiers::CMD, "Modifiers::CMD"),
+    ]
+    .into_iter()
+    .filter(|(bit, _)| m & bit != 0)
+    .map(|(_, name)| name)
+    .collect();
+    match names.is_empty() {
+        true => "0".to_string(),
+        false => names.join(" | "),
+    }
+}
+
+fn python(plan: &Plan) -> String {
+    let mut out = String::new();
+    out.push_str("#!/usr/bin/env python3\n");
+    let _ = writeln!(out, "\"\"\"{}, exported from a bigbrother recording", plan.name);
+    out.push_str("\nRuns each step through the `bb` CLI, which must be on PATH.\n\"\"\"\n\n");
+    out.push_str("import subprocess\nimport time\n\n\n");
+    out.push_str("def bb(*args):\n    subprocess.run([\"bb\", *map(str, args)], check=True)\n\n\n");
+    for step in &plan.steps {
+        let line = match step {
+            PlanStep::Activate { app } => format!("bb(\"activate\", {})", py_str(app)),
+            PlanStep::OpenUrl { url } => format!("bb(\"open\", {})", py_str(url)),
+            PlanStep::Click { selector, app: Some(app) } => {
+                format!("bb(\"click\", {}, \"--app\", {})", py_str(selector), py_str(app))
+            }
+            PlanStep::Click { selector, app: None } => {
+                format!("bb(\"click\", {})", py_str(selector))
+            }
+            PlanStep::TypeText { text } => format!("bb(\"type\", {})", py_str(text)),
+            PlanStep::Shortcut { key } => format!("bb(\"shortcut\", {})", py_str(key)),
+            PlanStep::Replay { events } => {
+                raw_events(&mut out, events, "", python_event, |ms| {
+                    format!("time.sleep({})", ms as f64 / 1000.0)
+                });
+                continue;
+            }
+        };
+        let _ = writeln!(out, "{}", line);
+    }
+    out
+}
+
+fn python_event(data: &EventData) -> String {
+    match data {
+        EventData::Click { x, y, b, n, .. } => {
+            let button = match (b, n) {
+                (_, 2) => "double",
+                (1, _) => "right",
+                (2, _) => "middle",
+                _ => "left",
+            };
+            format!("bb(\"click-at\", {}, {}, \"--button\", \"{}\")", x, y, button)
+        }
+        EventData::Key { k, m } => match k.name() {
+            Some(name) if *m & !(Modifiers::CAPS | Modifiers::FN) == 0 => {
+                format!("bb(\"press\", \"{}\")", name)
+            }
+            Some(name) => {
+                // `Hotkey` prints "cmd+shift+", the CLI takes "cmd,shift"
+                let hotkey = bigbrother_recorder::Hotkey::new(*k, *m).to_string();
+                let modifiers = hotkey.trim_end_matches(name).trim_end_matches('+');
+                let modifiers = modifiers.replace('+', ",");
+                format!("bb(\"shortcut\", \"{}\", \"--modifiers\", \"{}\")", name, modifiers)
+            }
+            None => format!("# not supported by the bb CLI: {}", data),
+        },
+        other => format!("# not supported by the bb CLI: {}", other),
+    }
+}
+
+/// A string literal Python reads back as `s`
+fn py_str(s: &str) -> String {
+    // JSON string escapes are valid in Python
+    serde_json::to_string(s).unwrap_or_default()
+}
+
+/// Write raw events a line each, with the recorded pauses between them
+fn raw_events(
+    out: &mut String,
+    events: &[Event],
+    indent: &str,
+    line: fn(&EventData) -> String,
+    pause: impl Fn(u64) -> String,
+) {
+    let mut last_t: Option<u64> = None;
+    for event in events {
+        if let EventData::KeyUp { .. } = event.data {
+            continue;
+        }
+        let gap = last_t.map_or(0, |t| event.t.saturating_sub(t));
+        if gap >= MIN_PAUSE_MS {
+            let _ = writeln!(out, "{}{}", indent, pause(gap));
+        }
+        last_t = Some(event.t);
+        let _ = writeln!(out, "{}{}", indent, line(&event.data));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigbrother_recorder::Key;
+
+    fn event(t: u64, data: EventData) -> Event {
+        Event { t, ts: None, data }
+    }
+
+    #[test]
+    fn writes_rust_and_python() {
+        let click = EventData::Click { x: 1, y: 2, b: 1, n: 1, m: 0, c: None };
+        let save_as = EventData::Key { k: Key::S, m: Modifiers::CMD | Modifiers::SHIFT };
+        let plan = Plan {
+            name: "save".into(),
+            steps: vec![
+                PlanStep::Activate { app: "TextEdit".into() },
+                PlanStep::TypeText { text: "say \"hi\"\n".into() },
+                PlanStep::Replay { events: vec![event(0, click), event(500, save_as)] },
+            ],
+        };
+
+        let rust = script(&plan, ScriptFormat::Rust);
+        assert!(rust.contains("    desktop.type_text(\"say \\\"hi\\\"\\n\")?;\n"));
+        assert!(rust.contains("    input.click(1, 2, 1, 1, 0)?;\n"));
+        assert!(rust.contains("    std::thread::sleep(Duration::from_millis(500));\n"));
+        assert!(rust.contains("    input.key(Key::S, Modifiers::SHIFT | Modifiers::CMD)?;\n"));
+
+        let python = script(&plan, ScriptFormat::Python);
+        assert!(python.contains("\nbb(\"activate\", \"TextEdit\")\n"));
+        assert!(python.contains("\nbb(\"click-at\", 1, 2, \"--button\", \"right\")\n"));
+        assert!(python.contains("\ntime.sleep(0.5)\n"));
+        assert!(python.contains("\nbb(\"shortcut\", \"s\", \"--modifiers\", \"shift,cmd\")\n"));
+    }
+}
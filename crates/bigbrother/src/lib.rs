@@ -9,24 +9,26 @@
 //! - **Recording**: Capture all user interactions
 //! - **Replay**: Temporal manipulation of recorded workflows
 //! - **Automation**: Direct control of the desktop
-//! - **Cross-platform**: macOS and Windows (Linux coming)
+//! - **Cross-platform**: macOS and Windows (Linux record & replay only)
 //!
 //! ## Quick Start
 //!
 //! ```rust,no_run
 //! use bigbrother::prelude::*;
 //!
-//! // Automation (macOS only for now)
+//! # fn main() -> anyhow::Result<()> {
+//! // Automation (macOS and Windows)
 //! let desktop = Desktop::new()?;
 //! desktop.locator("role:Button")?.click()?;
 //!
-//! // Recording (macOS only for now)
+//! // Recording
 //! let recorder = WorkflowRecorder::new();
 //! let stream = recorder.stream()?;
 //! for event in stream {
 //!     println!("{:?}", event);
 //! }
-//! # Ok::<(), anyhow::Error>(())
+//! # Ok(())
+//! # }
 //! ```
 
 // Re-export core automation
@@ -35,38 +37,55 @@ pub use bigbrother_core::*;
 // Re-export recorder module
 pub use bigbrother_recorder as recorder;
 
-// Re-export common types (cross-platform)
-pub use bigbrother_recorder::{Event, EventData, Modifiers, RecordedWorkflow, WorkflowStorage};
+// Recordings compiled to automation, and hand-written batches of steps
+pub mod batch;
+pub mod exec;
+pub mod export;
+pub mod plan;
 
-// Re-export platform-specific types
-#[cfg(target_os = "macos")]
-pub use bigbrother_recorder::{
-    EventStream, RecorderConfig, RecordingHandle, Replayer, WorkflowRecorder,
-};
+// Operations by name and JSON parameters, for servers and bindings
+pub mod ops;
+pub use batch::{Batch, BatchStep};
+pub use plan::{Plan, PlanStep};
+
+// Spans exported to an OpenTelemetry collector
+#[cfg(feature = "otlp")]
+pub mod telemetry;
 
-#[cfg(target_os = "windows")]
+// Re-export recording types (cross-platform)
 pub use bigbrother_recorder::{
-    EventStream, PermissionStatus, RecorderConfig, RecordingHandle, ReplayStats, Replayer,
-    WorkflowRecorder,
+    Assertion, AssertionResult, CancellationToken, CaptureHealth, Compression, Event, EventData,
+    EventIterExt, EventKind, EventReader, EventStream, Failure, Format, Hotkey, ImportFormat,
+    Injector, Key, Modifiers, Overflow, Permission, PermissionState, PermissionStatus, Progress,
+    Pruned, RecordedWorkflow, RecorderConfig, RecorderError, RecordingHandle, RecordingStats,
+    Recovery, ReplayObserver, ReplayStats, Replayer, RetentionPolicy, Schedule, Step, Subscription,
+    TableFormat, WorkflowEditor, WorkflowInfo, WorkflowQuery, WorkflowRecorder, WorkflowStorage,
 };
+#[cfg(feature = "async")]
+pub use bigbrother_recorder::AsyncEventStream;
+#[cfg(feature = "sqlite")]
+pub use bigbrother_recorder::{EventQuery, SqliteStore, StoredEvent, StoredWorkflow};
 
 /// Prelude - import everything you need
 pub mod prelude {
     // Core automation
     pub use bigbrother_core::prelude::*;
 
-    // Recording - common types
-    pub use bigbrother_recorder::{Event, EventData, Modifiers, RecordedWorkflow, WorkflowStorage};
-
-    // Recording - platform-specific
-    #[cfg(target_os = "macos")]
+    // Recording
     pub use bigbrother_recorder::{
-        EventStream, RecorderConfig, RecordingHandle, Replayer, WorkflowRecorder,
+        Assertion, AssertionResult, CancellationToken, CaptureHealth, Compression, Event, EventData,
+        EventIterExt, EventKind, EventReader, EventStream, Failure, Format, Hotkey, ImportFormat,
+        Injector, Key, Modifiers, Overflow, Permission, PermissionState, PermissionStatus, Progress,
+        Pruned, RecordedWorkflow, RecorderConfig, RecorderError, RecordingHandle, RecordingStats,
+        Recovery, ReplayObserver, ReplayStats, Replayer, RetentionPolicy, Schedule, Step,
+        Subscription, TableFormat, WorkflowEditor, WorkflowInfo, WorkflowQuery, WorkflowRecorder,
+        WorkflowStorage,
     };
+    #[cfg(feature = "async")]
+    pub use bigbrother_recorder::AsyncEventStream;
+    #[cfg(feature = "sqlite")]
+    pub use bigbrother_recorder::{EventQuery, SqliteStore, StoredEvent};
 
-    #[cfg(target_os = "windows")]
-    pub use bigbrother_recorder::{
-        EventStream, PermissionStatus, RecorderConfig, RecordingHandle, ReplayStats, Replayer,
-        WorkflowRecorder,
-    };
+    pub use crate::batch::{Batch, BatchStep};
+    pub use crate::plan::{Plan, PlanStep};
 }
@@ -0,0 +1,243 @@
+//! Automation operations by name, with a JSON object of parameters
+//!
+//! What `bb serve` answers over HTTP and `bigbrother-ffi` over C, so both
+//! take the same operations with the same parameters and errors:
+//!
+//! ```rust,no_run
+//! use bigbrother::ops::Session;
+//! use serde_json::json;
+//!
+//! # fn main() -> bigbrother::Result<()> {
+//! let mut session = Session::new();
+//! let found = session.call("find", &json!({"selector": "role:Button"}))?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`OPERATIONS`] describes each, for help and API schemas.
+
+use crate::{Batch, Desktop, Locator};
+use bigbrother_core::error::{Error, ErrorCode};
+use bigbrother_core::Result;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Tree and scrape depth when none is given
+pub const DEFAULT_DEPTH: usize = 15;
+
+/// How long element lookups wait when no `timeout` is given (ms)
+pub const DEFAULT_TIMEOUT_MS: u64 = 5000;
+
+/// An operation: name, what it does, and its parameters as (name, JSON
+/// schema type, required)
+pub type Operation = (&'static str, &'static str, &'static [(&'static str, &'static str, bool)]);
+
+/// What [`Session::call`] takes
+pub const OPERATIONS: &[Operation] = &[
+    ("apps", "Running applications", &[]),
+    ("focused", "Element with keyboard focus", &[]),
+    ("inspect", "Element at a screen point", &[("x", "number", true), ("y", "number", true)]),
+    ("tree", "Accessibility tree of an app, numbered for click", &[
+        ("app", "string", true),
+        ("depth", "integer", false),
+        ("max_nodes", "integer", false),
+        ("start", "integer", false),
+    ]),
+    ("find", "Elements matching a selector", &[
+        ("selector", "string", true),
+        ("app", "string", false),
+        ("timeout", "integer", false),
+    ]),
+    ("click", "Press an element, by selector or by index in the last tree", &[
+        ("selector", "string", false),
+        ("index", "integer", false),
+        ("app", "string", false),
+    ]),
+    ("type", "Type text, into an element or whatever has focus", &[
+        ("text", "string", true),
+        ("selector", "string", false),
+        ("app", "string", false),
+    ]),
+    ("scrape", "Text of an app", &[("app", "string", true), ("depth", "integer", false)]),
+    ("activate", "Bring an app to the front", &[("app", "string", true)]),
+    ("shortcut", "Cmd+key on macOS, Ctrl+key elsewhere", &[("key", "string", true)]),
+    ("open", "Open a URL in the default browser", &[("url", "string", true)]),
+    ("wait", "Sleep, or wait for an element to appear", &[
+        ("ms", "integer", false),
+        ("selector", "string", false),
+        ("app", "string", false),
+        ("timeout", "integer", false),
+    ]),
+    ("run", "Run a batch of steps, as in bb run", &[
+        ("steps", "array", true),
+        ("name", "string", false),
+    ]),
+];
+
+/// A desktop connection, made on first use, that keeps the last tree for
+/// clicks by index
+#[derive(Default)]
+pub struct Session {
+    desktop: Option<Desktop>,
+    allow_exec: bool,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let `run` batches have `exec` and `applescript` steps, which run shell
+    /// commands and scripts; off by default, since whoever calls can then do anything this user can
+    pub fn allow_exec(mut self, allow: bool) -> Self {
+        self.allow_exec = allow;
+        self
+    }
+
+    /// Run `method` with the parameters in `p`, a JSON object
+    pub fn call(&mut self, method: &str, p: &Value) -> Result<Value> {
+        Ok(match method {
+            "apps" => json!(self.desktop()?.apps()?),
+            "focused" => json!(self.desktop()?.focused()?.info()),
+            "inspect" => json!(self.desktop()?.element_at(need(p, "x")?, need(p, "y")?)?.info()),
+            "tree" => {
+                let app = need::<String>(p, "app")?;
+                let depth = get(p, "depth")?.unwrap_or(DEFAULT_DEPTH);
+                let start = get(p, "start")?.unwrap_or(0);
+                let max_nodes = get(p, "max_nodes")?.unwrap_or(usize::MAX);
+                json!(self.desktop()?.tree_page(&app, depth, start, max_nodes)?)
+            }
+            "find" => {
+                let found = self.locator(p, &need::<String>(p, "selector")?)?.find_all()?;
+                json!(found.iter().map(|e| e.info()).collect::<Vec<_>>())
+            }
+            "click" => match (get::<usize>(p, "index")?, get::<String>(p, "selector")?) {
+                (Some(index), _) => json!(self.desktop()?.element_by_index(index)?.click()?),
+                (None, Some(selector)) => json!(self.locator(p, &selector)?.click()?),
+                (None, None) => return Err(invalid("Pass a selector or an index")),
+            },
+            "type" => {
+                let text: String = need(p, "text")?;
+                match get::<String>(p, "selector")? {
+                    Some(selector) => json!(self.locator(p, &selector)?.type_text(&text)?),
+                    None => {
+                        self.desktop()?.type_text(&text)?;
+                        json!({"typed": text})
+                    }
+                }
+            }
+            "scrape" => {
+                let depth = get(p, "depth")?.unwrap_or(DEFAULT_DEPTH);
+                json!(self.desktop()?.scrape(&need::<String>(p, "app")?, depth)?)
+            }
+            "activate" => {
+                let app: String = need(p, "app")?;
+                self.desktop()?.activate(&app)?;
+                json!({"activated": app})
+            }
+            "shortcut" => {
+                let key: String = need(p, "key")?;
+                self.desktop()?.cmd(&key)?;
+                json!({"shortcut": key})
+            }
+            "open" => {
+                let url: String = need(p, "url")?;
+                self.desktop()?.open_url(&url)?;
+                json!({"opened": url})
+            }
+            "wait" => match (get::<u64>(p, "ms")?, get::<String>(p, "selector")?) {
+                (_, Some(selector)) => json!(self.locator(p, &selector)?.wait()?.info()),
+                (Some(ms), None) => {
+                    std::thread::sleep(Duration::from_millis(ms));
+                    json!({"waited_ms": ms})
+                }
+                (None, None) => return Err(invalid("Pass ms or a selector")),
+            },
+            "run" => {
+                let batch: Batch = serde_json::from_value(p.clone())
+                    .map_err(|e| invalid(format!("Invalid batch: {}", e)))?;
+                let batch = batch.allow_exec(self.allow_exec);
+                batch.check_exec()?;
+                json!(batch.run(self.desktop()?))
+            }
+            _ => {
+                let names = OPERATIONS.iter().map(|(name, ..)| name.to_string()).collect();
+                return Err(invalid(format!("No operation {}", method)).with_suggestions(names));
+            }
+        })
+    }
+
+    /// The desktop, connecting on first use
+    pub fn desktop(&mut self) -> Result<&mut Desktop> {
+        if self.desktop.is_none() {
+            self.desktop = Some(Desktop::new()?);
+        }
+        Ok(self.desktop.as_mut().unwrap())
+    }
+
+    /// A locator for `selector`, in the `app` parameter's app if given, with
+    /// the `timeout` parameter
+    fn locator(&mut self, p: &Value, selector: &str) -> Result<Locator> {
+        let timeout = get(p, "timeout")?.unwrap_or(DEFAULT_TIMEOUT_MS);
+        let locator = match get::<String>(p, "app")? {
+            Some(app) => Desktop::default().in_app(&app).locator(selector)?,
+            None => self.desktop()?.locator(selector)?,
+        };
+        Ok(locator.timeout(timeout))
+    }
+}
+
+/// Whether [`Session::call`] takes `method`
+pub fn known(method: &str) -> bool {
+    OPERATIONS.iter().any(|(name, ..)| *name == method)
+}
+
+/// Parameter `name`, if given and not null
+pub fn get<T: DeserializeOwned>(params: &Value, name: &str) -> Result<Option<T>> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => serde_json::from_value(value.clone())
+            .map(Some)
+            .map_err(|e| invalid(format!("Invalid parameter {}: {}", name, e))),
+    }
+}
+
+/// Parameter `name`, failing when it's missing
+pub fn need<T: DeserializeOwned>(params: &Value, name: &str) -> Result<T> {
+    get(params, name)?.ok_or_else(|| invalid(format!("Missing parameter: {}", name)))
+}
+
+fn invalid(message: impl Into<String>) -> Error {
+    Error::new(ErrorCode::Unknown, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_methods_and_parameters() {
+        let mut session = Session::new();
+        let err = session.call("fly", &json!({})).unwrap_err();
+        assert_eq!(err.message, "No operation fly");
+        assert_eq!(err.suggestions.len(), OPERATIONS.len());
+        let err = session.call("tree", &json!({})).unwrap_err();
+        assert_eq!(err.message, "Missing parameter: app");
+        let err = session.call("tree", &json!({"app": 3})).unwrap_err();
+        assert!(err.message.starts_with("Invalid parameter app: "), "{}", err.message);
+        assert!(OPERATIONS.iter().all(|(name, ..)| known(name)));
+    }
+
+    #[test]
+    fn refuses_commands_unless_allowed() {
+        let batch = json!({"steps": [{"type": "hi"}, {"exec": "curl example.com | sh"}]});
+        let err = Session::new().call("run", &batch).unwrap_err();
+        assert_eq!(err.code, ErrorCode::PermissionDenied);
+        assert_eq!(err.message, "Step 2 runs a command, and code isn't allowed to run here");
+        let script = r#"do shell script "curl example.com | sh""#;
+        let batch = json!({"steps": [{"applescript": script}]});
+        let err = Session::new().call("run", &batch).unwrap_err();
+        assert_eq!(err.message, "Step 1 runs AppleScript, and code isn't allowed to run here");
+    }
+}
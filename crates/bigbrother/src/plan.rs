@@ -0,0 +1,208 @@
+//! Hybrid replay: recordings compiled to automation
+//!
+//! [`compile`] turns a [`RecordedWorkflow`] into a [`Plan`] of automation
+//! steps: clicks find their element with a [`Locator`](crate::Locator)
+//! instead of going to recorded coordinates, apps are activated, text is
+//! typed and shortcuts pressed through [`Desktop`]. What has no element to
+//! go by (drags, scrolls, plain keys, clicks without context) stays as raw
+//! events, replayed with a [`Replayer`] in between.
+//!
+//! Plans serialize to JSON, so they can be saved, edited and run later.
+
+use crate::Desktop;
+use bigbrother_core::Result;
+use bigbrother_recorder::{Event, EventData, Modifiers, RecordedWorkflow, Replayer};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How long a step waits for its element to show up
+pub(crate) const ELEMENT_TIMEOUT_MS: u64 = 5000;
+
+/// Steps compiled from a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub name: String,
+    pub steps: Vec<PlanStep>,
+}
+
+/// One step of a [`Plan`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum PlanStep {
+    /// Bring an app to the front
+    Activate { app: String },
+    /// Open the page the recording started on
+    OpenUrl { url: String },
+    /// Press the element matching `selector`, waiting for it to appear
+    Click {
+        selector: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        app: Option<String>,
+    },
+    /// Type into whatever has focus
+    TypeText { text: String },
+    /// Cmd+key on macOS, Ctrl+key elsewhere
+    Shortcut { key: String },
+    /// Raw events with nothing higher-level to stand for them
+    Replay { events: Vec<Event> },
+}
+
+impl Plan {
+    /// Run the steps in order, stopping at the first that fails
+    pub fn run(&self, desktop: &Desktop) -> Result<()> {
+        for step in &self.steps {
+            step.run(desktop)?;
+        }
+        Ok(())
+    }
+}
+
+impl PlanStep {
+    pub fn run(&self, desktop: &Desktop) -> Result<()> {
+        match self {
+            PlanStep::Activate { app } => desktop.activate(app),
+            PlanStep::OpenUrl { url } => desktop.open_url(url),
+            PlanStep::Click { selector, app } => {
+                let locator = match app {
+                    Some(app) => Desktop::default().in_app(app).locator(selector)?,
+                    None => desktop.locator(selector)?,
+                };
+                locator.timeout(ELEMENT_TIMEOUT_MS).wait()?.click()?;
+                Ok(())
+            }
+            PlanStep::TypeText { text } => desktop.type_text(text),
+            PlanStep::Shortcut { key } => desktop.cmd(key),
+            PlanStep::Replay { events } => {
+                let mut workflow = RecordedWorkflow::new("plan");
+                workflow.events = events.clone();
+                // Assertions among them should stop the plan
+                Replayer::new().fail_fast(true).play(&workflow)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for PlanStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanStep::Activate { app } => write!(f, "activate {}", app),
+            PlanStep::OpenUrl { url } => write!(f, "open {}", url),
+            PlanStep::Click { selector, app: Some(app) } => {
+                write!(f, "click {} in {}", selector, app)
+            }
+            PlanStep::Click { selector, app: None } => write!(f, "click {}", selector),
+            PlanStep::TypeText { text } => write!(f, "type {:?}", text),
+            PlanStep::Shortcut { key } => write!(f, "shortcut cmd/ctrl+{}", key),
+            PlanStep::Replay { events } => write!(f, "replay {} raw events", events.len()),
+        }
+    }
+}
+
+/// Compile `workflow` into a [`Plan`]. Moves are dropped, since clicks find
+/// their own way; so is any URL after the first, which the replayed input
+/// navigates to by itself.
+pub fn compile(workflow: &RecordedWorkflow) -> Plan {
+    // Cmd on macOS recordings, Ctrl elsewhere
+    let primary = match workflow.meta.as_ref().map(|m| m.os.as_str()) {
+        Some("windows" | "linux") => Modifiers::CTRL,
+        _ => Modifiers::CMD,
+    };
+    let mut steps: Vec<PlanStep> = Vec::new();
+    let mut app: Option<&str> = None;
+    let mut opened_url = false;
+
+    for event in &workflow.events {
+        let step = match &event.data {
+            EventData::App { n, .. } if app != Some(n.as_str()) => {
+                app = Some(n);
+                PlanStep::Activate { app: n.clone() }
+            }
+            EventData::Window { u: Some(url), .. } if !opened_url => {
+                opened_url = true;
+                PlanStep::OpenUrl { url: url.clone() }
+            }
+            EventData::Click { b: 0, n: 1, c: Some(c), .. } if !c.s.is_empty() => {
+                PlanStep::Click { selector: c.s.clone(), app: c.a.clone() }
+            }
+            EventData::Text { s } => match steps.last_mut() {
+                Some(PlanStep::TypeText { text }) => {
+                    text.push_str(s);
+                    continue;
+                }
+                _ => PlanStep::TypeText { text: s.clone() },
+            },
+            EventData::Key { k, m } if *m == primary && k.name().is_some_and(|n| n.len() == 1) => {
+                PlanStep::Shortcut { key: k.to_string() }
+            }
+            EventData::Click { .. }
+            | EventData::Drag { .. }
+            | EventData::Scroll { .. }
+            | EventData::Key { .. }
+            | EventData::Assert { .. } => match steps.last_mut() {
+                Some(PlanStep::Replay { events }) => {
+                    events.push(event.clone());
+                    continue;
+                }
+                _ => PlanStep::Replay { events: vec![event.clone()] },
+            },
+            // Releases only matter next to the presses replayed with them
+            EventData::KeyUp { .. } => {
+                if let Some(PlanStep::Replay { events }) = steps.last_mut() {
+                    events.push(event.clone());
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        steps.push(step);
+    }
+    Plan { name: workflow.name.clone(), steps }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigbrother_recorder::{ElementContext, Key};
+
+    fn event(data: EventData) -> Event {
+        Event { t: 0, ts: None, data }
+    }
+
+    #[test]
+    fn compiles_to_automation_steps() {
+        let context = ElementContext {
+            s: "role:AXButton AND title:Save".into(),
+            r: "AXButton".into(),
+            a: Some("TextEdit".into()),
+            ..Default::default()
+        };
+        let mut workflow = RecordedWorkflow::new("save");
+        workflow.events = vec![
+            event(EventData::App { n: "TextEdit".into(), p: 1 }),
+            event(EventData::Move { x: 5, y: 5 }),
+            event(EventData::Text { s: "hel".into() }),
+            event(EventData::Text { s: "lo".into() }),
+            event(EventData::Key { k: Key::S, m: Modifiers::CMD }),
+            event(EventData::Click { x: 1, y: 2, b: 0, n: 1, m: 0, c: Some(Box::new(context)) }),
+            event(EventData::Scroll { x: 1, y: 2, dx: 0, dy: -3, px: false }),
+            event(EventData::Key { k: Key::Return, m: 0 }),
+        ];
+
+        let plan = compile(&workflow);
+        let steps: Vec<String> = plan.steps.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            steps,
+            [
+                "activate TextEdit",
+                "type \"hello\"",
+                "shortcut cmd/ctrl+s",
+                "click role:AXButton AND title:Save in TextEdit",
+                "replay 2 raw events",
+            ]
+        );
+        let json = serde_json::to_string(&plan).unwrap();
+        let parsed: Plan = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
+}
@@ -0,0 +1,94 @@
+//! Automation spans sent to an OpenTelemetry collector
+//!
+//! [`Desktop`](crate::Desktop), [`Locator`](crate::Locator) and
+//! [`Replayer`](crate::Replayer) trace what they do with `tracing`: one span
+//! per action or lookup, with the selector, the app, how long it took and
+//! how it ended. [`Telemetry::otlp`] exports them over OTLP/HTTP:
+//!
+//! ```rust,no_run
+//! use bigbrother::prelude::*;
+//! use bigbrother::telemetry::{Level, Telemetry};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! // Flushed when dropped, so keep it for as long as the process runs
+//! let _telemetry = Telemetry::otlp(None, Level::INFO)?;
+//! Desktop::new()?.locator("role:Button AND name:Send")?.click()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! To log as well, add [`Telemetry::layer`] to a subscriber of your own
+//! instead.
+//!
+//! Lookups retried while waiting (`search`) and each replayed event are
+//! debug spans; actions, waits and whole replays are info. Typed text is
+//! never recorded, only its length.
+
+use anyhow::{Context, Result};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+pub use tracing::Level;
+
+/// Service name spans are reported under
+const SERVICE_NAME: &str = "bigbrother";
+
+/// An OTLP exporter; sends what's left and stops when dropped
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+}
+
+impl Telemetry {
+    /// Export spans at `level` and above to `endpoint`, the full traces URL
+    /// (e.g. `http://collector:4318/v1/traces`). Without one, the
+    /// `OTEL_EXPORTER_OTLP_ENDPOINT` environment variable or a collector on
+    /// localhost. Fails if a tracing subscriber is already installed.
+    pub fn otlp(endpoint: Option<&str>, level: Level) -> Result<Self> {
+        let telemetry = Self::exporter(endpoint)?;
+        tracing_subscriber::registry()
+            .with(telemetry.layer().with_filter(LevelFilter::from_level(level)))
+            .try_init()
+            .context("A tracing subscriber is already installed")?;
+        Ok(telemetry)
+    }
+
+    /// An exporter to `endpoint`, as for [`otlp`](Self::otlp), that nothing
+    /// sends to until its [`layer`](Self::layer) is in a subscriber
+    pub fn exporter(endpoint: Option<&str>) -> Result<Self> {
+        let mut exporter = SpanExporter::builder().with_http();
+        if let Some(endpoint) = endpoint {
+            exporter = exporter.with_endpoint(endpoint);
+        }
+        let exporter = exporter.build().context("Couldn't create the OTLP exporter")?;
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+            .build();
+        Ok(Self { provider })
+    }
+
+    /// Turns spans into OpenTelemetry ones for this exporter
+    pub fn layer<S>(&self) -> OpenTelemetryLayer<S, SdkTracer>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.provider.tracer(SERVICE_NAME))
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("Couldn't flush spans: {}", e);
+        }
+    }
+}